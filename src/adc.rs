@@ -0,0 +1,131 @@
+//! Interrupt-driven ADC, see [adc_config](crate::adc_config).
+//!
+//! Set the inputs up with [setup_adc], add [adc_task] to the board's
+//! [Housekeeping](crate::housekeeping::Housekeeping) tasks to start the conversion rounds, and call
+//! [adc_interrupt] from the board's `ADC` interrupt. The main loop reads the latest samples with
+//! [adc_sample], without waiting for a conversion.
+//!
+//! The [AdcConversions] own the ADC, so they replace the blocking [arduino_hal::Adc] used by the
+//! [AnalogStick](crate::AnalogStick) and the [AdcChannels](crate::AdcChannels). Keep the
+//! [Adc](crate::power::Peripheral::Adc) powered in the board's
+//! [PowerReduction](crate::power::PowerReduction).
+
+use core::cell::RefCell;
+
+use arduino_hal::pac;
+use avr_device::interrupt::{self, Mutex};
+
+use crate::adc_config::{AdcConfig, AdcInput, AdcSchedule};
+use crate::F_CPU;
+
+/// Global ADC conversions, started from the main loop and finished by the `ADC` interrupt.
+pub static ADC_CONVERSIONS: Mutex<RefCell<Option<AdcConversions>>> = Mutex::new(RefCell::new(None));
+
+/// ADC converting the inputs of an [AdcSchedule] from its interrupt.
+pub struct AdcConversions {
+    adc: pac::ADC,
+    config: AdcConfig,
+    schedule: AdcSchedule,
+}
+
+impl AdcConversions {
+    /// Creates new [AdcConversions], enables the ADC and its interrupt, and disables the digital
+    /// input buffers of the scheduled pins.
+    pub fn new(adc: pac::ADC, config: AdcConfig, schedule: AdcSchedule) -> Self {
+        let (mut didr0, mut didr2) = (0u8, 0u8);
+
+        for index in 0..schedule.len() {
+            match schedule
+                .input(index)
+                .and_then(|input| input.digital_input())
+            {
+                Some((0, bit)) => didr0 |= 1 << bit,
+                Some((_, bit)) => didr2 |= 1 << bit,
+                None => (),
+            }
+        }
+
+        // the analog pins draw current through their digital input buffers at mid-rail levels
+        adc.didr0.modify(|r, w| unsafe { w.bits(r.bits() | didr0) });
+        adc.didr2.modify(|r, w| unsafe { w.bits(r.bits() | didr2) });
+        adc.adcsra
+            .write(|w| unsafe { w.bits(config.adcsra(F_CPU, false)) });
+
+        Self {
+            adc,
+            config,
+            schedule,
+        }
+    }
+
+    /// Gets the [AdcSchedule].
+    pub const fn schedule(&self) -> &AdcSchedule {
+        &self.schedule
+    }
+
+    /// Starts a conversion round at the time `now_ms`, at most every
+    /// [interval](AdcConfig::interval_ms).
+    pub fn start(&mut self, now_ms: u16) {
+        if let Some(input) = self.schedule.start_round(now_ms, self.config.interval_ms()) {
+            self.convert(input);
+        }
+    }
+
+    /// Stores the finished conversion, and starts the next one of the round.
+    pub fn complete(&mut self) {
+        let sample = self.adc.adc.read().bits();
+
+        if let Some(input) = self.schedule.complete(sample) {
+            self.convert(input);
+        }
+    }
+
+    // Selects the input, and starts a conversion.
+    fn convert(&mut self, input: AdcInput) {
+        self.adc
+            .admux
+            .write(|w| unsafe { w.bits(self.config.admux(input)) });
+        self.adc
+            .adcsrb
+            .write(|w| unsafe { w.bits(self.config.adcsrb(input)) });
+        self.adc
+            .adcsra
+            .write(|w| unsafe { w.bits(self.config.adcsra(F_CPU, true)) });
+    }
+}
+
+/// Sets up the [AdcConversions] of the inputs in the [AdcSchedule].
+pub fn setup_adc(adc: pac::ADC, config: AdcConfig, schedule: AdcSchedule) {
+    let conversions = AdcConversions::new(adc, config, schedule);
+
+    interrupt::free(|cs| ADC_CONVERSIONS.borrow(cs).replace(Some(conversions)));
+}
+
+/// Starts the ADC conversion rounds, an [IdleTask](crate::housekeeping::IdleTask).
+pub fn adc_task(now_ms: u16) {
+    interrupt::free(|cs| {
+        if let Some(conversions) = ADC_CONVERSIONS.borrow(cs).borrow_mut().as_mut() {
+            conversions.start(now_ms);
+        }
+    });
+}
+
+/// Finishes an ADC conversion, called from the `ADC` interrupt.
+pub fn adc_interrupt() {
+    interrupt::free(|cs| {
+        if let Some(conversions) = ADC_CONVERSIONS.borrow(cs).borrow_mut().as_mut() {
+            conversions.complete();
+        }
+    });
+}
+
+/// Gets the latest sample of the `input`, see [AdcSchedule::sample].
+pub fn adc_sample(input: AdcInput) -> Option<u16> {
+    interrupt::free(|cs| {
+        ADC_CONVERSIONS
+            .borrow(cs)
+            .borrow()
+            .as_ref()
+            .and_then(|conversions| conversions.schedule.sample(input))
+    })
+}
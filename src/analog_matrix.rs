@@ -0,0 +1,114 @@
+use arduino_hal::{adc::Channel, Adc};
+
+use crate::analog::{self, KeyThreshold};
+use crate::key_matrix::MatrixReader;
+use crate::{layers, RowState, MAX_COLS};
+
+/// Samples the analog level of each key.
+///
+/// Implemented for the ATmega32u4 [AdcChannels], and by boards with external multiplexers or
+/// ADCs.
+pub trait AnalogSampler {
+    /// Samples the key at the `row` and `col`, or returns `None` if there is no key.
+    fn sample(&mut self, row: usize, col: usize) -> Option<u16>;
+}
+
+/// ADC channels of the ATmega32u4, with one channel for each key.
+///
+/// Positions without a key are left as `None`.
+pub struct AdcChannels<const ROWS: usize = { layers::ROWS }, const COLS: usize = { layers::COLS }> {
+    adc: Adc,
+    channels: [[Option<Channel>; COLS]; ROWS],
+}
+
+impl<const ROWS: usize, const COLS: usize> AdcChannels<ROWS, COLS> {
+    /// Creates new [AdcChannels] from the ADC, and a grid of key channels.
+    pub fn new(adc: Adc, channels: [[Option<Channel>; COLS]; ROWS]) -> Self {
+        Self { adc, channels }
+    }
+}
+
+impl<const ROWS: usize, const COLS: usize> AnalogSampler for AdcChannels<ROWS, COLS> {
+    fn sample(&mut self, row: usize, col: usize) -> Option<u16> {
+        let channel = self.channels.get(row)?.get(col)?.as_ref()?;
+
+        Some(self.adc.read_blocking(channel))
+    }
+}
+
+/// Represents an experimental key matrix of analog switches, e.g. hall-effect switches.
+///
+/// Each key is sampled by an [AnalogSampler], and compared against its [KeyThreshold]. The
+/// resulting [RowState]s are debounced and reported like a digital matrix.
+pub struct AnalogMatrix<
+    const ROWS: usize = { layers::ROWS },
+    const COLS: usize = { layers::COLS },
+    S = AdcChannels<ROWS, COLS>,
+> {
+    sampler: S,
+    thresholds: [[KeyThreshold; COLS]; ROWS],
+    state: [RowState; ROWS],
+}
+
+impl<const ROWS: usize, const COLS: usize, S: AnalogSampler> AnalogMatrix<ROWS, COLS, S> {
+    const VALID_COLS: () = assert!(COLS <= MAX_COLS, "too many columns for a RowState");
+
+    /// Creates a new [AnalogMatrix], with the default [KeyThreshold] for every key.
+    pub fn new(sampler: S) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let _ = Self::VALID_COLS;
+
+        Self {
+            sampler,
+            thresholds: [[KeyThreshold::default(); COLS]; ROWS],
+            state: [RowState::new(); ROWS],
+        }
+    }
+
+    /// Gets the [KeyThreshold] of the key at the `row` and `col`.
+    pub fn threshold(&self, row: usize, col: usize) -> Option<KeyThreshold> {
+        self.thresholds.get(row)?.get(col).copied()
+    }
+
+    /// Sets the [KeyThreshold] of the key at the `row` and `col`.
+    ///
+    /// Keys outside of the matrix are ignored.
+    pub fn set_threshold(&mut self, row: usize, col: usize, threshold: KeyThreshold) {
+        if let Some(entry) = self.thresholds.get_mut(row).and_then(|r| r.get_mut(col)) {
+            *entry = threshold;
+        }
+    }
+
+    /// Builder function that sets the [KeyThreshold] of every key.
+    pub fn with_thresholds(mut self, thresholds: [[KeyThreshold; COLS]; ROWS]) -> Self {
+        self.thresholds = thresholds;
+        self
+    }
+
+    /// Gets a mutable reference to the [AnalogSampler].
+    pub fn sampler_mut(&mut self) -> &mut S {
+        &mut self.sampler
+    }
+}
+
+impl<const ROWS: usize, const COLS: usize, S: AnalogSampler> MatrixReader<ROWS, COLS>
+    for AnalogMatrix<ROWS, COLS, S>
+{
+    fn read_row(&mut self, row: usize) -> RowState {
+        let (Some(state), Some(thresholds)) = (self.state.get(row), self.thresholds.get(row))
+        else {
+            return RowState::new();
+        };
+
+        let mut samples = [None; COLS];
+
+        for (col, sample) in samples.iter_mut().enumerate() {
+            *sample = self.sampler.sample(row, col);
+        }
+
+        let state = analog::analog_row(&samples, thresholds, *state);
+        self.state[row] = state;
+
+        state
+    }
+}
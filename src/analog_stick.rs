@@ -0,0 +1,90 @@
+//! Analog thumbstick on two ADC channels, see [thumbstick](crate::thumbstick).
+//!
+//! Set the stick up with [setup_analog_stick] while it rests, add [analog_stick_task] to the
+//! board's [Housekeeping](crate::housekeeping::Housekeeping) tasks, and keep the
+//! [Adc](crate::power::Peripheral::Adc) powered. The motion goes out with the scan's
+//! [mouse](crate::mouse) report.
+
+use core::cell::RefCell;
+
+use arduino_hal::{adc::Channel, Adc};
+use avr_device::interrupt::{self, Mutex};
+
+use crate::mouse;
+use crate::thumbstick::{StickConfig, Thumbstick};
+
+/// Time (in milliseconds) between samples of the stick, the unit of the pointer speed.
+pub const STICK_POLL_MS: u16 = 8;
+
+/// Global thumbstick, sampled from the main loop.
+pub static ANALOG_STICK: Mutex<RefCell<Option<AnalogStick>>> = Mutex::new(RefCell::new(None));
+
+/// Two-axis analog stick on the ADC.
+pub struct AnalogStick {
+    adc: Adc,
+    x: Channel,
+    y: Channel,
+    stick: Thumbstick,
+    last_poll_ms: Option<u16>,
+}
+
+impl AnalogStick {
+    /// Creates a new [AnalogStick] from the ADC and the channels of the axes.
+    ///
+    /// The center is calibrated from the first samples, so leave the stick at rest.
+    pub fn new(mut adc: Adc, x: Channel, y: Channel, config: StickConfig) -> Self {
+        let mut stick = Thumbstick::new(config);
+        stick.calibrate(adc.read_blocking(&x), adc.read_blocking(&y));
+
+        Self {
+            adc,
+            x,
+            y,
+            stick,
+            last_poll_ms: None,
+        }
+    }
+
+    /// Gets the [Thumbstick] state.
+    pub fn stick(&mut self) -> &mut Thumbstick {
+        &mut self.stick
+    }
+
+    /// Samples the stick at the time `now_ms`, at most every [STICK_POLL_MS], and adds the
+    /// pointer motion to the next mouse report.
+    pub fn update(&mut self, now_ms: u16) {
+        if self
+            .last_poll_ms
+            .is_some_and(|last| now_ms.wrapping_sub(last) < STICK_POLL_MS)
+        {
+            return;
+        }
+        self.last_poll_ms = Some(now_ms);
+
+        let x = self.adc.read_blocking(&self.x);
+        let y = self.adc.read_blocking(&self.y);
+        let (dx, dy) = self.stick.motion(x, y);
+
+        if dx != 0 || dy != 0 {
+            mouse::add_motion(dx, dy);
+        }
+    }
+}
+
+/// Sets up the [AnalogStick] on the `x` and `y` channels, with the [StickConfig].
+pub fn setup_analog_stick(adc: Adc, x: Channel, y: Channel, config: StickConfig) {
+    let stick = AnalogStick::new(adc, x, y, config);
+
+    interrupt::free(|cs| ANALOG_STICK.borrow(cs).replace(Some(stick)));
+}
+
+/// Samples the thumbstick, an [IdleTask](crate::housekeeping::IdleTask).
+///
+/// The stick is taken out of [ANALOG_STICK] while sampling, so interrupts stay enabled during the
+/// conversions.
+pub fn analog_stick_task(now_ms: u16) {
+    if let Some(mut stick) = interrupt::free(|cs| ANALOG_STICK.borrow(cs).take()) {
+        stick.update(now_ms);
+        interrupt::free(|cs| ANALOG_STICK.borrow(cs).replace(Some(stick)));
+    }
+}
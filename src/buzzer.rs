@@ -0,0 +1,196 @@
+//! Piezo buzzer on a spare pin, see [audio](crate::audio).
+//!
+//! Timer 4 overflows at twice the tone frequency, and [buzzer_toggle] flips the pin from the
+//! `TIMER4_OVF` interrupt. Set the buzzer up with [setup_buzzer], add [buzzer_task] to the board's
+//! [Housekeeping](crate::housekeeping::Housekeeping) tasks, and keep the
+//! [Timer4](crate::power::Peripheral::Timer4) powered.
+
+use core::cell::RefCell;
+
+use arduino_hal::pac;
+use avr_device::interrupt::{self, Mutex};
+
+use crate::audio::{self, AudioPlayer, Sound, ToneTimer};
+use crate::layers;
+use crate::pin_map::{PinId, PinMap, Port};
+
+/// Timer 4 clock, from the PLL postscaler set up at boot.
+pub const TIMER4_CLOCK_HZ: u32 = 64_000_000;
+
+/// Global buzzer, driven from the main loop and the timer interrupt.
+pub static BUZZER: Mutex<RefCell<Option<Buzzer>>> = Mutex::new(RefCell::new(None));
+
+/// Piezo buzzer on a pin, toggled by Timer 4.
+pub struct Buzzer {
+    tc4: pac::TC4,
+    pin: PinId,
+    player: AudioPlayer,
+}
+
+impl Buzzer {
+    /// Creates a new [Buzzer] on the `pin`, and configures it as an output driven low.
+    ///
+    /// Returns `None` for key matrix pins, and pins not bonded out.
+    pub fn new(tc4: pac::TC4, pin: PinId, map: &PinMap) -> Option<Self> {
+        if !pin.is_available() || map.contains(&pin) {
+            return None;
+        }
+
+        let bit = 1 << pin.bit();
+
+        // Safety: only the bit of the buzzer pin is changed, before the timer interrupt is
+        // enabled.
+        unsafe {
+            match pin.port() {
+                Port::B => {
+                    (*pac::PORTB::ptr())
+                        .portb
+                        .modify(|r, w| w.bits(r.bits() & !bit));
+                    (*pac::PORTB::ptr())
+                        .ddrb
+                        .modify(|r, w| w.bits(r.bits() | bit));
+                }
+                Port::C => {
+                    (*pac::PORTC::ptr())
+                        .portc
+                        .modify(|r, w| w.bits(r.bits() & !bit));
+                    (*pac::PORTC::ptr())
+                        .ddrc
+                        .modify(|r, w| w.bits(r.bits() | bit));
+                }
+                Port::D => {
+                    (*pac::PORTD::ptr())
+                        .portd
+                        .modify(|r, w| w.bits(r.bits() & !bit));
+                    (*pac::PORTD::ptr())
+                        .ddrd
+                        .modify(|r, w| w.bits(r.bits() | bit));
+                }
+                Port::E => {
+                    (*pac::PORTE::ptr())
+                        .porte
+                        .modify(|r, w| w.bits(r.bits() & !bit));
+                    (*pac::PORTE::ptr())
+                        .ddre
+                        .modify(|r, w| w.bits(r.bits() | bit));
+                }
+                Port::F => {
+                    (*pac::PORTF::ptr())
+                        .portf
+                        .modify(|r, w| w.bits(r.bits() & !bit));
+                    (*pac::PORTF::ptr())
+                        .ddrf
+                        .modify(|r, w| w.bits(r.bits() | bit));
+                }
+            }
+        }
+
+        Some(Self {
+            tc4,
+            pin,
+            player: AudioPlayer::new(),
+        })
+    }
+
+    /// Gets the [AudioPlayer].
+    pub fn player(&mut self) -> &mut AudioPlayer {
+        &mut self.player
+    }
+
+    /// Plays the requested sounds and layer chirps at the time `now_ms`, and retunes the timer.
+    pub fn update(&mut self, now_ms: u16) {
+        if let Some(freq) = self.player.update(now_ms, layers::active_layer()) {
+            self.set_tone(freq);
+        }
+    }
+
+    /// Flips the buzzer pin, call on each timer overflow.
+    pub fn toggle(&self) {
+        let bit = 1 << self.pin.bit();
+
+        // Safety: writing a one to the input register flips only that bit of the port.
+        unsafe {
+            match self.pin.port() {
+                Port::B => (*pac::PORTB::ptr()).pinb.write(|w| w.bits(bit)),
+                Port::C => (*pac::PORTC::ptr()).pinc.write(|w| w.bits(bit)),
+                Port::D => (*pac::PORTD::ptr()).pind.write(|w| w.bits(bit)),
+                Port::E => (*pac::PORTE::ptr()).pine.write(|w| w.bits(bit)),
+                Port::F => (*pac::PORTF::ptr()).pinf.write(|w| w.bits(bit)),
+            }
+        }
+    }
+
+    // Starts the square wave at the frequency, or stops the timer for `0`.
+    fn set_tone(&mut self, freq: u16) {
+        // stop the timer while retuning
+        self.tc4.tccr4b.write(|w| unsafe { w.bits(0) });
+        self.tc4.tcnt4.write(|w| w.bits(0));
+
+        match ToneTimer::new(TIMER4_CLOCK_HZ, freq) {
+            Some(timer) => {
+                self.tc4.ocr4c.write(|w| w.bits(timer.top()));
+                self.tc4.timsk4.write(|w| w.toie4().set_bit());
+                self.tc4
+                    .tccr4b
+                    .write(|w| unsafe { w.bits(timer.clock_select()) });
+            }
+            None => {
+                self.tc4.timsk4.write(|w| w.toie4().clear_bit());
+                // leave the piezo without a DC bias
+                if self.pin_high() {
+                    self.toggle();
+                }
+            }
+        }
+    }
+
+    // Gets whether the buzzer pin is driven high.
+    fn pin_high(&self) -> bool {
+        // Safety: reading the output register has no side effects.
+        let port = unsafe {
+            match self.pin.port() {
+                Port::B => (*pac::PORTB::ptr()).portb.read().bits(),
+                Port::C => (*pac::PORTC::ptr()).portc.read().bits(),
+                Port::D => (*pac::PORTD::ptr()).portd.read().bits(),
+                Port::E => (*pac::PORTE::ptr()).porte.read().bits(),
+                Port::F => (*pac::PORTF::ptr()).portf.read().bits(),
+            }
+        };
+
+        port & (1 << self.pin.bit()) != 0
+    }
+}
+
+/// Sets up the [Buzzer] on the `pin`, and plays the startup melody.
+///
+/// Returns `false` if the pin is not usable, see [Buzzer::new].
+pub fn setup_buzzer(tc4: pac::TC4, pin: PinId, map: &PinMap) -> bool {
+    match Buzzer::new(tc4, pin, map) {
+        Some(buzzer) => {
+            interrupt::free(|cs| BUZZER.borrow(cs).replace(Some(buzzer)));
+            audio::request_sound(Sound::Startup);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Steps the buzzer melodies, an [IdleTask](crate::housekeeping::IdleTask).
+pub fn buzzer_task(now_ms: u16) {
+    interrupt::free(|cs| {
+        if let Some(buzzer) = BUZZER.borrow(cs).borrow_mut().as_mut() {
+            buzzer.update(now_ms);
+        }
+    });
+}
+
+/// Flips the buzzer pin.
+///
+/// Call from the `TIMER4_OVF` interrupt.
+pub fn buzzer_toggle() {
+    interrupt::free(|cs| {
+        if let Some(buzzer) = BUZZER.borrow(cs).borrow().as_ref() {
+            buzzer.toggle();
+        }
+    });
+}
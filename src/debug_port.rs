@@ -0,0 +1,149 @@
+//! USB-CDC debug port.
+//!
+//! Streams diagnostic text to the host over a USB-serial port, next to the keyboard HID
+//! interface, e.g. the [matrix_test](crate::matrix_test) lines. Lines from the host are run as
+//! [focus](crate::focus) commands, e.g. from Chrysalis, or as [console](crate::console) commands.
+//! Host tools can also reboot the keyboard into the [bootloader](crate::bootloader), and back up
+//! the EEPROM configuration, see [config_backup](crate::config_backup).
+
+use atmega_usbd::UsbBus;
+use usb_device::class_prelude::UsbBusAllocator;
+use usbd_serial::SerialPort;
+
+use crate::bootloader;
+use crate::bridge::ByteQueue;
+use crate::config_backup::{self, ConfigDump, LOAD_LINE_LEN};
+use crate::console::{self, ConsoleCommand, LineBuffer, CONSOLE_RESPONSE_LEN};
+use crate::eeprom_storage;
+use crate::focus::{FocusResponse, FOCUS_TOKEN_LEN};
+
+/// Maximum number of bytes read from the USB-serial port per poll.
+const HOST_READ_LEN: usize = 16;
+
+/// USB-CDC serial port for debug output, the command console, and the Focus protocol.
+///
+/// Output is queued, and written to the host without blocking. Writes that do not fit in the queue
+/// are dropped whole, so the host never sees partial lines.
+pub struct DebugPort {
+    port: SerialPort<'static, UsbBus>,
+    to_host: ByteQueue,
+    line: LineBuffer,
+    focus: Option<FocusResponse>,
+    dump: Option<ConfigDump>,
+}
+
+impl DebugPort {
+    /// Creates a new [DebugPort].
+    ///
+    /// Allocates the USB-serial port on the bus, so it must be created before the USB device is
+    /// built.
+    pub fn new(usb_bus: &'static UsbBusAllocator<UsbBus>) -> Self {
+        Self {
+            port: SerialPort::new(usb_bus),
+            to_host: ByteQueue::new(),
+            line: LineBuffer::new(),
+            focus: None,
+            dump: None,
+        }
+    }
+
+    /// Gets a mutable reference to the USB-serial port, e.g. for polling the USB device.
+    pub fn port_mut(&mut self) -> &mut SerialPort<'static, UsbBus> {
+        &mut self.port
+    }
+
+    /// Queues bytes to write to the host.
+    ///
+    /// Returns `false` if the bytes were dropped, because the host could not keep up.
+    pub fn write(&mut self, bytes: &[u8]) -> bool {
+        if self.to_host.free() < bytes.len() {
+            return false;
+        }
+
+        self.to_host.extend(bytes);
+        true
+    }
+
+    /// Runs console commands from the host, and writes queued bytes to the USB-serial port,
+    /// without blocking.
+    ///
+    /// Also requests the bootloader on a "1200-baud touch", see [bootloader].
+    ///
+    /// Called after every USB device poll.
+    pub fn pump(&mut self) {
+        if bootloader::is_bootloader_touch(self.port.line_coding().data_rate(), self.port.dtr()) {
+            bootloader::request_bootloader();
+        }
+
+        // drain host data, so the port does not stall
+        let mut buf = [0u8; HOST_READ_LEN];
+
+        while let Ok(len @ 1..) = self.port.read(&mut buf) {
+            for &byte in buf[..len].iter() {
+                if !self.line.push(byte) {
+                    continue;
+                }
+
+                if let Some(response) = FocusResponse::start(self.line.line()) {
+                    self.focus = Some(response);
+                } else {
+                    match ConsoleCommand::parse(self.line.line()) {
+                        Some(ConsoleCommand::Dump) => self.dump = Some(ConfigDump::new()),
+                        Some(ConsoleCommand::Load) => self.load(),
+                        _ => {
+                            let mut response = [0u8; CONSOLE_RESPONSE_LEN];
+                            let len = console::run_command(self.line.line(), &mut response);
+
+                            self.write(&response[..len]);
+                        }
+                    }
+                }
+            }
+        }
+
+        // Focus responses are longer than the queue, so they are queued as it drains
+        if let Some(focus) = self.focus.as_mut() {
+            let mut chunk = [0u8; FOCUS_TOKEN_LEN];
+            let len = focus.fill(&mut chunk[..self.to_host.free().min(FOCUS_TOKEN_LEN)]);
+
+            self.to_host.extend(&chunk[..len]);
+
+            if focus.is_done() {
+                self.focus = None;
+            }
+        }
+
+        // the dump is also longer than the queue, one line is read from the EEPROM at a time
+        if let Some(dump) = self.dump.as_mut() {
+            if self.to_host.free() >= LOAD_LINE_LEN {
+                let mut line = [0u8; LOAD_LINE_LEN];
+                let len = eeprom_storage::with_eeprom(|eeprom| dump.next_line(eeprom, &mut line))
+                    .flatten();
+
+                match len {
+                    Some(len) => {
+                        self.to_host.extend(&line[..len]);
+                    }
+                    None => self.dump = None,
+                }
+            }
+        }
+
+        if !self.to_host.is_empty() {
+            if let Ok(len) = self.port.write(self.to_host.front()) {
+                self.to_host.consume(len);
+            }
+        }
+    }
+
+    // Writes the `load` line to the EEPROM, and answers with its offset and the result.
+    fn load(&mut self) {
+        let args = self.line.line().trim_ascii().get(4..).unwrap_or_default();
+        let result = eeprom_storage::with_eeprom(|eeprom| config_backup::load_line(eeprom, args));
+
+        self.write(match result {
+            Some(Ok(_)) => b"load ok\r\n",
+            _ => b"load failed\r\n",
+        });
+    }
+}
@@ -0,0 +1,69 @@
+use arduino_hal::port::{
+    mode::{Input, PullUp},
+    Pin,
+};
+use embedded_hal::digital::v2::InputPin;
+
+use crate::{key_matrix::MatrixReader, layers, RowState, MAX_COLS};
+
+/// Represents a keyboard where every switch has its own pin, without a row/column matrix.
+///
+/// Each switch connects a pull-up input pin to ground, so the pin is driven low when the key is
+/// pressed. Pins are arranged in a `ROWS` x `COLS` grid that matches the key map, and positions
+/// without a switch are left as `None`.
+///
+/// Switches are read into the same [RowState]s as a [KeyMatrix](crate::KeyMatrix), so debouncing
+/// and reporting are unchanged.
+pub struct DirectPins<
+    const ROWS: usize = { layers::ROWS },
+    const COLS: usize = { layers::COLS },
+    P = Pin<Input<PullUp>>,
+> {
+    pins: [[Option<P>; COLS]; ROWS],
+}
+
+impl<const ROWS: usize, const COLS: usize, P: InputPin> DirectPins<ROWS, COLS, P> {
+    const VALID_COLS: () = assert!(COLS <= MAX_COLS, "too many columns for a RowState");
+
+    /// Creates a new [DirectPins] from a grid of pull-up input pins.
+    pub fn new(pins: [[Option<P>; COLS]; ROWS]) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let _ = Self::VALID_COLS;
+
+        Self { pins }
+    }
+
+    /// Gets a reference to the switch pins.
+    pub fn pins(&self) -> &[[Option<P>; COLS]] {
+        self.pins.as_ref()
+    }
+
+    /// Gets a mutable reference to the switch pins.
+    pub fn pins_mut(&mut self) -> &mut [[Option<P>; COLS]] {
+        self.pins.as_mut()
+    }
+}
+
+impl<const ROWS: usize, const COLS: usize, P: InputPin> MatrixReader<ROWS, COLS>
+    for DirectPins<ROWS, COLS, P>
+{
+    fn read_row(&mut self, row: usize) -> RowState {
+        let mut hot_pins = RowState::new();
+
+        if let Some(pins) = self.pins.get(row) {
+            for (j, pin) in pins.iter().enumerate() {
+                // if the pin is low, the key was pressed
+                if pin.as_ref().is_some_and(|p| p.is_low().unwrap_or(false)) {
+                    hot_pins.set_column(j, true);
+                }
+            }
+        }
+
+        hot_pins
+    }
+
+    fn prepare_idle(&mut self) -> bool {
+        // every switch already pulls its own pin low when pressed
+        true
+    }
+}
@@ -0,0 +1,226 @@
+use arduino_hal::port::{
+    mode::{Input, Output, PullUp},
+    Pin,
+};
+use embedded_hal::blocking::delay::DelayUs;
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+
+use crate::{key_matrix::MatrixScanner, RowState, SettleTimer, DEFAULT_SETTLE_US, MAX_COLS};
+
+/// Pin that switches between a pull-up input and an output at runtime.
+///
+/// Needed for [DuplexMatrix] lines, which are driven in one scan phase and read in the other.
+pub trait FlexPin: InputPin + OutputPin {
+    /// Switches the pin to a pull-up input.
+    fn set_pull_up_input(&mut self);
+
+    /// Switches the pin to an output, driven high.
+    fn set_output_high(&mut self);
+}
+
+// Mode of an AvrFlexPin, taken while switching modes.
+enum FlexMode {
+    Input(Pin<Input<PullUp>>),
+    Output(Pin<Output>),
+}
+
+/// [FlexPin] of the ATmega32u4, which starts as a pull-up input.
+pub struct AvrFlexPin(Option<FlexMode>);
+
+impl AvrFlexPin {
+    /// Creates a new [AvrFlexPin] from a pull-up input pin.
+    pub fn new(pin: Pin<Input<PullUp>>) -> Self {
+        Self(Some(FlexMode::Input(pin)))
+    }
+}
+
+impl InputPin for AvrFlexPin {
+    type Error = core::convert::Infallible;
+
+    fn is_high(&self) -> Result<bool, Self::Error> {
+        self.is_low().map(|low| !low)
+    }
+
+    fn is_low(&self) -> Result<bool, Self::Error> {
+        Ok(match self.0.as_ref() {
+            Some(FlexMode::Input(pin)) => pin.is_low(),
+            // outputs are only driven low to activate a line, and are never read
+            _ => false,
+        })
+    }
+}
+
+impl OutputPin for AvrFlexPin {
+    type Error = core::convert::Infallible;
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        if let Some(FlexMode::Output(pin)) = self.0.as_mut() {
+            pin.set_low();
+        }
+
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        if let Some(FlexMode::Output(pin)) = self.0.as_mut() {
+            pin.set_high();
+        }
+
+        Ok(())
+    }
+}
+
+impl FlexPin for AvrFlexPin {
+    fn set_pull_up_input(&mut self) {
+        self.0 = self.0.take().map(|mode| match mode {
+            FlexMode::Output(pin) => FlexMode::Input(pin.into_pull_up_input()),
+            input => input,
+        });
+    }
+
+    fn set_output_high(&mut self) {
+        self.0 = self.0.take().map(|mode| match mode {
+            FlexMode::Input(pin) => {
+                let mut pin = pin.into_output();
+                pin.set_high();
+                FlexMode::Output(pin)
+            }
+            output => output,
+        });
+    }
+}
+
+/// Represents a duplex ("round-robin") key matrix, where every line is used both as a row and as
+/// a column.
+///
+/// Each pair of keys shares a row and a column line, with the diodes pointing in opposite
+/// directions. The matrix is scanned in two phases:
+///
+/// - rows are driven low, and columns are read, for the keys with col-to-row diodes
+/// - columns are driven low, and rows are read, for the keys with row-to-col diodes
+///
+/// So `LINES` column lines produce `COLS = 2 * LINES` key columns, fitting twice the keys onto
+/// the same pins. Even key columns (`2 * line`) are read in the first phase, and odd key columns
+/// (`2 * line + 1`) in the second.
+///
+/// Each phase covers every row, so the matrix is read as a whole [MatrixScanner] pass.
+pub struct DuplexMatrix<
+    const ROWS: usize,
+    const COLS: usize,
+    const LINES: usize,
+    P = AvrFlexPin,
+    T = SettleTimer,
+> {
+    rows: [P; ROWS],
+    cols: [P; LINES],
+    settle: T,
+    settle_us: u16,
+}
+
+impl<const ROWS: usize, const COLS: usize, const LINES: usize, P: FlexPin, T>
+    DuplexMatrix<ROWS, COLS, LINES, P, T>
+{
+    const VALID_COLS: () = assert!(
+        COLS <= MAX_COLS && COLS == 2 * LINES,
+        "duplex matrices have two key columns per column line"
+    );
+
+    /// Creates a new [DuplexMatrix] from the row and column lines.
+    ///
+    /// Rows start as outputs, driven high, and columns as pull-up inputs.
+    pub fn new(mut rows: [P; ROWS], mut cols: [P; LINES]) -> Self
+    where
+        T: Default,
+    {
+        #[allow(clippy::let_unit_value)]
+        let _ = Self::VALID_COLS;
+
+        rows.iter_mut().for_each(|row| row.set_output_high());
+        cols.iter_mut().for_each(|col| col.set_pull_up_input());
+
+        Self {
+            rows,
+            cols,
+            settle: T::default(),
+            settle_us: DEFAULT_SETTLE_US,
+        }
+    }
+
+    /// Gets the time (in microseconds) for an input pin to settle before it is read.
+    pub const fn settle_us(&self) -> u16 {
+        self.settle_us
+    }
+
+    /// Sets the time (in microseconds) for an input pin to settle before it is read.
+    pub fn set_settle_us(&mut self, val: u16) {
+        self.settle_us = val;
+    }
+
+    /// Builder function that sets the time (in microseconds) for an input pin to settle.
+    pub fn with_settle_us(mut self, val: u16) -> Self {
+        self.set_settle_us(val);
+        self
+    }
+}
+
+impl<const ROWS: usize, const COLS: usize, const LINES: usize, P: FlexPin, T: DelayUs<u16>>
+    DuplexMatrix<ROWS, COLS, LINES, P, T>
+{
+    /// Scans the keys with col-to-row diodes, by driving the rows and reading the columns.
+    fn scan_forward(&mut self, states: &mut [RowState; ROWS]) {
+        for (row, state) in self.rows.iter_mut().zip(states.iter_mut()) {
+            // pull the row line low to "activate" the row
+            row.set_low().ok();
+            self.settle.delay_us(self.settle_us);
+
+            for (j, col) in self.cols.iter().enumerate() {
+                if col.is_low().unwrap_or(false) {
+                    state.set_column(2 * j, true);
+                }
+            }
+
+            row.set_high().ok();
+        }
+    }
+
+    /// Scans the keys with row-to-col diodes, by driving the columns and reading the rows.
+    fn scan_reverse(&mut self, states: &mut [RowState; ROWS]) {
+        self.rows.iter_mut().for_each(|row| row.set_pull_up_input());
+        self.cols.iter_mut().for_each(|col| col.set_output_high());
+
+        for (j, col) in self.cols.iter_mut().enumerate() {
+            // pull the column line low to "activate" the column
+            col.set_low().ok();
+            self.settle.delay_us(self.settle_us);
+
+            for (row, state) in self.rows.iter().zip(states.iter_mut()) {
+                if row.is_low().unwrap_or(false) {
+                    state.set_column(2 * j + 1, true);
+                }
+            }
+
+            col.set_high().ok();
+        }
+
+        // restore the lines for the col-to-row phase
+        self.cols.iter_mut().for_each(|col| col.set_pull_up_input());
+        self.rows.iter_mut().for_each(|row| row.set_output_high());
+    }
+}
+
+impl<const ROWS: usize, const COLS: usize, const LINES: usize, P: FlexPin, T: DelayUs<u16>>
+    MatrixScanner<ROWS, COLS> for DuplexMatrix<ROWS, COLS, LINES, P, T>
+{
+    fn scan(&mut self) -> [RowState; ROWS] {
+        let mut states = [RowState::new(); ROWS];
+
+        self.scan_forward(&mut states);
+        self.scan_reverse(&mut states);
+
+        states
+    }
+
+    fn set_settle_us(&mut self, val: u16) {
+        self.settle_us = val;
+    }
+}
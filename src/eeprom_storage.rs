@@ -0,0 +1,76 @@
+//! EEPROM storage of the keyboard.
+//!
+//! The EEPROM is shared by the main loop, which looks up keys in the
+//! [stored key map](crate::stored_keymap) and plays the
+//! [stored animation](crate::stored_animation), and the USB interrupts, which answer the
+//! configuration commands from host tools. Install it in [EEPROM] once the boot-time settings
+//! are read.
+
+use core::cell::RefCell;
+
+use avr_device::interrupt::{self, Mutex};
+
+use crate::config::ConfigError;
+use crate::layers::{self, AtreusKeymap, Keymap, COLS, NUM_LAYERS, ROWS};
+use crate::output_report::{self, OutputReport};
+use crate::storage::EepromStorage;
+use crate::stored_keymap;
+
+/// Global EEPROM, shared by the main loop and the USB interrupts.
+pub static EEPROM: Mutex<RefCell<Option<DeviceEeprom>>> = Mutex::new(RefCell::new(None));
+
+/// [EepromStorage] backed by the ATmega32u4 EEPROM.
+pub struct DeviceEeprom(arduino_hal::Eeprom);
+
+impl DeviceEeprom {
+    /// Creates a new [DeviceEeprom].
+    pub const fn new(eeprom: arduino_hal::Eeprom) -> Self {
+        Self(eeprom)
+    }
+}
+
+impl EepromStorage for DeviceEeprom {
+    fn load(&self, offset: usize, buf: &mut [u8]) -> Result<(), ConfigError> {
+        self.0
+            .read(offset as u16, buf)
+            .map_err(|_| ConfigError::InvalidArgument)
+    }
+
+    fn store(&mut self, offset: usize, data: &[u8]) -> Result<(), ConfigError> {
+        self.0
+            .write(offset as u16, data)
+            .map_err(|_| ConfigError::InvalidArgument)
+    }
+}
+
+/// Calls `f` with the global [EEPROM].
+///
+/// Returns `None` if the EEPROM is not installed yet.
+pub fn with_eeprom<R>(f: impl FnOnce(&mut DeviceEeprom) -> R) -> Option<R> {
+    interrupt::free(|cs| EEPROM.borrow(cs).borrow_mut().as_mut().map(f))
+}
+
+/// Handles the [configuration commands](crate::config::Command) on the raw HID interface, with
+/// the global [EEPROM], see [handle_config_report](output_report::handle_config_report).
+///
+/// Answers nothing until the EEPROM is installed.
+pub fn handle_config_report(report: &OutputReport, response: &mut [u8]) -> usize {
+    with_eeprom(|eeprom| output_report::handle_config_report(eeprom, report, response)).unwrap_or(0)
+}
+
+/// [Keymap] for the Atreus, reading the stored key map, with the default layers as fallback.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct EepromKeymap;
+
+impl Keymap<ROWS, COLS> for EepromKeymap {
+    fn layer_key(layer: usize, row: usize, col: usize) -> u8 {
+        let stored = if layer < NUM_LAYERS && row < ROWS && col < COLS {
+            let position = layer * ROWS * COLS + layers::layer_index(row, col);
+            with_eeprom(|eeprom| stored_keymap::stored_key(eeprom, position)).flatten()
+        } else {
+            None
+        };
+
+        stored.unwrap_or_else(|| AtreusKeymap::layer_key(layer, row, col))
+    }
+}
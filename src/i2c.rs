@@ -0,0 +1,174 @@
+//! Blocking I2C master on the TWI peripheral.
+//!
+//! Displays, sensors, and the [split link](crate::split_link) share the I2C pins (`PD0` SCL, `PD1`
+//! SDA), which are free on the Atreus.
+//! Transfers busy-wait on the bus, about 25 microseconds per byte at [I2C_FREQUENCY_HZ], and give
+//! up after [I2C_TIMEOUT_POLLS] polls of a stuck bus instead of hanging the main loop.
+//!
+//! Keep the [Twi](crate::power::Peripheral::Twi) powered in the board's
+//! [PowerReduction](crate::power::PowerReduction).
+
+use arduino_hal::pac;
+
+use crate::F_CPU;
+
+/// I2C clock frequency (fast mode).
+pub const I2C_FREQUENCY_HZ: u32 = 400_000;
+/// Number of status polls before a transfer step times out.
+pub const I2C_TIMEOUT_POLLS: u16 = 2000;
+
+/// Bit rate register value for [I2C_FREQUENCY_HZ], with the prescaler left at 1.
+const TWBR_VALUE: u8 = ((F_CPU / I2C_FREQUENCY_HZ - 16) / 2) as u8;
+
+// TWI status codes of a master transmitter.
+const STATUS_START: u8 = 0x08;
+const STATUS_REPEATED_START: u8 = 0x10;
+const STATUS_ADDR_ACK: u8 = 0x18;
+const STATUS_ADDR_NACK: u8 = 0x20;
+const STATUS_DATA_ACK: u8 = 0x28;
+const STATUS_DATA_NACK: u8 = 0x30;
+const STATUS_ARBITRATION_LOST: u8 = 0x38;
+// TWI status codes of a master receiver.
+const STATUS_READ_ADDR_ACK: u8 = 0x40;
+const STATUS_READ_ADDR_NACK: u8 = 0x48;
+const STATUS_READ_DATA_ACK: u8 = 0x50;
+const STATUS_READ_DATA_NACK: u8 = 0x58;
+
+/// Errors from an I2C transfer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum I2cError {
+    /// No device acknowledged the address.
+    AddressNack,
+    /// The device did not acknowledge a data byte.
+    DataNack,
+    /// Another master took the bus.
+    ArbitrationLost,
+    /// The bus did not respond, e.g. without pull-up resistors.
+    Timeout,
+    /// Unexpected bus state.
+    Bus(u8),
+}
+
+/// Blocking I2C master, see the [module](self) docs.
+pub struct I2cMaster {
+    twi: pac::TWI,
+}
+
+impl I2cMaster {
+    /// Creates a new [I2cMaster], and sets the bus clock to [I2C_FREQUENCY_HZ].
+    ///
+    /// The internal pull-ups of the I2C pins are enabled, most modules also have their own.
+    pub fn new(twi: pac::TWI) -> Self {
+        // Safety: only the bits of the I2C pins are changed, from the main loop like the key
+        // matrix pins.
+        unsafe {
+            (*pac::PORTD::ptr())
+                .portd
+                .modify(|r, w| w.bits(r.bits() | 0b11));
+        }
+
+        twi.twbr.write(|w| w.bits(TWBR_VALUE));
+
+        Self { twi }
+    }
+
+    /// Writes the `bytes` to the device at the 7-bit `addr`.
+    pub fn write<I: IntoIterator<Item = u8>>(
+        &mut self,
+        addr: u8,
+        bytes: I,
+    ) -> Result<(), I2cError> {
+        let result = self.start().and_then(|_| {
+            self.send(addr << 1)?;
+
+            for byte in bytes {
+                self.send(byte)?;
+            }
+
+            Ok(())
+        });
+
+        self.stop();
+
+        result
+    }
+
+    /// Reads `buf.len()` bytes from the device at the 7-bit `addr`.
+    pub fn read(&mut self, addr: u8, buf: &mut [u8]) -> Result<(), I2cError> {
+        let result = self.start().and_then(|_| {
+            self.send((addr << 1) | 1)?;
+
+            let len = buf.len();
+            for (i, byte) in buf.iter_mut().enumerate() {
+                // the last byte is not acknowledged, which ends the transfer
+                *byte = self.receive(i + 1 < len)?;
+            }
+
+            Ok(())
+        });
+
+        self.stop();
+
+        result
+    }
+
+    // Sends a start condition.
+    fn start(&mut self) -> Result<(), I2cError> {
+        self.twi
+            .twcr
+            .write(|w| w.twint().set_bit().twsta().set_bit().twen().set_bit());
+
+        match self.wait()? {
+            STATUS_START | STATUS_REPEATED_START => Ok(()),
+            STATUS_ARBITRATION_LOST => Err(I2cError::ArbitrationLost),
+            status => Err(I2cError::Bus(status)),
+        }
+    }
+
+    // Sends an address or data byte, and checks the acknowledge.
+    fn send(&mut self, byte: u8) -> Result<(), I2cError> {
+        self.twi.twdr.write(|w| w.bits(byte));
+        self.twi
+            .twcr
+            .write(|w| w.twint().set_bit().twen().set_bit());
+
+        match self.wait()? {
+            STATUS_ADDR_ACK | STATUS_READ_ADDR_ACK | STATUS_DATA_ACK => Ok(()),
+            STATUS_ADDR_NACK | STATUS_READ_ADDR_NACK => Err(I2cError::AddressNack),
+            STATUS_ARBITRATION_LOST => Err(I2cError::ArbitrationLost),
+            STATUS_DATA_NACK => Err(I2cError::DataNack),
+            status => Err(I2cError::Bus(status)),
+        }
+    }
+
+    // Receives a data byte, and acknowledges it if more bytes follow.
+    fn receive(&mut self, ack: bool) -> Result<u8, I2cError> {
+        self.twi
+            .twcr
+            .write(|w| w.twint().set_bit().twea().bit(ack).twen().set_bit());
+
+        match self.wait()? {
+            STATUS_READ_DATA_ACK | STATUS_READ_DATA_NACK => Ok(self.twi.twdr.read().bits()),
+            STATUS_ARBITRATION_LOST => Err(I2cError::ArbitrationLost),
+            status => Err(I2cError::Bus(status)),
+        }
+    }
+
+    // Sends a stop condition, the hardware releases the bus on its own.
+    fn stop(&mut self) {
+        self.twi
+            .twcr
+            .write(|w| w.twint().set_bit().twsto().set_bit().twen().set_bit());
+    }
+
+    // Waits for the current step, and returns the bus status.
+    fn wait(&self) -> Result<u8, I2cError> {
+        for _ in 0..I2C_TIMEOUT_POLLS {
+            if self.twi.twcr.read().twint().bit_is_set() {
+                return Ok(self.twi.twsr.read().tws().bits() << 3);
+            }
+        }
+
+        Err(I2cError::Timeout)
+    }
+}
@@ -0,0 +1,98 @@
+use arduino_hal::pac;
+
+use crate::host_leds::{self, HostLedState};
+use crate::indicator::{Indicator, MAX_INDICATORS};
+use crate::pin_map::{PinId, PinMap, Port};
+
+/// Sets or clears a bit of a port register, must be used in an `unsafe` block.
+macro_rules! write_bit {
+    ($reg:expr, $bit:expr, $val:expr) => {
+        $reg.modify(|r, w| {
+            w.bits(if $val {
+                r.bits() | (1 << $bit)
+            } else {
+                r.bits() & !(1 << $bit)
+            })
+        })
+    };
+}
+
+/// Represents the [Indicator] LEDs of a board, following the host LED state.
+///
+/// The key matrix owns the HAL pins, so the LEDs are driven through the port registers instead.
+/// Indicators on key matrix pins are left out, see [is_valid](Indicator::is_valid).
+pub struct IndicatorLeds {
+    indicators: [Option<Indicator>; MAX_INDICATORS],
+    leds: Option<HostLedState>,
+}
+
+impl IndicatorLeds {
+    /// Creates a new [IndicatorLeds], and configures the pins of the `indicators` as outputs.
+    ///
+    /// At most [MAX_INDICATORS] are driven.
+    pub fn new(indicators: &[Indicator], map: &PinMap) -> Self {
+        let mut valid = [None; MAX_INDICATORS];
+
+        for (slot, indicator) in valid
+            .iter_mut()
+            .zip(indicators.iter().filter(|i| i.is_valid(map)))
+        {
+            write_pin(indicator.pin(), indicator.pin_high(HostLedState::new()));
+            set_output(indicator.pin());
+
+            *slot = Some(*indicator);
+        }
+
+        Self {
+            indicators: valid,
+            leds: None,
+        }
+    }
+
+    /// Updates the LEDs, if the host LED state changed.
+    pub fn update(&mut self) {
+        let leds = host_leds::host_leds();
+
+        if self.leds != Some(leds) {
+            for indicator in self.indicators.iter().flatten() {
+                write_pin(indicator.pin(), indicator.pin_high(leds));
+            }
+
+            self.leds = Some(leds);
+        }
+    }
+}
+
+// Configures the pin as an output.
+fn set_output(pin: PinId) {
+    let bit = pin.bit();
+
+    // Safety: only the bit of the indicator pin is changed, from the main loop like the key
+    // matrix pins.
+    unsafe {
+        match pin.port() {
+            Port::B => write_bit!((*pac::PORTB::ptr()).ddrb, bit, true),
+            Port::C => write_bit!((*pac::PORTC::ptr()).ddrc, bit, true),
+            Port::D => write_bit!((*pac::PORTD::ptr()).ddrd, bit, true),
+            Port::E => write_bit!((*pac::PORTE::ptr()).ddre, bit, true),
+            Port::F => write_bit!((*pac::PORTF::ptr()).ddrf, bit, true),
+        }
+    }
+}
+
+// Drives the pin high or low.
+fn write_pin(pin: PinId, high: bool) {
+    let bit = pin.bit();
+
+    // Safety: only the bit of the indicator pin is changed, from the main loop like the key
+    // matrix pins.
+    unsafe {
+        match pin.port() {
+            Port::B => write_bit!((*pac::PORTB::ptr()).portb, bit, high),
+            Port::C => write_bit!((*pac::PORTC::ptr()).portc, bit, high),
+            Port::D => write_bit!((*pac::PORTD::ptr()).portd, bit, high),
+            Port::E => write_bit!((*pac::PORTE::ptr()).porte, bit, high),
+            Port::F => write_bit!((*pac::PORTF::ptr()).portf, bit, high),
+        }
+    }
+}
@@ -1,21 +1,280 @@
+use core::marker::PhantomData;
+
 use arduino_hal::hal::port::Pins;
+use arduino_hal::pac;
 use arduino_hal::port::{
-    mode::{Input, Output, PullUp},
+    mode::{Floating, Input, Output, PullUp},
     Pin,
 };
+use embedded_hal::blocking::delay::DelayUs;
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+
+use crate::pin_map::{PinId, PinMap, Port, NUM_PINS};
+use crate::{layers, RowState, F_CPU, MAX_COLS};
+
+pub use crate::scanner::{MatrixReader, MatrixScanner, DEFAULT_SETTLE_US};
+
+/// Number of rows in the Atreus key matrix.
+pub const ROWS: usize = layers::ROWS;
+/// Number of columns in the Atreus key matrix.
+pub const COLS: usize = layers::COLS;
+
+/// Prescaler of the settle timer clock.
+pub const SETTLE_TIMER_PRESCALER: u32 = 8;
+
+/// Number of settle timer ticks per microsecond.
+pub const SETTLE_TICKS_PER_US: u16 = (F_CPU / SETTLE_TIMER_PRESCALER / 1_000_000) as u16;
+
+/// Microsecond delay based on the free-running TC3 hardware timer.
+///
+/// Unlike spinning on NOPs, the delay does not depend on the compiler output or the CPU clock.
+/// The timer is started by [setup_settle_timer](crate::setup_settle_timer).
+///
+/// The same timer measures short durations, e.g. for [timing](crate::timing) instrumentation.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SettleTimer;
+
+impl SettleTimer {
+    /// Gets the timer count, which advances [SETTLE_TICKS_PER_US] ticks per microsecond.
+    pub fn ticks() -> u16 {
+        // Safety: TC3 is free-running after setup, and only its counter is read here.
+        unsafe { &*pac::TC3::ptr() }.tcnt3.read().bits()
+    }
+
+    /// Gets the microseconds elapsed since the `start` ticks.
+    ///
+    /// The timer wraps, so durations are only measured up to `u16::MAX / SETTLE_TICKS_PER_US`
+    /// microseconds.
+    pub fn elapsed_us(start: u16) -> u16 {
+        Self::ticks().wrapping_sub(start) / SETTLE_TICKS_PER_US
+    }
+}
 
-pub const ROWS: usize = 4;
-pub const COLS: usize = 12;
+impl DelayUs<u16> for SettleTimer {
+    fn delay_us(&mut self, us: u16) {
+        let ticks = us.saturating_mul(SETTLE_TICKS_PER_US);
+        let start = Self::ticks();
+
+        while Self::ticks().wrapping_sub(start) < ticks {}
+    }
+}
+
+/// Diode direction of the key matrix wiring.
+pub trait DiodeDirection {}
+
+/// Diodes point from the columns to the rows (cathodes on the rows).
+///
+/// Rows are driven low, and columns are pull-up inputs.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Col2Row;
+
+impl DiodeDirection for Col2Row {}
+
+/// Diodes point from the rows to the columns (cathodes on the columns).
+///
+/// Columns are driven low, and rows are pull-up inputs.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Row2Col;
+
+impl DiodeDirection for Row2Col {}
 
 /// Represents the rows and columns of the key matrix.
 ///
-/// Rows are made of `Output` pins that are driven low to "activate" them.
+/// With the default [Col2Row] wiring, rows are made of `Output` pins that are driven low to
+/// "activate" them, and columns are made of `Input` pins connected to pull-up resistors, that are
+/// driven low when a key is pressed.
+///
+/// Boards with reversed diodes use [Row2Col], where the columns are the `Output` pins and the rows
+/// are the pull-up `Input` pins.
 ///
-/// Columns are made of `Input` pins connected to pull-up resistors, that are driven low when a key
-/// is pressed.
-pub struct KeyMatrix {
-    pub(crate) rows: [Pin<Output>; ROWS],
-    pub(crate) cols: [Pin<Input<PullUp>>; COLS],
+/// The matrix dimensions default to the Atreus [ROWS] x [COLS] layout. Other boards can use
+/// [from_pins](Self::from_pins) with their own dimensions, up to [MAX_COLS] columns.
+///
+/// The pins default to the AVR HAL pin types, but any `embedded-hal` [OutputPin] and [InputPin]
+/// implementations work, e.g. mock pins for testing off-target.
+///
+/// After activating a row (or column), inputs are read once they had [settle_us](Self::settle_us)
+/// to settle, timed by the [SettleTimer] by default. Any `embedded-hal` [DelayUs] works instead.
+pub struct KeyMatrix<
+    const ROWS: usize = { layers::ROWS },
+    const COLS: usize = { layers::COLS },
+    R = Pin<Output>,
+    C = Pin<Input<PullUp>>,
+    D: DiodeDirection = Col2Row,
+    T = SettleTimer,
+> {
+    pub(crate) rows: [R; ROWS],
+    pub(crate) cols: [C; COLS],
+    settle: T,
+    settle_us: u16,
+    _direction: PhantomData<D>,
+}
+
+impl<const ROWS: usize, const COLS: usize, R, C, D: DiodeDirection, T>
+    KeyMatrix<ROWS, COLS, R, C, D, T>
+{
+    const VALID_COLS: () = assert!(COLS <= MAX_COLS, "too many columns for a RowState");
+
+    /// Creates a new [KeyMatrix] from row and column pins.
+    ///
+    /// For [Col2Row] wiring, rows are output pins and columns are pull-up input pins. For
+    /// [Row2Col] wiring, it is the other way around.
+    pub fn from_pins(rows: [R; ROWS], cols: [C; COLS]) -> Self
+    where
+        T: Default,
+    {
+        #[allow(clippy::let_unit_value)]
+        let _ = Self::VALID_COLS;
+
+        Self {
+            rows,
+            cols,
+            settle: T::default(),
+            settle_us: DEFAULT_SETTLE_US,
+            _direction: PhantomData,
+        }
+    }
+
+    /// Builder function that replaces the delay used to let inputs settle.
+    pub fn with_settle_delay<T2>(self, settle: T2) -> KeyMatrix<ROWS, COLS, R, C, D, T2> {
+        KeyMatrix {
+            rows: self.rows,
+            cols: self.cols,
+            settle,
+            settle_us: self.settle_us,
+            _direction: PhantomData,
+        }
+    }
+
+    /// Gets the time (in microseconds) for an input pin to settle before it is read.
+    pub const fn settle_us(&self) -> u16 {
+        self.settle_us
+    }
+
+    /// Sets the time (in microseconds) for an input pin to settle before it is read.
+    ///
+    /// Tune it per board: long traces or weak pull-ups need more time, while short settle times
+    /// shorten every matrix scan.
+    pub fn set_settle_us(&mut self, val: u16) {
+        self.settle_us = val;
+    }
+
+    /// Builder function that sets the time (in microseconds) for an input pin to settle.
+    pub fn with_settle_us(mut self, val: u16) -> Self {
+        self.set_settle_us(val);
+        self
+    }
+
+    /// Gets a reference to the row pins.
+    pub fn rows(&self) -> &[R] {
+        self.rows.as_ref()
+    }
+
+    /// Gets a mutable reference to the row pins.
+    pub fn rows_mut(&mut self) -> &mut [R] {
+        self.rows.as_mut()
+    }
+
+    /// Gets a reference to the column pins.
+    pub fn cols(&self) -> &[C] {
+        self.cols.as_ref()
+    }
+
+    /// Gets a mutable reference to the column pins.
+    pub fn cols_mut(&mut self) -> &mut [C] {
+        self.cols.as_mut()
+    }
+}
+
+impl<const ROWS: usize, const COLS: usize, R: OutputPin, C: InputPin, T: DelayUs<u16>>
+    MatrixReader<ROWS, COLS> for KeyMatrix<ROWS, COLS, R, C, Col2Row, T>
+{
+    fn read_row(&mut self, row: usize) -> RowState {
+        let mut hot_pins = RowState::new();
+
+        if let Some(row) = self.rows.get_mut(row) {
+            // pull the row pin low to "activate" the row
+            row.set_low().ok();
+            // wait for the column pins to settle, once for the whole row
+            self.settle.delay_us(self.settle_us);
+
+            for (j, col) in self.cols.iter().enumerate() {
+                // if the column pin is low, the key was pressed
+                if col.is_low().unwrap_or(false) {
+                    hot_pins.set_column(j, true);
+                }
+            }
+
+            // pull the row pin high to "deactivate" the row, and avoid electrical interference
+            // with following reads
+            row.set_high().ok();
+        }
+
+        hot_pins
+    }
+
+    fn prepare_idle(&mut self) -> bool {
+        // "activate" every row, so a key press anywhere pulls its column low
+        for row in self.rows.iter_mut() {
+            row.set_low().ok();
+        }
+
+        true
+    }
+
+    fn resume_scan(&mut self) {
+        for row in self.rows.iter_mut() {
+            row.set_high().ok();
+        }
+    }
+
+    fn set_settle_us(&mut self, val: u16) {
+        self.settle_us = val;
+    }
+}
+
+impl<const ROWS: usize, const COLS: usize, R: InputPin, C: OutputPin, T: DelayUs<u16>>
+    MatrixReader<ROWS, COLS> for KeyMatrix<ROWS, COLS, R, C, Row2Col, T>
+{
+    fn read_row(&mut self, row: usize) -> RowState {
+        let mut hot_pins = RowState::new();
+
+        if let Some(row) = self.rows.get(row) {
+            for (j, col) in self.cols.iter_mut().enumerate() {
+                // pull the column pin low to "activate" the column
+                col.set_low().ok();
+                // wait for a stable read of the input pin
+                self.settle.delay_us(self.settle_us);
+                // if the row pin is low, the key was pressed
+                if row.is_low().unwrap_or(false) {
+                    hot_pins.set_column(j, true);
+                }
+                // pull the column pin high to "deactivate" the column
+                col.set_high().ok();
+            }
+        }
+
+        hot_pins
+    }
+
+    fn prepare_idle(&mut self) -> bool {
+        // "activate" every column, so a key press anywhere pulls its row low
+        for col in self.cols.iter_mut() {
+            col.set_low().ok();
+        }
+
+        true
+    }
+
+    fn resume_scan(&mut self) {
+        for col in self.cols.iter_mut() {
+            col.set_high().ok();
+        }
+    }
+
+    fn set_settle_us(&mut self, val: u16) {
+        self.settle_us = val;
+    }
 }
 
 impl KeyMatrix {
@@ -30,67 +289,50 @@ impl KeyMatrix {
     /// After the row has been scanned, its pin is reset to pulled-high, and the process repeats for every
     /// other row. This all happens very quickly, so it appears simultaneous.
     ///
+    /// The pins are assigned with the [Atreus](PinMap::atreus) [PinMap], see
+    /// [from_pin_map](Self::from_pin_map) for other wirings.
+    ///
     /// For more information, see the great writeup by [Technomancy](https://atreus.technomancy.us/firmware).
     pub fn new(pins: Pins) -> Self {
-        Self {
-            rows: [
-                // Row 0
-                pins.pf6.into_output().downgrade(),
-                // Row 1
-                pins.pf5.into_output().downgrade(),
-                // Row 3
-                pins.pf4.into_output().downgrade(),
-                // Row 4
-                pins.pf1.into_output().downgrade(),
-            ],
-            cols: [
-                // Col 0
-                pins.pf7.into_pull_up_input().downgrade(),
-                // Col 1
-                pins.pe2.into_pull_up_input().downgrade(),
-                // Col 2
-                pins.pc7.into_pull_up_input().downgrade(),
-                // Col 3
-                pins.pc6.into_pull_up_input().downgrade(),
-                // Col 4
-                pins.pb6.into_pull_up_input().downgrade(),
-                // Col 5
-                pins.pb5.into_pull_up_input().downgrade(),
-                // Col 6 is a blank column
-                //pins.pb4.into_pull_up_input().downgrade(),
-                // Col 7
-                pins.pd7.into_pull_up_input().downgrade(),
-                // Col 8
-                pins.pd6.into_pull_up_input().downgrade(),
-                // Col 9
-                pins.pd4.into_pull_up_input().downgrade(),
-                // Col 10
-                pins.pd5.into_pull_up_input().downgrade(),
-                // Col 11
-                pins.pd3.into_pull_up_input().downgrade(),
-                // Col 12
-                pins.pd2.into_pull_up_input().downgrade(),
-            ],
-        }
+        Self::from_pin_map(pins, &PinMap::atreus())
     }
 
-    /// Gets a reference to the row pins.
-    pub fn rows(&self) -> &[Pin<Output>] {
-        self.rows.as_ref()
-    }
+    /// Creates a new [KeyMatrix], with the rows and columns assigned to pins by the [PinMap].
+    ///
+    /// Lets alternative wirings, e.g. loaded from EEPROM, run with the same firmware binary.
+    pub fn from_pin_map(pins: Pins, map: &PinMap) -> Self {
+        let mut pins = dynamic_pins(pins);
 
-    /// Gets a mutable reference to the row pins.
-    pub fn rows_mut(&mut self) -> &mut [Pin<Output>] {
-        self.rows.as_mut()
-    }
+        // the pin map is validated on creation, so every pin is available exactly once
+        let mut take = |pin: &PinId| pins[pin.index()].take().expect("valid pin map");
 
-    /// Gets a reference to the column pins.
-    pub fn cols(&self) -> &[Pin<Input<PullUp>>] {
-        self.cols.as_ref()
+        let rows = map.rows().each_ref().map(|pin| take(pin).into_output());
+        let cols = map
+            .cols()
+            .each_ref()
+            .map(|pin| take(pin).into_pull_up_input());
+
+        Self::from_pins(rows, cols)
     }
+}
 
-    /// Gets a mutable reference to the column pins.
-    pub fn cols_mut(&mut self) -> &mut [Pin<Input<PullUp>>] {
-        self.cols.as_mut()
+/// Converts the [Pins] into a table of dynamic pins, indexed by [PinId::index].
+pub(crate) fn dynamic_pins(pins: Pins) -> [Option<Pin<Input<Floating>>>; NUM_PINS] {
+    let mut table = [const { None }; NUM_PINS];
+
+    macro_rules! insert {
+        ($($pin:ident: $port:ident $bit:literal),+ $(,)?) => {
+            $(table[PinId::new(Port::$port, $bit).index()] = Some(pins.$pin.downgrade());)+
+        };
     }
+
+    insert!(
+        pb0: B 0, pb1: B 1, pb2: B 2, pb3: B 3, pb4: B 4, pb5: B 5, pb6: B 6, pb7: B 7,
+        pc6: C 6, pc7: C 7,
+        pd0: D 0, pd1: D 1, pd2: D 2, pd3: D 3, pd4: D 4, pd5: D 5, pd6: D 6, pd7: D 7,
+        pe2: E 2, pe6: E 6,
+        pf0: F 0, pf1: F 1, pf4: F 4, pf5: F 5, pf6: F 6, pf7: F 7,
+    );
+
+    table
 }
@@ -2,25 +2,47 @@
 //!
 //! Types and functionality for scanning the key matrix, and debouncing key activation state.
 
-use core::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not};
+use core::marker::PhantomData;
 use core::sync::atomic::{AtomicBool, Ordering};
 
-use avr_device::asm;
 use usbd_hid::descriptor::KeyboardReport;
 
-use crate::{key_matrix::KeyMatrix, layers};
-
-/// Maximum number of columns of in a [RowState].
-pub const MAX_COLS: usize = 16;
-
-/// Blank [KeyboardReport].
-pub const BLANK_REPORT: KeyboardReport = KeyboardReport {
-    modifier: 0,
-    reserved: 0,
-    leds: 0,
-    keycodes: [0; 6],
+use crate::{
+    audio,
+    auto_shift::AutoShift,
+    bridge,
+    caps_word::CapsWord,
+    consumer,
+    debounce::Debounce,
+    diagnostics::{self, Diagnostic},
+    events::{EventQueue, KeyEvent},
+    factory_reset::{self, ComboHold},
+    features::{self, Feature},
+    gamepad::GamepadReport,
+    ghosting,
+    hooks::Hooks,
+    key_matrix::{KeyMatrix, MatrixScanner},
+    layers::{self, Keymap},
+    macros::{self, Macro, MacroPlayer},
+    matrix_test,
+    mouse::MouseReport,
+    mouse_keys::MouseKeys,
+    nkro::{self, NkroReport},
+    one_shot::OneShot,
+    pipeline::Pipeline,
+    report::ReportBuilder,
+    scan_rate::AdaptiveScanRate,
+    status_leds::{self, StatusFlag},
+    system_control,
+    tap_hold::{TapCounter, TapHoldConfig, TapHoldKey, TapHoldKeys, TAPPING_TERM_MS},
+    time,
+    tuning::{self, Tuning},
+    turbo::Turbo,
 };
 
+pub use crate::debounce::{DebounceRowState, EagerPress, RowState, SymmetricDefer, MAX_COLS};
+pub use crate::report::BLANK_REPORT;
+
 static DO_SCAN: AtomicBool = AtomicBool::new(false);
 
 /// Gets whether to do a key matrix scan.
@@ -33,470 +55,806 @@ pub fn set_do_scan(val: bool) {
     DO_SCAN.store(val, Ordering::SeqCst);
 }
 
-bitfield! {
-    /// Activated status for a row of keys.
-    ///
-    /// Each row-column key is represented by a single bit to save space.
-    #[derive(Clone, Copy, Debug, Default, PartialEq)]
-    pub struct RowState(u16);
-
-    /// Getter/setter for column 0
-    pub col0, set_col0: 0;
-    /// Getter/setter for column 1
-    pub col1, set_col1: 1;
-    /// Getter/setter for column 2
-    pub col2, set_col2: 2;
-    /// Getter/setter for column 3
-    pub col3, set_col3: 3;
-    /// Getter/setter for column 4
-    pub col4, set_col4: 4;
-    /// Getter/setter for column 5
-    pub col5, set_col5: 5;
-    /// Getter/setter for column 6
-    pub col6, set_col6: 6;
-    /// Getter/setter for column 7
-    pub col7, set_col7: 7;
-    /// Getter/setter for column 8
-    pub col8, set_col8: 8;
-    /// Getter/setter for column 9
-    pub col9, set_col9: 9;
-    /// Getter/setter for column 10
-    pub col10, set_col10: 10;
-    /// Getter/setter for column 11
-    pub col11, set_col11: 11;
-    /// Getter/setter for column 12
-    pub col12, set_col12: 12;
-    /// Getter/setter for column 13
-    pub col13, set_col13: 13;
-    /// Getter/setter for column 14
-    pub col14, set_col14: 14;
-    /// Getter/setter for column 15
-    pub col15, set_col15: 15;
-}
+/// Handler for user-defined keys, called with the user key index and whether it was pressed.
+///
+/// Called from the matrix scan, so it should return quickly.
+pub type UserKeyHandler = fn(index: u8, pressed: bool);
 
-impl RowState {
-    /// Creates a new [RowState].
-    pub const fn new() -> Self {
-        Self(0)
-    }
-
-    /// Gets the underlying integer representation of the [RowState].
-    pub const fn as_inner(&self) -> u16 {
-        self.0
-    }
-
-    /// Creates a new [RowState] from a `u16`.
-    pub const fn from_u16(val: u16) -> Self {
-        Self(val)
-    }
-
-    /// Gets the column activation state.
-    pub fn column(&self, index: usize) -> bool {
-        match index % MAX_COLS {
-            0 => self.col0(),
-            1 => self.col1(),
-            2 => self.col2(),
-            3 => self.col3(),
-            4 => self.col4(),
-            5 => self.col5(),
-            6 => self.col6(),
-            7 => self.col7(),
-            8 => self.col8(),
-            9 => self.col9(),
-            10 => self.col10(),
-            11 => self.col11(),
-            12 => self.col12(),
-            13 => self.col13(),
-            14 => self.col14(),
-            _ => self.col15(),
-        }
-    }
-
-    /// Sets the column activation state.
-    pub fn set_column(&mut self, index: usize, val: bool) {
-        match index % MAX_COLS {
-            0 => self.set_col0(val),
-            1 => self.set_col1(val),
-            2 => self.set_col2(val),
-            3 => self.set_col3(val),
-            4 => self.set_col4(val),
-            5 => self.set_col5(val),
-            6 => self.set_col6(val),
-            7 => self.set_col7(val),
-            8 => self.set_col8(val),
-            9 => self.set_col9(val),
-            10 => self.set_col10(val),
-            11 => self.set_col11(val),
-            12 => self.set_col12(val),
-            13 => self.set_col13(val),
-            14 => self.set_col14(val),
-            _ => self.set_col15(val),
-        }
-    }
-
-    /// Gets whether the [RowState] has active columns.
-    pub const fn is_active(&self) -> bool {
-        self.0 != 0
-    }
-
-    /// Gets whether the [RowState] has no active columns.
-    pub const fn is_inactive(&self) -> bool {
-        self.0 == 0
-    }
+/// Represents the key matrix scanner for reading row and column pin sctivation.
+///
+/// Uses a debouncing algorithm to normalize reads, and avoid producing multiple reports for a
+/// single key press.
+///
+/// The matrix dimensions and [Keymap] default to the Atreus layout, and switches are read from a
+/// [KeyMatrix] by default. Any other [MatrixScanner], like [DirectPins](crate::DirectPins) or a
+/// third-party backend, produces the same [RowState]s for the rest of the pipeline.
+///
+/// Rows are debounced with the [SymmetricDefer] algorithm by default, boards with other switch
+/// types can select a different [Debounce] algorithm, e.g. [EagerPress] for the lowest press
+/// latency. The debounce window is set in milliseconds with
+/// [set_debounce_ms](Self::set_debounce_ms).
+///
+/// Debounced changes are queued as [KeyEvent]s, and each press and release runs once through the
+/// dual-role key resolution ([TapHoldKeys]), the [Pipeline] of event handlers, and the layer, user
+/// key, and report stage, instead of comparing the full matrix state on every scan.
+///
+/// The runtime [features] (mouse keys, Auto Shift, Caps Word, and one-shot modifiers) are applied
+/// in the report stage, while they are enabled.
+pub struct KeyScanner<
+    const ROWS: usize = { layers::ROWS },
+    const COLS: usize = { layers::COLS },
+    K: Keymap<ROWS, COLS> = layers::AtreusKeymap,
+    M: MatrixScanner<ROWS, COLS> = KeyMatrix<ROWS, COLS>,
+    D: Debounce = SymmetricDefer,
+> {
+    matrix_pins: M,
+    matrix_state: [DebounceRowState<D>; ROWS],
+    do_scan: bool,
+    ghost_detection: bool,
+    turbo: Turbo,
+    scan_rate: Option<AdaptiveScanRate>,
+    scan_interval: Option<u16>,
+    idle_sleep_ms: Option<u16>,
+    last_active_ms: u16,
+    sleeping: bool,
+    user_handler: Option<UserKeyHandler>,
+    hooks: Hooks,
+    pipeline: Pipeline,
+    macros: &'static [Macro],
+    macro_player: MacroPlayer,
+    tap_hold: TapHoldKeys,
+    layer_taps: TapCounter,
+    mouse_keys: MouseKeys,
+    auto_shift: AutoShift,
+    caps_word: CapsWord,
+    one_shot: OneShot,
+    events: EventQueue,
+    events_pending: bool,
+    tapped: Option<KeyEvent>,
+    held: [[u8; COLS]; ROWS],
+    held_mods: [[u8; COLS]; ROWS],
+    report_shift: bool,
+    nkro_report: NkroReport,
+    mouse_report: MouseReport,
+    consumer_usage: u16,
+    gamepad_report: GamepadReport,
+    system_usage: u8,
+    raw_state: [RowState; ROWS],
+    test_events: EventQueue,
+    combo_held: bool,
+    reset_hold: ComboHold,
+    now_ms: u16,
+    _keymap: PhantomData<K>,
 }
 
-impl From<u16> for RowState {
-    fn from(val: u16) -> Self {
-        Self::from_u16(val)
+impl<const ROWS: usize, const COLS: usize, K, M, D> KeyScanner<ROWS, COLS, K, M, D>
+where
+    K: Keymap<ROWS, COLS>,
+    M: MatrixScanner<ROWS, COLS>,
+    D: Debounce + Copy + Default,
+{
+    pub fn new(matrix_pins: M) -> Self {
+        Self {
+            matrix_pins,
+            matrix_state: [DebounceRowState::new(); ROWS],
+            do_scan: true,
+            ghost_detection: false,
+            turbo: Turbo::new(),
+            scan_rate: None,
+            scan_interval: None,
+            idle_sleep_ms: None,
+            last_active_ms: 0,
+            sleeping: false,
+            user_handler: None,
+            hooks: Hooks::new(),
+            pipeline: Pipeline::new(),
+            macros: &[],
+            macro_player: MacroPlayer::new(),
+            tap_hold: TapHoldKeys::new(),
+            layer_taps: TapCounter::new(TAPPING_TERM_MS),
+            mouse_keys: MouseKeys::new(),
+            auto_shift: AutoShift::new(),
+            caps_word: CapsWord::new(),
+            one_shot: OneShot::new(),
+            events: EventQueue::new(),
+            events_pending: false,
+            tapped: None,
+            held: [[0; COLS]; ROWS],
+            held_mods: [[0; COLS]; ROWS],
+            report_shift: false,
+            nkro_report: NkroReport::new(),
+            mouse_report: MouseReport::new(),
+            consumer_usage: 0,
+            gamepad_report: GamepadReport::new(),
+            system_usage: 0,
+            raw_state: [RowState::new(); ROWS],
+            test_events: EventQueue::new(),
+            combo_held: false,
+            reset_hold: ComboHold::new(),
+            now_ms: 0,
+            _keymap: PhantomData,
+        }
     }
-}
 
-impl From<&u16> for RowState {
-    fn from(val: &u16) -> Self {
-        (*val).into()
+    /// Sets the handler for user-defined keys ([USER0](layers::USER0) - [USER15](layers::USER15)).
+    pub fn set_user_handler(&mut self, handler: UserKeyHandler) {
+        self.user_handler = Some(handler);
     }
-}
 
-impl From<&RowState> for u16 {
-    fn from(val: &RowState) -> Self {
-        val.as_inner()
+    /// Builder function that sets the handler for user-defined keys.
+    pub fn with_user_handler(mut self, handler: UserKeyHandler) -> Self {
+        self.set_user_handler(handler);
+        self
     }
-}
 
-impl From<RowState> for u16 {
-    fn from(val: RowState) -> Self {
-        (&val).into()
+    /// Sets the [Hooks] run for key events and built reports.
+    ///
+    /// The [AfterReportHook](crate::hooks::AfterReportHook)s run in the
+    /// [UsbContext](crate::UsbContext), which has its own copy.
+    pub fn set_hooks(&mut self, hooks: Hooks) {
+        self.hooks = hooks;
     }
-}
-
-impl BitAnd for RowState {
-    type Output = RowState;
 
-    fn bitand(self, rhs: Self) -> Self::Output {
-        (self.0 & rhs.0).into()
+    /// Builder function that sets the [Hooks] run for key events and built reports.
+    pub fn with_hooks(mut self, hooks: Hooks) -> Self {
+        self.set_hooks(hooks);
+        self
     }
-}
 
-impl BitAndAssign for RowState {
-    fn bitand_assign(&mut self, rhs: Self) {
-        self.0 &= rhs.0;
+    /// Sets the [Pipeline] of event handlers run before the report stage.
+    pub fn set_pipeline(&mut self, pipeline: Pipeline) {
+        self.pipeline = pipeline;
     }
-}
-
-impl BitOr for RowState {
-    type Output = RowState;
 
-    fn bitor(self, rhs: Self) -> Self::Output {
-        (self.0 | rhs.0).into()
+    /// Builder function that sets the [Pipeline] of event handlers run before the report stage.
+    pub fn with_pipeline(mut self, pipeline: Pipeline) -> Self {
+        self.set_pipeline(pipeline);
+        self
     }
-}
 
-impl BitOrAssign for RowState {
-    fn bitor_assign(&mut self, rhs: Self) {
-        self.0 |= rhs.0;
+    /// Sets the table of [Macro]s started with [request_macro](macros::request_macro).
+    pub fn set_macros(&mut self, macros: &'static [Macro]) {
+        self.macros = macros;
     }
-}
-
-impl BitXor for RowState {
-    type Output = RowState;
 
-    fn bitxor(self, rhs: Self) -> Self::Output {
-        (self.0 ^ rhs.0).into()
+    /// Builder function that sets the table of [Macro]s started with
+    /// [request_macro](macros::request_macro).
+    pub fn with_macros(mut self, macros: &'static [Macro]) -> Self {
+        self.set_macros(macros);
+        self
     }
-}
 
-impl BitXorAssign for RowState {
-    fn bitxor_assign(&mut self, rhs: Self) {
-        self.0 ^= rhs.0;
+    /// Starts playing a [Macro], stopping the one playing.
+    pub fn play_macro(&mut self, program: Macro) {
+        self.macro_player.play(program);
     }
-}
 
-impl Not for RowState {
-    type Output = RowState;
-
-    fn not(self) -> Self::Output {
-        (!self.0).into()
+    /// Gets the [MacroPlayer], e.g. to check whether a macro is playing.
+    pub const fn macro_player(&self) -> &MacroPlayer {
+        &self.macro_player
     }
-}
-
-/// Debounce state for the keyscanner matrix.
-#[derive(Clone, Copy, Debug, Default, PartialEq)]
-pub struct Debounce {
-    /// Debounce counter 0 bits for a given [RowState].
-    db0: RowState,
-    /// Debounce counter 1 bits for a given [RowState].
-    db1: RowState,
-    /// Debounced [RowState].
-    debounced: RowState,
-}
 
-impl Debounce {
-    /// Creates a new [Debounce] state.
-    pub const fn new() -> Self {
-        Self {
-            db0: RowState::new(),
-            db1: RowState::new(),
-            debounced: RowState::new(),
-        }
+    /// Sets the table of dual-role keys, indexed by the [TAP_HOLD0](layers::TAP_HOLD0) -
+    /// [TAP_HOLD7](layers::TAP_HOLD7) keycodes in the [Keymap].
+    pub fn set_tap_hold_keys(&mut self, keys: &'static [TapHoldKey]) {
+        self.tap_hold.set_keys(keys);
     }
 
-    /// Gets the debounce counter bit zero.
-    pub const fn db0(&self) -> RowState {
-        self.db0
+    /// Builder function that sets the table of dual-role keys.
+    pub fn with_tap_hold_keys(mut self, keys: &'static [TapHoldKey]) -> Self {
+        self.set_tap_hold_keys(keys);
+        self
     }
 
-    /// Gets the debounce counter bit one.
-    pub const fn db1(&self) -> RowState {
-        self.db1
+    /// Sets the [TapHoldConfig] timings used to resolve dual-role keys.
+    pub fn set_tap_hold_config(&mut self, config: TapHoldConfig) {
+        self.tap_hold.set_config(config);
     }
 
-    /// Gets the debounced [RowState].
-    pub const fn debounced(&self) -> RowState {
-        self.debounced
+    /// Builder function that sets the [TapHoldConfig] timings used to resolve dual-role keys.
+    pub fn with_tap_hold_config(mut self, config: TapHoldConfig) -> Self {
+        self.set_tap_hold_config(config);
+        self
     }
 
-    /// Debounce the sampled [RowState].
-    pub fn debounce(&mut self, sample: RowState) -> RowState {
-        // Use xor to detect changes from last stable state:
-        // if a key has changed, its bit will be 1, otherwise 0
-        let delta = sample ^ self.debounced;
+    /// Sets the time (in milliseconds) a key is held before [Auto Shift](crate::auto_shift) sends
+    /// it shifted.
+    pub fn set_auto_shift_term_ms(&mut self, val: u16) {
+        self.auto_shift.set_term_ms(val);
+    }
 
-        // Increment counters and reset any unchanged bits:
-        // increment bit 1 for all changed keys
-        self.db1 = (self.db1 ^ self.db0) & delta;
-        // increment bit 0 for all changed keys
-        self.db0 = !self.db0 & delta;
+    /// Builder function that sets the time (in milliseconds) a key is held before Auto Shift
+    /// sends it shifted.
+    pub fn with_auto_shift_term_ms(mut self, val: u16) -> Self {
+        self.set_auto_shift_term_ms(val);
+        self
+    }
 
-        // Calculate returned change set: if delta is still true
-        // and the counter has wrapped back to 0, the key is changed.
-        let changes = !(!delta | self.db0 | self.db1);
-        // Update state: in this case use xor to flip any bit that is true in changes.
-        self.debounced ^= changes;
+    /// Sets the time (in milliseconds) between taps of a layer-lock key to count as a double-tap.
+    pub fn set_layer_tap_ms(&mut self, val: u16) {
+        self.layer_taps.set_tapping_term_ms(val);
+    }
 
-        changes
+    /// Sets the debounce window (in milliseconds) for every row.
+    ///
+    /// Raise it for chattery switches, or lower it to reduce latency for fast typing.
+    pub fn set_debounce_ms(&mut self, val: u16) {
+        for row_state in self.matrix_state.iter_mut() {
+            row_state.debouncer_mut().set_debounce_ms(val);
+        }
     }
-}
 
-/// Represents the previous, current, and debounced state for a given row.
-#[derive(Clone, Copy, Debug, Default, PartialEq)]
-pub struct DebounceRowState {
-    /// Previous [RowState].
-    previous: RowState,
-    /// Current [RowState].
-    current: RowState,
-    /// [Debounce]r for this [RowState].
-    debouncer: Debounce,
-}
+    /// Builder function that sets the debounce window (in milliseconds) for every row.
+    pub fn with_debounce_ms(mut self, val: u16) -> Self {
+        self.set_debounce_ms(val);
+        self
+    }
 
-impl DebounceRowState {
-    /// Creates a new [DebounceRowState].
-    pub const fn new() -> Self {
-        Self {
-            previous: RowState::new(),
-            current: RowState::new(),
-            debouncer: Debounce::new(),
-        }
+    /// Sets the time (in microseconds) for the matrix inputs to settle before they are read.
+    ///
+    /// The required settle time depends on the matrix capacitance and pull-up strength, so it is
+    /// tuned per board. Backends without a settle delay ignore it.
+    pub fn set_settle_us(&mut self, val: u16) {
+        self.matrix_pins.set_settle_us(val);
     }
 
-    /// Gets the previous [RowState].
-    pub const fn previous(&self) -> RowState {
-        self.previous
+    /// Builder function that sets the time (in microseconds) for the matrix inputs to settle.
+    pub fn with_settle_us(mut self, val: u16) -> Self {
+        self.set_settle_us(val);
+        self
     }
 
-    /// Sets the previous [RowState].
-    pub fn set_previous(&mut self, state: RowState) {
-        self.previous = state;
+    /// Applies the running [tuning] parameters: the debounce window, the settle time, the
+    /// tapping term, and the mouse key speeds.
+    ///
+    /// Call after the parameters change, e.g. from a host tool.
+    pub fn apply_tuning(&mut self) {
+        self.set_debounce_ms(tuning::tuning(Tuning::DebounceMs));
+        self.set_settle_us(tuning::tuning(Tuning::SettleUs));
+        self.set_layer_tap_ms(tuning::tuning(Tuning::TappingTermMs));
+        self.mouse_keys
+            .set_speed(tuning::tuning(Tuning::MouseSpeed));
+        self.mouse_keys
+            .set_wheel_speed(tuning::tuning(Tuning::WheelSpeed));
+    }
+
+    /// Sets the [AdaptiveScanRate] that slows scanning while the matrix is idle.
+    ///
+    /// Interval changes are requested through [take_scan_interval](Self::take_scan_interval).
+    pub fn set_adaptive_scan_rate(&mut self, rate: AdaptiveScanRate) {
+        self.scan_rate = Some(rate);
     }
 
-    /// Builder function that sets the previous [RowState].
-    pub fn with_previous(mut self, state: RowState) -> Self {
-        self.set_previous(state);
+    /// Builder function that sets the [AdaptiveScanRate].
+    pub fn with_adaptive_scan_rate(mut self, rate: AdaptiveScanRate) -> Self {
+        self.set_adaptive_scan_rate(rate);
         self
     }
 
-    /// Gets the current [RowState].
-    pub const fn current(&self) -> RowState {
-        self.current
+    /// Takes the scan interval (in microseconds) requested by the [AdaptiveScanRate], if it
+    /// changed since the last call.
+    ///
+    /// The caller applies the interval to the scan timer.
+    pub fn take_scan_interval(&mut self) -> Option<u16> {
+        self.scan_interval.take()
     }
 
-    /// Sets the current [RowState].
-    pub fn set_current(&mut self, state: RowState) {
-        self.current = state;
+    /// Sets the idle time (in milliseconds) before the matrix can sleep until a key press.
+    ///
+    /// `None` keeps the matrix scanning, which is the default.
+    pub fn set_idle_sleep_ms(&mut self, val: Option<u16>) {
+        self.idle_sleep_ms = val;
     }
 
-    /// Builder function that sets the current [RowState].
-    pub fn with_current(mut self, state: RowState) -> Self {
-        self.set_current(state);
+    /// Builder function that sets the idle time (in milliseconds) before the matrix can sleep.
+    pub fn with_idle_sleep_ms(mut self, val: Option<u16>) -> Self {
+        self.set_idle_sleep_ms(val);
         self
     }
 
-    /// Gets the `[Debounce]r` for the [RowState].
-    pub const fn debouncer(&self) -> Debounce {
-        self.debouncer
+    /// Gets whether the matrix is prepared for sleeping until a key press.
+    pub const fn sleeping(&self) -> bool {
+        self.sleeping
     }
 
-    /// Sets the `[Debounce]r` for the [RowState].
-    pub fn set_debouncer(&mut self, state: Debounce) {
-        self.debouncer = state;
+    /// Prepares the matrix for sleeping, if it has been idle for the idle sleep time, no macro is
+    /// playing, and no dual-role key is undecided or waiting to be released.
+    ///
+    /// Returns `true` if the caller can stop the scan timer, and wait for a pin-change interrupt
+    /// on the column pins. The next scan restores the matrix for scanning.
+    pub fn prepare_sleep(&mut self) -> bool {
+        let idle = self
+            .idle_sleep_ms
+            .is_some_and(|ms| self.now_ms.wrapping_sub(self.last_active_ms) >= ms)
+            // a playing macro needs the scan clock to advance
+            && !self.macro_player.is_playing()
+            // as do undecided dual-role keys, and releases held back to the next scan
+            && self.tap_hold.is_idle();
+
+        if idle {
+            self.prepare_sleep_now()
+        } else {
+            self.sleeping
+        }
     }
 
-    /// Builder function that sets the `[Debounce]r` for the [RowState].
-    pub fn with_debouncer(mut self, state: Debounce) -> Self {
-        self.set_debouncer(state);
-        self
+    /// Prepares the matrix for sleeping right away, e.g. while the USB bus is suspended.
+    ///
+    /// Only sleeps if an idle sleep time is set, since the board may not wake on every key
+    /// otherwise, see [set_idle_sleep_ms](Self::set_idle_sleep_ms).
+    pub fn prepare_sleep_now(&mut self) -> bool {
+        if self.idle_sleep_ms.is_some() && !self.sleeping {
+            self.sleeping = self.matrix_pins.prepare_idle();
+        }
+
+        self.sleeping
     }
-}
 
-/// Represents the key matrix scanner for reading row and column pin sctivation.
-///
-/// Uses a debouncing algorithm to normalize reads, and avoid producing multiple reports for a
-/// single key press.
-pub struct KeyScanner {
-    matrix_pins: KeyMatrix,
-    matrix_state: [DebounceRowState; layers::ROWS],
-    do_scan: bool,
-}
+    /// Sets the time (in milliseconds) that keys stay pressed (and released) while turbo is held.
+    pub fn set_turbo_half_period_ms(&mut self, val: u16) {
+        self.turbo.set_half_period_ms(val);
+    }
 
-fn small_delay(count: usize) {
-    for _ in 0..count {
-        asm::nop();
+    /// Sets whether rows with possible ghost keys are blocked, see [ghosting].
+    ///
+    /// Only needed for handwired or partially diode-less matrices.
+    pub fn set_ghost_detection(&mut self, val: bool) {
+        self.ghost_detection = val;
     }
-}
 
-impl KeyScanner {
-    pub fn new(matrix_pins: KeyMatrix) -> Self {
-        Self {
-            matrix_pins,
-            matrix_state: [DebounceRowState::new(); layers::ROWS],
-            do_scan: true,
-        }
+    /// Builder function that sets whether rows with possible ghost keys are blocked.
+    pub fn with_ghost_detection(mut self, val: bool) -> Self {
+        self.set_ghost_detection(val);
+        self
     }
 
     pub fn set_do_scan(&mut self, val: bool) {
         self.do_scan = val;
     }
 
-    /// Reads the [KeyMatrix] pins, and updates the debouncer state.
+    /// Reads the switch states from the [MatrixScanner], and updates the debouncer state.
+    ///
+    /// Debounced changes are queued as [KeyEvent]s for
+    /// [matrix_scan_report](Self::matrix_scan_report).
     pub fn read_matrix(&mut self) {
+        if self.sleeping {
+            self.matrix_pins.resume_scan();
+            self.sleeping = false;
+        }
+
         let mut any_debounced_changes = RowState::new();
+        let mut any_hot_pins = RowState::new();
 
-        for (i, row) in self.matrix_pins.rows.iter_mut().enumerate() {
-            // pull the row pin low to "activate" the row
-            row.set_low();
-
-            let mut hot_pins = RowState::new();
-            for (j, col) in self.matrix_pins.cols.iter().enumerate() {
-                // add a slight delay to allow for stable read of the input pin
-                small_delay(512);
-                // if the column pin is low, the key was pressed
-                if col.is_low() {
-                    hot_pins.set_column(j, true);
-                }
-            }
+        for (i, hot_pins) in self.matrix_pins.scan().into_iter().enumerate() {
+            any_hot_pins |= hot_pins;
 
-            // pull the row pin high to "deactivate" the row, and avoid electrical interference
-            // with following reads
-            row.set_high();
+            // stream raw switch changes in matrix test mode
+            self.raw_state[i] = if matrix_test::matrix_test_enabled() {
+                self.test_events
+                    .push_row_changes(i as u8, self.raw_state[i], hot_pins, self.now_ms)
+            } else {
+                hot_pins
+            };
 
             any_debounced_changes = RowState::from(
                 any_debounced_changes.as_inner()
-                    | self.matrix_state[i].debouncer.debounce(hot_pins).as_inner(),
+                    | self.matrix_state[i]
+                        .debouncer_mut()
+                        .debounce(hot_pins, self.now_ms)
+                        .as_inner(),
             );
         }
 
-        if any_debounced_changes.is_active() {
-            for s in 0..layers::ROWS {
-                let debounced = self.matrix_state[s].debouncer.debounced();
-                self.matrix_state[s].set_current(debounced);
+        // changes that did not fit in the event queue are queued on the next scan
+        if any_debounced_changes.is_active() || self.events_pending {
+            let mut debounced = [RowState::new(); ROWS];
+
+            for (row, row_state) in debounced.iter_mut().zip(self.matrix_state.iter_mut()) {
+                *row = row_state.debouncer_mut().debounced();
+            }
+
+            self.events_pending = false;
+
+            for (s, row_state) in self.matrix_state.iter_mut().enumerate() {
+                // keep the last state of rows that may contain a ghost key
+                if self.ghost_detection && ghosting::row_has_ghost(&debounced, s) {
+                    diagnostics::record(Diagnostic::Ghosting);
+                } else {
+                    let current = self.events.push_row_changes(
+                        s as u8,
+                        row_state.current(),
+                        debounced[s],
+                        self.now_ms,
+                    );
+
+                    self.events_pending |= current != debounced[s];
+                    row_state.set_current(current);
+                }
+            }
+        }
+
+        let combo_held = matrix_test::MAGIC_COMBO.iter().all(|&(row, col)| {
+            row < ROWS && col < COLS && self.matrix_state[row].current().column(col)
+        });
+
+        if combo_held && !self.combo_held {
+            matrix_test::toggle_matrix_test();
+        }
+
+        self.combo_held = combo_held;
+
+        let reset_held = factory_reset::FACTORY_RESET_COMBO
+            .iter()
+            .all(|&(row, col)| {
+                row < ROWS && col < COLS && self.matrix_state[row].current().column(col)
+            });
+
+        if self.reset_hold.update(reset_held, self.now_ms) {
+            factory_reset::request_factory_reset();
+        }
+
+        let active = any_hot_pins.is_active() || any_debounced_changes.is_active();
+
+        if active {
+            self.last_active_ms = self.now_ms;
+        }
+
+        if let Some(rate) = self.scan_rate.as_mut() {
+            if let Some(interval) = rate.update(active) {
+                self.scan_interval = Some(interval);
             }
         }
     }
 
-    /// Gets the debounced [KeyboardReports] from the most recent matrix scan.
-    pub fn matrix_scan_reports<const N: usize>(&mut self) -> [KeyboardReport; N] {
-        let mut reports = [BLANK_REPORT; N];
-        let mut report_idx = 0;
-        let mut keycodes = 0;
-        let mut fun_pressed = false;
-        let mut upper_pressed = false;
+    /// Resolves the keycode of a key press from the [Keymap], and hands the event to the
+    /// [TapHoldKeys], which replace dual-role keys with their tap or hold key.
+    ///
+    /// The keycode is resolved when the key is pressed, so layer changes while the key is held do
+    /// not change the keycode it releases.
+    fn tap_hold_event(&mut self, event: KeyEvent) {
+        let (row, col) = (event.row() as usize, event.col() as usize);
 
-        for (row, row_state) in self.matrix_state.iter_mut().enumerate().rev() {
-            for col in 0..layers::COLS {
-                if row_state.previous.column(col) || row_state.current.column(col) {
-                    let active_layer = layers::active_layer();
+        if row >= ROWS || col >= COLS {
+            return;
+        }
 
-                    // read the key value from the key map
-                    let key = layers::passthrough_key(
-                        active_layer.index(),
-                        layers::layer_index(row, col),
-                    );
+        let key = if event.pressed() {
+            K::passthrough_key(layers::active_layer().index(), row, col)
+        } else {
+            0
+        };
 
-                    if layers::key_is_fun(key) {
-                        // function key was pressed, switch layer based on the active layer
-                        match active_layer {
-                            layers::Layer::Base => layers::set_active_layer(layers::Layer::Fun),
-                            layers::Layer::Fun => active_layer,
-                            layers::Layer::Upper => layers::set_active_layer(layers::Layer::Base),
-                        };
-
-                        fun_pressed = true;
-                    } else if layers::key_is_upper(key) {
-                        // upper key was pressed, if on a non-upper layer, switch to upper layer
-                        // otherwise, switch to the layer below the upper layer
-                        match active_layer {
-                            layers::Layer::Base | layers::Layer::Fun => {
-                                layers::set_active_layer(layers::Layer::Upper)
-                            }
-                            layers::Layer::Upper => layers::set_active_layer(layers::Layer::from(
-                                active_layer.index().saturating_sub(1),
-                            )),
-                        };
-
-                        upper_pressed = true;
-                    } else if layers::key_is_shifted(key) {
-                        reports[report_idx].modifier |= layers::key_to_modifier(layers::SHIFT);
-                        reports[report_idx].keycodes = [layers::shifted_key(key), 0, 0, 0, 0, 0];
-
-                        report_idx += 1;
-                        keycodes = 0;
-                    } else if layers::key_is_modifier(key) {
-                        reports[report_idx].modifier |= layers::key_to_modifier(key);
-                    } else {
-                        reports[report_idx].keycodes[keycodes] = key;
-                        keycodes += 1;
-                    }
+        self.tap_hold.push(event, key);
+    }
 
-                    // if the current report has the max non-modifier keys, move to the next report
-                    if keycodes >= 6 {
-                        report_idx = (report_idx + 1) % N;
-                        keycodes = 0;
-                    }
+    /// Runs a key press or release resolved by the [TapHoldKeys] through the [Pipeline], and
+    /// hands it to the report stage.
+    fn handle_event(&mut self, event: KeyEvent, key: u8) {
+        let (row, col) = (event.row() as usize, event.col() as usize);
+
+        if !event.pressed() {
+            if let Some((press, key)) = self.auto_shift.release(&event) {
+                // tapped before the Auto Shift term, sent unshifted and released on the next scan
+                self.press_key(press, key, 0);
+                self.tapped = Some(event);
+                return;
+            }
+        }
+
+        let key = if event.pressed() {
+            key
+        } else {
+            self.held_mods[row][col] = 0;
+            // cleared first, so a consumed release can not leave the key held
+            core::mem::take(&mut self.held[row][col])
+        };
+
+        if let Some(key) = self.pipeline.run(&event, key) {
+            self.report_stage(event, key);
+        }
+    }
+
+    /// Final stage of the [Pipeline], handles layer and user keys, and tracks the keycode held
+    /// by the key for the reports.
+    fn report_stage(&mut self, event: KeyEvent, key: u8) {
+        if !event.pressed() {
+            self.hooks.on_key_event(&event, key);
+
+            if features::feature_enabled(Feature::OneShot) {
+                self.one_shot.release(key);
+                status_leds::set_status(StatusFlag::OneShotPending, self.one_shot.is_pending());
+            }
+
+            if layers::key_is_user(key) {
+                if let Some(handler) = self.user_handler {
+                    handler(layers::user_index(key), false);
                 }
             }
 
-            row_state.previous = row_state.current;
+            return;
         }
 
         let active_layer = layers::active_layer();
+        let mut key = key;
+
+        if layers::key_is_layer_lock(key)
+            && self.layer_taps.press(key, event.timestamp()) >= 2
+            && layers::double_tap_to_base()
+        {
+            // double-tapping a layer-lock key always returns to the base layer, and the key is
+            // ignored until it is released
+            layers::set_active_layer(layers::Layer::Base);
+            self.layer_taps.reset();
+            key = 0;
+        } else if layers::key_is_fun(key) {
+            // function key was pressed, switch layer based on the active layer
+            match active_layer {
+                layers::Layer::Base | layers::Layer::Numpad => {
+                    layers::set_active_layer(layers::Layer::Fun)
+                }
+                layers::Layer::Fun => active_layer,
+                layers::Layer::Upper => layers::set_active_layer(layers::Layer::Base),
+            };
+        } else if layers::key_is_upper(key) {
+            // upper key was pressed, if on a non-upper layer, switch to upper layer
+            // otherwise, switch to the layer below the upper layer
+            match active_layer {
+                layers::Layer::Base | layers::Layer::Fun | layers::Layer::Numpad => {
+                    layers::set_active_layer(layers::Layer::Upper)
+                }
+                layers::Layer::Upper => layers::set_active_layer(layers::Layer::from(
+                    active_layer.index().saturating_sub(1),
+                )),
+            };
+        } else if layers::key_is_user(key) {
+            if let Some(handler) = self.user_handler {
+                handler(layers::user_index(key), true);
+            }
+        } else if layers::key_is_serial(key) {
+            bridge::toggle_bridge();
+        } else if layers::key_is_nkro(key) {
+            nkro::toggle_report_mode();
+        } else if layers::key_is_audio(key) {
+            audio::handle_audio_key(key);
+        } else if layers::key_is_numpad(key) {
+            match active_layer {
+                layers::Layer::Numpad => layers::set_active_layer(layers::Layer::Base),
+                _ => layers::set_active_layer(layers::Layer::Numpad),
+            };
+        } else if active_layer == layers::Layer::Fun {
+            // the numeric cluster on the function layer sends the preferred digits
+            key = layers::numeric_key(key, layers::numeric_mode());
+        }
+
+        // a pending Auto Shift key is sent unshifted before the next key
+        if let Some((press, pending)) = self.auto_shift.take() {
+            self.press_key(press, pending, 0);
+        }
+
+        let flags = features::features();
+        let mut mods = 0;
+
+        if flags.is_enabled(Feature::CapsWord) {
+            mods |= self.caps_word.press(key);
+        } else {
+            self.caps_word.set_active(false);
+        }
+
+        if flags.is_enabled(Feature::OneShot) {
+            mods |= self.one_shot.press(key);
+        } else {
+            self.one_shot.clear();
+        }
+
+        status_leds::set_status(StatusFlag::CapsWord, self.caps_word.is_active());
+        status_leds::set_status(StatusFlag::OneShotPending, self.one_shot.is_pending());
+
+        // keys sent with modifiers already are not auto-shifted
+        if mods == 0 && flags.is_enabled(Feature::AutoShift) && self.auto_shift.press(event, key) {
+            return;
+        }
+
+        self.press_key(event, key, mods);
+    }
+
+    /// Holds the keycode of a key press for the reports, with the modifiers `mods` sent along
+    /// while the key is held.
+    fn press_key(&mut self, event: KeyEvent, key: u8, mods: u8) {
+        let (row, col) = (event.row() as usize, event.col() as usize);
+
+        self.held[row][col] = key;
+        self.held_mods[row][col] = mods;
+        self.hooks.on_key_event(&event, key);
+    }
+
+    /// Takes the next raw switch change recorded in [matrix_test] mode.
+    ///
+    /// Changes are recorded without debouncing, so builders can spot chattering switches.
+    pub fn pop_test_event(&mut self) -> Option<KeyEvent> {
+        self.test_events.pop()
+    }
+
+    /// Gets the [NkroReport] built by the most recent
+    /// [matrix_scan_report](Self::matrix_scan_report).
+    ///
+    /// Holds the same keys as the boot report, without the six-key limit.
+    pub const fn nkro_report(&self) -> &NkroReport {
+        &self.nkro_report
+    }
+
+    /// Gets the Consumer Control usage from the most recent matrix scan.
+    ///
+    /// Returns `0` if no [Consumer Control key](consumer) is held.
+    pub const fn consumer_usage(&self) -> u16 {
+        self.consumer_usage
+    }
+
+    /// Gets the [MouseReport] of the [mouse keys](crate::mouse_keys) from the most recent matrix
+    /// scan.
+    pub const fn mouse_report(&self) -> &MouseReport {
+        &self.mouse_report
+    }
+
+    /// Gets the [GamepadReport] from the most recent matrix scan.
+    pub const fn gamepad_report(&self) -> &GamepadReport {
+        &self.gamepad_report
+    }
+
+    /// Gets the System Control usage from the most recent matrix scan.
+    ///
+    /// Returns `0` if no [System Control key](system_control) is held.
+    pub const fn system_usage(&self) -> u8 {
+        self.system_usage
+    }
+
+    /// Gets the debounced [KeyboardReport] from the most recent matrix scan.
+    ///
+    /// Handles the queued [KeyEvent]s, and builds the report from the held keys.
+    pub fn matrix_scan_report(&mut self) -> KeyboardReport {
+        // keys tapped before the Auto Shift term were sent with the previous report
+        if let Some(event) = self.tapped.take() {
+            self.handle_event(event, 0);
+        }
+
+        // keys pressed and released in the previous scan are released now, so the host sees them
+        self.tap_hold.next_scan();
+
+        while let Some((event, key)) = self.tap_hold.pop() {
+            self.handle_event(event, key);
+        }
+
+        while let Some(event) = self.events.pop() {
+            self.tap_hold_event(event);
+
+            while let Some((event, key)) = self.tap_hold.pop() {
+                self.handle_event(event, key);
+            }
+        }
 
-        if active_layer == layers::Layer::Fun && !fun_pressed {
+        // dual-role keys held past the tapping term resolve to holds
+        self.tap_hold.tick(self.now_ms);
+
+        while let Some((event, key)) = self.tap_hold.pop() {
+            self.handle_event(event, key);
+        }
+
+        // keys held past the Auto Shift term are sent shifted
+        if let Some((press, key)) = self.auto_shift.tick(self.now_ms) {
+            self.press_key(press, key, layers::key_to_modifier(layers::SHIFT));
+        }
+
+        if let Some(program) =
+            macros::take_macro_request().and_then(|index| self.macros.get(index as usize))
+        {
+            self.macro_player.play(program);
+        }
+        self.macro_player.poll(self.now_ms);
+
+        // keys keep the Shift state of the previous report, see ReportBuilder
+        let mut builder = ReportBuilder::new()
+            .with_previous_shift(self.report_shift)
+            .with_shape_shifter(layers::SHAPE_SHIFTER);
+        let mut consumer_usage = 0;
+        let mut gamepad_report = GamepadReport::new();
+        let mut system_usage = 0;
+        let mut fun_pressed = false;
+        let mut turbo_pressed = false;
+
+        for (row, row_mods) in self.held.iter().zip(self.held_mods.iter()).rev() {
+            for (&key, &mods) in row.iter().zip(row_mods.iter()) {
+                // modifiers sent along with the key
+                for bit in 0..8 {
+                    if mods & (1 << bit) != 0 {
+                        builder.add_key(layers::CTRL + bit);
+                    }
+                }
+
+                if layers::key_is_fun(key) {
+                    fun_pressed = true;
+                } else if layers::key_is_turbo(key) {
+                    turbo_pressed = true;
+                } else if let Some(usage) = consumer::consumer_usage(key) {
+                    // one Consumer Control usage is sent at a time
+                    consumer_usage = usage;
+                } else if gamepad_report.add_key(key) {
+                    // sent on the gamepad interface
+                } else if let Some(usage) = system_control::system_usage(key) {
+                    system_usage = usage;
+                } else if self.mouse_keys.add_key(key) {
+                    // sent on the mouse interface
+                } else if let Some(modifier) = layers::one_shot_modifier(key) {
+                    builder.add_key(modifier);
+                } else if !(layers::key_is_upper(key)
+                    || layers::key_is_user(key)
+                    || layers::key_is_serial(key)
+                    || layers::key_is_nkro(key)
+                    || layers::key_is_audio(key)
+                    || layers::key_is_numpad(key)
+                    || layers::key_is_caps_word(key))
+                {
+                    // shifted keys are sent as the base key with Shift injected
+                    builder.add_key(layers::num_lock_key(key, layers::NUM_LOCK_MODE));
+                }
+            }
+        }
+
+        for key in self.macro_player.keys() {
+            builder.add_key(key);
+        }
+
+        if layers::active_layer() == layers::Layer::Fun && !fun_pressed {
             layers::set_active_layer(layers::Layer::Base);
         }
 
-        if active_layer != layers::Layer::Upper && upper_pressed {
-            layers::set_active_layer(layers::Layer::Upper);
+        // while turbo is held, release the other keys on alternating turbo phases
+        if !self.turbo.update(turbo_pressed, self.now_ms) {
+            builder.clear_keys();
+        }
+
+        let mut mouse_report = self
+            .mouse_keys
+            .update(features::feature_enabled(Feature::MouseKeys), self.now_ms);
+
+        // keys are only streamed over the debug channel in matrix test mode
+        if matrix_test::matrix_test_enabled() {
+            builder = ReportBuilder::new()
+                .with_previous_shift(self.report_shift)
+                .with_shape_shifter(layers::SHAPE_SHIFTER);
+            consumer_usage = 0;
+            gamepad_report = GamepadReport::new();
+            system_usage = 0;
+            mouse_report = MouseReport::new();
         }
 
-        reports
+        self.report_shift = builder.shift();
+        self.nkro_report = NkroReport::from_builder(&builder);
+        self.consumer_usage = consumer_usage;
+        self.gamepad_report = gamepad_report;
+        self.system_usage = system_usage;
+        self.mouse_report = mouse_report;
+
+        let mut report = builder.build();
+
+        self.hooks.before_report(&mut report);
+
+        report
+    }
+
+    /// Gets the time (in milliseconds) of the last matrix scan, from the [time] module.
+    ///
+    /// The clock is advanced by the scan timer, so it only measures time while scanning.
+    pub const fn now_ms(&self) -> u16 {
+        self.now_ms
     }
 
-    /// Perform a debounced [KeyMatrix] scan, and return any [KeyboardReport]s.
-    pub fn scan<const N: usize>(&mut self) -> [KeyboardReport; N] {
+    /// Perform a debounced [KeyMatrix] scan, and return the [KeyboardReport].
+    pub fn scan(&mut self) -> KeyboardReport {
         if do_scan() {
+            self.now_ms = time::millis() as u16;
             self.read_matrix();
             set_do_scan(false);
         }
 
-        self.matrix_scan_reports::<N>()
+        self.matrix_scan_report()
     }
 }
@@ -0,0 +1,120 @@
+//! LED animation played from EEPROM on an [RgbStrip], see
+//! [stored_animation](crate::stored_animation).
+//!
+//! Set the strip up with [setup_led_animation], and add [led_animation_task] to the board's
+//! [Housekeeping](crate::housekeeping::Housekeeping) tasks. The strip stays off until host tools
+//! upload an animation, and restarts with every new upload.
+
+use core::cell::RefCell;
+
+use avr_device::interrupt::{self, Mutex};
+
+use crate::animation::AnimationPlayer;
+use crate::eeprom_storage::with_eeprom;
+use crate::pin_map::{PinId, PinMap};
+use crate::rgb_strip::RgbStrip;
+use crate::stored_animation::{self, StoredAnimation};
+use crate::ws2812::{ColorOrder, FrameBuffer};
+
+/// Maximum number of LEDs of a stored animation, animations for longer strips are not played.
+pub const MAX_ANIMATION_LEDS: usize = 32;
+
+/// Global animated LED strip, refreshed from the main loop.
+pub static LED_ANIMATION: Mutex<RefCell<Option<LedAnimation>>> = Mutex::new(RefCell::new(None));
+
+/// Plays the stored animation on an [RgbStrip].
+pub struct LedAnimation {
+    strip: RgbStrip,
+    frame: FrameBuffer<MAX_ANIMATION_LEDS>,
+    player: Option<AnimationPlayer>,
+    revision: Option<u8>,
+    last_frame: Option<u16>,
+}
+
+impl LedAnimation {
+    /// Creates a new [LedAnimation] on the `strip`.
+    pub const fn new(strip: RgbStrip) -> Self {
+        Self {
+            strip,
+            frame: FrameBuffer::new(),
+            player: None,
+            revision: None,
+            last_frame: None,
+        }
+    }
+
+    /// Shows the next frame of the stored animation once the current frame is over, at the time
+    /// `now_ms`, and sends the changed colors to the strip.
+    ///
+    /// An animation that fails to decode is stopped, and the strip turned off.
+    pub fn update(&mut self, now_ms: u16) {
+        let revision = stored_animation::revision();
+
+        if self.revision != Some(revision) {
+            self.revision = Some(revision);
+            self.player = with_eeprom(|eeprom| {
+                StoredAnimation::active(eeprom).and_then(|data| AnimationPlayer::new(&data).ok())
+            })
+            .flatten();
+            self.last_frame = None;
+            self.frame.clear();
+        }
+
+        if let Some(mut player) = self.player {
+            let frame_ms = player.header().frame_ms() as u16;
+            let due = self
+                .last_frame
+                .is_none_or(|last| now_ms.wrapping_sub(last) >= frame_ms);
+
+            if due {
+                let leds = self.frame.leds_mut();
+
+                // an upload in between stops the animation, the next update loads the new one
+                let played = with_eeprom(|eeprom| match StoredAnimation::active(eeprom) {
+                    Some(data) if stored_animation::revision() == revision => {
+                        player.next_frame(&data, leds).is_ok()
+                    }
+                    _ => false,
+                });
+
+                if played == Some(true) {
+                    self.player = Some(player);
+                    self.last_frame = Some(now_ms);
+                } else {
+                    self.player = None;
+                    self.frame.clear();
+                }
+            }
+        }
+
+        self.strip.refresh(&mut self.frame);
+    }
+}
+
+/// Sets up the [LedAnimation] on a strip at the data `pin`, with the [ColorOrder] of its LEDs.
+///
+/// Returns `false` if the pin is not usable, see [RgbStrip::new].
+pub fn setup_led_animation(pin: PinId, order: ColorOrder, map: &PinMap) -> bool {
+    match RgbStrip::new(pin, order, map) {
+        Some(strip) => {
+            interrupt::free(|cs| {
+                LED_ANIMATION
+                    .borrow(cs)
+                    .replace(Some(LedAnimation::new(strip)))
+            });
+            true
+        }
+        None => false,
+    }
+}
+
+/// Plays the stored animation, an [IdleTask](crate::housekeeping::IdleTask).
+///
+/// The strip is taken out of [LED_ANIMATION] while playing, only the frame decoding and the
+/// strip refresh run with interrupts disabled.
+pub fn led_animation_task(now_ms: u16) {
+    if let Some(mut animation) = interrupt::free(|cs| LED_ANIMATION.borrow(cs).take()) {
+        animation.update(now_ms);
+        interrupt::free(|cs| LED_ANIMATION.borrow(cs).replace(Some(animation)));
+    }
+}
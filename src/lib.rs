@@ -1,31 +1,85 @@
 #![no_std]
 #![feature(lang_items)]
 #![feature(abi_avr_interrupt)]
-
-#[macro_use(bitfield)]
-extern crate bitfield;
+#![feature(asm_experimental_arch)]
 
 use core::cell::RefCell;
 
 use avr_device::interrupt::Mutex;
 
-pub use trove_internal::layers;
+pub use trove_internal::{
+    adc_config, analog, animation, audio, audit, auto_shift, bootloader, bridge, caps_word, config,
+    config_backup, console, consumer, debounce, diagnostics, events, factory_reset, features,
+    firmware_info, flash, focus, gamepad, ghosting, haptic, health, hooks, host_leds, housekeeping,
+    idle_rate, indicator, layers, macros, matrix_test, mouse, mouse_keys, nkro, oled, one_shot,
+    output_report, panic_report, pin_map, pipeline, pmw3360, poll_rate, power, raw_hid, report,
+    scan_rate, scan_timer, scanner, scheduler, serial_number, settings, shared_report,
+    shift_register, soft_pwm, spi_config, split, spsc, status_leds, storage, stored_animation,
+    stored_keymap, system_control, tap_hold, thumbstick, time, timing, tuning, turbo, uart_config,
+    usb_descriptors, usb_identity, usb_watchdog, wpm, ws2812,
+};
 
+pub mod adc;
+pub mod analog_matrix;
+pub mod analog_stick;
+pub mod buzzer;
+pub mod debug_port;
+pub mod direct_pins;
+pub mod duplex_matrix;
+pub mod eeprom_storage;
+pub mod i2c;
+pub mod indicator_leds;
 pub mod key_matrix;
 pub mod key_scanner;
+pub mod led_animation;
 pub mod lock;
+pub mod oled_display;
+pub mod port_matrix;
+pub mod power_management;
+pub mod pwm_output;
+pub mod rgb_strip;
+pub mod serial_bridge;
 pub mod setup;
+pub mod shift_register_matrix;
+pub mod solenoid;
+pub mod spi;
+pub mod split_link;
 pub mod std_stub;
+pub mod trackball;
+pub mod uart;
 pub mod usb_context;
 
+pub use adc::*;
+pub use analog_matrix::*;
+pub use analog_stick::*;
+pub use buzzer::*;
+pub use debug_port::*;
+pub use direct_pins::*;
+pub use duplex_matrix::*;
+pub use eeprom_storage::*;
+pub use i2c::*;
+pub use indicator_leds::*;
 pub use key_matrix::*;
 pub use key_scanner::*;
+pub use led_animation::*;
 pub use lock::*;
+pub use oled_display::*;
+pub use port_matrix::*;
+pub use power_management::*;
+pub use pwm_output::*;
+pub use rgb_strip::*;
+pub use serial_bridge::*;
 pub use setup::*;
+pub use shift_register_matrix::*;
+pub use solenoid::*;
+pub use spi::*;
+pub use split_link::*;
+pub use trackball::*;
+pub use uart::*;
 pub use usb_context::*;
 
 /// CPU frequency of the ATmega32u4 (16Mhz).
 pub const F_CPU: u32 = 16_000_000;
 
-/// Global USB context for scanning the key matrix, and handling device-host communication.
+/// Global USB context for queueing keyboard reports, and handling device-host communication.
 pub static USB_CTX: Mutex<RefCell<Option<UsbContext>>> = Mutex::new(RefCell::new(None));
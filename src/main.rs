@@ -6,16 +6,94 @@
 
 use arduino_hal::{entry, hal::pins, Peripherals};
 use atmega_usbd::UsbBus;
-use avr_device::{asm::sleep, interrupt};
-use usb_device::{
-    class_prelude::UsbBusAllocator,
-    device::{UsbDeviceBuilder, UsbVidPid},
-};
-use usbd_hid::{
-    descriptor::{KeyboardReport, SerializedDescriptor},
-    hid_class::HIDClass,
+use avr_device::interrupt;
+use trove::storage::EepromStorage;
+use trove::usb_descriptors::{Descriptor, DescriptorTable};
+use trove::MatrixScanner;
+use usb_device::class_prelude::UsbBusAllocator;
+use usbd_hid::hid_class::{
+    HIDClass, HidClassSettings, HidCountryCode, HidProtocol, HidSubClass, ProtocolModeConfig,
 };
 
+/// Column pins that can wake the keyboard from idle (`PB5` and `PB6`).
+///
+/// The other Atreus columns have no pin-change interrupt, so idle sleep is not enabled on the
+/// Atreus. Boards with every column on port B can enable it with
+/// [with_idle_sleep_ms](trove::KeyScanner::with_idle_sleep_ms).
+const IDLE_WAKE_PCINT_MASK: u8 = 0b0110_0000;
+
+/// HID endpoint poll interval and scan timer interval.
+///
+/// Use [from_poll_interval_ms](trove::poll_rate::PollRate::from_poll_interval_ms) for genuine
+/// 1000 Hz reporting, or a longer poll interval for hosts that struggle with it.
+const POLL_RATE: trove::poll_rate::PollRate = trove::poll_rate::PollRate::new();
+
+/// Lock indicator LEDs of the board.
+///
+/// The Keyboardio Atreus has none. Boards built on a Pro Micro can show a lock on a free LED, e.g.
+/// `Indicator::pro_micro_rx(HostLock::CapsLock)`.
+const INDICATORS: &[trove::indicator::Indicator] = &[];
+
+/// Software PWM outputs of the board, e.g. a backlight or a buzzer.
+///
+/// The Keyboardio Atreus has none. Dim them with [set_pwm_duty](trove::set_pwm_duty).
+const PWM_CHANNELS: &[trove::soft_pwm::PwmChannel] = &[];
+
+/// Piezo buzzer pin of the board, see [audio](trove::audio).
+///
+/// The Keyboardio Atreus has none. Builds with a speaker pad set a spare pin, e.g.
+/// `Some(PinId::new(Port::B, 7))`, which is free on the Atreus wiring.
+const BUZZER_PIN: Option<trove::pin_map::PinId> = None;
+
+/// WS2812 LED strip of the board, playing the animation uploaded by host tools, see
+/// [led_animation](trove::led_animation).
+///
+/// The Keyboardio Atreus has none. Boards with underglow set the data pin and the color order of
+/// the LEDs, e.g. `Some((PinId::new(Port::B, 7), ColorOrder::Grb))`, and add
+/// [led_animation_task](trove::led_animation_task) to the [HOUSEKEEPING] tasks.
+const LED_STRIP: Option<(trove::pin_map::PinId, trove::ws2812::ColorOrder)> = None;
+
+/// Peripherals kept powered, with the clocks of the others stopped at boot.
+///
+/// Boards with an analog matrix, a shift register matrix, or a serial link also keep the
+/// [Adc](trove::power::Peripheral::Adc), [Spi](trove::power::Peripheral::Spi), or
+/// [Usart1](trove::power::Peripheral::Usart1). Split builds keep the
+/// [Twi](trove::power::Peripheral::Twi) for the link between the halves.
+const POWER_REDUCTION: trove::power::PowerReduction = trove::power::PowerReduction::new()
+    .with_used(trove::power::Peripheral::Timer0, !PWM_CHANNELS.is_empty())
+    .with_used(trove::power::Peripheral::Timer4, BUZZER_PIN.is_some())
+    .with_used(trove::power::Peripheral::Twi, cfg!(feature = "split"));
+
+/// Non-time-critical tasks of the board, run from the main loop after every scan, e.g.
+/// [oled_task](trove::oled_task) on boards with a status display,
+/// [buzzer_task](trove::buzzer_task) on boards with a [BUZZER_PIN],
+/// [led_animation_task](trove::led_animation_task) on boards with an [LED_STRIP], or
+/// [solenoid_task](trove::solenoid_task) on boards with haptic feedback.
+///
+/// Interrupts with work for a task call [wake_main_loop](trove::housekeeping::wake_main_loop).
+const HOUSEKEEPING: trove::housekeeping::Housekeeping = trove::housekeeping::Housekeeping::new();
+
+/// Matrix scans handed from the main loop to the USB interrupts, without disabling interrupts.
+static SCAN_QUEUE: trove::spsc::SpscQueue<trove::ScanReports, { trove::SCAN_QUEUE_LEN }> =
+    trove::spsc::SpscQueue::new();
+
+/// Key scanner of the Atreus, reading the columns from the port registers.
+#[cfg(not(feature = "split"))]
+type AtreusScanner =
+    trove::KeyScanner<{ trove::ROWS }, { trove::COLS }, trove::EepromKeymap, trove::PortMatrix>;
+
+/// Key scanner of two Atreus boards linked into one split keyboard, see
+/// [split_link](trove::split_link).
+///
+/// Split builds use the default split key map, not the key map stored by host tools.
+#[cfg(feature = "split")]
+type AtreusScanner = trove::KeyScanner<
+    { trove::split::SPLIT_ROWS },
+    { trove::COLS },
+    trove::split::SplitKeymap<trove::split::SplitAtreusKeymap>,
+    trove::SplitMatrix<trove::PortMatrix>,
+>;
+
 #[entry]
 fn main() -> ! {
     let dp = Peripherals::take().unwrap();
@@ -23,6 +101,8 @@ fn main() -> ! {
     let pll = dp.PLL;
     let usb = dp.USB_DEVICE;
 
+    trove::setup_power_reduction(POWER_REDUCTION);
+
     // Configure PLL interface
     // prescale 16MHz crystal -> 8MHz
     pll.pllcsr.write(|w| w.pindiv().set_bit());
@@ -36,7 +116,8 @@ fn main() -> ! {
     // Check PLL lock
     while pll.pllcsr.read().plock().bit_is_clear() {}
 
-    trove::setup_timer(dp.TC1, 1500);
+    trove::setup_timer(dp.TC1, POLL_RATE.scan_interval_us());
+    trove::setup_settle_timer(dp.TC3);
 
     // Safety: mutable static is initialized exactly once, and only borrowed as a shared reference.
     let usb_bus = unsafe {
@@ -44,20 +125,167 @@ fn main() -> ! {
         &*USB_BUS.insert(UsbBus::new(usb))
     };
 
-    let hid_class = HIDClass::new(usb_bus, KeyboardReport::desc(), 1);
-    let usb_device = UsbDeviceBuilder::new(usb_bus, UsbVidPid(0x1209, 0x2303))
-        .manufacturer("Keyboardio")
-        .product("Trove Atreus")
+    let poll_ms = POLL_RATE.poll_interval_ms();
+
+    // the keyboard report has the boot layout, so the host can select either protocol
+    //
+    // every interface only has an IN endpoint, to fit in the six endpoints of the ATmega32u4 with
+    // the debug port, and the host sends the LED report over the control endpoint instead
+    //
+    // the N-key rollover, System Control, Consumer Control, and mouse reports share one interface
+    //
+    // the report descriptors stay in program memory, and the classes only get placeholders of the
+    // same length, so the HID classes must be allocated first, in the order of the descriptor table
+    let hid_class = HIDClass::new_ep_in_with_settings(
+        usb_bus,
+        Descriptor::KeyboardReport.placeholder(),
+        poll_ms,
+        HidClassSettings {
+            subclass: HidSubClass::Boot,
+            protocol: HidProtocol::Keyboard,
+            config: ProtocolModeConfig::DefaultBehavior,
+            locale: HidCountryCode::NotSupported,
+        },
+    );
+    let shared_class =
+        HIDClass::new_ep_in(usb_bus, Descriptor::SharedReport.placeholder(), poll_ms);
+    // the gamepad and raw HID interfaces take the endpoint budget of the debug port
+    let gamepad_class = cfg!(feature = "gamepad")
+        .then(|| HIDClass::new_ep_in(usb_bus, Descriptor::GamepadReport.placeholder(), poll_ms));
+    let raw_hid_class = cfg!(feature = "raw-hid")
+        .then(|| HIDClass::new_ep_in(usb_bus, Descriptor::RawHidReport.placeholder(), poll_ms));
+    let mut descriptors = DescriptorTable::new()
+        .with_report(Descriptor::KeyboardReport)
+        .with_report(Descriptor::SharedReport)
+        .with_strings(true);
+    if gamepad_class.is_some() {
+        descriptors = descriptors.with_report(Descriptor::GamepadReport);
+    }
+    if raw_hid_class.is_some() {
+        descriptors = descriptors.with_report(Descriptor::RawHidReport);
+    }
+    let debug_port = (!cfg!(any(feature = "gamepad", feature = "raw-hid")))
+        .then(|| trove::DebugPort::new(usb_bus));
+    // Safety: mutable static is initialized exactly once, and only borrowed as a shared reference.
+    let serial_number = unsafe {
+        static mut SERIAL_NUMBER: Option<trove::serial_number::SerialNumber> = None;
+        &*SERIAL_NUMBER.insert(trove::serial_number::SerialNumber::from_signature(
+            &trove::read_serial_bytes(),
+        ))
+    };
+    // the manufacturer and product strings are served from program memory, so the builder only
+    // allocates their indexes
+    let usb_identity = trove::usb_identity::BOARD_USB_IDENTITY
+        .with_manufacturer("")
+        .with_product("");
+    let usb_device = trove::usb_device_builder(usb_bus, &usb_identity)
+        .serial_number(serial_number.as_str())
+        .supports_remote_wakeup(true)
         .build();
 
-    let key_scanner = trove::KeyScanner::new(trove::KeyMatrix::new(pins));
+    // use the pin map stored in EEPROM, falling back to the Atreus wiring
+    let mut eeprom = trove::DeviceEeprom::new(arduino_hal::Eeprom::new(dp.EEPROM));
+    let mut pin_map_buf = [0u8; trove::pin_map::PIN_MAP_LEN];
+    let pin_map = eeprom
+        .load(trove::pin_map::PIN_MAP_OFFSET, &mut pin_map_buf)
+        .ok()
+        .and_then(|_| trove::pin_map::PinMap::from_bytes(&pin_map_buf).ok())
+        .unwrap_or_default();
+
+    // reset the settings and the key map to the defaults, if the combo is held while plugging in
+    let mut matrix = trove::PortMatrix::from_pin_map(pins, &pin_map);
+    if trove::factory_reset::combo_held(trove::factory_reset::FACTORY_RESET_COMBO, &matrix.scan()) {
+        trove::factory_reset::wipe(&mut eeprom).ok();
+    }
+
+    // restore the user settings, and rewrite the block if it was missing, invalid, or older
+    let mut settings_buf = [0u8; trove::settings::SETTINGS_LEN];
+    eeprom
+        .load(trove::settings::SETTINGS_OFFSET, &mut settings_buf)
+        .ok();
+    let (settings, restored) = trove::settings::restore(&settings_buf);
+    if restored.needs_write() {
+        eeprom
+            .store(trove::settings::SETTINGS_OFFSET, &settings.to_bytes())
+            .ok();
+    }
+    settings.apply();
+    let mut settings_tracker = trove::settings::SettingsTracker::new(settings);
+
+    // share the EEPROM with the USB interrupts, for the key map and animation commands
+    trove::stored_keymap::reload_active_keymap(&eeprom);
+    trove::stored_animation::reload_animation(&eeprom);
+    interrupt::free(|cs| {
+        trove::EEPROM.borrow(cs).borrow_mut().replace(eeprom);
+    });
+
+    let mut indicator_leds = trove::IndicatorLeds::new(INDICATORS, &pin_map);
+    trove::setup_soft_pwm(dp.TC0, PWM_CHANNELS, &pin_map);
+    if let Some(pin) = BUZZER_PIN {
+        trove::setup_buzzer(dp.TC4, pin, &pin_map);
+    }
+    if let Some((pin, order)) = LED_STRIP {
+        trove::setup_led_animation(pin, order, &pin_map);
+    }
+
+    // the board plugged into USB is the primary, left half, and reads the other half over I2C
+    #[cfg(feature = "split")]
+    let matrix = trove::SplitMatrix::new(matrix, dp.TWI, trove::detect_split_role());
+
+    let mut key_scanner = AtreusScanner::new(matrix).with_adaptive_scan_rate(
+        trove::scan_rate::AdaptiveScanRate::new()
+            .with_fast_interval_us(POLL_RATE.scan_interval_us()),
+    );
+    // debounce, settle, and tapping term from the restored settings
+    key_scanner.apply_tuning();
+
+    let (mut scans, scans_consumer) = SCAN_QUEUE.split().unwrap();
 
     let usb_ctx = trove::UsbContext {
         usb_device,
         hid_class,
-        key_scanner,
+        scans: scans_consumer,
+        reports: trove::report::ReportQueue::new(),
+        idle_rate: trove::idle_rate::IdleRate::new(),
+        descriptors,
+        shared_class: Some(shared_class),
+        nkro_class: None,
+        nkro_pending: None,
+        nkro_sent: trove::nkro::NkroReport::new(),
+        consumer_class: None,
+        consumer_pending: None,
+        consumer_sent: 0,
+        gamepad_class,
+        gamepad_pending: None,
+        gamepad_sent: trove::gamepad::GamepadReport::new(),
+        system_class: None,
+        system_pending: None,
+        system_sent: 0,
+        mouse_pending: None,
+        mouse_sent: trove::mouse::MouseReport::new(),
+        raw_hid_class,
+        raw_hid_pending: None,
+        usb_watchdog: trove::usb_watchdog::UsbWatchdog::new(),
+        output_handlers: trove::output_report::OutputHandlers::new()
+            .with_handler(
+                trove::output_report::ReportInterface::Keyboard,
+                trove::output_report::handle_led_report,
+            )
+            .with_handler(
+                trove::output_report::ReportInterface::RawHid,
+                trove::handle_config_report,
+            ),
+        keystroke_handler: None,
+        hooks: trove::hooks::Hooks::new(),
+        // the Atreus uses the UART pins for key matrix columns, so the SERIAL key does nothing
+        serial_bridge: None,
+        debug_port,
     };
 
+    if cfg!(feature = "matrix-test") {
+        trove::matrix_test::set_matrix_test_enabled(true);
+    }
+
     interrupt::free(|cs| {
         trove::USB_CTX.borrow(cs).borrow_mut().replace(usb_ctx);
     });
@@ -65,29 +293,182 @@ fn main() -> ! {
     unsafe { interrupt::enable() };
 
     loop {
-        sleep();
+        // reboot once the response to the request had time to reach the host
+        if trove::bootloader::bootloader_requested() {
+            arduino_hal::delay_ms(trove::bootloader::BOOTLOADER_DELAY_MS);
+            trove::enter_bootloader();
+        }
+
+        // the factory reset combo was held, restore the defaults without a reboot
+        if trove::factory_reset::take_factory_reset_request() {
+            if let Some(Ok(settings)) = trove::with_eeprom(trove::factory_reset::wipe) {
+                settings.apply();
+                settings_tracker = trove::settings::SettingsTracker::new(settings);
+                key_scanner.apply_tuning();
+            }
+        }
+
+        // scan outside of the interrupts, and hand the reports over through the lock-free queue
+        let scanned = trove::key_scanner::do_scan();
+        if scanned {
+            let start = trove::SettleTimer::ticks();
+            let report = key_scanner.scan();
+            trove::timing::record_scan_us(trove::SettleTimer::elapsed_us(start));
+
+            let scan = trove::ScanReports {
+                report,
+                nkro_report: *key_scanner.nkro_report(),
+                consumer_usage: key_scanner.consumer_usage(),
+                gamepad_report: *key_scanner.gamepad_report(),
+                system_usage: key_scanner.system_usage(),
+                mouse_report: key_scanner
+                    .mouse_report()
+                    .with_added_motion(trove::mouse::take_motion()),
+                now_ms: key_scanner.now_ms(),
+            };
+
+            if !scans.push(scan) {
+                trove::diagnostics::record(trove::diagnostics::Diagnostic::ReportBlocked);
+            }
+
+            // send right away, instead of waiting for the next USB interrupt
+            with_usb_ctx(|ctx| ctx.send_scans());
+
+            while let Some(event) = key_scanner.pop_test_event() {
+                let mut line = [0u8; trove::matrix_test::MATRIX_TEST_LINE_LEN];
+                let len = trove::matrix_test::write_event(&event, &mut line);
+
+                with_usb_ctx(|ctx| ctx.write_debug(&line[..len]));
+            }
+
+            indicator_leds.update();
+
+            // persist the layer, report mode, feature toggles, and tuning once they stop changing
+            let current = settings_tracker.saved().with_runtime_state();
+            if current != settings_tracker.saved() {
+                // apply tuning changes from host tools right away
+                key_scanner.apply_tuning();
+            }
+            if let Some(settings) = settings_tracker.update(current, key_scanner.now_ms()) {
+                let stored = trove::with_eeprom(|eeprom| {
+                    eeprom
+                        .store(trove::settings::SETTINGS_OFFSET, &settings.to_bytes())
+                        .is_ok()
+                });
+                if stored != Some(true) {
+                    trove::audio::request_sound(trove::audio::Sound::Error);
+                }
+            }
+
+            // slow down scanning while idle, and snap back on the first key press
+            if let Some(interval) = key_scanner.take_scan_interval() {
+                trove::set_scan_interval(interval);
+            }
+
+            // stop scanning entirely until a key press, if the board supports it, and right away
+            // while the bus is suspended
+            let sleeping = if with_usb_ctx(|ctx| ctx.suspended()).unwrap_or(false) {
+                key_scanner.prepare_sleep_now()
+            } else {
+                key_scanner.prepare_sleep()
+            };
+            if sleeping {
+                trove::enter_idle(IDLE_WAKE_PCINT_MASK);
+            }
+        }
+
+        // run the board tasks after each scan, or when an interrupt has work for them
+        if trove::housekeeping::take_wake_request() || scanned {
+            HOUSEKEEPING.run(trove::time::millis() as u16);
+        }
+
+        interrupt::disable();
+
+        // an interrupt asked for another run since, do not sleep through it
+        if trove::housekeeping::wake_requested() {
+            unsafe { interrupt::enable() };
+            continue;
+        }
+
+        // a key press since the last scan already restarted the scan timer
+        let matrix_sleeping = key_scanner.sleeping() && !trove::key_scanner::do_scan();
+        let mode = with_usb_ctx(|ctx| ctx.sleep_mode(matrix_sleeping)).unwrap_or_default();
+
+        // power down while the bus is suspended, until a key press or the USB wakeup interrupt
+        trove::sleep_until_interrupt(mode);
     }
 }
 
 #[interrupt(atmega32u4)]
 fn USB_GEN() {
-    scan_matrix();
+    timed_isr(|| {
+        with_usb_ctx(|ctx| ctx.poll());
+    });
 }
 
 #[interrupt(atmega32u4)]
 fn USB_COM() {
-    scan_matrix();
+    timed_isr(|| {
+        with_usb_ctx(|ctx| ctx.poll());
+    });
+}
+
+#[interrupt(atmega32u4)]
+fn PCINT0() {
+    timed_isr(|| {
+        // a key was pressed while idle, resume scanning
+        trove::exit_idle();
+        trove::key_scanner::set_do_scan(true);
+    });
 }
 
 #[interrupt(atmega32u4)]
 fn TIMER1_OVF() {
-    trove::key_scanner::set_do_scan(true);
+    timed_isr(|| {
+        trove::key_scanner::set_do_scan(true);
+        trove::health::tick();
+        trove::time::tick();
+    });
 }
 
-fn scan_matrix() {
+#[interrupt(atmega32u4)]
+fn TIMER0_COMPA() {
+    timed_isr(trove::soft_pwm_step);
+}
+
+#[interrupt(atmega32u4)]
+fn TIMER4_OVF() {
+    timed_isr(trove::buzzer_toggle);
+}
+
+#[interrupt(atmega32u4)]
+fn USART1_RX() {
+    timed_isr(trove::uart_rx_interrupt);
+}
+
+#[interrupt(atmega32u4)]
+fn ADC() {
+    timed_isr(trove::adc_interrupt);
+}
+
+#[interrupt(atmega32u4)]
+fn TWI() {
+    timed_isr(trove::split_link_interrupt);
+}
+
+/// Runs an interrupt handler body, and records its duration.
+fn timed_isr<F: FnOnce()>(f: F) {
+    let start = trove::SettleTimer::ticks();
+
+    f();
+
+    trove::timing::record_isr_us(trove::SettleTimer::elapsed_us(start));
+}
+
+fn with_usb_ctx<R, F: FnOnce(&mut trove::UsbContext) -> R>(f: F) -> Option<R> {
     interrupt::free(|cs| {
-        if let Some(ctx) = trove::USB_CTX.borrow(cs).borrow_mut().as_mut() {
-            ctx.scan_matrix();
-        }
-    });
+        let _guard = trove::audit::enter(trove::audit::Resource::UsbContext);
+
+        trove::USB_CTX.borrow(cs).borrow_mut().as_mut().map(f)
+    })
 }
@@ -0,0 +1,130 @@
+//! SSD1306 OLED status display on the I2C bus, see [oled](crate::oled).
+//!
+//! Set the display up with [setup_oled], add [oled_task] to the board's
+//! [Housekeeping](crate::housekeeping::Housekeeping) tasks, and register
+//! [count_key_event](crate::wpm::count_key_event) as a key event hook for the typing speed.
+
+use core::cell::RefCell;
+
+use arduino_hal::pac;
+use avr_device::interrupt::{self, Mutex};
+
+use crate::i2c::{I2cError, I2cMaster};
+use crate::oled::{
+    self, OledBuffer, CONTROL_COMMANDS, CONTROL_DATA, SSD1306_ADDR, SSD1306_INIT, SSD1306_WINDOW,
+};
+use crate::status_leds::FirmwareStatus;
+use crate::wpm::{self, WpmMeter};
+
+/// Time (in milliseconds) between redraws of the status screen.
+pub const OLED_REFRESH_MS: u16 = 100;
+
+/// Global status display, drawn from the main loop.
+pub static OLED: Mutex<RefCell<Option<StatusDisplay>>> = Mutex::new(RefCell::new(None));
+
+/// SSD1306 display on an [I2cMaster].
+pub struct Ssd1306 {
+    i2c: I2cMaster,
+    addr: u8,
+}
+
+impl Ssd1306 {
+    /// Creates a new [Ssd1306] at the I2C `addr`, and turns the display on.
+    ///
+    /// Returns an error if no display answers.
+    pub fn new(i2c: I2cMaster, addr: u8) -> Result<Self, I2cError> {
+        let mut display = Self { i2c, addr };
+        display.commands(&SSD1306_INIT)?;
+
+        Ok(display)
+    }
+
+    /// Sends the `buf` to the display, if it changed since the last flush.
+    ///
+    /// Returns `true` if the buffer was sent. A full buffer takes about 13 milliseconds, a failed
+    /// flush is retried on the next call.
+    pub fn flush(&mut self, buf: &mut OledBuffer) -> Result<bool, I2cError> {
+        if !buf.is_dirty() {
+            return Ok(false);
+        }
+
+        self.commands(&SSD1306_WINDOW)?;
+        self.i2c.write(
+            self.addr,
+            core::iter::once(CONTROL_DATA).chain(buf.pixels().iter().copied()),
+        )?;
+        buf.take_dirty();
+
+        Ok(true)
+    }
+
+    // Sends a stream of commands.
+    fn commands(&mut self, commands: &[u8]) -> Result<(), I2cError> {
+        self.i2c.write(
+            self.addr,
+            core::iter::once(CONTROL_COMMANDS).chain(commands.iter().copied()),
+        )
+    }
+}
+
+/// Status screen on an [Ssd1306], with its buffer and typing speed meter.
+pub struct StatusDisplay {
+    display: Ssd1306,
+    buffer: OledBuffer,
+    wpm: WpmMeter,
+    last_draw_ms: Option<u16>,
+}
+
+impl StatusDisplay {
+    /// Creates a new [StatusDisplay].
+    pub fn new(display: Ssd1306) -> Self {
+        Self {
+            display,
+            buffer: OledBuffer::new(),
+            wpm: WpmMeter::new(),
+            last_draw_ms: None,
+        }
+    }
+
+    /// Redraws the status screen at the time `now_ms`, at most every [OLED_REFRESH_MS].
+    pub fn update(&mut self, now_ms: u16) {
+        self.wpm.update(now_ms, wpm::key_presses());
+
+        if self
+            .last_draw_ms
+            .is_some_and(|last| now_ms.wrapping_sub(last) < OLED_REFRESH_MS)
+        {
+            return;
+        }
+        self.last_draw_ms = Some(now_ms);
+
+        oled::render_status(&mut self.buffer, &FirmwareStatus::current(), self.wpm.wpm());
+        // a disconnected display is retried on the next redraw
+        self.display.flush(&mut self.buffer).ok();
+    }
+}
+
+/// Sets up the [StatusDisplay] on the I2C bus, at the [SSD1306_ADDR].
+///
+/// Returns `false` if no display answers, keep the [Twi](crate::power::Peripheral::Twi) powered
+/// for the display.
+pub fn setup_oled(twi: pac::TWI) -> bool {
+    match Ssd1306::new(I2cMaster::new(twi), SSD1306_ADDR) {
+        Ok(display) => {
+            interrupt::free(|cs| OLED.borrow(cs).replace(Some(StatusDisplay::new(display))));
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+/// Redraws the status display, an [IdleTask](crate::housekeeping::IdleTask).
+///
+/// The display is taken out of [OLED] while drawing, so interrupts stay enabled during the
+/// I2C transfer.
+pub fn oled_task(now_ms: u16) {
+    if let Some(mut display) = interrupt::free(|cs| OLED.borrow(cs).take()) {
+        display.update(now_ms);
+        interrupt::free(|cs| OLED.borrow(cs).replace(Some(display)));
+    }
+}
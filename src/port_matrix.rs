@@ -0,0 +1,129 @@
+use arduino_hal::hal::port::Pins;
+use arduino_hal::pac;
+use arduino_hal::port::{
+    mode::{Input, Output, PullUp},
+    Pin,
+};
+use embedded_hal::blocking::delay::DelayUs;
+
+use crate::key_matrix::{dynamic_pins, MatrixReader};
+use crate::pin_map::{self, PinId, PinMap, NUM_PORTS};
+use crate::{layers, RowState, SettleTimer, DEFAULT_SETTLE_US};
+
+/// Represents a [Col2Row](crate::Col2Row) key matrix, that reads the columns from the port
+/// registers.
+///
+/// Instead of reading each column pin on its own, every `PIN` register is read once per row, and
+/// the bits are mapped to columns with the [PinMap]. This cuts the time spent reading a row to a
+/// handful of instructions, regardless of the number of columns.
+///
+/// Rows are made of `Output` pins that are driven low to "activate" them, and columns are
+/// pull-up `Input` pins, like the default [KeyMatrix](crate::KeyMatrix) wiring.
+pub struct PortMatrix<
+    const ROWS: usize = { layers::ROWS },
+    const COLS: usize = { layers::COLS },
+    T = SettleTimer,
+> {
+    rows: [Pin<Output>; ROWS],
+    // kept to hold the pins in pull-up input mode, the columns are read from the port registers
+    _cols: [Pin<Input<PullUp>>; COLS],
+    col_ids: [PinId; COLS],
+    settle: T,
+    settle_us: u16,
+}
+
+impl PortMatrix {
+    /// Creates a new [PortMatrix], with the rows and columns assigned to pins by the [PinMap].
+    pub fn from_pin_map(pins: Pins, map: &PinMap) -> Self {
+        let mut pins = dynamic_pins(pins);
+
+        // the pin map is validated on creation, so every pin is available exactly once
+        let mut take = |pin: &PinId| pins[pin.index()].take().expect("valid pin map");
+
+        let rows = map.rows().each_ref().map(|pin| take(pin).into_output());
+        let cols = map
+            .cols()
+            .each_ref()
+            .map(|pin| take(pin).into_pull_up_input());
+
+        Self {
+            rows,
+            _cols: cols,
+            col_ids: *map.cols(),
+            settle: SettleTimer,
+            settle_us: DEFAULT_SETTLE_US,
+        }
+    }
+}
+
+impl<const ROWS: usize, const COLS: usize, T> PortMatrix<ROWS, COLS, T> {
+    /// Gets the time (in microseconds) for an input pin to settle before it is read.
+    pub const fn settle_us(&self) -> u16 {
+        self.settle_us
+    }
+
+    /// Sets the time (in microseconds) for an input pin to settle before it is read.
+    pub fn set_settle_us(&mut self, val: u16) {
+        self.settle_us = val;
+    }
+
+    /// Builder function that sets the time (in microseconds) for an input pin to settle.
+    pub fn with_settle_us(mut self, val: u16) -> Self {
+        self.set_settle_us(val);
+        self
+    }
+}
+
+/// Reads the `PIN` registers of every port.
+fn read_ports() -> [u8; NUM_PORTS] {
+    // Safety: the input registers are only read, which has no side effects.
+    unsafe {
+        [
+            (*pac::PORTB::ptr()).pinb.read().bits(),
+            (*pac::PORTC::ptr()).pinc.read().bits(),
+            (*pac::PORTD::ptr()).pind.read().bits(),
+            (*pac::PORTE::ptr()).pine.read().bits(),
+            (*pac::PORTF::ptr()).pinf.read().bits(),
+        ]
+    }
+}
+
+impl<const ROWS: usize, const COLS: usize, T: DelayUs<u16>> MatrixReader<ROWS, COLS>
+    for PortMatrix<ROWS, COLS, T>
+{
+    fn read_row(&mut self, row: usize) -> RowState {
+        let mut hot_pins = RowState::new();
+
+        if let Some(row) = self.rows.get_mut(row) {
+            // pull the row pin low to "activate" the row
+            row.set_low();
+            // wait for the column pins to settle, once for the whole row
+            self.settle.delay_us(self.settle_us);
+
+            hot_pins = pin_map::low_cols(&self.col_ids, &read_ports());
+
+            row.set_high();
+        }
+
+        hot_pins
+    }
+
+    fn prepare_idle(&mut self) -> bool {
+        // "activate" every row, so a key press anywhere pulls its column low
+        for row in self.rows.iter_mut() {
+            row.set_low();
+        }
+
+        true
+    }
+
+    fn resume_scan(&mut self) {
+        for row in self.rows.iter_mut() {
+            row.set_high();
+        }
+    }
+
+    fn set_settle_us(&mut self, val: u16) {
+        self.settle_us = val;
+    }
+}
@@ -0,0 +1,131 @@
+//! Power management of the keyboard, see [power](crate::power).
+//!
+//! The main loop stops the scan timer while the matrix sleeps until a key press
+//! ([enter_idle]), and powers down the MCU with [sleep_until_interrupt]. Waking is transparent:
+//! the pin-change interrupt restarts scanning, and the next scan wakes the host if the bus is
+//! suspended.
+//!
+//! At boot, [setup_power_reduction] stops the clocks of the unused peripherals.
+
+use arduino_hal::pac;
+use avr_device::{asm::sleep, interrupt};
+
+use crate::power::{PowerReduction, SleepMode};
+
+/// Stops the keyscan timer, and wakes on a pin-change interrupt instead.
+///
+/// The `pcint_mask` selects the column pins (`PCINT0` - `PCINT7` on port B) that wake the
+/// keyboard. Call after [KeyScanner::prepare_sleep](crate::KeyScanner::prepare_sleep), and
+/// [exit_idle] from the `PCINT0` interrupt.
+pub fn enter_idle(pcint_mask: u8) {
+    interrupt::free(|_| {
+        // Safety: the timer interrupt and the pin-change interrupt registers are only changed
+        // here and in `exit_idle`, with interrupts disabled.
+        let (tc1, exint) = unsafe { (&*pac::TC1::ptr(), &*pac::EXINT::ptr()) };
+
+        tc1.timsk1.modify(|_, w| w.toie1().bit(false));
+
+        exint.pcmsk0.write(|w| w.bits(pcint_mask));
+        // clear any stale pin-change flag before enabling the interrupt
+        exint.pcifr.write(|w| w.pcif0().set_bit());
+        exint.pcicr.write(|w| w.pcie0().set_bit());
+    });
+}
+
+/// Disables the pin-change interrupt, and restarts the keyscan timer.
+pub fn exit_idle() {
+    interrupt::free(|_| {
+        // Safety: see `enter_idle`.
+        let (tc1, exint) = unsafe { (&*pac::TC1::ptr(), &*pac::EXINT::ptr()) };
+
+        exint.pcicr.write(|w| w.pcie0().clear_bit());
+        exint.pcmsk0.write(|w| w.bits(0));
+
+        tc1.timsk1.modify(|_, w| w.toie1().bit(true));
+    });
+}
+
+/// Stops the keyscan timer, and selects the power-down sleep mode while the USB bus is suspended.
+///
+/// Power-down stops every clock, to meet the USB suspend current limit. The MCU wakes on the USB
+/// wakeup interrupt once the bus resumes, and [exit_suspend] restores scanning.
+pub fn enter_suspend() {
+    interrupt::free(|_| {
+        // Safety: the timer interrupt and the sleep mode are only changed here, in `exit_suspend`,
+        // and in the idle functions, with interrupts disabled.
+        let (tc1, cpu) = unsafe { (&*pac::TC1::ptr(), &*pac::CPU::ptr()) };
+
+        tc1.timsk1.modify(|_, w| w.toie1().bit(false));
+        cpu.smcr.write(|w| w.sm().pdown().se().set_bit());
+    });
+}
+
+/// Restores the default sleep mode, and restarts the keyscan timer after [enter_suspend].
+pub fn exit_suspend() {
+    interrupt::free(|_| {
+        // Safety: see `enter_suspend`.
+        let (tc1, cpu) = unsafe { (&*pac::TC1::ptr(), &*pac::CPU::ptr()) };
+
+        cpu.smcr.reset();
+        tc1.timsk1.modify(|_, w| w.toie1().bit(true));
+    });
+}
+
+/// Signals a remote wakeup to the suspended host.
+///
+/// The USB clock is stopped while the bus is suspended, so it is restarted first. Only call while
+/// the bus is suspended, and the host enabled remote wakeup.
+pub fn signal_remote_wakeup() {
+    interrupt::free(|_| {
+        // Safety: the USB bus driver restarts the same clocks when the bus resumes, and the
+        // wakeup bit is cleared by hardware once the resume signal was sent.
+        let (pll, usb) = unsafe { (&*pac::PLL::ptr(), &*pac::USB_DEVICE::ptr()) };
+
+        pll.pllcsr.modify(|_, w| w.plle().set_bit());
+        while pll.pllcsr.read().plock().bit_is_clear() {}
+
+        usb.usbcon.modify(|_, w| w.frzclk().clear_bit());
+        usb.udcon.modify(|_, w| w.rmwkup().set_bit());
+    });
+}
+
+/// Sleeps in the [SleepMode] until the next interrupt.
+///
+/// Call with interrupts disabled, after choosing the `mode`, so a wakeup interrupt between the
+/// choice and the sleep is not missed. Interrupts are enabled right before sleeping.
+pub fn sleep_until_interrupt(mode: SleepMode) {
+    match mode {
+        SleepMode::Idle => {
+            // Safety: the sleep instruction right after enabling interrupts is always executed.
+            unsafe { interrupt::enable() };
+            sleep();
+        }
+        SleepMode::PowerDown => {
+            enter_suspend();
+
+            // Safety: see above.
+            unsafe { interrupt::enable() };
+            sleep();
+
+            exit_suspend();
+            // the bus may have resumed while the matrix was sleeping, scan again until it is idle
+            exit_idle();
+        }
+    }
+}
+
+/// Stops the clocks of the unused peripherals, see [PowerReduction].
+///
+/// Call once at boot, before setting up the used peripherals: the registers of a stopped
+/// peripheral can not be written.
+pub fn setup_power_reduction(reduction: PowerReduction) {
+    let [prr0, prr1] = reduction.prr();
+
+    interrupt::free(|_| {
+        // Safety: the power reduction registers are only changed here, with interrupts disabled.
+        let cpu = unsafe { &*pac::CPU::ptr() };
+
+        cpu.prr0.write(|w| unsafe { w.bits(prr0) });
+        cpu.prr1.write(|w| unsafe { w.bits(prr1) });
+    });
+}
@@ -0,0 +1,128 @@
+//! Software PWM outputs of the keyboard, see [soft_pwm](crate::soft_pwm).
+//!
+//! Timer 0 steps the [SoftPwm] from its compare match interrupt. Install the channels with
+//! [setup_soft_pwm], call [soft_pwm_step] from the `TIMER0_COMPA` interrupt, and change the
+//! brightness with [set_pwm_duty].
+
+use core::cell::RefCell;
+
+use arduino_hal::pac;
+use avr_device::interrupt::{self, Mutex};
+
+use crate::pin_map::{PinMap, Port};
+use crate::soft_pwm::{PwmChannel, PwmLevels, SoftPwm, PWM_FREQUENCY_HZ, PWM_STEPS};
+use crate::F_CPU;
+
+/// Timer 0 clock prescaler.
+const PWM_TIMER_PRESCALER: u32 = 64;
+/// Timer 0 compare value for [PWM_STEPS] interrupts per PWM period.
+const PWM_TIMER_TOP: u8 =
+    (F_CPU / PWM_TIMER_PRESCALER / (PWM_STEPS as u32 * PWM_FREQUENCY_HZ as u32) - 1) as u8;
+
+/// Global software PWM, shared by the main loop and the timer interrupt.
+pub static SOFT_PWM: Mutex<RefCell<Option<SoftPwm>>> = Mutex::new(RefCell::new(None));
+
+/// Configures the pins of the `channels` as outputs, and starts stepping the [SoftPwm] on Timer 0.
+///
+/// Channels on key matrix pins are left out, see [is_valid](PwmChannel::is_valid). Timer 0 is
+/// left stopped without channels.
+pub fn setup_soft_pwm(tc0: pac::TC0, channels: &[PwmChannel], map: &PinMap) {
+    let pwm = SoftPwm::new(channels, map);
+
+    if pwm.channels().next().is_none() {
+        return;
+    }
+
+    write_levels(&pwm.levels());
+    for channel in pwm.channels() {
+        set_output(channel);
+    }
+
+    interrupt::free(|cs| SOFT_PWM.borrow(cs).replace(Some(pwm)));
+
+    // clocked at F_CPU / PWM_TIMER_PRESCALER
+    tc0.tccr0a.write(|w| w.wgm0().ctc());
+    tc0.ocr0a.write(|w| w.bits(PWM_TIMER_TOP));
+    tc0.tccr0b.write(|w| w.cs0().prescale_64());
+    tc0.timsk0.modify(|_, w| w.ocie0a().set_bit());
+}
+
+/// Sets the duty cycle of a PWM channel, from `0` (off) to [MAX_DUTY](crate::soft_pwm::MAX_DUTY).
+///
+/// Returns `false` if the channel is not driven.
+pub fn set_pwm_duty(channel: usize, duty: u8) -> bool {
+    interrupt::free(|cs| {
+        SOFT_PWM
+            .borrow(cs)
+            .borrow_mut()
+            .as_mut()
+            .is_some_and(|pwm| pwm.set_duty(channel, duty))
+    })
+}
+
+/// Advances the [SoftPwm] by one step, and drives the PWM pins.
+///
+/// Call from the `TIMER0_COMPA` interrupt.
+pub fn soft_pwm_step() {
+    interrupt::free(|cs| {
+        if let Some(pwm) = SOFT_PWM.borrow(cs).borrow_mut().as_mut() {
+            write_levels(&pwm.step());
+        }
+    });
+}
+
+// Drives the PWM pins of every port.
+fn write_levels(levels: &PwmLevels) {
+    for (port, &mask) in levels.mask().iter().enumerate() {
+        if mask == 0 {
+            continue;
+        }
+
+        // Safety: only the bits of the PWM pins are changed, with interrupts disabled.
+        unsafe {
+            match port {
+                0 => (*pac::PORTB::ptr())
+                    .portb
+                    .modify(|r, w| w.bits(levels.apply(port, r.bits()))),
+                1 => (*pac::PORTC::ptr())
+                    .portc
+                    .modify(|r, w| w.bits(levels.apply(port, r.bits()))),
+                2 => (*pac::PORTD::ptr())
+                    .portd
+                    .modify(|r, w| w.bits(levels.apply(port, r.bits()))),
+                3 => (*pac::PORTE::ptr())
+                    .porte
+                    .modify(|r, w| w.bits(levels.apply(port, r.bits()))),
+                _ => (*pac::PORTF::ptr())
+                    .portf
+                    .modify(|r, w| w.bits(levels.apply(port, r.bits()))),
+            }
+        }
+    }
+}
+
+// Configures the pin of the channel as an output.
+fn set_output(channel: &PwmChannel) {
+    let bit = 1 << channel.pin().bit();
+
+    // Safety: only the bit of the PWM pin is changed, before the timer interrupt is enabled.
+    unsafe {
+        match channel.pin().port() {
+            Port::B => (*pac::PORTB::ptr())
+                .ddrb
+                .modify(|r, w| w.bits(r.bits() | bit)),
+            Port::C => (*pac::PORTC::ptr())
+                .ddrc
+                .modify(|r, w| w.bits(r.bits() | bit)),
+            Port::D => (*pac::PORTD::ptr())
+                .ddrd
+                .modify(|r, w| w.bits(r.bits() | bit)),
+            Port::E => (*pac::PORTE::ptr())
+                .ddre
+                .modify(|r, w| w.bits(r.bits() | bit)),
+            Port::F => (*pac::PORTF::ptr())
+                .ddrf
+                .modify(|r, w| w.bits(r.bits() | bit)),
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! Bit-banged WS2812 and SK6812 LED strips, see [ws2812](crate::ws2812).
+//!
+//! The LEDs read each bit from the length of a high pulse on the data pin, 1.25 microseconds per
+//! bit, so the bits are sent by a cycle-counted loop with interrupts disabled. Any free pin can
+//! drive a strip, create an [RgbStrip] for it, and send the [FrameBuffer] from the main loop with
+//! [refresh](RgbStrip::refresh).
+
+use arduino_hal::pac;
+use avr_device::interrupt;
+use embedded_hal::blocking::delay::DelayUs;
+
+use crate::pin_map::{PinId, PinMap, Port};
+use crate::ws2812::{ColorOrder, FrameBuffer, WS2812_RESET_US};
+use crate::{SettleTimer, F_CPU};
+
+// I/O addresses of the port output registers, for the `out` instruction.
+const PORTB_IO_ADDR: u8 = 0x05;
+const PORTC_IO_ADDR: u8 = 0x08;
+const PORTD_IO_ADDR: u8 = 0x0b;
+const PORTE_IO_ADDR: u8 = 0x0e;
+const PORTF_IO_ADDR: u8 = 0x11;
+
+// the bit timing below is counted in cycles of a 16 MHz clock
+const _: () = assert!(F_CPU == 16_000_000);
+
+/// Defines a function sending bytes on a pin of a port, most significant bit first.
+///
+/// Each bit takes 20 cycles (1.25 microseconds): a `0` is high for 5 cycles (312 nanoseconds), a
+/// `1` for 12 cycles (750 nanoseconds). Skipping the first low write takes as long as executing
+/// it, so both bit values take the same time.
+macro_rules! send_bytes {
+    ($name:ident, $port:expr) => {
+        fn $name(bytes: impl Iterator<Item = u8>, high: u8, low: u8) {
+            for byte in bytes {
+                // Safety: only writes the port register, with the values read from it while
+                // interrupts are disabled.
+                unsafe {
+                    core::arch::asm!(
+                        "ldi {count}, 8",
+                        "1:",
+                        "out {port}, {high}",
+                        "nop",
+                        "nop",
+                        "nop",
+                        "sbrs {byte}, 7",
+                        "out {port}, {low}",
+                        "lsl {byte}",
+                        "nop",
+                        "nop",
+                        "nop",
+                        "nop",
+                        "nop",
+                        "out {port}, {low}",
+                        "nop",
+                        "nop",
+                        "nop",
+                        "nop",
+                        "dec {count}",
+                        "brne 1b",
+                        port = const $port,
+                        high = in(reg) high,
+                        low = in(reg) low,
+                        byte = inout(reg) byte => _,
+                        count = out(reg_upper) _,
+                        options(nomem, nostack),
+                    );
+                }
+            }
+        }
+    };
+}
+
+send_bytes!(send_bytes_b, PORTB_IO_ADDR);
+send_bytes!(send_bytes_c, PORTC_IO_ADDR);
+send_bytes!(send_bytes_d, PORTD_IO_ADDR);
+send_bytes!(send_bytes_e, PORTE_IO_ADDR);
+send_bytes!(send_bytes_f, PORTF_IO_ADDR);
+
+/// Represents a strip of WS2812 or SK6812 LEDs on a data pin.
+pub struct RgbStrip {
+    pin: PinId,
+    order: ColorOrder,
+}
+
+impl RgbStrip {
+    /// Creates a new [RgbStrip], and configures the data `pin` as a low output.
+    ///
+    /// Returns `None` if the pin is a key matrix pin, or not bonded out.
+    pub fn new(pin: PinId, order: ColorOrder, map: &PinMap) -> Option<Self> {
+        if !pin.is_available() || map.contains(&pin) {
+            return None;
+        }
+
+        let bit = 1 << pin.bit();
+
+        // Safety: only the bit of the data pin is changed, from the main loop like the key
+        // matrix pins.
+        interrupt::free(|_| unsafe {
+            match pin.port() {
+                Port::B => {
+                    let port = &*pac::PORTB::ptr();
+                    port.portb.modify(|r, w| w.bits(r.bits() & !bit));
+                    port.ddrb.modify(|r, w| w.bits(r.bits() | bit));
+                }
+                Port::C => {
+                    let port = &*pac::PORTC::ptr();
+                    port.portc.modify(|r, w| w.bits(r.bits() & !bit));
+                    port.ddrc.modify(|r, w| w.bits(r.bits() | bit));
+                }
+                Port::D => {
+                    let port = &*pac::PORTD::ptr();
+                    port.portd.modify(|r, w| w.bits(r.bits() & !bit));
+                    port.ddrd.modify(|r, w| w.bits(r.bits() | bit));
+                }
+                Port::E => {
+                    let port = &*pac::PORTE::ptr();
+                    port.porte.modify(|r, w| w.bits(r.bits() & !bit));
+                    port.ddre.modify(|r, w| w.bits(r.bits() | bit));
+                }
+                Port::F => {
+                    let port = &*pac::PORTF::ptr();
+                    port.portf.modify(|r, w| w.bits(r.bits() & !bit));
+                    port.ddrf.modify(|r, w| w.bits(r.bits() | bit));
+                }
+            }
+        });
+
+        Some(Self { pin, order })
+    }
+
+    /// Gets the data pin of the strip.
+    pub const fn pin(&self) -> PinId {
+        self.pin
+    }
+
+    /// Gets the [ColorOrder] of the LEDs.
+    pub const fn order(&self) -> ColorOrder {
+        self.order
+    }
+
+    /// Sends the colors of the `frame`, and waits for the LEDs to latch them.
+    ///
+    /// Interrupts are disabled while sending, about 30 microseconds per LED.
+    pub fn write<const N: usize>(&self, frame: &FrameBuffer<N>) {
+        let bytes = frame.bytes(self.order);
+        let bit = 1 << self.pin.bit();
+
+        interrupt::free(|_| {
+            // Safety: the port is only read here, the other pins keep their levels while
+            // interrupts are disabled.
+            let port = unsafe {
+                match self.pin.port() {
+                    Port::B => (*pac::PORTB::ptr()).portb.read().bits(),
+                    Port::C => (*pac::PORTC::ptr()).portc.read().bits(),
+                    Port::D => (*pac::PORTD::ptr()).portd.read().bits(),
+                    Port::E => (*pac::PORTE::ptr()).porte.read().bits(),
+                    Port::F => (*pac::PORTF::ptr()).portf.read().bits(),
+                }
+            };
+            let (high, low) = (port | bit, port & !bit);
+
+            match self.pin.port() {
+                Port::B => send_bytes_b(bytes, high, low),
+                Port::C => send_bytes_c(bytes, high, low),
+                Port::D => send_bytes_d(bytes, high, low),
+                Port::E => send_bytes_e(bytes, high, low),
+                Port::F => send_bytes_f(bytes, high, low),
+            }
+        });
+
+        SettleTimer.delay_us(WS2812_RESET_US);
+    }
+
+    /// Sends the colors of the `frame`, if they changed since the last refresh.
+    ///
+    /// Returns `true` if the colors were sent.
+    pub fn refresh<const N: usize>(&self, frame: &mut FrameBuffer<N>) -> bool {
+        let dirty = frame.take_dirty();
+
+        if dirty {
+            self.write(frame);
+        }
+
+        dirty
+    }
+}
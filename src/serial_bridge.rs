@@ -0,0 +1,117 @@
+//! UART to USB-CDC serial bridge.
+//!
+//! Exposes the board UART to the host as a USB-serial port, next to the keyboard HID interface.
+//! Bytes are only forwarded while the bridge is [enabled](crate::bridge::bridge_enabled), which is
+//! toggled with the [SERIAL](crate::layers::SERIAL) key.
+
+use arduino_hal::hal::port::{PD2, PD3};
+use arduino_hal::pac;
+use arduino_hal::port::{
+    mode::{Input, Output},
+    Pin,
+};
+use atmega_usbd::UsbBus;
+use embedded_hal::serial;
+use usb_device::class_prelude::UsbBusAllocator;
+use usbd_serial::SerialPort;
+
+use crate::bridge::{self, ByteQueue};
+
+/// Maximum number of bytes read from the USB-serial port per poll.
+const HOST_READ_LEN: usize = 16;
+
+/// UART of the ATmega32u4, with RX on `PD2` and TX on `PD3`.
+///
+/// The Atreus uses these pins for key matrix columns, so boards that want the bridge need to wire
+/// them to a header instead.
+pub type BoardUart = arduino_hal::Usart<pac::USART1, Pin<Input, PD2>, Pin<Output, PD3>>;
+
+/// Forwards bytes between a UART and a USB-CDC serial port.
+///
+/// The UART defaults to the ATmega32u4 [BoardUart], but any `embedded-hal` serial implementation
+/// works, so boards can define their own TX/RX pins, or buffer RX with the
+/// [Uart](crate::Uart) interrupt.
+pub struct SerialBridge<S = BoardUart> {
+    port: SerialPort<'static, UsbBus>,
+    uart: S,
+    to_host: ByteQueue,
+    to_uart: ByteQueue,
+}
+
+impl<S: serial::Read<u8> + serial::Write<u8>> SerialBridge<S> {
+    /// Creates a new [SerialBridge].
+    ///
+    /// Allocates the USB-serial port on the bus, so it must be created before the USB device is
+    /// built. Enables the [SERIAL](crate::layers::SERIAL) key, see
+    /// [bridge_available](bridge::bridge_available).
+    pub fn new(usb_bus: &'static UsbBusAllocator<UsbBus>, uart: S) -> Self {
+        bridge::set_bridge_available(true);
+
+        Self {
+            port: SerialPort::new(usb_bus),
+            uart,
+            to_host: ByteQueue::new(),
+            to_uart: ByteQueue::new(),
+        }
+    }
+
+    /// Gets a mutable reference to the USB-serial port, e.g. for polling the USB device.
+    pub fn port_mut(&mut self) -> &mut SerialPort<'static, UsbBus> {
+        &mut self.port
+    }
+
+    /// Gets a mutable reference to the UART.
+    pub fn uart_mut(&mut self) -> &mut S {
+        &mut self.uart
+    }
+
+    /// Gets the number of bytes dropped because the host or UART could not keep up.
+    pub fn dropped(&self) -> u16 {
+        self.to_host
+            .dropped()
+            .saturating_add(self.to_uart.dropped())
+    }
+
+    /// Moves pending bytes between the UART and the USB-serial port, without blocking.
+    ///
+    /// Called after every USB device poll.
+    pub fn pump(&mut self) {
+        if !bridge::bridge_enabled() {
+            // keep draining host data, so the port does not stall while the bridge is disabled
+            let mut buf = [0u8; HOST_READ_LEN];
+            while matches!(self.port.read(&mut buf), Ok(len) if len > 0) {}
+
+            self.to_host.clear();
+            self.to_uart.clear();
+
+            return;
+        }
+
+        // UART -> host
+        while let Ok(byte) = self.uart.read() {
+            self.to_host.push(byte);
+        }
+
+        if !self.to_host.is_empty() {
+            if let Ok(len) = self.port.write(self.to_host.front()) {
+                self.to_host.consume(len);
+            }
+        }
+
+        // host -> UART
+        let mut buf = [0u8; HOST_READ_LEN];
+        let free = core::cmp::min(self.to_uart.free(), HOST_READ_LEN);
+
+        if let Ok(len) = self.port.read(&mut buf[..free]) {
+            self.to_uart.extend(&buf[..len]);
+        }
+
+        while let Some(byte) = self.to_uart.peek() {
+            if self.uart.write(byte).is_err() {
+                break;
+            }
+
+            self.to_uart.pop();
+        }
+    }
+}
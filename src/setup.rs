@@ -1,16 +1,132 @@
 use arduino_hal::pac;
+use avr_device::interrupt;
 
-use crate::F_CPU;
+use crate::bootloader::{BOOT_KEY, BOOT_KEY_ADDR};
+use crate::scan_timer::ScanTimerConfig;
+use crate::serial_number::{SIGNATURE_SERIAL_LEN, SIGNATURE_SERIAL_OFFSET};
+use crate::{health, F_CPU};
+
+/// I/O address of the `SPMCSR` register.
+const SPMCSR_IO_ADDR: u8 = 0x37;
+/// `SPMCSR` bits that read the signature row with the next `LPM` instruction (`SIGRD | SPMEN`).
+const SPMCSR_SIGRD: u8 = 0b0010_0001;
 
 /// Setup the timer used to trigger a keyscan.
-pub fn setup_timer(tc1: pac::TC1, interval: u32) {
-    tc1.tccr1b.write(|w| w.wgm1().bits(0b10));
+///
+/// The `interval` is the time between scans in microseconds, see [ScanTimerConfig] for the range.
+pub fn setup_timer(tc1: pac::TC1, interval: u16) {
     tc1.tccr1a.write(|w| unsafe { w.bits(0) });
 
-    let cycles = (F_CPU / 2_000_000) * interval;
-
-    tc1.icr1.write(|w| w.bits(cycles as u16));
+    configure_scan_timer(&tc1, ScanTimerConfig::from_interval_us(F_CPU, interval));
 
-    tc1.tccr1b.write(|w| w.wgm1().bits(0b10).cs1().bits(0b01));
     tc1.timsk1.modify(|_, w| w.toie1().bit(true));
 }
+
+/// Changes the interval of the keyscan timer set up by [setup_timer].
+///
+/// The `interval` is the time between scans in microseconds, see [ScanTimerConfig] for the range.
+/// Returns the achieved interval, after rounding to timer cycles.
+pub fn set_scan_interval(interval: u16) -> u16 {
+    reconfigure_scan_timer(ScanTimerConfig::from_interval_us(F_CPU, interval))
+}
+
+/// Changes the frequency (in Hz) of the keyscan timer set up by [setup_timer].
+///
+/// Returns the achieved interval in microseconds, after rounding to timer cycles.
+pub fn set_scan_frequency_hz(hz: u16) -> u16 {
+    reconfigure_scan_timer(ScanTimerConfig::from_frequency_hz(F_CPU, hz))
+}
+
+/// Detaches from the USB bus, and reboots into the Caterina bootloader with a watchdog reset.
+///
+/// See [bootloader](crate::bootloader).
+pub fn enter_bootloader() -> ! {
+    interrupt::disable();
+
+    // Safety: interrupts stay disabled until the watchdog reset, so nothing else touches the USB
+    // controller or the watchdog.
+    let (usb, wdt) = unsafe { (&*pac::USB_DEVICE::ptr(), &*pac::WDT::ptr()) };
+
+    usb.udcon.modify(|_, w| w.detach().set_bit());
+    usb.usbcon.modify(|_, w| w.frzclk().set_bit());
+
+    // Safety: the boot key address is in SRAM, and the firmware never returns to read it again.
+    unsafe { core::ptr::write_volatile(BOOT_KEY_ADDR as *mut u16, BOOT_KEY) };
+
+    // the watchdog timeout must be set within four cycles of the change enable, the shortest
+    // timeout (16 ms) is selected by leaving the prescaler bits cleared
+    wdt.wdtcsr.write(|w| w.wdce().set_bit().wde().set_bit());
+    wdt.wdtcsr.write(|w| w.wde().set_bit());
+
+    loop {
+        core::hint::spin_loop();
+    }
+}
+
+/// Reads a byte from the signature row, e.g. the device signature or the unique serial number.
+pub fn read_signature_byte(addr: u8) -> u8 {
+    interrupt::free(|_| {
+        let byte: u8;
+
+        // Safety: `LPM` must follow the `SPMCSR` write within three cycles, so both are written in
+        // assembly with interrupts disabled. Reading the signature row has no side effects.
+        unsafe {
+            core::arch::asm!(
+                "out {spmcsr}, {sigrd}",
+                "lpm {byte}, Z",
+                spmcsr = const SPMCSR_IO_ADDR,
+                sigrd = in(reg) SPMCSR_SIGRD,
+                byte = out(reg) byte,
+                in("Z") addr as u16,
+            );
+        }
+
+        byte
+    })
+}
+
+/// Reads the unique serial number bytes from the signature row.
+pub fn read_serial_bytes() -> [u8; SIGNATURE_SERIAL_LEN] {
+    let mut bytes = [0u8; SIGNATURE_SERIAL_LEN];
+
+    for (addr, byte) in (SIGNATURE_SERIAL_OFFSET..).zip(bytes.iter_mut()) {
+        *byte = read_signature_byte(addr);
+    }
+
+    bytes
+}
+
+// Reconfigures the running keyscan timer with interrupts disabled.
+fn reconfigure_scan_timer(config: ScanTimerConfig) -> u16 {
+    interrupt::free(|_| {
+        // Safety: only the timer owned by `setup_timer` is changed, with interrupts disabled.
+        let tc1 = unsafe { &*pac::TC1::ptr() };
+
+        configure_scan_timer(tc1, config);
+    });
+
+    config.interval_us()
+}
+
+// Stops the keyscan timer, and restarts it from `BOTTOM` with the new prescaler and `TOP`.
+//
+// `ICR1` is not double-buffered, so the counter could be past a lowered `TOP` otherwise, and run
+// up to `0xffff` before the next overflow.
+fn configure_scan_timer(tc1: &pac::tc1::RegisterBlock, config: ScanTimerConfig) {
+    tc1.tccr1b.write(|w| w.wgm1().bits(0b10));
+
+    tc1.icr1.write(|w| w.bits(config.top()));
+    tc1.tcnt1.write(|w| w.bits(0));
+
+    tc1.tccr1b
+        .write(|w| w.wgm1().bits(0b10).cs1().bits(config.clock_select()));
+
+    health::set_tick_interval_us(config.interval_us());
+}
+
+/// Setup the free-running timer used by the [SettleTimer](crate::SettleTimer).
+pub fn setup_settle_timer(tc3: pac::TC3) {
+    tc3.tccr3a.write(|w| unsafe { w.bits(0) });
+    // normal mode, clocked at F_CPU / SETTLE_TIMER_PRESCALER
+    tc3.tccr3b.write(|w| w.cs3().bits(0b010));
+}
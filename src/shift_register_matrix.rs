@@ -0,0 +1,141 @@
+use embedded_hal::blocking::delay::DelayUs;
+use embedded_hal::blocking::spi::{Transfer, Write};
+use embedded_hal::digital::v2::OutputPin;
+
+use crate::key_matrix::MatrixReader;
+use crate::shift_register::{self, MAX_COL_BYTES, MAX_ROW_BYTES, MAX_SHIFT_ROWS};
+use crate::{layers, RowState, SettleTimer, DEFAULT_SETTLE_US, MAX_COLS};
+
+/// Represents a key matrix driven and read through shift registers over SPI.
+///
+/// Rows are driven by chained 74HC595 shift registers, and columns are read by chained 74HC165
+/// shift registers, so a high key-count board only needs the SPI pins and two latch pins. See
+/// [shift_register] for the bit layout.
+///
+/// The columns can only be read over SPI, so the matrix can not wake from idle on a key press.
+///
+/// Both chains share the SPI bus:
+///
+/// - `row_latch` is the 74HC595 storage clock (`RCLK`), and outputs the shifted row on a rising
+///   edge
+/// - `col_load` is the 74HC165 shift/load input (`SH/LD`), and samples the columns while low
+///
+/// On the hardware SPI, pass the [SpiBus](crate::SpiBus), and [ChipSelect](crate::ChipSelect)
+/// pins as the latches.
+pub struct ShiftRegisterMatrix<
+    SPI,
+    L,
+    const ROWS: usize = { layers::ROWS },
+    const COLS: usize = { layers::COLS },
+    T = SettleTimer,
+> {
+    spi: SPI,
+    row_latch: L,
+    col_load: L,
+    settle: T,
+    settle_us: u16,
+}
+
+impl<SPI, L: OutputPin, const ROWS: usize, const COLS: usize, T>
+    ShiftRegisterMatrix<SPI, L, ROWS, COLS, T>
+{
+    const VALID_LINES: () = assert!(
+        ROWS <= MAX_SHIFT_ROWS && COLS <= MAX_COLS,
+        "too many lines for the shift-register chains"
+    );
+
+    /// Creates a new [ShiftRegisterMatrix] from the SPI bus and latch pins.
+    pub fn new(spi: SPI, mut row_latch: L, mut col_load: L) -> Self
+    where
+        T: Default,
+    {
+        #[allow(clippy::let_unit_value)]
+        let _ = Self::VALID_LINES;
+
+        // idle in shift mode, with the row outputs held
+        row_latch.set_low().ok();
+        col_load.set_high().ok();
+
+        Self {
+            spi,
+            row_latch,
+            col_load,
+            settle: T::default(),
+            settle_us: DEFAULT_SETTLE_US,
+        }
+    }
+
+    /// Gets the time (in microseconds) for the column inputs to settle before they are read.
+    pub const fn settle_us(&self) -> u16 {
+        self.settle_us
+    }
+
+    /// Sets the time (in microseconds) for the column inputs to settle before they are read.
+    pub fn set_settle_us(&mut self, val: u16) {
+        self.settle_us = val;
+    }
+
+    /// Builder function that sets the time (in microseconds) for the column inputs to settle.
+    pub fn with_settle_us(mut self, val: u16) -> Self {
+        self.set_settle_us(val);
+        self
+    }
+
+    /// Gets a mutable reference to the SPI bus.
+    pub fn spi_mut(&mut self) -> &mut SPI {
+        &mut self.spi
+    }
+
+    /// Shifts out the row outputs, and latches them onto the rows.
+    fn write_rows(&mut self, bytes: &[u8])
+    where
+        SPI: Write<u8>,
+    {
+        self.spi.write(bytes).ok();
+
+        self.row_latch.set_high().ok();
+        self.row_latch.set_low().ok();
+    }
+}
+
+impl<SPI, L, const ROWS: usize, const COLS: usize, T> MatrixReader<ROWS, COLS>
+    for ShiftRegisterMatrix<SPI, L, ROWS, COLS, T>
+where
+    SPI: Transfer<u8> + Write<u8>,
+    L: OutputPin,
+    T: DelayUs<u16>,
+{
+    fn read_row(&mut self, row: usize) -> RowState {
+        if row >= ROWS {
+            return RowState::new();
+        }
+
+        let row_bytes = shift_register::row_select_bytes(row, ROWS);
+        self.write_rows(&row_bytes[..shift_register::chain_bytes(ROWS)]);
+
+        // wait for the column inputs to settle, once for the whole row
+        self.settle.delay_us(self.settle_us);
+
+        // sample the columns, then shift them in
+        self.col_load.set_low().ok();
+        self.col_load.set_high().ok();
+
+        let mut col_bytes = [0xff; MAX_COL_BYTES];
+        let col_bytes = &mut col_bytes[..shift_register::chain_bytes(COLS)];
+
+        // unread columns stay high, and read as released
+        let hot_pins = match self.spi.transfer(col_bytes) {
+            Ok(bytes) => shift_register::cols_from_bytes(bytes, COLS),
+            Err(_) => RowState::new(),
+        };
+
+        // deactivate the row, to avoid interference with following reads
+        self.write_rows(&[0xff; MAX_ROW_BYTES][..shift_register::chain_bytes(ROWS)]);
+
+        hot_pins
+    }
+
+    fn set_settle_us(&mut self, val: u16) {
+        self.settle_us = val;
+    }
+}
@@ -0,0 +1,141 @@
+//! Solenoid or vibration motor on a spare pin, see [haptic](crate::haptic).
+//!
+//! Set the output up with [setup_solenoid], add [solenoid_task] to the board's
+//! [Housekeeping](crate::housekeeping::Housekeeping) tasks, and register
+//! [haptic_key_event](crate::haptic::haptic_key_event) as a key event hook.
+
+use core::cell::RefCell;
+
+use arduino_hal::pac;
+use avr_device::interrupt::{self, Mutex};
+
+use crate::haptic::{self, Haptic, HapticConfig};
+use crate::housekeeping;
+use crate::layers;
+use crate::pin_map::{PinId, PinMap, Port};
+
+/// Global haptic output, pulsed from the main loop.
+pub static SOLENOID: Mutex<RefCell<Option<Solenoid>>> = Mutex::new(RefCell::new(None));
+
+/// Haptic output on a pin, driven high during a pulse.
+pub struct Solenoid {
+    pin: PinId,
+    haptic: Haptic,
+    on: bool,
+}
+
+impl Solenoid {
+    /// Creates a new [Solenoid] on the `pin`, and configures it as an output driven low.
+    ///
+    /// Returns `None` for key matrix pins, and pins not bonded out.
+    pub fn new(pin: PinId, map: &PinMap, config: HapticConfig) -> Option<Self> {
+        if !pin.is_available() || map.contains(&pin) {
+            return None;
+        }
+
+        let solenoid = Self {
+            pin,
+            haptic: Haptic::new(config),
+            on: false,
+        };
+        solenoid.write_pin(false);
+
+        let bit = 1 << pin.bit();
+
+        // Safety: only the bit of the haptic pin is changed, from the main loop.
+        unsafe {
+            match pin.port() {
+                Port::B => (*pac::PORTB::ptr())
+                    .ddrb
+                    .modify(|r, w| w.bits(r.bits() | bit)),
+                Port::C => (*pac::PORTC::ptr())
+                    .ddrc
+                    .modify(|r, w| w.bits(r.bits() | bit)),
+                Port::D => (*pac::PORTD::ptr())
+                    .ddrd
+                    .modify(|r, w| w.bits(r.bits() | bit)),
+                Port::E => (*pac::PORTE::ptr())
+                    .ddre
+                    .modify(|r, w| w.bits(r.bits() | bit)),
+                Port::F => (*pac::PORTF::ptr())
+                    .ddrf
+                    .modify(|r, w| w.bits(r.bits() | bit)),
+            }
+        }
+
+        Some(solenoid)
+    }
+
+    /// Gets the [Haptic] state.
+    pub fn haptic(&mut self) -> &mut Haptic {
+        &mut self.haptic
+    }
+
+    /// Fires or ends a pulse at the time `now_ms`, and drives the pin.
+    ///
+    /// Keeps the main loop awake while a pulse runs, so it is ended on time even if the matrix
+    /// goes idle.
+    pub fn update(&mut self, now_ms: u16) {
+        let on = self
+            .haptic
+            .update(now_ms, haptic::take_key_press(), layers::active_layer());
+
+        if on != self.on {
+            self.on = on;
+            self.write_pin(on);
+        }
+
+        if on {
+            housekeeping::wake_main_loop();
+        }
+    }
+
+    // Drives the pin.
+    fn write_pin(&self, high: bool) {
+        let bit = 1 << self.pin.bit();
+        let level = |val: u8| if high { val | bit } else { val & !bit };
+
+        // Safety: only the bit of the haptic pin is changed, with interrupts disabled.
+        interrupt::free(|_| unsafe {
+            match self.pin.port() {
+                Port::B => (*pac::PORTB::ptr())
+                    .portb
+                    .modify(|r, w| w.bits(level(r.bits()))),
+                Port::C => (*pac::PORTC::ptr())
+                    .portc
+                    .modify(|r, w| w.bits(level(r.bits()))),
+                Port::D => (*pac::PORTD::ptr())
+                    .portd
+                    .modify(|r, w| w.bits(level(r.bits()))),
+                Port::E => (*pac::PORTE::ptr())
+                    .porte
+                    .modify(|r, w| w.bits(level(r.bits()))),
+                Port::F => (*pac::PORTF::ptr())
+                    .portf
+                    .modify(|r, w| w.bits(level(r.bits()))),
+            }
+        });
+    }
+}
+
+/// Sets up the [Solenoid] on the `pin`, with the [HapticConfig].
+///
+/// Returns `false` if the pin is not usable, see [Solenoid::new].
+pub fn setup_solenoid(pin: PinId, map: &PinMap, config: HapticConfig) -> bool {
+    match Solenoid::new(pin, map, config) {
+        Some(solenoid) => {
+            interrupt::free(|cs| SOLENOID.borrow(cs).replace(Some(solenoid)));
+            true
+        }
+        None => false,
+    }
+}
+
+/// Pulses the haptic output for key presses, an [IdleTask](crate::housekeeping::IdleTask).
+pub fn solenoid_task(now_ms: u16) {
+    interrupt::free(|cs| {
+        if let Some(solenoid) = SOLENOID.borrow(cs).borrow_mut().as_mut() {
+            solenoid.update(now_ms);
+        }
+    });
+}
@@ -0,0 +1,222 @@
+//! Hardware SPI master, see [spi_config](crate::spi_config).
+//!
+//! The [SpiBus] owns the SPI pins (`PB1` SCK, `PB2` MOSI, `PB3` MISO), and implements the blocking
+//! SPI traits of `embedded-hal`, so drivers like the [ShiftRegisterMatrix](crate::ShiftRegisterMatrix)
+//! and the [Pmw3360](crate::Pmw3360) take it directly. Each device on the bus gets a
+//! [ChipSelect] pin, and a [transaction](SpiBus::transaction) applies the [SpiConfig] of the
+//! device while its chip-select is held low.
+//!
+//! `PB0` (SS) is driven as an output, since a low SS input would switch the bus to slave mode.
+//! Keep the [Spi](crate::power::Peripheral::Spi) powered in the board's
+//! [PowerReduction](crate::power::PowerReduction).
+
+use arduino_hal::pac;
+use embedded_hal::blocking::spi::{Transfer, Write};
+use embedded_hal::digital::v2::OutputPin;
+
+use crate::pin_map::{PinId, PinMap, Port};
+use crate::spi_config::SpiConfig;
+use crate::F_CPU;
+
+// Bits of the SPI pins in port B.
+const SS: u8 = 1 << 0;
+const SCK: u8 = 1 << 1;
+const MOSI: u8 = 1 << 2;
+
+/// Blocking SPI master on the hardware SPI, see the [module](self) docs.
+pub struct SpiBus {
+    spi: pac::SPI,
+    config: SpiConfig,
+}
+
+impl SpiBus {
+    /// Creates a new [SpiBus], configures the SPI pins, and applies the [SpiConfig].
+    pub fn new(spi: pac::SPI, config: SpiConfig) -> Self {
+        // Safety: only the bits of the SPI pins are changed, at boot like the key matrix pins.
+        unsafe {
+            (*pac::PORTB::ptr())
+                .portb
+                .modify(|r, w| w.bits(r.bits() | SS));
+            (*pac::PORTB::ptr())
+                .ddrb
+                .modify(|r, w| w.bits(r.bits() | SS | SCK | MOSI));
+        }
+
+        let mut bus = Self { spi, config };
+        bus.apply(config);
+
+        bus
+    }
+
+    /// Gets the [SpiConfig] applied to the bus.
+    pub const fn config(&self) -> &SpiConfig {
+        &self.config
+    }
+
+    /// Applies the [SpiConfig], e.g. before talking to a device with another clock mode.
+    pub fn set_config(&mut self, config: SpiConfig) {
+        if config != self.config {
+            self.apply(config);
+        }
+    }
+
+    /// Sends a byte, and returns the byte received at the same time.
+    pub fn transfer_byte(&mut self, byte: u8) -> u8 {
+        self.spi.spdr.write(|w| w.bits(byte));
+        while self.spi.spsr.read().spif().bit_is_clear() {}
+
+        self.spi.spdr.read().bits()
+    }
+
+    /// Runs `f` with the bus set up for a device, while the device's [ChipSelect] is held low.
+    pub fn transaction<R, F: FnOnce(&mut Self) -> R>(
+        &mut self,
+        cs: &mut ChipSelect,
+        config: SpiConfig,
+        f: F,
+    ) -> R {
+        self.set_config(config);
+
+        cs.select();
+        let result = f(self);
+        cs.deselect();
+
+        result
+    }
+
+    // Writes the control registers.
+    fn apply(&mut self, config: SpiConfig) {
+        self.config = config;
+
+        self.spi
+            .spsr
+            .write(|w| w.spi2x().bit(config.double_speed(F_CPU)));
+        self.spi
+            .spcr
+            .write(|w| unsafe { w.bits(config.spcr(F_CPU)) });
+    }
+}
+
+impl Transfer<u8> for SpiBus {
+    type Error = core::convert::Infallible;
+
+    fn transfer<'w>(&mut self, words: &'w mut [u8]) -> Result<&'w [u8], Self::Error> {
+        for word in words.iter_mut() {
+            *word = self.transfer_byte(*word);
+        }
+
+        Ok(words)
+    }
+}
+
+impl Write<u8> for SpiBus {
+    type Error = core::convert::Infallible;
+
+    fn write(&mut self, words: &[u8]) -> Result<(), Self::Error> {
+        for &word in words {
+            self.transfer_byte(word);
+        }
+
+        Ok(())
+    }
+}
+
+/// Chip-select pin of an SPI device, idle high.
+pub struct ChipSelect {
+    pin: PinId,
+}
+
+impl ChipSelect {
+    /// Creates a new [ChipSelect] on the `pin`, and configures it as an output driven high.
+    ///
+    /// Returns `None` for key matrix pins, and pins not bonded out.
+    pub fn new(pin: PinId, map: &PinMap) -> Option<Self> {
+        if !pin.is_available() || map.contains(&pin) {
+            return None;
+        }
+
+        let mut cs = Self { pin };
+        cs.deselect();
+
+        let bit = 1 << pin.bit();
+
+        // Safety: only the bit of the chip-select pin is changed, from the main loop.
+        unsafe {
+            match pin.port() {
+                Port::B => (*pac::PORTB::ptr())
+                    .ddrb
+                    .modify(|r, w| w.bits(r.bits() | bit)),
+                Port::C => (*pac::PORTC::ptr())
+                    .ddrc
+                    .modify(|r, w| w.bits(r.bits() | bit)),
+                Port::D => (*pac::PORTD::ptr())
+                    .ddrd
+                    .modify(|r, w| w.bits(r.bits() | bit)),
+                Port::E => (*pac::PORTE::ptr())
+                    .ddre
+                    .modify(|r, w| w.bits(r.bits() | bit)),
+                Port::F => (*pac::PORTF::ptr())
+                    .ddrf
+                    .modify(|r, w| w.bits(r.bits() | bit)),
+            }
+        }
+
+        Some(cs)
+    }
+
+    /// Gets the pin.
+    pub const fn pin(&self) -> PinId {
+        self.pin
+    }
+
+    /// Drives the pin low, selecting the device.
+    pub fn select(&mut self) {
+        self.write(false);
+    }
+
+    /// Drives the pin high, releasing the device.
+    pub fn deselect(&mut self) {
+        self.write(true);
+    }
+
+    // Drives the pin.
+    fn write(&mut self, high: bool) {
+        let bit = 1 << self.pin.bit();
+        let level = |val: u8| if high { val | bit } else { val & !bit };
+
+        // Safety: only the bit of the chip-select pin is changed, with interrupts disabled.
+        avr_device::interrupt::free(|_| unsafe {
+            match self.pin.port() {
+                Port::B => (*pac::PORTB::ptr())
+                    .portb
+                    .modify(|r, w| w.bits(level(r.bits()))),
+                Port::C => (*pac::PORTC::ptr())
+                    .portc
+                    .modify(|r, w| w.bits(level(r.bits()))),
+                Port::D => (*pac::PORTD::ptr())
+                    .portd
+                    .modify(|r, w| w.bits(level(r.bits()))),
+                Port::E => (*pac::PORTE::ptr())
+                    .porte
+                    .modify(|r, w| w.bits(level(r.bits()))),
+                Port::F => (*pac::PORTF::ptr())
+                    .portf
+                    .modify(|r, w| w.bits(level(r.bits()))),
+            }
+        });
+    }
+}
+
+impl OutputPin for ChipSelect {
+    type Error = core::convert::Infallible;
+
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.select();
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.deselect();
+        Ok(())
+    }
+}
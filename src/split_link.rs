@@ -0,0 +1,178 @@
+//! Split keyboard link over the I2C bus, see [split](crate::split).
+//!
+//! Both halves scan their own matrix with a [SplitMatrix]. The primary half is the I2C master, and
+//! reads the matrix state of the secondary half every scan, which takes about 300 microseconds.
+//! The secondary half answers as the I2C device at [SPLIT_LINK_ADDR] from the `TWI` interrupt of
+//! the board, which calls [split_link_interrupt], and reports no keys itself.
+//!
+//! The primary half scans the [SPLIT_ROWS] rows of both halves, so its key scanner is a
+//! `KeyScanner<SPLIT_ROWS, COLS, SplitKeymap<K>, SplitMatrix<M>>` with a `ROWS` x
+//! [SPLIT_COLS](crate::split::SPLIT_COLS) logical key map `K`, e.g.
+//! [SplitAtreusKeymap](crate::split::SplitAtreusKeymap).
+//!
+//! The link uses the I2C pins (`PD0` SCL, `PD1` SDA), which are free on the Atreus. Wire SCL, SDA,
+//! VCC, and ground straight through between the halves, e.g. over a TRRS cable, with 4.7k pull-up
+//! resistors from SCL and SDA to VCC on one half. Pick the role of a half with [detect_split_role]
+//! once the USB bus is created, and keep the [Twi](crate::power::Peripheral::Twi) powered. The link
+//! takes the TWI peripheral, so it does not work alongside other I2C devices, e.g. a
+//! [status display](crate::oled_display).
+
+use core::cell::RefCell;
+
+use arduino_hal::pac;
+use avr_device::interrupt::{self, Mutex};
+
+use crate::debounce::RowState;
+use crate::i2c::I2cMaster;
+use crate::layers::{COLS, ROWS};
+use crate::scanner::MatrixScanner;
+use crate::split::{
+    self, LinkReceiver, LinkTransmitter, SplitHand, SplitRole, LINK_FRAME_LEN, SPLIT_ROWS,
+};
+use crate::time;
+
+/// 7-bit I2C address of the secondary half.
+pub const SPLIT_LINK_ADDR: u8 = 0x2a;
+
+// TWI status codes of a slave transmitter.
+const STATUS_SLAVE_READ: u8 = 0xa8;
+const STATUS_SLAVE_DATA_ACK: u8 = 0xb8;
+const STATUS_BUS_ERROR: u8 = 0x00;
+
+/// Matrix state of the secondary half, served by the `TWI` interrupt.
+static LINK_TX: Mutex<RefCell<LinkTransmitter>> = Mutex::new(RefCell::new(LinkTransmitter::new()));
+
+/// Key matrix of one half of a split keyboard, merged with the other half over the link.
+pub struct SplitMatrix<M> {
+    local: M,
+    i2c: Option<I2cMaster>,
+    role: SplitRole,
+    hand: SplitHand,
+    receiver: LinkReceiver,
+}
+
+impl<M: MatrixScanner<ROWS, COLS>> SplitMatrix<M> {
+    /// Creates a new [SplitMatrix] for the `local` matrix of a half with the [SplitRole] `role`,
+    /// linked to the other half over the `twi`.
+    ///
+    /// The primary half is the left half, so either board can be plugged in, see
+    /// [from_role](SplitHand::from_role). The secondary half answers from the `TWI` interrupt,
+    /// see [split_link_interrupt].
+    pub fn new(local: M, twi: pac::TWI, role: SplitRole) -> Self {
+        let i2c = match role {
+            SplitRole::Primary => Some(I2cMaster::new(twi)),
+            SplitRole::Secondary => {
+                twi.twar.write(|w| w.bits(SPLIT_LINK_ADDR << 1));
+                twi.twcr
+                    .write(|w| w.twea().set_bit().twen().set_bit().twie().set_bit());
+                None
+            }
+        };
+
+        Self {
+            local,
+            i2c,
+            role,
+            hand: SplitHand::from_role(role),
+            receiver: LinkReceiver::new(),
+        }
+    }
+
+    /// Gets the [SplitRole] of the half.
+    pub const fn role(&self) -> SplitRole {
+        self.role
+    }
+
+    /// Gets the [SplitHand] of the half.
+    pub const fn hand(&self) -> SplitHand {
+        self.hand
+    }
+
+    /// Gets whether the primary half receives the matrix state of the secondary half.
+    pub fn connected(&self) -> bool {
+        self.receiver.connected(time::millis() as u16)
+    }
+
+    /// Gets the number of link frames dropped due to checksum errors.
+    pub fn link_errors(&self) -> u16 {
+        self.receiver.errors()
+    }
+}
+
+impl<M: MatrixScanner<ROWS, COLS>> MatrixScanner<SPLIT_ROWS, COLS> for SplitMatrix<M> {
+    fn scan(&mut self) -> [RowState; SPLIT_ROWS] {
+        let local = self.local.scan();
+        let now_ms = time::millis() as u16;
+
+        match self.i2c.as_mut() {
+            Some(i2c) => {
+                // a failed read releases the keys of the secondary half once the link times out
+                let mut frame = [0u8; LINK_FRAME_LEN];
+                if i2c.read(SPLIT_LINK_ADDR, &mut frame).is_ok() {
+                    for &byte in frame.iter() {
+                        self.receiver.push(byte, now_ms);
+                    }
+                }
+
+                split::merge_halves(&local, &self.receiver.rows(now_ms), self.hand)
+            }
+            None => {
+                let rows = split::link_rows(&local);
+                interrupt::free(|cs| LINK_TX.borrow(cs).borrow_mut().publish(&rows));
+
+                [RowState::new(); SPLIT_ROWS]
+            }
+        }
+    }
+
+    fn set_settle_us(&mut self, val: u16) {
+        self.local.set_settle_us(val);
+    }
+}
+
+/// Sends the matrix state of the secondary half to the primary half, called from the `TWI`
+/// interrupt.
+pub fn split_link_interrupt() {
+    // Safety: the interrupt only runs on the secondary half, where the main loop does not touch
+    // the TWI registers once the [SplitMatrix] is created.
+    let twi = unsafe { &*pac::TWI::ptr() };
+
+    let status = twi.twsr.read().tws().bits() << 3;
+
+    interrupt::free(|cs| {
+        let mut tx = LINK_TX.borrow(cs).borrow_mut();
+
+        match status {
+            STATUS_SLAVE_READ => twi.twdr.write(|w| w.bits(tx.start())),
+            STATUS_SLAVE_DATA_ACK => twi.twdr.write(|w| w.bits(tx.next_byte())),
+            _ => (),
+        }
+    });
+
+    // a bus error releases the bus, and the half waits to be addressed again
+    let stop = status == STATUS_BUS_ERROR;
+    twi.twcr.write(|w| {
+        w.twint()
+            .set_bit()
+            .twsto()
+            .bit(stop)
+            .twea()
+            .set_bit()
+            .twen()
+            .set_bit()
+            .twie()
+            .set_bit()
+    });
+}
+
+/// Detects the [SplitRole] of the half: the half powered over USB is the primary.
+///
+/// Call once the USB bus is created, which powers the USB pads.
+pub fn detect_split_role() -> SplitRole {
+    // Safety: only enables the VBUS pad, which the USB bus driver enables as well, and reads the
+    // VBUS status.
+    let usb = unsafe { &*pac::USB_DEVICE::ptr() };
+    usb.usbcon.modify(|_, w| w.otgpade().set_bit());
+
+    SplitRole::from_usb_powered(usb.usbsta.read().vbus().bit_is_set())
+}
@@ -3,24 +3,64 @@
 use core::panic::PanicInfo;
 
 use arduino_hal::{delay_ms, pins, Peripherals};
+use avr_device::interrupt;
 
+use crate::panic_report::{self, PANIC_BLINK_PATTERN, PANIC_BOOTLOADER_BLINKS, PANIC_LINE_LEN};
+use crate::USB_CTX;
+
+/// Blinks the status LED, reports the panic location on the debug port, and with the
+/// `panic-bootloader` feature reboots into the bootloader, see [panic_report].
 #[panic_handler]
-fn panic(_info: &PanicInfo) -> ! {
+fn panic(info: &PanicInfo) -> ! {
+    interrupt::disable();
+
+    // Safety: interrupts stay disabled, and the firmware never returns to the code that owned the
+    // peripherals.
     let dp = unsafe { Peripherals::steal() };
     let pins = pins!(dp);
     let mut status = pins.d13.into_output();
+
+    let mut line = [0u8; PANIC_LINE_LEN];
+    let len = info.location().map_or(0, |location| {
+        panic_report::write_panic_line(
+            location.file(),
+            location.line(),
+            location.column(),
+            &mut line,
+        )
+    });
+    with_usb(|ctx| ctx.write_debug(&line[..len]));
+
+    let mut blinks = 0u8;
+
     loop {
-        status.set_high();
-        delay_ms(100);
-        status.set_low();
-        delay_ms(100);
-        status.set_high();
-        delay_ms(300);
-        status.set_low();
-        delay_ms(500);
+        for (on, ms) in PANIC_BLINK_PATTERN {
+            if on {
+                status.set_high();
+            } else {
+                status.set_low();
+            }
+
+            // keep the USB device polled, so the host receives the panic line
+            for _ in 0..ms {
+                with_usb(|ctx| ctx.poll());
+                delay_ms(1);
+            }
+        }
+
+        blinks = blinks.saturating_add(1);
+
+        if cfg!(feature = "panic-bootloader") && blinks >= PANIC_BOOTLOADER_BLINKS {
+            crate::enter_bootloader();
+        }
     }
 }
 
+// Runs `f` with the USB context, unless the panic happened while it was borrowed.
+fn with_usb<R>(f: impl FnOnce(&mut crate::UsbContext) -> R) -> Option<R> {
+    interrupt::free(|cs| USB_CTX.borrow(cs).try_borrow_mut().ok()?.as_mut().map(f))
+}
+
 /// # Safety
 ///
 /// No-op personality function.
@@ -0,0 +1,224 @@
+//! PMW3360 trackball sensor on the SPI bus, see [pmw3360](crate::pmw3360).
+//!
+//! The sensor is generic over the SPI traits, like the
+//! [ShiftRegisterMatrix](crate::ShiftRegisterMatrix), with its own chip-select pin: pass it the
+//! [SpiBus](crate::SpiBus) in [SpiMode::Mode3](crate::spi_config::SpiMode::Mode3), and a
+//! [ChipSelect](crate::ChipSelect). Create it with
+//! [Pmw3360::new] at boot, then [poll](Pmw3360::poll) it from the main loop before each scan, so
+//! the motion goes out with the scan's [mouse](crate::mouse) report.
+//!
+//! The sensor tracks best with the PixArt SROM firmware, which is not distributed with trove.
+//! Boards with the firmware blob place it in program memory with the `flash_slice!` macro of
+//! [flash](crate::flash), and [upload](Pmw3360::upload_srom) it after the reset.
+
+use embedded_hal::blocking::delay::DelayUs;
+use embedded_hal::blocking::spi::{Transfer, Write};
+use embedded_hal::digital::v2::OutputPin;
+
+use crate::flash::FlashSlice;
+use crate::mouse;
+use crate::pmw3360::{
+    self, Motion, SensorConfig, MOTION_BURST_LEN, POWER_UP_RESET, PRODUCT_ID, REG_CONFIG1,
+    REG_CONFIG2, REG_MOTION, REG_MOTION_BURST, REG_POWER_UP_RESET, REG_PRODUCT_ID, REG_SROM_ENABLE,
+    REG_SROM_ID, REG_SROM_LOAD_BURST, SROM_DOWNLOAD_INIT, SROM_DOWNLOAD_START,
+};
+use crate::SettleTimer;
+
+// Sensor timing (in microseconds) from the datasheet.
+const RESET_US: u16 = 50_000;
+const SROM_INIT_US: u16 = 10_000;
+const SROM_BYTE_US: u16 = 15;
+const SROM_EXIT_US: u16 = 200;
+const READ_ADDR_US: u16 = 160;
+const BURST_ADDR_US: u16 = 35;
+const WRITE_HOLD_US: u16 = 35;
+const WRITE_WRITE_US: u16 = 180;
+const READ_READ_US: u16 = 20;
+
+/// Errors from a PMW3360 sensor.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SensorError {
+    /// An SPI transfer failed.
+    Spi,
+    /// The sensor answered with another product ID, e.g. if it is not connected.
+    ProductId(u8),
+    /// The SROM firmware did not start after the upload.
+    Srom,
+}
+
+/// PMW3360 sensor on an SPI bus, see the [module](self) docs.
+pub struct Pmw3360<SPI, CS, T = SettleTimer> {
+    spi: SPI,
+    cs: CS,
+    delay: T,
+    config: SensorConfig,
+    burst: bool,
+}
+
+impl<SPI, CS, T> Pmw3360<SPI, CS, T>
+where
+    SPI: Transfer<u8> + Write<u8>,
+    CS: OutputPin,
+    T: DelayUs<u16>,
+{
+    /// Creates a new [Pmw3360], resets the sensor, and applies the [SensorConfig].
+    ///
+    /// The bus must be in SPI mode 3, at 2 MHz or less. Returns an error if the sensor does not
+    /// answer with the PMW3360 product ID.
+    pub fn new(spi: SPI, cs: CS, config: SensorConfig) -> Result<Self, SensorError>
+    where
+        T: Default,
+    {
+        let mut sensor = Self {
+            spi,
+            cs,
+            delay: T::default(),
+            config,
+            burst: false,
+        };
+
+        // a chip-select pulse resets the serial port of the sensor
+        sensor.cs.set_high().ok();
+        sensor.cs.set_low().ok();
+        sensor.cs.set_high().ok();
+
+        sensor.write_reg(REG_POWER_UP_RESET, POWER_UP_RESET)?;
+        sensor.delay.delay_us(RESET_US);
+
+        // clear the motion counted during the reset
+        for reg in REG_MOTION..REG_MOTION + 5 {
+            sensor.read_reg(reg)?;
+        }
+
+        match sensor.read_reg(REG_PRODUCT_ID)? {
+            PRODUCT_ID => (),
+            id => return Err(SensorError::ProductId(id)),
+        }
+
+        sensor.set_cpi(config.cpi())?;
+
+        Ok(sensor)
+    }
+
+    /// Gets the [SensorConfig].
+    pub const fn config(&self) -> &SensorConfig {
+        &self.config
+    }
+
+    /// Sets the resolution (in counts per inch).
+    pub fn set_cpi(&mut self, cpi: u16) -> Result<(), SensorError> {
+        self.config = self.config.with_cpi(cpi);
+        self.write_reg(REG_CONFIG1, pmw3360::cpi_config(cpi))
+    }
+
+    /// Uploads the SROM `firmware` to the sensor.
+    ///
+    /// Takes about 70 milliseconds for the 4 KB firmware, call once after [new](Self::new).
+    /// Returns the ID of the running firmware.
+    pub fn upload_srom(&mut self, firmware: FlashSlice<u8>) -> Result<u8, SensorError> {
+        self.write_reg(REG_SROM_ENABLE, SROM_DOWNLOAD_INIT)?;
+        self.delay.delay_us(SROM_INIT_US);
+        self.write_reg(REG_SROM_ENABLE, SROM_DOWNLOAD_START)?;
+
+        self.cs.set_low().ok();
+        let result = self.write(&[pmw3360::write_addr(REG_SROM_LOAD_BURST)]);
+        let result = firmware.iter().fold(result, |result, byte| {
+            self.delay.delay_us(SROM_BYTE_US);
+            result.and_then(|_| self.write(&[byte]))
+        });
+        self.cs.set_high().ok();
+        result?;
+
+        self.delay.delay_us(SROM_EXIT_US);
+
+        let id = self.read_reg(REG_SROM_ID)?;
+        if id == 0 {
+            return Err(SensorError::Srom);
+        }
+
+        // stay awake, rest modes add latency
+        self.write_reg(REG_CONFIG2, 0)?;
+
+        Ok(id)
+    }
+
+    /// Reads the [Motion] counted since the last read, in a motion burst.
+    pub fn read_motion(&mut self) -> Result<Motion, SensorError> {
+        // the sensor stays in burst mode until another register is accessed
+        if !self.burst {
+            self.write_reg(REG_MOTION_BURST, 0)?;
+            self.burst = true;
+        }
+
+        let mut burst = [0u8; MOTION_BURST_LEN];
+
+        self.cs.set_low().ok();
+        let result = self.write(&[REG_MOTION_BURST]).and_then(|_| {
+            self.delay.delay_us(BURST_ADDR_US);
+            self.transfer(&mut burst)
+        });
+        self.cs.set_high().ok();
+
+        if result.is_err() {
+            self.burst = false;
+        }
+        result?;
+
+        Ok(Motion::from_burst(&burst))
+    }
+
+    /// Reads the motion, and adds it to the next mouse report.
+    pub fn poll(&mut self) -> Result<(), SensorError> {
+        let motion = self.read_motion()?;
+        let (dx, dy) = self.config.pointer_motion(&motion);
+
+        if dx != 0 || dy != 0 {
+            mouse::add_motion(dx, dy);
+        }
+
+        Ok(())
+    }
+
+    // Reads a register.
+    fn read_reg(&mut self, reg: u8) -> Result<u8, SensorError> {
+        self.burst = false;
+
+        let mut val = [0u8];
+
+        self.cs.set_low().ok();
+        let result = self.write(&[reg]).and_then(|_| {
+            self.delay.delay_us(READ_ADDR_US);
+            self.transfer(&mut val)
+        });
+        self.cs.set_high().ok();
+        self.delay.delay_us(READ_READ_US);
+
+        result.map(|_| val[0])
+    }
+
+    // Writes a register.
+    fn write_reg(&mut self, reg: u8, val: u8) -> Result<(), SensorError> {
+        self.burst = false;
+
+        self.cs.set_low().ok();
+        let result = self.write(&[pmw3360::write_addr(reg), val]);
+        self.delay.delay_us(WRITE_HOLD_US);
+        self.cs.set_high().ok();
+        self.delay.delay_us(WRITE_WRITE_US);
+
+        result
+    }
+
+    // Writes bytes on the bus.
+    fn write(&mut self, bytes: &[u8]) -> Result<(), SensorError> {
+        self.spi.write(bytes).map_err(|_| SensorError::Spi)
+    }
+
+    // Reads bytes from the bus.
+    fn transfer(&mut self, buf: &mut [u8]) -> Result<(), SensorError> {
+        self.spi
+            .transfer(buf)
+            .map(|_| ())
+            .map_err(|_| SensorError::Spi)
+    }
+}
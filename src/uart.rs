@@ -0,0 +1,221 @@
+//! Hardware UART on USART1, see [uart_config](crate::uart_config).
+//!
+//! The [Uart] sends with blocking writes, and receives either by polling the data register, or
+//! from an interrupt: after [enable_rx_interrupt](Uart::enable_rx_interrupt), the `USART1_RX`
+//! interrupt of the board calls [uart_rx_interrupt], which pushes the bytes onto a lock-free
+//! [SpscQueue], so bytes arriving while the main loop scans are not lost.
+//!
+//! The [Uart] implements the serial traits of `embedded-hal`, so it also works with the
+//! [SerialBridge](crate::SerialBridge), and [core::fmt::Write] for debug logging on boards without
+//! the USB-CDC [DebugPort](crate::DebugPort).
+//!
+//! RX is on `PD2` and TX on `PD3`, which the Atreus uses for key matrix columns. Keep the
+//! [Usart1](crate::power::Peripheral::Usart1) powered in the board's
+//! [PowerReduction](crate::power::PowerReduction).
+
+use core::cell::RefCell;
+use core::sync::atomic::{AtomicU16, Ordering};
+
+use arduino_hal::pac;
+use avr_device::interrupt::{self, Mutex};
+use embedded_hal::serial;
+
+use crate::spsc::{Consumer, Producer, SpscQueue};
+use crate::uart_config::UartConfig;
+use crate::F_CPU;
+
+/// Length of the RX ring buffer, holding up to `UART_RX_LEN - 1` bytes.
+pub const UART_RX_LEN: usize = 64;
+
+/// Bytes received by the `USART1_RX` interrupt.
+static UART_RX: SpscQueue<u8, UART_RX_LEN> = SpscQueue::new();
+
+/// Producer side of [UART_RX], used by the `USART1_RX` interrupt.
+static UART_RX_PRODUCER: Mutex<RefCell<Option<Producer<'static, u8, UART_RX_LEN>>>> =
+    Mutex::new(RefCell::new(None));
+
+/// Number of received bytes dropped by the interrupt, because of a full buffer or a bad frame.
+///
+/// Only stored by the `USART1_RX` interrupt.
+static UART_RX_DROPPED: AtomicU16 = AtomicU16::new(0);
+
+/// Represents a receive error of the [Uart].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UartError {
+    /// The stop bit of the frame was not high, e.g. from a baud rate mismatch.
+    Framing,
+    /// A byte arrived before the previous one was read.
+    Overrun,
+    /// The parity bit did not match the frame.
+    Parity,
+}
+
+/// UART on USART1, see the [module](self) docs.
+pub struct Uart {
+    usart: pac::USART1,
+    rx: Option<Consumer<'static, u8, UART_RX_LEN>>,
+}
+
+impl Uart {
+    /// Creates a new [Uart], applies the [UartConfig], and enables the transmitter and receiver.
+    pub fn new(usart: pac::USART1, config: UartConfig) -> Self {
+        let baud = config.baud(F_CPU);
+
+        usart.ubrr1.write(|w| w.bits(baud.ubrr));
+        usart.ucsr1a.write(|w| w.u2x1().bit(baud.double_speed));
+        usart.ucsr1c.write(|w| unsafe { w.bits(config.ucsr1c()) });
+        usart
+            .ucsr1b
+            .write(|w| w.rxen1().set_bit().txen1().set_bit());
+
+        Self { usart, rx: None }
+    }
+
+    /// Receives from the `USART1_RX` interrupt from now on, see [uart_rx_interrupt].
+    ///
+    /// Returns `false` if the RX buffer is already in use by another [Uart].
+    pub fn enable_rx_interrupt(&mut self) -> bool {
+        let Some((producer, consumer)) = UART_RX.split() else {
+            return false;
+        };
+
+        interrupt::free(|cs| UART_RX_PRODUCER.borrow(cs).replace(Some(producer)));
+        self.rx = Some(consumer);
+
+        self.usart.ucsr1b.modify(|_, w| w.rxcie1().set_bit());
+
+        true
+    }
+
+    /// Gets the number of received bytes dropped by the interrupt.
+    pub fn rx_dropped(&self) -> u16 {
+        UART_RX_DROPPED.load(Ordering::Relaxed)
+    }
+
+    /// Sends a byte, waiting for room in the transmit buffer.
+    pub fn write_byte(&mut self, byte: u8) {
+        while self.usart.ucsr1a.read().udre1().bit_is_clear() {}
+
+        self.usart.udr1.write(|w| w.bits(byte));
+    }
+
+    /// Sends the bytes, waiting for room in the transmit buffer.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.write_byte(byte);
+        }
+    }
+
+    /// Waits until the transmit buffer has room for the next byte.
+    pub fn flush(&mut self) {
+        while self.usart.ucsr1a.read().udre1().bit_is_clear() {}
+    }
+
+    /// Gets the next received byte, without blocking.
+    ///
+    /// Returns `Ok(None)` if nothing was received.
+    pub fn read_byte(&mut self) -> Result<Option<u8>, UartError> {
+        if let Some(rx) = self.rx.as_mut() {
+            return Ok(rx.pop());
+        }
+
+        let status = self.usart.ucsr1a.read();
+
+        if status.rxc1().bit_is_clear() {
+            return Ok(None);
+        }
+
+        // the error flags belong to the byte in the data register, so read it either way
+        let byte = self.usart.udr1.read().bits();
+
+        if status.fe1().bit_is_set() {
+            Err(UartError::Framing)
+        } else if status.dor1().bit_is_set() {
+            Err(UartError::Overrun)
+        } else if status.upe1().bit_is_set() {
+            Err(UartError::Parity)
+        } else {
+            Ok(Some(byte))
+        }
+    }
+
+    /// Waits for the next received byte.
+    pub fn read_blocking(&mut self) -> Result<u8, UartError> {
+        loop {
+            if let Some(byte) = self.read_byte()? {
+                return Ok(byte);
+            }
+        }
+    }
+}
+
+impl serial::Read<u8> for Uart {
+    type Error = UartError;
+
+    fn read(&mut self) -> nb::Result<u8, Self::Error> {
+        match self.read_byte() {
+            Ok(Some(byte)) => Ok(byte),
+            Ok(None) => Err(nb::Error::WouldBlock),
+            Err(err) => Err(nb::Error::Other(err)),
+        }
+    }
+}
+
+impl serial::Write<u8> for Uart {
+    type Error = core::convert::Infallible;
+
+    fn write(&mut self, word: u8) -> nb::Result<(), Self::Error> {
+        if self.usart.ucsr1a.read().udre1().bit_is_clear() {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        self.usart.udr1.write(|w| w.bits(word));
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> nb::Result<(), Self::Error> {
+        if self.usart.ucsr1a.read().udre1().bit_is_clear() {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        Ok(())
+    }
+}
+
+impl core::fmt::Write for Uart {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.write_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// Moves a received byte into the RX buffer, called from the `USART1_RX` interrupt.
+///
+/// Bytes with a receive error, and bytes that do not fit in the buffer, are dropped.
+pub fn uart_rx_interrupt() {
+    // Safety: the interrupt only reads the USART1 status and data registers, which are not
+    // written by the main loop once the RX interrupt is enabled.
+    let usart = unsafe { &*pac::USART1::ptr() };
+
+    let status = usart.ucsr1a.read();
+    let byte = usart.udr1.read().bits();
+    let bad_frame =
+        status.fe1().bit_is_set() || status.dor1().bit_is_set() || status.upe1().bit_is_set();
+
+    let pushed = !bad_frame
+        && interrupt::free(|cs| {
+            UART_RX_PRODUCER
+                .borrow(cs)
+                .borrow_mut()
+                .as_mut()
+                .is_some_and(|rx| rx.push(byte))
+        });
+
+    if !pushed {
+        UART_RX_DROPPED.store(
+            UART_RX_DROPPED.load(Ordering::Relaxed).wrapping_add(1),
+            Ordering::SeqCst,
+        );
+    }
+}
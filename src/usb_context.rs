@@ -1,50 +1,699 @@
 use atmega_usbd::UsbBus;
-use usb_device::device::UsbDevice;
-use usbd_hid::hid_class::HIDClass;
+use usb_device::{
+    class::{ControlIn, ControlOut, UsbClass},
+    class_prelude::UsbBusAllocator,
+    control::{Recipient, Request, RequestType},
+    device::{UsbDevice, UsbDeviceBuilder, UsbDeviceState, UsbVidPid},
+    UsbError,
+};
+use usbd_hid::descriptor::{KeyboardReport, MediaKeyboardReport, SystemControlReport};
+use usbd_hid::hid_class::{HIDClass, HidProtocolMode, ReportType};
 
-use crate::{KeyScanner, BLANK_REPORT};
+use crate::diagnostics::{self, Diagnostic};
+use crate::gamepad::GamepadReport;
+use crate::hooks::Hooks;
+use crate::idle_rate::{self, IdleRate};
+use crate::mouse::MouseReport;
+use crate::nkro::{self, NkroReport, ReportMode};
+use crate::output_report::{OutputHandlers, OutputReport, ReportInterface};
+use crate::power::{self, SleepMode};
+use crate::raw_hid::RAW_REPORT_LEN;
+use crate::report::{self, ReportQueue, BLANK_REPORT};
+use crate::shared_report::SharedReport;
+use crate::spsc::Consumer;
+use crate::usb_descriptors::{
+    DescriptorTable, MAX_DESCRIPTOR_LEN, REPORT_DESCRIPTOR_TYPE, STRING_DESCRIPTOR_TYPE,
+};
+use crate::usb_identity::UsbIdentity;
+use crate::usb_watchdog::{UsbStatus, UsbWatchdog};
+use crate::{health, DebugPort, SerialBridge};
 
-/// Maximum number of [KeyboardReport]s that can be returned by a matrix scan.
+/// Size of the queue of [ScanReports] handed to the [UsbContext], holding one less scan.
+pub const SCAN_QUEUE_LEN: usize = 4;
+
+/// Interface number of the keyboard HID class.
+///
+/// The keyboard class must be the first class allocated on the USB bus.
+pub const KEYBOARD_INTERFACE: u16 = 0;
+
+/// Handler for keystrokes sent to the host, called with the modifier bitfield and a keycode.
 ///
-/// There are 4 rows, 12 columns, and each report holds 6 key codes: 4 * 12 / 6 = 8
-pub const MAX_KEYBOARD_REPORTS: usize = 8;
+/// Lets assistive tooling (e.g. on-screen key displays) mirror exactly what the firmware sent.
+/// Called from the USB interrupts, so it should return quickly.
+pub type KeystrokeHandler = fn(modifier: u8, keycode: u8);
 
-/// Represents the USB context used for scanning the key matrix,
-/// and sending keyboard reports to the host.
+/// Creates a [UsbDeviceBuilder] that presents the [UsbIdentity] to the host.
+///
+/// Device features (e.g. remote wakeup) are left to the caller.
+pub fn usb_device_builder(
+    usb_bus: &'static UsbBusAllocator<UsbBus>,
+    identity: &UsbIdentity,
+) -> UsbDeviceBuilder<'static, UsbBus> {
+    UsbDeviceBuilder::new(usb_bus, UsbVidPid(identity.vid(), identity.pid()))
+        .manufacturer(identity.manufacturer())
+        .product(identity.product())
+        .device_release(identity.device_release())
+}
+
+/// Represents the reports of one matrix scan, handed to the [UsbContext].
+pub struct ScanReports {
+    /// Boot keyboard report.
+    pub report: KeyboardReport,
+    /// N-key rollover report.
+    pub nkro_report: NkroReport,
+    /// Consumer Control usage, or `0`.
+    pub consumer_usage: u16,
+    /// Gamepad report.
+    pub gamepad_report: GamepadReport,
+    /// System Control usage, or `0`.
+    pub system_usage: u8,
+    /// Mouse report, with the pointer motion since the last scan.
+    pub mouse_report: MouseReport,
+    /// Scan clock (in milliseconds) at the end of the scan.
+    pub now_ms: u16,
+}
+
+/// Represents the USB context used for sending keyboard reports to the host.
+///
+/// The key matrix is scanned outside of the USB interrupts, and the resulting [ScanReports] are
+/// pushed onto a lock-free [SpscQueue](crate::spsc::SpscQueue). The USB context pops them with
+/// [send_scans](Self::send_scans), so a matrix scan never holds off USB servicing, and only
+/// sending the reports runs with interrupts disabled.
 pub struct UsbContext {
     pub usb_device: UsbDevice<'static, UsbBus>,
     pub hid_class: HIDClass<'static, UsbBus>,
-    pub key_scanner: KeyScanner,
+    /// Matrix scans handed over by the main loop, waiting to be queued.
+    pub scans: Consumer<'static, ScanReports, SCAN_QUEUE_LEN>,
+    /// Reports prepared by the matrix scan, waiting to be sent to the host.
+    pub reports: ReportQueue,
+    /// Idle rate of the keyboard interface, negotiated by the host.
+    pub idle_rate: IdleRate,
+    /// Descriptors served from program memory, see [usb_descriptors](crate::usb_descriptors).
+    pub descriptors: DescriptorTable,
+    /// Optional interface with the N-key rollover, System Control, and Consumer Control reports
+    /// under distinct report IDs, see [shared_report](crate::shared_report).
+    ///
+    /// Reports without their own interface are sent on the shared interface.
+    pub shared_class: Option<HIDClass<'static, UsbBus>>,
+    /// Optional N-key rollover interface, used in [ReportMode::Nkro].
+    pub nkro_class: Option<HIDClass<'static, UsbBus>>,
+    /// N-key rollover report waiting to be sent to the host.
+    pub nkro_pending: Option<NkroReport>,
+    /// Last N-key rollover report sent to the host.
+    pub nkro_sent: NkroReport,
+    /// Optional Consumer Control interface, for media and application keys.
+    pub consumer_class: Option<HIDClass<'static, UsbBus>>,
+    /// Consumer Control usage waiting to be sent to the host.
+    pub consumer_pending: Option<u16>,
+    /// Last Consumer Control usage sent to the host.
+    pub consumer_sent: u16,
+    /// Optional gamepad interface, for [gamepad](crate::gamepad) keys.
+    pub gamepad_class: Option<HIDClass<'static, UsbBus>>,
+    /// Gamepad report waiting to be sent to the host.
+    pub gamepad_pending: Option<GamepadReport>,
+    /// Last gamepad report sent to the host.
+    pub gamepad_sent: GamepadReport,
+    /// Optional System Control interface, for [system_control](crate::system_control) keys.
+    pub system_class: Option<HIDClass<'static, UsbBus>>,
+    /// System Control usage waiting to be sent to the host.
+    pub system_pending: Option<u8>,
+    /// Last System Control usage sent to the host.
+    pub system_sent: u8,
+    /// Mouse report waiting to be sent to the host, on the shared interface.
+    pub mouse_pending: Option<MouseReport>,
+    /// Last mouse report sent to the host.
+    pub mouse_sent: MouseReport,
+    /// Optional raw HID interface, for configuration protocol commands from host tools.
+    pub raw_hid_class: Option<HIDClass<'static, UsbBus>>,
+    /// Response to the last configuration command, waiting to be sent to the host.
+    pub raw_hid_pending: Option<[u8; RAW_REPORT_LEN]>,
+    /// Detects a wedged USB device, see [check_usb](Self::check_usb).
+    pub usb_watchdog: UsbWatchdog,
+    /// Handlers for the output and feature reports sent by the host.
+    pub output_handlers: OutputHandlers,
+    /// Optional consumer of every keystroke sent to the host in boot reports.
+    pub keystroke_handler: Option<KeystrokeHandler>,
+    /// Hooks run for every boot keyboard report accepted by the endpoint, see
+    /// [after_report_sent](Hooks::after_report_sent).
+    pub hooks: Hooks,
+    /// Optional UART to USB-serial bridge, polled alongside the keyboard HID interface.
+    pub serial_bridge: Option<SerialBridge>,
+    /// Optional USB-serial port for debug output (e.g. [matrix_test](crate::matrix_test) lines),
+    /// and the [console](crate::console).
+    pub debug_port: Option<DebugPort>,
 }
 
 impl UsbContext {
-    /// Scans the key matrix for key presses.
-    pub fn scan_matrix(&mut self) {
-        let reports = self.key_scanner.scan::<MAX_KEYBOARD_REPORTS>();
+    /// Queues the [ScanReports] handed over by the main loop, and starts sending them.
+    ///
+    /// Also [checks](Self::check_usb) for a wedged USB device after every scan.
+    pub fn send_scans(&mut self) {
+        while let Some(scan) = self.scans.pop() {
+            self.queue_reports(scan.report, &scan.nkro_report, scan.now_ms);
+            self.queue_consumer(scan.consumer_usage);
+            self.queue_gamepad(&scan.gamepad_report);
+            self.queue_system(scan.system_usage);
+            self.queue_mouse(&scan.mouse_report);
+            self.check_usb(scan.now_ms);
+        }
+    }
+
+    /// Queues the report from a matrix scan, and starts sending the queued reports to the host.
+    ///
+    /// Reports are only queued when they change, so unchanged scans do not flood the endpoint.
+    /// The last report is repeated at the [IdleRate] negotiated by the host, timed by the scan
+    /// clock `now_ms`.
+    ///
+    /// In [ReportMode::Nkro], keys are sent in the `nkro_report` instead, and the boot report is
+    /// released. Falls back to the boot report if there is no N-key rollover interface, or
+    /// the host selected the [boot protocol](Self::boot_protocol).
+    ///
+    /// Also records the scan in [health], which must happen with interrupts disabled.
+    pub fn queue_reports(&mut self, report: KeyboardReport, nkro_report: &NkroReport, now_ms: u16) {
+        health::record_scan();
+
+        let (report, nkro_report) = if (self.nkro_class.is_some() || self.shared_class.is_some())
+            && nkro::report_mode() == ReportMode::Nkro
+            && !self.boot_protocol()
+        {
+            (BLANK_REPORT, *nkro_report)
+        } else {
+            (report, NkroReport::new())
+        };
 
-        for report in reports.iter() {
-            self.hid_class.push_input(report).ok();
+        if nkro_report != self.nkro_pending.unwrap_or(self.nkro_sent) {
+            self.nkro_pending = Some(nkro_report);
+        }
+
+        let queued = self.reports.len();
 
-            if self.usb_device.poll(&mut [&mut self.hid_class]) {
-                let mut report_buf = [0u8; 1];
+        if !self.reports.push(report) {
+            diagnostics::record(Diagnostic::ReportBlocked);
+            health::record_report(false);
+        }
 
-                self.hid_class.pull_raw_output(&mut report_buf).ok();
+        if self.reports.len() > queued {
+            self.idle_rate.record_report(now_ms);
+            // a key changed while the host sleeps
+            self.wake_host();
+        } else if self.reports.is_empty() && self.idle_rate.repeat_due(now_ms) {
+            self.reports.repeat_last();
+            self.idle_rate.record_report(now_ms);
+        }
+
+        self.send_reports();
+    }
+
+    /// Queues the Consumer Control usage from a matrix scan, if it changed.
+    ///
+    /// A usage of `0` releases the last Consumer Control key.
+    pub fn queue_consumer(&mut self, usage: u16) {
+        if (self.consumer_class.is_some() || self.shared_class.is_some())
+            && usage != self.consumer_pending.unwrap_or(self.consumer_sent)
+        {
+            self.consumer_pending = Some(usage);
+        }
+
+        self.send_reports();
+    }
+
+    /// Queues the [GamepadReport] from a matrix scan, if it changed.
+    pub fn queue_gamepad(&mut self, report: &GamepadReport) {
+        if self.gamepad_class.is_some()
+            && *report != self.gamepad_pending.unwrap_or(self.gamepad_sent)
+        {
+            self.gamepad_pending = Some(*report);
+        }
+
+        self.send_reports();
+    }
+
+    /// Queues the System Control usage from a matrix scan, if it changed.
+    ///
+    /// Pressing a System Control key also [wakes the host](Self::wake_host), since hosts sleeping
+    /// with the System Control interface suspended only see the usage after a remote wakeup.
+    /// A usage of `0` releases the last System Control key.
+    pub fn queue_system(&mut self, usage: u8) {
+        let last = self.system_pending.unwrap_or(self.system_sent);
+
+        if usage != last {
+            if usage != 0 {
+                self.wake_host();
             }
 
-            if report.modifier != 0 || report.keycodes != [0; 6] {
-                self.poll();
+            if self.system_class.is_some() || self.shared_class.is_some() {
+                self.system_pending = Some(usage);
             }
         }
+
+        self.send_reports();
     }
 
-    /// Polls the USB host with a blank HID report.
+    /// Queues the [MouseReport] from a matrix scan, if it moves or the buttons changed.
+    ///
+    /// Motion not picked up by the host yet is merged into the queued report.
+    pub fn queue_mouse(&mut self, report: &MouseReport) {
+        let buttons = self
+            .mouse_pending
+            .map_or(self.mouse_sent.buttons(), |pending| pending.buttons());
+
+        if self.shared_class.is_some() && (report.has_motion() || report.buttons() != buttons) {
+            self.mouse_pending = Some(match self.mouse_pending {
+                Some(pending) => pending.merge(report),
+                None => *report,
+            });
+        }
+
+        self.send_reports();
+    }
+
+    /// Checks for a wedged USB device at the scan clock `now_ms`, and re-enumerates it.
+    ///
+    /// Recovers from flaky hubs and KVM switches without a replug, see [UsbWatchdog]. Queued
+    /// reports are dropped, and the held keys are sent again after the re-enumeration.
+    pub fn check_usb(&mut self, now_ms: u16) {
+        let status = match self.usb_device.state() {
+            UsbDeviceState::Configured => UsbStatus::Configured,
+            UsbDeviceState::Suspend => UsbStatus::Suspended,
+            _ => UsbStatus::Enumerating,
+        };
+        let busy = !self.reports.is_empty() || self.nkro_pending.is_some();
+
+        if self.usb_watchdog.check(status, busy, now_ms) {
+            diagnostics::record(Diagnostic::UsbRecovered);
+
+            // detaches from the bus, so the host enumerates the device again
+            self.usb_device.force_reset().ok();
+
+            self.reports = ReportQueue::new();
+            self.nkro_pending = None;
+            self.nkro_sent = NkroReport::new();
+            self.consumer_pending = None;
+            self.consumer_sent = 0;
+            self.gamepad_pending = None;
+            self.gamepad_sent = GamepadReport::new();
+            self.system_pending = None;
+            self.system_sent = 0;
+            self.mouse_pending = None;
+            self.mouse_sent = MouseReport::new();
+            self.raw_hid_pending = None;
+        }
+    }
+
+    /// Gets whether the host suspended the USB bus.
+    pub fn suspended(&self) -> bool {
+        self.usb_device.state() == UsbDeviceState::Suspend
+    }
+
+    /// Gets the [SleepMode] of the MCU, depending on whether the matrix is sleeping until a key
+    /// press.
+    ///
+    /// While the host has remote wakeup enabled, a key press must still
+    /// [wake the host](Self::wake_host), so the MCU only powers down with a sleeping matrix.
+    pub fn sleep_mode(&self, matrix_sleeping: bool) -> SleepMode {
+        power::sleep_mode(
+            matrix_sleeping,
+            self.suspended(),
+            self.usb_device.remote_wakeup_enabled(),
+        )
+    }
+
+    /// Wakes the host from suspend, if it enabled remote wakeup.
+    ///
+    /// Returns `true` if a wakeup was signalled.
+    pub fn wake_host(&mut self) -> bool {
+        let wake = self.suspended() && self.usb_device.remote_wakeup_enabled();
+
+        if wake {
+            crate::signal_remote_wakeup();
+        }
+
+        wake
+    }
+
+    /// Gets whether the host selected the boot protocol with a `SET_PROTOCOL` request.
+    ///
+    /// Hosts without a full HID stack (e.g. BIOS or UEFI setup, or a disk-encryption prompt) use
+    /// the boot protocol, and only read the boot keyboard reports.
+    pub fn boot_protocol(&self) -> bool {
+        matches!(
+            self.hid_class.get_protocol_mode(),
+            Ok(HidProtocolMode::Boot)
+        )
+    }
+
+    /// Queues debug output for the host, if there is a [DebugPort].
+    ///
+    /// Returns `false` if the output was dropped.
+    pub fn write_debug(&mut self, bytes: &[u8]) -> bool {
+        self.debug_port
+            .as_mut()
+            .is_some_and(|port| port.write(bytes))
+    }
+
+    /// Polls the USB device, and sends any queued reports.
+    ///
+    /// Reports the endpoints did not accept yet, because they were busy, stay queued, and are
+    /// retried on every poll.
     pub fn poll(&mut self) {
-        self.hid_class.push_input(&BLANK_REPORT).ok();
+        self.poll_usb();
+        self.send_scans();
+        self.send_reports();
+    }
+
+    /// Sends queued reports until the queue is empty, or the endpoint is busy.
+    ///
+    /// Reports are kept queued while the endpoint is busy, other failures are counted in
+    /// [diagnostics] and drop the report.
+    fn send_reports(&mut self) {
+        while let Some(report) = self.reports.front() {
+            match self.hid_class.push_input(report) {
+                Ok(_) => {
+                    health::record_report(true);
+                    self.hooks.after_report_sent(report);
+
+                    if let Some(handler) = self.keystroke_handler {
+                        for (modifier, keycode) in report::keystrokes(report) {
+                            handler(modifier, keycode);
+                        }
+                    }
+                }
+                // retry on the next USB interrupt
+                Err(UsbError::WouldBlock) => break,
+                Err(_) => {
+                    diagnostics::record(Diagnostic::ReportFailed);
+                    health::record_report(false);
+                }
+            }
+
+            self.reports.pop();
+        }
+
+        if let Some(usage) = self.consumer_pending {
+            match self.push_report(
+                self.consumer_class.as_ref(),
+                SharedReport::consumer(usage),
+                |class| class.push_input(&MediaKeyboardReport { usage_id: usage }),
+            ) {
+                Ok(_) => {
+                    health::record_report(true);
+                    self.consumer_sent = usage;
+                    self.consumer_pending = None;
+                }
+                // retry on the next USB interrupt
+                Err(UsbError::WouldBlock) => (),
+                Err(_) => {
+                    diagnostics::record(Diagnostic::ReportFailed);
+                    health::record_report(false);
+                    self.consumer_pending = None;
+                }
+            }
+        }
+
+        if let (Some(gamepad_class), Some(report)) =
+            (self.gamepad_class.as_ref(), self.gamepad_pending)
+        {
+            match gamepad_class.push_raw_input(&report.to_bytes()) {
+                Ok(_) => {
+                    health::record_report(true);
+                    self.gamepad_sent = report;
+                    self.gamepad_pending = None;
+                }
+                // retry on the next USB interrupt
+                Err(UsbError::WouldBlock) => (),
+                Err(_) => {
+                    diagnostics::record(Diagnostic::ReportFailed);
+                    health::record_report(false);
+                    self.gamepad_pending = None;
+                }
+            }
+        }
 
-        if self.usb_device.poll(&mut [&mut self.hid_class]) {
-            let mut report_buf = [0u8; 1];
+        if let Some(usage) = self.system_pending {
+            match self.push_report(
+                self.system_class.as_ref(),
+                SharedReport::system(usage),
+                |class| class.push_input(&SystemControlReport { usage_id: usage }),
+            ) {
+                Ok(_) => {
+                    health::record_report(true);
+                    self.system_sent = usage;
+                    self.system_pending = None;
+                }
+                // retry on the next USB interrupt
+                Err(UsbError::WouldBlock) => (),
+                Err(_) => {
+                    diagnostics::record(Diagnostic::ReportFailed);
+                    health::record_report(false);
+                    self.system_pending = None;
+                }
+            }
+        }
+
+        if let Some(report) = self.mouse_pending {
+            match self.push_report(None, SharedReport::mouse(&report), |class| {
+                class.push_raw_input(&report.to_bytes())
+            }) {
+                Ok(_) => {
+                    health::record_report(true);
+                    self.mouse_sent = report;
+                    self.mouse_pending = None;
+                }
+                // retry on the next USB interrupt
+                Err(UsbError::WouldBlock) => (),
+                Err(_) => {
+                    diagnostics::record(Diagnostic::ReportFailed);
+                    health::record_report(false);
+                    self.mouse_pending = None;
+                }
+            }
+        }
+
+        if let (Some(raw_hid_class), Some(response)) =
+            (self.raw_hid_class.as_ref(), self.raw_hid_pending.as_ref())
+        {
+            match raw_hid_class.push_raw_input(response) {
+                Ok(_) => self.raw_hid_pending = None,
+                // retry on the next USB interrupt
+                Err(UsbError::WouldBlock) => (),
+                Err(_) => {
+                    diagnostics::record(Diagnostic::ReportFailed);
+                    self.raw_hid_pending = None;
+                }
+            }
+        }
+
+        if let Some(nkro_report) = self.nkro_pending {
+            match self.push_report(
+                self.nkro_class.as_ref(),
+                SharedReport::nkro(&nkro_report),
+                |class| class.push_raw_input(nkro_report.as_bytes()),
+            ) {
+                Ok(_) => {
+                    health::record_report(true);
+                    self.nkro_sent = nkro_report;
+                }
+                // retry on the next USB interrupt
+                Err(UsbError::WouldBlock) => return,
+                Err(_) => {
+                    diagnostics::record(Diagnostic::ReportFailed);
+                    health::record_report(false);
+                }
+            }
+
+            self.nkro_pending = None;
+        }
+    }
+
+    /// Sends a report on its own interface, or with its report ID on the shared interface.
+    ///
+    /// Fails with [UsbError::InvalidState] if there is neither interface.
+    fn push_report<F>(
+        &self,
+        class: Option<&HIDClass<'static, UsbBus>>,
+        shared_report: SharedReport,
+        push: F,
+    ) -> Result<usize, UsbError>
+    where
+        F: FnOnce(&HIDClass<'static, UsbBus>) -> Result<usize, UsbError>,
+    {
+        match (class, self.shared_class.as_ref()) {
+            (Some(class), _) => push(class),
+            (None, Some(shared_class)) => shared_class.push_raw_input(shared_report.as_bytes()),
+            (None, None) => Err(UsbError::InvalidState),
+        }
+    }
+
+    /// Polls the USB device, reads the host output reports, and pumps the serial ports.
+    fn poll_usb(&mut self) {
+        // absent interfaces are replaced with classes that do nothing
+        let (mut no_nkro, mut no_consumer, mut no_gamepad, mut no_system, mut no_raw_hid) =
+            (NoClass, NoClass, NoClass, NoClass, NoClass);
+        let (mut no_shared, mut no_bridge, mut no_debug) = (NoClass, NoClass, NoClass);
+
+        let polled = self.usb_device.poll(&mut [
+            // answers the descriptor requests from program memory before the HID classes
+            &mut DescriptorRequests(&self.descriptors),
+            // handles the idle requests before the keyboard class
+            &mut IdleRequests(&mut self.idle_rate),
+            &mut self.hid_class,
+            match self.shared_class.as_mut() {
+                Some(shared_class) => shared_class,
+                None => &mut no_shared,
+            },
+            match self.nkro_class.as_mut() {
+                Some(nkro_class) => nkro_class,
+                None => &mut no_nkro,
+            },
+            match self.consumer_class.as_mut() {
+                Some(consumer_class) => consumer_class,
+                None => &mut no_consumer,
+            },
+            match self.gamepad_class.as_mut() {
+                Some(gamepad_class) => gamepad_class,
+                None => &mut no_gamepad,
+            },
+            match self.system_class.as_mut() {
+                Some(system_class) => system_class,
+                None => &mut no_system,
+            },
+            match self.raw_hid_class.as_mut() {
+                Some(raw_hid_class) => raw_hid_class,
+                None => &mut no_raw_hid,
+            },
+            match self.serial_bridge.as_mut() {
+                Some(bridge) => bridge.port_mut(),
+                None => &mut no_bridge,
+            },
+            match self.debug_port.as_mut() {
+                Some(debug_port) => debug_port.port_mut(),
+                None => &mut no_debug,
+            },
+        ]);
+
+        if polled {
+            let mut request = [0u8; RAW_REPORT_LEN];
+
+            // the keyboard interface has no OUT endpoint, so the LED report arrives with SET_REPORT
+            if let Some((report_type, data)) = pull_report(&mut self.hid_class, &mut request) {
+                let report = OutputReport::new(ReportInterface::Keyboard, report_type, data);
+
+                // there is no input report in response on the keyboard interface
+                self.output_handlers.dispatch(&report, &mut []);
+            }
+
+            if let Some(raw_hid_class) = self.raw_hid_class.as_mut() {
+                let mut response = [0u8; RAW_REPORT_LEN];
+
+                // like the LED report, configuration commands arrive with SET_REPORT
+                if let Some((report_type, data)) = pull_report(raw_hid_class, &mut request) {
+                    let report = OutputReport::new(ReportInterface::RawHid, report_type, data);
+
+                    if self.output_handlers.dispatch(&report, &mut response) > 0 {
+                        self.raw_hid_pending = Some(response);
+                    }
+                }
+            }
+        }
+
+        if let Some(bridge) = self.serial_bridge.as_mut() {
+            bridge.pump();
+        }
+
+        if let Some(debug_port) = self.debug_port.as_mut() {
+            debug_port.pump();
+        }
+    }
+}
+
+/// Reads an output or feature report sent to the `class` with `SET_REPORT`.
+///
+/// Returns the report type, and the report data in the `buf`.
+fn pull_report<'a>(
+    class: &mut HIDClass<'static, UsbBus>,
+    buf: &'a mut [u8],
+) -> Option<(ReportType, &'a [u8])> {
+    match class.pull_raw_report(buf) {
+        Ok(info) if matches!(info.report_type, ReportType::Output | ReportType::Feature) => {
+            Some((info.report_type, &buf[..info.len.min(buf.len())]))
+        }
+        Ok(_) => None,
+        // no report is pending
+        Err(UsbError::WouldBlock) => None,
+        Err(_) => {
+            diagnostics::record(Diagnostic::OutputFailed);
+            None
+        }
+    }
+}
+
+/// USB class without interfaces, standing in for absent optional interfaces.
+struct NoClass;
+
+impl UsbClass<UsbBus> for NoClass {}
+
+/// USB class handling the `SET_IDLE` and `GET_IDLE` requests of the keyboard interface.
+struct IdleRequests<'a>(&'a mut IdleRate);
+
+impl IdleRequests<'_> {
+    /// Gets whether the request is a HID class request to the keyboard interface.
+    fn is_keyboard_request(req: &Request) -> bool {
+        req.request_type == RequestType::Class
+            && req.recipient == Recipient::Interface
+            && req.index == KEYBOARD_INTERFACE
+    }
+}
+
+impl UsbClass<UsbBus> for IdleRequests<'_> {
+    fn reset(&mut self) {
+        self.0.reset();
+    }
+
+    fn control_out(&mut self, xfer: ControlOut<UsbBus>) {
+        let req = *xfer.request();
+
+        if Self::is_keyboard_request(&req) && req.request == idle_rate::SET_IDLE {
+            // the low byte selects the report ID, and the keyboard report has none
+            if req.value & 0xff == 0 {
+                self.0.set_duration((req.value >> 8) as u8);
+            }
+
+            xfer.accept().ok();
+        }
+    }
+
+    fn control_in(&mut self, xfer: ControlIn<UsbBus>) {
+        let req = *xfer.request();
+
+        if Self::is_keyboard_request(&req) && req.request == idle_rate::GET_IDLE {
+            xfer.accept_with(&[self.0.duration()]).ok();
+        }
+    }
+}
+
+/// Answers `GET_DESCRIPTOR` requests with the descriptors stored in program memory.
+struct DescriptorRequests<'a>(&'a DescriptorTable);
+
+impl UsbClass<UsbBus> for DescriptorRequests<'_> {
+    fn control_in(&mut self, xfer: ControlIn<UsbBus>) {
+        let req = *xfer.request();
+
+        if req.request_type != RequestType::Standard || req.request != Request::GET_DESCRIPTOR {
+            return;
+        }
+
+        let (descriptor_type, index) = req.descriptor_type_index();
+        let descriptor = match (req.recipient, descriptor_type) {
+            (Recipient::Interface, REPORT_DESCRIPTOR_TYPE) if index == 0 => {
+                self.0.report(req.index)
+            }
+            (Recipient::Device, STRING_DESCRIPTOR_TYPE) => self.0.string(index),
+            _ => None,
+        };
 
-            self.hid_class.pull_raw_output(&mut report_buf).ok();
+        if let Some(descriptor) = descriptor {
+            let mut buf = [0u8; MAX_DESCRIPTOR_LEN];
+            xfer.accept_with(descriptor.load(&mut buf)).ok();
         }
     }
 }
@@ -0,0 +1,37 @@
+//! Embeds the git revision and the build date, to identify the flashed firmware.
+//!
+//! Set `TROVE_GIT_HASH` or `TROVE_BUILD_DATE` to override them, e.g. for reproducible builds.
+
+use std::env;
+use std::process::Command;
+
+fn main() {
+    let git_hash = env::var("TROVE_GIT_HASH")
+        .ok()
+        .or_else(|| command_output("git", &["rev-parse", "--short=12", "HEAD"]))
+        .unwrap_or_else(|| "unknown".into());
+
+    let build_date = env::var("TROVE_BUILD_DATE")
+        .ok()
+        .or_else(|| command_output("date", &["-u", "+%Y-%m-%d"]))
+        .unwrap_or_else(|| "unknown".into());
+
+    println!("cargo:rustc-env=TROVE_GIT_HASH={git_hash}");
+    println!("cargo:rustc-env=TROVE_BUILD_DATE={build_date}");
+
+    println!("cargo:rerun-if-env-changed=TROVE_GIT_HASH");
+    println!("cargo:rerun-if-env-changed=TROVE_BUILD_DATE");
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+    println!("cargo:rerun-if-changed=../.git/refs/heads");
+}
+
+/// Runs a command, and gets its trimmed output if it succeeded.
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .filter(|out| !out.is_empty())
+}
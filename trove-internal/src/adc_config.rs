@@ -0,0 +1,338 @@
+//! Interrupt-driven ADC conversions.
+//!
+//! Analog sticks, battery monitors, and analog matrices all sample ADC inputs, but a blocking read
+//! stalls the main loop for about 100 µs per input. An [AdcSchedule] instead lists the inputs to
+//! sample, and the ADC interrupt converts them one after the other:
+//!
+//! - each round is started from the main loop, at most every [AdcConfig::interval_ms]
+//! - every finished conversion is stored as the latest [sample](AdcSchedule::sample) of its input
+//! - and passed to the [AdcCallback] of the input, if any
+//!
+//! Callbacks run inside the ADC interrupt, so they should only store the sample, e.g. in an
+//! atomic, and leave the processing to the main loop.
+
+/// Maximum number of inputs in an [AdcSchedule].
+pub const MAX_ADC_INPUTS: usize = 8;
+/// Highest ADC clock frequency for full 10-bit resolution.
+pub const MAX_ADC_CLOCK_HZ: u32 = 200_000;
+/// Default time (in milliseconds) between conversion rounds.
+pub const DEFAULT_ADC_INTERVAL_MS: u16 = 8;
+
+/// Bits of the ADC control and status register A (`ADCSRA`).
+const ADEN: u8 = 1 << 7;
+const ADSC: u8 = 1 << 6;
+const ADIE: u8 = 1 << 3;
+/// Multiplexer bit 5 of `ADCSRB`.
+const MUX5: u8 = 1 << 5;
+
+/// Called with every finished conversion of an input, from the ADC interrupt.
+pub type AdcCallback = fn(input: AdcInput, sample: u16);
+
+/// Represents the voltage reference of the ADC.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum AdcReference {
+    /// External reference on the `AREF` pin.
+    Aref,
+    /// Supply voltage (`AVCC`), the usual choice for potentiometers.
+    #[default]
+    AVcc,
+    /// Internal 2.56 V reference, e.g. for battery voltage dividers.
+    Internal,
+}
+
+impl AdcReference {
+    /// Gets the reference selection bits (`REFS`) of `ADMUX`.
+    pub const fn bits(&self) -> u8 {
+        match self {
+            Self::Aref => 0,
+            Self::AVcc => 0b01 << 6,
+            Self::Internal => 0b11 << 6,
+        }
+    }
+}
+
+/// Represents an input of the ADC multiplexer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AdcInput(u8);
+
+impl AdcInput {
+    /// Internal temperature sensor, only with the [AdcReference::Internal].
+    pub const TEMPERATURE: Self = Self(0b10_0111);
+    /// Internal 1.1 V bandgap, e.g. to measure the supply voltage against [AdcReference::AVcc].
+    pub const BANDGAP: Self = Self(0b01_1110);
+
+    /// Creates a new [AdcInput] for the single-ended pin channel `ADC0` to `ADC13`.
+    ///
+    /// Returns `None` for channels missing on the ATmega32u4 (`ADC2`, `ADC3`, and above `ADC13`).
+    pub const fn pin(channel: u8) -> Option<Self> {
+        match channel {
+            0 | 1 | 4..=7 => Some(Self(channel)),
+            8..=13 => Some(Self(MUX5 | (channel - 8))),
+            _ => None,
+        }
+    }
+
+    /// Gets the multiplexer bits (`MUX4:0`) of `ADMUX`.
+    pub const fn mux(&self) -> u8 {
+        self.0 & 0b1_1111
+    }
+
+    /// Gets whether the `MUX5` bit of `ADCSRB` is set.
+    pub const fn mux5(&self) -> bool {
+        self.0 & MUX5 != 0
+    }
+
+    /// Gets the digital input disable register (`0` for `DIDR0`, `2` for `DIDR2`) and bit of a
+    /// pin channel.
+    ///
+    /// Returns `None` for internal inputs.
+    pub const fn digital_input(&self) -> Option<(u8, u8)> {
+        match self.0 {
+            0 | 1 | 4..=7 => Some((0, self.0)),
+            0b10_0000..=0b10_0101 => Some((2, self.0 & 0b111)),
+            _ => None,
+        }
+    }
+}
+
+/// Voltage reference, clock, and round interval of the ADC.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AdcConfig {
+    reference: AdcReference,
+    interval_ms: u16,
+}
+
+impl AdcConfig {
+    /// Creates a new [AdcConfig], with the [AdcReference::AVcc] and the
+    /// [DEFAULT_ADC_INTERVAL_MS].
+    pub const fn new() -> Self {
+        Self {
+            reference: AdcReference::AVcc,
+            interval_ms: DEFAULT_ADC_INTERVAL_MS,
+        }
+    }
+
+    /// Gets the [AdcReference].
+    pub const fn reference(&self) -> AdcReference {
+        self.reference
+    }
+
+    /// Builder function that sets the [AdcReference].
+    pub const fn with_reference(mut self, reference: AdcReference) -> Self {
+        self.reference = reference;
+        self
+    }
+
+    /// Gets the time (in milliseconds) between conversion rounds.
+    pub const fn interval_ms(&self) -> u16 {
+        self.interval_ms
+    }
+
+    /// Builder function that sets the time (in milliseconds) between conversion rounds.
+    pub const fn with_interval_ms(mut self, val: u16) -> Self {
+        self.interval_ms = val;
+        self
+    }
+
+    /// Gets the ADC clock prescaler bits (`ADPS`), the smallest division of the CPU clock `f_cpu`
+    /// not above the [MAX_ADC_CLOCK_HZ].
+    pub const fn prescaler_bits(&self, f_cpu: u32) -> u8 {
+        let mut bits = 1;
+
+        while bits < 7 && f_cpu >> bits > MAX_ADC_CLOCK_HZ {
+            bits += 1;
+        }
+
+        bits
+    }
+
+    /// Gets the `ADMUX` value selecting the `input`.
+    pub const fn admux(&self, input: AdcInput) -> u8 {
+        self.reference.bits() | input.mux()
+    }
+
+    /// Gets the `ADCSRB` value selecting the `input`.
+    pub const fn adcsrb(&self, input: AdcInput) -> u8 {
+        if input.mux5() {
+            MUX5
+        } else {
+            0
+        }
+    }
+
+    /// Gets the `ADCSRA` value with the ADC and its interrupt enabled.
+    ///
+    /// With `start` set, the value also starts a conversion.
+    pub const fn adcsra(&self, f_cpu: u32, start: bool) -> u8 {
+        let start = if start { ADSC } else { 0 };
+
+        ADEN | ADIE | start | self.prescaler_bits(f_cpu)
+    }
+}
+
+impl Default for AdcConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Inputs converted by the ADC interrupt, see the [module](self) docs.
+#[derive(Clone, Copy, Debug)]
+pub struct AdcSchedule {
+    inputs: [Option<(AdcInput, Option<AdcCallback>)>; MAX_ADC_INPUTS],
+    samples: [Option<u16>; MAX_ADC_INPUTS],
+    len: usize,
+    current: Option<usize>,
+    last_round_ms: Option<u16>,
+}
+
+impl AdcSchedule {
+    /// Creates a new, empty [AdcSchedule].
+    pub const fn new() -> Self {
+        Self {
+            inputs: [None; MAX_ADC_INPUTS],
+            samples: [None; MAX_ADC_INPUTS],
+            len: 0,
+            current: None,
+            last_round_ms: None,
+        }
+    }
+
+    /// Builder function that adds an input, with an optional [AdcCallback].
+    ///
+    /// Inputs beyond [MAX_ADC_INPUTS] are ignored.
+    pub const fn with_input(mut self, input: AdcInput, callback: Option<AdcCallback>) -> Self {
+        if self.len < MAX_ADC_INPUTS {
+            self.inputs[self.len] = Some((input, callback));
+            self.len += 1;
+        }
+        self
+    }
+
+    /// Gets the number of inputs.
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Gets whether the schedule has no inputs.
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Gets whether a conversion round is running.
+    pub const fn is_converting(&self) -> bool {
+        self.current.is_some()
+    }
+
+    /// Gets the input at the `index`, in the order added.
+    pub fn input(&self, index: usize) -> Option<AdcInput> {
+        self.inputs[..self.len]
+            .get(index)
+            .copied()
+            .flatten()
+            .map(|(input, _)| input)
+    }
+
+    /// Gets the latest sample of the `input`.
+    ///
+    /// Returns `None` if the input is not scheduled, or was not converted yet.
+    pub fn sample(&self, input: AdcInput) -> Option<u16> {
+        let index = self.inputs[..self.len]
+            .iter()
+            .position(|entry| matches!(entry, Some((i, _)) if *i == input))?;
+
+        self.samples[index]
+    }
+
+    /// Starts a conversion round at the time `now_ms`, if the `interval_ms` passed since the last
+    /// one.
+    ///
+    /// Returns the first input to convert, or `None` if no round was started.
+    pub fn start_round(&mut self, now_ms: u16, interval_ms: u16) -> Option<AdcInput> {
+        if self.is_converting()
+            || self
+                .last_round_ms
+                .is_some_and(|last| now_ms.wrapping_sub(last) < interval_ms)
+        {
+            return None;
+        }
+
+        let input = self.input(0)?;
+
+        self.last_round_ms = Some(now_ms);
+        self.current = Some(0);
+
+        Some(input)
+    }
+
+    /// Stores the finished conversion of the current input, and runs its [AdcCallback].
+    ///
+    /// Returns the next input to convert, or `None` at the end of the round.
+    pub fn complete(&mut self, sample: u16) -> Option<AdcInput> {
+        let index = self.current.take()?;
+
+        if let Some((input, callback)) = self.inputs[index] {
+            self.samples[index] = Some(sample);
+
+            if let Some(callback) = callback {
+                callback(input, sample);
+            }
+        }
+
+        let next = index + 1;
+        let input = self.input(next)?;
+        self.current = Some(next);
+
+        Some(input)
+    }
+}
+
+impl Default for AdcSchedule {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicU16, Ordering};
+
+    use super::*;
+
+    static LAST_SAMPLE: AtomicU16 = AtomicU16::new(0);
+
+    fn store_sample(_input: AdcInput, sample: u16) {
+        LAST_SAMPLE.store(sample, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn test_adc_schedule() {
+        let config = AdcConfig::new();
+        assert_eq!(config.prescaler_bits(16_000_000), 7);
+        assert_eq!(config.prescaler_bits(1_000_000), 3);
+
+        assert_eq!(AdcInput::pin(2), None);
+        let x = AdcInput::pin(7).unwrap();
+        let y = AdcInput::pin(12).unwrap();
+        assert_eq!(config.admux(y), 0b0100_0100);
+        assert_eq!(config.adcsrb(y), MUX5);
+        assert_eq!(y.digital_input(), Some((2, 4)));
+        assert_eq!(AdcInput::BANDGAP.digital_input(), None);
+
+        let mut schedule = AdcSchedule::new()
+            .with_input(x, None)
+            .with_input(y, Some(store_sample));
+
+        assert_eq!(schedule.start_round(100, 8), Some(x));
+        assert_eq!(schedule.start_round(200, 8), None);
+        assert_eq!(schedule.complete(300), Some(y));
+        assert_eq!(schedule.complete(700), None);
+        assert!(!schedule.is_converting());
+        assert_eq!(LAST_SAMPLE.load(Ordering::Relaxed), 700);
+        assert_eq!(schedule.sample(x), Some(300));
+        assert_eq!(schedule.sample(AdcInput::BANDGAP), None);
+
+        assert_eq!(schedule.start_round(104, 8), None);
+        assert_eq!(schedule.start_round(108, 8), Some(x));
+    }
+}
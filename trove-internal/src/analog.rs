@@ -0,0 +1,126 @@
+//! Analog key thresholds.
+//!
+//! Experimental support for hall-effect and other analog switches, that are sampled through the
+//! ADC instead of read as digital pins. Each key has its own [KeyThreshold], with separate press
+//! and release levels, so noise around a single actuation point does not chatter.
+//!
+//! Samples are converted into the same [RowState]s as a digital matrix, so they are debounced and
+//! reported like any other switch.
+
+use crate::debounce::{RowState, MAX_COLS};
+
+/// Default ADC level a key is pressed at, for a 10-bit ADC.
+pub const DEFAULT_PRESS_LEVEL: u16 = 600;
+/// Default ADC level a key is released at, for a 10-bit ADC.
+pub const DEFAULT_RELEASE_LEVEL: u16 = 500;
+
+/// Represents the actuation levels of an analog key.
+///
+/// If the press level is above the release level, the key is pressed when the sample rises to
+/// the press level. Otherwise, e.g. for switches with the magnet flipped, the key is pressed when
+/// the sample falls to the press level.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeyThreshold {
+    press: u16,
+    release: u16,
+}
+
+impl KeyThreshold {
+    /// Creates a new [KeyThreshold].
+    pub const fn new(press: u16, release: u16) -> Self {
+        Self { press, release }
+    }
+
+    /// Gets the ADC level the key is pressed at.
+    pub const fn press(&self) -> u16 {
+        self.press
+    }
+
+    /// Gets the ADC level the key is released at.
+    pub const fn release(&self) -> u16 {
+        self.release
+    }
+
+    /// Gets whether the key is pressed after the `sample`, given whether it was `pressed` before.
+    pub const fn is_pressed(&self, sample: u16, pressed: bool) -> bool {
+        let rising = self.press >= self.release;
+
+        match (pressed, rising) {
+            (false, true) => sample >= self.press,
+            (false, false) => sample <= self.press,
+            (true, true) => sample > self.release,
+            (true, false) => sample < self.release,
+        }
+    }
+}
+
+impl Default for KeyThreshold {
+    fn default() -> Self {
+        Self::new(DEFAULT_PRESS_LEVEL, DEFAULT_RELEASE_LEVEL)
+    }
+}
+
+/// Gets the pressed keys in a row, from the ADC `samples` of each column.
+///
+/// Columns without a sample keep their `last` state.
+pub fn analog_row(
+    samples: &[Option<u16>],
+    thresholds: &[KeyThreshold],
+    last: RowState,
+) -> RowState {
+    let mut state = last;
+
+    for (j, (sample, threshold)) in samples
+        .iter()
+        .zip(thresholds.iter())
+        .enumerate()
+        .take(MAX_COLS)
+    {
+        if let Some(sample) = sample {
+            state.set_column(j, threshold.is_pressed(*sample, last.column(j)));
+        }
+    }
+
+    state
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_threshold() {
+        let threshold = KeyThreshold::default();
+
+        assert!(!threshold.is_pressed(599, false));
+        assert!(threshold.is_pressed(600, false));
+        // stays pressed between the release and press levels
+        assert!(threshold.is_pressed(550, true));
+        assert!(!threshold.is_pressed(500, true));
+
+        // flipped magnet
+        let threshold = KeyThreshold::new(300, 400);
+
+        assert!(!threshold.is_pressed(350, false));
+        assert!(threshold.is_pressed(300, false));
+        assert!(threshold.is_pressed(350, true));
+        assert!(!threshold.is_pressed(400, true));
+    }
+
+    #[test]
+    fn test_analog_row() {
+        let thresholds = [KeyThreshold::default(); 3];
+
+        let row = analog_row(
+            &[Some(700), Some(550), None],
+            &thresholds,
+            RowState::from_u16(0b110),
+        );
+
+        // the unsampled column keeps its state
+        assert_eq!(row, RowState::from_u16(0b111));
+
+        let row = analog_row(&[Some(400), Some(400), Some(400)], &thresholds, row);
+        assert!(row.is_inactive());
+    }
+}
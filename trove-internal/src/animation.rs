@@ -0,0 +1,395 @@
+//! Serialized LED animation format.
+//!
+//! Animations are stored as a compact byte stream, so they can be shared between users and
+//! uploaded to the keyboard without rebuilding the firmware, see
+//! [stored_animation](crate::stored_animation).
+//!
+//! The layout of a serialized animation:
+//!
+//! ```text
+//! | magic "TA" | version | LED count | frame count | frame duration (ms) | frame data ... |
+//! ```
+//!
+//! Each frame starts with a [FrameKind] tag:
+//!
+//! - [Key](FrameKind::Key): followed by one RGB triplet for every LED.
+//! - [Delta](FrameKind::Delta): followed by a count of changed LEDs, and `(index, r, g, b)` for
+//!   each change relative to the previous frame.
+//!
+//! The first frame must be a key frame.
+
+/// Magic bytes at the start of a serialized animation.
+pub const ANIMATION_MAGIC: [u8; 2] = *b"TA";
+/// Current version of the serialized animation format.
+pub const ANIMATION_VERSION: u8 = 1;
+/// Length of the serialized animation header.
+pub const ANIMATION_HEADER_LEN: usize = 6;
+
+/// Represents an RGB LED color.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    /// Creates a new [Rgb] color.
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    /// Creates a new [Rgb] color with all channels off.
+    pub const fn off() -> Self {
+        Self::new(0, 0, 0)
+    }
+}
+
+/// Errors from decoding a serialized animation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AnimationError {
+    /// The animation does not begin with [ANIMATION_MAGIC].
+    InvalidMagic,
+    /// The animation version is not supported.
+    InvalidVersion(u8),
+    /// The animation data ended in the middle of a header or frame.
+    Truncated,
+    /// Unknown frame tag.
+    InvalidFrame(u8),
+    /// A delta frame referenced an LED outside of the animation LED count.
+    InvalidLed(u8),
+    /// The first frame is not a key frame.
+    MissingKeyFrame,
+    /// The frame buffer is smaller than the animation LED count.
+    BufferTooSmall,
+}
+
+/// Represents the kind of a serialized frame.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FrameKind {
+    /// Full frame with a color for every LED.
+    Key = 0,
+    /// Changes relative to the previous frame.
+    Delta = 1,
+}
+
+impl TryFrom<u8> for FrameKind {
+    type Error = AnimationError;
+
+    fn try_from(val: u8) -> Result<Self, Self::Error> {
+        match val {
+            0 => Ok(Self::Key),
+            1 => Ok(Self::Delta),
+            _ => Err(AnimationError::InvalidFrame(val)),
+        }
+    }
+}
+
+/// Header of a serialized animation.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AnimationHeader {
+    led_count: u8,
+    frame_count: u8,
+    frame_ms: u8,
+}
+
+impl AnimationHeader {
+    /// Creates a new [AnimationHeader].
+    pub const fn new(led_count: u8, frame_count: u8, frame_ms: u8) -> Self {
+        Self {
+            led_count,
+            frame_count,
+            frame_ms,
+        }
+    }
+
+    /// Gets the number of LEDs in each frame.
+    pub const fn led_count(&self) -> u8 {
+        self.led_count
+    }
+
+    /// Gets the number of frames in the animation.
+    pub const fn frame_count(&self) -> u8 {
+        self.frame_count
+    }
+
+    /// Gets the duration of each frame in milliseconds.
+    pub const fn frame_ms(&self) -> u8 {
+        self.frame_ms
+    }
+
+    /// Parses an [AnimationHeader] from the start of a serialized animation.
+    pub fn parse(data: &[u8]) -> Result<Self, AnimationError> {
+        if data.len() < ANIMATION_HEADER_LEN {
+            Err(AnimationError::Truncated)
+        } else if data[..2] != ANIMATION_MAGIC {
+            Err(AnimationError::InvalidMagic)
+        } else if data[2] != ANIMATION_VERSION {
+            Err(AnimationError::InvalidVersion(data[2]))
+        } else {
+            Ok(Self::new(data[3], data[4], data[5]))
+        }
+    }
+
+    /// Writes the serialized [AnimationHeader] into the buffer.
+    ///
+    /// Returns the number of bytes written.
+    pub fn write(&self, buf: &mut [u8]) -> Result<usize, AnimationError> {
+        let out = buf
+            .get_mut(..ANIMATION_HEADER_LEN)
+            .ok_or(AnimationError::BufferTooSmall)?;
+
+        out[..2].copy_from_slice(ANIMATION_MAGIC.as_ref());
+        out[2] = ANIMATION_VERSION;
+        out[3] = self.led_count;
+        out[4] = self.frame_count;
+        out[5] = self.frame_ms;
+
+        Ok(ANIMATION_HEADER_LEN)
+    }
+}
+
+/// Source of the bytes of a serialized animation, e.g. a slice, or the
+/// [stored animation](crate::stored_animation) in EEPROM.
+pub trait AnimationData {
+    /// Gets the byte at the `offset`, or `None` past the end of the animation.
+    fn byte(&self, offset: usize) -> Option<u8>;
+}
+
+impl AnimationData for [u8] {
+    fn byte(&self, offset: usize) -> Option<u8> {
+        self.get(offset).copied()
+    }
+}
+
+/// Plays a serialized animation into an LED frame buffer.
+///
+/// The player does not hold the animation, the [AnimationData] is passed to every call, so it can
+/// be read from the EEPROM without a copy in RAM.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AnimationPlayer {
+    header: AnimationHeader,
+    offset: usize,
+    frame: u8,
+}
+
+impl AnimationPlayer {
+    /// Creates a new [AnimationPlayer] for a serialized animation.
+    pub fn new<D: AnimationData + ?Sized>(data: &D) -> Result<Self, AnimationError> {
+        let mut header = [0u8; ANIMATION_HEADER_LEN];
+        for (i, byte) in header.iter_mut().enumerate() {
+            *byte = data.byte(i).ok_or(AnimationError::Truncated)?;
+        }
+        let header = AnimationHeader::parse(header.as_ref())?;
+
+        match data.byte(ANIMATION_HEADER_LEN) {
+            Some(tag) if tag == FrameKind::Key as u8 => Ok(Self {
+                header,
+                offset: ANIMATION_HEADER_LEN,
+                frame: 0,
+            }),
+            Some(_) => Err(AnimationError::MissingKeyFrame),
+            None => Err(AnimationError::Truncated),
+        }
+    }
+
+    /// Gets the [AnimationHeader].
+    pub const fn header(&self) -> AnimationHeader {
+        self.header
+    }
+
+    /// Gets the index of the next frame to be played.
+    pub const fn frame(&self) -> u8 {
+        self.frame
+    }
+
+    /// Restarts the animation from the first frame.
+    pub fn reset(&mut self) {
+        self.offset = ANIMATION_HEADER_LEN;
+        self.frame = 0;
+    }
+
+    /// Applies the next frame of the animation `data` to the LED frame buffer.
+    ///
+    /// After the last frame, the animation loops back to the first frame.
+    pub fn next_frame<D: AnimationData + ?Sized>(
+        &mut self,
+        data: &D,
+        leds: &mut [Rgb],
+    ) -> Result<(), AnimationError> {
+        let led_count = self.header.led_count as usize;
+
+        if leds.len() < led_count {
+            return Err(AnimationError::BufferTooSmall);
+        }
+
+        if self.frame >= self.header.frame_count {
+            self.reset();
+        }
+
+        let kind = FrameKind::try_from(self.read_u8(data)?)?;
+
+        match kind {
+            FrameKind::Key => {
+                for led in leds[..led_count].iter_mut() {
+                    *led = self.read_rgb(data)?;
+                }
+            }
+            FrameKind::Delta => {
+                for _ in 0..self.read_u8(data)? {
+                    let index = self.read_u8(data)?;
+                    let color = self.read_rgb(data)?;
+
+                    if (index as usize) < led_count {
+                        leds[index as usize] = color;
+                    } else {
+                        return Err(AnimationError::InvalidLed(index));
+                    }
+                }
+            }
+        }
+
+        self.frame += 1;
+
+        Ok(())
+    }
+
+    fn read_u8<D: AnimationData + ?Sized>(&mut self, data: &D) -> Result<u8, AnimationError> {
+        let val = data.byte(self.offset).ok_or(AnimationError::Truncated)?;
+        self.offset += 1;
+        Ok(val)
+    }
+
+    fn read_rgb<D: AnimationData + ?Sized>(&mut self, data: &D) -> Result<Rgb, AnimationError> {
+        Ok(Rgb::new(
+            self.read_u8(data)?,
+            self.read_u8(data)?,
+            self.read_u8(data)?,
+        ))
+    }
+}
+
+/// Serializes the difference between two frames as a [Delta](FrameKind::Delta) frame.
+///
+/// If the delta would be larger than a [Key](FrameKind::Key) frame, a key frame is written
+/// instead.
+///
+/// Returns the number of bytes written.
+pub fn write_frame(prev: &[Rgb], next: &[Rgb], buf: &mut [u8]) -> Result<usize, AnimationError> {
+    let changes = prev.iter().zip(next.iter()).filter(|(p, n)| p != n).count();
+    let delta_len = 2 + changes * 4;
+    let key_len = 1 + next.len() * 3;
+
+    if prev.len() != next.len() || delta_len >= key_len {
+        let out = buf
+            .get_mut(..key_len)
+            .ok_or(AnimationError::BufferTooSmall)?;
+
+        out[0] = FrameKind::Key as u8;
+        for (chunk, led) in out[1..].chunks_exact_mut(3).zip(next.iter()) {
+            chunk.copy_from_slice([led.r, led.g, led.b].as_ref());
+        }
+
+        Ok(key_len)
+    } else {
+        let out = buf
+            .get_mut(..delta_len)
+            .ok_or(AnimationError::BufferTooSmall)?;
+
+        out[0] = FrameKind::Delta as u8;
+        out[1] = changes as u8;
+
+        let changed = next
+            .iter()
+            .enumerate()
+            .zip(prev.iter())
+            .filter(|((_, n), p)| n != p);
+
+        for (chunk, ((i, led), _)) in out[2..].chunks_exact_mut(4).zip(changed) {
+            chunk.copy_from_slice([i as u8, led.r, led.g, led.b].as_ref());
+        }
+
+        Ok(delta_len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Rgb = Rgb::new(0xff, 0, 0);
+    const BLUE: Rgb = Rgb::new(0, 0, 0xff);
+
+    #[test]
+    fn test_animation_round_trip() {
+        let frames = [
+            [RED, RED, RED, RED],
+            [RED, BLUE, RED, RED],
+            [BLUE, BLUE, BLUE, BLUE],
+        ];
+
+        let mut data = [0u8; 64];
+        let mut len = AnimationHeader::new(4, 3, 20).write(&mut data).unwrap();
+
+        let mut prev = [Rgb::off(); 4];
+        for (i, frame) in frames.iter().enumerate() {
+            let prev_frame: &[Rgb] = if i == 0 { &[] } else { prev.as_ref() };
+            len += write_frame(prev_frame, frame.as_ref(), &mut data[len..]).unwrap();
+            prev = *frame;
+        }
+
+        // key frame, one-LED delta frame, key frame (delta would be larger)
+        assert_eq!(data[ANIMATION_HEADER_LEN], FrameKind::Key as u8);
+        assert_eq!(data[ANIMATION_HEADER_LEN + 13], FrameKind::Delta as u8);
+        assert_eq!(len, ANIMATION_HEADER_LEN + 13 + 6 + 13);
+
+        let data = &data[..len];
+        let mut player = AnimationPlayer::new(data).unwrap();
+        let mut leds = [Rgb::off(); 4];
+
+        assert_eq!(player.header().frame_ms(), 20);
+
+        for frame in frames.iter() {
+            player.next_frame(data, &mut leds).unwrap();
+            assert_eq!(&leds, frame);
+        }
+
+        // loops back to the start
+        player.next_frame(data, &mut leds).unwrap();
+        assert_eq!(leds, frames[0]);
+    }
+
+    #[test]
+    fn test_animation_errors() {
+        assert_eq!(
+            AnimationPlayer::new(b"XX\x01\x01\x01\x01\x00".as_ref()),
+            Err(AnimationError::InvalidMagic)
+        );
+        assert_eq!(
+            AnimationPlayer::new(b"TA\x09\x01\x01\x01\x00".as_ref()),
+            Err(AnimationError::InvalidVersion(9))
+        );
+        assert_eq!(
+            AnimationPlayer::new(b"TA\x01\x01\x01\x01\x01".as_ref()),
+            Err(AnimationError::MissingKeyFrame)
+        );
+
+        let mut leds = [Rgb::off(); 1];
+        let data = b"TA\x01\x01\x01\x01\x00\xff".as_ref();
+        let mut player = AnimationPlayer::new(data).unwrap();
+        assert_eq!(
+            player.next_frame(data, &mut leds),
+            Err(AnimationError::Truncated)
+        );
+        assert_eq!(
+            player.next_frame(data, &mut []),
+            Err(AnimationError::BufferTooSmall)
+        );
+        assert_eq!(
+            AnimationPlayer::new(b"TA\x01".as_ref()),
+            Err(AnimationError::Truncated)
+        );
+    }
+}
@@ -0,0 +1,340 @@
+//! Audio feedback on a piezo buzzer.
+//!
+//! Builds with a speaker pad play short melodies: a [startup melody](STARTUP_MELODY), a
+//! [chirp](LAYER_CHIRP) on every layer change, and an [error tone](ERROR_TONE). Features ask for
+//! a [Sound] with [request_sound], and the [AudioPlayer] steps through the [Note]s from the main
+//! loop.
+//!
+//! The buzzer is driven with a square wave: a timer interrupts at twice the tone frequency, and
+//! toggles the pin. [ToneTimer] finds the timer settings for a frequency.
+//!
+//! The [AUDIO_ON](crate::layers::AUDIO_ON), [AUDIO_OFF](crate::layers::AUDIO_OFF), and
+//! [AUDIO_TOG](crate::layers::AUDIO_TOG) keys mute and unmute the buzzer.
+
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+use crate::layers::{self, Layer};
+use crate::scheduler::Timeout;
+
+/// Largest clock prescaler of a [ToneTimer], as a power of two.
+pub const MAX_PRESCALER_SHIFT: u8 = 14;
+
+static MUTED: AtomicBool = AtomicBool::new(false);
+static SOUND_REQUEST: AtomicU8 = AtomicU8::new(0);
+
+/// Represents a tone of a melody.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Note {
+    freq_hz: u16,
+    duration_ms: u16,
+}
+
+impl Note {
+    /// Creates a new [Note], playing the frequency for the duration (in milliseconds).
+    pub const fn new(freq_hz: u16, duration_ms: u16) -> Self {
+        Self {
+            freq_hz,
+            duration_ms,
+        }
+    }
+
+    /// Creates a new silent [Note] for the duration (in milliseconds).
+    pub const fn rest(duration_ms: u16) -> Self {
+        Self::new(0, duration_ms)
+    }
+
+    /// Gets the frequency in Hz, `0` for a rest.
+    pub const fn freq_hz(&self) -> u16 {
+        self.freq_hz
+    }
+
+    /// Gets the duration in milliseconds.
+    pub const fn duration_ms(&self) -> u16 {
+        self.duration_ms
+    }
+}
+
+/// Sequence of [Note]s.
+///
+/// Repeated notes run together, separate them with a short [rest](Note::rest).
+pub type Melody = &'static [Note];
+
+/// Rising arpeggio played at boot.
+pub const STARTUP_MELODY: Melody = &[
+    Note::new(523, 80),
+    Note::new(659, 80),
+    Note::new(784, 80),
+    Note::new(1047, 160),
+];
+/// Short chirp played on a layer change.
+pub const LAYER_CHIRP: Melody = &[Note::new(1568, 30)];
+/// Low double beep played on errors.
+pub const ERROR_TONE: Melody = &[Note::new(220, 150), Note::rest(50), Note::new(220, 150)];
+
+/// Represents a sound requested by a feature.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sound {
+    /// The keyboard started.
+    Startup = 1,
+    /// The active layer changed.
+    LayerChange = 2,
+    /// Something failed, e.g. saving the settings.
+    Error = 3,
+}
+
+impl Sound {
+    /// Gets the [Melody] of the sound.
+    pub const fn melody(&self) -> Melody {
+        match self {
+            Self::Startup => STARTUP_MELODY,
+            Self::LayerChange => LAYER_CHIRP,
+            Self::Error => ERROR_TONE,
+        }
+    }
+
+    /// Converts the requested byte into a [Sound].
+    pub const fn from_u8(val: u8) -> Option<Self> {
+        match val {
+            1 => Some(Self::Startup),
+            2 => Some(Self::LayerChange),
+            3 => Some(Self::Error),
+            _ => None,
+        }
+    }
+}
+
+/// Requests a [Sound], played on the next [update](AudioPlayer::update).
+///
+/// A later request replaces a pending one.
+pub fn request_sound(sound: Sound) {
+    SOUND_REQUEST.store(sound as u8, Ordering::SeqCst);
+}
+
+/// Takes the pending [Sound] request.
+pub fn take_sound_request() -> Option<Sound> {
+    let sound = Sound::from_u8(SOUND_REQUEST.load(Ordering::Relaxed));
+
+    if sound.is_some() {
+        SOUND_REQUEST.store(0, Ordering::SeqCst);
+    }
+
+    sound
+}
+
+/// Gets whether the buzzer is muted.
+pub fn muted() -> bool {
+    MUTED.load(Ordering::Relaxed)
+}
+
+/// Sets whether the buzzer is muted.
+pub fn set_muted(val: bool) {
+    MUTED.store(val, Ordering::SeqCst);
+}
+
+/// Toggles whether the buzzer is muted.
+pub fn toggle_muted() {
+    set_muted(!muted());
+}
+
+/// Handles a press of an audio key.
+///
+/// Returns `false` for other keys.
+pub fn handle_audio_key(key: u8) -> bool {
+    match key {
+        layers::AUDIO_ON => set_muted(false),
+        layers::AUDIO_OFF => set_muted(true),
+        layers::AUDIO_TOG => toggle_muted(),
+        _ => return false,
+    }
+
+    true
+}
+
+/// Timer settings for a square wave, toggling the pin on each timer overflow.
+///
+/// The timer counts from zero to [top](Self::top), clocked at the timer clock divided by
+/// `2^(clock_select - 1)`, like Timer 4 of the ATmega32u4.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ToneTimer {
+    clock_select: u8,
+    top: u8,
+}
+
+impl ToneTimer {
+    /// Creates a new [ToneTimer] for the tone frequency, with the smallest prescaler that fits.
+    ///
+    /// Returns `None` for a rest, or a frequency out of range of the `clock_hz`.
+    pub const fn new(clock_hz: u32, freq_hz: u16) -> Option<Self> {
+        if freq_hz == 0 {
+            return None;
+        }
+
+        let ticks = clock_hz / (2 * freq_hz as u32);
+        let mut shift = 0;
+
+        while shift <= MAX_PRESCALER_SHIFT {
+            let count = ticks >> shift;
+
+            if count == 0 {
+                return None;
+            } else if count <= 256 {
+                return Some(Self {
+                    clock_select: shift + 1,
+                    top: (count - 1) as u8,
+                });
+            }
+            shift += 1;
+        }
+
+        None
+    }
+
+    /// Gets the clock select bits of the timer.
+    pub const fn clock_select(&self) -> u8 {
+        self.clock_select
+    }
+
+    /// Gets the top of the timer count.
+    pub const fn top(&self) -> u8 {
+        self.top
+    }
+}
+
+/// Plays [Melody]s, one [Note] at a time.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AudioPlayer {
+    melody: Melody,
+    index: usize,
+    note: Timeout,
+    tone: u16,
+    layer: Option<Layer>,
+}
+
+impl AudioPlayer {
+    /// Creates a new [AudioPlayer], which is silent.
+    pub const fn new() -> Self {
+        Self {
+            melody: &[],
+            index: 0,
+            note: Timeout::new(),
+            tone: 0,
+            layer: None,
+        }
+    }
+
+    /// Starts playing the `melody` on the next poll, replacing the current one.
+    pub fn play(&mut self, melody: Melody) {
+        self.melody = melody;
+        self.index = 0;
+        self.note.cancel();
+    }
+
+    /// Stops playing.
+    pub fn stop(&mut self) {
+        self.play(&[]);
+    }
+
+    /// Gets whether a melody is playing.
+    pub const fn is_playing(&self) -> bool {
+        self.index < self.melody.len() || self.note.is_pending()
+    }
+
+    /// Gets the frequency of the tone playing, `0` if silent.
+    pub const fn tone(&self) -> u16 {
+        self.tone
+    }
+
+    /// Plays the requested [Sound], or the [LAYER_CHIRP] if the `layer` changed since the last
+    /// update, then [polls](Self::poll) the player.
+    pub fn update(&mut self, now_ms: u16, layer: Layer) -> Option<u16> {
+        if let Some(sound) = take_sound_request() {
+            self.play(sound.melody());
+        } else if self.layer.is_some_and(|last| last != layer) {
+            self.play(LAYER_CHIRP);
+        }
+        self.layer = Some(layer);
+
+        self.poll(now_ms)
+    }
+
+    /// Advances to the next note once the current one ended, at the time `now_ms`.
+    ///
+    /// Returns the new tone frequency when it changes, `0` to silence the buzzer. Nothing plays
+    /// while [muted].
+    pub fn poll(&mut self, now_ms: u16) -> Option<u16> {
+        if muted() {
+            self.stop();
+            return self.set_tone(0);
+        }
+
+        if self.note.is_pending() && !self.note.poll(now_ms) {
+            return None;
+        }
+
+        let freq = match self.melody.get(self.index) {
+            Some(note) => {
+                self.index += 1;
+                self.note.start(now_ms, note.duration_ms);
+                note.freq_hz
+            }
+            None => 0,
+        };
+
+        self.set_tone(freq)
+    }
+
+    // Records the tone, and returns it if it changed.
+    fn set_tone(&mut self, freq: u16) -> Option<u16> {
+        (self.tone != freq).then(|| {
+            self.tone = freq;
+            freq
+        })
+    }
+}
+
+impl Default for AudioPlayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audio_player() {
+        // 440 Hz at 64 MHz: 72727 ticks per half period, prescaled by 512
+        let timer = ToneTimer::new(64_000_000, 440).unwrap();
+        assert_eq!(timer.clock_select(), 10);
+        assert_eq!(timer.top(), 141);
+        assert_eq!(ToneTimer::new(64_000_000, 0), None);
+        assert_eq!(ToneTimer::new(64_000_000, 1), None);
+
+        let mut player = AudioPlayer::new();
+        assert_eq!(player.update(0, Layer::Base), None);
+
+        request_sound(Sound::Error);
+        assert_eq!(player.update(10, Layer::Base), Some(220));
+        assert!(player.is_playing());
+        assert_eq!(player.poll(100), None);
+        assert_eq!(player.poll(160), Some(0));
+        assert_eq!(player.poll(210), Some(220));
+        assert_eq!(player.poll(360), Some(0));
+        assert!(!player.is_playing());
+
+        assert_eq!(player.update(400, Layer::Fun), Some(1568));
+        assert_eq!(player.update(430, Layer::Fun), Some(0));
+
+        assert!(handle_audio_key(layers::AUDIO_TOG));
+        assert!(muted());
+        player.play(STARTUP_MELODY);
+        assert_eq!(player.poll(500), None);
+        assert!(!player.is_playing());
+
+        assert!(handle_audio_key(layers::AUDIO_ON));
+        assert!(!muted());
+        assert!(!handle_audio_key(layers::A));
+    }
+}
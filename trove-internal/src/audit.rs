@@ -0,0 +1,148 @@
+//! Shared state access auditing.
+//!
+//! With the `audit` feature enabled, accesses to shared state are counted, and re-entrant
+//! accesses (e.g. an interrupt touching state that the interrupted code was in the middle of
+//! updating) are recorded and trigger a panic, so concurrency bugs show up on hardware.
+//!
+//! Without the feature, [enter] compiles to nothing.
+
+#[cfg(feature = "audit")]
+use core::sync::atomic::{AtomicBool, AtomicU16, Ordering};
+
+/// Number of audited [Resource]s.
+pub const NUM_RESOURCES: usize = 3;
+
+/// Represents an audited piece of shared state.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Resource {
+    /// The global USB context.
+    UsbContext = 0,
+    /// The active layer.
+    ActiveLayer = 1,
+    /// The host LED state.
+    HostLeds = 2,
+}
+
+impl Resource {
+    /// Converts the [Resource] to a `usize`.
+    pub const fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+#[cfg(feature = "audit")]
+#[allow(clippy::declare_interior_mutable_const)]
+const BUSY_INIT: AtomicBool = AtomicBool::new(false);
+#[cfg(feature = "audit")]
+#[allow(clippy::declare_interior_mutable_const)]
+const COUNT_INIT: AtomicU16 = AtomicU16::new(0);
+
+#[cfg(feature = "audit")]
+static BUSY: [AtomicBool; NUM_RESOURCES] = [BUSY_INIT; NUM_RESOURCES];
+#[cfg(feature = "audit")]
+static ACCESSES: [AtomicU16; NUM_RESOURCES] = [COUNT_INIT; NUM_RESOURCES];
+#[cfg(feature = "audit")]
+static CONFLICTS: [AtomicU16; NUM_RESOURCES] = [COUNT_INIT; NUM_RESOURCES];
+
+/// Marks an audited [Resource] as in use until dropped.
+#[must_use]
+pub struct AccessGuard {
+    #[cfg(feature = "audit")]
+    resource: Resource,
+}
+
+impl Drop for AccessGuard {
+    fn drop(&mut self) {
+        #[cfg(feature = "audit")]
+        BUSY[self.resource.index()].store(false, Ordering::SeqCst);
+    }
+}
+
+/// Marks the start of an access to a shared [Resource].
+///
+/// The access lasts until the returned [AccessGuard] is dropped.
+///
+/// # Panics
+///
+/// With the `audit` feature, panics if the [Resource] is already being accessed.
+#[cfg_attr(not(feature = "audit"), allow(unused_variables))]
+pub fn enter(resource: Resource) -> AccessGuard {
+    #[cfg(feature = "audit")]
+    {
+        let idx = resource.index();
+
+        increment(&ACCESSES[idx]);
+
+        if BUSY[idx].load(Ordering::Relaxed) {
+            increment(&CONFLICTS[idx]);
+            panic!("re-entrant shared state access");
+        }
+
+        BUSY[idx].store(true, Ordering::SeqCst);
+
+        AccessGuard { resource }
+    }
+
+    #[cfg(not(feature = "audit"))]
+    AccessGuard {}
+}
+
+/// Gets the number of accesses to the [Resource].
+///
+/// Always zero without the `audit` feature.
+#[cfg_attr(not(feature = "audit"), allow(unused_variables))]
+pub fn accesses(resource: Resource) -> u16 {
+    #[cfg(feature = "audit")]
+    return ACCESSES[resource.index()].load(Ordering::Relaxed);
+
+    #[cfg(not(feature = "audit"))]
+    0
+}
+
+/// Gets the number of re-entrant accesses to the [Resource].
+///
+/// Always zero without the `audit` feature.
+#[cfg_attr(not(feature = "audit"), allow(unused_variables))]
+pub fn conflicts(resource: Resource) -> u16 {
+    #[cfg(feature = "audit")]
+    return CONFLICTS[resource.index()].load(Ordering::Relaxed);
+
+    #[cfg(not(feature = "audit"))]
+    0
+}
+
+#[cfg(feature = "audit")]
+fn increment(counter: &AtomicU16) {
+    counter.store(
+        counter.load(Ordering::Relaxed).saturating_add(1),
+        Ordering::SeqCst,
+    );
+}
+
+#[cfg(all(test, feature = "audit"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audit_counts() {
+        let before = accesses(Resource::HostLeds);
+
+        {
+            let _guard = enter(Resource::HostLeds);
+        }
+        {
+            let _guard = enter(Resource::HostLeds);
+        }
+
+        assert_eq!(accesses(Resource::HostLeds), before + 2);
+        assert_eq!(conflicts(Resource::HostLeds), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_audit_reentrant() {
+        let _outer = enter(Resource::UsbContext);
+        let _inner = enter(Resource::UsbContext);
+    }
+}
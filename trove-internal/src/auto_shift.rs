@@ -0,0 +1,136 @@
+//! Auto Shift.
+//!
+//! With the [AutoShift](crate::features::Feature::AutoShift) feature enabled, holding a letter,
+//! digit, or punctuation key for the [term](AutoShift::term_ms) sends its shifted version, so
+//! Shift is rarely needed. Keys released before the term are sent unshifted.
+//!
+//! The press of an auto-shifted key is delayed until it resolves, one key at a time. Pressing
+//! another key while one is pending resolves the pending key unshifted first, so fast typing
+//! keeps its order.
+
+use crate::events::KeyEvent;
+use crate::layers::{A, DASH, SLASH, ZERO};
+
+/// Default time (in milliseconds) a key is held before it is sent shifted.
+pub const AUTO_SHIFT_TERM_MS: u16 = 175;
+
+/// Gets whether the key is sent shifted when held: letters, digits, and punctuation.
+pub fn key_is_auto_shifted(key: u8) -> bool {
+    (A..=ZERO).contains(&key) || (DASH..=SLASH).contains(&key)
+}
+
+/// Auto Shift state, holding the pending key press.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AutoShift {
+    term_ms: u16,
+    pending: Option<(KeyEvent, u8)>,
+}
+
+impl AutoShift {
+    /// Creates a new [AutoShift] with the default term.
+    pub const fn new() -> Self {
+        Self {
+            term_ms: AUTO_SHIFT_TERM_MS,
+            pending: None,
+        }
+    }
+
+    /// Gets the time (in milliseconds) a key is held before it is sent shifted.
+    pub const fn term_ms(&self) -> u16 {
+        self.term_ms
+    }
+
+    /// Sets the time (in milliseconds) a key is held before it is sent shifted.
+    pub fn set_term_ms(&mut self, val: u16) {
+        self.term_ms = val;
+    }
+
+    /// Builder function that sets the time (in milliseconds) a key is held before it is sent
+    /// shifted.
+    pub fn with_term_ms(mut self, val: u16) -> Self {
+        self.set_term_ms(val);
+        self
+    }
+
+    /// Gets whether a key press is pending.
+    pub const fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Delays the press of an [auto-shifted key](key_is_auto_shifted).
+    ///
+    /// Returns `false` for other keys, or if a key press is already pending, which are not
+    /// delayed.
+    pub fn press(&mut self, event: KeyEvent, key: u8) -> bool {
+        if self.pending.is_none() && key_is_auto_shifted(key) {
+            self.pending = Some((event, key));
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Takes the pending key press if the `event` releases its key before the term, to send it
+    /// unshifted.
+    pub fn release(&mut self, event: &KeyEvent) -> Option<(KeyEvent, u8)> {
+        self.pending
+            .take_if(|(press, _)| press.row() == event.row() && press.col() == event.col())
+    }
+
+    /// Takes the pending key press once held for the term at the scan clock time `now_ms`, to
+    /// send it shifted.
+    pub fn tick(&mut self, now_ms: u16) -> Option<(KeyEvent, u8)> {
+        let term_ms = self.term_ms;
+
+        self.pending
+            .take_if(|(press, _)| now_ms.wrapping_sub(press.timestamp()) >= term_ms)
+    }
+
+    /// Takes the pending key press, e.g. when another key is pressed, to send it unshifted.
+    pub fn take(&mut self) -> Option<(KeyEvent, u8)> {
+        self.pending.take()
+    }
+}
+
+impl Default for AutoShift {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::{B, ENTER, ONE, SHIFT};
+
+    #[test]
+    fn test_auto_shift() {
+        let mut auto_shift = AutoShift::new().with_term_ms(100);
+        let press_a = KeyEvent::new(0, 1, true, 10);
+
+        assert!(key_is_auto_shifted(ONE) && key_is_auto_shifted(SLASH));
+        assert!(!key_is_auto_shifted(ENTER) && !key_is_auto_shifted(SHIFT));
+
+        assert!(!auto_shift.press(KeyEvent::new(0, 0, true, 0), ENTER));
+        assert!(auto_shift.press(press_a, A));
+        assert!(!auto_shift.press(KeyEvent::new(0, 2, true, 20), B));
+
+        // released before the term
+        assert_eq!(auto_shift.tick(109), None);
+        assert_eq!(auto_shift.release(&KeyEvent::new(0, 2, false, 50)), None);
+        assert_eq!(
+            auto_shift.release(&KeyEvent::new(0, 1, false, 60)),
+            Some((press_a, A))
+        );
+        assert!(!auto_shift.is_pending());
+
+        // held for the term
+        auto_shift.press(press_a, A);
+        assert_eq!(auto_shift.tick(110), Some((press_a, A)));
+        assert_eq!(auto_shift.release(&KeyEvent::new(0, 1, false, 200)), None);
+
+        auto_shift.press(press_a, A);
+        assert_eq!(auto_shift.take(), Some((press_a, A)));
+        assert_eq!(auto_shift.tick(u16::MAX), None);
+    }
+}
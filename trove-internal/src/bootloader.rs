@@ -0,0 +1,68 @@
+//! Bootloader entry.
+//!
+//! The Caterina bootloader of the Atreus (and the Pro Micro) only stays active after a reset if
+//! it finds the [BOOT_KEY] at [BOOT_KEY_ADDR] in SRAM, otherwise it starts the firmware right
+//! away. Host tools can ask the keyboard to reboot into the bootloader, so `avrdude` can flash it
+//! without shorting the reset pin:
+//!
+//! - the Arduino "1200-baud touch": opening the USB-serial port at [BOOTLOADER_BAUD_RATE], and
+//!   closing it again (dropping DTR)
+//! - the [EnterBootloader](crate::config::Command::EnterBootloader) raw HID command
+//!
+//! Both only [request](request_bootloader) the reboot, and the main loop reboots after
+//! [BOOTLOADER_DELAY_MS], so the host sees the response before the keyboard detaches.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::config::ConfigError;
+
+/// Baud rate of the USB-serial port that requests the bootloader, once DTR is dropped.
+pub const BOOTLOADER_BAUD_RATE: u32 = 1200;
+/// Key that keeps the Caterina bootloader active after a watchdog reset.
+pub const BOOT_KEY: u16 = 0x7777;
+/// SRAM address checked for the [BOOT_KEY] by the Caterina bootloader.
+pub const BOOT_KEY_ADDR: u16 = 0x0800;
+/// Time (in milliseconds) between a bootloader request and the reboot.
+pub const BOOTLOADER_DELAY_MS: u16 = 100;
+
+/// Whether a reboot into the bootloader was requested.
+static BOOTLOADER_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Gets whether a reboot into the bootloader was requested.
+pub fn bootloader_requested() -> bool {
+    BOOTLOADER_REQUESTED.load(Ordering::Relaxed)
+}
+
+/// Requests a reboot into the bootloader from the main loop.
+pub fn request_bootloader() {
+    BOOTLOADER_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Gets whether the line coding and control line state of the USB-serial port are a
+/// "1200-baud touch".
+pub fn is_bootloader_touch(data_rate: u32, dtr: bool) -> bool {
+    data_rate == BOOTLOADER_BAUD_RATE && !dtr
+}
+
+/// Handles the [EnterBootloader](crate::config::Command::EnterBootloader) command.
+///
+/// Responds without data, the reboot follows after [BOOTLOADER_DELAY_MS].
+pub fn handle_bootloader_command(_buf: &mut [u8]) -> Result<usize, ConfigError> {
+    request_bootloader();
+    Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bootloader_request() {
+        assert!(is_bootloader_touch(1200, false));
+        assert!(!is_bootloader_touch(1200, true));
+        assert!(!is_bootloader_touch(9600, false));
+
+        assert_eq!(handle_bootloader_command(&mut []), Ok(0));
+        assert!(bootloader_requested());
+    }
+}
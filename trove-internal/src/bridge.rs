@@ -0,0 +1,207 @@
+//! UART to USB-serial bridge state.
+//!
+//! Firmware developers can use the keyboard as a makeshift USB-serial adapter for debugging
+//! companion devices. While the bridge is enabled, bytes received on the board UART are forwarded
+//! to the host over a USB-CDC serial port, and bytes from the host are written to the UART. The
+//! keyboard HID interface stays active the whole time.
+//!
+//! The bridge is toggled with the [SERIAL](crate::layers::SERIAL) key.
+//!
+//! The bridge needs a free UART, on the ATmega32u4 USART1 with RX on `PD2` and TX on `PD3`. The
+//! Atreus uses these pins for key matrix columns, so it has no bridge, and the [SERIAL] key does
+//! nothing until a board sets the bridge up, see [set_bridge_available].
+//!
+//! [SERIAL]: crate::layers::SERIAL
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Capacity of the [ByteQueue] for each bridge direction.
+pub const BRIDGE_QUEUE_LEN: usize = 64;
+
+static BRIDGE_AVAILABLE: AtomicBool = AtomicBool::new(false);
+static BRIDGE_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Gets whether the board has a serial bridge.
+pub fn bridge_available() -> bool {
+    BRIDGE_AVAILABLE.load(Ordering::Relaxed)
+}
+
+/// Sets whether the board has a serial bridge, set once the bridge is created.
+///
+/// Disables the bridge when it is no longer available.
+pub fn set_bridge_available(val: bool) {
+    BRIDGE_AVAILABLE.store(val, Ordering::SeqCst);
+
+    if !val {
+        set_bridge_enabled(false);
+    }
+}
+
+/// Gets whether the serial bridge is enabled.
+pub fn bridge_enabled() -> bool {
+    BRIDGE_ENABLED.load(Ordering::Relaxed)
+}
+
+/// Sets whether the serial bridge is enabled.
+pub fn set_bridge_enabled(val: bool) {
+    BRIDGE_ENABLED.store(val, Ordering::SeqCst);
+}
+
+/// Toggles the serial bridge, and returns whether it is now enabled.
+///
+/// The bridge stays disabled on boards without one, see [bridge_available].
+pub fn toggle_bridge() -> bool {
+    let enabled = !bridge_enabled() && bridge_available();
+    set_bridge_enabled(enabled);
+    enabled
+}
+
+/// Fixed-capacity FIFO of bytes in flight between the UART and the USB-serial port.
+///
+/// Neither side is allowed to block the matrix scan, so bytes pushed into a full queue are dropped
+/// and counted instead.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ByteQueue<const N: usize = BRIDGE_QUEUE_LEN> {
+    buf: [u8; N],
+    head: usize,
+    len: usize,
+    dropped: u16,
+}
+
+impl<const N: usize> ByteQueue<N> {
+    /// Creates a new [ByteQueue].
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; N],
+            head: 0,
+            len: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Gets the number of queued bytes.
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Gets whether the queue is empty.
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Gets the number of bytes that can be pushed before the queue is full.
+    pub const fn free(&self) -> usize {
+        N - self.len
+    }
+
+    /// Gets the number of bytes dropped because the queue was full.
+    pub const fn dropped(&self) -> u16 {
+        self.dropped
+    }
+
+    /// Pushes a byte to the back of the queue.
+    ///
+    /// Returns `false`, and drops the byte, if the queue is full.
+    pub fn push(&mut self, byte: u8) -> bool {
+        if self.len == N {
+            self.dropped = self.dropped.saturating_add(1);
+            false
+        } else {
+            self.buf[(self.head + self.len) % N] = byte;
+            self.len += 1;
+            true
+        }
+    }
+
+    /// Pushes bytes to the back of the queue, and returns the number of bytes queued.
+    pub fn extend(&mut self, bytes: &[u8]) -> usize {
+        bytes.iter().filter(|&&b| self.push(b)).count()
+    }
+
+    /// Gets the byte at the front of the queue, without removing it.
+    pub fn peek(&self) -> Option<u8> {
+        (!self.is_empty()).then_some(self.buf[self.head])
+    }
+
+    /// Removes the byte at the front of the queue.
+    pub fn pop(&mut self) -> Option<u8> {
+        let byte = self.peek()?;
+        self.consume(1);
+        Some(byte)
+    }
+
+    /// Gets the longest contiguous run of bytes at the front of the queue.
+    ///
+    /// Useful for writing to endpoints that accept partial writes, followed by
+    /// [consume](Self::consume) with the number of bytes written.
+    pub fn front(&self) -> &[u8] {
+        let end = core::cmp::min(self.head + self.len, N);
+        &self.buf[self.head..end]
+    }
+
+    /// Removes up to `count` bytes from the front of the queue.
+    pub fn consume(&mut self, count: usize) {
+        let count = core::cmp::min(count, self.len);
+
+        self.len -= count;
+        self.head = if self.len == 0 {
+            0
+        } else {
+            (self.head + count) % N
+        };
+    }
+
+    /// Removes all bytes from the queue.
+    pub fn clear(&mut self) {
+        self.head = 0;
+        self.len = 0;
+    }
+}
+
+impl<const N: usize> Default for ByteQueue<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_byte_queue() {
+        let mut queue = ByteQueue::<4>::new();
+
+        assert!(queue.is_empty());
+        assert_eq!(queue.extend(b"abcde"), 4);
+        assert_eq!(queue.dropped(), 1);
+        assert_eq!(queue.free(), 0);
+
+        assert_eq!(queue.pop(), Some(b'a'));
+        assert_eq!(queue.front(), b"bcd");
+
+        // wrap around the end of the buffer
+        assert!(queue.push(b'e'));
+        assert_eq!(queue.front(), b"bcd");
+        queue.consume(3);
+        assert_eq!(queue.front(), b"e");
+        assert_eq!(queue.pop(), Some(b'e'));
+        assert_eq!(queue.pop(), None);
+    }
+
+    #[test]
+    fn test_toggle_bridge() {
+        // the SERIAL key does nothing without a bridge
+        assert!(!bridge_available());
+        assert!(!toggle_bridge());
+        assert!(!bridge_enabled());
+
+        set_bridge_available(true);
+        assert!(toggle_bridge());
+        assert!(!toggle_bridge());
+
+        assert!(toggle_bridge());
+        set_bridge_available(false);
+        assert!(!bridge_enabled());
+    }
+}
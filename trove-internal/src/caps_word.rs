@@ -0,0 +1,95 @@
+//! Caps Word.
+//!
+//! With the [CapsWord](crate::features::Feature::CapsWord) feature enabled, the
+//! [CAPS_WORD](layers::CAPS_WORD) key shifts letters until a key that does not belong in a word
+//! is pressed, e.g. to type a `CONSTANT_NAME` without holding Shift or toggling Caps Lock.
+//!
+//! Digits, Backspace, Delete, modifiers, and layer keys continue the word, and Dash is shifted to
+//! an underscore. Any other key ends the word, and is sent unshifted.
+
+use crate::layers::{self, A, BKSP, DASH, DEL, ONE, SHIFT, Z, ZERO};
+
+/// Caps Word state.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct CapsWord {
+    active: bool,
+}
+
+impl CapsWord {
+    /// Creates a new, inactive [CapsWord].
+    pub const fn new() -> Self {
+        Self { active: false }
+    }
+
+    /// Gets whether Caps Word is active.
+    pub const fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Sets whether Caps Word is active.
+    pub fn set_active(&mut self, val: bool) {
+        self.active = val;
+    }
+
+    /// Handles a key press, and returns the modifiers to send with the key.
+    ///
+    /// The [CAPS_WORD](layers::CAPS_WORD) key toggles Caps Word.
+    pub fn press(&mut self, key: u8) -> u8 {
+        if layers::key_is_caps_word(key) {
+            self.active = !self.active;
+            return 0;
+        }
+
+        if !self.active {
+            return 0;
+        }
+
+        match key {
+            A..=Z | DASH => layers::key_to_modifier(SHIFT),
+            ONE..=ZERO | BKSP | DEL => 0,
+            key if layers::key_is_modifier(key)
+                || layers::key_is_one_shot(key)
+                || layers::key_is_fun(key)
+                || layers::key_is_upper(key) =>
+            {
+                0
+            }
+            _ => {
+                self.active = false;
+                0
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::{CAPS_WORD, FIVE, SPACE};
+
+    #[test]
+    fn test_caps_word() {
+        let mut caps_word = CapsWord::new();
+        let shift = layers::key_to_modifier(SHIFT);
+
+        assert_eq!(caps_word.press(A), 0);
+        assert_eq!(caps_word.press(CAPS_WORD), 0);
+        assert!(caps_word.is_active());
+
+        assert_eq!(caps_word.press(A), shift);
+        assert_eq!(caps_word.press(DASH), shift);
+        assert_eq!(caps_word.press(FIVE), 0);
+        assert_eq!(caps_word.press(SHIFT), 0);
+        assert_eq!(caps_word.press(Z), shift);
+
+        // a space ends the word
+        assert_eq!(caps_word.press(SPACE), 0);
+        assert!(!caps_word.is_active());
+        assert_eq!(caps_word.press(A), 0);
+
+        // the key toggles Caps Word off again
+        caps_word.press(CAPS_WORD);
+        caps_word.press(CAPS_WORD);
+        assert!(!caps_word.is_active());
+    }
+}
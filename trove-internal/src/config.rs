@@ -0,0 +1,208 @@
+//! Configuration protocol commands.
+//!
+//! Host tools send a command byte followed by the command arguments, and receive a
+//! command-specific response.
+//!
+//! On the keyboard, commands arrive over the [raw_hid](crate::raw_hid) interface.
+
+use crate::storage::EepromStorage;
+use crate::{
+    bootloader, features, firmware_info, rgb_map, stored_animation, stored_keymap, timing, tuning,
+};
+
+/// Represents a configuration protocol command.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command {
+    /// Analyze the active key map, and respond with a list of warnings.
+    Lint = 0x01,
+    /// Get the palette index and color of a key: `| layer | key |`.
+    GetKeyColor = 0x02,
+    /// Set the palette index of a key: `| layer | key | palette index |`.
+    SetKeyColor = 0x03,
+    /// Get a palette color: `| palette index |`.
+    GetPaletteColor = 0x04,
+    /// Set a palette color: `| palette index | r | g | b |`.
+    SetPaletteColor = 0x05,
+    /// Get the scan timing measurements, see [TimingReport](crate::timing::TimingReport).
+    GetTiming = 0x06,
+    /// Get the firmware identification, see [FirmwareInfo](crate::firmware_info::FirmwareInfo).
+    GetFirmwareInfo = 0x07,
+    /// Reboot into the bootloader, see [bootloader](crate::bootloader).
+    EnterBootloader = 0x08,
+    /// Read keys of the stored key map: `| position (u16) | count |`, see
+    /// [stored_keymap](crate::stored_keymap).
+    ReadKeymap = 0x09,
+    /// Stage keys of the stored key map: `| position (u16) | count | keys ... |`.
+    WriteKeymap = 0x0a,
+    /// Make the staged keys of the stored key map active.
+    CommitKeymap = 0x0b,
+    /// Discard the staged keys of the stored key map.
+    RevertKeymap = 0x0c,
+    /// Get a tuning parameter: `| parameter |`, see [tuning](crate::tuning).
+    GetTuning = 0x0d,
+    /// Set a tuning parameter: `| parameter | value (u16) |`.
+    SetTuning = 0x0e,
+    /// Get the enabled runtime features, see [features](crate::features).
+    GetFeatures = 0x0f,
+    /// Enable or disable a runtime feature: `| feature | enabled |`.
+    SetFeature = 0x10,
+    /// Write bytes of an LED animation upload: `| position (u16) | count | bytes ... |`, see
+    /// [stored_animation](crate::stored_animation).
+    WriteAnimation = 0x11,
+    /// Make the uploaded LED animation active: `| length (u16) |`.
+    CommitAnimation = 0x12,
+}
+
+impl TryFrom<u8> for Command {
+    type Error = ConfigError;
+
+    fn try_from(val: u8) -> Result<Self, Self::Error> {
+        match val {
+            0x01 => Ok(Self::Lint),
+            0x02 => Ok(Self::GetKeyColor),
+            0x03 => Ok(Self::SetKeyColor),
+            0x04 => Ok(Self::GetPaletteColor),
+            0x05 => Ok(Self::SetPaletteColor),
+            0x06 => Ok(Self::GetTiming),
+            0x07 => Ok(Self::GetFirmwareInfo),
+            0x08 => Ok(Self::EnterBootloader),
+            0x09 => Ok(Self::ReadKeymap),
+            0x0a => Ok(Self::WriteKeymap),
+            0x0b => Ok(Self::CommitKeymap),
+            0x0c => Ok(Self::RevertKeymap),
+            0x0d => Ok(Self::GetTuning),
+            0x0e => Ok(Self::SetTuning),
+            0x0f => Ok(Self::GetFeatures),
+            0x10 => Ok(Self::SetFeature),
+            0x11 => Ok(Self::WriteAnimation),
+            0x12 => Ok(Self::CommitAnimation),
+            _ => Err(ConfigError::InvalidCommand(val)),
+        }
+    }
+}
+
+impl From<Command> for u8 {
+    fn from(val: Command) -> Self {
+        val as u8
+    }
+}
+
+/// Errors from handling a configuration protocol command.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConfigError {
+    /// Unknown command byte.
+    InvalidCommand(u8),
+    /// The command is not handled by the receiving subsystem.
+    UnhandledCommand(Command),
+    /// The command arguments are missing or out of range.
+    InvalidArgument,
+    /// The response buffer is too small.
+    BufferTooSmall,
+}
+
+impl ConfigError {
+    /// Gets the status code sent to host tools for the [ConfigError].
+    ///
+    /// A status of `0` means success, so error codes start at `1`.
+    pub const fn code(&self) -> u8 {
+        match self {
+            Self::InvalidCommand(_) => 1,
+            Self::UnhandledCommand(_) => 2,
+            Self::InvalidArgument => 3,
+            Self::BufferTooSmall => 4,
+        }
+    }
+}
+
+/// Handles the commands answered by the firmware.
+///
+/// The color map, key map, and animation commands read and write the `storage`, see
+/// [storage](crate::storage).
+/// Commands of host-side subsystems (e.g. [Lint](Command::Lint)) return
+/// [ConfigError::UnhandledCommand].
+pub fn handle_command<S: EepromStorage>(
+    storage: &mut S,
+    command: Command,
+    args: &[u8],
+    buf: &mut [u8],
+) -> Result<usize, ConfigError> {
+    match command {
+        Command::GetKeyColor
+        | Command::SetKeyColor
+        | Command::GetPaletteColor
+        | Command::SetPaletteColor => rgb_map::handle_rgb_map_command(storage, command, args, buf),
+        Command::GetTiming => timing::handle_timing_command(buf),
+        Command::GetFirmwareInfo => firmware_info::handle_firmware_info_command(buf),
+        Command::EnterBootloader => bootloader::handle_bootloader_command(buf),
+        Command::ReadKeymap
+        | Command::WriteKeymap
+        | Command::CommitKeymap
+        | Command::RevertKeymap => {
+            stored_keymap::handle_keymap_command(storage, command, args, buf)
+        }
+        Command::GetTuning | Command::SetTuning => {
+            tuning::handle_tuning_command(command, args, buf)
+        }
+        Command::GetFeatures | Command::SetFeature => {
+            features::handle_features_command(command, args, buf)
+        }
+        Command::WriteAnimation | Command::CommitAnimation => {
+            stored_animation::handle_animation_command(storage, command, args)
+        }
+        Command::Lint => Err(ConfigError::UnhandledCommand(command)),
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::rgb_map::{load_rgb_map, RgbMap};
+    use crate::sim::SimEeprom;
+
+    #[test]
+    fn test_color_commands() {
+        let mut eeprom = SimEeprom::new();
+        let mut buf = [0u8; 4];
+
+        // an erased EEPROM reads as the blank color map
+        assert_eq!(
+            handle_command(&mut eeprom, Command::GetKeyColor, &[1, 12], &mut buf),
+            Ok(4)
+        );
+        assert_eq!(buf, [0; 4]);
+
+        assert_eq!(
+            handle_command(
+                &mut eeprom,
+                Command::SetPaletteColor,
+                &[2, 0xff, 0, 0],
+                &mut buf
+            ),
+            Ok(0)
+        );
+        assert_eq!(
+            handle_command(&mut eeprom, Command::SetKeyColor, &[1, 12, 2], &mut buf),
+            Ok(0)
+        );
+        assert_eq!(
+            handle_command(&mut eeprom, Command::GetKeyColor, &[1, 12], &mut buf),
+            Ok(4)
+        );
+        assert_eq!(buf, [2, 0xff, 0, 0]);
+
+        // the color map is persisted in the EEPROM
+        let map = load_rgb_map(&eeprom);
+        assert_ne!(map, RgbMap::new());
+        assert_eq!(map.key_index(1, 12), Ok(2));
+
+        assert_eq!(
+            handle_command(&mut eeprom, Command::SetKeyColor, &[4, 0, 1], &mut buf),
+            Err(ConfigError::InvalidArgument)
+        );
+        assert_eq!(
+            handle_command(&mut eeprom, Command::Lint, &[], &mut buf),
+            Err(ConfigError::UnhandledCommand(Command::Lint))
+        );
+    }
+}
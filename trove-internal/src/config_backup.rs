@@ -0,0 +1,191 @@
+//! EEPROM configuration backup over the serial console.
+//!
+//! The `dump` [console](crate::console) command prints the whole configuration stored in EEPROM
+//! (the [color map](crate::rgb_map), [pin map](crate::pin_map), [settings](crate::settings), and
+//! [stored key map](crate::stored_keymap)) as hex lines, which are `load` commands themselves:
+//!
+//! ```text
+//! > dump
+//! load 0000 00ff00ff00ff00ff00ff00ff00ff00ff
+//! load 0010 00ff00ff00ff00ff00ff00ff00ff00ff
+//! ...
+//! > load 0010 00ff00ff00ff00ff00ff00ff00ff00ff
+//! load ok
+//! ```
+//!
+//! Pasting a saved dump back into a terminal restores the configuration, e.g. after a firmware
+//! update, or on another keyboard. Every block keeps its own magic and CRC, so a partial restore
+//! falls back to the defaults for the torn block. The key map is reloaded right away, the other
+//! blocks are read at the next boot.
+
+use crate::config::ConfigError;
+use crate::storage::EepromStorage;
+use crate::stored_keymap::{self, KEYMAP_LEN, KEYMAP_OFFSET};
+
+/// Length of the configuration stored in EEPROM, from the start of the EEPROM to the end of the
+/// stored key map.
+pub const CONFIG_LEN: usize = KEYMAP_OFFSET + KEYMAP_LEN;
+/// Number of configuration bytes in a `load` line.
+pub const LOAD_LINE_BYTES: usize = 16;
+/// Maximum length of a `load` line: `load OOOO <hex bytes>\r\n`.
+pub const LOAD_LINE_LEN: usize = 10 + 2 * LOAD_LINE_BYTES + 2;
+
+const HEX_DIGITS: &[u8; 16] = b"0123456789abcdef";
+
+/// Streams the configuration as `load` lines, for the `dump` command.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ConfigDump {
+    offset: usize,
+}
+
+impl ConfigDump {
+    /// Creates a new [ConfigDump], starting at the beginning of the configuration.
+    pub const fn new() -> Self {
+        Self { offset: 0 }
+    }
+
+    /// Gets whether every line was written.
+    pub const fn is_done(&self) -> bool {
+        self.offset >= CONFIG_LEN
+    }
+
+    /// Writes the next `load` line from the `storage` into the `buf`.
+    ///
+    /// Returns the length of the line, or `None` once the dump is done.
+    pub fn next_line<S: EepromStorage>(
+        &mut self,
+        storage: &S,
+        buf: &mut [u8; LOAD_LINE_LEN],
+    ) -> Option<usize> {
+        if self.is_done() {
+            return None;
+        }
+
+        let mut data = [0u8; LOAD_LINE_BYTES];
+        let data = &mut data[..(CONFIG_LEN - self.offset).min(LOAD_LINE_BYTES)];
+        storage.load(self.offset, data).ok()?;
+
+        buf[..5].copy_from_slice(b"load ");
+        put_hex(&(self.offset as u16).to_be_bytes(), &mut buf[5..9]);
+        buf[9] = b' ';
+
+        let end = 10 + 2 * data.len();
+        put_hex(data, &mut buf[10..end]);
+        buf[end..end + 2].copy_from_slice(b"\r\n");
+
+        self.offset += data.len();
+
+        Some(end + 2)
+    }
+}
+
+/// Writes the configuration bytes of a `load` line (`OOOO <hex bytes>`, without the command)
+/// into the `storage`.
+///
+/// Returns the offset of the written bytes, or [ConfigError::InvalidArgument] if the line is
+/// malformed, or reaches beyond [CONFIG_LEN].
+pub fn load_line<S: EepromStorage>(storage: &mut S, args: &[u8]) -> Result<u16, ConfigError> {
+    let (offset, hex) = match args.trim_ascii().split_at_checked(4) {
+        Some((offset, [b' ', hex @ ..])) => (offset, hex),
+        _ => return Err(ConfigError::InvalidArgument),
+    };
+
+    let mut offset_bytes = [0u8; 2];
+    parse_hex(offset, &mut offset_bytes)?;
+    let offset = u16::from_be_bytes(offset_bytes);
+
+    let mut data = [0u8; LOAD_LINE_BYTES];
+    let data = data
+        .get_mut(..hex.len() / 2)
+        .ok_or(ConfigError::InvalidArgument)?;
+    parse_hex(hex, data)?;
+
+    if data.is_empty() || offset as usize + data.len() > CONFIG_LEN {
+        return Err(ConfigError::InvalidArgument);
+    }
+
+    storage.store(offset as usize, data)?;
+    stored_keymap::reload_active_keymap(storage);
+
+    Ok(offset)
+}
+
+// Writes the `data` as lowercase hex digits into the `out`, two digits per byte.
+fn put_hex(data: &[u8], out: &mut [u8]) {
+    for (byte, digits) in data.iter().zip(out.chunks_exact_mut(2)) {
+        digits[0] = HEX_DIGITS[(byte >> 4) as usize];
+        digits[1] = HEX_DIGITS[(byte & 0xf) as usize];
+    }
+}
+
+// Parses hex digits into the `out`, which must be exactly half as long as the `hex`.
+fn parse_hex(hex: &[u8], out: &mut [u8]) -> Result<(), ConfigError> {
+    if hex.len() != 2 * out.len() {
+        return Err(ConfigError::InvalidArgument);
+    }
+
+    for (byte, digits) in out.iter_mut().zip(hex.chunks_exact(2)) {
+        let digit = |d: u8| match d {
+            b'0'..=b'9' => Ok(d - b'0'),
+            b'a'..=b'f' => Ok(d - b'a' + 10),
+            b'A'..=b'F' => Ok(d - b'A' + 10),
+            _ => Err(ConfigError::InvalidArgument),
+        };
+
+        *byte = (digit(digits[0])? << 4) | digit(digits[1])?;
+    }
+
+    Ok(())
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::sim::SimEeprom;
+
+    #[test]
+    fn test_config_backup() {
+        let mut eeprom = SimEeprom::new();
+        eeprom.write(0x10, &[0x12, 0xab]).unwrap();
+
+        let mut dump = ConfigDump::new();
+        let mut line = [0u8; LOAD_LINE_LEN];
+        let mut copy = SimEeprom::new();
+        copy.write(0, &[0u8; CONFIG_LEN]).unwrap();
+
+        assert_eq!(dump.next_line(&eeprom, &mut line), Some(LOAD_LINE_LEN));
+        assert!(line.starts_with(b"load 0000 ffff"));
+
+        let len = dump.next_line(&eeprom, &mut line).unwrap();
+        assert_eq!(
+            &line[..len],
+            b"load 0010 12abffffffffffffffffffffffffffff\r\n"
+        );
+        assert_eq!(load_line(&mut copy, &line[5..len]), Ok(0x10));
+
+        let mut lines = 2;
+        while let Some(len) = dump.next_line(&eeprom, &mut line) {
+            load_line(&mut copy, &line[5..len]).unwrap();
+            lines += 1;
+        }
+        assert!(dump.is_done());
+        assert_eq!(lines, CONFIG_LEN.div_ceil(LOAD_LINE_BYTES));
+        assert_eq!(
+            copy.read(0x10, CONFIG_LEN - 0x10),
+            eeprom.read(0x10, CONFIG_LEN - 0x10)
+        );
+
+        assert_eq!(
+            load_line(&mut copy, b"0010 12a"),
+            Err(ConfigError::InvalidArgument)
+        );
+        assert_eq!(
+            load_line(&mut copy, b"ffff 12"),
+            Err(ConfigError::InvalidArgument)
+        );
+        assert_eq!(
+            load_line(&mut copy, b"0010 zz"),
+            Err(ConfigError::InvalidArgument)
+        );
+    }
+}
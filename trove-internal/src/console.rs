@@ -0,0 +1,284 @@
+//! Serial command console.
+//!
+//! The USB-serial debug port doubles as a command console, so diagnostics can be read with any
+//! terminal program instead of an external UART adapter. The host types a command followed by
+//! Enter, and the keyboard answers with a line of text:
+//!
+//! ```text
+//! > diag
+//! diag 0 0 1 0 0 0
+//! > timing
+//! timing scan 118 max 302 isr 41
+//! ```
+//!
+//! Received bytes are collected with a [LineBuffer], and complete lines are answered by
+//! [run_command]. The port also speaks the [focus](crate::focus) protocol, which provides the
+//! `help` and `version` commands.
+//!
+//! The `dump` and `load` commands back up and restore the EEPROM configuration, see
+//! [config_backup](crate::config_backup). They need the EEPROM, so the caller runs them.
+
+use crate::{diagnostics, health, matrix_test, timing};
+
+/// Maximum length of a console command line, longer lines are cut.
+///
+/// Fits a `load` line of a [config_backup](crate::config_backup) dump.
+pub const CONSOLE_LINE_LEN: usize = 48;
+/// Maximum length of a console response.
+pub const CONSOLE_RESPONSE_LEN: usize = 64;
+
+/// Names of the console commands, as typed by the host.
+pub const CONSOLE_COMMANDS: [&str; 7] = [
+    "diag", "health", "timing", "matrix", "reset", "dump", "load",
+];
+
+/// Represents a console command.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConsoleCommand {
+    /// Print the [diagnostics] counters, in [Diagnostic](diagnostics::Diagnostic) order: `diag`.
+    Diagnostics,
+    /// Print the scan frequency and report counters from [health]: `health`.
+    Health,
+    /// Print the scan timing measurements from [timing]: `timing`.
+    Timing,
+    /// Toggle [matrix_test] mode: `matrix`.
+    MatrixTest,
+    /// Reset the [diagnostics] counters and the longest [timing] measurements: `reset`.
+    Reset,
+    /// Print the EEPROM configuration as `load` lines: `dump`.
+    Dump,
+    /// Write a line of the EEPROM configuration: `load <offset> <hex bytes>`.
+    Load,
+}
+
+impl ConsoleCommand {
+    /// Parses a console command `line`, ignoring surrounding whitespace.
+    ///
+    /// Returns `None` for unknown commands.
+    pub fn parse(line: &[u8]) -> Option<Self> {
+        match line.trim_ascii() {
+            b"diag" => Some(Self::Diagnostics),
+            b"health" => Some(Self::Health),
+            b"timing" => Some(Self::Timing),
+            b"matrix" => Some(Self::MatrixTest),
+            b"reset" => Some(Self::Reset),
+            b"dump" => Some(Self::Dump),
+            line if line == b"load" || line.starts_with(b"load ") => Some(Self::Load),
+            _ => None,
+        }
+    }
+}
+
+/// Collects bytes received from the host into command lines.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LineBuffer {
+    buf: [u8; CONSOLE_LINE_LEN],
+    len: usize,
+    complete: bool,
+}
+
+impl LineBuffer {
+    /// Creates a new (empty) [LineBuffer].
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; CONSOLE_LINE_LEN],
+            len: 0,
+            complete: false,
+        }
+    }
+
+    /// Pushes a received byte, and returns whether it completed a line.
+    ///
+    /// Lines end at a carriage return or line feed. The next byte after a complete line starts
+    /// a new line.
+    pub fn push(&mut self, byte: u8) -> bool {
+        if self.complete {
+            self.clear();
+        }
+
+        match byte {
+            b'\r' | b'\n' => self.complete = true,
+            _ if self.len < CONSOLE_LINE_LEN => {
+                self.buf[self.len] = byte;
+                self.len += 1;
+            }
+            // the rest of an overlong line is dropped
+            _ => (),
+        }
+
+        self.complete
+    }
+
+    /// Gets the received line.
+    pub fn line(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    /// Clears the received line.
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.complete = false;
+    }
+}
+
+impl Default for LineBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Runs a console command `line`, and writes the response into the `buf`.
+///
+/// Returns the length of the response, which is `0` for blank lines. The `dump` and `load`
+/// commands are answered as unavailable, see [config_backup](crate::config_backup) instead.
+pub fn run_command(line: &[u8], buf: &mut [u8; CONSOLE_RESPONSE_LEN]) -> usize {
+    if line.trim_ascii().is_empty() {
+        return 0;
+    }
+
+    let mut out = ResponseWriter { buf, len: 0 };
+
+    match ConsoleCommand::parse(line) {
+        Some(ConsoleCommand::Diagnostics) => {
+            out.put(b"diag");
+
+            for count in diagnostics::counts() {
+                out.put(b" ");
+                out.put_u16(count);
+            }
+        }
+        Some(ConsoleCommand::Health) => {
+            let report = health::health_report();
+
+            out.put(b"health rate ");
+            out.put_u16(report.scan_rate());
+            out.put(b" sent ");
+            out.put_u16(report.reports_sent());
+            out.put(b" failed ");
+            out.put_u16(report.reports_failed());
+        }
+        Some(ConsoleCommand::Timing) => {
+            let report = timing::timing_report();
+
+            out.put(b"timing scan ");
+            out.put_u16(report.last_scan_us());
+            out.put(b" max ");
+            out.put_u16(report.max_scan_us());
+            out.put(b" isr ");
+            out.put_u16(report.max_isr_us());
+        }
+        Some(ConsoleCommand::MatrixTest) => {
+            out.put(if matrix_test::toggle_matrix_test() {
+                b"matrix on"
+            } else {
+                b"matrix off"
+            });
+        }
+        Some(ConsoleCommand::Reset) => {
+            diagnostics::reset();
+            timing::reset_max();
+            out.put(b"reset");
+        }
+        Some(ConsoleCommand::Dump | ConsoleCommand::Load) => out.put(b"no config storage"),
+        None => out.put(b"unknown command, try help"),
+    }
+
+    out.put(b"\r\n");
+    out.len
+}
+
+// Writes response text, cutting anything that does not fit.
+struct ResponseWriter<'a> {
+    buf: &'a mut [u8; CONSOLE_RESPONSE_LEN],
+    len: usize,
+}
+
+impl ResponseWriter<'_> {
+    fn put(&mut self, bytes: &[u8]) {
+        let len = bytes.len().min(CONSOLE_RESPONSE_LEN - self.len);
+
+        self.buf[self.len..self.len + len].copy_from_slice(&bytes[..len]);
+        self.len += len;
+    }
+
+    // Writes a `u16` as decimal digits, without leading zeros.
+    fn put_u16(&mut self, val: u16) {
+        let mut digits = [0u8; 5];
+        let mut start = digits.len();
+        let mut val = val;
+
+        loop {
+            start -= 1;
+            digits[start] = b'0' + (val % 10) as u8;
+            val /= 10;
+
+            if val == 0 {
+                break;
+            }
+        }
+
+        self.put(&digits[start..]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_line_buffer() {
+        let mut line = LineBuffer::new();
+
+        assert!(!b"diag".iter().any(|&b| line.push(b)));
+        assert!(line.push(b'\r'));
+        assert_eq!(line.line(), b"diag");
+
+        // CRLF line endings complete an empty line
+        assert!(line.push(b'\n'));
+        assert_eq!(line.line(), b"");
+
+        // overlong lines are cut
+        for _ in 0..CONSOLE_LINE_LEN + 8 {
+            line.push(b'x');
+        }
+        assert!(line.push(b'\n'));
+        assert_eq!(line.line().len(), CONSOLE_LINE_LEN);
+    }
+
+    #[test]
+    fn test_console_command() {
+        assert_eq!(
+            ConsoleCommand::parse(b"diag"),
+            Some(ConsoleCommand::Diagnostics)
+        );
+        assert_eq!(
+            ConsoleCommand::parse(b"  timing "),
+            Some(ConsoleCommand::Timing)
+        );
+        assert_eq!(ConsoleCommand::parse(b"Diag"), None);
+        assert_eq!(
+            ConsoleCommand::parse(b"load 0010 ff00"),
+            Some(ConsoleCommand::Load)
+        );
+        assert_eq!(ConsoleCommand::parse(b"loader"), None);
+
+        // every listed command parses
+        assert!(CONSOLE_COMMANDS
+            .iter()
+            .all(|name| ConsoleCommand::parse(name.as_bytes()).is_some()));
+    }
+
+    #[test]
+    fn test_run_command() {
+        let mut buf = [0u8; CONSOLE_RESPONSE_LEN];
+
+        let len = run_command(b"diag", &mut buf);
+        assert!(buf[..len].starts_with(b"diag "));
+        assert!(buf[..len].ends_with(b"\r\n"));
+
+        let len = run_command(b"nope", &mut buf);
+        assert_eq!(&buf[..len], b"unknown command, try help\r\n");
+
+        assert_eq!(run_command(b" ", &mut buf), 0);
+    }
+}
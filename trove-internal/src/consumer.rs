@@ -0,0 +1,59 @@
+//! Consumer Control keys.
+//!
+//! Media, browser navigation, and application-launch keys are Consumer page usages. Hosts only
+//! handle them reliably from a Consumer Control interface, so they are sent there instead of in
+//! the keyboard reports, one 16-bit usage per report.
+//!
+//! Consumer usages do not fit in the 8-bit keycodes of a keymap, so the keymap uses the
+//! [CONSUMER0](crate::layers::CONSUMER0) - [CONSUMER10](crate::layers::CONSUMER10) keycodes, and
+//! [consumer_usage] looks up the usage to send.
+
+use usbd_hid::descriptor::MediaKey;
+
+use crate::layers::{CONSUMER0, NUM_CONSUMER_KEYS};
+
+/// Consumer usages of the Consumer Control keycodes, starting at [CONSUMER0].
+pub const CONSUMER_USAGES: [u16; NUM_CONSUMER_KEYS as usize] = [
+    MediaKey::PlayPause as u16,
+    MediaKey::NextTrack as u16,
+    MediaKey::PrevTrack as u16,
+    MediaKey::Stop as u16,
+    MediaKey::Mute as u16,
+    MediaKey::VolumeIncrement as u16,
+    MediaKey::VolumeDecrement as u16,
+    // AC Back
+    0x0224,
+    // AC Forward
+    0x0225,
+    // AC Home
+    0x0223,
+    // AL Calculator
+    0x0192,
+];
+
+/// Gets the Consumer usage of a Consumer Control `key`.
+///
+/// Returns `None` for any other key.
+pub fn consumer_usage(key: u8) -> Option<u16> {
+    CONSUMER_USAGES
+        .get(key.wrapping_sub(CONSUMER0) as usize)
+        .copied()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::{A, CALC, CONSUMER10, MD_MUTE, PLAY_PS, WWW_BACK};
+
+    #[test]
+    fn test_consumer_usage() {
+        assert_eq!(consumer_usage(PLAY_PS), Some(0xcd));
+        assert_eq!(consumer_usage(MD_MUTE), Some(0xe2));
+        assert_eq!(consumer_usage(WWW_BACK), Some(0x0224));
+        assert_eq!(consumer_usage(CALC), Some(0x0192));
+
+        assert_eq!(consumer_usage(CONSUMER0 - 1), None);
+        assert_eq!(consumer_usage(CONSUMER10 + 1), None);
+        assert_eq!(consumer_usage(A), None);
+    }
+}
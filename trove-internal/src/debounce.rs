@@ -0,0 +1,609 @@
+//! Key switch debouncing.
+//!
+//! Mechanical switches "bounce" between open and closed for a short time when pressed or
+//! released. Debouncers filter the raw [RowState] samples from each matrix scan, so a single key
+//! press does not produce multiple reports.
+//!
+//! Different switch types need different strategies, so the debouncer is selected per board with
+//! the [Debounce] trait:
+//!
+//! - [SymmetricDefer]: changes are reported once the whole row has been stable for the debounce
+//!   window (the default).
+//! - [EagerPress]: presses are reported immediately, releases after the debounce window.
+//! - [Integrator]: changes are reported after four consistent samples.
+//! - [NoDebounce]: samples are reported as-is, for switches that do not bounce.
+//!
+//! The debounce window is measured in milliseconds rather than scans, so it does not depend on
+//! the scan rate. Users with chattery switches can raise it, and fast typists can lower it.
+//!
+//! Debouncers can be checked against recorded bounce traces on the host with the
+//! [debounce_sim](crate::debounce_sim) module.
+
+use core::ops::{BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Not};
+
+/// Maximum number of columns of in a [RowState].
+pub const MAX_COLS: usize = 16;
+
+/// Default debounce window in milliseconds.
+pub const DEFAULT_DEBOUNCE_MS: u16 = 5;
+
+bitfield! {
+    /// Activated status for a row of keys.
+    ///
+    /// Each row-column key is represented by a single bit to save space.
+    #[derive(Clone, Copy, Debug, Default, PartialEq)]
+    pub struct RowState(u16);
+
+    /// Getter/setter for column 0
+    pub col0, set_col0: 0;
+    /// Getter/setter for column 1
+    pub col1, set_col1: 1;
+    /// Getter/setter for column 2
+    pub col2, set_col2: 2;
+    /// Getter/setter for column 3
+    pub col3, set_col3: 3;
+    /// Getter/setter for column 4
+    pub col4, set_col4: 4;
+    /// Getter/setter for column 5
+    pub col5, set_col5: 5;
+    /// Getter/setter for column 6
+    pub col6, set_col6: 6;
+    /// Getter/setter for column 7
+    pub col7, set_col7: 7;
+    /// Getter/setter for column 8
+    pub col8, set_col8: 8;
+    /// Getter/setter for column 9
+    pub col9, set_col9: 9;
+    /// Getter/setter for column 10
+    pub col10, set_col10: 10;
+    /// Getter/setter for column 11
+    pub col11, set_col11: 11;
+    /// Getter/setter for column 12
+    pub col12, set_col12: 12;
+    /// Getter/setter for column 13
+    pub col13, set_col13: 13;
+    /// Getter/setter for column 14
+    pub col14, set_col14: 14;
+    /// Getter/setter for column 15
+    pub col15, set_col15: 15;
+}
+
+impl RowState {
+    /// Creates a new [RowState].
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    /// Gets the underlying integer representation of the [RowState].
+    pub const fn as_inner(&self) -> u16 {
+        self.0
+    }
+
+    /// Creates a new [RowState] from a `u16`.
+    pub const fn from_u16(val: u16) -> Self {
+        Self(val)
+    }
+
+    /// Gets the column activation state.
+    pub fn column(&self, index: usize) -> bool {
+        match index % MAX_COLS {
+            0 => self.col0(),
+            1 => self.col1(),
+            2 => self.col2(),
+            3 => self.col3(),
+            4 => self.col4(),
+            5 => self.col5(),
+            6 => self.col6(),
+            7 => self.col7(),
+            8 => self.col8(),
+            9 => self.col9(),
+            10 => self.col10(),
+            11 => self.col11(),
+            12 => self.col12(),
+            13 => self.col13(),
+            14 => self.col14(),
+            _ => self.col15(),
+        }
+    }
+
+    /// Sets the column activation state.
+    pub fn set_column(&mut self, index: usize, val: bool) {
+        match index % MAX_COLS {
+            0 => self.set_col0(val),
+            1 => self.set_col1(val),
+            2 => self.set_col2(val),
+            3 => self.set_col3(val),
+            4 => self.set_col4(val),
+            5 => self.set_col5(val),
+            6 => self.set_col6(val),
+            7 => self.set_col7(val),
+            8 => self.set_col8(val),
+            9 => self.set_col9(val),
+            10 => self.set_col10(val),
+            11 => self.set_col11(val),
+            12 => self.set_col12(val),
+            13 => self.set_col13(val),
+            14 => self.set_col14(val),
+            _ => self.set_col15(val),
+        }
+    }
+
+    /// Gets whether the [RowState] has active columns.
+    pub const fn is_active(&self) -> bool {
+        self.0 != 0
+    }
+
+    /// Gets whether the [RowState] has no active columns.
+    pub const fn is_inactive(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl From<u16> for RowState {
+    fn from(val: u16) -> Self {
+        Self::from_u16(val)
+    }
+}
+
+impl From<&u16> for RowState {
+    fn from(val: &u16) -> Self {
+        (*val).into()
+    }
+}
+
+impl From<&RowState> for u16 {
+    fn from(val: &RowState) -> Self {
+        val.as_inner()
+    }
+}
+
+impl From<RowState> for u16 {
+    fn from(val: RowState) -> Self {
+        (&val).into()
+    }
+}
+
+impl BitAnd for RowState {
+    type Output = RowState;
+
+    fn bitand(self, rhs: Self) -> Self::Output {
+        (self.0 & rhs.0).into()
+    }
+}
+
+impl BitAndAssign for RowState {
+    fn bitand_assign(&mut self, rhs: Self) {
+        self.0 &= rhs.0;
+    }
+}
+
+impl BitOr for RowState {
+    type Output = RowState;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        (self.0 | rhs.0).into()
+    }
+}
+
+impl BitOrAssign for RowState {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl BitXor for RowState {
+    type Output = RowState;
+
+    fn bitxor(self, rhs: Self) -> Self::Output {
+        (self.0 ^ rhs.0).into()
+    }
+}
+
+impl BitXorAssign for RowState {
+    fn bitxor_assign(&mut self, rhs: Self) {
+        self.0 ^= rhs.0;
+    }
+}
+
+impl Not for RowState {
+    type Output = RowState;
+
+    fn not(self) -> Self::Output {
+        (!self.0).into()
+    }
+}
+
+/// Debounce algorithm for the [RowState] samples of a single row.
+pub trait Debounce {
+    /// Debounce the sampled [RowState].
+    ///
+    /// Returns the columns that changed debounced state.
+    fn debounce(&mut self, sample: RowState, now_ms: u16) -> RowState;
+
+    /// Gets the debounced [RowState].
+    fn debounced(&self) -> RowState;
+
+    /// Sets the debounce window in milliseconds.
+    ///
+    /// Algorithms without a time window ignore it.
+    fn set_debounce_ms(&mut self, _val: u16) {}
+}
+
+/// Integrating debouncer using a two-bit vertical counter for each key.
+///
+/// A key changes state after four consecutive samples that differ from the debounced state.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Integrator {
+    /// Debounce counter 0 bits for a given [RowState].
+    db0: RowState,
+    /// Debounce counter 1 bits for a given [RowState].
+    db1: RowState,
+    /// Debounced [RowState].
+    debounced: RowState,
+}
+
+impl Integrator {
+    /// Creates a new [Integrator] state.
+    pub const fn new() -> Self {
+        Self {
+            db0: RowState::new(),
+            db1: RowState::new(),
+            debounced: RowState::new(),
+        }
+    }
+
+    /// Gets the debounce counter bit zero.
+    pub const fn db0(&self) -> RowState {
+        self.db0
+    }
+
+    /// Gets the debounce counter bit one.
+    pub const fn db1(&self) -> RowState {
+        self.db1
+    }
+}
+
+impl Debounce for Integrator {
+    fn debounce(&mut self, sample: RowState, _now_ms: u16) -> RowState {
+        // Use xor to detect changes from last stable state:
+        // if a key has changed, its bit will be 1, otherwise 0
+        let delta = sample ^ self.debounced;
+
+        // Increment counters and reset any unchanged bits:
+        // increment bit 1 for all changed keys
+        self.db1 = (self.db1 ^ self.db0) & delta;
+        // increment bit 0 for all changed keys
+        self.db0 = !self.db0 & delta;
+
+        // Calculate returned change set: if delta is still true
+        // and the counter has wrapped back to 0, the key is changed.
+        let changes = !(!delta | self.db0 | self.db1);
+        // Update state: in this case use xor to flip any bit that is true in changes.
+        self.debounced ^= changes;
+
+        changes
+    }
+
+    fn debounced(&self) -> RowState {
+        self.debounced
+    }
+}
+
+/// Per-key debouncer that reports presses immediately.
+///
+/// Releases are reported after a key has been sampled released for the debounce window, which
+/// filters the bounce at the end of a press without adding latency to the start of one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EagerPress {
+    debounce_ms: u16,
+    releasing: RowState,
+    released_at: [u16; MAX_COLS],
+    debounced: RowState,
+}
+
+impl EagerPress {
+    /// Creates a new [EagerPress] debouncer.
+    pub const fn new() -> Self {
+        Self {
+            debounce_ms: DEFAULT_DEBOUNCE_MS,
+            releasing: RowState::new(),
+            released_at: [0; MAX_COLS],
+            debounced: RowState::new(),
+        }
+    }
+
+    /// Gets the debounce window in milliseconds.
+    pub const fn debounce_ms(&self) -> u16 {
+        self.debounce_ms
+    }
+
+    /// Builder function that sets the debounce window in milliseconds.
+    pub fn with_debounce_ms(mut self, val: u16) -> Self {
+        self.set_debounce_ms(val);
+        self
+    }
+}
+
+impl Default for EagerPress {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debounce for EagerPress {
+    fn debounce(&mut self, sample: RowState, now_ms: u16) -> RowState {
+        let mut changes = RowState::new();
+
+        for (col, released_at) in self.released_at.iter_mut().enumerate() {
+            if sample.column(col) {
+                self.releasing.set_column(col, false);
+
+                if !self.debounced.column(col) {
+                    changes.set_column(col, true);
+                }
+            } else if self.debounced.column(col) {
+                if !self.releasing.column(col) {
+                    self.releasing.set_column(col, true);
+                    *released_at = now_ms;
+                }
+
+                if now_ms.wrapping_sub(*released_at) >= self.debounce_ms {
+                    self.releasing.set_column(col, false);
+                    changes.set_column(col, true);
+                }
+            }
+        }
+
+        self.debounced ^= changes;
+
+        changes
+    }
+
+    fn debounced(&self) -> RowState {
+        self.debounced
+    }
+
+    fn set_debounce_ms(&mut self, val: u16) {
+        self.debounce_ms = val;
+    }
+}
+
+/// Row-wide debouncer that defers all changes until the row is stable.
+///
+/// Changes are reported once the row has been sampled unchanged for the debounce window.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SymmetricDefer {
+    debounce_ms: u16,
+    changed_at: u16,
+    last_sample: RowState,
+    debounced: RowState,
+}
+
+impl SymmetricDefer {
+    /// Creates a new [SymmetricDefer] debouncer.
+    pub const fn new() -> Self {
+        Self {
+            debounce_ms: DEFAULT_DEBOUNCE_MS,
+            changed_at: 0,
+            last_sample: RowState::new(),
+            debounced: RowState::new(),
+        }
+    }
+
+    /// Gets the debounce window in milliseconds.
+    pub const fn debounce_ms(&self) -> u16 {
+        self.debounce_ms
+    }
+
+    /// Builder function that sets the debounce window in milliseconds.
+    pub fn with_debounce_ms(mut self, val: u16) -> Self {
+        self.set_debounce_ms(val);
+        self
+    }
+}
+
+impl Default for SymmetricDefer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debounce for SymmetricDefer {
+    fn debounce(&mut self, sample: RowState, now_ms: u16) -> RowState {
+        if sample != self.last_sample {
+            self.last_sample = sample;
+            self.changed_at = now_ms;
+        }
+
+        if sample != self.debounced && now_ms.wrapping_sub(self.changed_at) >= self.debounce_ms {
+            let changes = sample ^ self.debounced;
+            self.debounced = sample;
+            changes
+        } else {
+            RowState::new()
+        }
+    }
+
+    fn debounced(&self) -> RowState {
+        self.debounced
+    }
+
+    fn set_debounce_ms(&mut self, val: u16) {
+        self.debounce_ms = val;
+    }
+}
+
+/// Pass-through debouncer, for switches that do not bounce (e.g. optical or hall-effect).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct NoDebounce {
+    debounced: RowState,
+}
+
+impl NoDebounce {
+    /// Creates a new [NoDebounce] debouncer.
+    pub const fn new() -> Self {
+        Self {
+            debounced: RowState::new(),
+        }
+    }
+}
+
+impl Debounce for NoDebounce {
+    fn debounce(&mut self, sample: RowState, _now_ms: u16) -> RowState {
+        let changes = sample ^ self.debounced;
+        self.debounced = sample;
+        changes
+    }
+
+    fn debounced(&self) -> RowState {
+        self.debounced
+    }
+}
+
+/// Represents the previous, current, and debounced state for a given row.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DebounceRowState<D = SymmetricDefer> {
+    /// Previous [RowState].
+    previous: RowState,
+    /// Current [RowState].
+    current: RowState,
+    /// [Debounce]r for this [RowState].
+    debouncer: D,
+}
+
+impl<D: Debounce + Default> DebounceRowState<D> {
+    /// Creates a new [DebounceRowState].
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl<D: Debounce + Copy> DebounceRowState<D> {
+    /// Gets the previous [RowState].
+    pub const fn previous(&self) -> RowState {
+        self.previous
+    }
+
+    /// Sets the previous [RowState].
+    pub fn set_previous(&mut self, state: RowState) {
+        self.previous = state;
+    }
+
+    /// Builder function that sets the previous [RowState].
+    pub fn with_previous(mut self, state: RowState) -> Self {
+        self.set_previous(state);
+        self
+    }
+
+    /// Gets the current [RowState].
+    pub const fn current(&self) -> RowState {
+        self.current
+    }
+
+    /// Sets the current [RowState].
+    pub fn set_current(&mut self, state: RowState) {
+        self.current = state;
+    }
+
+    /// Builder function that sets the current [RowState].
+    pub fn with_current(mut self, state: RowState) -> Self {
+        self.set_current(state);
+        self
+    }
+
+    /// Gets the `[Debounce]r` for the [RowState].
+    pub const fn debouncer(&self) -> D {
+        self.debouncer
+    }
+
+    /// Sets the `[Debounce]r` for the [RowState].
+    pub fn set_debouncer(&mut self, state: D) {
+        self.debouncer = state;
+    }
+
+    /// Builder function that sets the `[Debounce]r` for the [RowState].
+    pub fn with_debouncer(mut self, state: D) -> Self {
+        self.set_debouncer(state);
+        self
+    }
+
+    /// Gets a mutable reference to the `[Debounce]r` for the [RowState].
+    pub fn debouncer_mut(&mut self) -> &mut D {
+        &mut self.debouncer
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRESSED: RowState = RowState::from_u16(0b1);
+    const RELEASED: RowState = RowState::new();
+
+    fn run<D: Debounce>(debouncer: &mut D, samples: &[RowState]) -> [bool; 8] {
+        let mut out = [false; 8];
+
+        // one sample per millisecond
+        for (now, (o, &sample)) in out.iter_mut().zip(samples.iter()).enumerate() {
+            debouncer.debounce(sample, now as u16);
+            *o = debouncer.debounced().column(0);
+        }
+
+        out
+    }
+
+    #[test]
+    fn test_debouncers() {
+        // bouncy press, held, then a bouncy release
+        let samples = [
+            PRESSED, RELEASED, PRESSED, PRESSED, PRESSED, PRESSED, RELEASED, PRESSED,
+        ];
+
+        assert_eq!(
+            run(&mut Integrator::new(), &samples),
+            [false, false, false, false, false, true, true, true]
+        );
+        assert_eq!(
+            run(&mut EagerPress::new().with_debounce_ms(2), &samples),
+            [true, true, true, true, true, true, true, true]
+        );
+        assert_eq!(
+            run(&mut SymmetricDefer::new().with_debounce_ms(3), &samples),
+            [false, false, false, false, false, true, true, true]
+        );
+        assert_eq!(
+            run(&mut NoDebounce::new(), &samples),
+            [true, false, true, true, true, true, false, true]
+        );
+
+        // releases are deferred by the eager debouncer
+        let mut eager = EagerPress::new().with_debounce_ms(2);
+        assert_eq!(eager.debounce(PRESSED, 0), PRESSED);
+        assert_eq!(eager.debounce(RELEASED, 1), RELEASED);
+        assert_eq!(eager.debounce(RELEASED, 2), RELEASED);
+        assert_eq!(eager.debounce(RELEASED, 3), PRESSED);
+        assert!(eager.debounced().is_inactive());
+    }
+
+    #[test]
+    fn test_eager_press_release_bounce() {
+        let mut eager = EagerPress::new().with_debounce_ms(3);
+
+        // the press is reported on the first sample
+        assert_eq!(eager.debounce(PRESSED, 0), PRESSED);
+
+        // release bounce restarts the debounce window
+        assert_eq!(eager.debounce(RELEASED, 10), RELEASED);
+        assert_eq!(eager.debounce(PRESSED, 11), RELEASED);
+        assert_eq!(eager.debounce(RELEASED, 12), RELEASED);
+        assert_eq!(eager.debounce(RELEASED, 14), RELEASED);
+        assert!(eager.debounced().column(0));
+
+        // the release is reported once the key stays released for the window
+        assert_eq!(eager.debounce(RELEASED, 15), PRESSED);
+        assert!(eager.debounced().is_inactive());
+
+        // the next press is reported immediately again
+        assert_eq!(eager.debounce(PRESSED, 16), PRESSED);
+    }
+}
@@ -0,0 +1,256 @@
+//! Debounce simulation with recorded or synthetic bounce traces.
+//!
+//! Feeds a [BounceTrace] of per-sample [RowState]s into a [Debounce]r, and yields the debounced
+//! [Transition]s, so debounce changes can be validated on the host without hardware.
+//!
+//! Traces can be captured from a board (e.g. with the [matrix_test](crate::matrix_test) mode),
+//! or drawn per column with [draw_column]:
+//!
+//! ```text
+//! __-_--------_-_____
+//! ```
+//!
+//! where `-` is a pressed sample, and `_` is a released sample.
+
+use crate::debounce::{Debounce, RowState, MAX_COLS};
+
+/// Character for a pressed sample in a drawn trace.
+pub const PRESSED_SAMPLE: char = '-';
+/// Character for a released sample in a drawn trace.
+pub const RELEASED_SAMPLE: char = '_';
+
+/// Represents a debounced key transition.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transition {
+    col: u8,
+    pressed: bool,
+    time_ms: u16,
+}
+
+impl Transition {
+    /// Creates a new [Transition].
+    pub const fn new(col: u8, pressed: bool, time_ms: u16) -> Self {
+        Self {
+            col,
+            pressed,
+            time_ms,
+        }
+    }
+
+    /// Gets the column of the key.
+    pub const fn col(&self) -> u8 {
+        self.col
+    }
+
+    /// Gets whether the key was pressed, or released.
+    pub const fn pressed(&self) -> bool {
+        self.pressed
+    }
+
+    /// Gets the time of the sample that reported the transition, in milliseconds.
+    pub const fn time_ms(&self) -> u16 {
+        self.time_ms
+    }
+}
+
+/// Represents a row of [RowState] samples, taken at a fixed period.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BounceTrace<'a> {
+    samples: &'a [RowState],
+    period_ms: u16,
+}
+
+impl<'a> BounceTrace<'a> {
+    /// Creates a new [BounceTrace], sampled every millisecond.
+    pub const fn new(samples: &'a [RowState]) -> Self {
+        Self {
+            samples,
+            period_ms: 1,
+        }
+    }
+
+    /// Gets the samples.
+    pub const fn samples(&self) -> &'a [RowState] {
+        self.samples
+    }
+
+    /// Gets the sample period in milliseconds.
+    pub const fn period_ms(&self) -> u16 {
+        self.period_ms
+    }
+
+    /// Sets the sample period in milliseconds.
+    pub fn set_period_ms(&mut self, val: u16) {
+        self.period_ms = val;
+    }
+
+    /// Builder function that sets the sample period in milliseconds.
+    pub fn with_period_ms(mut self, val: u16) -> Self {
+        self.set_period_ms(val);
+        self
+    }
+
+    /// Feeds the samples into the [Debounce]r, and yields the debounced [Transition]s.
+    pub fn run<'d, D: Debounce>(&self, debouncer: &'d mut D) -> Transitions<'a, 'd, D> {
+        Transitions {
+            trace: *self,
+            debouncer,
+            sample: 0,
+            changes: RowState::new(),
+            col: MAX_COLS,
+        }
+    }
+}
+
+/// Iterator over the debounced [Transition]s of a [BounceTrace].
+pub struct Transitions<'a, 'd, D: Debounce> {
+    trace: BounceTrace<'a>,
+    debouncer: &'d mut D,
+    sample: usize,
+    changes: RowState,
+    col: usize,
+}
+
+impl<D: Debounce> Transitions<'_, '_, D> {
+    /// Gets the time of the last sample fed into the [Debounce]r, in milliseconds.
+    fn time_ms(&self) -> u16 {
+        (self.sample.saturating_sub(1) as u16).wrapping_mul(self.trace.period_ms)
+    }
+}
+
+impl<D: Debounce> Iterator for Transitions<'_, '_, D> {
+    type Item = Transition;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            // report the changes of the last sample, one column at a time
+            while self.col < MAX_COLS {
+                let col = self.col;
+                self.col += 1;
+
+                if self.changes.column(col) {
+                    return Some(Transition::new(
+                        col as u8,
+                        self.debouncer.debounced().column(col),
+                        self.time_ms(),
+                    ));
+                }
+            }
+
+            let sample = *self.trace.samples.get(self.sample)?;
+            let now_ms = (self.sample as u16).wrapping_mul(self.trace.period_ms);
+
+            self.sample += 1;
+            self.changes = self.debouncer.debounce(sample, now_ms);
+            self.col = 0;
+        }
+    }
+}
+
+/// Draws the samples of a `col` from a trace of [PRESSED_SAMPLE] and [RELEASED_SAMPLE]
+/// characters.
+///
+/// Other characters are skipped, so traces can be spaced for readability. The other columns of
+/// the samples are kept, so several columns can be drawn into the same buffer.
+///
+/// Returns the number of samples drawn, limited to the length of the buffer.
+pub fn draw_column(trace: &str, col: usize, buf: &mut [RowState]) -> usize {
+    let levels = trace.chars().filter_map(|c| match c {
+        PRESSED_SAMPLE => Some(true),
+        RELEASED_SAMPLE => Some(false),
+        _ => None,
+    });
+
+    let mut len = 0;
+
+    for (sample, pressed) in buf.iter_mut().zip(levels) {
+        sample.set_column(col, pressed);
+        len += 1;
+    }
+
+    len
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::debounce::{EagerPress, Integrator, NoDebounce, SymmetricDefer};
+
+    const MAX_SAMPLES: usize = 32;
+
+    // bouncy press, held, then a bouncy release
+    const BOUNCE: &str = "__-_-------- -_-____";
+
+    fn transitions<D: Debounce>(
+        debouncer: &mut D,
+        trace: BounceTrace,
+    ) -> ([Option<Transition>; 8], usize) {
+        let mut out = [None; 8];
+        let mut len = 0;
+
+        for (o, transition) in out.iter_mut().zip(trace.run(debouncer)) {
+            *o = Some(transition);
+            len += 1;
+        }
+
+        (out, len)
+    }
+
+    #[test]
+    fn test_draw_column() {
+        let mut buf = [RowState::new(); MAX_SAMPLES];
+
+        assert_eq!(draw_column("_-- _", 0, &mut buf), 4);
+        assert_eq!(draw_column("-_", 3, &mut buf), 2);
+
+        assert_eq!(buf[0].as_inner(), 0b1000);
+        assert_eq!(buf[1].as_inner(), 0b0001);
+        assert_eq!(buf[2].as_inner(), 0b0001);
+        assert_eq!(buf[3].as_inner(), 0b0000);
+
+        // the trace is cut to the buffer
+        assert_eq!(draw_column("----", 1, &mut buf[..2]), 2);
+    }
+
+    #[test]
+    fn test_bounce_traces() {
+        let mut buf = [RowState::new(); MAX_SAMPLES];
+        let len = draw_column(BOUNCE, 0, &mut buf);
+        let trace = BounceTrace::new(&buf[..len]);
+
+        // every bounce is reported without debouncing
+        assert_eq!(transitions(&mut NoDebounce::new(), trace).1, 6);
+
+        let (out, len) = transitions(&mut EagerPress::new().with_debounce_ms(3), trace);
+        assert_eq!(len, 2);
+        assert_eq!(out[0], Some(Transition::new(0, true, 2)));
+        assert_eq!(out[1], Some(Transition::new(0, false, 18)));
+
+        let (out, len) = transitions(&mut SymmetricDefer::new().with_debounce_ms(3), trace);
+        assert_eq!(len, 2);
+        assert_eq!(out[0], Some(Transition::new(0, true, 7)));
+        assert_eq!(out[1], Some(Transition::new(0, false, 18)));
+
+        let (out, len) = transitions(&mut Integrator::new(), trace);
+        assert_eq!(len, 2);
+        assert_eq!(out[0], Some(Transition::new(0, true, 7)));
+        assert_eq!(out[1], Some(Transition::new(0, false, 18)));
+    }
+
+    #[test]
+    fn test_sample_period() {
+        let mut buf = [RowState::new(); MAX_SAMPLES];
+        let len = draw_column("_-_------___", 2, &mut buf);
+        draw_column("___-----", 5, &mut buf);
+
+        // samples are timed by the period, so the window is filled in fewer samples
+        let trace = BounceTrace::new(&buf[..len]).with_period_ms(2);
+        let (out, len) = transitions(&mut SymmetricDefer::new().with_debounce_ms(4), trace);
+
+        assert_eq!(len, 4);
+        assert_eq!(out[0], Some(Transition::new(2, true, 10)));
+        assert_eq!(out[1], Some(Transition::new(5, true, 10)));
+        assert_eq!(out[2], Some(Transition::new(2, false, 22)));
+        assert_eq!(out[3], Some(Transition::new(5, false, 22)));
+    }
+}
@@ -0,0 +1,81 @@
+//! Diagnostic counters.
+//!
+//! Counts unexpected conditions that the firmware recovers from, so configuration bugs and
+//! intermittent failures are visible to host tools instead of being silently masked.
+//!
+//! USB failures are grouped by cause, so intermittent "my keypress vanished" reports can be
+//! correlated with endpoint pressure.
+//!
+//! Like the [health](crate::health) counters, diagnostic counters are updated with plain loads
+//! and stores, since the AVR target has no atomic read-modify-write operations.
+
+use core::sync::atomic::{AtomicU16, Ordering};
+
+/// Number of diagnostic counters.
+pub const NUM_DIAGNOSTICS: usize = 7;
+
+/// Represents a diagnostic counter.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Diagnostic {
+    /// A key lookup used a layer beyond the defined layers, and fell back to the base layer.
+    InvalidLayer = 0,
+    /// A key lookup used an index beyond the keys in a layer, and produced no key.
+    InvalidKey = 1,
+    /// A matrix scan found a possible ghost key, and kept the last state of the affected rows.
+    Ghosting = 2,
+    /// An input report was dropped, because the USB endpoint stayed busy until the report queue
+    /// was full.
+    ReportBlocked = 3,
+    /// An input report was dropped, because of a USB error other than a busy endpoint.
+    ReportFailed = 4,
+    /// Reading the host LED output report failed with a USB error.
+    OutputFailed = 5,
+    /// The USB device was wedged, and was re-enumerated by the
+    /// [usb_watchdog](crate::usb_watchdog).
+    UsbRecovered = 6,
+}
+
+impl Diagnostic {
+    /// Gets the index of the [Diagnostic] counter.
+    pub const fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+static COUNTERS: [AtomicU16; NUM_DIAGNOSTICS] = [const { AtomicU16::new(0) }; NUM_DIAGNOSTICS];
+
+/// Records an occurrence of the [Diagnostic].
+///
+/// Counters saturate at `u16::MAX`.
+pub fn record(diagnostic: Diagnostic) {
+    let counter = &COUNTERS[diagnostic.index()];
+
+    counter.store(
+        counter.load(Ordering::Relaxed).saturating_add(1),
+        Ordering::SeqCst,
+    );
+}
+
+/// Gets the number of recorded occurrences of the [Diagnostic].
+pub fn count(diagnostic: Diagnostic) -> u16 {
+    COUNTERS[diagnostic.index()].load(Ordering::Relaxed)
+}
+
+/// Gets the number of recorded occurrences of every [Diagnostic], in counter order.
+pub fn counts() -> [u16; NUM_DIAGNOSTICS] {
+    let mut out = [0u16; NUM_DIAGNOSTICS];
+
+    for (o, counter) in out.iter_mut().zip(COUNTERS.iter()) {
+        *o = counter.load(Ordering::Relaxed);
+    }
+
+    out
+}
+
+/// Resets all diagnostic counters.
+pub fn reset() {
+    for counter in COUNTERS.iter() {
+        counter.store(0, Ordering::SeqCst);
+    }
+}
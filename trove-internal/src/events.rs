@@ -0,0 +1,175 @@
+//! Key press and release events.
+//!
+//! The key scanner turns debounced matrix changes into a queue of [KeyEvent]s, which the
+//! downstream stages (layers, tap-hold, macros, and the report builder) consume in order. Stages
+//! only see changes, instead of rebuilding everything from the current and previous matrix state
+//! on every scan.
+
+use crate::debounce::{RowState, MAX_COLS};
+
+/// Default capacity of an [EventQueue].
+pub const EVENT_QUEUE_LEN: usize = 16;
+
+/// Represents a key press or release.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct KeyEvent {
+    row: u8,
+    col: u8,
+    pressed: bool,
+    timestamp: u16,
+}
+
+impl KeyEvent {
+    /// Creates a new [KeyEvent].
+    pub const fn new(row: u8, col: u8, pressed: bool, timestamp: u16) -> Self {
+        Self {
+            row,
+            col,
+            pressed,
+            timestamp,
+        }
+    }
+
+    /// Gets the matrix row of the key.
+    pub const fn row(&self) -> u8 {
+        self.row
+    }
+
+    /// Gets the matrix column of the key.
+    pub const fn col(&self) -> u8 {
+        self.col
+    }
+
+    /// Gets whether the key was pressed, or released.
+    pub const fn pressed(&self) -> bool {
+        self.pressed
+    }
+
+    /// Gets the time (in milliseconds) of the event.
+    pub const fn timestamp(&self) -> u16 {
+        self.timestamp
+    }
+}
+
+/// Fixed-capacity FIFO of [KeyEvent]s.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EventQueue<const N: usize = EVENT_QUEUE_LEN> {
+    events: [KeyEvent; N],
+    head: usize,
+    len: usize,
+}
+
+impl<const N: usize> EventQueue<N> {
+    /// Creates a new [EventQueue].
+    pub const fn new() -> Self {
+        Self {
+            events: [KeyEvent::new(0, 0, false, 0); N],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// Gets the number of queued events.
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Gets whether the queue is empty.
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Gets whether the queue is full.
+    pub const fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    /// Pushes an event to the back of the queue.
+    ///
+    /// Returns `false` if the queue is full.
+    pub fn push(&mut self, event: KeyEvent) -> bool {
+        if self.is_full() {
+            false
+        } else {
+            self.events[(self.head + self.len) % N] = event;
+            self.len += 1;
+            true
+        }
+    }
+
+    /// Removes the event at the front of the queue.
+    pub fn pop(&mut self) -> Option<KeyEvent> {
+        if self.is_empty() {
+            None
+        } else {
+            let event = self.events[self.head];
+
+            self.head = (self.head + 1) % N;
+            self.len -= 1;
+
+            Some(event)
+        }
+    }
+
+    /// Queues an event for every column that differs between the `last` and `next` states of a
+    /// row.
+    ///
+    /// Returns the row state covered by the queued events. Changes that do not fit in the queue
+    /// keep their `last` state, so they are queued again on the next call.
+    pub fn push_row_changes(
+        &mut self,
+        row: u8,
+        last: RowState,
+        next: RowState,
+        timestamp: u16,
+    ) -> RowState {
+        let mut state = last;
+
+        for col in 0..MAX_COLS {
+            let pressed = next.column(col);
+
+            if pressed != last.column(col)
+                && self.push(KeyEvent::new(row, col as u8, pressed, timestamp))
+            {
+                state.set_column(col, pressed);
+            }
+        }
+
+        state
+    }
+}
+
+impl<const N: usize> Default for EventQueue<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_queue() {
+        let mut queue = EventQueue::<2>::new();
+
+        let last = RowState::from_u16(0b0011);
+        let next = RowState::from_u16(0b1100);
+
+        // only two of the four changes fit
+        assert_eq!(
+            queue.push_row_changes(1, last, next, 42),
+            RowState::from_u16(0b0000)
+        );
+        assert!(queue.is_full());
+
+        assert_eq!(queue.pop(), Some(KeyEvent::new(1, 0, false, 42)));
+        assert_eq!(queue.pop(), Some(KeyEvent::new(1, 1, false, 42)));
+        assert_eq!(queue.pop(), None);
+
+        // the rest are queued on the next call
+        assert_eq!(queue.push_row_changes(1, RowState::new(), next, 43), next);
+        assert_eq!(queue.pop().map(|e| (e.col(), e.pressed())), Some((2, true)));
+        assert_eq!(queue.pop().map(|e| e.timestamp()), Some(43));
+    }
+}
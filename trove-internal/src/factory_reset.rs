@@ -0,0 +1,132 @@
+//! Factory reset.
+//!
+//! Restores the [settings](crate::settings) and the [stored key map](crate::stored_keymap) in
+//! EEPROM to the compiled-in defaults, e.g. after a host tool left the keyboard in an unusable
+//! state. The reset is triggered by the [FACTORY_RESET_COMBO] keys:
+//!
+//! - held while plugging in the keyboard, before the settings are restored
+//! - held for [FACTORY_RESET_HOLD_MS] at runtime, see [ComboHold]
+//!
+//! The pin map and the color map are left alone, so the keyboard keeps working on boards with a
+//! custom wiring.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::config::ConfigError;
+use crate::debounce::RowState;
+use crate::layers::{COLS, ROWS};
+use crate::settings::{Settings, SETTINGS_OFFSET};
+use crate::storage::EepromStorage;
+use crate::stored_keymap;
+
+/// Matrix positions (`(row, col)`) that reset the EEPROM configuration when held together.
+///
+/// The top-left key, and the outer keys of the bottom row, so the combo never includes the
+/// [MAGIC_COMBO](crate::matrix_test::MAGIC_COMBO).
+pub const FACTORY_RESET_COMBO: &[(usize, usize)] = &[(0, 0), (ROWS - 1, 0), (ROWS - 1, COLS - 1)];
+/// Time (in milliseconds) the [FACTORY_RESET_COMBO] is held at runtime before the reset.
+pub const FACTORY_RESET_HOLD_MS: u16 = 5000;
+
+/// Whether a factory reset was requested.
+static FACTORY_RESET_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Requests a factory reset from the main loop.
+pub fn request_factory_reset() {
+    FACTORY_RESET_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Gets whether a factory reset was requested, and clears the request.
+pub fn take_factory_reset_request() -> bool {
+    let requested = FACTORY_RESET_REQUESTED.load(Ordering::Relaxed);
+
+    if requested {
+        FACTORY_RESET_REQUESTED.store(false, Ordering::SeqCst);
+    }
+
+    requested
+}
+
+/// Gets whether every key of the `combo` is pressed in the `rows`.
+pub fn combo_held(combo: &[(usize, usize)], rows: &[RowState]) -> bool {
+    combo
+        .iter()
+        .all(|&(row, col)| col < COLS && rows.get(row).is_some_and(|r| r.column(col)))
+}
+
+/// Tracks how long a key combo is held, to trigger on long holds only.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ComboHold {
+    since_ms: Option<u16>,
+    fired: bool,
+}
+
+impl ComboHold {
+    /// Creates a new [ComboHold].
+    pub const fn new() -> Self {
+        Self {
+            since_ms: None,
+            fired: false,
+        }
+    }
+
+    /// Updates whether the combo is `held` at the time `now_ms`.
+    ///
+    /// Returns `true` once the combo is held for [FACTORY_RESET_HOLD_MS], and not again until it
+    /// is released.
+    pub fn update(&mut self, held: bool, now_ms: u16) -> bool {
+        if !held {
+            *self = Self::new();
+            return false;
+        }
+
+        let since_ms = *self.since_ms.get_or_insert(now_ms);
+
+        if !self.fired && now_ms.wrapping_sub(since_ms) >= FACTORY_RESET_HOLD_MS {
+            self.fired = true;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+/// Resets the settings and the stored key map in the `storage` to the defaults.
+///
+/// Returns the default [Settings], for the caller to apply.
+pub fn wipe<S: EepromStorage>(storage: &mut S) -> Result<Settings, ConfigError> {
+    let settings = Settings::new();
+
+    storage.store(SETTINGS_OFFSET, &settings.to_bytes())?;
+    stored_keymap::erase(storage)?;
+
+    Ok(settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_combo_hold() {
+        let mut rows = [RowState::new(); ROWS];
+        assert!(!combo_held(FACTORY_RESET_COMBO, &rows));
+
+        for &(row, col) in FACTORY_RESET_COMBO {
+            rows[row] = RowState::from(rows[row].as_inner() | (1 << col));
+        }
+        assert!(combo_held(FACTORY_RESET_COMBO, &rows));
+        assert!(!combo_held(FACTORY_RESET_COMBO, &rows[..1]));
+
+        // the time wraps around while held
+        let start = 65_000u16;
+        let mut hold = ComboHold::new();
+        assert!(!hold.update(true, start));
+        assert!(!hold.update(true, start.wrapping_add(FACTORY_RESET_HOLD_MS - 1)));
+        assert!(hold.update(true, start.wrapping_add(FACTORY_RESET_HOLD_MS)));
+        assert!(!hold.update(true, start.wrapping_add(FACTORY_RESET_HOLD_MS + 1)));
+
+        assert!(!hold.update(false, 0));
+        assert!(!hold.update(true, 1));
+        assert!(hold.update(true, 1 + FACTORY_RESET_HOLD_MS));
+    }
+}
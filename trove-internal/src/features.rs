@@ -0,0 +1,230 @@
+//! Runtime feature toggles.
+//!
+//! Optional firmware features can be enabled and disabled at runtime, so users can experiment
+//! without rebuilding the firmware. The flags are stored as a single byte, which makes them easy
+//! to persist and to report to host tools.
+//!
+//! Host tools read the flags with the [GetFeatures](Command::GetFeatures) command, and toggle
+//! each feature with the [SetFeature](Command::SetFeature) command. Changes are persisted to the
+//! [settings](crate::settings) block like the other runtime state.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use crate::config::{Command, ConfigError};
+
+/// Represents a runtime-toggleable firmware feature.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Feature {
+    /// Mouse movement and button keys.
+    MouseKeys = 0,
+    /// Holding a key sends its shifted version.
+    AutoShift = 1,
+    /// Shifts letters until a word-breaking key is pressed.
+    CapsWord = 2,
+    /// Modifier and layer keys that apply to the next key press only.
+    OneShot = 3,
+}
+
+impl Feature {
+    /// Every feature.
+    pub const ALL: [Self; 4] = [
+        Self::MouseKeys,
+        Self::AutoShift,
+        Self::CapsWord,
+        Self::OneShot,
+    ];
+
+    /// Gets the bit mask for the [Feature] in [FeatureFlags].
+    pub const fn mask(&self) -> u8 {
+        1 << (*self as u8)
+    }
+}
+
+impl TryFrom<u8> for Feature {
+    type Error = u8;
+
+    fn try_from(val: u8) -> Result<Self, Self::Error> {
+        match val {
+            0 => Ok(Self::MouseKeys),
+            1 => Ok(Self::AutoShift),
+            2 => Ok(Self::CapsWord),
+            3 => Ok(Self::OneShot),
+            _ => Err(val),
+        }
+    }
+}
+
+/// Features supported by the firmware, reported to host tools.
+pub const SUPPORTED_FEATURES: FeatureFlags = FeatureFlags::all();
+
+/// Bitfield of enabled [Feature]s.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FeatureFlags(u8);
+
+impl FeatureFlags {
+    /// Creates a new [FeatureFlags] with no features enabled.
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    /// Creates a new [FeatureFlags] with every [Feature] enabled.
+    pub const fn all() -> Self {
+        let mut flags = 0;
+        let mut i = 0;
+
+        while i < Feature::ALL.len() {
+            flags |= Feature::ALL[i].mask();
+            i += 1;
+        }
+
+        Self(flags)
+    }
+
+    /// Creates a new [FeatureFlags] from a `u8`.
+    pub const fn from_u8(val: u8) -> Self {
+        Self(val)
+    }
+
+    /// Gets the underlying integer representation of the [FeatureFlags].
+    pub const fn as_inner(&self) -> u8 {
+        self.0
+    }
+
+    /// Gets whether the [Feature] is enabled.
+    pub const fn is_enabled(&self, feature: Feature) -> bool {
+        self.0 & feature.mask() != 0
+    }
+
+    /// Sets whether the [Feature] is enabled.
+    pub fn set_enabled(&mut self, feature: Feature, val: bool) {
+        if val {
+            self.0 |= feature.mask();
+        } else {
+            self.0 &= !feature.mask();
+        }
+    }
+
+    /// Builder function that sets whether the [Feature] is enabled.
+    pub fn with_enabled(mut self, feature: Feature, val: bool) -> Self {
+        self.set_enabled(feature, val);
+        self
+    }
+}
+
+impl From<u8> for FeatureFlags {
+    fn from(val: u8) -> Self {
+        Self::from_u8(val)
+    }
+}
+
+impl From<FeatureFlags> for u8 {
+    fn from(val: FeatureFlags) -> Self {
+        val.as_inner()
+    }
+}
+
+/// Currently enabled features.
+static FEATURES: AtomicU8 = AtomicU8::new(0);
+
+/// Gets the currently enabled [FeatureFlags].
+pub fn features() -> FeatureFlags {
+    FEATURES.load(Ordering::Relaxed).into()
+}
+
+/// Sets the currently enabled [FeatureFlags].
+///
+/// Returns the previously enabled [FeatureFlags].
+pub fn set_features(flags: FeatureFlags) -> FeatureFlags {
+    let last = features();
+    FEATURES.store(flags.into(), Ordering::SeqCst);
+    last
+}
+
+/// Gets whether the [Feature] is currently enabled.
+pub fn feature_enabled(feature: Feature) -> bool {
+    features().is_enabled(feature)
+}
+
+/// Sets whether the [Feature] is currently enabled.
+pub fn set_feature_enabled(feature: Feature, val: bool) {
+    set_features(features().with_enabled(feature, val));
+}
+
+/// Handles the [GetFeatures](Command::GetFeatures) and [SetFeature](Command::SetFeature)
+/// commands.
+///
+/// Both respond with the enabled and the [supported](SUPPORTED_FEATURES) flags:
+/// `| enabled flags | supported flags |`.
+pub fn handle_features_command(
+    command: Command,
+    args: &[u8],
+    buf: &mut [u8],
+) -> Result<usize, ConfigError> {
+    match (command, args) {
+        (Command::GetFeatures, _) => (),
+        (Command::SetFeature, &[feature, val, ..]) => {
+            let feature = Feature::try_from(feature).map_err(|_| ConfigError::InvalidArgument)?;
+            set_feature_enabled(feature, val != 0);
+        }
+        (Command::SetFeature, _) => return Err(ConfigError::InvalidArgument),
+        _ => return Err(ConfigError::UnhandledCommand(command)),
+    }
+
+    let response = [features().into(), SUPPORTED_FEATURES.into()];
+
+    buf.get_mut(..response.len())
+        .ok_or(ConfigError::BufferTooSmall)?
+        .copy_from_slice(&response);
+
+    Ok(response.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_features_command() {
+        let mut buf = [0u8; 2];
+        let caps_word = Feature::CapsWord.mask();
+
+        assert_eq!(SUPPORTED_FEATURES.as_inner(), 0b1111);
+
+        assert_eq!(
+            handle_features_command(Command::SetFeature, &[2, 1], &mut buf),
+            Ok(2)
+        );
+        assert_eq!(buf, [caps_word, 0b1111]);
+        assert!(feature_enabled(Feature::CapsWord));
+
+        assert_eq!(
+            handle_features_command(Command::SetFeature, &[0, 1], &mut buf),
+            Ok(2)
+        );
+        assert_eq!(
+            handle_features_command(Command::SetFeature, &[0, 0], &mut buf),
+            Ok(2)
+        );
+        assert_eq!(
+            handle_features_command(Command::GetFeatures, &[], &mut buf),
+            Ok(2)
+        );
+        assert_eq!(buf, [caps_word, 0b1111]);
+
+        assert_eq!(
+            handle_features_command(Command::SetFeature, &[4, 1], &mut buf),
+            Err(ConfigError::InvalidArgument)
+        );
+        assert_eq!(
+            handle_features_command(Command::SetFeature, &[2], &mut buf),
+            Err(ConfigError::InvalidArgument)
+        );
+        assert_eq!(
+            handle_features_command(Command::GetFeatures, &[], &mut buf[..1]),
+            Err(ConfigError::BufferTooSmall)
+        );
+
+        set_features(FeatureFlags::new());
+    }
+}
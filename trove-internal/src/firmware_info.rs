@@ -0,0 +1,134 @@
+//! Firmware identification.
+//!
+//! Host tools and bug reports need to know exactly which firmware is flashed. The firmware name,
+//! version, git revision, and build date are embedded at build time, and sent to host tools with
+//! the [GetFirmwareInfo](crate::config::Command::GetFirmwareInfo) command, or printed by the
+//! `version` [console](crate::console) command.
+//!
+//! The command response is a list of length-prefixed strings:
+//!
+//! ```text
+//! | len | name ... | len | version ... | len | git hash ... | len | build date ... |
+//! ```
+
+use crate::config::ConfigError;
+
+/// Name of the firmware.
+pub const FIRMWARE_NAME: &str = "trove";
+/// Semantic version of the firmware.
+pub const FIRMWARE_VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Abbreviated git revision the firmware was built from, or `unknown`.
+pub const GIT_HASH: &str = env!("TROVE_GIT_HASH");
+/// Build date of the firmware (`YYYY-MM-DD`, UTC), or `unknown`.
+pub const BUILD_DATE: &str = env!("TROVE_BUILD_DATE");
+
+/// Identification of the running firmware.
+pub const FIRMWARE_INFO: FirmwareInfo =
+    FirmwareInfo::new(FIRMWARE_NAME, FIRMWARE_VERSION, GIT_HASH, BUILD_DATE);
+
+/// Represents the identification of a firmware build.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FirmwareInfo {
+    name: &'static str,
+    version: &'static str,
+    git_hash: &'static str,
+    build_date: &'static str,
+}
+
+impl FirmwareInfo {
+    /// Creates a new [FirmwareInfo].
+    pub const fn new(
+        name: &'static str,
+        version: &'static str,
+        git_hash: &'static str,
+        build_date: &'static str,
+    ) -> Self {
+        Self {
+            name,
+            version,
+            git_hash,
+            build_date,
+        }
+    }
+
+    /// Gets the firmware name.
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Gets the semantic version.
+    pub const fn version(&self) -> &'static str {
+        self.version
+    }
+
+    /// Gets the abbreviated git revision.
+    pub const fn git_hash(&self) -> &'static str {
+        self.git_hash
+    }
+
+    /// Gets the build date.
+    pub const fn build_date(&self) -> &'static str {
+        self.build_date
+    }
+
+    /// Gets the identification strings, in serialization order.
+    pub const fn fields(&self) -> [&'static str; 4] {
+        [self.name, self.version, self.git_hash, self.build_date]
+    }
+
+    /// Serializes the [FirmwareInfo] as length-prefixed strings into the `buf`.
+    ///
+    /// Returns the number of bytes written. Strings are cut to 255 bytes.
+    pub fn write_bytes(&self, buf: &mut [u8]) -> Result<usize, ConfigError> {
+        let mut len = 0;
+
+        for field in self.fields() {
+            let field = &field.as_bytes()[..field.len().min(u8::MAX as usize)];
+            let out = buf
+                .get_mut(len..len + 1 + field.len())
+                .ok_or(ConfigError::BufferTooSmall)?;
+
+            out[0] = field.len() as u8;
+            out[1..].copy_from_slice(field);
+            len += out.len();
+        }
+
+        Ok(len)
+    }
+}
+
+impl Default for FirmwareInfo {
+    fn default() -> Self {
+        FIRMWARE_INFO
+    }
+}
+
+/// Handles the [GetFirmwareInfo](crate::config::Command::GetFirmwareInfo) command.
+pub fn handle_firmware_info_command(buf: &mut [u8]) -> Result<usize, ConfigError> {
+    FIRMWARE_INFO.write_bytes(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_firmware_info() {
+        let info = FirmwareInfo::new("trove", "1.2.3", "0123abcd", "2026-01-31");
+        let mut buf = [0u8; 32];
+
+        assert_eq!(info.write_bytes(&mut buf), Ok(32));
+        assert_eq!(buf[..7], *b"\x05trove\x05");
+        assert_eq!(buf[7..12], *b"1.2.3");
+        assert_eq!(buf[12..22], *b"\x080123abcd\x0a");
+        assert_eq!(buf[22..], *b"2026-01-31");
+
+        assert_eq!(
+            info.write_bytes(&mut buf[..16]),
+            Err(ConfigError::BufferTooSmall)
+        );
+
+        assert_eq!(FIRMWARE_INFO.version(), env!("CARGO_PKG_VERSION"));
+        assert!(!FIRMWARE_INFO.git_hash().is_empty());
+    }
+}
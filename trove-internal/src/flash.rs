@@ -0,0 +1,287 @@
+//! Constant data in program memory.
+//!
+//! Constants are copied from flash to SRAM at startup on AVR, so large tables (e.g. macro text,
+//! or unicode code points) would quickly fill the 2.5 KB of SRAM. The
+//! [flash_slice](crate::flash_slice) and [flash_str](crate::flash_str) macros place the data in
+//! program memory instead, behind a [FlashSlice] or [FlashStr] handle.
+//!
+//! Handles read one element at a time, without copying the data to the stack, so they are cheap
+//! enough for the report-building path. On other targets the data stays in normal memory, and the
+//! same handles are read, so host tests cover the accessors.
+//!
+//! ```no_run
+//! # use trove_internal::{flash_slice, flash_str};
+//! flash_str! {
+//!     /// Text typed by a macro key.
+//!     pub static GREETING = "Hello from trove!";
+//! }
+//!
+//! flash_slice! {
+//!     /// Code points of the unicode keys.
+//!     pub static CODE_POINTS: [u32] = &[0x00e9, 0x2713, 0x1f600];
+//! }
+//!
+//! assert_eq!(GREETING.get(0), Some(b'H'));
+//! assert_eq!(CODE_POINTS.get(1), Some(0x2713));
+//! ```
+
+/// Handle to a slice of constant data in program memory.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FlashSlice<T: 'static> {
+    ptr: *const T,
+    len: usize,
+}
+
+// Safety: the data behind the pointer is immutable, and lives for the whole program.
+unsafe impl<T: Sync> Sync for FlashSlice<T> {}
+// Safety: see `Sync`.
+unsafe impl<T: Sync> Send for FlashSlice<T> {}
+
+impl<T: Copy + 'static> FlashSlice<T> {
+    /// Creates a new [FlashSlice] from a pointer to `len` elements.
+    ///
+    /// Use the [flash_slice](crate::flash_slice) macro instead, which places the data in program
+    /// memory.
+    ///
+    /// # Safety
+    ///
+    /// The pointer must point to `len` immutable elements in a `static`, placed in program memory
+    /// on AVR.
+    pub const unsafe fn from_raw_parts(ptr: *const T, len: usize) -> Self {
+        Self { ptr, len }
+    }
+
+    /// Gets the number of elements.
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Gets whether the slice is empty.
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Gets the element at the `index`.
+    ///
+    /// Returns `None` if the `index` is out of bounds.
+    pub fn get(&self, index: usize) -> Option<T> {
+        // Safety: the index is in bounds of the data given to `from_raw_parts`
+        (index < self.len).then(|| unsafe { read(self.ptr.add(index)) })
+    }
+
+    /// Gets an iterator over the elements.
+    pub fn iter(&self) -> FlashIter<T> {
+        FlashIter {
+            slice: *self,
+            index: 0,
+        }
+    }
+
+    /// Loads the elements starting at the `offset` into the `buf`.
+    ///
+    /// Returns the number of loaded elements, cut off at the end of the slice or the `buf`.
+    pub fn load(&self, offset: usize, buf: &mut [T]) -> usize {
+        let mut len = 0;
+
+        for (slot, val) in buf.iter_mut().zip(self.iter().skip(offset)) {
+            *slot = val;
+            len += 1;
+        }
+
+        len
+    }
+}
+
+impl<T: Copy + 'static> IntoIterator for FlashSlice<T> {
+    type Item = T;
+    type IntoIter = FlashIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over the elements of a [FlashSlice].
+#[derive(Clone, Copy, Debug)]
+pub struct FlashIter<T: 'static> {
+    slice: FlashSlice<T>,
+    index: usize,
+}
+
+impl<T: Copy + 'static> Iterator for FlashIter<T> {
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        let val = self.slice.get(self.index)?;
+        self.index += 1;
+        Some(val)
+    }
+
+    fn nth(&mut self, n: usize) -> Option<T> {
+        self.index = self.index.saturating_add(n);
+        self.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = self.slice.len().saturating_sub(self.index);
+        (len, Some(len))
+    }
+}
+
+impl<T: Copy + 'static> ExactSizeIterator for FlashIter<T> {}
+
+/// Handle to UTF-8 text in program memory.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FlashStr {
+    bytes: FlashSlice<u8>,
+}
+
+impl FlashStr {
+    /// Creates a new [FlashStr] from the bytes of a `str`.
+    ///
+    /// Use the [flash_str](crate::flash_str) macro instead, which places the text in program
+    /// memory.
+    ///
+    /// # Safety
+    ///
+    /// The bytes must be valid UTF-8, see [FlashSlice::from_raw_parts].
+    pub const unsafe fn from_utf8_unchecked(bytes: FlashSlice<u8>) -> Self {
+        Self { bytes }
+    }
+
+    /// Gets the length of the text in bytes.
+    pub const fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Gets whether the text is empty.
+    pub const fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Gets the byte at the `index`.
+    pub fn get(&self, index: usize) -> Option<u8> {
+        self.bytes.get(index)
+    }
+
+    /// Gets an iterator over the bytes of the text.
+    pub fn bytes(&self) -> FlashIter<u8> {
+        self.bytes.iter()
+    }
+
+    /// Gets the underlying [FlashSlice] of the text.
+    pub const fn as_bytes(&self) -> FlashSlice<u8> {
+        self.bytes
+    }
+
+    /// Loads the text into the `buf`.
+    ///
+    /// The text is cut off at the last whole character that fits.
+    pub fn load<'b>(&self, buf: &'b mut [u8]) -> &'b str {
+        let len = self.bytes.load(0, buf);
+
+        match core::str::from_utf8(&buf[..len]) {
+            Ok(text) => text,
+            // Safety: the text is valid UTF-8, and only the last character can be cut off
+            Err(err) => unsafe { core::str::from_utf8_unchecked(&buf[..err.valid_up_to()]) },
+        }
+    }
+}
+
+/// Copies a slice into an array of the same length, for the [flash_slice](crate::flash_slice)
+/// macro.
+#[doc(hidden)]
+pub const fn to_array<T: Copy, const N: usize>(data: &[T]) -> [T; N] {
+    match data.first_chunk::<N>() {
+        Some(array) => *array,
+        None => panic!("flash data length mismatch"),
+    }
+}
+
+// Reads an element from program memory.
+#[cfg(target_arch = "avr")]
+unsafe fn read<T: Copy>(ptr: *const T) -> T {
+    // Safety: the caller guarantees the pointer is in program memory
+    unsafe { avr_progmem::raw::read_value(ptr) }
+}
+
+// Reads an element from program memory, which is normal memory on other targets.
+#[cfg(not(target_arch = "avr"))]
+unsafe fn read<T: Copy>(ptr: *const T) -> T {
+    // Safety: the caller guarantees the pointer is valid
+    unsafe { ptr.read() }
+}
+
+/// Defines a [FlashSlice] `static`, with the data placed in program memory on AVR.
+///
+/// See the [module documentation](crate::flash).
+#[macro_export]
+macro_rules! flash_slice {
+    ($(#[$attr:meta])* $vis:vis static $name:ident: [$ty:ty] = $data:expr;) => {
+        $(#[$attr])*
+        $vis static $name: $crate::flash::FlashSlice<$ty> = {
+            const DATA: &[$ty] = $data;
+
+            #[cfg_attr(target_arch = "avr", link_section = ".progmem.data")]
+            static STORED: [$ty; DATA.len()] = $crate::flash::to_array(DATA);
+
+            // Safety: the data is only read through the handle
+            unsafe { $crate::flash::FlashSlice::from_raw_parts(STORED.as_ptr(), DATA.len()) }
+        };
+    };
+}
+
+/// Defines a [FlashStr] `static`, with the text placed in program memory on AVR.
+///
+/// See the [module documentation](crate::flash).
+#[macro_export]
+macro_rules! flash_str {
+    ($(#[$attr:meta])* $vis:vis static $name:ident = $text:expr;) => {
+        $(#[$attr])*
+        $vis static $name: $crate::flash::FlashStr = {
+            $crate::flash_slice! {
+                static BYTES: [u8] = str::as_bytes($text);
+            }
+
+            // Safety: the bytes are copied from a `str`
+            unsafe { $crate::flash::FlashStr::from_utf8_unchecked(BYTES) }
+        };
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    flash_str! {
+        static TEXT = "trove ✓";
+    }
+
+    flash_slice! {
+        static TABLE: [u16] = &[0x2713, 0x00e9, 0x03bb];
+    }
+
+    #[test]
+    fn test_flash_slice() {
+        assert_eq!(TABLE.len(), 3);
+        assert_eq!(TABLE.get(2), Some(0x03bb));
+        assert_eq!(TABLE.get(3), None);
+        assert_eq!(TABLE.iter().nth(1), Some(0x00e9));
+        assert_eq!(TABLE.iter().len(), 3);
+
+        let mut buf = [0u16; 4];
+        assert_eq!(TABLE.load(1, &mut buf), 2);
+        assert_eq!(buf, [0x00e9, 0x03bb, 0, 0]);
+    }
+
+    #[test]
+    fn test_flash_str() {
+        assert_eq!(TEXT.len(), 9);
+        assert!(TEXT.bytes().eq("trove ✓".bytes()));
+
+        let mut buf = [0u8; 16];
+        assert_eq!(TEXT.load(&mut buf), "trove ✓");
+
+        // the check mark does not fit, and is left out whole
+        let mut buf = [0u8; 8];
+        assert_eq!(TEXT.load(&mut buf), "trove ");
+    }
+}
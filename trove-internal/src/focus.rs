@@ -0,0 +1,428 @@
+//! Kaleidoscope Focus protocol.
+//!
+//! [Chrysalis](https://github.com/keyboardio/Chrysalis) and other Kaleidoscope tools talk to the
+//! keyboard with the Focus protocol over the USB-serial port. The host sends a command line, e.g.
+//! `keymap.custom`, optionally followed by space-separated arguments, and the keyboard answers
+//! with any number of lines, terminated by a line with a single `.`:
+//!
+//! ```text
+//! > layer.state
+//! 1 0 0 0
+//! .
+//! ```
+//!
+//! Only a subset of the protocol is supported: [FocusCommand] lists the commands. Keys are sent
+//! as Kaleidoscope key codes, see [focus_key]. The keymap is built into the firmware, so writes
+//! to `keymap.custom` are ignored.
+//!
+//! Responses can be longer than the serial queue, so a [FocusResponse] is written in pieces with
+//! [fill](FocusResponse::fill), as the queue drains.
+
+use crate::console::CONSOLE_COMMANDS;
+use crate::consumer;
+use crate::firmware_info::FIRMWARE_INFO;
+use crate::layers::{self, Layer, COLS, FUN, NUMPAD, NUM_LAYERS, ROWS, TRANS, UPPER};
+use crate::tuning::{self, Tuning};
+
+/// Version of the settings reported by `settings.version`.
+pub const FOCUS_SETTINGS_VERSION: u8 = 1;
+/// Maximum length of a single piece of a [FocusResponse].
+pub const FOCUS_TOKEN_LEN: usize = 48;
+
+/// Number of keys in the keymap sent by `keymap.custom`.
+const KEYMAP_LEN: usize = NUM_LAYERS * ROWS * COLS;
+/// Line ending the response to every command.
+const END_OF_RESPONSE: &[u8] = b".\r\n";
+
+/// Kaleidoscope key flags of synthetic keys.
+const SYNTHETIC: u16 = 0b0100_0000;
+/// Kaleidoscope key flags of Shift held with a key (`LSHIFT(key)`).
+const SHIFT_HELD: u16 = 0b0000_1000;
+/// Kaleidoscope key flags of a layer key.
+const SWITCH_TO_KEYMAP: u16 = 0b0000_0100;
+/// Kaleidoscope key flags of a Consumer Control key.
+const IS_CONSUMER: u16 = 0b0000_1000;
+/// Offset of the `ShiftToLayer` keys from the `LockLayer` keys.
+const LAYER_SHIFT_OFFSET: u16 = 42;
+/// Kaleidoscope transparent key.
+const KEY_TRANSPARENT: u16 = 0xffff;
+/// First reserved keyboard usage, the keycodes from here up are firmware keys.
+const FIRST_RESERVED_USAGE: u8 = 0xa5;
+
+/// Represents a supported Focus command.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FocusCommand {
+    /// List the supported commands, including the [console](crate::console) commands: `help`.
+    Help,
+    /// Print the [firmware_info](crate::firmware_info): `version`.
+    Version,
+    /// Print the keys of every layer as Kaleidoscope key codes: `keymap.custom`.
+    KeymapCustom,
+    /// Print, or set, whether each layer is active: `layer.state`.
+    LayerState,
+    /// Print whether the stored settings are valid: `settings.valid?`.
+    SettingsValid,
+    /// Print the [FOCUS_SETTINGS_VERSION]: `settings.version`.
+    SettingsVersion,
+    /// Print, or set, a [tuning](crate::tuning) parameter, e.g. `tuning.debounce`.
+    Tuning(Tuning),
+}
+
+impl FocusCommand {
+    /// Every supported Focus command.
+    pub const ALL: [Self; 11] = [
+        Self::Help,
+        Self::Version,
+        Self::KeymapCustom,
+        Self::LayerState,
+        Self::SettingsValid,
+        Self::SettingsVersion,
+        Self::Tuning(Tuning::DebounceMs),
+        Self::Tuning(Tuning::SettleUs),
+        Self::Tuning(Tuning::TappingTermMs),
+        Self::Tuning(Tuning::MouseSpeed),
+        Self::Tuning(Tuning::WheelSpeed),
+    ];
+
+    /// Gets the name of the command, as sent by the host.
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Help => "help",
+            Self::Version => "version",
+            Self::KeymapCustom => "keymap.custom",
+            Self::LayerState => "layer.state",
+            Self::SettingsValid => "settings.valid?",
+            Self::SettingsVersion => "settings.version",
+            Self::Tuning(param) => param.focus_name(),
+        }
+    }
+
+    /// Parses a Focus command `line` into the command, and its arguments.
+    ///
+    /// Returns `None` if the line does not start with a supported command.
+    pub fn parse(line: &[u8]) -> Option<(Self, &[u8])> {
+        let line = line.trim_ascii();
+        let (name, args) = match line.iter().position(|&b| b == b' ') {
+            Some(pos) => (&line[..pos], line[pos + 1..].trim_ascii()),
+            None => (line, &[][..]),
+        };
+
+        Self::ALL
+            .iter()
+            .find(|command| command.name().as_bytes() == name)
+            .map(|&command| (command, args))
+    }
+}
+
+/// Converts a trove key into a Kaleidoscope key code.
+///
+/// Keys without a Kaleidoscope equivalent (e.g. the user-defined keys) are sent as `Key_NoKey`.
+pub fn focus_key(key: u8) -> u16 {
+    let layer_key = |layer: Layer| ((SYNTHETIC | SWITCH_TO_KEYMAP) << 8) | layer.index() as u16;
+
+    match key {
+        TRANS => KEY_TRANSPARENT,
+        // the function key is held, the upper and numpad keys toggle their layer
+        FUN => layer_key(Layer::Fun) + LAYER_SHIFT_OFFSET,
+        UPPER => layer_key(Layer::Upper),
+        NUMPAD => layer_key(Layer::Numpad),
+        _ if layers::key_is_shifted(key) => (SHIFT_HELD << 8) | layers::shifted_key(key) as u16,
+        _ if layers::key_is_modifier(key) => key as u16,
+        _ if layers::key_is_one_shot(key) => 0,
+        _ => match consumer::consumer_usage(key) {
+            Some(usage) => ((SYNTHETIC | IS_CONSUMER | (usage >> 8)) << 8) | (usage & 0xff),
+            // the other firmware keycodes shadow the reserved and extended keypad usages
+            None if key < FIRST_RESERVED_USAGE => key as u16,
+            None => 0,
+        },
+    }
+}
+
+/// Response to a Focus command, written in pieces.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FocusResponse {
+    command: Option<FocusCommand>,
+    step: usize,
+}
+
+impl FocusResponse {
+    /// Runs a Focus command `line`, and starts its response.
+    ///
+    /// Commands with arguments set the value instead, and only end the response. Returns `None`
+    /// if the line is not a supported Focus command.
+    pub fn start(line: &[u8]) -> Option<Self> {
+        let (command, args) = FocusCommand::parse(line)?;
+
+        let command = if args.is_empty() {
+            Some(command)
+        } else {
+            match command {
+                FocusCommand::LayerState => set_layer_state(args),
+                FocusCommand::Tuning(param) => set_tuning(param, args),
+                _ => (),
+            }
+
+            None
+        };
+
+        Some(Self { command, step: 0 })
+    }
+
+    /// Gets whether the whole response was written.
+    pub fn is_done(&self) -> bool {
+        self.token(self.step, &mut [0; FOCUS_TOKEN_LEN]).is_none()
+    }
+
+    /// Writes the next pieces of the response into the `buf`, and returns the number of bytes
+    /// written.
+    ///
+    /// Pieces are never split, so nothing is written if the next piece does not fit.
+    pub fn fill(&mut self, buf: &mut [u8]) -> usize {
+        let mut len = 0;
+        let mut token = [0u8; FOCUS_TOKEN_LEN];
+
+        while let Some(token_len) = self.token(self.step, &mut token) {
+            match buf.get_mut(len..len + token_len) {
+                Some(out) => out.copy_from_slice(&token[..token_len]),
+                None => break,
+            }
+
+            len += token_len;
+            self.step += 1;
+        }
+
+        len
+    }
+
+    // Writes the piece of the response at the `step`, and returns its length.
+    fn token(&self, step: usize, buf: &mut [u8; FOCUS_TOKEN_LEN]) -> Option<usize> {
+        let mut out = TokenWriter { buf, len: 0 };
+
+        // the number of pieces before the end of the response
+        let pieces = match self.command {
+            None => 0,
+            Some(FocusCommand::Help) => FocusCommand::ALL.len() + CONSOLE_COMMANDS.len(),
+            Some(FocusCommand::KeymapCustom) => KEYMAP_LEN,
+            Some(FocusCommand::LayerState) => NUM_LAYERS,
+            Some(_) => 1,
+        };
+
+        match (self.command, step) {
+            (_, step) if step == pieces => out.put(END_OF_RESPONSE),
+            (_, step) if step > pieces => return None,
+            (Some(FocusCommand::Help), step) => {
+                let name = FocusCommand::ALL
+                    .get(step)
+                    .map(|command| command.name())
+                    .or_else(|| {
+                        CONSOLE_COMMANDS
+                            .get(step - FocusCommand::ALL.len())
+                            .copied()
+                    })
+                    .unwrap_or_default();
+
+                out.put(name.as_bytes());
+                out.put(b"\r\n");
+            }
+            (Some(FocusCommand::Version), _) => {
+                for (i, field) in FIRMWARE_INFO.fields().iter().enumerate() {
+                    if i > 0 {
+                        out.put(b" ");
+                    }
+
+                    out.put(field.as_bytes());
+                }
+
+                out.put(b"\r\n");
+            }
+            (Some(FocusCommand::KeymapCustom), step) => {
+                let layer = step / (ROWS * COLS);
+                let key = layers::layer_key(layer, step % (ROWS * COLS)).unwrap_or(0);
+
+                out.put_u16(focus_key(key));
+                out.put(separator(step, KEYMAP_LEN));
+            }
+            (Some(FocusCommand::LayerState), step) => {
+                let active = step == 0 || step == layers::active_layer().index();
+
+                out.put(if active { b"1" } else { b"0" });
+                out.put(separator(step, NUM_LAYERS));
+            }
+            (Some(FocusCommand::SettingsValid), _) => out.put(b"true\r\n"),
+            (Some(FocusCommand::SettingsVersion), _) => {
+                out.put_u16(FOCUS_SETTINGS_VERSION as u16);
+                out.put(b"\r\n");
+            }
+            (Some(FocusCommand::Tuning(param)), _) => {
+                out.put_u16(tuning::tuning(param));
+                out.put(b"\r\n");
+            }
+            (None, _) => return None,
+        }
+
+        Some(out.len)
+    }
+}
+
+// Gets the separator after the value at `step` of a line with `len` values.
+fn separator(step: usize, len: usize) -> &'static [u8] {
+    if step + 1 == len {
+        b"\r\n"
+    } else {
+        b" "
+    }
+}
+
+// Activates the highest layer set in a `layer.state` argument list, e.g. `1 0 1 0`.
+fn set_layer_state(args: &[u8]) {
+    let layer = args
+        .split(|&b| b == b' ')
+        .filter(|arg| !arg.is_empty())
+        .take(NUM_LAYERS)
+        .enumerate()
+        .filter(|(_, arg)| *arg == b"1")
+        .map(|(layer, _)| layer)
+        .last()
+        .unwrap_or(0);
+
+    layers::set_active_layer(Layer::from(layer));
+}
+
+// Sets a tuning parameter from a decimal argument, ignoring invalid values.
+fn set_tuning(param: Tuning, args: &[u8]) {
+    let val = core::str::from_utf8(args)
+        .ok()
+        .and_then(|arg| arg.parse::<u16>().ok());
+
+    if let Some(val) = val {
+        tuning::set_tuning(param, val).ok();
+    }
+}
+
+// Writes response pieces, cutting anything that does not fit.
+struct TokenWriter<'a> {
+    buf: &'a mut [u8; FOCUS_TOKEN_LEN],
+    len: usize,
+}
+
+impl TokenWriter<'_> {
+    fn put(&mut self, bytes: &[u8]) {
+        let len = bytes.len().min(FOCUS_TOKEN_LEN - self.len);
+
+        self.buf[self.len..self.len + len].copy_from_slice(&bytes[..len]);
+        self.len += len;
+    }
+
+    // Writes a `u16` as decimal digits, without leading zeros.
+    fn put_u16(&mut self, val: u16) {
+        let mut digits = [0u8; 5];
+        let mut start = digits.len();
+        let mut val = val;
+
+        loop {
+            start -= 1;
+            digits[start] = b'0' + (val % 10) as u8;
+            val /= 10;
+
+            if val == 0 {
+                break;
+            }
+        }
+
+        self.put(&digits[start..]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::{A, BTN0, CTRL, EXCL, MD_VOL_UP, OS_SHIFT, PLUS, USER0, WWW_BACK};
+
+    fn response(line: &[u8], buf: &mut [u8]) -> usize {
+        let mut response = FocusResponse::start(line).unwrap();
+        let len = response.fill(buf);
+
+        assert!(response.is_done());
+        len
+    }
+
+    #[test]
+    fn test_focus_command() {
+        assert_eq!(
+            FocusCommand::parse(b"help"),
+            Some((FocusCommand::Help, &b""[..]))
+        );
+        assert_eq!(
+            FocusCommand::parse(b"layer.state 1 0 1 0\r"),
+            Some((FocusCommand::LayerState, &b"1 0 1 0"[..]))
+        );
+        assert_eq!(
+            FocusCommand::parse(b"settings.valid?"),
+            Some((FocusCommand::SettingsValid, &b""[..]))
+        );
+        assert_eq!(FocusCommand::parse(b"diag"), None);
+        assert_eq!(FocusCommand::parse(b"keymap"), None);
+    }
+
+    #[test]
+    fn test_focus_key() {
+        assert_eq!(focus_key(0), 0);
+        assert_eq!(focus_key(A), 4);
+        assert_eq!(focus_key(CTRL), 0xe0);
+        assert_eq!(focus_key(TRANS), 65535);
+        // LSHIFT(Key_1)
+        assert_eq!(focus_key(EXCL), 2078);
+        // ShiftToLayer(1), LockLayer(2)
+        assert_eq!(focus_key(FUN), 17451);
+        assert_eq!(focus_key(UPPER), 17410);
+        // Consumer_VolumeIncrement, Consumer_AC_Back
+        assert_eq!(focus_key(MD_VOL_UP), 18665);
+        assert_eq!(focus_key(WWW_BACK), 0x4a24);
+        assert_eq!(focus_key(USER0), 0);
+        assert_eq!(focus_key(BTN0), 0);
+        assert_eq!(focus_key(OS_SHIFT), 0);
+        assert_eq!(focus_key(PLUS), 2094);
+    }
+
+    #[test]
+    fn test_focus_response() {
+        let mut buf = [0u8; 4096];
+
+        let len = response(b"settings.version", &mut buf);
+        assert_eq!(&buf[..len], b"1\r\n.\r\n");
+
+        let len = response(b"help", &mut buf);
+        assert!(buf[..len].starts_with(b"help\r\nversion\r\nkeymap.custom\r\n"));
+        assert!(buf[..len].ends_with(b"load\r\n.\r\n"));
+
+        // Q W E R T, then the blank keys between the halves
+        let len = response(b"keymap.custom", &mut buf);
+        assert!(buf[..len].starts_with(b"20 26 8 21 23 0 0 "));
+        assert_eq!(
+            buf[..len].iter().filter(|&&b| b == b' ').count(),
+            KEYMAP_LEN - 1
+        );
+        assert!(buf[..len].ends_with(b"\r\n.\r\n"));
+
+        // writes only end the response
+        let len = response(b"keymap.custom 4 5 6", &mut buf);
+        assert_eq!(&buf[..len], END_OF_RESPONSE);
+
+        let len = response(b"mousekeys.speed 12", &mut buf);
+        assert_eq!(&buf[..len], END_OF_RESPONSE);
+        let len = response(b"mousekeys.speed", &mut buf);
+        assert_eq!(&buf[..len], b"12\r\n.\r\n");
+    }
+
+    #[test]
+    fn test_focus_response_pieces() {
+        let mut response = FocusResponse::start(b"keymap.custom").unwrap();
+        let mut buf = [0u8; 4];
+
+        // pieces are never split
+        assert_eq!(response.fill(&mut buf), 3);
+        assert_eq!(&buf[..3], b"20 ");
+        assert_eq!(response.fill(&mut buf[..2]), 0);
+        assert!(!response.is_done());
+    }
+}
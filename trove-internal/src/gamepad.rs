@@ -0,0 +1,129 @@
+//! Gamepad keys.
+//!
+//! Lets a layer act as a game controller, e.g. for gaming or as an accessibility controller. The
+//! [BTN0](crate::layers::BTN0) - [BTN15](crate::layers::BTN15) keys press gamepad buttons, and
+//! the joystick keys ([JOY_UP](crate::layers::JOY_UP), etc.) move the X/Y axes to their limits.
+//!
+//! Gamepad keys are sent in a [GamepadReport] on an optional HID interface, instead of the
+//! keyboard reports.
+
+use crate::layers::{self, BTN0, JOY_DOWN, JOY_LEFT, JOY_RIGHT, JOY_UP};
+
+/// Length of a [GamepadReport] in bytes.
+pub const GAMEPAD_REPORT_LEN: usize = 4;
+
+/// Axis value of a held joystick key.
+pub const AXIS_MAX: i8 = 127;
+
+/// HID report descriptor for the [GamepadReport] interface.
+pub const GAMEPAD_REPORT_DESCRIPTOR: &[u8] = &[
+    0x05, 0x01, // Usage Page (Generic Desktop)
+    0x09, 0x05, // Usage (Game Pad)
+    0xa1, 0x01, // Collection (Application)
+    0x05, 0x09, //   Usage Page (Button)
+    0x19, 0x01, //   Usage Minimum (1)
+    0x29, 0x10, //   Usage Maximum (16)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x25, 0x01, //   Logical Maximum (1)
+    0x75, 0x01, //   Report Size (1)
+    0x95, 0x10, //   Report Count (16)
+    0x81, 0x02, //   Input (Data, Variable, Absolute)
+    0x05, 0x01, //   Usage Page (Generic Desktop)
+    0x09, 0x30, //   Usage (X)
+    0x09, 0x31, //   Usage (Y)
+    0x15, 0x81, //   Logical Minimum (-127)
+    0x25, 0x7f, //   Logical Maximum (127)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x02, //   Report Count (2)
+    0x81, 0x02, //   Input (Data, Variable, Absolute)
+    0xc0, // End Collection
+];
+
+/// Represents a gamepad report, with sixteen buttons and an X/Y joystick.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GamepadReport {
+    buttons: u16,
+    x: i8,
+    y: i8,
+}
+
+impl GamepadReport {
+    /// Creates a new (blank) [GamepadReport].
+    pub const fn new() -> Self {
+        Self {
+            buttons: 0,
+            x: 0,
+            y: 0,
+        }
+    }
+
+    /// Gets the pressed buttons, one bit for each button.
+    pub const fn buttons(&self) -> u16 {
+        self.buttons
+    }
+
+    /// Gets the X axis.
+    pub const fn x(&self) -> i8 {
+        self.x
+    }
+
+    /// Gets the Y axis.
+    pub const fn y(&self) -> i8 {
+        self.y
+    }
+
+    /// Adds a held gamepad `key` to the report.
+    ///
+    /// Opposite joystick keys cancel out. Returns `false` if the key is not a gamepad key.
+    pub fn add_key(&mut self, key: u8) -> bool {
+        match key {
+            JOY_UP => self.y = self.y.saturating_sub(AXIS_MAX),
+            JOY_DOWN => self.y = self.y.saturating_add(AXIS_MAX),
+            JOY_LEFT => self.x = self.x.saturating_sub(AXIS_MAX),
+            JOY_RIGHT => self.x = self.x.saturating_add(AXIS_MAX),
+            _ if layers::key_is_gamepad(key) => self.buttons |= 1 << (key - BTN0),
+            _ => return false,
+        }
+
+        true
+    }
+
+    /// Gets whether no button is pressed, and the joystick is centered.
+    pub const fn is_blank(&self) -> bool {
+        self.buttons == 0 && self.x == 0 && self.y == 0
+    }
+
+    /// Gets the report bytes sent to the host.
+    pub const fn to_bytes(&self) -> [u8; GAMEPAD_REPORT_LEN] {
+        let [lo, hi] = self.buttons.to_le_bytes();
+
+        [lo, hi, self.x as u8, self.y as u8]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::{A, BTN15};
+
+    #[test]
+    fn test_gamepad_report() {
+        let mut report = GamepadReport::new();
+
+        assert!(report.add_key(BTN0));
+        assert!(report.add_key(BTN15));
+        assert!(report.add_key(JOY_UP));
+        assert!(report.add_key(JOY_LEFT));
+        assert!(!report.add_key(A));
+
+        assert_eq!(report.buttons(), 0x8001);
+        assert_eq!((report.x(), report.y()), (-AXIS_MAX, -AXIS_MAX));
+        assert_eq!(report.to_bytes(), [0x01, 0x80, 0x81, 0x81]);
+
+        // opposite directions cancel out
+        assert!(report.add_key(JOY_RIGHT));
+        assert_eq!(report.x(), 0);
+
+        assert!(GamepadReport::new().is_blank());
+    }
+}
@@ -0,0 +1,60 @@
+//! Key matrix ghosting detection.
+//!
+//! Without a diode on every switch, holding three corners of a rectangle in the matrix lets
+//! current flow backwards through the held switches, so the fourth corner reads as pressed too.
+//! A scan can not tell which of the four keys is the phantom, so rows with a fully held rectangle
+//! are treated as blocked, and keep their last state until the ambiguity is resolved.
+//!
+//! Detection is optional: handwired and partially diode-less builds can enable it in the key
+//! scanner, while fully dioded boards do not need it.
+
+use crate::debounce::RowState;
+
+/// Gets whether the `row` shares two or more pressed columns with another row.
+///
+/// Any such row holds a rectangle of pressed keys, where one of the corners may be a ghost.
+pub fn row_has_ghost(rows: &[RowState], row: usize) -> bool {
+    let Some(&state) = rows.get(row) else {
+        return false;
+    };
+
+    if state.as_inner().count_ones() < 2 {
+        return false;
+    }
+
+    rows.iter()
+        .enumerate()
+        .any(|(i, &other)| i != row && (state & other).as_inner().count_ones() >= 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_row_has_ghost() {
+        // three corners held, with the ghost in row 1 column 2
+        let rows = [
+            RowState::from_u16(0b0101),
+            RowState::from_u16(0b0101),
+            RowState::from_u16(0b0010),
+        ];
+
+        assert!(row_has_ghost(&rows, 0));
+        assert!(row_has_ghost(&rows, 1));
+        assert!(!row_has_ghost(&rows, 2));
+        assert!(!row_has_ghost(&rows, 3));
+
+        // keys in the same column, or the same row, are unambiguous
+        let rows = [
+            RowState::from_u16(0b0011),
+            RowState::from_u16(0b0001),
+            RowState::from_u16(0b0001),
+        ];
+
+        assert!(!rows
+            .iter()
+            .enumerate()
+            .any(|(i, _)| row_has_ghost(&rows, i)));
+    }
+}
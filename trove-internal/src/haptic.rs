@@ -0,0 +1,191 @@
+//! Haptic feedback on key presses.
+//!
+//! Builds with silent switches can add a solenoid or a vibration motor, driven from a GPIO pin
+//! through a transistor. [haptic_key_event] is a [KeyEventHook](crate::hooks::KeyEventHook)
+//! recording key presses, and the [Haptic] fires a short pulse for them from the main loop:
+//!
+//! - only on the layers enabled in the [HapticConfig]
+//! - at most once per cooldown, so fast typing does not overheat a solenoid
+//!
+//! Presses during the cooldown are dropped, not delayed.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::events::KeyEvent;
+use crate::layers::{Layer, NUM_LAYERS};
+use crate::scheduler::Timeout;
+
+/// Default length (in milliseconds) of a pulse.
+pub const DEFAULT_PULSE_MS: u16 = 10;
+/// Default time (in milliseconds) from the start of a pulse to the next.
+pub const DEFAULT_COOLDOWN_MS: u16 = 40;
+
+static KEY_PRESSED: AtomicBool = AtomicBool::new(false);
+
+/// Records a key press for the [Haptic], register as a [KeyEventHook](crate::hooks::KeyEventHook).
+pub fn haptic_key_event(event: &KeyEvent, _key: u8) {
+    if event.pressed() {
+        KEY_PRESSED.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Takes the key press recorded by [haptic_key_event].
+pub fn take_key_press() -> bool {
+    let pressed = KEY_PRESSED.load(Ordering::Relaxed);
+
+    if pressed {
+        KEY_PRESSED.store(false, Ordering::SeqCst);
+    }
+
+    pressed
+}
+
+/// Pulse length, cooldown, and enabled layers of a [Haptic].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HapticConfig {
+    pulse_ms: u16,
+    cooldown_ms: u16,
+    layers: u8,
+}
+
+impl HapticConfig {
+    /// Creates a new [HapticConfig], with the default timing, enabled on every layer.
+    pub const fn new() -> Self {
+        Self {
+            pulse_ms: DEFAULT_PULSE_MS,
+            cooldown_ms: DEFAULT_COOLDOWN_MS,
+            layers: (1 << NUM_LAYERS) - 1,
+        }
+    }
+
+    /// Gets the length (in milliseconds) of a pulse.
+    pub const fn pulse_ms(&self) -> u16 {
+        self.pulse_ms
+    }
+
+    /// Builder function that sets the length (in milliseconds) of a pulse.
+    pub const fn with_pulse_ms(mut self, val: u16) -> Self {
+        self.pulse_ms = val;
+        self
+    }
+
+    /// Gets the time (in milliseconds) from the start of a pulse to the next.
+    pub const fn cooldown_ms(&self) -> u16 {
+        self.cooldown_ms
+    }
+
+    /// Builder function that sets the time (in milliseconds) from the start of a pulse to the
+    /// next.
+    ///
+    /// Cooldowns shorter than the pulse are extended to the pulse.
+    pub const fn with_cooldown_ms(mut self, val: u16) -> Self {
+        self.cooldown_ms = val;
+        self
+    }
+
+    /// Gets whether key presses fire a pulse on the `layer`.
+    pub const fn enabled_on(&self, layer: Layer) -> bool {
+        self.layers & (1 << layer.index()) != 0
+    }
+
+    /// Builder function that sets whether key presses fire a pulse on the `layer`.
+    pub const fn with_layer(mut self, layer: Layer, val: bool) -> Self {
+        if val {
+            self.layers |= 1 << layer.index();
+        } else {
+            self.layers &= !(1 << layer.index());
+        }
+        self
+    }
+}
+
+impl Default for HapticConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Fires pulses for key presses, see the [module](self) docs.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Haptic {
+    config: HapticConfig,
+    pulse: Timeout,
+    cooldown: Timeout,
+}
+
+impl Haptic {
+    /// Creates a new [Haptic] with the [HapticConfig].
+    pub const fn new(config: HapticConfig) -> Self {
+        Self {
+            config,
+            pulse: Timeout::new(),
+            cooldown: Timeout::new(),
+        }
+    }
+
+    /// Gets the [HapticConfig].
+    pub const fn config(&self) -> &HapticConfig {
+        &self.config
+    }
+
+    /// Sets the [HapticConfig].
+    pub fn set_config(&mut self, config: HapticConfig) {
+        self.config = config;
+    }
+
+    /// Gets whether a pulse is running.
+    pub const fn is_active(&self) -> bool {
+        self.pulse.is_pending()
+    }
+
+    /// Fires a pulse at the time `now_ms` if a key was `pressed` on the `layer`, and ends the
+    /// running pulse once it is over.
+    ///
+    /// Returns whether the output is on.
+    pub fn update(&mut self, now_ms: u16, pressed: bool, layer: Layer) -> bool {
+        self.pulse.poll(now_ms);
+        self.cooldown.poll(now_ms);
+
+        if pressed && self.config.enabled_on(layer) && !self.cooldown.is_pending() {
+            let cooldown_ms = self.config.cooldown_ms.max(self.config.pulse_ms);
+
+            self.pulse.start(now_ms, self.config.pulse_ms);
+            self.cooldown.start(now_ms, cooldown_ms);
+        }
+
+        self.pulse.is_pending()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_haptic() {
+        let config = HapticConfig::new().with_layer(Layer::Fun, false);
+        assert!(config.enabled_on(Layer::Base));
+        assert!(!config.enabled_on(Layer::Fun));
+
+        let mut haptic = Haptic::new(config);
+        assert!(!haptic.update(0, false, Layer::Base));
+
+        assert!(haptic.update(100, true, Layer::Base));
+        assert!(haptic.update(105, false, Layer::Base));
+        assert!(!haptic.update(110, false, Layer::Base));
+
+        // dropped during the cooldown
+        assert!(!haptic.update(120, true, Layer::Base));
+        assert!(haptic.update(140, true, Layer::Base));
+        assert!(!haptic.update(150, false, Layer::Base));
+
+        // disabled layer
+        assert!(!haptic.update(200, true, Layer::Fun));
+
+        haptic_key_event(&KeyEvent::new(0, 0, false, 0), 0);
+        assert!(!take_key_press());
+        haptic_key_event(&KeyEvent::new(0, 0, true, 0), 0);
+        assert!(take_key_press());
+        assert!(!take_key_press());
+    }
+}
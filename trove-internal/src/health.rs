@@ -0,0 +1,226 @@
+//! Keyboard health monitoring.
+//!
+//! Measures the achieved matrix scan frequency and the USB report success rate over a rolling
+//! window, so degraded scanning can be detected and reported to the host.
+//!
+//! Counters are updated with plain loads and stores, since the AVR target has no atomic
+//! read-modify-write operations. Scans and reports are recorded from the USB interrupts, or with
+//! interrupts disabled, and the window is advanced from the scan timer interrupt. AVR interrupts
+//! do not nest, so the updates cannot interleave.
+
+use core::sync::atomic::{AtomicBool, AtomicU16, AtomicU8, Ordering};
+
+/// Length of a serialized [HealthReport].
+pub const HEALTH_REPORT_LEN: usize = 8;
+
+/// Default minimum scan frequency (in Hz) before scanning is considered degraded.
+pub const DEFAULT_MIN_SCAN_RATE: u16 = 250;
+
+/// Default scan timer interval in microseconds.
+pub const DEFAULT_TICK_INTERVAL_US: u16 = 1500;
+
+static TICK_INTERVAL_US: AtomicU16 = AtomicU16::new(DEFAULT_TICK_INTERVAL_US);
+static MIN_SCAN_RATE: AtomicU16 = AtomicU16::new(DEFAULT_MIN_SCAN_RATE);
+
+static TICKS: AtomicU16 = AtomicU16::new(0);
+static SCANS: AtomicU16 = AtomicU16::new(0);
+static REPORTS_SENT: AtomicU16 = AtomicU16::new(0);
+static REPORTS_FAILED: AtomicU16 = AtomicU16::new(0);
+
+static SCAN_RATE: AtomicU16 = AtomicU16::new(0);
+static LAST_SENT: AtomicU16 = AtomicU16::new(0);
+static LAST_FAILED: AtomicU16 = AtomicU16::new(0);
+static REPORT_SUCCESS: AtomicU8 = AtomicU8::new(100);
+static DEGRADED: AtomicBool = AtomicBool::new(false);
+
+/// Snapshot of the keyboard health over the last complete window.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct HealthReport {
+    scan_rate: u16,
+    reports_sent: u16,
+    reports_failed: u16,
+    report_success: u8,
+    degraded: bool,
+}
+
+impl HealthReport {
+    /// Gets the achieved scan frequency in Hz.
+    pub const fn scan_rate(&self) -> u16 {
+        self.scan_rate
+    }
+
+    /// Gets the number of reports accepted by the USB endpoint.
+    pub const fn reports_sent(&self) -> u16 {
+        self.reports_sent
+    }
+
+    /// Gets the number of reports rejected by the USB endpoint.
+    pub const fn reports_failed(&self) -> u16 {
+        self.reports_failed
+    }
+
+    /// Gets the percentage of reports accepted by the USB endpoint.
+    pub const fn report_success(&self) -> u8 {
+        self.report_success
+    }
+
+    /// Gets whether the scan frequency dropped below the configured minimum.
+    pub const fn degraded(&self) -> bool {
+        self.degraded
+    }
+
+    /// Serializes the [HealthReport] for sending to the host.
+    ///
+    /// Multi-byte fields are little-endian.
+    pub fn to_bytes(&self) -> [u8; HEALTH_REPORT_LEN] {
+        let rate = self.scan_rate.to_le_bytes();
+        let sent = self.reports_sent.to_le_bytes();
+        let failed = self.reports_failed.to_le_bytes();
+
+        [
+            rate[0],
+            rate[1],
+            sent[0],
+            sent[1],
+            failed[0],
+            failed[1],
+            self.report_success,
+            self.degraded as u8,
+        ]
+    }
+}
+
+/// Gets the interval (in microseconds) between calls to [tick].
+pub fn tick_interval_us() -> u16 {
+    TICK_INTERVAL_US.load(Ordering::Relaxed)
+}
+
+/// Sets the interval (in microseconds) between calls to [tick].
+///
+/// Changing the interval restarts the scan frequency measurement window, so it only ever
+/// measures a single interval.
+pub fn set_tick_interval_us(val: u16) {
+    let val = val.max(1);
+
+    if val != tick_interval_us() {
+        TICK_INTERVAL_US.store(val, Ordering::SeqCst);
+        TICKS.store(0, Ordering::SeqCst);
+        SCANS.store(0, Ordering::SeqCst);
+    }
+}
+
+/// Gets the minimum scan frequency (in Hz) before scanning is considered degraded.
+pub fn min_scan_rate() -> u16 {
+    MIN_SCAN_RATE.load(Ordering::Relaxed)
+}
+
+/// Sets the minimum scan frequency (in Hz) before scanning is considered degraded.
+///
+/// The minimum is capped at half the scan timer frequency, so slowed scan intervals (e.g. from an
+/// [AdaptiveScanRate](crate::scan_rate::AdaptiveScanRate)) are not reported as degraded.
+pub fn set_min_scan_rate(val: u16) {
+    MIN_SCAN_RATE.store(val, Ordering::SeqCst);
+}
+
+/// Records a completed matrix scan.
+pub fn record_scan() {
+    increment(&SCANS);
+}
+
+/// Records the result of pushing a report to the USB endpoint.
+pub fn record_report(sent: bool) {
+    if sent {
+        increment(&REPORTS_SENT);
+    } else {
+        increment(&REPORTS_FAILED);
+    }
+}
+
+/// Advances the measurement window by one scan timer interval.
+///
+/// Once per second, the counters are converted into rates and reset.
+pub fn tick() {
+    let interval = TICK_INTERVAL_US.load(Ordering::Relaxed) as u32;
+    let ticks = TICKS.load(Ordering::Relaxed).saturating_add(1);
+
+    if (ticks as u32) * interval < 1_000_000 {
+        TICKS.store(ticks, Ordering::SeqCst);
+        return;
+    }
+
+    let elapsed_us = (ticks as u32) * interval;
+    let scans = take(&SCANS) as u32;
+    let scan_rate = ((scans * 1_000_000) / elapsed_us) as u16;
+
+    let sent = take(&REPORTS_SENT);
+    let failed = take(&REPORTS_FAILED);
+    let total = sent as u32 + failed as u32;
+    let success = (sent as u32 * 100).checked_div(total).unwrap_or(100) as u8;
+
+    SCAN_RATE.store(scan_rate, Ordering::SeqCst);
+    LAST_SENT.store(sent, Ordering::SeqCst);
+    LAST_FAILED.store(failed, Ordering::SeqCst);
+    REPORT_SUCCESS.store(success, Ordering::SeqCst);
+    let tick_rate = (1_000_000 / interval) as u16;
+    DEGRADED.store(
+        scan_rate < min_scan_rate().min(tick_rate / 2),
+        Ordering::SeqCst,
+    );
+    TICKS.store(0, Ordering::SeqCst);
+}
+
+/// Gets whether the scan frequency dropped below the configured minimum in the last window.
+pub fn is_degraded() -> bool {
+    DEGRADED.load(Ordering::Relaxed)
+}
+
+/// Gets the [HealthReport] for the last complete window.
+pub fn health_report() -> HealthReport {
+    HealthReport {
+        scan_rate: SCAN_RATE.load(Ordering::Relaxed),
+        reports_sent: LAST_SENT.load(Ordering::Relaxed),
+        reports_failed: LAST_FAILED.load(Ordering::Relaxed),
+        report_success: REPORT_SUCCESS.load(Ordering::Relaxed),
+        degraded: is_degraded(),
+    }
+}
+
+fn increment(counter: &AtomicU16) {
+    counter.store(
+        counter.load(Ordering::Relaxed).saturating_add(1),
+        Ordering::SeqCst,
+    );
+}
+
+fn take(counter: &AtomicU16) -> u16 {
+    let val = counter.load(Ordering::Relaxed);
+    counter.store(0, Ordering::SeqCst);
+    val
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_health_window() {
+        set_tick_interval_us(1000);
+        set_min_scan_rate(500);
+
+        for i in 0..1000 {
+            if i % 4 == 0 {
+                record_scan();
+                record_report(i % 8 == 0);
+            }
+            tick();
+        }
+
+        let report = health_report();
+        assert_eq!(report.scan_rate(), 250);
+        assert_eq!(report.reports_sent(), 125);
+        assert_eq!(report.reports_failed(), 125);
+        assert_eq!(report.report_success(), 50);
+        assert!(report.degraded());
+        assert_eq!(report.to_bytes(), [250, 0, 125, 0, 125, 0, 50, 1]);
+    }
+}
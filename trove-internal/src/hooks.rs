@@ -0,0 +1,162 @@
+//! Hook points in the key event path.
+//!
+//! Board code and feature modules (e.g. indicators, or logging) observe the firmware through
+//! [Hooks], instead of changing the key scanner:
+//!
+//! - [KeyEventHook]: every key press and release reaching the report stage of the
+//!   [pipeline](crate::pipeline), with the keycode it resolved to
+//! - [BeforeReportHook]: every boot keyboard report built from a matrix scan, before it is queued
+//! - [AfterReportHook]: every boot keyboard report accepted by the USB endpoint
+//!
+//! Up to [MAX_HOOKS] hooks can be registered at each point, and run in registration order. The
+//! report hooks run in the USB interrupts, so they should return quickly.
+
+use usbd_hid::descriptor::KeyboardReport;
+
+use crate::events::KeyEvent;
+
+/// Maximum number of hooks registered at each hook point.
+pub const MAX_HOOKS: usize = 4;
+
+/// Hook called with a debounced [KeyEvent], and the keycode of the key.
+///
+/// Presses pass the keycode resolved from the key map, releases the keycode the key held.
+pub type KeyEventHook = fn(event: &KeyEvent, key: u8);
+/// Hook called with a boot keyboard report built from a matrix scan, which it can modify.
+pub type BeforeReportHook = fn(report: &mut KeyboardReport);
+/// Hook called with a boot keyboard report accepted by the USB endpoint.
+pub type AfterReportHook = fn(report: &KeyboardReport);
+
+/// Hooks registered at each hook point.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Hooks {
+    on_key_event: [Option<KeyEventHook>; MAX_HOOKS],
+    before_report: [Option<BeforeReportHook>; MAX_HOOKS],
+    after_report_sent: [Option<AfterReportHook>; MAX_HOOKS],
+}
+
+impl Hooks {
+    /// Creates a new [Hooks], without any hooks.
+    pub const fn new() -> Self {
+        Self {
+            on_key_event: [None; MAX_HOOKS],
+            before_report: [None; MAX_HOOKS],
+            after_report_sent: [None; MAX_HOOKS],
+        }
+    }
+
+    /// Builder function that registers a [KeyEventHook].
+    ///
+    /// Hooks past [MAX_HOOKS] are dropped.
+    pub const fn with_on_key_event(mut self, hook: KeyEventHook) -> Self {
+        if let Some(i) = free_slot(&self.on_key_event) {
+            self.on_key_event[i] = Some(hook);
+        }
+        self
+    }
+
+    /// Builder function that registers a [BeforeReportHook].
+    ///
+    /// Hooks past [MAX_HOOKS] are dropped.
+    pub const fn with_before_report(mut self, hook: BeforeReportHook) -> Self {
+        if let Some(i) = free_slot(&self.before_report) {
+            self.before_report[i] = Some(hook);
+        }
+        self
+    }
+
+    /// Builder function that registers an [AfterReportHook].
+    ///
+    /// Hooks past [MAX_HOOKS] are dropped.
+    pub const fn with_after_report_sent(mut self, hook: AfterReportHook) -> Self {
+        if let Some(i) = free_slot(&self.after_report_sent) {
+            self.after_report_sent[i] = Some(hook);
+        }
+        self
+    }
+
+    /// Runs the [KeyEventHook]s.
+    pub fn on_key_event(&self, event: &KeyEvent, key: u8) {
+        for hook in self.on_key_event.iter().flatten() {
+            hook(event, key);
+        }
+    }
+
+    /// Runs the [BeforeReportHook]s.
+    pub fn before_report(&self, report: &mut KeyboardReport) {
+        for hook in self.before_report.iter().flatten() {
+            hook(report);
+        }
+    }
+
+    /// Runs the [AfterReportHook]s.
+    pub fn after_report_sent(&self, report: &KeyboardReport) {
+        for hook in self.after_report_sent.iter().flatten() {
+            hook(report);
+        }
+    }
+}
+
+// Finds the first free slot of a hook point.
+const fn free_slot<T>(hooks: &[Option<T>; MAX_HOOKS]) -> Option<usize> {
+    let mut i = 0;
+
+    while i < MAX_HOOKS {
+        if hooks[i].is_none() {
+            return Some(i);
+        }
+        i += 1;
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::sync::atomic::{AtomicU8, Ordering};
+
+    static LAST_KEY: AtomicU8 = AtomicU8::new(0);
+
+    fn record_key(event: &KeyEvent, key: u8) {
+        if event.pressed() {
+            LAST_KEY.store(key, Ordering::SeqCst);
+        }
+    }
+
+    fn add_shift(report: &mut KeyboardReport) {
+        report.modifier |= 0x02;
+    }
+
+    fn add_ctrl(report: &mut KeyboardReport) {
+        report.modifier |= 0x01;
+    }
+
+    #[test]
+    fn test_hooks() {
+        let hooks = Hooks::new()
+            .with_on_key_event(record_key)
+            .with_before_report(add_shift)
+            .with_before_report(add_ctrl);
+
+        hooks.on_key_event(&KeyEvent::new(0, 1, true, 0), 0x04);
+        hooks.on_key_event(&KeyEvent::new(0, 1, false, 10), 0x05);
+        assert_eq!(LAST_KEY.load(Ordering::Relaxed), 0x04);
+
+        let mut report = KeyboardReport {
+            modifier: 0,
+            reserved: 0,
+            leds: 0,
+            keycodes: [0; 6],
+        };
+        hooks.before_report(&mut report);
+        assert_eq!(report.modifier, 0x03);
+        hooks.after_report_sent(&report);
+
+        let mut full = Hooks::new();
+        for _ in 0..=MAX_HOOKS {
+            full = full.with_before_report(add_shift);
+        }
+        assert_eq!(full.before_report.iter().flatten().count(), MAX_HOOKS);
+    }
+}
@@ -0,0 +1,82 @@
+//! Host keyboard LED state.
+//!
+//! The host sends the state of its lock keys in the boot keyboard LED output report. The
+//! firmware keeps the last reported state, so features like the numpad layer, Caps Word, or
+//! indicator LEDs can follow the host instead of guessing.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use crate::audit::{self, Resource};
+
+bitfield! {
+    /// Lock key state of the host, from the LED output report.
+    #[derive(Clone, Copy, Debug, Default, PartialEq)]
+    pub struct HostLedState(u8);
+
+    /// Getter/setter for NumLock
+    pub num_lock, set_num_lock: 0;
+    /// Getter/setter for CapsLock
+    pub caps_lock, set_caps_lock: 1;
+    /// Getter/setter for ScrollLock
+    pub scroll_lock, set_scroll_lock: 2;
+    /// Getter/setter for Compose
+    pub compose, set_compose: 3;
+    /// Getter/setter for Kana
+    pub kana, set_kana: 4;
+}
+
+impl HostLedState {
+    /// Creates a new [HostLedState], with every lock off.
+    pub const fn new() -> Self {
+        Self(0)
+    }
+
+    /// Creates a new [HostLedState] from a `u8`.
+    pub const fn from_u8(val: u8) -> Self {
+        Self(val)
+    }
+
+    /// Gets the underlying integer representation of the [HostLedState].
+    pub const fn as_inner(&self) -> u8 {
+        self.0
+    }
+
+    /// Parses a boot keyboard LED output report.
+    ///
+    /// Returns `None` if the report is empty.
+    pub fn from_report(report: &[u8]) -> Option<Self> {
+        report.first().map(|&leds| Self::from_u8(leds))
+    }
+}
+
+/// Host LED state from the last LED output report.
+static HOST_LEDS: AtomicU8 = AtomicU8::new(0);
+
+/// Gets the [HostLedState] from the last LED output report.
+pub fn host_leds() -> HostLedState {
+    HostLedState::from_u8(HOST_LEDS.load(Ordering::Relaxed))
+}
+
+/// Sets the [HostLedState] from an LED output report.
+pub fn set_host_leds(leds: HostLedState) {
+    let _guard = audit::enter(Resource::HostLeds);
+    HOST_LEDS.store(leds.as_inner(), Ordering::SeqCst);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_host_led_state() {
+        let leds = HostLedState::from_report(&[0b0000_0110]).unwrap();
+
+        assert!(!leds.num_lock());
+        assert!(leds.caps_lock());
+        assert!(leds.scroll_lock());
+        assert!(!leds.compose());
+        assert!(!leds.kana());
+
+        assert_eq!(HostLedState::from_report(&[]), None);
+    }
+}
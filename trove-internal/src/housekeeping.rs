@@ -0,0 +1,131 @@
+//! Main loop housekeeping.
+//!
+//! Work that is not time-critical (e.g. LED effects, EEPROM writes, or flushing logs) runs as
+//! [IdleTask]s from the main loop, outside of interrupt context, instead of lengthening the
+//! interrupts. The main loop runs the [Housekeeping] tasks after every matrix scan, and before
+//! going back to sleep.
+//!
+//! An interrupt with work for the tasks calls [wake_main_loop], so the main loop runs them once
+//! more before sleeping, even while the matrix is not scanned.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Maximum number of [IdleTask]s in a [Housekeeping].
+pub const MAX_IDLE_TASKS: usize = 8;
+
+static WAKE_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Task run from the main loop, with the time (in milliseconds) of the run.
+///
+/// Tasks share the main loop cooperatively, so they should do a bounded amount of work per run.
+pub type IdleTask = fn(now_ms: u16);
+
+/// Asks the main loop to run the [Housekeeping] tasks before it sleeps again.
+///
+/// Call from interrupts, e.g. once data for an [IdleTask] is ready.
+pub fn wake_main_loop() {
+    WAKE_REQUESTED.store(true, Ordering::SeqCst);
+}
+
+/// Gets whether an interrupt asked for a [Housekeeping] run, without clearing the request.
+///
+/// Check with interrupts disabled right before sleeping, the main loop must not sleep while set.
+pub fn wake_requested() -> bool {
+    WAKE_REQUESTED.load(Ordering::Relaxed)
+}
+
+/// Takes the request of [wake_main_loop].
+pub fn take_wake_request() -> bool {
+    let requested = wake_requested();
+
+    if requested {
+        WAKE_REQUESTED.store(false, Ordering::SeqCst);
+    }
+
+    requested
+}
+
+/// Up to [MAX_IDLE_TASKS] [IdleTask]s, run in registration order.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Housekeeping {
+    tasks: [Option<IdleTask>; MAX_IDLE_TASKS],
+}
+
+impl Housekeeping {
+    /// Creates a new [Housekeeping], without tasks.
+    pub const fn new() -> Self {
+        Self {
+            tasks: [None; MAX_IDLE_TASKS],
+        }
+    }
+
+    /// Gets the number of tasks.
+    pub fn len(&self) -> usize {
+        self.tasks.iter().flatten().count()
+    }
+
+    /// Gets whether there are no tasks.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Builder function that adds a task.
+    ///
+    /// Tasks past [MAX_IDLE_TASKS] are dropped.
+    pub const fn with_task(mut self, task: IdleTask) -> Self {
+        let mut i = 0;
+
+        while i < MAX_IDLE_TASKS {
+            if self.tasks[i].is_none() {
+                self.tasks[i] = Some(task);
+                break;
+            }
+            i += 1;
+        }
+
+        self
+    }
+
+    /// Runs every task at the time `now_ms`.
+    pub fn run(&self, now_ms: u16) {
+        for task in self.tasks.iter().flatten() {
+            task(now_ms);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::sync::atomic::AtomicU16;
+
+    static LAST_RUN: AtomicU16 = AtomicU16::new(0);
+    static RUNS: AtomicU16 = AtomicU16::new(0);
+
+    fn record_run(now_ms: u16) {
+        LAST_RUN.store(now_ms, Ordering::SeqCst);
+    }
+
+    fn count_run(_now_ms: u16) {
+        RUNS.store(RUNS.load(Ordering::Relaxed) + 1, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn test_housekeeping() {
+        let tasks = Housekeeping::new()
+            .with_task(record_run)
+            .with_task(count_run);
+        assert_eq!(tasks.len(), 2);
+
+        tasks.run(42);
+        tasks.run(43);
+        assert_eq!(LAST_RUN.load(Ordering::Relaxed), 43);
+        assert_eq!(RUNS.load(Ordering::Relaxed), 2);
+
+        assert!(!take_wake_request());
+        wake_main_loop();
+        assert!(wake_requested());
+        assert!(take_wake_request());
+        assert!(!wake_requested());
+    }
+}
@@ -0,0 +1,113 @@
+//! HID idle rate.
+//!
+//! Hosts choose how often an unchanged keyboard report is repeated with a `SET_IDLE` request, and
+//! read it back with `GET_IDLE`. The idle duration is sent in units of 4 milliseconds, and a
+//! duration of `0` only sends reports when they change.
+//!
+//! Most operating systems disable the repeats, but some BIOS and UEFI implementations rely on
+//! them to notice held keys.
+
+/// `GET_IDLE` HID class request code.
+pub const GET_IDLE: u8 = 0x02;
+/// `SET_IDLE` HID class request code.
+pub const SET_IDLE: u8 = 0x0a;
+/// Milliseconds per unit of the idle duration.
+pub const IDLE_UNIT_MS: u16 = 4;
+/// Default idle duration for keyboards (500 ms), as recommended by the HID specification.
+pub const DEFAULT_IDLE_DURATION: u8 = 125;
+
+/// Tracks the negotiated idle duration, and when the last report was sent.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct IdleRate {
+    duration: u8,
+    last_report_ms: u16,
+}
+
+impl IdleRate {
+    /// Creates a new [IdleRate] with the [DEFAULT_IDLE_DURATION].
+    pub const fn new() -> Self {
+        Self {
+            duration: DEFAULT_IDLE_DURATION,
+            last_report_ms: 0,
+        }
+    }
+
+    /// Gets the idle duration, in units of [IDLE_UNIT_MS].
+    pub const fn duration(&self) -> u8 {
+        self.duration
+    }
+
+    /// Sets the idle duration, in units of [IDLE_UNIT_MS].
+    ///
+    /// A duration of `0` disables the repeats.
+    pub fn set_duration(&mut self, val: u8) {
+        self.duration = val;
+    }
+
+    /// Builder function that sets the idle duration, in units of [IDLE_UNIT_MS].
+    pub fn with_duration(mut self, val: u8) -> Self {
+        self.set_duration(val);
+        self
+    }
+
+    /// Gets the time (in milliseconds) between repeats of an unchanged report.
+    ///
+    /// Returns `None` if the repeats are disabled.
+    pub const fn rate_ms(&self) -> Option<u16> {
+        match self.duration {
+            0 => None,
+            duration => Some(duration as u16 * IDLE_UNIT_MS),
+        }
+    }
+
+    /// Records that a report was sent at `now_ms`.
+    pub fn record_report(&mut self, now_ms: u16) {
+        self.last_report_ms = now_ms;
+    }
+
+    /// Gets whether the last report is due to be repeated at `now_ms`.
+    pub fn repeat_due(&self, now_ms: u16) -> bool {
+        self.rate_ms()
+            .is_some_and(|rate| now_ms.wrapping_sub(self.last_report_ms) >= rate)
+    }
+
+    /// Restores the [DEFAULT_IDLE_DURATION], e.g. after a USB bus reset.
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+impl Default for IdleRate {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_idle_rate() {
+        let mut idle = IdleRate::new();
+
+        assert_eq!(idle.rate_ms(), Some(500));
+
+        idle.record_report(100);
+        assert!(!idle.repeat_due(599));
+        assert!(idle.repeat_due(600));
+
+        // the timer wraps around
+        idle.record_report(u16::MAX - 100);
+        assert!(!idle.repeat_due(398));
+        assert!(idle.repeat_due(399));
+
+        // reports are only sent on changes
+        idle.set_duration(0);
+        assert_eq!(idle.rate_ms(), None);
+        assert!(!idle.repeat_due(u16::MAX));
+
+        idle.reset();
+        assert_eq!(idle.duration(), DEFAULT_IDLE_DURATION);
+    }
+}
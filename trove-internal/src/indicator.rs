@@ -0,0 +1,142 @@
+//! Lock indicator LEDs.
+//!
+//! Boards can light an LED for a host lock (e.g. Caps Lock), following the
+//! [host LED state](crate::host_leds) instead of guessing from key presses, so the indicator stays
+//! right when the lock is toggled from another keyboard.
+//!
+//! An [Indicator] assigns a lock to a pin. The LED can be wired to the pin or to VCC, e.g. the
+//! TX and RX LEDs of a Pro Micro are lit by driving their pins low.
+
+use crate::host_leds::HostLedState;
+use crate::pin_map::{PinId, PinMap, Port};
+
+/// Maximum number of [Indicator]s on a board.
+pub const MAX_INDICATORS: usize = 4;
+
+/// Pin of the TX LED on the Pro Micro (`PD5`), lit while driven low.
+pub const PRO_MICRO_TX_LED: PinId = PinId::new(Port::D, 5);
+/// Pin of the RX LED on the Pro Micro (`PB0`), lit while driven low.
+pub const PRO_MICRO_RX_LED: PinId = PinId::new(Port::B, 0);
+
+/// Represents a host lock shown by an [Indicator].
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum HostLock {
+    /// NumLock LED.
+    NumLock = 0,
+    /// CapsLock LED.
+    #[default]
+    CapsLock = 1,
+    /// ScrollLock LED.
+    ScrollLock = 2,
+    /// Compose LED.
+    Compose = 3,
+    /// Kana LED.
+    Kana = 4,
+}
+
+impl HostLock {
+    /// Gets whether the lock is on in the [HostLedState].
+    pub fn is_on(&self, leds: HostLedState) -> bool {
+        leds.as_inner() & (1 << *self as u8) != 0
+    }
+}
+
+/// Represents an LED on a pin, lit while a [HostLock] is on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Indicator {
+    pin: PinId,
+    lock: HostLock,
+    active_low: bool,
+}
+
+impl Indicator {
+    /// Creates a new [Indicator], with the LED lit while the `pin` is driven high.
+    pub const fn new(pin: PinId, lock: HostLock) -> Self {
+        Self {
+            pin,
+            lock,
+            active_low: false,
+        }
+    }
+
+    /// Creates a new [Indicator] on the TX LED of the Pro Micro.
+    pub const fn pro_micro_tx(lock: HostLock) -> Self {
+        Self::new(PRO_MICRO_TX_LED, lock).with_active_low(true)
+    }
+
+    /// Creates a new [Indicator] on the RX LED of the Pro Micro.
+    pub const fn pro_micro_rx(lock: HostLock) -> Self {
+        Self::new(PRO_MICRO_RX_LED, lock).with_active_low(true)
+    }
+
+    /// Gets the pin of the LED.
+    pub const fn pin(&self) -> PinId {
+        self.pin
+    }
+
+    /// Gets the [HostLock] shown by the LED.
+    pub const fn lock(&self) -> HostLock {
+        self.lock
+    }
+
+    /// Gets whether the LED is lit while the pin is driven low.
+    pub const fn active_low(&self) -> bool {
+        self.active_low
+    }
+
+    /// Builder function that sets whether the LED is lit while the pin is driven low.
+    pub const fn with_active_low(mut self, val: bool) -> Self {
+        self.active_low = val;
+        self
+    }
+
+    /// Gets whether the LED is lit in the [HostLedState].
+    pub fn is_lit(&self, leds: HostLedState) -> bool {
+        self.lock.is_on(leds)
+    }
+
+    /// Gets whether the pin is driven high in the [HostLedState].
+    pub fn pin_high(&self, leds: HostLedState) -> bool {
+        self.is_lit(leds) != self.active_low
+    }
+
+    /// Gets whether the pin is usable for the LED with the key matrix [PinMap].
+    ///
+    /// Pins of the key matrix, and pins not bonded out, are never driven.
+    pub fn is_valid(&self, map: &PinMap) -> bool {
+        self.pin.is_available() && !map.contains(&self.pin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_indicator() {
+        let mut leds = HostLedState::new();
+        leds.set_caps_lock(true);
+
+        let caps = Indicator::new(PinId::new(Port::B, 7), HostLock::CapsLock);
+        assert!(caps.is_lit(leds));
+        assert!(caps.pin_high(leds));
+        assert!(caps.is_valid(&PinMap::atreus()));
+
+        let scroll = Indicator::pro_micro_rx(HostLock::ScrollLock);
+        assert!(!scroll.is_lit(leds));
+        assert!(scroll.pin_high(leds));
+
+        leds.set_scroll_lock(true);
+        assert!(!scroll.pin_high(leds));
+
+        assert!(HostLock::NumLock.is_on(HostLedState::from_u8(0b1)));
+        assert!(HostLock::Kana.is_on(HostLedState::from_u8(0b1_0000)));
+
+        // the TX LED pin is a column of the Atreus
+        assert!(!Indicator::pro_micro_tx(HostLock::CapsLock).is_valid(&PinMap::atreus()));
+        assert!(
+            !Indicator::new(PinId::new(Port::E, 0), HostLock::CapsLock).is_valid(&PinMap::atreus())
+        );
+    }
+}
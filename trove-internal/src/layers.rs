@@ -7,9 +7,18 @@
 
 use core::sync::atomic::{AtomicU8, Ordering};
 
+use crate::audit::{self, Resource};
+use crate::diagnostics::{self, Diagnostic};
+
 mod key_defs;
+mod layer_lock;
+mod numpad;
+mod shape_shifter;
 
 pub use key_defs::*;
+pub use layer_lock::*;
+pub use numpad::*;
+pub use shape_shifter::*;
 
 /// Represents a layer selection.
 #[repr(u8)]
@@ -22,6 +31,8 @@ pub enum Layer {
     Fun = 1,
     /// Upper layer key layout.
     Upper = 2,
+    /// Numpad layer key layout.
+    Numpad = 3,
 }
 
 impl Layer {
@@ -40,6 +51,11 @@ impl Layer {
         Self::Upper
     }
 
+    /// Creates a numpad [Layer](Layer::Numpad).
+    pub const fn numpad() -> Self {
+        Self::Numpad
+    }
+
     /// Converts the [Layer] to a `usize`.
     pub const fn index(&self) -> usize {
         *self as usize
@@ -48,11 +64,12 @@ impl Layer {
 
 impl From<u8> for Layer {
     fn from(val: u8) -> Self {
-        match val % 3 {
+        match val {
             0 => Self::Base,
             1 => Self::Fun,
             2 => Self::Upper,
-            _ => Self::Base, // technically unreachable, but let's not panic
+            3 => Self::Numpad,
+            _ => Self::Base,
         }
     }
 }
@@ -87,7 +104,8 @@ impl From<Layer> for usize {
     }
 }
 
-pub type LayerKeys = [[u8; 12]; 4];
+/// Key table for a single layer, defaulting to the Atreus [ROWS] x [COLS] layout.
+pub type LayerKeys<const R: usize = ROWS, const C: usize = COLS> = [[u8; C]; R];
 
 /// Base layer of keys on the default Atreus layout.
 #[rustfmt::skip]
@@ -116,58 +134,139 @@ const LAYER2_KEYS: LayerKeys = [
     [ UPPER, VOL_DN, TRANS, TRANS, TRANS, TRANS, TRANS,  TRANS,  FUN, PRT_SC, SCR_LK, PLAY_PS ],
 ];
 
+/// Numpad layer of keys, with a keypad on the right half.
+///
+/// Every key of the other default layers is taken, so user key maps add a [NUMPAD] key to enter
+/// the layer, e.g. on the upper layer. The [NUMPAD] key on the layer leaves it again.
+#[rustfmt::skip]
+const LAYER3_KEYS: LayerKeys = [
+    [ TRANS,  TRANS, TRANS, TRANS, TRANS, 0,         0,  NUM_LOCK, KP7, KP8,    KP9, KP_MINUS ],
+    [ TRANS,  TRANS, TRANS, TRANS, TRANS, 0,         0,  KP_SLASH, KP4, KP5,    KP6,  KP_PLUS ],
+    [ NUMPAD, TRANS, TRANS, TRANS, TRANS, TRANS, TRANS,   KP_STAR, KP1, KP2,    KP3, KP_ENTER ],
+    [ TRANS,  TRANS, TRANS, TRANS, TRANS, TRANS, TRANS,     TRANS, FUN, KP0, KP_DOT, KP_EQUAL ],
+];
+
 /// Total number of layers.
-pub const NUM_LAYERS: usize = 3;
+pub const NUM_LAYERS: usize = 4;
 
 #[cfg(target_arch = "avr")]
 avr_progmem::progmem! {
     /// Collection of all the layers.
-    static progmem LAYERS: [LayerKeys; NUM_LAYERS] = [LAYER0_KEYS, LAYER1_KEYS, LAYER2_KEYS];
+    static progmem LAYERS: [LayerKeys; NUM_LAYERS] = [LAYER0_KEYS, LAYER1_KEYS, LAYER2_KEYS, LAYER3_KEYS];
 }
 
 /// Collection of all the layers.
 #[cfg(not(target_arch = "avr"))]
-static LAYERS: [LayerKeys; NUM_LAYERS] = [LAYER0_KEYS, LAYER1_KEYS, LAYER2_KEYS];
+static LAYERS: [LayerKeys; NUM_LAYERS] = [LAYER0_KEYS, LAYER1_KEYS, LAYER2_KEYS, LAYER3_KEYS];
 
 /// Currently active layer.
 static ACTIVE_LAYER: AtomicU8 = AtomicU8::new(0);
 
 /// Get the key for a given `layer` and `index` (both zero-indexed).
 ///
-/// The index is modulo the number of keys in a layer. For example, the Atreus has 4 rows of 12
-/// keys = 48 keys total (with 4 blank keys). So, any index at or above 48 will start wrapping
-/// around to the beginning.
-pub fn layer_key(layer: usize, index: usize) -> u8 {
-    // 0-47 => 0..3, mod 4 should be unneeded, but just in case...
-    let row = (index / 12) % 4;
-    // regardless of the row (since they are multiples of 12), this should give the column
-    let col = index % 12;
+/// Returns `None` if the layer is not one of the [NUM_LAYERS] defined layers, or the index is not
+/// one of the keys in a layer. For example, the Atreus has 4 rows of 12 keys = 48 keys total
+/// (with 4 blank keys), so any index at or above 48 is invalid.
+pub fn layer_key(layer: usize, index: usize) -> Option<u8> {
+    if layer >= NUM_LAYERS || index >= ROWS * COLS {
+        return None;
+    }
+
+    // 0-47 => 0..3
+    let row = index / COLS;
+    // regardless of the row (since they are multiples of COLS), this should give the column
+    let col = index % COLS;
 
     #[cfg(target_arch = "avr")]
-    let key_layer = LAYERS.load_at(layer & NUM_LAYERS);
+    let key_layer = LAYERS.load_at(layer);
     #[cfg(not(target_arch = "avr"))]
-    let key_layer = LAYERS[layer % NUM_LAYERS];
+    let key_layer = LAYERS[layer];
 
-    key_layer[row][col]
+    Some(key_layer[row][col])
 }
 
 /// Gets the key for a given `layer` and `index`, with pass-through for any transparent keys.
 ///
 /// Transparent keys will pass-through to the next lowest layer, until a non-transparent key is
 /// found, or the bottom layer is reached.
+///
+/// Layers beyond the defined layers fall back to the base layer, and indices beyond the keys in a
+/// layer produce no key. Both are counted as [diagnostics].
 pub fn passthrough_key(layer: usize, index: usize) -> u8 {
-    let key = layer_key(layer, index);
+    let layer = checked_layer(layer);
+
+    match layer_key(layer, index) {
+        Some(TRANS) if layer > 0 => passthrough_key(layer - 1, index),
+        Some(key) => key,
+        None => {
+            diagnostics::record(Diagnostic::InvalidKey);
+            0
+        }
+    }
+}
 
-    if key == TRANS && layer > 0 {
-        passthrough_key(layer - 1, index)
+/// Gets the `layer` if it is defined, otherwise records the invalid layer and falls back to the
+/// base layer.
+pub(crate) fn checked_layer(layer: usize) -> usize {
+    if layer < NUM_LAYERS {
+        layer
     } else {
-        key
+        diagnostics::record(Diagnostic::InvalidLayer);
+        Layer::Base.index()
     }
 }
 
 /// Converts a given row and column index into the absolute index for a layer.
 pub fn layer_index(row: usize, col: usize) -> usize {
-    (row * 12) + col
+    (row * COLS) + col
+}
+
+/// Key map lookup for a keyboard with `ROWS` x `COLS` keys.
+///
+/// Implement this trait to reuse the key scanner on boards with a different matrix size.
+pub trait Keymap<const ROWS: usize, const COLS: usize> {
+    /// Number of layers in the key map.
+    const LAYERS: usize = NUM_LAYERS;
+
+    /// Gets the key for a given `layer`, `row`, and `col` (all zero-indexed).
+    fn layer_key(layer: usize, row: usize, col: usize) -> u8;
+
+    /// Gets the key for a given `layer`, `row`, and `col`, with pass-through for any transparent
+    /// keys.
+    fn passthrough_key(layer: usize, row: usize, col: usize) -> u8 {
+        let key = Self::layer_key(layer, row, col);
+
+        if key == TRANS && layer > 0 {
+            Self::passthrough_key(layer - 1, row, col)
+        } else {
+            key
+        }
+    }
+}
+
+/// [Keymap] for the default Atreus layers.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct AtreusKeymap;
+
+impl Keymap<ROWS, COLS> for AtreusKeymap {
+    fn layer_key(layer: usize, row: usize, col: usize) -> u8 {
+        match layer_key(checked_layer(layer), layer_index(row, col)) {
+            Some(key) if col < COLS => key,
+            _ => {
+                diagnostics::record(Diagnostic::InvalidKey);
+                0
+            }
+        }
+    }
+
+    fn passthrough_key(layer: usize, row: usize, col: usize) -> u8 {
+        if col < COLS {
+            passthrough_key(layer, layer_index(row, col))
+        } else {
+            diagnostics::record(Diagnostic::InvalidKey);
+            0
+        }
+    }
 }
 
 /// Gets the currently active layer.
@@ -177,6 +276,7 @@ pub fn active_layer() -> Layer {
 
 /// Sets the currently active layer.
 pub fn set_active_layer(layer: Layer) -> Layer {
+    let _guard = audit::enter(Resource::ActiveLayer);
     let last = active_layer();
     ACTIVE_LAYER.store(layer.into(), Ordering::SeqCst);
     last
@@ -185,182 +285,183 @@ pub fn set_active_layer(layer: Layer) -> Layer {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::consumer::consumer_usage;
 
     #[test]
     fn test_layer_zero_keys() {
         // row 0
-        assert_eq!(layer_key(0, 0), Q);
-        assert_eq!(layer_key(0, 1), W);
-        assert_eq!(layer_key(0, 2), E);
-        assert_eq!(layer_key(0, 3), R);
-        assert_eq!(layer_key(0, 4), T);
-        assert_eq!(layer_key(0, 5), 0);
-        assert_eq!(layer_key(0, 6), 0);
-        assert_eq!(layer_key(0, 7), Y);
-        assert_eq!(layer_key(0, 8), U);
-        assert_eq!(layer_key(0, 9), I);
-        assert_eq!(layer_key(0, 10), O);
-        assert_eq!(layer_key(0, 11), P);
+        assert_eq!(layer_key(0, 0), Some(Q));
+        assert_eq!(layer_key(0, 1), Some(W));
+        assert_eq!(layer_key(0, 2), Some(E));
+        assert_eq!(layer_key(0, 3), Some(R));
+        assert_eq!(layer_key(0, 4), Some(T));
+        assert_eq!(layer_key(0, 5), Some(0));
+        assert_eq!(layer_key(0, 6), Some(0));
+        assert_eq!(layer_key(0, 7), Some(Y));
+        assert_eq!(layer_key(0, 8), Some(U));
+        assert_eq!(layer_key(0, 9), Some(I));
+        assert_eq!(layer_key(0, 10), Some(O));
+        assert_eq!(layer_key(0, 11), Some(P));
 
         // row 1
-        assert_eq!(layer_key(0, 12), A);
-        assert_eq!(layer_key(0, 13), S);
-        assert_eq!(layer_key(0, 14), D);
-        assert_eq!(layer_key(0, 15), F);
-        assert_eq!(layer_key(0, 16), G);
-        assert_eq!(layer_key(0, 17), 0);
-        assert_eq!(layer_key(0, 18), 0);
-        assert_eq!(layer_key(0, 19), H);
-        assert_eq!(layer_key(0, 20), J);
-        assert_eq!(layer_key(0, 21), K);
-        assert_eq!(layer_key(0, 22), L);
-        assert_eq!(layer_key(0, 23), SEMI);
+        assert_eq!(layer_key(0, 12), Some(A));
+        assert_eq!(layer_key(0, 13), Some(S));
+        assert_eq!(layer_key(0, 14), Some(D));
+        assert_eq!(layer_key(0, 15), Some(F));
+        assert_eq!(layer_key(0, 16), Some(G));
+        assert_eq!(layer_key(0, 17), Some(0));
+        assert_eq!(layer_key(0, 18), Some(0));
+        assert_eq!(layer_key(0, 19), Some(H));
+        assert_eq!(layer_key(0, 20), Some(J));
+        assert_eq!(layer_key(0, 21), Some(K));
+        assert_eq!(layer_key(0, 22), Some(L));
+        assert_eq!(layer_key(0, 23), Some(SEMI));
 
         // row 2
-        assert_eq!(layer_key(0, 24), Z);
-        assert_eq!(layer_key(0, 25), X);
-        assert_eq!(layer_key(0, 26), C);
-        assert_eq!(layer_key(0, 27), V);
-        assert_eq!(layer_key(0, 28), B);
-        assert_eq!(layer_key(0, 29), TICK);
-        assert_eq!(layer_key(0, 30), PIPE);
-        assert_eq!(layer_key(0, 31), N);
-        assert_eq!(layer_key(0, 32), M);
-        assert_eq!(layer_key(0, 33), COMMA);
-        assert_eq!(layer_key(0, 34), DOT);
-        assert_eq!(layer_key(0, 35), SLASH);
+        assert_eq!(layer_key(0, 24), Some(Z));
+        assert_eq!(layer_key(0, 25), Some(X));
+        assert_eq!(layer_key(0, 26), Some(C));
+        assert_eq!(layer_key(0, 27), Some(V));
+        assert_eq!(layer_key(0, 28), Some(B));
+        assert_eq!(layer_key(0, 29), Some(TICK));
+        assert_eq!(layer_key(0, 30), Some(PIPE));
+        assert_eq!(layer_key(0, 31), Some(N));
+        assert_eq!(layer_key(0, 32), Some(M));
+        assert_eq!(layer_key(0, 33), Some(COMMA));
+        assert_eq!(layer_key(0, 34), Some(DOT));
+        assert_eq!(layer_key(0, 35), Some(SLASH));
 
         // row 3
-        assert_eq!(layer_key(0, 36), ESC);
-        assert_eq!(layer_key(0, 37), TAB);
-        assert_eq!(layer_key(0, 38), CMD);
-        assert_eq!(layer_key(0, 39), SHIFT);
-        assert_eq!(layer_key(0, 40), BKSP);
-        assert_eq!(layer_key(0, 41), CTRL);
-        assert_eq!(layer_key(0, 42), ALT);
-        assert_eq!(layer_key(0, 43), SPACE);
-        assert_eq!(layer_key(0, 44), FUN);
-        assert_eq!(layer_key(0, 45), DASH);
-        assert_eq!(layer_key(0, 46), QUOTE);
-        assert_eq!(layer_key(0, 47), ENTER);
+        assert_eq!(layer_key(0, 36), Some(ESC));
+        assert_eq!(layer_key(0, 37), Some(TAB));
+        assert_eq!(layer_key(0, 38), Some(CMD));
+        assert_eq!(layer_key(0, 39), Some(SHIFT));
+        assert_eq!(layer_key(0, 40), Some(BKSP));
+        assert_eq!(layer_key(0, 41), Some(CTRL));
+        assert_eq!(layer_key(0, 42), Some(ALT));
+        assert_eq!(layer_key(0, 43), Some(SPACE));
+        assert_eq!(layer_key(0, 44), Some(FUN));
+        assert_eq!(layer_key(0, 45), Some(DASH));
+        assert_eq!(layer_key(0, 46), Some(QUOTE));
+        assert_eq!(layer_key(0, 47), Some(ENTER));
     }
 
     #[test]
     fn test_layer_one_keys() {
         // row 0
-        assert_eq!(layer_key(1, 0), EXCL);
-        assert_eq!(layer_key(1, 1), AT);
-        assert_eq!(layer_key(1, 2), U_ARROW);
-        assert_eq!(layer_key(1, 3), DOLLAR);
-        assert_eq!(layer_key(1, 4), MOD);
-        assert_eq!(layer_key(1, 5), 0);
-        assert_eq!(layer_key(1, 6), 0);
-        assert_eq!(layer_key(1, 7), PGUP);
-        assert_eq!(layer_key(1, 8), SEVEN);
-        assert_eq!(layer_key(1, 9), EIGHT);
-        assert_eq!(layer_key(1, 10), NINE);
-        assert_eq!(layer_key(1, 11), BKSP);
+        assert_eq!(layer_key(1, 0), Some(EXCL));
+        assert_eq!(layer_key(1, 1), Some(AT));
+        assert_eq!(layer_key(1, 2), Some(U_ARROW));
+        assert_eq!(layer_key(1, 3), Some(DOLLAR));
+        assert_eq!(layer_key(1, 4), Some(MOD));
+        assert_eq!(layer_key(1, 5), Some(0));
+        assert_eq!(layer_key(1, 6), Some(0));
+        assert_eq!(layer_key(1, 7), Some(PGUP));
+        assert_eq!(layer_key(1, 8), Some(SEVEN));
+        assert_eq!(layer_key(1, 9), Some(EIGHT));
+        assert_eq!(layer_key(1, 10), Some(NINE));
+        assert_eq!(layer_key(1, 11), Some(BKSP));
 
         // row 1
-        assert_eq!(layer_key(1, 12), L_PAREN);
-        assert_eq!(layer_key(1, 13), L_ARROW);
-        assert_eq!(layer_key(1, 14), D_ARROW);
-        assert_eq!(layer_key(1, 15), R_ARROW);
-        assert_eq!(layer_key(1, 16), R_PAREN);
-        assert_eq!(layer_key(1, 17), 0);
-        assert_eq!(layer_key(1, 18), 0);
-        assert_eq!(layer_key(1, 19), PGDN);
-        assert_eq!(layer_key(1, 20), FOUR);
-        assert_eq!(layer_key(1, 21), FIVE);
-        assert_eq!(layer_key(1, 22), SIX);
-        assert_eq!(layer_key(1, 23), TRANS);
+        assert_eq!(layer_key(1, 12), Some(L_PAREN));
+        assert_eq!(layer_key(1, 13), Some(L_ARROW));
+        assert_eq!(layer_key(1, 14), Some(D_ARROW));
+        assert_eq!(layer_key(1, 15), Some(R_ARROW));
+        assert_eq!(layer_key(1, 16), Some(R_PAREN));
+        assert_eq!(layer_key(1, 17), Some(0));
+        assert_eq!(layer_key(1, 18), Some(0));
+        assert_eq!(layer_key(1, 19), Some(PGDN));
+        assert_eq!(layer_key(1, 20), Some(FOUR));
+        assert_eq!(layer_key(1, 21), Some(FIVE));
+        assert_eq!(layer_key(1, 22), Some(SIX));
+        assert_eq!(layer_key(1, 23), Some(TRANS));
 
         // row 2
-        assert_eq!(layer_key(1, 24), L_BRACK);
-        assert_eq!(layer_key(1, 25), R_BRACK);
-        assert_eq!(layer_key(1, 26), HASH);
-        assert_eq!(layer_key(1, 27), L_BRACE);
-        assert_eq!(layer_key(1, 28), R_BRACE);
-        assert_eq!(layer_key(1, 29), CARET);
-        assert_eq!(layer_key(1, 30), AMP);
-        assert_eq!(layer_key(1, 31), STAR);
-        assert_eq!(layer_key(1, 32), ONE);
-        assert_eq!(layer_key(1, 33), TWO);
-        assert_eq!(layer_key(1, 34), THREE);
-        assert_eq!(layer_key(1, 35), PLUS);
+        assert_eq!(layer_key(1, 24), Some(L_BRACK));
+        assert_eq!(layer_key(1, 25), Some(R_BRACK));
+        assert_eq!(layer_key(1, 26), Some(HASH));
+        assert_eq!(layer_key(1, 27), Some(L_BRACE));
+        assert_eq!(layer_key(1, 28), Some(R_BRACE));
+        assert_eq!(layer_key(1, 29), Some(CARET));
+        assert_eq!(layer_key(1, 30), Some(AMP));
+        assert_eq!(layer_key(1, 31), Some(STAR));
+        assert_eq!(layer_key(1, 32), Some(ONE));
+        assert_eq!(layer_key(1, 33), Some(TWO));
+        assert_eq!(layer_key(1, 34), Some(THREE));
+        assert_eq!(layer_key(1, 35), Some(PLUS));
 
         // row 3
-        assert_eq!(layer_key(1, 36), UPPER);
-        assert_eq!(layer_key(1, 37), INS);
-        assert_eq!(layer_key(1, 38), TRANS);
-        assert_eq!(layer_key(1, 39), TRANS);
-        assert_eq!(layer_key(1, 40), TRANS);
-        assert_eq!(layer_key(1, 41), TRANS);
-        assert_eq!(layer_key(1, 42), TRANS);
-        assert_eq!(layer_key(1, 43), TRANS);
-        assert_eq!(layer_key(1, 44), FUN);
-        assert_eq!(layer_key(1, 45), DOT);
-        assert_eq!(layer_key(1, 46), ZERO);
-        assert_eq!(layer_key(1, 47), EQUAL);
+        assert_eq!(layer_key(1, 36), Some(UPPER));
+        assert_eq!(layer_key(1, 37), Some(INS));
+        assert_eq!(layer_key(1, 38), Some(TRANS));
+        assert_eq!(layer_key(1, 39), Some(TRANS));
+        assert_eq!(layer_key(1, 40), Some(TRANS));
+        assert_eq!(layer_key(1, 41), Some(TRANS));
+        assert_eq!(layer_key(1, 42), Some(TRANS));
+        assert_eq!(layer_key(1, 43), Some(TRANS));
+        assert_eq!(layer_key(1, 44), Some(FUN));
+        assert_eq!(layer_key(1, 45), Some(DOT));
+        assert_eq!(layer_key(1, 46), Some(ZERO));
+        assert_eq!(layer_key(1, 47), Some(EQUAL));
     }
 
     #[test]
     fn test_layer_two_keys() {
         // row 0
-        assert_eq!(layer_key(2, 0), INS);
-        assert_eq!(layer_key(2, 1), HOME);
-        assert_eq!(layer_key(2, 2), TRANS);
-        assert_eq!(layer_key(2, 3), END);
-        assert_eq!(layer_key(2, 4), PGUP);
-        assert_eq!(layer_key(2, 5), 0);
-        assert_eq!(layer_key(2, 6), 0);
-        assert_eq!(layer_key(2, 7), U_ARROW);
-        assert_eq!(layer_key(2, 8), F7);
-        assert_eq!(layer_key(2, 9), F8);
-        assert_eq!(layer_key(2, 10), F9);
-        assert_eq!(layer_key(2, 11), F10);
+        assert_eq!(layer_key(2, 0), Some(INS));
+        assert_eq!(layer_key(2, 1), Some(HOME));
+        assert_eq!(layer_key(2, 2), Some(TRANS));
+        assert_eq!(layer_key(2, 3), Some(END));
+        assert_eq!(layer_key(2, 4), Some(PGUP));
+        assert_eq!(layer_key(2, 5), Some(0));
+        assert_eq!(layer_key(2, 6), Some(0));
+        assert_eq!(layer_key(2, 7), Some(U_ARROW));
+        assert_eq!(layer_key(2, 8), Some(F7));
+        assert_eq!(layer_key(2, 9), Some(F8));
+        assert_eq!(layer_key(2, 10), Some(F9));
+        assert_eq!(layer_key(2, 11), Some(F10));
 
         // row 1
-        assert_eq!(layer_key(2, 12), DEL);
-        assert_eq!(layer_key(2, 13), TRANS);
-        assert_eq!(layer_key(2, 14), TRANS);
-        assert_eq!(layer_key(2, 15), TRANS);
-        assert_eq!(layer_key(2, 16), PGDN);
-        assert_eq!(layer_key(2, 17), 0);
-        assert_eq!(layer_key(2, 18), 0);
-        assert_eq!(layer_key(2, 19), D_ARROW);
-        assert_eq!(layer_key(2, 20), F4);
-        assert_eq!(layer_key(2, 21), F5);
-        assert_eq!(layer_key(2, 22), F6);
-        assert_eq!(layer_key(2, 23), F11);
+        assert_eq!(layer_key(2, 12), Some(DEL));
+        assert_eq!(layer_key(2, 13), Some(TRANS));
+        assert_eq!(layer_key(2, 14), Some(TRANS));
+        assert_eq!(layer_key(2, 15), Some(TRANS));
+        assert_eq!(layer_key(2, 16), Some(PGDN));
+        assert_eq!(layer_key(2, 17), Some(0));
+        assert_eq!(layer_key(2, 18), Some(0));
+        assert_eq!(layer_key(2, 19), Some(D_ARROW));
+        assert_eq!(layer_key(2, 20), Some(F4));
+        assert_eq!(layer_key(2, 21), Some(F5));
+        assert_eq!(layer_key(2, 22), Some(F6));
+        assert_eq!(layer_key(2, 23), Some(F11));
 
         // row 2
-        assert_eq!(layer_key(2, 24), TRANS);
-        assert_eq!(layer_key(2, 25), VOL_UP);
-        assert_eq!(layer_key(2, 26), TRANS);
-        assert_eq!(layer_key(2, 27), TRANS);
-        assert_eq!(layer_key(2, 28), TRANS);
-        assert_eq!(layer_key(2, 29), TRANS);
-        assert_eq!(layer_key(2, 30), TRANS);
-        assert_eq!(layer_key(2, 31), TRANS);
-        assert_eq!(layer_key(2, 32), F1);
-        assert_eq!(layer_key(2, 33), F2);
-        assert_eq!(layer_key(2, 34), F3);
-        assert_eq!(layer_key(2, 35), F12);
+        assert_eq!(layer_key(2, 24), Some(TRANS));
+        assert_eq!(layer_key(2, 25), Some(VOL_UP));
+        assert_eq!(layer_key(2, 26), Some(TRANS));
+        assert_eq!(layer_key(2, 27), Some(TRANS));
+        assert_eq!(layer_key(2, 28), Some(TRANS));
+        assert_eq!(layer_key(2, 29), Some(TRANS));
+        assert_eq!(layer_key(2, 30), Some(TRANS));
+        assert_eq!(layer_key(2, 31), Some(TRANS));
+        assert_eq!(layer_key(2, 32), Some(F1));
+        assert_eq!(layer_key(2, 33), Some(F2));
+        assert_eq!(layer_key(2, 34), Some(F3));
+        assert_eq!(layer_key(2, 35), Some(F12));
 
         // row 3
-        assert_eq!(layer_key(2, 36), UPPER);
-        assert_eq!(layer_key(2, 37), VOL_DN);
-        assert_eq!(layer_key(2, 38), TRANS);
-        assert_eq!(layer_key(2, 39), TRANS);
-        assert_eq!(layer_key(2, 40), TRANS);
-        assert_eq!(layer_key(2, 41), TRANS);
-        assert_eq!(layer_key(2, 42), TRANS);
-        assert_eq!(layer_key(2, 43), TRANS);
-        assert_eq!(layer_key(2, 44), FUN);
-        assert_eq!(layer_key(2, 45), PRT_SC);
-        assert_eq!(layer_key(2, 46), SCR_LK);
-        assert_eq!(layer_key(2, 47), PLAY_PS);
+        assert_eq!(layer_key(2, 36), Some(UPPER));
+        assert_eq!(layer_key(2, 37), Some(VOL_DN));
+        assert_eq!(layer_key(2, 38), Some(TRANS));
+        assert_eq!(layer_key(2, 39), Some(TRANS));
+        assert_eq!(layer_key(2, 40), Some(TRANS));
+        assert_eq!(layer_key(2, 41), Some(TRANS));
+        assert_eq!(layer_key(2, 42), Some(TRANS));
+        assert_eq!(layer_key(2, 43), Some(TRANS));
+        assert_eq!(layer_key(2, 44), Some(FUN));
+        assert_eq!(layer_key(2, 45), Some(PRT_SC));
+        assert_eq!(layer_key(2, 46), Some(SCR_LK));
+        assert_eq!(layer_key(2, 47), Some(PLAY_PS));
     }
 
     #[test]
@@ -393,4 +494,212 @@ mod tests {
         assert_eq!(passthrough_key(2, 42), ALT);
         assert_eq!(passthrough_key(2, 43), SPACE);
     }
+
+    #[test]
+    fn test_international_keys() {
+        for key in [NUHS, NUBS, INTL1, INTL5, INTL9, LANG1, LANG8, LANG9] {
+            assert!(!key_is_fun(key));
+            assert!(!key_is_upper(key));
+            assert!(!key_is_trans(key));
+            assert!(!key_is_shifted(key));
+            assert!(!key_is_modifier(key));
+        }
+
+        assert_eq!(HENKAN, INTL4);
+        assert_eq!(MUHENKAN, INTL5);
+        assert_eq!(KANA, INTL2);
+    }
+
+    #[test]
+    fn test_modifier_keys() {
+        let modifiers = [CTRL, SHIFT, ALT, CMD, R_CTRL, R_SHIFT, R_ALT, R_CMD];
+
+        for (i, &key) in modifiers.iter().enumerate() {
+            assert!(key_is_modifier(key));
+            assert_eq!(key_to_modifier(key), 1 << i);
+        }
+
+        assert_eq!(ALT_GR, R_ALT);
+        assert!(!key_is_modifier(A));
+        assert_eq!(key_to_modifier(A), 0);
+        assert_eq!(key_to_modifier(TRANS), 0);
+    }
+
+    #[test]
+    fn test_keyboard_usage_keys() {
+        // the firmware keycodes only shadow usages from the reserved usages (`0xa5`) up, so every
+        // layer key with a keyboard usage still goes to the keyboard report
+        let is_usage = |key| key_is_shifted(key) || key < 0xa5 || key_is_modifier(key);
+
+        for layer in 0..NUM_LAYERS {
+            for index in 0..ROWS * COLS {
+                let key = layer_key(layer, index).unwrap();
+
+                if is_usage(key) {
+                    assert_eq!(consumer_usage(key), None, "layer {layer}, key {index}");
+                    assert!(!key_is_gamepad(key), "layer {layer}, key {index}");
+                    assert!(!key_is_mouse(key), "layer {layer}, key {index}");
+                    assert!(!key_is_one_shot(key), "layer {layer}, key {index}");
+                    assert!(!key_is_caps_word(key), "layer {layer}, key {index}");
+                }
+            }
+        }
+
+        for key in [STAR, L_PAREN, R_PAREN, PLUS, L_BRACE, R_BRACE] {
+            assert!(is_usage(key));
+            assert_eq!(consumer_usage(key), None);
+            assert!(!key_is_gamepad(key));
+            assert!(!key_is_mouse(key));
+            assert!(!key_is_one_shot(key));
+            assert!(!key_is_caps_word(key));
+        }
+    }
+
+    #[test]
+    fn test_numpad_layer() {
+        assert_eq!(Layer::from(3u8), Layer::Numpad);
+        assert_eq!(Layer::from(4u8), Layer::Base);
+
+        assert_eq!(layer_key(3, 8), Some(KP7));
+        assert_eq!(layer_key(3, 20), Some(KP4));
+        assert_eq!(layer_key(3, 24), Some(NUMPAD));
+        assert_eq!(layer_key(3, 32), Some(KP1));
+        assert_eq!(layer_key(3, 45), Some(KP0));
+
+        // transparent keys pass through to the upper layer
+        assert_eq!(passthrough_key(3, 1), HOME);
+        assert_eq!(passthrough_key(3, 13), L_ARROW);
+
+        // the other default layers keep their keys, user key maps enter the numpad layer
+        for layer in 0..Layer::Numpad.index() {
+            for index in 0..ROWS * COLS {
+                assert!(!key_is_numpad(passthrough_key(layer, index)));
+            }
+        }
+        assert!(key_is_keypad(KP0));
+        assert!(!key_is_keypad(KP_ENTER));
+    }
+
+    #[test]
+    fn test_numeric_mode() {
+        assert_eq!(numeric_mode(), NumericMode::TopRow);
+
+        assert_eq!(numeric_key(KP1, NumericMode::TopRow), ONE);
+        assert_eq!(numeric_key(KP5, NumericMode::TopRow), FIVE);
+        assert_eq!(numeric_key(KP0, NumericMode::TopRow), ZERO);
+        assert_eq!(numeric_key(KP_DOT, NumericMode::TopRow), DOT);
+        assert_eq!(numeric_key(SEVEN, NumericMode::TopRow), SEVEN);
+
+        assert_eq!(numeric_key(ONE, NumericMode::Keypad), KP1);
+        assert_eq!(numeric_key(NINE, NumericMode::Keypad), KP9);
+        assert_eq!(numeric_key(ZERO, NumericMode::Keypad), KP0);
+        assert_eq!(numeric_key(DOT, NumericMode::Keypad), KP_DOT);
+        assert_eq!(numeric_key(A, NumericMode::Keypad), A);
+
+        assert_eq!(NumericMode::try_from(1), Ok(NumericMode::Keypad));
+        assert_eq!(NumericMode::try_from(2), Err(2));
+    }
+
+    #[test]
+    fn test_user_keys() {
+        assert!(key_is_user(USER0));
+        assert!(key_is_user(USER15));
+        assert!(!key_is_user(USER0 - 1));
+        assert!(!key_is_user(USER15 + 1));
+        assert!(!key_is_user(A));
+        assert!(!key_is_user(PLAY_PS));
+
+        assert_eq!(user_key(0), USER0);
+        assert_eq!(user_key(15), USER15);
+        assert_eq!(user_index(USER0), 0);
+        assert_eq!(user_index(user_key(11)), 11);
+    }
+
+    #[test]
+    fn test_tap_hold_keys() {
+        assert!(key_is_tap_hold(TAP_HOLD0));
+        assert!(key_is_tap_hold(TAP_HOLD7));
+        assert!(!key_is_tap_hold(TAP_HOLD0 - 1));
+        assert!(!key_is_tap_hold(TAP_HOLD7 + 1));
+        assert!(!key_is_tap_hold(R_CMD));
+
+        assert_eq!(tap_hold_key(0), TAP_HOLD0);
+        assert_eq!(tap_hold_key(7), TAP_HOLD7);
+        assert_eq!(tap_hold_index(TAP_HOLD0), 0);
+        assert_eq!(tap_hold_index(tap_hold_key(5)), 5);
+    }
+
+    #[test]
+    fn test_feature_keys() {
+        assert_eq!(one_shot_modifier(OS_SHIFT), Some(SHIFT));
+        assert_eq!(one_shot_modifier(OS_CMD), Some(CMD));
+        assert_eq!(one_shot_modifier(SHIFT), None);
+        assert!(key_is_one_shot(OS_CTRL) && !key_is_one_shot(OS_CMD + 1));
+        assert!(!key_is_one_shot(LANG9) && !key_is_one_shot(EXCL));
+
+        assert!(key_is_mouse(MS_UP) && key_is_mouse(MS_RIGHT));
+        assert!(key_is_mouse(MS_BTN1) && key_is_mouse(MS_WH_DN));
+        assert!(!key_is_mouse(R_CMD) && !key_is_mouse(MS_RIGHT + 1));
+        assert!(!key_is_mouse(MS_BTN1 - 1) && !key_is_mouse(MS_WH_DN + 1));
+        assert!(!key_is_mouse(CAPS_WORD));
+        assert!(key_is_caps_word(CAPS_WORD) && !key_is_caps_word(AUDIO_ON));
+    }
+
+    #[test]
+    fn test_keymap() {
+        struct MacroPad;
+
+        const MACRO_PAD_LAYERS: [LayerKeys<2, 3>; 2] = [
+            [[A, B, C], [D, E, F]],
+            [[ONE, TRANS, THREE], [TRANS, TRANS, SIX]],
+        ];
+
+        impl Keymap<2, 3> for MacroPad {
+            const LAYERS: usize = 2;
+
+            fn layer_key(layer: usize, row: usize, col: usize) -> u8 {
+                MACRO_PAD_LAYERS[layer % 2][row][col]
+            }
+        }
+
+        assert_eq!(MacroPad::passthrough_key(0, 1, 2), F);
+        assert_eq!(MacroPad::passthrough_key(1, 0, 0), ONE);
+        assert_eq!(MacroPad::passthrough_key(1, 0, 1), B);
+        assert_eq!(MacroPad::passthrough_key(1, 1, 0), D);
+
+        assert_eq!(AtreusKeymap::layer_key(0, 1, 0), A);
+        assert_eq!(
+            AtreusKeymap::passthrough_key(3, 1, 1),
+            passthrough_key(3, layer_index(1, 1))
+        );
+    }
+
+    #[test]
+    fn test_layer_lock_keys() {
+        assert!(key_is_layer_lock(UPPER));
+        assert!(key_is_layer_lock(NUMPAD));
+        assert!(!key_is_layer_lock(FUN));
+        assert!(!key_is_layer_lock(TRANS));
+    }
+
+    #[test]
+    fn test_invalid_layer_keys() {
+        assert_eq!(layer_key(NUM_LAYERS, 0), None);
+        assert_eq!(layer_key(0, ROWS * COLS), None);
+
+        let invalid_layers = diagnostics::count(Diagnostic::InvalidLayer);
+        let invalid_keys = diagnostics::count(Diagnostic::InvalidKey);
+
+        // invalid layers fall back to the base layer
+        assert_eq!(passthrough_key(NUM_LAYERS, 0), Q);
+        assert_eq!(AtreusKeymap::layer_key(NUM_LAYERS + 1, 0, 1), W);
+        // invalid keys produce no key
+        assert_eq!(passthrough_key(0, ROWS * COLS), 0);
+        assert_eq!(AtreusKeymap::passthrough_key(0, 0, COLS), 0);
+
+        assert!(diagnostics::count(Diagnostic::InvalidLayer) >= invalid_layers + 2);
+        assert!(diagnostics::count(Diagnostic::InvalidKey) >= invalid_keys + 2);
+
+        assert_eq!(Layer::from(NUM_LAYERS + 1), Layer::Base);
+    }
 }
@@ -1,6 +1,6 @@
 //! Key definitions
 
-use usbd_hid::descriptor::{KeyboardUsage as KB, MediaKey as MD, SystemControlKey as SC};
+use usbd_hid::descriptor::KeyboardUsage as KB;
 
 /// Number of columns in the keyboard layout.
 pub const COLS: usize = 12;
@@ -47,6 +47,12 @@ pub const BKSP: u8 = KB::KeyboardBackspace as u8;
 pub const SPACE: u8 = KB::KeyboardSpacebar as u8;
 pub const ALT: u8 = KB::KeyboardLeftAlt as u8;
 pub const CTRL: u8 = KB::KeyboardLeftControl as u8;
+pub const R_CTRL: u8 = KB::KeyboardRightControl as u8;
+pub const R_SHIFT: u8 = KB::KeyboardRightShift as u8;
+pub const R_ALT: u8 = KB::KeyboardRightAlt as u8;
+pub const R_CMD: u8 = KB::KeyboardRightGUI as u8;
+/// AltGr key on international layouts (Right Alt).
+pub const ALT_GR: u8 = R_ALT;
 pub const QUOTE: u8 = KB::KeyboardSingleDoubleQuote as u8;
 pub const ENTER: u8 = KB::KeyboardEnter as u8;
 pub const DASH: u8 = KB::KeyboardDashUnderscore as u8;
@@ -93,11 +99,52 @@ pub const HOME: u8 = KB::KeyboardHome as u8;
 pub const END: u8 = KB::KeyboardEnd as u8;
 pub const PRT_SC: u8 = KB::KeyboardPrintScreen as u8;
 pub const SCR_LK: u8 = KB::KeyboardScrollLock as u8;
-pub const PLAY_PS: u8 = MD::PlayPause as u8;
+
+pub const NUM_LOCK: u8 = KB::KeypadNumLock as u8;
+pub const KP_SLASH: u8 = KB::KeypadDivide as u8;
+pub const KP_STAR: u8 = KB::KeypadMultiply as u8;
+pub const KP_MINUS: u8 = KB::KeypadMinus as u8;
+pub const KP_PLUS: u8 = KB::KeypadPlus as u8;
+pub const KP_ENTER: u8 = KB::KeypadEnter as u8;
+pub const KP1: u8 = KB::Keypad1End as u8;
+pub const KP2: u8 = KB::Keypad2DownArrow as u8;
+pub const KP3: u8 = KB::Keypad3PageDown as u8;
+pub const KP4: u8 = KB::Keypad4LeftArrow as u8;
+pub const KP5: u8 = KB::Keypad5 as u8;
+pub const KP6: u8 = KB::Keypad6RightArrow as u8;
+pub const KP7: u8 = KB::Keypad7Home as u8;
+pub const KP8: u8 = KB::Keypad8UpArrow as u8;
+pub const KP9: u8 = KB::Keypad9PageUp as u8;
+pub const KP0: u8 = KB::Keypad0Insert as u8;
+pub const KP_DOT: u8 = KB::KeypadPeriodDelete as u8;
+pub const KP_EQUAL: u8 = KB::KeypadEqual as u8;
 
 pub const VOL_UP: u8 = KB::KeyboardVolumeUp as u8;
 pub const VOL_DN: u8 = KB::KeyboardVolumeDown as u8;
 
+/// Number of Consumer Control keycodes.
+pub const NUM_CONSUMER_KEYS: u8 = 11;
+/// First Consumer Control keycode.
+///
+/// Consumer Control keycodes are sent on the Consumer Control interface instead of the keyboard
+/// reports, see [consumer](crate::consumer). The range shadows rarely used extended keypad usages
+/// (`0xc5..=0xcf`), clear of the [shifted keys](key_is_shifted).
+pub const CONSUMER0: u8 = 0xc5;
+/// Last Consumer Control keycode.
+pub const CONSUMER10: u8 = CONSUMER0 + NUM_CONSUMER_KEYS - 1;
+
+pub const PLAY_PS: u8 = CONSUMER0;
+pub const MD_NEXT: u8 = CONSUMER0 + 1;
+pub const MD_PREV: u8 = CONSUMER0 + 2;
+pub const MD_STOP: u8 = CONSUMER0 + 3;
+pub const MD_MUTE: u8 = CONSUMER0 + 4;
+pub const MD_VOL_UP: u8 = CONSUMER0 + 5;
+pub const MD_VOL_DN: u8 = CONSUMER0 + 6;
+pub const WWW_BACK: u8 = CONSUMER0 + 7;
+pub const WWW_FWD: u8 = CONSUMER0 + 8;
+pub const WWW_HOME: u8 = CONSUMER0 + 9;
+pub const CALC: u8 = CONSUMER10;
+
 pub const F1: u8 = KB::KeyboardF1 as u8;
 pub const F2: u8 = KB::KeyboardF2 as u8;
 pub const F3: u8 = KB::KeyboardF3 as u8;
@@ -111,7 +158,148 @@ pub const F10: u8 = KB::KeyboardF10 as u8;
 pub const F11: u8 = KB::KeyboardF11 as u8;
 pub const F12: u8 = KB::KeyboardF12 as u8;
 
-pub const FUN: u8 = SC::SystemFunctionShift as u8;
+pub const NUHS: u8 = KB::KeyboardNonUSHash as u8;
+pub const NUBS: u8 = KB::KeyboardNonUSSlash as u8;
+
+pub const INTL1: u8 = KB::KeyboardInternational1 as u8;
+pub const INTL2: u8 = KB::KeyboardInternational2 as u8;
+pub const INTL3: u8 = KB::KeyboardInternational3 as u8;
+pub const INTL4: u8 = KB::KeyboardInternational4 as u8;
+pub const INTL5: u8 = KB::KeyboardInternational5 as u8;
+pub const INTL6: u8 = KB::KeyboardInternational6 as u8;
+pub const INTL7: u8 = KB::KeyboardInternational7 as u8;
+pub const INTL8: u8 = KB::KeyboardInternational8 as u8;
+pub const INTL9: u8 = KB::KeyboardInternational9 as u8;
+
+pub const LANG1: u8 = KB::KeyboardLANG1 as u8;
+pub const LANG2: u8 = KB::KeyboardLANG2 as u8;
+pub const LANG3: u8 = KB::KeyboardLANG3 as u8;
+pub const LANG4: u8 = KB::KeyboardLANG4 as u8;
+pub const LANG5: u8 = KB::KeyboardLANG5 as u8;
+pub const LANG6: u8 = KB::KeyboardLANG6 as u8;
+pub const LANG7: u8 = KB::KeyboardLANG7 as u8;
+pub const LANG8: u8 = KB::KeyboardLANG8 as u8;
+pub const LANG9: u8 = KB::KeyboardLANG9 as u8;
+
+/// JIS `\ _` key.
+pub const RO: u8 = INTL1;
+/// JIS Katakana/Hiragana key.
+pub const KANA: u8 = INTL2;
+/// JIS `¥ |` key.
+pub const YEN: u8 = INTL3;
+/// JIS Henkan (conversion) key.
+pub const HENKAN: u8 = INTL4;
+/// JIS Muhenkan (non-conversion) key.
+pub const MUHENKAN: u8 = INTL5;
+/// Korean Hangul/English toggle key.
+pub const HANGUL: u8 = LANG1;
+/// Korean Hanja conversion key.
+pub const HANJA: u8 = LANG2;
+
+/// Number of gamepad button keycodes.
+pub const NUM_GAMEPAD_BUTTONS: u8 = 16;
+/// First gamepad button keycode, see [gamepad](crate::gamepad).
+///
+/// Gamepad keycodes are sent on the gamepad interface instead of the keyboard reports. Like the
+/// [user-defined keys](USER0), the range shadows rarely used extended keypad usages
+/// (`0xb1..=0xc4`), starting after [R_BRACE], the last [shifted key](key_is_shifted).
+pub const BTN0: u8 = 0xb1;
+/// Last gamepad button keycode.
+pub const BTN15: u8 = BTN0 + NUM_GAMEPAD_BUTTONS - 1;
+/// Gamepad joystick up key.
+pub const JOY_UP: u8 = BTN15 + 1;
+/// Gamepad joystick down key.
+pub const JOY_DOWN: u8 = BTN15 + 2;
+/// Gamepad joystick left key.
+pub const JOY_LEFT: u8 = BTN15 + 3;
+/// Gamepad joystick right key.
+pub const JOY_RIGHT: u8 = BTN15 + 4;
+
+/// Number of dual-role (tap-hold) keycodes.
+pub const NUM_TAP_HOLD_KEYS: u8 = 8;
+/// First dual-role keycode, see [tap_hold](crate::tap_hold).
+///
+/// Each keycode stands for the [TapHoldKey](crate::tap_hold::TapHoldKey) at the same index in
+/// the table set on the key scanner, and is replaced by its tap or hold key once resolved. The
+/// range shadows the reserved usages after the modifiers (`0xe8..=0xef`).
+pub const TAP_HOLD0: u8 = 0xe8;
+/// Last dual-role keycode.
+pub const TAP_HOLD7: u8 = TAP_HOLD0 + NUM_TAP_HOLD_KEYS - 1;
+
+/// One-shot Control key, see [one_shot](crate::one_shot).
+///
+/// Held, the one-shot keys act as their modifier. Tapped, the modifier applies to the next key
+/// press only. The range shadows rarely used keyboard usages (`0x99..=0x9c`, Alternate Erase to
+/// Clear).
+pub const OS_CTRL: u8 = 0x99;
+/// One-shot Shift key.
+pub const OS_SHIFT: u8 = 0x9a;
+/// One-shot Alt key.
+pub const OS_ALT: u8 = 0x9b;
+/// One-shot Command (GUI) key.
+pub const OS_CMD: u8 = 0x9c;
+
+/// Mouse pointer up key, see [mouse_keys](crate::mouse_keys).
+///
+/// Mouse keycodes are sent on the mouse interface instead of the keyboard reports. The movement
+/// keys shadow the reserved usages after the modifiers (`0xf0..=0xf3`), and the button and wheel
+/// keys the reserved usages between the [shifted keys](key_is_shifted) (`0xa8..=0xac`).
+pub const MS_UP: u8 = 0xf0;
+/// Mouse pointer down key.
+pub const MS_DOWN: u8 = 0xf1;
+/// Mouse pointer left key.
+pub const MS_LEFT: u8 = 0xf2;
+/// Mouse pointer right key.
+pub const MS_RIGHT: u8 = 0xf3;
+/// Left mouse button key.
+pub const MS_BTN1: u8 = 0xa8;
+/// Right mouse button key.
+pub const MS_BTN2: u8 = 0xa9;
+/// Middle mouse button key.
+pub const MS_BTN3: u8 = 0xaa;
+/// Mouse wheel up key.
+pub const MS_WH_UP: u8 = 0xab;
+/// Mouse wheel down key.
+pub const MS_WH_DN: u8 = 0xac;
+/// Caps Word toggle key, see [caps_word](crate::caps_word).
+pub const CAPS_WORD: u8 = 0xf4;
+
+/// Number of user-defined keycodes.
+pub const NUM_USER_KEYS: u8 = 16;
+/// First user-defined keycode.
+///
+/// User keycodes are never sent to the host, and instead invoke a handler registered by the
+/// firmware binary. The range shadows the rarely used extended keypad usages (`0xd0..=0xdf`).
+pub const USER0: u8 = 0xd0;
+/// Last user-defined keycode.
+pub const USER15: u8 = USER0 + NUM_USER_KEYS - 1;
+
+/// Audio unmute key, see [audio](crate::audio).
+pub const AUDIO_ON: u8 = 0xf5;
+/// Audio mute key.
+pub const AUDIO_OFF: u8 = 0xf6;
+/// Audio mute toggle key.
+pub const AUDIO_TOG: u8 = 0xf7;
+/// System wake key, see [system_control](crate::system_control).
+///
+/// Every key of the default Atreus layers is taken, so the key is left for user key maps, e.g. on
+/// the function layer.
+pub const WAKE: u8 = 0xf8;
+/// N-key rollover report mode toggle key, see [nkro](crate::nkro).
+pub const NKRO: u8 = 0xf9;
+/// Serial bridge toggle key, see [bridge](crate::bridge).
+///
+/// Does nothing on boards without a bridge, e.g. the Atreus.
+pub const SERIAL: u8 = 0xfa;
+/// Turbo key, repeatedly presses and releases other held keys.
+pub const TURBO: u8 = 0xfb;
+/// Numpad layer toggle key.
+pub const NUMPAD: u8 = 0xfc;
+/// Function layer key.
+///
+/// Uses a firmware-internal code, since the System Control `SystemFunctionShift` usage has the
+/// same value as [LANG8] on the keyboard usage page.
+pub const FUN: u8 = 0xfd;
 pub const UPPER: u8 = 0xfe;
 pub const TRANS: u8 = 0xff;
 
@@ -125,6 +313,112 @@ pub fn key_is_upper(key: u8) -> bool {
     key == UPPER
 }
 
+/// Gets the user-defined keycode for the `index` (modulo [NUM_USER_KEYS]).
+pub const fn user_key(index: u8) -> u8 {
+    USER0 + (index % NUM_USER_KEYS)
+}
+
+/// Gets whether the key is a user-defined key.
+pub fn key_is_user(key: u8) -> bool {
+    (USER0..=USER15).contains(&key)
+}
+
+/// Gets the index of a user-defined key, e.g. `0` for [USER0].
+pub const fn user_index(key: u8) -> u8 {
+    key.wrapping_sub(USER0) % NUM_USER_KEYS
+}
+
+/// Gets the dual-role keycode for the `index` (modulo [NUM_TAP_HOLD_KEYS]).
+pub const fn tap_hold_key(index: u8) -> u8 {
+    TAP_HOLD0 + (index % NUM_TAP_HOLD_KEYS)
+}
+
+/// Gets whether the key is a dual-role key.
+pub fn key_is_tap_hold(key: u8) -> bool {
+    (TAP_HOLD0..=TAP_HOLD7).contains(&key)
+}
+
+/// Gets the index of a dual-role key, e.g. `0` for [TAP_HOLD0].
+pub const fn tap_hold_index(key: u8) -> u8 {
+    key.wrapping_sub(TAP_HOLD0) % NUM_TAP_HOLD_KEYS
+}
+
+/// Gets the modifier keycode of a one-shot key, e.g. [SHIFT] for [OS_SHIFT].
+pub const fn one_shot_modifier(key: u8) -> Option<u8> {
+    match key {
+        OS_CTRL => Some(CTRL),
+        OS_SHIFT => Some(SHIFT),
+        OS_ALT => Some(ALT),
+        OS_CMD => Some(CMD),
+        _ => None,
+    }
+}
+
+/// Gets whether the key is a one-shot modifier key.
+pub fn key_is_one_shot(key: u8) -> bool {
+    (OS_CTRL..=OS_CMD).contains(&key)
+}
+
+/// Gets whether the key is a mouse key.
+pub fn key_is_mouse(key: u8) -> bool {
+    (MS_UP..=MS_RIGHT).contains(&key) || (MS_BTN1..=MS_WH_DN).contains(&key)
+}
+
+/// Gets whether the key is the Caps Word toggle key.
+pub fn key_is_caps_word(key: u8) -> bool {
+    key == CAPS_WORD
+}
+
+/// Gets whether the key is the turbo key.
+pub fn key_is_turbo(key: u8) -> bool {
+    key == TURBO
+}
+
+/// Gets whether the key is the numpad layer toggle key.
+pub fn key_is_numpad(key: u8) -> bool {
+    key == NUMPAD
+}
+
+/// Gets whether the key is the serial bridge toggle key.
+pub fn key_is_serial(key: u8) -> bool {
+    key == SERIAL
+}
+
+/// Gets whether the key is the system wake key.
+pub fn key_is_wake(key: u8) -> bool {
+    key == WAKE
+}
+
+/// Gets whether the key is the N-key rollover report mode toggle key.
+pub fn key_is_nkro(key: u8) -> bool {
+    key == NKRO
+}
+
+/// Gets whether the key is an audio mute key.
+pub fn key_is_audio(key: u8) -> bool {
+    (AUDIO_ON..=AUDIO_TOG).contains(&key)
+}
+
+/// Gets whether the key is a Consumer Control key.
+pub fn key_is_consumer(key: u8) -> bool {
+    (CONSUMER0..=CONSUMER10).contains(&key)
+}
+
+/// Gets the gamepad button keycode for the `index` (modulo [NUM_GAMEPAD_BUTTONS]).
+pub const fn gamepad_button(index: u8) -> u8 {
+    BTN0 + (index % NUM_GAMEPAD_BUTTONS)
+}
+
+/// Gets whether the key is a gamepad button or joystick key.
+pub fn key_is_gamepad(key: u8) -> bool {
+    (BTN0..=JOY_RIGHT).contains(&key)
+}
+
+/// Gets whether the key is a keypad key affected by NumLock.
+pub fn key_is_keypad(key: u8) -> bool {
+    (KP1..=KP_DOT).contains(&key)
+}
+
 /// Gets whether the key is a transparent key.
 pub fn key_is_trans(key: u8) -> bool {
     key == TRANS
@@ -153,19 +447,35 @@ pub fn key_is_shifted(key: u8) -> bool {
 /// Gets a shifted keycode.
 ///
 /// Useful for sending the base keycode with the shift modifier in a [KeyboardReport](usbd_hid::descriptor::KeyboardReport).
+///
+/// Only clears the [SHIFTED] flag from [shifted keys](key_is_shifted). Other keys are returned
+/// unchanged, since usages from `0x80` up (e.g. [INTL1] and [LANG1]) have the same bit set.
 pub fn shifted_key(key: u8) -> u8 {
-    key & !SHIFTED
+    if key_is_shifted(key) {
+        key & !SHIFTED
+    } else {
+        key
+    }
 }
 
 /// Gets whether the keycode is for a modifier key.
 pub fn key_is_modifier(key: u8) -> bool {
-    let left_ctl = KB::KeyboardLeftControl as u8;
-    let right_gui = KB::KeyboardRightGUI as u8;
-
-    (left_ctl..=right_gui).contains(&key)
+    (CTRL..=R_CMD).contains(&key)
 }
 
 /// Converts the key to a modifier bitfield for a [KeyboardReport](usbd_hid::descriptor::KeyboardReport).
+///
+/// Returns zero for non-modifier keys.
 pub const fn key_to_modifier(key: u8) -> u8 {
-    1 << (key - (KB::KeyboardLeftControl as u8))
+    match key {
+        CTRL => 1 << 0,
+        SHIFT => 1 << 1,
+        ALT => 1 << 2,
+        CMD => 1 << 3,
+        R_CTRL => 1 << 4,
+        R_SHIFT => 1 << 5,
+        R_ALT => 1 << 6,
+        R_CMD => 1 << 7,
+        _ => 0,
+    }
 }
@@ -0,0 +1,27 @@
+//! Escape hatch for locked layers.
+//!
+//! Layer-lock keys ([UPPER](super::UPPER) and [NUMPAD](super::NUMPAD)) keep a layer active after
+//! they are released, which can leave users lost in a layer they did not mean to enter.
+//! Double-tapping any layer-lock key always returns to the base layer.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use super::{key_is_numpad, key_is_upper};
+
+/// Whether double-tapping a layer-lock key returns to the base layer.
+static DOUBLE_TAP_TO_BASE: AtomicBool = AtomicBool::new(true);
+
+/// Gets whether the key locks a layer after release.
+pub fn key_is_layer_lock(key: u8) -> bool {
+    key_is_upper(key) || key_is_numpad(key)
+}
+
+/// Gets whether double-tapping a layer-lock key returns to the base layer.
+pub fn double_tap_to_base() -> bool {
+    DOUBLE_TAP_TO_BASE.load(Ordering::Relaxed)
+}
+
+/// Sets whether double-tapping a layer-lock key returns to the base layer.
+pub fn set_double_tap_to_base(val: bool) {
+    DOUBLE_TAP_TO_BASE.store(val, Ordering::SeqCst);
+}
@@ -0,0 +1,115 @@
+//! NumLock handling for the numpad layer.
+//!
+//! Keypad digit usages produce navigation keys (arrows, Home, End, etc.) when the host NumLock
+//! is off. To make the numpad layer behave predictably, the firmware can track the host NumLock
+//! state and tap NumLock before sending keypad keys.
+//!
+//! Alternatively, the numeric cluster on the function layer can avoid NumLock entirely with the
+//! [NumericMode], which selects between top-row digit and keypad digit usages.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use super::{key_is_keypad, DOT, KP0, KP1, KP9, KP_DOT, NINE, NUM_LOCK, ONE, ZERO};
+use crate::host_leds;
+
+/// Represents how the firmware handles NumLock for keypad keys.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum NumLockMode {
+    /// Send keypad keys as-is, regardless of the host NumLock state.
+    #[default]
+    Ignore = 0,
+    /// Send NumLock instead of a keypad key while the host NumLock is off.
+    ///
+    /// Only useful with hosts that report their NumLock state.
+    Assert = 1,
+}
+
+/// NumLock handling for keypad keys.
+pub const NUM_LOCK_MODE: NumLockMode = NumLockMode::Ignore;
+
+/// Gets whether NumLock is on at the host, see [host_leds](crate::host_leds).
+pub fn host_num_lock() -> bool {
+    host_leds::host_leds().num_lock()
+}
+
+/// Gets the key to send for a pressed `key`, given the [NumLockMode].
+///
+/// With [Assert](NumLockMode::Assert), keypad keys are replaced by [NUM_LOCK] until the host
+/// reports NumLock on.
+pub fn num_lock_key(key: u8, mode: NumLockMode) -> u8 {
+    if mode == NumLockMode::Assert && key_is_keypad(key) && !host_num_lock() {
+        NUM_LOCK
+    } else {
+        key
+    }
+}
+
+/// Represents which usages the numeric cluster on the function layer sends.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum NumericMode {
+    /// Always send top-row digits, which type numbers regardless of the host NumLock state.
+    #[default]
+    TopRow = 0,
+    /// Always send keypad digits, e.g. for applications that bind keypad keys.
+    Keypad = 1,
+}
+
+impl TryFrom<u8> for NumericMode {
+    type Error = u8;
+
+    fn try_from(val: u8) -> Result<Self, Self::Error> {
+        match val {
+            0 => Ok(Self::TopRow),
+            1 => Ok(Self::Keypad),
+            _ => Err(val),
+        }
+    }
+}
+
+impl From<NumericMode> for u8 {
+    fn from(val: NumericMode) -> Self {
+        val as u8
+    }
+}
+
+/// Numeric cluster mode selected by the user.
+static NUMERIC_MODE: AtomicU8 = AtomicU8::new(NumericMode::TopRow as u8);
+
+/// Gets the [NumericMode] for the numeric cluster on the function layer.
+pub fn numeric_mode() -> NumericMode {
+    NumericMode::try_from(NUMERIC_MODE.load(Ordering::Relaxed)).unwrap_or_default()
+}
+
+/// Sets the [NumericMode] for the numeric cluster on the function layer.
+///
+/// Stored as a single byte, so it can be saved along with the rest of a user profile.
+pub fn set_numeric_mode(mode: NumericMode) {
+    NUMERIC_MODE.store(mode.into(), Ordering::SeqCst);
+}
+
+/// Gets the key to send for a digit or decimal point `key`, given the [NumericMode].
+///
+/// Digits and the decimal point are converted between top-row and keypad usages, all other keys
+/// are returned as-is.
+pub fn numeric_key(key: u8, mode: NumericMode) -> u8 {
+    // top-row and keypad digits both run from 1 to 9, followed by 0
+    const KEYPAD_OFFSET: u8 = KP1 - ONE;
+
+    match mode {
+        NumericMode::TopRow => match key {
+            KP1..=KP0 => key - KEYPAD_OFFSET,
+            KP_DOT => DOT,
+            _ => key,
+        },
+        NumericMode::Keypad => match key {
+            ONE..=ZERO => key + KEYPAD_OFFSET,
+            DOT => KP_DOT,
+            _ => key,
+        },
+    }
+}
+
+// the digit ranges must line up for the offset conversion
+const _: () = assert!(KP9 - KP1 == NINE - ONE && KP0 - KP1 == ZERO - ONE);
@@ -0,0 +1,81 @@
+//! Shifted-symbol substitution
+//!
+//! Allows `Shift + key` to produce a completely different usage than the host would normally
+//! produce, e.g. `Shift + ,` producing `!` instead of `<`.
+//!
+//! Based on the [Kaleidoscope ShapeShifter plugin](https://kaleidoscope.readthedocs.io/en/latest/plugins/Kaleidoscope-ShapeShifter.html).
+
+use super::{key_to_modifier, R_SHIFT, SHIFT};
+
+/// Represents a substitution of a key when Shift is held.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ShapeShift {
+    original: u8,
+    replacement: u8,
+}
+
+impl ShapeShift {
+    /// Creates a new [ShapeShift].
+    ///
+    /// If the `replacement` is a [SHIFTED](super::SHIFTED) key, Shift stays held and the base key
+    /// is sent. Otherwise, Shift is released for the `replacement` key.
+    pub const fn new(original: u8, replacement: u8) -> Self {
+        Self {
+            original,
+            replacement,
+        }
+    }
+
+    /// Gets the original key.
+    pub const fn original(&self) -> u8 {
+        self.original
+    }
+
+    /// Gets the replacement key.
+    pub const fn replacement(&self) -> u8 {
+        self.replacement
+    }
+}
+
+/// Dictionary of shifted-symbol substitutions, applied by the
+/// [ReportBuilder](crate::report::ReportBuilder) to each key while Shift is physically held.
+///
+/// Empty by default, add entries to change what `Shift + key` produces. For example:
+///
+/// ```no_run
+/// # use trove_internal::layers::*;
+/// const SHAPE_SHIFTER: &[ShapeShift] = &[
+///     // Shift + , => !
+///     ShapeShift::new(COMMA, EXCL),
+///     // Shift + 1 => 1
+///     ShapeShift::new(ONE, ONE),
+/// ];
+/// ```
+pub const SHAPE_SHIFTER: &[ShapeShift] = &[];
+
+/// Bitmask of both Shift modifiers in a keyboard report.
+pub const SHIFT_MODIFIERS: u8 = key_to_modifier(SHIFT) | key_to_modifier(R_SHIFT);
+
+/// Finds the replacement key for the `key` in the `dictionary`.
+pub fn shape_shifted_key(key: u8, dictionary: &[ShapeShift]) -> Option<u8> {
+    dictionary
+        .iter()
+        .find(|s| s.original == key)
+        .map(|s| s.replacement)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::{COMMA, EXCL, ONE, Q};
+
+    const DICTIONARY: &[ShapeShift] = &[ShapeShift::new(COMMA, EXCL), ShapeShift::new(ONE, ONE)];
+
+    #[test]
+    fn test_shape_shifted_key() {
+        assert_eq!(shape_shifted_key(COMMA, DICTIONARY), Some(EXCL));
+        assert_eq!(shape_shifted_key(ONE, DICTIONARY), Some(ONE));
+        assert_eq!(shape_shifted_key(Q, DICTIONARY), None);
+        assert_eq!(shape_shifted_key(COMMA, SHAPE_SHIFTER), None);
+    }
+}
@@ -1,3 +1,90 @@
 #![no_std]
 
+#[macro_use(bitfield)]
+extern crate bitfield;
+
+#[cfg(feature = "std")]
+extern crate std;
+
+pub mod adc_config;
+pub mod analog;
+pub mod animation;
+pub mod audio;
+pub mod audit;
+pub mod auto_shift;
+pub mod bootloader;
+pub mod bridge;
+pub mod caps_word;
+pub mod config;
+pub mod config_backup;
+pub mod console;
+pub mod consumer;
+pub mod debounce;
+pub mod debounce_sim;
+pub mod diagnostics;
+pub mod events;
+pub mod factory_reset;
+pub mod features;
+pub mod firmware_info;
+pub mod flash;
+pub mod focus;
+pub mod gamepad;
+pub mod ghosting;
+pub mod haptic;
+pub mod health;
+pub mod hooks;
+pub mod host_leds;
+pub mod housekeeping;
+pub mod idle_rate;
+pub mod indicator;
 pub mod layers;
+#[cfg(feature = "std")]
+pub mod lint;
+pub mod macros;
+pub mod matrix_test;
+pub mod mouse;
+pub mod mouse_keys;
+pub mod nkro;
+pub mod oled;
+pub mod one_shot;
+pub mod output_report;
+pub mod panic_report;
+pub mod pin_map;
+pub mod pipeline;
+pub mod pmw3360;
+pub mod poll_rate;
+pub mod power;
+pub mod raw_hid;
+pub mod report;
+pub mod rgb_map;
+pub mod scan_rate;
+pub mod scan_timer;
+pub mod scanner;
+pub mod scheduler;
+pub mod serial_number;
+pub mod settings;
+pub mod shared_report;
+pub mod shift_register;
+#[cfg(feature = "std")]
+pub mod sim;
+pub mod soft_pwm;
+pub mod spi_config;
+pub mod split;
+pub mod spsc;
+pub mod status_leds;
+pub mod storage;
+pub mod stored_animation;
+pub mod stored_keymap;
+pub mod system_control;
+pub mod tap_hold;
+pub mod thumbstick;
+pub mod time;
+pub mod timing;
+pub mod tuning;
+pub mod turbo;
+pub mod uart_config;
+pub mod usb_descriptors;
+pub mod usb_identity;
+pub mod usb_watchdog;
+pub mod wpm;
+pub mod ws2812;
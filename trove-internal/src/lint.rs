@@ -0,0 +1,254 @@
+//! Key map linting for host-side tools.
+//!
+//! Analyzes a [Keymap] for common configuration problems, and returns structured warnings that
+//! configurator UIs can display next to the offending key or layer.
+
+use std::vec::Vec;
+
+use crate::config::ConfigError;
+use crate::layers::{self, AtreusKeymap, Keymap, Layer, COLS, ROWS, TRANS};
+
+/// Length of a serialized [LintWarning].
+pub const LINT_WARNING_LEN: usize = 5;
+
+/// Represents a problem found in a key map.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LintWarning {
+    /// No sequence of layer keys switches to the layer from the base layer.
+    UnreachableLayer { layer: u8 },
+    /// The layer has no key that switches back to another layer.
+    MissingLayerExit { layer: u8 },
+    /// The key is already defined at an earlier position on the same layer.
+    DuplicateKey {
+        layer: u8,
+        key: u8,
+        row: u8,
+        col: u8,
+    },
+    /// Transparent key on the base layer, which has nothing to pass through to.
+    TransOnBase { row: u8, col: u8 },
+}
+
+impl LintWarning {
+    /// Gets the wire code for the kind of [LintWarning].
+    pub const fn kind(&self) -> u8 {
+        match self {
+            Self::UnreachableLayer { .. } => 0,
+            Self::MissingLayerExit { .. } => 1,
+            Self::DuplicateKey { .. } => 2,
+            Self::TransOnBase { .. } => 3,
+        }
+    }
+
+    /// Converts the [LintWarning] into its serialized form.
+    ///
+    /// The layout is `| kind | layer | key | row | col |`, with unused fields set to zero.
+    pub const fn to_bytes(&self) -> [u8; LINT_WARNING_LEN] {
+        let kind = self.kind();
+
+        match *self {
+            Self::UnreachableLayer { layer } | Self::MissingLayerExit { layer } => {
+                [kind, layer, 0, 0, 0]
+            }
+            Self::DuplicateKey {
+                layer,
+                key,
+                row,
+                col,
+            } => [kind, layer, key, row, col],
+            Self::TransOnBase { row, col } => [kind, 0, TRANS, row, col],
+        }
+    }
+}
+
+/// Gets the layer that pressing `key` on the `layer` switches to, mirroring the key scanner.
+fn layer_target(layer: usize, key: u8) -> Option<usize> {
+    let current = Layer::from(layer);
+
+    let target = if layers::key_is_fun(key) {
+        match current {
+            Layer::Upper => Layer::Base,
+            _ => Layer::Fun,
+        }
+    } else if layers::key_is_upper(key) {
+        match current {
+            Layer::Upper => Layer::from(layer.saturating_sub(1)),
+            _ => Layer::Upper,
+        }
+    } else if layers::key_is_numpad(key) {
+        match current {
+            Layer::Numpad => Layer::Base,
+            _ => Layer::Numpad,
+        }
+    } else {
+        return None;
+    };
+
+    Some(target.index())
+}
+
+/// Analyzes the key map `K` for problems.
+pub fn lint_keymap<const ROWS: usize, const COLS: usize, K: Keymap<ROWS, COLS>>() -> Vec<LintWarning>
+{
+    let mut warnings = Vec::new();
+
+    let positions = || (0..ROWS).flat_map(|row| (0..COLS).map(move |col| (row, col)));
+
+    // walk the layer keys reachable from the base layer
+    let mut reachable = std::vec![false; K::LAYERS];
+    let mut pending = std::vec![0usize];
+
+    if let Some(base) = reachable.first_mut() {
+        *base = true;
+    }
+
+    while let Some(layer) = pending.pop() {
+        for (row, col) in positions() {
+            let key = K::passthrough_key(layer, row, col);
+
+            if let Some(target) = layer_target(layer, key).filter(|&t| t < K::LAYERS) {
+                if !reachable[target] {
+                    reachable[target] = true;
+                    pending.push(target);
+                }
+            }
+        }
+    }
+
+    for (layer, &is_reachable) in reachable.iter().enumerate().skip(1) {
+        if !is_reachable {
+            warnings.push(LintWarning::UnreachableLayer { layer: layer as u8 });
+            continue;
+        }
+
+        // the function layer is momentary, releasing the function key always exits
+        if Layer::from(layer) == Layer::Fun {
+            continue;
+        }
+
+        let has_exit = positions().any(|(row, col)| {
+            layer_target(layer, K::passthrough_key(layer, row, col)).is_some_and(|t| t != layer)
+        });
+
+        if !has_exit {
+            warnings.push(LintWarning::MissingLayerExit { layer: layer as u8 });
+        }
+    }
+
+    for layer in 0..K::LAYERS {
+        for (i, (row, col)) in positions().enumerate() {
+            let key = K::layer_key(layer, row, col);
+
+            if key == TRANS {
+                if layer == 0 {
+                    warnings.push(LintWarning::TransOnBase {
+                        row: row as u8,
+                        col: col as u8,
+                    });
+                }
+            } else if key != 0
+                && positions()
+                    .take(i)
+                    .any(|(r, c)| K::layer_key(layer, r, c) == key)
+            {
+                warnings.push(LintWarning::DuplicateKey {
+                    layer: layer as u8,
+                    key,
+                    row: row as u8,
+                    col: col as u8,
+                });
+            }
+        }
+    }
+
+    warnings
+}
+
+/// Analyzes the active Atreus key map for problems.
+pub fn lint_active_keymap() -> Vec<LintWarning> {
+    lint_keymap::<ROWS, COLS, AtreusKeymap>()
+}
+
+/// Handles the [Lint](crate::config::Command::Lint) configuration protocol command.
+///
+/// The response is the number of warnings, followed by each serialized [LintWarning]. Warnings
+/// that do not fit in the buffer are dropped.
+///
+/// Returns the number of bytes written.
+pub fn handle_lint_command(buf: &mut [u8]) -> Result<usize, ConfigError> {
+    let (count, out) = buf.split_first_mut().ok_or(ConfigError::BufferTooSmall)?;
+
+    let warnings = lint_active_keymap();
+    let mut written = 0;
+
+    for (chunk, warning) in out
+        .chunks_exact_mut(LINT_WARNING_LEN)
+        .zip(warnings.iter())
+        .take(u8::MAX as usize)
+    {
+        chunk.copy_from_slice(warning.to_bytes().as_ref());
+        written += 1;
+    }
+
+    *count = written as u8;
+
+    Ok(1 + written * LINT_WARNING_LEN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::{A, B, FUN, NUMPAD, UPPER};
+
+    struct MacroPad;
+
+    const MACRO_PAD_LAYERS: [layers::LayerKeys<2, 3>; 4] = [
+        [[A, B, TRANS], [FUN, A, UPPER]],
+        [[TRANS, TRANS, TRANS], [TRANS, TRANS, TRANS]],
+        [[B, 0, 0], [0, 0, 0]],
+        [[NUMPAD, 0, 0], [0, 0, 0]],
+    ];
+
+    impl Keymap<2, 3> for MacroPad {
+        fn layer_key(layer: usize, row: usize, col: usize) -> u8 {
+            MACRO_PAD_LAYERS[layer % 4][row][col]
+        }
+    }
+
+    #[test]
+    fn test_lint_keymap() {
+        let warnings = lint_keymap::<2, 3, MacroPad>();
+
+        assert_eq!(
+            warnings,
+            [
+                LintWarning::MissingLayerExit { layer: 2 },
+                LintWarning::UnreachableLayer { layer: 3 },
+                LintWarning::TransOnBase { row: 0, col: 2 },
+                LintWarning::DuplicateKey {
+                    layer: 0,
+                    key: A,
+                    row: 1,
+                    col: 1
+                },
+            ]
+        );
+
+        assert_eq!(warnings[3].to_bytes(), [2, 0, A, 1, 1],);
+    }
+
+    #[test]
+    fn test_lint_command() {
+        let warnings = lint_active_keymap();
+
+        let mut buf = [0u8; 1 + LINT_WARNING_LEN];
+        let len = handle_lint_command(&mut buf).unwrap();
+
+        assert_eq!(buf[0] as usize, warnings.len().min(1));
+        assert_eq!(len, 1 + buf[0] as usize * LINT_WARNING_LEN);
+        assert_eq!(
+            handle_lint_command(&mut []),
+            Err(ConfigError::BufferTooSmall)
+        );
+    }
+}
@@ -0,0 +1,297 @@
+//! Macro playback.
+//!
+//! A macro is a program of [MacroStep]s, played back by a [MacroPlayer] one step per
+//! [poll](MacroPlayer::poll), so a long macro never blocks the scan loop. The keys held by the
+//! player are added to the reports built from the matrix, like keys held on the keyboard.
+//!
+//! The player is the shared backend of every macro source: static macros in the firmware, and
+//! macros recorded or looked up at runtime, e.g. dynamic macros or leader sequences.
+//!
+//! Board code (e.g. a [user key](crate::layers::USER0) handler) starts a macro from its table with
+//! [request_macro], the key scanner plays it on the next scan.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use crate::layers::{self, SHIFTED};
+use crate::scheduler::Timeout;
+
+/// Maximum number of keys held by a [MacroPlayer] at once.
+pub const MAX_MACRO_KEYS: usize = 6;
+
+const NO_MACRO: u8 = u8::MAX;
+
+static REQUESTED_MACRO: AtomicU8 = AtomicU8::new(NO_MACRO);
+
+/// Represents a step of a macro program.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MacroStep {
+    /// Presses and holds a key.
+    Press(u8),
+    /// Releases a held key.
+    Release(u8),
+    /// Presses a key, and releases it on the next poll.
+    Tap(u8),
+    /// Waits for the delay (in milliseconds) before the next step.
+    Delay(u16),
+    /// Taps the key of each character, see [ascii_key]. Characters without a key are skipped.
+    Text(&'static str),
+}
+
+/// Macro program.
+pub type Macro = &'static [MacroStep];
+
+/// Requests playing the macro at `index` of the key scanner's macro table.
+///
+/// A macro already playing is stopped.
+pub fn request_macro(index: u8) {
+    REQUESTED_MACRO.store(index, Ordering::SeqCst);
+}
+
+/// Takes the pending [request_macro] index.
+pub fn take_macro_request() -> Option<u8> {
+    let index = REQUESTED_MACRO.load(Ordering::Relaxed);
+
+    if index == NO_MACRO {
+        None
+    } else {
+        REQUESTED_MACRO.store(NO_MACRO, Ordering::SeqCst);
+        Some(index)
+    }
+}
+
+/// Gets the keycode typing an ASCII character on a US layout.
+///
+/// Characters typed with Shift return a [SHIFTED](layers::SHIFTED) keycode.
+pub const fn ascii_key(byte: u8) -> Option<u8> {
+    let key = match byte {
+        b'a'..=b'z' => layers::A + (byte - b'a'),
+        b'A'..=b'Z' => (layers::A + (byte - b'A')) | SHIFTED,
+        b'1'..=b'9' => layers::ONE + (byte - b'1'),
+        b'0' => layers::ZERO,
+        b'\n' => layers::ENTER,
+        b'\t' => layers::TAB,
+        b' ' => layers::SPACE,
+        b'-' => layers::DASH,
+        b'=' => layers::EQUAL,
+        b'[' => layers::L_BRACK,
+        b']' => layers::R_BRACK,
+        b'\\' => layers::PIPE,
+        b';' => layers::SEMI,
+        b'\'' => layers::QUOTE,
+        b'`' => layers::TICK,
+        b',' => layers::COMMA,
+        b'.' => layers::DOT,
+        b'/' => layers::SLASH,
+        b'!' => layers::EXCL,
+        b'@' => layers::AT,
+        b'#' => layers::HASH,
+        b'$' => layers::DOLLAR,
+        b'%' => layers::MOD,
+        b'^' => layers::CARET,
+        b'&' => layers::AMP,
+        b'*' => layers::STAR,
+        b'(' => layers::L_PAREN,
+        b')' => layers::R_PAREN,
+        b'{' => layers::L_BRACE,
+        b'}' => layers::R_BRACE,
+        b'+' => layers::PLUS,
+        b'_' => layers::DASH | SHIFTED,
+        b'|' => layers::PIPE | SHIFTED,
+        b':' => layers::SEMI | SHIFTED,
+        b'"' => layers::QUOTE | SHIFTED,
+        b'~' => layers::TICK | SHIFTED,
+        b'<' => layers::COMMA | SHIFTED,
+        b'>' => layers::DOT | SHIFTED,
+        b'?' => layers::SLASH | SHIFTED,
+        _ => return None,
+    };
+
+    Some(key)
+}
+
+/// Plays a [Macro] back incrementally.
+///
+/// Each [poll](Self::poll) runs one step, and the [keys](Self::keys) held after it are sent in
+/// the next reports. Keys still held when the program ends are released.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MacroPlayer {
+    program: Option<Macro>,
+    step: usize,
+    offset: usize,
+    tapped: Option<u8>,
+    delay: Timeout,
+    held: [u8; MAX_MACRO_KEYS],
+}
+
+impl MacroPlayer {
+    /// Creates a new [MacroPlayer], which is not playing.
+    pub const fn new() -> Self {
+        Self {
+            program: None,
+            step: 0,
+            offset: 0,
+            tapped: None,
+            delay: Timeout::new(),
+            held: [0; MAX_MACRO_KEYS],
+        }
+    }
+
+    /// Starts playing a macro, stopping the one playing.
+    pub fn play(&mut self, program: Macro) {
+        *self = Self::new();
+        self.program = Some(program);
+    }
+
+    /// Stops playing, and releases every held key.
+    pub fn stop(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Gets whether a macro is playing.
+    pub const fn is_playing(&self) -> bool {
+        self.program.is_some()
+    }
+
+    /// Gets the keys held by the macro.
+    pub fn keys(&self) -> impl Iterator<Item = u8> + '_ {
+        self.held.iter().copied().filter(|&key| key != 0)
+    }
+
+    /// Runs the next step of the macro at the time `now_ms`.
+    ///
+    /// A tapped key is released on the poll after it was pressed, so the host sees the press.
+    pub fn poll(&mut self, now_ms: u16) {
+        let Some(program) = self.program else {
+            return;
+        };
+
+        if let Some(key) = self.tapped.take() {
+            self.release(key);
+            return;
+        }
+
+        let Some(&step) = program.get(self.step) else {
+            self.stop();
+            return;
+        };
+
+        match step {
+            MacroStep::Press(key) => {
+                self.press(key);
+                self.step += 1;
+            }
+            MacroStep::Release(key) => {
+                self.release(key);
+                self.step += 1;
+            }
+            MacroStep::Tap(key) => {
+                self.tap(key);
+                self.step += 1;
+            }
+            MacroStep::Delay(delay_ms) => {
+                if !self.delay.is_pending() {
+                    self.delay.start(now_ms, delay_ms);
+                }
+
+                if self.delay.poll(now_ms) {
+                    self.step += 1;
+                }
+            }
+            MacroStep::Text(text) => {
+                let text = text.as_bytes();
+
+                // skip the characters without a key
+                while self.offset < text.len() {
+                    let byte = text[self.offset];
+                    self.offset += 1;
+
+                    if let Some(key) = ascii_key(byte) {
+                        self.tap(key);
+                        break;
+                    }
+                }
+
+                if self.offset >= text.len() {
+                    self.offset = 0;
+                    self.step += 1;
+                }
+            }
+        }
+    }
+
+    // Holds a key, keys past MAX_MACRO_KEYS are dropped.
+    fn press(&mut self, key: u8) {
+        if key == 0 || self.held.contains(&key) {
+            return;
+        }
+
+        if let Some(slot) = self.held.iter_mut().find(|k| **k == 0) {
+            *slot = key;
+        }
+    }
+
+    fn release(&mut self, key: u8) {
+        for held in self.held.iter_mut().filter(|k| **k == key) {
+            *held = 0;
+        }
+    }
+
+    fn tap(&mut self, key: u8) {
+        self.press(key);
+        self.tapped = Some(key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(player: &mut MacroPlayer, now_ms: u16) -> [u8; 2] {
+        player.poll(now_ms);
+
+        let mut keys = [0; 2];
+        for (slot, key) in keys.iter_mut().zip(player.keys()) {
+            *slot = key;
+        }
+        keys
+    }
+
+    #[test]
+    fn test_macro_player() {
+        static PROGRAM: [MacroStep; 5] = [
+            MacroStep::Press(layers::CTRL),
+            MacroStep::Tap(layers::C),
+            MacroStep::Release(layers::CTRL),
+            MacroStep::Delay(20),
+            MacroStep::Text("a\u{e9}B"),
+        ];
+
+        let mut player = MacroPlayer::new();
+        player.play(&PROGRAM);
+        assert!(player.is_playing());
+
+        assert_eq!(run(&mut player, 0), [layers::CTRL, 0]);
+        assert_eq!(run(&mut player, 1), [layers::CTRL, layers::C]);
+        assert_eq!(run(&mut player, 2), [layers::CTRL, 0]);
+        assert_eq!(run(&mut player, 3), [0, 0]);
+
+        // waits for the delay
+        assert_eq!(run(&mut player, 4), [0, 0]);
+        assert_eq!(run(&mut player, 10), [0, 0]);
+        assert_eq!(run(&mut player, 24), [0, 0]);
+
+        // the non-ASCII character is skipped
+        assert_eq!(run(&mut player, 25), [layers::A, 0]);
+        assert_eq!(run(&mut player, 26), [0, 0]);
+        assert_eq!(run(&mut player, 27), [layers::B | SHIFTED, 0]);
+        assert_eq!(run(&mut player, 28), [0, 0]);
+        assert!(player.is_playing());
+        assert_eq!(run(&mut player, 29), [0, 0]);
+        assert!(!player.is_playing());
+
+        assert_eq!(take_macro_request(), None);
+        request_macro(3);
+        assert_eq!(take_macro_request(), Some(3));
+        assert_eq!(take_macro_request(), None);
+    }
+}
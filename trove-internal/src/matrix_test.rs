@@ -0,0 +1,101 @@
+//! Matrix test mode.
+//!
+//! Helps builders verify solder joints and diodes. While test mode is enabled, every raw
+//! (undebounced) row and column change is streamed over a debug channel as a line of text, e.g.
+//! `R1 C4 down`, and no keys are sent to the host.
+//!
+//! Test mode is toggled by holding the [MAGIC_COMBO] keys, or enabled from power-on with the
+//! `matrix-test` firmware feature.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::events::KeyEvent;
+use crate::layers::{COLS, ROWS};
+
+/// Matrix positions (`(row, col)`) that toggle test mode when held together.
+///
+/// The outer keys of the top row, and the bottom-left key.
+pub const MAGIC_COMBO: &[(usize, usize)] = &[(0, 0), (0, COLS - 1), (ROWS - 1, 0)];
+
+/// Maximum length of a matrix test line, e.g. `R255 C255 down\r\n`.
+pub const MATRIX_TEST_LINE_LEN: usize = 16;
+
+static MATRIX_TEST: AtomicBool = AtomicBool::new(false);
+
+/// Gets whether matrix test mode is enabled.
+pub fn matrix_test_enabled() -> bool {
+    MATRIX_TEST.load(Ordering::Relaxed)
+}
+
+/// Sets whether matrix test mode is enabled.
+pub fn set_matrix_test_enabled(val: bool) {
+    MATRIX_TEST.store(val, Ordering::SeqCst);
+}
+
+/// Toggles matrix test mode, and returns whether it is now enabled.
+pub fn toggle_matrix_test() -> bool {
+    let enabled = !matrix_test_enabled();
+    set_matrix_test_enabled(enabled);
+    enabled
+}
+
+/// Writes the matrix test line for a raw switch change into the `buf`.
+///
+/// Returns the length of the line, e.g. `R1 C4 down\r\n` or `R1 C4 up\r\n`.
+pub fn write_event(event: &KeyEvent, buf: &mut [u8; MATRIX_TEST_LINE_LEN]) -> usize {
+    let mut len = 0;
+    let mut put = |bytes: &[u8]| {
+        buf[len..len + bytes.len()].copy_from_slice(bytes);
+        len += bytes.len();
+    };
+
+    put(b"R");
+    put(decimal(event.row(), &mut [0; 3]));
+    put(b" C");
+    put(decimal(event.col(), &mut [0; 3]));
+    put(if event.pressed() {
+        b" down\r\n"
+    } else {
+        b" up\r\n"
+    });
+
+    len
+}
+
+// Formats a `u8` as decimal digits, without leading zeros.
+fn decimal(val: u8, digits: &mut [u8; 3]) -> &[u8] {
+    let mut start = digits.len();
+    let mut val = val;
+
+    loop {
+        start -= 1;
+        digits[start] = b'0' + val % 10;
+        val /= 10;
+
+        if val == 0 {
+            break;
+        }
+    }
+
+    &digits[start..]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_event() {
+        let mut buf = [0u8; MATRIX_TEST_LINE_LEN];
+
+        let len = write_event(&KeyEvent::new(1, 4, true, 0), &mut buf);
+        assert_eq!(&buf[..len], b"R1 C4 down\r\n");
+
+        let len = write_event(&KeyEvent::new(3, 11, false, 0), &mut buf);
+        assert_eq!(&buf[..len], b"R3 C11 up\r\n");
+
+        // the longest line still fits
+        let len = write_event(&KeyEvent::new(255, 255, true, 0), &mut buf);
+        assert_eq!(&buf[..len], b"R255 C255 down\r\n");
+    }
+}
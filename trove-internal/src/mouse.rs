@@ -0,0 +1,157 @@
+//! Mouse reports.
+//!
+//! Pointing devices (e.g. an optical [trackball sensor](crate::pmw3360)) add their motion with
+//! [add_motion], usually from the main loop. Each matrix scan takes the motion gathered since the
+//! last scan with [take_motion], and sends it in a [MouseReport] on the
+//! [shared interface](crate::shared_report).
+//!
+//! Reports move at most 127 counts per axis, larger motion is kept for the following reports.
+
+use core::sync::atomic::{AtomicI16, Ordering};
+
+/// Length of a [MouseReport], without the report ID.
+pub const MOUSE_REPORT_LEN: usize = 4;
+
+static MOTION_X: AtomicI16 = AtomicI16::new(0);
+static MOTION_Y: AtomicI16 = AtomicI16::new(0);
+
+/// Adds motion (in counts) of a pointing device, sent with the next matrix scan.
+pub fn add_motion(dx: i16, dy: i16) {
+    MOTION_X.store(
+        MOTION_X.load(Ordering::Relaxed).saturating_add(dx),
+        Ordering::SeqCst,
+    );
+    MOTION_Y.store(
+        MOTION_Y.load(Ordering::Relaxed).saturating_add(dy),
+        Ordering::SeqCst,
+    );
+}
+
+/// Takes up to one report of the motion gathered by [add_motion], and keeps the rest.
+pub fn take_motion() -> (i8, i8) {
+    (take_axis(&MOTION_X), take_axis(&MOTION_Y))
+}
+
+// Takes up to one report of motion from an axis.
+fn take_axis(axis: &AtomicI16) -> i8 {
+    let motion = axis.load(Ordering::Relaxed);
+    let step = motion.clamp(-(i8::MAX as i16), i8::MAX as i16);
+
+    axis.store(motion - step, Ordering::SeqCst);
+
+    step as i8
+}
+
+/// Relative mouse report, with five buttons and a wheel.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MouseReport {
+    buttons: u8,
+    x: i8,
+    y: i8,
+    wheel: i8,
+}
+
+impl MouseReport {
+    /// Creates a new [MouseReport], without motion or buttons.
+    pub const fn new() -> Self {
+        Self {
+            buttons: 0,
+            x: 0,
+            y: 0,
+            wheel: 0,
+        }
+    }
+
+    /// Gets the bitfield of the pressed buttons, the left button in the lowest bit.
+    pub const fn buttons(&self) -> u8 {
+        self.buttons
+    }
+
+    /// Builder function that sets the bitfield of the pressed buttons.
+    pub const fn with_buttons(mut self, buttons: u8) -> Self {
+        self.buttons = buttons;
+        self
+    }
+
+    /// Gets the horizontal motion, positive to the right.
+    pub const fn x(&self) -> i8 {
+        self.x
+    }
+
+    /// Gets the vertical motion, positive downwards.
+    pub const fn y(&self) -> i8 {
+        self.y
+    }
+
+    /// Builder function that sets the motion.
+    pub const fn with_motion(mut self, (x, y): (i8, i8)) -> Self {
+        self.x = x;
+        self.y = y;
+        self
+    }
+
+    /// Builder function that adds motion, e.g. of a pointing device to the motion of the
+    /// [mouse keys](crate::mouse_keys).
+    pub const fn with_added_motion(mut self, (x, y): (i8, i8)) -> Self {
+        self.x = self.x.saturating_add(x);
+        self.y = self.y.saturating_add(y);
+        self
+    }
+
+    /// Gets the wheel steps, positive upwards.
+    pub const fn wheel(&self) -> i8 {
+        self.wheel
+    }
+
+    /// Builder function that sets the wheel steps.
+    pub const fn with_wheel(mut self, wheel: i8) -> Self {
+        self.wheel = wheel;
+        self
+    }
+
+    /// Gets whether the report moves the pointer or the wheel.
+    pub const fn has_motion(&self) -> bool {
+        self.x != 0 || self.y != 0 || self.wheel != 0
+    }
+
+    /// Adds the motion of a later report, and takes its buttons.
+    ///
+    /// Used to combine reports the host did not pick up yet, motion beyond one report is cut off.
+    pub const fn merge(mut self, later: &Self) -> Self {
+        self.buttons = later.buttons;
+        self.x = self.x.saturating_add(later.x);
+        self.y = self.y.saturating_add(later.y);
+        self.wheel = self.wheel.saturating_add(later.wheel);
+        self
+    }
+
+    /// Converts the report into its bytes.
+    pub const fn to_bytes(&self) -> [u8; MOUSE_REPORT_LEN] {
+        [self.buttons, self.x as u8, self.y as u8, self.wheel as u8]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mouse_motion() {
+        add_motion(300, -5);
+        assert_eq!(take_motion(), (127, -5));
+        assert_eq!(take_motion(), (127, 0));
+        assert_eq!(take_motion(), (46, 0));
+        assert_eq!(take_motion(), (0, 0));
+
+        let report = MouseReport::new().with_buttons(0b1).with_motion((100, -2));
+        assert!(report.has_motion());
+        assert_eq!(report.with_added_motion((50, 1)).x(), 127);
+        assert_eq!(report.with_added_motion((50, 1)).y(), -1);
+        assert_eq!(report.to_bytes(), [1, 100, 0xfe, 0]);
+
+        let merged = report.merge(&MouseReport::new().with_motion((100, 1)));
+        assert_eq!(merged.buttons(), 0);
+        assert_eq!((merged.x(), merged.y()), (127, -1));
+        assert!(!MouseReport::new().with_buttons(1).has_motion());
+    }
+}
@@ -0,0 +1,178 @@
+//! Mouse keys.
+//!
+//! With the [MouseKeys](crate::features::Feature::MouseKeys) feature enabled, the
+//! [MS_UP](layers::MS_UP) - [MS_WH_DN](layers::MS_WH_DN) keys move the pointer, press the mouse
+//! buttons, and scroll the wheel. Held movement and wheel keys step once every
+//! [MOUSE_KEY_INTERVAL_MS], by the [MouseSpeed](crate::tuning::Tuning::MouseSpeed) and
+//! [WheelSpeed](crate::tuning::Tuning::WheelSpeed) tuning parameters.
+//!
+//! With the feature disabled, mouse keys do nothing, and are never sent as keyboard usages.
+
+use crate::layers::{self, MS_BTN1, MS_DOWN, MS_LEFT, MS_RIGHT, MS_UP, MS_WH_DN, MS_WH_UP};
+use crate::mouse::MouseReport;
+use crate::tuning::{DEFAULT_MOUSE_SPEED, DEFAULT_WHEEL_SPEED};
+
+/// Time (in milliseconds) between the steps of held movement and wheel keys.
+pub const MOUSE_KEY_INTERVAL_MS: u16 = 16;
+
+// Bits of the movement and wheel keys in the held keys.
+const STEP_KEYS: u16 =
+    bit(MS_UP) | bit(MS_DOWN) | bit(MS_LEFT) | bit(MS_RIGHT) | bit(MS_WH_UP) | bit(MS_WH_DN);
+
+/// Mouse key state, updated once per matrix scan.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MouseKeys {
+    speed: i8,
+    wheel_speed: i8,
+    held: u16,
+    last_step: Option<u16>,
+}
+
+impl MouseKeys {
+    /// Creates a new [MouseKeys] with the default speeds.
+    pub const fn new() -> Self {
+        Self {
+            speed: DEFAULT_MOUSE_SPEED as i8,
+            wheel_speed: DEFAULT_WHEEL_SPEED as i8,
+            held: 0,
+            last_step: None,
+        }
+    }
+
+    /// Gets the distance (in pixels) the pointer moves per step.
+    pub const fn speed(&self) -> i8 {
+        self.speed
+    }
+
+    /// Sets the distance (in pixels) the pointer moves per step.
+    pub fn set_speed(&mut self, val: u16) {
+        self.speed = val.min(i8::MAX as u16) as i8;
+    }
+
+    /// Gets the number of wheel steps scrolled per step.
+    pub const fn wheel_speed(&self) -> i8 {
+        self.wheel_speed
+    }
+
+    /// Sets the number of wheel steps scrolled per step.
+    pub fn set_wheel_speed(&mut self, val: u16) {
+        self.wheel_speed = val.min(i8::MAX as u16) as i8;
+    }
+
+    /// Adds a key held during the current matrix scan.
+    ///
+    /// Returns whether the key is a mouse key, which is not sent in the keyboard reports.
+    pub fn add_key(&mut self, key: u8) -> bool {
+        if layers::key_is_mouse(key) {
+            self.held |= bit(key);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Builds the [MouseReport] of the keys added during the matrix scan at the scan clock time
+    /// `now_ms`, and clears them for the next scan.
+    ///
+    /// The first scan with a movement or wheel key held always steps. Returns a blank report
+    /// while the feature is not `enabled`.
+    pub fn update(&mut self, enabled: bool, now_ms: u16) -> MouseReport {
+        let held = core::mem::take(&mut self.held);
+
+        if !enabled || held & STEP_KEYS == 0 {
+            self.last_step = None;
+        }
+
+        if !enabled {
+            return MouseReport::new();
+        }
+
+        let buttons = (held >> index(MS_BTN1)) as u8 & 0b111;
+        let report = MouseReport::new().with_buttons(buttons);
+
+        let step = match self.last_step {
+            Some(last) => now_ms.wrapping_sub(last) >= MOUSE_KEY_INTERVAL_MS,
+            None => held & STEP_KEYS != 0,
+        };
+
+        if !step {
+            return report;
+        }
+
+        self.last_step = Some(now_ms);
+
+        let x = axis(held, MS_LEFT, MS_RIGHT) * self.speed;
+        let y = axis(held, MS_UP, MS_DOWN) * self.speed;
+        let wheel = axis(held, MS_WH_DN, MS_WH_UP) * self.wheel_speed;
+
+        report.with_motion((x, y)).with_wheel(wheel)
+    }
+}
+
+impl Default for MouseKeys {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Gets the index of a mouse key in the held keys: the movement keys, then the button and wheel
+// keys.
+const fn index(key: u8) -> u8 {
+    if key >= MS_UP {
+        key - MS_UP
+    } else {
+        key - MS_BTN1 + (MS_RIGHT - MS_UP + 1)
+    }
+}
+
+// Gets the bit of a mouse key in the held keys.
+const fn bit(key: u8) -> u16 {
+    1 << index(key)
+}
+
+// Gets the direction of an axis from its held keys, cancelling out when both are held.
+fn axis(held: u16, negative: u8, positive: u8) -> i8 {
+    (held & bit(positive) != 0) as i8 - (held & bit(negative) != 0) as i8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::{A, MS_BTN2};
+
+    #[test]
+    fn test_mouse_keys() {
+        let mut keys = MouseKeys::new();
+
+        assert!(keys.add_key(MS_RIGHT));
+        assert!(keys.add_key(MS_UP));
+        assert!(keys.add_key(MS_BTN2));
+        assert!(!keys.add_key(A));
+
+        let report = keys.update(true, 100);
+        assert_eq!((report.x(), report.y()), (8, -8));
+        assert_eq!(report.buttons(), 0b10);
+
+        // held keys step once per interval, buttons stay pressed
+        keys.add_key(MS_RIGHT);
+        keys.add_key(MS_BTN2);
+        let report = keys.update(true, 110);
+        assert!(!report.has_motion());
+        assert_eq!(report.buttons(), 0b10);
+
+        keys.set_speed(300);
+        keys.set_wheel_speed(3);
+        keys.add_key(MS_RIGHT);
+        keys.add_key(MS_LEFT);
+        keys.add_key(MS_WH_UP);
+        let report = keys.update(true, 116);
+        assert_eq!((report.x(), report.y(), report.wheel()), (0, 0, 3));
+        assert_eq!(keys.speed(), i8::MAX);
+
+        // released keys stop, and disabled mouse keys send nothing
+        assert_eq!(keys.update(true, 200), MouseReport::new());
+        keys.add_key(MS_DOWN);
+        keys.add_key(MS_BTN1);
+        assert_eq!(keys.update(false, 300), MouseReport::new());
+    }
+}
@@ -0,0 +1,203 @@
+//! N-key rollover report mode.
+//!
+//! The boot keyboard report holds at most six non-modifier keys, see
+//! [ERROR_ROLL_OVER](crate::report::ERROR_ROLL_OVER). In [ReportMode::Nkro], keys are sent as a
+//! bitmap of keyboard usages on a second HID interface instead, so any number of keys can be held
+//! at once, e.g. for gaming or stenography.
+//!
+//! The boot interface stays active in both modes, so the keyboard still works in the BIOS, and
+//! the host LED output report is still read from it. The mode is toggled with the
+//! [NKRO](crate::layers::NKRO) key.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use crate::layers;
+use crate::report::ReportBuilder;
+
+/// Length of an [NkroReport] in bytes, one bit for each keyboard usage.
+pub const NKRO_REPORT_LEN: usize = 32;
+
+/// HID report descriptor for the [NkroReport] interface.
+pub const NKRO_REPORT_DESCRIPTOR: &[u8] = &[
+    0x05, 0x01, // Usage Page (Generic Desktop)
+    0x09, 0x06, // Usage (Keyboard)
+    0xa1, 0x01, // Collection (Application)
+    0x05, 0x07, //   Usage Page (Keyboard/Keypad)
+    0x19, 0x00, //   Usage Minimum (0)
+    0x29, 0xff, //   Usage Maximum (255)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x25, 0x01, //   Logical Maximum (1)
+    0x75, 0x01, //   Report Size (1)
+    0x96, 0x00, 0x01, //   Report Count (256)
+    0x81, 0x02, //   Input (Data, Variable, Absolute)
+    0xc0, // End Collection
+];
+
+/// Represents the report format used to send keys to the host.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ReportMode {
+    /// Six-key boot keyboard reports.
+    #[default]
+    Boot = 0,
+    /// N-key rollover bitmap reports, see [NkroReport].
+    Nkro = 1,
+}
+
+impl TryFrom<u8> for ReportMode {
+    type Error = u8;
+
+    fn try_from(val: u8) -> Result<Self, Self::Error> {
+        match val {
+            0 => Ok(Self::Boot),
+            1 => Ok(Self::Nkro),
+            _ => Err(val),
+        }
+    }
+}
+
+impl From<ReportMode> for u8 {
+    fn from(val: ReportMode) -> Self {
+        val as u8
+    }
+}
+
+/// Report mode selected by the user.
+static REPORT_MODE: AtomicU8 = AtomicU8::new(ReportMode::Boot as u8);
+
+/// Gets the [ReportMode] used to send keys to the host.
+pub fn report_mode() -> ReportMode {
+    ReportMode::try_from(REPORT_MODE.load(Ordering::Relaxed)).unwrap_or_default()
+}
+
+/// Sets the [ReportMode] used to send keys to the host.
+pub fn set_report_mode(mode: ReportMode) {
+    REPORT_MODE.store(mode.into(), Ordering::SeqCst);
+}
+
+/// Toggles between the [ReportMode]s, and returns the new mode.
+pub fn toggle_report_mode() -> ReportMode {
+    let mode = match report_mode() {
+        ReportMode::Boot => ReportMode::Nkro,
+        ReportMode::Nkro => ReportMode::Boot,
+    };
+
+    set_report_mode(mode);
+    mode
+}
+
+/// Represents an N-key rollover report, with one bit set for each held keyboard usage.
+///
+/// Modifiers are sent as their usages (`0xe0` - `0xe7`).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct NkroReport([u8; NKRO_REPORT_LEN]);
+
+impl NkroReport {
+    /// Creates a new (blank) [NkroReport].
+    pub const fn new() -> Self {
+        Self([0; NKRO_REPORT_LEN])
+    }
+
+    /// Creates an [NkroReport] from the keys added to a [ReportBuilder].
+    ///
+    /// Holds the same keys and modifiers as the boot report from the [ReportBuilder], so shifted
+    /// keys are sent with Left Shift held.
+    pub fn from_builder(builder: &ReportBuilder) -> Self {
+        let mut report = Self::new();
+        let modifier = builder.report_modifier();
+
+        for bit in 0..8 {
+            if modifier & (1 << bit) != 0 {
+                report.set_usage(layers::CTRL + bit, true);
+            }
+        }
+
+        for key in builder.report_keys() {
+            report.set_usage(key, true);
+        }
+
+        report
+    }
+
+    /// Gets whether the keyboard `usage` is held.
+    pub const fn usage(&self, usage: u8) -> bool {
+        self.0[(usage / 8) as usize] & (1 << (usage % 8)) != 0
+    }
+
+    /// Sets whether the keyboard `usage` is held.
+    pub fn set_usage(&mut self, usage: u8, val: bool) {
+        let (byte, bit) = ((usage / 8) as usize, usage % 8);
+
+        if val {
+            self.0[byte] |= 1 << bit;
+        } else {
+            self.0[byte] &= !(1 << bit);
+        }
+    }
+
+    /// Gets whether no usage is held.
+    pub fn is_blank(&self) -> bool {
+        self.0.iter().all(|&b| b == 0)
+    }
+
+    /// Gets the report bytes sent to the host.
+    pub const fn as_bytes(&self) -> &[u8; NKRO_REPORT_LEN] {
+        &self.0
+    }
+}
+
+impl Default for NkroReport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::{A, B, C, CTRL, D, E, EXCL, F, G, INTL1, LANG9, ONE, SHIFT};
+
+    #[test]
+    fn test_nkro_report() {
+        let mut builder = ReportBuilder::new();
+
+        for key in [A, B, C, D, E, F, G, CTRL] {
+            builder.add_key(key);
+        }
+
+        let report = NkroReport::from_builder(&builder);
+
+        // more than six keys are held at once
+        for key in [A, B, C, D, E, F, G, CTRL] {
+            assert!(report.usage(key));
+        }
+
+        assert!(!report.usage(SHIFT));
+        assert_eq!(report.as_bytes()[0], 0b1111_0000);
+
+        let mut builder = ReportBuilder::new();
+        builder.add_key(EXCL);
+
+        let report = NkroReport::from_builder(&builder);
+        assert!(report.usage(ONE) && report.usage(SHIFT));
+
+        let mut builder = ReportBuilder::new();
+        builder.add_key(INTL1);
+        builder.add_key(LANG9);
+
+        let report = NkroReport::from_builder(&builder);
+        assert!(report.usage(0x87) && report.usage(0x98));
+        assert!(!report.usage(0x07) && !report.usage(0x18) && !report.usage(SHIFT));
+
+        assert!(NkroReport::from_builder(&ReportBuilder::new()).is_blank());
+    }
+
+    #[test]
+    fn test_report_mode() {
+        assert_eq!(report_mode(), ReportMode::Boot);
+        assert_eq!(toggle_report_mode(), ReportMode::Nkro);
+        assert_eq!(report_mode(), ReportMode::Nkro);
+        assert_eq!(toggle_report_mode(), ReportMode::Boot);
+        assert_eq!(ReportMode::try_from(2), Err(2));
+    }
+}
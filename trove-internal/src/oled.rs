@@ -0,0 +1,316 @@
+//! SSD1306 OLED status display.
+//!
+//! Handwired builds often add a 128x32 SSD1306 module on the I2C pins. The display shows a status
+//! screen (see [render_status]) with:
+//!
+//! - the active layer
+//! - the host lock states
+//! - the typing speed, see [wpm](crate::wpm)
+//! - a notice while the keyboard is about to enter the bootloader
+//!
+//! The screen is drawn into an [OledBuffer] in the memory layout of the display: [OLED_PAGES]
+//! pages of 8 pixel rows, one byte per column, least significant bit on top. Text uses a 5x7
+//! font in 6 pixel wide cells, upper case only.
+
+use crate::indicator::HostLock;
+use crate::layers::Layer;
+use crate::status_leds::FirmwareStatus;
+
+/// Default I2C address of SSD1306 modules.
+pub const SSD1306_ADDR: u8 = 0x3c;
+/// Width of the display in pixels.
+pub const OLED_WIDTH: usize = 128;
+/// Number of 8 pixel high pages of the display.
+pub const OLED_PAGES: usize = 4;
+/// Size of the display memory in bytes.
+pub const OLED_BUFFER_LEN: usize = OLED_WIDTH * OLED_PAGES;
+/// Width of a text character cell in pixels.
+pub const CHAR_WIDTH: usize = 6;
+/// Number of text characters per line.
+pub const LINE_CHARS: usize = OLED_WIDTH / CHAR_WIDTH;
+
+/// I2C control byte starting a stream of commands.
+pub const CONTROL_COMMANDS: u8 = 0x00;
+/// I2C control byte starting a stream of display data.
+pub const CONTROL_DATA: u8 = 0x40;
+
+/// Commands setting up a 128x32 display, and turning it on.
+pub const SSD1306_INIT: [u8; 25] = [
+    0xae, // display off
+    0xd5, 0x80, // clock divider
+    0xa8, 0x1f, // multiplex ratio: 32 rows
+    0xd3, 0x00, // no display offset
+    0x40, // start line 0
+    0x8d, 0x14, // charge pump on
+    0x20, 0x00, // horizontal addressing
+    0xa1, // segment remap
+    0xc8, // scan rows from the bottom
+    0xda, 0x02, // COM pins of 128x32 modules
+    0x81, 0x8f, // contrast
+    0xd9, 0xf1, // pre-charge period
+    0xdb, 0x40, // VCOMH level
+    0xa4, // show the display memory
+    0xa6, // not inverted
+    0xaf, // display on
+];
+
+/// Commands selecting the full display memory (every column, then every page), before writing
+/// an [OledBuffer].
+pub const SSD1306_WINDOW: [u8; 6] = [0x21, 0, LAST_COLUMN, 0x22, 0, LAST_PAGE];
+
+const LAST_COLUMN: u8 = (OLED_WIDTH - 1) as u8;
+const LAST_PAGE: u8 = (OLED_PAGES - 1) as u8;
+
+/// Display memory of a 128x32 SSD1306.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OledBuffer {
+    pixels: [u8; OLED_BUFFER_LEN],
+    dirty: bool,
+}
+
+impl OledBuffer {
+    /// Creates a new blank [OledBuffer].
+    ///
+    /// The buffer starts dirty, so the first flush clears the display.
+    pub const fn new() -> Self {
+        Self {
+            pixels: [0; OLED_BUFFER_LEN],
+            dirty: true,
+        }
+    }
+
+    /// Gets the display memory.
+    pub const fn pixels(&self) -> &[u8; OLED_BUFFER_LEN] {
+        &self.pixels
+    }
+
+    /// Gets whether the buffer changed since it was last sent.
+    pub const fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Takes the changed flag, call before sending the buffer.
+    pub fn take_dirty(&mut self) -> bool {
+        core::mem::take(&mut self.dirty)
+    }
+
+    /// Draws a character in the text cell at `col` on the `page`.
+    ///
+    /// Lower case letters are drawn in upper case, characters outside of the font as blanks.
+    /// Cells outside of the display are ignored.
+    pub fn draw_char(&mut self, col: usize, page: usize, ch: u8) {
+        if col >= LINE_CHARS || page >= OLED_PAGES {
+            return;
+        }
+
+        let start = page * OLED_WIDTH + col * CHAR_WIDTH;
+        let glyph = glyph(ch);
+
+        for (i, &bits) in glyph.iter().chain(&[0]).enumerate() {
+            self.put(start + i, bits);
+        }
+    }
+
+    /// Draws a line of text on the `page`, blanking the rest of the line.
+    pub fn draw_line(&mut self, page: usize, text: &[u8]) {
+        for col in 0..LINE_CHARS {
+            self.draw_char(col, page, text.get(col).copied().unwrap_or(b' '));
+        }
+    }
+
+    /// Blanks the display.
+    pub fn clear(&mut self) {
+        for page in 0..OLED_PAGES {
+            self.draw_line(page, b"");
+        }
+    }
+
+    // Writes a byte of display memory, and marks the buffer dirty on changes.
+    fn put(&mut self, index: usize, bits: u8) {
+        if self.pixels[index] != bits {
+            self.pixels[index] = bits;
+            self.dirty = true;
+        }
+    }
+}
+
+impl Default for OledBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Draws the status screen for the [FirmwareStatus], and the words per minute.
+pub fn render_status(buf: &mut OledBuffer, status: &FirmwareStatus, wpm: u16) {
+    let mut line = LineWriter::new();
+
+    line.put(b"LAYER ");
+    line.put(match status.layer() {
+        Layer::Base => b"BASE".as_slice(),
+        Layer::Fun => b"FUN",
+        Layer::Upper => b"UPPER",
+        Layer::Numpad => b"NUMPAD",
+    });
+    buf.draw_line(0, line.take());
+
+    // keep each lock in its place, so the others do not move
+    for (lock, name) in [
+        (HostLock::NumLock, b"NUM "),
+        (HostLock::CapsLock, b"CAPS"),
+        (HostLock::ScrollLock, b"SCRL"),
+    ] {
+        line.put(if lock.is_on(status.host_leds()) {
+            name
+        } else {
+            b"    "
+        });
+        line.put(b" ");
+    }
+    buf.draw_line(1, line.take());
+
+    line.put(b"WPM ");
+    line.put_u16(wpm);
+    buf.draw_line(2, line.take());
+
+    buf.draw_line(
+        3,
+        if status.bootloader() {
+            b"BOOTLOADER"
+        } else {
+            b""
+        },
+    );
+}
+
+// Collects a line of text.
+struct LineWriter {
+    buf: [u8; LINE_CHARS],
+    len: usize,
+}
+
+impl LineWriter {
+    fn new() -> Self {
+        Self {
+            buf: [b' '; LINE_CHARS],
+            len: 0,
+        }
+    }
+
+    fn put(&mut self, text: &[u8]) {
+        for &ch in text {
+            if self.len < LINE_CHARS {
+                self.buf[self.len] = ch;
+                self.len += 1;
+            }
+        }
+    }
+
+    fn put_u16(&mut self, val: u16) {
+        let mut digits = [0u8; 5];
+        let mut start = digits.len();
+        let mut val = val;
+
+        loop {
+            start -= 1;
+            digits[start] = b'0' + (val % 10) as u8;
+            val /= 10;
+
+            if val == 0 {
+                break;
+            }
+        }
+
+        self.put(&digits[start..]);
+    }
+
+    // Gets the line, and starts the next one.
+    fn take(&mut self) -> &[u8] {
+        let len = core::mem::take(&mut self.len);
+        &self.buf[..len]
+    }
+}
+
+// Gets the columns of a 5x7 font character.
+const fn glyph(ch: u8) -> [u8; 5] {
+    match ch.to_ascii_uppercase() {
+        b'-' => [0x08, 0x08, 0x08, 0x08, 0x08],
+        b'/' => [0x20, 0x10, 0x08, 0x04, 0x02],
+        b':' => [0x00, 0x36, 0x36, 0x00, 0x00],
+        b'0' => [0x3e, 0x51, 0x49, 0x45, 0x3e],
+        b'1' => [0x00, 0x42, 0x7f, 0x40, 0x00],
+        b'2' => [0x42, 0x61, 0x51, 0x49, 0x46],
+        b'3' => [0x21, 0x41, 0x45, 0x4b, 0x31],
+        b'4' => [0x18, 0x14, 0x12, 0x7f, 0x10],
+        b'5' => [0x27, 0x45, 0x45, 0x45, 0x39],
+        b'6' => [0x3c, 0x4a, 0x49, 0x49, 0x30],
+        b'7' => [0x01, 0x71, 0x09, 0x05, 0x03],
+        b'8' => [0x36, 0x49, 0x49, 0x49, 0x36],
+        b'9' => [0x06, 0x49, 0x49, 0x29, 0x1e],
+        b'A' => [0x7e, 0x11, 0x11, 0x11, 0x7e],
+        b'B' => [0x7f, 0x49, 0x49, 0x49, 0x36],
+        b'C' => [0x3e, 0x41, 0x41, 0x41, 0x22],
+        b'D' => [0x7f, 0x41, 0x41, 0x22, 0x1c],
+        b'E' => [0x7f, 0x49, 0x49, 0x49, 0x41],
+        b'F' => [0x7f, 0x09, 0x09, 0x09, 0x01],
+        b'G' => [0x3e, 0x41, 0x49, 0x49, 0x7a],
+        b'H' => [0x7f, 0x08, 0x08, 0x08, 0x7f],
+        b'I' => [0x00, 0x41, 0x7f, 0x41, 0x00],
+        b'J' => [0x20, 0x40, 0x41, 0x3f, 0x01],
+        b'K' => [0x7f, 0x08, 0x14, 0x22, 0x41],
+        b'L' => [0x7f, 0x40, 0x40, 0x40, 0x40],
+        b'M' => [0x7f, 0x02, 0x0c, 0x02, 0x7f],
+        b'N' => [0x7f, 0x04, 0x08, 0x10, 0x7f],
+        b'O' => [0x3e, 0x41, 0x41, 0x41, 0x3e],
+        b'P' => [0x7f, 0x09, 0x09, 0x09, 0x06],
+        b'Q' => [0x3e, 0x41, 0x51, 0x21, 0x5e],
+        b'R' => [0x7f, 0x09, 0x19, 0x29, 0x46],
+        b'S' => [0x46, 0x49, 0x49, 0x49, 0x31],
+        b'T' => [0x01, 0x01, 0x7f, 0x01, 0x01],
+        b'U' => [0x3f, 0x40, 0x40, 0x40, 0x3f],
+        b'V' => [0x1f, 0x20, 0x40, 0x20, 0x1f],
+        b'W' => [0x3f, 0x40, 0x38, 0x40, 0x3f],
+        b'X' => [0x63, 0x14, 0x08, 0x14, 0x63],
+        b'Y' => [0x07, 0x08, 0x70, 0x08, 0x07],
+        b'Z' => [0x61, 0x51, 0x49, 0x45, 0x43],
+        _ => [0; 5],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::host_leds::HostLedState;
+
+    #[test]
+    fn test_status_screen() {
+        let mut buf = OledBuffer::new();
+        assert!(buf.take_dirty());
+
+        buf.draw_char(1, 2, b'a');
+        let start = 2 * OLED_WIDTH + CHAR_WIDTH;
+        assert_eq!(
+            buf.pixels()[start..start + CHAR_WIDTH],
+            [0x7e, 0x11, 0x11, 0x11, 0x7e, 0]
+        );
+        assert!(buf.take_dirty());
+
+        // drawing the same character again is not a change
+        buf.draw_char(1, 2, b'A');
+        assert!(!buf.is_dirty());
+
+        let status = FirmwareStatus::new()
+            .with_layer(Layer::Fun)
+            .with_host_leds(HostLedState::from_u8(0b10));
+        render_status(&mut buf, &status, 42);
+        assert!(buf.take_dirty());
+
+        let mut expected = OledBuffer::new();
+        expected.draw_line(0, b"LAYER FUN");
+        expected.draw_line(1, b"     CAPS");
+        expected.draw_line(2, b"WPM 42");
+        assert_eq!(buf.pixels(), expected.pixels());
+
+        render_status(&mut buf, &status, 42);
+        assert!(!buf.is_dirty());
+    }
+}
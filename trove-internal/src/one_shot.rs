@@ -0,0 +1,117 @@
+//! One-shot modifiers.
+//!
+//! With the [OneShot](crate::features::Feature::OneShot) feature enabled, tapping a one-shot key
+//! ([OS_CTRL](layers::OS_CTRL) - [OS_CMD](layers::OS_CMD)) applies its modifier to the next key
+//! press only, so modifier combinations can be typed one key at a time. Held while another key is
+//! pressed, a one-shot key acts as its plain modifier.
+//!
+//! With the feature disabled, the one-shot keys are always plain modifiers.
+
+use crate::layers;
+
+/// One-shot modifier state.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct OneShot {
+    pending: u8,
+    held: u8,
+    used: bool,
+}
+
+impl OneShot {
+    /// Creates a new [OneShot], without pending modifiers.
+    pub const fn new() -> Self {
+        Self {
+            pending: 0,
+            held: 0,
+            used: false,
+        }
+    }
+
+    /// Gets the modifiers waiting for the next key press.
+    pub const fn pending(&self) -> u8 {
+        self.pending
+    }
+
+    /// Gets whether modifiers are waiting for the next key press.
+    pub const fn is_pending(&self) -> bool {
+        self.pending != 0
+    }
+
+    /// Clears the pending modifiers.
+    pub fn clear(&mut self) {
+        self.pending = 0;
+    }
+
+    /// Handles a key press, and returns the pending modifiers to send with the key.
+    ///
+    /// Modifier keys leave the pending modifiers for the next key.
+    pub fn press(&mut self, key: u8) -> u8 {
+        if let Some(modifier) = layers::one_shot_modifier(key) {
+            self.held |= layers::key_to_modifier(modifier);
+            self.used = false;
+            return 0;
+        }
+
+        if layers::key_is_modifier(key) {
+            return 0;
+        }
+
+        if self.held != 0 {
+            self.used = true;
+        }
+
+        core::mem::take(&mut self.pending)
+    }
+
+    /// Handles a key release.
+    ///
+    /// A one-shot key released without another key pressed while it was held leaves its modifier
+    /// pending.
+    pub fn release(&mut self, key: u8) {
+        if let Some(modifier) = layers::one_shot_modifier(key) {
+            let modifier = layers::key_to_modifier(modifier);
+
+            self.held &= !modifier;
+
+            if !self.used {
+                self.pending |= modifier;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::{A, B, CTRL, OS_CTRL, OS_SHIFT, SHIFT};
+
+    #[test]
+    fn test_one_shot() {
+        let mut one_shot = OneShot::new();
+        let ctrl = layers::key_to_modifier(CTRL);
+        let shift = layers::key_to_modifier(SHIFT);
+
+        // tapped one-shot keys apply to the next key only
+        assert_eq!(one_shot.press(OS_SHIFT), 0);
+        one_shot.release(OS_SHIFT);
+        assert_eq!(one_shot.press(OS_CTRL), 0);
+        one_shot.release(OS_CTRL);
+        assert_eq!(one_shot.pending(), ctrl | shift);
+
+        assert_eq!(one_shot.press(CTRL), 0);
+        assert_eq!(one_shot.press(A), ctrl | shift);
+        assert_eq!(one_shot.press(B), 0);
+        assert!(!one_shot.is_pending());
+
+        // held while another key is pressed, a one-shot key is a plain modifier
+        one_shot.press(OS_SHIFT);
+        assert_eq!(one_shot.press(A), 0);
+        one_shot.release(OS_SHIFT);
+        assert!(!one_shot.is_pending());
+
+        one_shot.press(OS_SHIFT);
+        one_shot.release(OS_SHIFT);
+        one_shot.clear();
+        assert_eq!(one_shot.press(A), 0);
+    }
+}
@@ -0,0 +1,268 @@
+//! Host to device output and feature reports.
+//!
+//! The host sends output reports (e.g. the LED state) and feature reports to the keyboard with
+//! `SET_REPORT` requests. Subsystems register an [OutputReportHandler] for the interface they
+//! own, and the USB context dispatches every report it reads to the [OutputHandlers].
+//!
+//! Handlers on the raw HID interface can answer with an input report, e.g. the
+//! [configuration](crate::config) responses, or the replies of a VIA-compatible handler.
+
+use usbd_hid::hid_class::ReportType;
+
+use crate::config;
+use crate::host_leds::{self, HostLedState};
+use crate::raw_hid::{self, RAW_REPORT_LEN};
+use crate::storage::EepromStorage;
+
+/// Maximum number of registered [OutputReportHandler]s.
+pub const MAX_OUTPUT_HANDLERS: usize = 4;
+
+/// Represents the HID interface a host report was sent to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReportInterface {
+    /// Boot keyboard interface.
+    Keyboard,
+    /// Raw HID interface.
+    RawHid,
+}
+
+/// Represents a report sent by the host.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OutputReport<'a> {
+    interface: ReportInterface,
+    report_type: ReportType,
+    data: &'a [u8],
+}
+
+impl<'a> OutputReport<'a> {
+    /// Creates a new [OutputReport].
+    pub const fn new(interface: ReportInterface, report_type: ReportType, data: &'a [u8]) -> Self {
+        Self {
+            interface,
+            report_type,
+            data,
+        }
+    }
+
+    /// Gets the interface the report was sent to.
+    pub const fn interface(&self) -> ReportInterface {
+        self.interface
+    }
+
+    /// Gets the report type, [Output](ReportType::Output) or [Feature](ReportType::Feature).
+    pub const fn report_type(&self) -> ReportType {
+        self.report_type
+    }
+
+    /// Gets the report data.
+    pub const fn data(&self) -> &'a [u8] {
+        self.data
+    }
+}
+
+/// Handler for host reports, called with the report, and a buffer for an input report in
+/// response.
+///
+/// Returns the length of the response, or `0` if there is none. Called from the USB interrupts,
+/// so it should return quickly.
+pub type OutputReportHandler = fn(report: &OutputReport, response: &mut [u8]) -> usize;
+
+/// Registered [OutputReportHandler]s, by interface.
+#[derive(Clone, Copy, Debug)]
+pub struct OutputHandlers {
+    handlers: [Option<(ReportInterface, OutputReportHandler)>; MAX_OUTPUT_HANDLERS],
+}
+
+impl OutputHandlers {
+    /// Creates a new [OutputHandlers], without any handler.
+    pub const fn new() -> Self {
+        Self {
+            handlers: [None; MAX_OUTPUT_HANDLERS],
+        }
+    }
+
+    /// Creates a new [OutputHandlers], with the firmware [LED report](handle_led_report) handler.
+    ///
+    /// The [configuration commands](handle_config_report) need the EEPROM, so the keyboard
+    /// registers their handler once the EEPROM is set up.
+    pub const fn firmware() -> Self {
+        let mut handlers = Self::new();
+
+        handlers.handlers[0] = Some((ReportInterface::Keyboard, handle_led_report));
+
+        handlers
+    }
+
+    /// Registers the `handler` for reports sent to the `interface`.
+    ///
+    /// Returns `false` if [MAX_OUTPUT_HANDLERS] are already registered.
+    pub fn register(&mut self, interface: ReportInterface, handler: OutputReportHandler) -> bool {
+        match self.handlers.iter_mut().find(|h| h.is_none()) {
+            Some(slot) => {
+                *slot = Some((interface, handler));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Builder function that registers the `handler` for reports sent to the `interface`.
+    pub fn with_handler(
+        mut self,
+        interface: ReportInterface,
+        handler: OutputReportHandler,
+    ) -> Self {
+        self.register(interface, handler);
+        self
+    }
+
+    /// Removes every handler registered for the `interface`.
+    pub fn clear(&mut self, interface: ReportInterface) {
+        for slot in self.handlers.iter_mut() {
+            if slot.is_some_and(|(i, _)| i == interface) {
+                *slot = None;
+            }
+        }
+    }
+
+    /// Dispatches the `report` to the handlers registered for its interface, in registration
+    /// order.
+    ///
+    /// Returns the length of the first response written to the `response` buffer, later
+    /// handlers only see the report.
+    pub fn dispatch(&self, report: &OutputReport, response: &mut [u8]) -> usize {
+        let mut len = 0;
+
+        for (_, handler) in self
+            .handlers
+            .iter()
+            .flatten()
+            .filter(|(interface, _)| *interface == report.interface())
+        {
+            if len == 0 {
+                len = handler(report, response);
+            } else {
+                handler(report, &mut []);
+            }
+        }
+
+        len
+    }
+}
+
+impl Default for OutputHandlers {
+    fn default() -> Self {
+        Self::firmware()
+    }
+}
+
+/// Handles the boot keyboard LED output report, see [host_leds].
+pub fn handle_led_report(report: &OutputReport, _response: &mut [u8]) -> usize {
+    if report.report_type() == ReportType::Output {
+        if let Some(leds) = HostLedState::from_report(report.data()) {
+            host_leds::set_host_leds(leds);
+        }
+    }
+
+    0
+}
+
+/// Handles the [configuration commands](config::Command) on the raw HID interface, see
+/// [raw_hid], with the EEPROM `storage`.
+///
+/// Called by the keyboard's raw HID [OutputReportHandler], which owns the EEPROM.
+pub fn handle_config_report<S: EepromStorage>(
+    storage: &mut S,
+    report: &OutputReport,
+    response: &mut [u8],
+) -> usize {
+    match response.get_mut(..RAW_REPORT_LEN) {
+        Some(response) if report.report_type() == ReportType::Output => {
+            response.copy_from_slice(&raw_hid::handle_raw_report(
+                report.data(),
+                |command, args, buf| config::handle_command(storage, command, args, buf),
+            ));
+            RAW_REPORT_LEN
+        }
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{Command, ConfigError};
+
+    fn echo(report: &OutputReport, response: &mut [u8]) -> usize {
+        let len = report.data().len().min(response.len());
+        response[..len].copy_from_slice(&report.data()[..len]);
+        len
+    }
+
+    fn silent(_report: &OutputReport, _response: &mut [u8]) -> usize {
+        0
+    }
+
+    #[test]
+    fn test_output_handlers() {
+        let mut handlers = OutputHandlers::new().with_handler(ReportInterface::RawHid, silent);
+        let mut response = [0u8; 4];
+
+        let report = OutputReport::new(ReportInterface::RawHid, ReportType::Output, &[1, 2]);
+        assert_eq!(handlers.dispatch(&report, &mut response), 0);
+
+        assert!(handlers.register(ReportInterface::RawHid, echo));
+        assert_eq!(handlers.dispatch(&report, &mut response), 2);
+        assert_eq!(response, [1, 2, 0, 0]);
+
+        // only handlers for the interface of the report are called
+        let report = OutputReport::new(ReportInterface::Keyboard, ReportType::Output, &[3]);
+        assert_eq!(handlers.dispatch(&report, &mut response), 0);
+
+        assert!(handlers.register(ReportInterface::Keyboard, echo));
+        assert!(handlers.register(ReportInterface::Keyboard, silent));
+        assert!(!handlers.register(ReportInterface::Keyboard, silent));
+
+        handlers.clear(ReportInterface::RawHid);
+        assert!(handlers.register(ReportInterface::Keyboard, silent));
+    }
+
+    /// Storage without any space, so every access fails.
+    struct NoStorage;
+
+    impl EepromStorage for NoStorage {
+        fn load(&self, _offset: usize, _buf: &mut [u8]) -> Result<(), ConfigError> {
+            Err(ConfigError::InvalidArgument)
+        }
+
+        fn store(&mut self, _offset: usize, _data: &[u8]) -> Result<(), ConfigError> {
+            Err(ConfigError::InvalidArgument)
+        }
+    }
+
+    #[test]
+    fn test_config_report() {
+        let mut response = [0u8; RAW_REPORT_LEN];
+
+        // unknown commands are answered with an error status
+        let report = OutputReport::new(ReportInterface::RawHid, ReportType::Output, &[0xff]);
+        assert_eq!(
+            handle_config_report(&mut NoStorage, &report, &mut response),
+            RAW_REPORT_LEN
+        );
+        assert_eq!(response[0], 0xff);
+        assert_ne!(response[1], 0);
+
+        // storage errors are reported to the host
+        let read = [Command::ReadKeymap.into(), 0, 0, 1];
+        let report = OutputReport::new(ReportInterface::RawHid, ReportType::Output, &read);
+        handle_config_report(&mut NoStorage, &report, &mut response);
+        assert_eq!(response[1], ConfigError::InvalidArgument.code());
+
+        let report = OutputReport::new(ReportInterface::RawHid, ReportType::Feature, &[0xff]);
+        assert_eq!(
+            handle_config_report(&mut NoStorage, &report, &mut response),
+            0
+        );
+    }
+}
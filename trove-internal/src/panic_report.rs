@@ -0,0 +1,120 @@
+//! Panic diagnostics.
+//!
+//! A panic stops the keyboard, so the panic handler makes the failure visible instead of hanging
+//! silently:
+//!
+//! - the status LED blinks the [PANIC_BLINK_PATTERN]
+//! - the panic location is written to the debug port, if there is one, e.g.
+//!   `panic at src/key_scanner.rs:123:9`
+//! - with the `panic-bootloader` feature, the keyboard reboots into the bootloader after
+//!   [PANIC_BOOTLOADER_BLINKS] blinks, ready for a fixed firmware
+//!
+//! Only the location is reported, formatting the panic message would pull the `core::fmt`
+//! machinery into every build.
+
+/// Maximum length of a panic line, including the line ending.
+pub const PANIC_LINE_LEN: usize = 64;
+/// Status LED states, and how long (in milliseconds) each is held, repeated while panicking.
+pub const PANIC_BLINK_PATTERN: [(bool, u16); 4] =
+    [(true, 100), (false, 100), (true, 300), (false, 500)];
+/// Number of repeats of the [PANIC_BLINK_PATTERN] before rebooting into the bootloader.
+pub const PANIC_BOOTLOADER_BLINKS: u8 = 5;
+
+const PANIC_PREFIX: &[u8] = b"panic at ";
+const ELLIPSIS: &[u8] = b"...";
+
+/// Writes the panic line for a location (`panic at <file>:<line>:<column>\r\n`) into the `buf`.
+///
+/// Long file paths are cut from the front, keeping the file name. Returns the length of the line.
+pub fn write_panic_line(
+    file: &str,
+    line: u32,
+    column: u32,
+    buf: &mut [u8; PANIC_LINE_LEN],
+) -> usize {
+    let mut digits = [0u8; 24];
+    let mut suffix = DigitWriter::new(&mut digits);
+    suffix.put_byte(b':');
+    suffix.put_u32(line);
+    suffix.put_byte(b':');
+    suffix.put_u32(column);
+    let suffix_len = suffix.len;
+
+    let file = file.as_bytes();
+    let room = PANIC_LINE_LEN - PANIC_PREFIX.len() - suffix_len - 2;
+    let mut len = 0;
+
+    let mut put = |bytes: &[u8]| {
+        buf[len..len + bytes.len()].copy_from_slice(bytes);
+        len += bytes.len();
+    };
+
+    put(PANIC_PREFIX);
+    if file.len() > room {
+        put(ELLIPSIS);
+        put(&file[file.len() - (room - ELLIPSIS.len())..]);
+    } else {
+        put(file);
+    }
+    put(&digits[..suffix_len]);
+    put(b"\r\n");
+
+    len
+}
+
+// Writes decimal numbers without `core::fmt`.
+struct DigitWriter<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> DigitWriter<'a> {
+    fn new(buf: &'a mut [u8]) -> Self {
+        Self { buf, len: 0 }
+    }
+
+    fn put_byte(&mut self, byte: u8) {
+        self.buf[self.len] = byte;
+        self.len += 1;
+    }
+
+    // Writes a `u32` as decimal digits, without leading zeros.
+    fn put_u32(&mut self, val: u32) {
+        let mut digits = [0u8; 10];
+        let mut start = digits.len();
+        let mut val = val;
+
+        loop {
+            start -= 1;
+            digits[start] = b'0' + (val % 10) as u8;
+            val /= 10;
+
+            if val == 0 {
+                break;
+            }
+        }
+
+        for &digit in &digits[start..] {
+            self.put_byte(digit);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_panic_line() {
+        let mut buf = [0u8; PANIC_LINE_LEN];
+
+        let len = write_panic_line("src/main.rs", 42, 7, &mut buf);
+        assert_eq!(&buf[..len], b"panic at src/main.rs:42:7\r\n");
+
+        let file = "/home/user/.cargo/registry/src/index.crates.io/some-crate-0.1.0/src/lib.rs";
+        let len = write_panic_line(file, u32::MAX, 0, &mut buf);
+        assert_eq!(len, PANIC_LINE_LEN);
+        assert!(buf.starts_with(b"panic at ..."));
+        assert!(buf[..len].ends_with(b"/src/lib.rs:4294967295:0\r\n"));
+    }
+}
@@ -0,0 +1,328 @@
+//! Key matrix pin assignments.
+//!
+//! Maps each matrix row and column to a physical ATmega32u4 pin, so alternative Atreus wirings
+//! and handwired clones can run the same firmware binary. The assignment defaults to the
+//! [Atreus](PinMap::atreus) wiring, and can be stored in EEPROM at [PIN_MAP_OFFSET].
+//!
+//! The layout of a serialized pin map:
+//!
+//! ```text
+//! | magic "PM" | version | row pins ... | column pins ... |
+//! ```
+//!
+//! Each pin is stored as a [PinId] byte, with the port in the high nibble.
+
+use crate::debounce::RowState;
+use crate::layers::{COLS, ROWS};
+use crate::rgb_map::{RGB_MAP_LEN, RGB_MAP_OFFSET};
+
+/// Magic bytes at the start of a serialized pin map.
+pub const PIN_MAP_MAGIC: [u8; 2] = *b"PM";
+/// Current version of the serialized pin map format.
+pub const PIN_MAP_VERSION: u8 = 1;
+/// Length of a serialized pin map.
+pub const PIN_MAP_LEN: usize = 3 + ROWS + COLS;
+/// Offset of the serialized [PinMap] in the EEPROM, right after the color map.
+pub const PIN_MAP_OFFSET: usize = RGB_MAP_OFFSET + RGB_MAP_LEN;
+/// Number of I/O [Port]s.
+pub const NUM_PORTS: usize = 5;
+/// Number of pin slots in the I/O ports (8 pins for each [Port]).
+pub const NUM_PINS: usize = NUM_PORTS * 8;
+
+/// Errors from decoding or validating a pin map.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PinMapError {
+    /// The pin map does not begin with [PIN_MAP_MAGIC].
+    InvalidMagic,
+    /// The pin map version is not supported.
+    InvalidVersion(u8),
+    /// The pin map data is shorter than [PIN_MAP_LEN].
+    Truncated,
+    /// The pin is not available on the ATmega32u4.
+    InvalidPin(u8),
+    /// The pin is assigned more than once.
+    DuplicatePin(u8),
+}
+
+/// Represents an I/O port of the ATmega32u4.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Port {
+    /// Port B (`PB0` - `PB7`).
+    B = 0,
+    /// Port C (`PC6` and `PC7`).
+    C = 1,
+    /// Port D (`PD0` - `PD7`).
+    D = 2,
+    /// Port E (`PE2` and `PE6`).
+    E = 3,
+    /// Port F (`PF0`, `PF1`, and `PF4` - `PF7`).
+    F = 4,
+}
+
+/// Represents a physical pin, e.g. `PD3`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PinId {
+    port: Port,
+    bit: u8,
+}
+
+impl PinId {
+    /// Creates a new [PinId].
+    ///
+    /// The `bit` is reduced modulo 8.
+    pub const fn new(port: Port, bit: u8) -> Self {
+        Self { port, bit: bit % 8 }
+    }
+
+    /// Gets the [Port] of the pin.
+    pub const fn port(&self) -> Port {
+        self.port
+    }
+
+    /// Gets the bit of the pin in its port registers.
+    pub const fn bit(&self) -> u8 {
+        self.bit
+    }
+
+    /// Gets the index of the pin, in `0..NUM_PINS`.
+    pub const fn index(&self) -> usize {
+        self.port as usize * 8 + self.bit as usize
+    }
+
+    /// Gets whether the pin is bonded out on the ATmega32u4.
+    pub const fn is_available(&self) -> bool {
+        match self.port {
+            Port::B | Port::D => true,
+            Port::C => matches!(self.bit, 6 | 7),
+            Port::E => matches!(self.bit, 2 | 6),
+            Port::F => matches!(self.bit, 0 | 1 | 4..=7),
+        }
+    }
+
+    /// Converts the [PinId] into its serialized byte.
+    pub const fn to_u8(&self) -> u8 {
+        ((self.port as u8) << 4) | self.bit
+    }
+}
+
+impl TryFrom<u8> for PinId {
+    type Error = PinMapError;
+
+    fn try_from(val: u8) -> Result<Self, Self::Error> {
+        let port = match val >> 4 {
+            0 => Port::B,
+            1 => Port::C,
+            2 => Port::D,
+            3 => Port::E,
+            4 => Port::F,
+            _ => return Err(PinMapError::InvalidPin(val)),
+        };
+
+        let pin = Self::new(port, val & 0xf);
+
+        if val & 0xf < 8 && pin.is_available() {
+            Ok(pin)
+        } else {
+            Err(PinMapError::InvalidPin(val))
+        }
+    }
+}
+
+/// Assignment of the key matrix rows and columns to physical pins.
+///
+/// Every pin is available on the ATmega32u4, and assigned at most once.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PinMap {
+    rows: [PinId; ROWS],
+    cols: [PinId; COLS],
+}
+
+impl PinMap {
+    /// Creates a new [PinMap], after checking the pins are valid.
+    pub fn new(rows: [PinId; ROWS], cols: [PinId; COLS]) -> Result<Self, PinMapError> {
+        let mut used = [false; NUM_PINS];
+
+        for pin in rows.iter().chain(cols.iter()) {
+            if !pin.is_available() {
+                return Err(PinMapError::InvalidPin(pin.to_u8()));
+            } else if used[pin.index()] {
+                return Err(PinMapError::DuplicatePin(pin.to_u8()));
+            }
+
+            used[pin.index()] = true;
+        }
+
+        Ok(Self { rows, cols })
+    }
+
+    /// Creates the [PinMap] of the Keyboardio Atreus.
+    pub const fn atreus() -> Self {
+        use Port::*;
+
+        Self {
+            rows: [
+                PinId::new(F, 6),
+                PinId::new(F, 5),
+                PinId::new(F, 4),
+                PinId::new(F, 1),
+            ],
+            // PB4 is a blank column, and is left out
+            cols: [
+                PinId::new(F, 7),
+                PinId::new(E, 2),
+                PinId::new(C, 7),
+                PinId::new(C, 6),
+                PinId::new(B, 6),
+                PinId::new(B, 5),
+                PinId::new(D, 7),
+                PinId::new(D, 6),
+                PinId::new(D, 4),
+                PinId::new(D, 5),
+                PinId::new(D, 3),
+                PinId::new(D, 2),
+            ],
+        }
+    }
+
+    /// Gets the row pins.
+    pub const fn rows(&self) -> &[PinId; ROWS] {
+        &self.rows
+    }
+
+    /// Gets the column pins.
+    pub const fn cols(&self) -> &[PinId; COLS] {
+        &self.cols
+    }
+
+    /// Gets whether the `pin` is a row or column pin.
+    pub fn contains(&self, pin: &PinId) -> bool {
+        self.rows.contains(pin) || self.cols.contains(pin)
+    }
+
+    /// Converts the [PinMap] into its serialized form.
+    pub fn to_bytes(&self) -> [u8; PIN_MAP_LEN] {
+        let mut out = [0u8; PIN_MAP_LEN];
+
+        out[..2].copy_from_slice(PIN_MAP_MAGIC.as_ref());
+        out[2] = PIN_MAP_VERSION;
+
+        for (byte, pin) in out[3..]
+            .iter_mut()
+            .zip(self.rows.iter().chain(self.cols.iter()))
+        {
+            *byte = pin.to_u8();
+        }
+
+        out
+    }
+
+    /// Parses a [PinMap] from its serialized form.
+    ///
+    /// Erased EEPROM fails with [PinMapError::InvalidMagic], so callers can fall back to the
+    /// [Atreus](Self::atreus) wiring.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, PinMapError> {
+        if data.len() < PIN_MAP_LEN {
+            return Err(PinMapError::Truncated);
+        } else if data[..2] != PIN_MAP_MAGIC {
+            return Err(PinMapError::InvalidMagic);
+        } else if data[2] != PIN_MAP_VERSION {
+            return Err(PinMapError::InvalidVersion(data[2]));
+        }
+
+        let (rows, cols) = data[3..PIN_MAP_LEN].split_at(ROWS);
+
+        let mut map = Self::atreus();
+
+        for (pin, &byte) in map.rows.iter_mut().zip(rows.iter()) {
+            *pin = PinId::try_from(byte)?;
+        }
+
+        for (pin, &byte) in map.cols.iter_mut().zip(cols.iter()) {
+            *pin = PinId::try_from(byte)?;
+        }
+
+        Self::new(map.rows, map.cols)
+    }
+}
+
+/// Gets the pressed columns from a snapshot of the `PIN` registers of every [Port].
+///
+/// Columns are pull-up inputs, so a low pin means the key is pressed. Reading whole ports at once
+/// replaces a pin read per column.
+pub fn low_cols(cols: &[PinId], ports: &[u8; NUM_PORTS]) -> RowState {
+    let mut hot_pins = RowState::new();
+
+    for (j, pin) in cols.iter().enumerate() {
+        if ports[pin.port() as usize] & (1 << pin.bit()) == 0 {
+            hot_pins.set_column(j, true);
+        }
+    }
+
+    hot_pins
+}
+
+impl Default for PinMap {
+    fn default() -> Self {
+        Self::atreus()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pin_map() {
+        let atreus = PinMap::atreus();
+
+        assert_eq!(PinMap::new(atreus.rows, atreus.cols), Ok(atreus));
+        assert_eq!(PinMap::from_bytes(atreus.to_bytes().as_ref()), Ok(atreus));
+
+        // erased EEPROM
+        assert_eq!(
+            PinMap::from_bytes(&[0xff; PIN_MAP_LEN]),
+            Err(PinMapError::InvalidMagic)
+        );
+
+        // swapped rows are a different wiring
+        let mut bytes = atreus.to_bytes();
+        bytes.swap(3, 4);
+
+        let map = PinMap::from_bytes(bytes.as_ref()).unwrap();
+        assert_eq!(map.rows()[0], PinId::new(Port::F, 5));
+
+        // PF2 is not bonded out
+        bytes[3] = 0x42;
+        assert_eq!(
+            PinMap::from_bytes(bytes.as_ref()),
+            Err(PinMapError::InvalidPin(0x42))
+        );
+
+        // a column on a row pin
+        let mut cols = atreus.cols;
+        cols[0] = atreus.rows[0];
+        assert_eq!(
+            PinMap::new(atreus.rows, cols),
+            Err(PinMapError::DuplicatePin(0x46))
+        );
+    }
+
+    #[test]
+    fn test_low_cols() {
+        let cols = PinMap::atreus().cols;
+
+        // nothing pressed, every pull-up reads high
+        assert!(low_cols(&cols, &[0xff; NUM_PORTS]).is_inactive());
+
+        // PB6 is column 4, and PD2 is column 11
+        let mut ports = [0xff; NUM_PORTS];
+        ports[Port::B as usize] &= !(1 << 6);
+        ports[Port::D as usize] &= !(1 << 2);
+
+        assert_eq!(
+            low_cols(&cols, &ports),
+            RowState::from_u16((1 << 4) | (1 << 11))
+        );
+    }
+}
@@ -0,0 +1,140 @@
+//! Key event pipeline.
+//!
+//! Every [KeyEvent] runs through a chain of [EventHandler]s before it reaches the report stage,
+//! which tracks the held keys the reports are built from. Each handler sees the event with its
+//! keycode, and either passes it on to the next handler, possibly with a different keycode, or
+//! consumes it:
+//!
+//! ```text
+//! KeyEvent -> tap-hold -> handler 0 -> ... -> layers, user keys, and held keys -> reports
+//! ```
+//!
+//! Presses start with the keycode from the key map, after dual-role keys are resolved by the
+//! [TapHoldKeys](crate::tap_hold::TapHoldKeys), and releases with the keycode the key held, so a
+//! handler that changes the keycode of a press also changes the keycode released later. Features
+//! like combos, macros, and swap-hands are handlers, and run in registration order.
+//! A handler that consumes a press should also consume the release.
+
+use crate::events::KeyEvent;
+
+/// Maximum number of handlers in a [Pipeline].
+pub const MAX_EVENT_HANDLERS: usize = 8;
+
+/// Represents what an [EventHandler] did with a [KeyEvent].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum EventResult {
+    /// The event is passed on to the next stage.
+    #[default]
+    Continue,
+    /// The event is dropped, later stages never see it.
+    Consumed,
+}
+
+/// Handler called with a [KeyEvent] and its keycode, which it can change.
+pub type EventHandler = fn(event: &KeyEvent, key: &mut u8) -> EventResult;
+
+/// Chain of [EventHandler]s run for every key event.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Pipeline {
+    handlers: [Option<EventHandler>; MAX_EVENT_HANDLERS],
+}
+
+impl Pipeline {
+    /// Creates a new [Pipeline], without any handlers.
+    pub const fn new() -> Self {
+        Self {
+            handlers: [None; MAX_EVENT_HANDLERS],
+        }
+    }
+
+    /// Gets the number of handlers.
+    pub fn len(&self) -> usize {
+        self.handlers.iter().flatten().count()
+    }
+
+    /// Gets whether the pipeline has no handlers.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Builder function that appends a handler to the chain.
+    ///
+    /// Handlers past [MAX_EVENT_HANDLERS] are dropped.
+    pub const fn with_handler(mut self, handler: EventHandler) -> Self {
+        let mut i = 0;
+
+        while i < MAX_EVENT_HANDLERS {
+            if self.handlers[i].is_none() {
+                self.handlers[i] = Some(handler);
+                break;
+            }
+            i += 1;
+        }
+
+        self
+    }
+
+    /// Runs the handlers in order, until one consumes the event.
+    ///
+    /// Returns the keycode to pass to the report stage, or `None` if the event was consumed.
+    pub fn run(&self, event: &KeyEvent, key: u8) -> Option<u8> {
+        let mut key = key;
+
+        for handler in self.handlers.iter().flatten() {
+            if handler(event, &mut key) == EventResult::Consumed {
+                return None;
+            }
+        }
+
+        Some(key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // swaps A and B
+    fn swap_ab(_event: &KeyEvent, key: &mut u8) -> EventResult {
+        match *key {
+            0x04 => *key = 0x05,
+            0x05 => *key = 0x04,
+            _ => (),
+        }
+        EventResult::Continue
+    }
+
+    // drops every event of the top-left key
+    fn disable_corner(event: &KeyEvent, _key: &mut u8) -> EventResult {
+        if event.row() == 0 && event.col() == 0 {
+            EventResult::Consumed
+        } else {
+            EventResult::Continue
+        }
+    }
+
+    // would turn every key into C, if it was ever reached
+    fn all_c(_event: &KeyEvent, key: &mut u8) -> EventResult {
+        *key = 0x06;
+        EventResult::Continue
+    }
+
+    #[test]
+    fn test_pipeline() {
+        let press = KeyEvent::new(1, 2, true, 0);
+        assert_eq!(Pipeline::new().run(&press, 0x04), Some(0x04));
+
+        let pipeline = Pipeline::new()
+            .with_handler(swap_ab)
+            .with_handler(disable_corner);
+        assert_eq!(pipeline.len(), 2);
+        assert_eq!(pipeline.run(&press, 0x04), Some(0x05));
+        assert_eq!(pipeline.run(&press, 0x07), Some(0x07));
+        assert_eq!(pipeline.run(&KeyEvent::new(0, 0, true, 0), 0x04), None);
+
+        // consumed events stop the chain
+        let pipeline = pipeline.with_handler(all_c);
+        assert_eq!(pipeline.run(&KeyEvent::new(0, 0, false, 10), 0x05), None);
+        assert_eq!(pipeline.run(&press, 0x05), Some(0x06));
+    }
+}
@@ -0,0 +1,230 @@
+//! PMW3360 optical sensor.
+//!
+//! Integrated-trackball builds put a PixArt PMW3360 (or the register-compatible PMW3389) under the
+//! ball, on the SPI bus. The sensor counts motion since the last read, which is read in one
+//! [motion burst](REG_MOTION_BURST) of [MOTION_BURST_LEN] bytes, and parsed into a [Motion].
+//!
+//! A [SensorConfig] sets the resolution, and rotates the motion into the orientation of the
+//! sensor in the case. The motion is then added to the [mouse](crate::mouse) reports.
+
+/// Product ID register, reads [PRODUCT_ID].
+pub const REG_PRODUCT_ID: u8 = 0x00;
+/// Motion register, latches the motion counts when read.
+pub const REG_MOTION: u8 = 0x02;
+/// Resolution register, see [cpi_config].
+pub const REG_CONFIG1: u8 = 0x0f;
+/// Rest mode register.
+pub const REG_CONFIG2: u8 = 0x10;
+/// SROM download register.
+pub const REG_SROM_ENABLE: u8 = 0x13;
+/// ID of the running SROM firmware, `0` if none was downloaded.
+pub const REG_SROM_ID: u8 = 0x2a;
+/// Power-up reset register, resets the sensor when [POWER_UP_RESET] is written.
+pub const REG_POWER_UP_RESET: u8 = 0x3a;
+/// Motion burst register, reads the [MOTION_BURST_LEN] motion bytes in one transfer.
+pub const REG_MOTION_BURST: u8 = 0x50;
+/// SROM burst download register.
+pub const REG_SROM_LOAD_BURST: u8 = 0x62;
+
+/// Product ID of the PMW3360.
+pub const PRODUCT_ID: u8 = 0x42;
+/// Value resetting the sensor, written to [REG_POWER_UP_RESET].
+pub const POWER_UP_RESET: u8 = 0x5a;
+/// Values written to [REG_SROM_ENABLE] to prepare and start an SROM download.
+pub const SROM_DOWNLOAD_INIT: u8 = 0x1d;
+/// See [SROM_DOWNLOAD_INIT].
+pub const SROM_DOWNLOAD_START: u8 = 0x18;
+/// Number of bytes read in a motion burst.
+pub const MOTION_BURST_LEN: usize = 6;
+
+/// Lowest resolution (in counts per inch).
+pub const MIN_CPI: u16 = 100;
+/// Highest resolution (in counts per inch).
+pub const MAX_CPI: u16 = 12_000;
+/// Default resolution (in counts per inch).
+pub const DEFAULT_CPI: u16 = 1600;
+
+// Bits of the motion register.
+const MOTION_MOT: u8 = 1 << 7;
+const MOTION_LIFT: u8 = 1 << 3;
+
+/// Gets the address byte writing a register, reads send the plain register.
+pub const fn write_addr(reg: u8) -> u8 {
+    reg | 0x80
+}
+
+/// Gets the [REG_CONFIG1] value for the resolution, in steps of [MIN_CPI].
+///
+/// Resolutions out of range are clamped.
+pub const fn cpi_config(cpi: u16) -> u8 {
+    let cpi = if cpi < MIN_CPI {
+        MIN_CPI
+    } else if cpi > MAX_CPI {
+        MAX_CPI
+    } else {
+        cpi
+    };
+
+    (cpi / MIN_CPI - 1) as u8
+}
+
+/// Motion counted by the sensor since the last read.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Motion {
+    dx: i16,
+    dy: i16,
+    lifted: bool,
+}
+
+impl Motion {
+    /// Creates a new [Motion] from the counts.
+    pub const fn new(dx: i16, dy: i16) -> Self {
+        Self {
+            dx,
+            dy,
+            lifted: false,
+        }
+    }
+
+    /// Parses the bytes of a motion burst.
+    ///
+    /// The counts are only valid if the sensor reported motion.
+    pub const fn from_burst(burst: &[u8; MOTION_BURST_LEN]) -> Self {
+        let lifted = burst[0] & MOTION_LIFT != 0;
+
+        if burst[0] & MOTION_MOT == 0 {
+            return Self {
+                dx: 0,
+                dy: 0,
+                lifted,
+            };
+        }
+
+        Self {
+            dx: i16::from_le_bytes([burst[2], burst[3]]),
+            dy: i16::from_le_bytes([burst[4], burst[5]]),
+            lifted,
+        }
+    }
+
+    /// Gets the motion along the sensor X axis.
+    pub const fn dx(&self) -> i16 {
+        self.dx
+    }
+
+    /// Gets the motion along the sensor Y axis.
+    pub const fn dy(&self) -> i16 {
+        self.dy
+    }
+
+    /// Gets whether the ball (or the surface) is too far from the sensor to track.
+    pub const fn lifted(&self) -> bool {
+        self.lifted
+    }
+}
+
+/// Resolution and orientation of a sensor.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SensorConfig {
+    cpi: u16,
+    swap_xy: bool,
+    invert_x: bool,
+    invert_y: bool,
+}
+
+impl SensorConfig {
+    /// Creates a new [SensorConfig], at the [DEFAULT_CPI], with the sensor axes as the pointer
+    /// axes.
+    pub const fn new() -> Self {
+        Self {
+            cpi: DEFAULT_CPI,
+            swap_xy: false,
+            invert_x: false,
+            invert_y: false,
+        }
+    }
+
+    /// Gets the resolution (in counts per inch).
+    pub const fn cpi(&self) -> u16 {
+        self.cpi
+    }
+
+    /// Builder function that sets the resolution (in counts per inch).
+    pub const fn with_cpi(mut self, cpi: u16) -> Self {
+        self.cpi = cpi;
+        self
+    }
+
+    /// Builder function that sets whether the sensor X axis moves the pointer vertically.
+    pub const fn with_swap_xy(mut self, val: bool) -> Self {
+        self.swap_xy = val;
+        self
+    }
+
+    /// Builder function that sets whether the horizontal pointer motion is inverted.
+    pub const fn with_invert_x(mut self, val: bool) -> Self {
+        self.invert_x = val;
+        self
+    }
+
+    /// Builder function that sets whether the vertical pointer motion is inverted.
+    pub const fn with_invert_y(mut self, val: bool) -> Self {
+        self.invert_y = val;
+        self
+    }
+
+    /// Gets the pointer motion for the sensor [Motion].
+    ///
+    /// Motion while lifted is dropped, so the pointer does not jump when the ball is put back.
+    pub const fn pointer_motion(&self, motion: &Motion) -> (i16, i16) {
+        if motion.lifted {
+            return (0, 0);
+        }
+
+        let (x, y) = if self.swap_xy {
+            (motion.dy, motion.dx)
+        } else {
+            (motion.dx, motion.dy)
+        };
+
+        (
+            if self.invert_x { x.saturating_neg() } else { x },
+            if self.invert_y { y.saturating_neg() } else { y },
+        )
+    }
+}
+
+impl Default for SensorConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_motion_burst() {
+        let motion = Motion::from_burst(&[MOTION_MOT, 0, 0x10, 0x00, 0xfe, 0xff]);
+        assert_eq!((motion.dx(), motion.dy()), (16, -2));
+        assert!(!motion.lifted());
+
+        // the counts are stale without the motion bit
+        assert_eq!(
+            Motion::from_burst(&[0, 0, 0x10, 0x00, 0xfe, 0xff]),
+            Motion::new(0, 0)
+        );
+
+        let config = SensorConfig::new().with_swap_xy(true).with_invert_y(true);
+        assert_eq!(config.pointer_motion(&motion), (-2, -16));
+
+        let lifted = Motion::from_burst(&[MOTION_MOT | MOTION_LIFT, 0, 0x10, 0, 0, 0]);
+        assert_eq!(config.pointer_motion(&lifted), (0, 0));
+
+        assert_eq!(cpi_config(DEFAULT_CPI), 15);
+        assert_eq!(cpi_config(0), 0);
+        assert_eq!(cpi_config(u16::MAX), 0x77);
+        assert_eq!(write_addr(REG_POWER_UP_RESET), 0xba);
+    }
+}
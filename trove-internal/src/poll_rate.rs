@@ -0,0 +1,137 @@
+//! HID polling interval.
+//!
+//! The host polls the HID endpoints at the interval in their endpoint descriptors, and the scan
+//! timer decides how often a fresh report is ready. A short poll interval only lowers latency if
+//! the matrix is scanned at least as often, so the [PollRate] configures both together.
+//!
+//! The default keeps the 1 ms poll interval, with the scan timer at
+//! [DEFAULT_TICK_INTERVAL_US]. Boards can choose genuine 1000 Hz reporting with
+//! [from_poll_interval_ms](PollRate::from_poll_interval_ms), or relax the interval for hosts and
+//! hubs that struggle with it.
+
+use crate::health::DEFAULT_TICK_INTERVAL_US;
+
+/// Default HID endpoint poll interval (in milliseconds).
+pub const DEFAULT_POLL_INTERVAL_MS: u8 = 1;
+/// Longest scan interval (in microseconds) of a [PollRate].
+pub const MAX_SCAN_INTERVAL_US: u16 = 8000;
+
+/// Coordinated HID endpoint poll interval, and scan timer interval.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PollRate {
+    poll_interval_ms: u8,
+    scan_interval_us: u16,
+}
+
+impl PollRate {
+    /// Creates a new [PollRate], with the [DEFAULT_POLL_INTERVAL_MS] and the
+    /// [DEFAULT_TICK_INTERVAL_US].
+    pub const fn new() -> Self {
+        Self {
+            poll_interval_ms: DEFAULT_POLL_INTERVAL_MS,
+            scan_interval_us: DEFAULT_TICK_INTERVAL_US,
+        }
+    }
+
+    /// Creates a new [PollRate] that scans the matrix once every poll interval.
+    ///
+    /// Every host poll gets a fresh report, e.g. genuine 1000 Hz reporting at 1 ms. Scan intervals
+    /// are capped at [MAX_SCAN_INTERVAL_US].
+    pub const fn from_poll_interval_ms(val: u8) -> Self {
+        let poll_interval_ms = if val == 0 { 1 } else { val };
+        let scan_interval_us = poll_interval_ms as u16 * 1000;
+
+        Self {
+            poll_interval_ms,
+            scan_interval_us: if scan_interval_us > MAX_SCAN_INTERVAL_US {
+                MAX_SCAN_INTERVAL_US
+            } else {
+                scan_interval_us
+            },
+        }
+    }
+
+    /// Gets the HID endpoint poll interval (in milliseconds).
+    pub const fn poll_interval_ms(&self) -> u8 {
+        self.poll_interval_ms
+    }
+
+    /// Sets the HID endpoint poll interval (in milliseconds).
+    ///
+    /// The scan interval is shortened if it would be longer than the poll interval.
+    pub fn set_poll_interval_ms(&mut self, val: u8) {
+        self.poll_interval_ms = val.max(1);
+        self.scan_interval_us = self
+            .scan_interval_us
+            .min((self.poll_interval_ms as u16 * 1000).min(MAX_SCAN_INTERVAL_US));
+    }
+
+    /// Builder function that sets the HID endpoint poll interval (in milliseconds).
+    pub fn with_poll_interval_ms(mut self, val: u8) -> Self {
+        self.set_poll_interval_ms(val);
+        self
+    }
+
+    /// Gets the scan timer interval (in microseconds).
+    pub const fn scan_interval_us(&self) -> u16 {
+        self.scan_interval_us
+    }
+
+    /// Sets the scan timer interval (in microseconds).
+    ///
+    /// The interval is limited to [MAX_SCAN_INTERVAL_US].
+    pub fn set_scan_interval_us(&mut self, val: u16) {
+        self.scan_interval_us = val.clamp(1, MAX_SCAN_INTERVAL_US);
+    }
+
+    /// Builder function that sets the scan timer interval (in microseconds).
+    pub fn with_scan_interval_us(mut self, val: u16) -> Self {
+        self.set_scan_interval_us(val);
+        self
+    }
+
+    /// Gets the report rate (in Hz) requested from the host.
+    pub const fn report_rate_hz(&self) -> u16 {
+        1000 / self.poll_interval_ms as u16
+    }
+}
+
+impl Default for PollRate {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_poll_rate() {
+        let rate = PollRate::new();
+        assert_eq!(rate.poll_interval_ms(), 1);
+        assert_eq!(rate.scan_interval_us(), DEFAULT_TICK_INTERVAL_US);
+
+        // genuine 1000 Hz reporting
+        let rate = PollRate::from_poll_interval_ms(1);
+        assert_eq!(rate.scan_interval_us(), 1000);
+        assert_eq!(rate.report_rate_hz(), 1000);
+
+        // relaxed for compatibility, limited by the scan timer
+        let rate = PollRate::from_poll_interval_ms(10);
+        assert_eq!(rate.scan_interval_us(), MAX_SCAN_INTERVAL_US);
+        assert_eq!(rate.report_rate_hz(), 100);
+
+        // the scan interval follows a shorter poll interval
+        let rate = PollRate::new()
+            .with_scan_interval_us(4000)
+            .with_poll_interval_ms(2);
+        assert_eq!(rate.scan_interval_us(), 2000);
+
+        assert_eq!(PollRate::from_poll_interval_ms(0).poll_interval_ms(), 1);
+        assert_eq!(
+            PollRate::new().with_scan_interval_us(0).scan_interval_us(),
+            1
+        );
+    }
+}
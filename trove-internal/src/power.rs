@@ -0,0 +1,188 @@
+//! Power management.
+//!
+//! Between main loop iterations the MCU sleeps until the next interrupt. While the USB bus is
+//! active, the USB controller and the scan timer need their clocks, so only the CPU is stopped.
+//! Once the host suspends the bus, the MCU powers down and stops every clock, as long as nothing
+//! needs the scan timer:
+//!
+//! - the host did not enable remote wakeup, so key presses are dropped until the bus resumes
+//! - the matrix sleeps until a key press, so the pin-change interrupt wakes the MCU, and the
+//!   next scan wakes the host
+//!
+//! The USB wakeup interrupt wakes the MCU once the bus resumes.
+//!
+//! At boot, the clocks of unused peripherals are stopped through the power reduction registers,
+//! see [PowerReduction], which lowers the current in every sleep mode.
+
+/// Represents the sleep mode of the MCU between main loop iterations.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SleepMode {
+    /// Stops the CPU, and wakes on the next scan timer or USB interrupt.
+    #[default]
+    Idle,
+    /// Stops every clock, and wakes on a pin-change or USB wakeup interrupt.
+    PowerDown,
+}
+
+/// Gets the [SleepMode] for the power state.
+///
+/// - `matrix_sleeping`: the matrix waits for a pin-change interrupt instead of scanning
+/// - `suspended`: the host suspended the USB bus
+/// - `remote_wakeup`: the host enabled remote wakeup
+pub const fn sleep_mode(matrix_sleeping: bool, suspended: bool, remote_wakeup: bool) -> SleepMode {
+    if suspended && (matrix_sleeping || !remote_wakeup) {
+        SleepMode::PowerDown
+    } else {
+        SleepMode::Idle
+    }
+}
+
+/// Represents a peripheral with a bit in the power reduction registers.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Peripheral {
+    /// Two-wire serial interface.
+    Twi = 0,
+    /// Timer 0, used by the [software PWM](crate::soft_pwm).
+    Timer0 = 1,
+    /// Timer 1, used as the scan timer.
+    Timer1 = 2,
+    /// SPI, e.g. for a [shift register](crate::shift_register) matrix.
+    Spi = 3,
+    /// ADC, e.g. for an [analog](crate::analog) matrix.
+    Adc = 4,
+    /// USB controller.
+    Usb = 5,
+    /// Timer 4.
+    Timer4 = 6,
+    /// Timer 3, used as the settle timer.
+    Timer3 = 7,
+    /// USART 1, e.g. for the [serial bridge](crate::bridge), or a [split](crate::split) keyboard
+    /// link.
+    Usart1 = 8,
+}
+
+impl Peripheral {
+    /// Every peripheral.
+    pub const ALL: [Self; 9] = [
+        Self::Twi,
+        Self::Timer0,
+        Self::Timer1,
+        Self::Spi,
+        Self::Adc,
+        Self::Usb,
+        Self::Timer4,
+        Self::Timer3,
+        Self::Usart1,
+    ];
+
+    /// Gets the power reduction register (`0` for `PRR0`, `1` for `PRR1`), and the bit of the
+    /// peripheral.
+    pub const fn prr_bit(&self) -> (usize, u8) {
+        match self {
+            Self::Twi => (0, 7),
+            Self::Timer0 => (0, 5),
+            Self::Timer1 => (0, 3),
+            Self::Spi => (0, 2),
+            Self::Adc => (0, 0),
+            Self::Usb => (1, 7),
+            Self::Timer4 => (1, 4),
+            Self::Timer3 => (1, 3),
+            Self::Usart1 => (1, 0),
+        }
+    }
+}
+
+/// Peripherals to keep powered, with every other peripheral stopped.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PowerReduction {
+    used: u16,
+}
+
+impl PowerReduction {
+    /// Creates a new [PowerReduction], keeping the peripherals the firmware always uses: the
+    /// [Usb](Peripheral::Usb) controller, the scan timer ([Timer1](Peripheral::Timer1)), and the
+    /// settle timer ([Timer3](Peripheral::Timer3)).
+    pub const fn new() -> Self {
+        Self { used: 0 }
+            .with_used(Peripheral::Usb, true)
+            .with_used(Peripheral::Timer1, true)
+            .with_used(Peripheral::Timer3, true)
+    }
+
+    /// Gets whether the peripheral is kept powered.
+    pub const fn is_used(&self, peripheral: Peripheral) -> bool {
+        self.used & (1 << peripheral as u8) != 0
+    }
+
+    /// Sets whether the peripheral is kept powered.
+    pub fn set_used(&mut self, peripheral: Peripheral, val: bool) {
+        *self = self.with_used(peripheral, val);
+    }
+
+    /// Builder function that sets whether the peripheral is kept powered.
+    pub const fn with_used(mut self, peripheral: Peripheral, val: bool) -> Self {
+        if val {
+            self.used |= 1 << peripheral as u8;
+        } else {
+            self.used &= !(1 << peripheral as u8);
+        }
+        self
+    }
+
+    /// Gets the `PRR0` and `PRR1` register values, which stop the unused peripherals.
+    pub const fn prr(&self) -> [u8; 2] {
+        let mut prr = [0u8; 2];
+        let mut i = 0;
+
+        while i < Peripheral::ALL.len() {
+            let peripheral = Peripheral::ALL[i];
+
+            if !self.is_used(peripheral) {
+                let (reg, bit) = peripheral.prr_bit();
+                prr[reg] |= 1 << bit;
+            }
+
+            i += 1;
+        }
+
+        prr
+    }
+}
+
+impl Default for PowerReduction {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sleep_mode() {
+        assert_eq!(sleep_mode(false, false, false), SleepMode::Idle);
+        assert_eq!(sleep_mode(true, false, true), SleepMode::Idle);
+
+        assert_eq!(sleep_mode(false, true, false), SleepMode::PowerDown);
+        // key presses must still be scanned to wake the host
+        assert_eq!(sleep_mode(false, true, true), SleepMode::Idle);
+        assert_eq!(sleep_mode(true, true, true), SleepMode::PowerDown);
+    }
+
+    #[test]
+    fn test_power_reduction() {
+        let reduction = PowerReduction::new();
+        assert!(reduction.is_used(Peripheral::Usb));
+        assert!(!reduction.is_used(Peripheral::Adc));
+        // TWI, timer 0, SPI, ADC | timer 4, USART 1
+        assert_eq!(reduction.prr(), [0b1010_0101, 0b0001_0001]);
+
+        let reduction = reduction
+            .with_used(Peripheral::Adc, true)
+            .with_used(Peripheral::Usart1, true)
+            .with_used(Peripheral::Usb, false);
+        assert_eq!(reduction.prr(), [0b1010_0100, 0b1001_0000]);
+    }
+}
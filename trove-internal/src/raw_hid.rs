@@ -0,0 +1,111 @@
+//! Raw HID transport for the configuration protocol.
+//!
+//! Host tools send configuration [Command]s in vendor-defined 64-byte reports, so they can talk
+//! to the keyboard without a serial port. The reports are framed as:
+//!
+//! ```text
+//! request:  | command | args ... |
+//! response: | command | status | length | data ... |
+//! ```
+//!
+//! Both reports are zero-padded to [RAW_REPORT_LEN]. The response echoes the command byte, so
+//! host tools can match it to the request. The status is `0` on success, or a [ConfigError]
+//! [code](ConfigError::code), and the length counts the data bytes.
+//!
+//! The interface uses the usage page and usage of other open-source keyboard firmware, so
+//! existing host tools can find it.
+
+use crate::config::{Command, ConfigError};
+
+/// Length of the raw HID input and output reports.
+pub const RAW_REPORT_LEN: usize = 64;
+/// Length of the response header: `| command | status | length |`.
+pub const RAW_HEADER_LEN: usize = 3;
+/// Maximum length of the response data.
+pub const MAX_RAW_RESPONSE_LEN: usize = RAW_REPORT_LEN - RAW_HEADER_LEN;
+
+/// HID report descriptor for the raw HID interface.
+pub const RAW_HID_REPORT_DESCRIPTOR: &[u8] = &[
+    0x06, 0x60, 0xff, // Usage Page (Vendor Defined 0xFF60)
+    0x09, 0x61, // Usage (0x61)
+    0xa1, 0x01, // Collection (Application)
+    0x09, 0x62, //   Usage (0x62)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x26, 0xff, 0x00, //   Logical Maximum (255)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x40, //   Report Count (64)
+    0x81, 0x02, //   Input (Data, Variable, Absolute)
+    0x09, 0x63, //   Usage (0x63)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x26, 0xff, 0x00, //   Logical Maximum (255)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x40, //   Report Count (64)
+    0x91, 0x02, //   Output (Data, Variable, Absolute)
+    0xc0, // End Collection
+];
+
+/// Handles a raw HID `request` report, and returns the response report.
+///
+/// The `handler` is called with the [Command], its arguments (including the zero padding), and
+/// a buffer of [MAX_RAW_RESPONSE_LEN] bytes for the response data.
+pub fn handle_raw_report<F>(request: &[u8], handler: F) -> [u8; RAW_REPORT_LEN]
+where
+    F: FnOnce(Command, &[u8], &mut [u8]) -> Result<usize, ConfigError>,
+{
+    let mut response = [0u8; RAW_REPORT_LEN];
+    let (header, data) = response.split_at_mut(RAW_HEADER_LEN);
+
+    let result = match request.split_first() {
+        Some((&command, args)) => {
+            header[0] = command;
+            Command::try_from(command).and_then(|command| handler(command, args, data))
+        }
+        None => Err(ConfigError::InvalidArgument),
+    };
+
+    match result {
+        Ok(len) => header[2] = len.min(MAX_RAW_RESPONSE_LEN) as u8,
+        Err(err) => {
+            header[1] = err.code();
+            data.fill(0);
+        }
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_timing(command: Command, _args: &[u8], buf: &mut [u8]) -> Result<usize, ConfigError> {
+        match command {
+            Command::GetTiming => {
+                buf[..2].copy_from_slice(&[0x34, 0x12]);
+                Ok(2)
+            }
+            _ => Err(ConfigError::UnhandledCommand(command)),
+        }
+    }
+
+    #[test]
+    fn test_raw_report() {
+        let mut request = [0u8; RAW_REPORT_LEN];
+
+        request[0] = Command::GetTiming.into();
+        let response = handle_raw_report(&request, get_timing);
+        assert_eq!(response[..5], [0x06, 0, 2, 0x34, 0x12]);
+        assert!(response[5..].iter().all(|&b| b == 0));
+
+        request[0] = Command::Lint.into();
+        let response = handle_raw_report(&request, get_timing);
+        assert_eq!(response[..3], [0x01, 2, 0]);
+
+        request[0] = 0xee;
+        let response = handle_raw_report(&request, get_timing);
+        assert_eq!(response[..3], [0xee, 1, 0]);
+
+        let response = handle_raw_report(&[], get_timing);
+        assert_eq!(response[..3], [0, 3, 0]);
+    }
+}
@@ -0,0 +1,645 @@
+//! Types and functionality for building keyboard HID reports.
+
+use usbd_hid::descriptor::{KeyboardReport, KeyboardUsage};
+
+use crate::layers::{self, ShapeShift, COLS, ROWS, SHIFT};
+
+/// Maximum number of non-modifier keycodes in a [KeyboardReport].
+pub const REPORT_KEYS: usize = 6;
+
+/// Maximum number of pressed keys tracked by a [ReportBuilder].
+pub const MAX_KEYS: usize = ROWS * COLS;
+
+/// Keycode sent in every slot of a [KeyboardReport] when more than [REPORT_KEYS] keys are held.
+pub const ERROR_ROLL_OVER: u8 = KeyboardUsage::KeyboardErrorRollOver as u8;
+
+/// Blank [KeyboardReport].
+pub const BLANK_REPORT: KeyboardReport = KeyboardReport {
+    modifier: 0,
+    reserved: 0,
+    leds: 0,
+    keycodes: [0; REPORT_KEYS],
+};
+
+/// Builds the [KeyboardReport] for the keys pressed during a matrix scan.
+///
+/// Keys defined with the [SHIFTED](layers::SHIFTED) flag are sent as their base usage with Left
+/// Shift injected into the modifier byte. If Shift is physically held, every key is shifted
+/// anyway, unless the [shape shifter](Self::set_shape_shifter) replaces it. Replacements only
+/// apply to physically held Shift, never to the Shift injected for shifted keys.
+///
+/// Every scan builds a single report, so held keys are never released and pressed again at the
+/// host. The injected Shift would change the meaning of unshifted keys, so shifted and unshifted
+/// keys can not share the report: while both are held, the keys matching the Shift state of the
+/// [previous report](Self::set_previous_shift) stay in the report, and the others are left out
+/// until those keys are released.
+///
+/// Keys are never split across reports. If more than [REPORT_KEYS] keys are in the report, it
+/// reports [ERROR_ROLL_OVER] instead, and the host keeps the previously reported keys held. Keys
+/// waiting for the Shift state to change do not count towards the limit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReportBuilder {
+    modifier: u8,
+    keys: [u8; MAX_KEYS],
+    len: usize,
+    previous_shift: bool,
+    shape_shifter: &'static [ShapeShift],
+}
+
+impl ReportBuilder {
+    /// Creates a new [ReportBuilder].
+    pub const fn new() -> Self {
+        Self {
+            modifier: 0,
+            keys: [0; MAX_KEYS],
+            len: 0,
+            previous_shift: false,
+            shape_shifter: &[],
+        }
+    }
+
+    /// Gets the modifier bitfield for held modifier keys.
+    pub const fn modifier(&self) -> u8 {
+        self.modifier
+    }
+
+    /// Gets the pressed non-modifier keys.
+    pub fn keys(&self) -> &[u8] {
+        self.keys[..self.len].as_ref()
+    }
+
+    /// Gets whether no keys have been added to the [ReportBuilder].
+    pub const fn is_empty(&self) -> bool {
+        self.modifier == 0 && self.len == 0
+    }
+
+    /// Gets whether the previous report held Shift.
+    pub const fn previous_shift(&self) -> bool {
+        self.previous_shift
+    }
+
+    /// Sets whether the previous report held Shift, see [shift](Self::shift).
+    pub fn set_previous_shift(&mut self, val: bool) {
+        self.previous_shift = val;
+    }
+
+    /// Builder function that sets whether the previous report held Shift.
+    pub fn with_previous_shift(mut self, val: bool) -> Self {
+        self.set_previous_shift(val);
+        self
+    }
+
+    /// Gets the [ShapeShift] dictionary applied while Shift is physically held.
+    pub const fn shape_shifter(&self) -> &'static [ShapeShift] {
+        self.shape_shifter
+    }
+
+    /// Sets the [ShapeShift] dictionary applied while Shift is physically held.
+    ///
+    /// Keys with a replacement are sent as the replacement key. Shift is released for unshifted
+    /// replacements, so they follow the same rules as unshifted keys mixed with shifted keys.
+    pub fn set_shape_shifter(&mut self, val: &'static [ShapeShift]) {
+        self.shape_shifter = val;
+    }
+
+    /// Builder function that sets the [ShapeShift] dictionary applied while Shift is physically
+    /// held.
+    pub fn with_shape_shifter(mut self, val: &'static [ShapeShift]) -> Self {
+        self.set_shape_shifter(val);
+        self
+    }
+
+    /// Adds a key to the [ReportBuilder].
+    ///
+    /// Modifier keys are added to the modifier bitfield, and blank keys are ignored. Keys beyond
+    /// [MAX_KEYS] are dropped.
+    pub fn add_key(&mut self, key: u8) {
+        if key == 0 {
+            return;
+        }
+
+        if layers::key_is_modifier(key) {
+            self.modifier |= layers::key_to_modifier(key);
+        } else if self.len < MAX_KEYS && !self.keys().contains(&key) {
+            self.keys[self.len] = key;
+            self.len += 1;
+        }
+    }
+
+    /// Releases all non-modifier keys, keeping held modifiers.
+    pub fn clear_keys(&mut self) {
+        self.len = 0;
+    }
+
+    /// Gets whether the report holds Shift, physically held or injected for shifted keys.
+    ///
+    /// If keys that need Shift and keys that must not have it are held together, the report
+    /// keeps the Shift state of the [previous report](Self::previous_shift).
+    pub fn shift(&self) -> bool {
+        let mut shifted = false;
+        let mut unshifted = false;
+
+        for &key in self.keys() {
+            if self.resolve(key).1 {
+                shifted = true;
+            } else {
+                unshifted = true;
+            }
+        }
+
+        match (shifted, unshifted) {
+            (true, true) => self.previous_shift,
+            (true, false) => true,
+            (false, true) => false,
+            (false, false) => self.modifier & layers::SHIFT_MODIFIERS != 0,
+        }
+    }
+
+    /// Gets the modifier bitfield sent in the report.
+    ///
+    /// Shift is injected for shifted keys, and released for unshifted shape-shift replacements.
+    pub fn report_modifier(&self) -> u8 {
+        let shift_held = self.modifier & layers::SHIFT_MODIFIERS != 0;
+
+        match (self.shift(), shift_held) {
+            (true, false) => self.modifier | layers::key_to_modifier(SHIFT),
+            (false, true) => self.modifier & !layers::SHIFT_MODIFIERS,
+            _ => self.modifier,
+        }
+    }
+
+    /// Gets the usages of the keys sent in the report, see [shift](Self::shift).
+    pub fn report_keys(&self) -> impl Iterator<Item = u8> + '_ {
+        let shift = self.shift();
+
+        self.keys()
+            .iter()
+            .map(move |&k| self.resolve(k))
+            .filter(move |&(_, needs_shift)| needs_shift == shift)
+            .map(|(k, _)| layers::shifted_key(k))
+    }
+
+    /// Builds the [KeyboardReport] for the added keys.
+    ///
+    /// Reports [ERROR_ROLL_OVER] when more than [REPORT_KEYS] keys are in the report.
+    pub fn build(&self) -> KeyboardReport {
+        let mut report = BLANK_REPORT;
+        report.modifier = self.report_modifier();
+
+        if self.report_keys().count() > REPORT_KEYS {
+            // the host keeps the previously reported keys held until the rollover clears
+            report.keycodes = [ERROR_ROLL_OVER; REPORT_KEYS];
+        } else {
+            for (keycode, key) in report.keycodes.iter_mut().zip(self.report_keys()) {
+                *keycode = key;
+            }
+        }
+
+        report
+    }
+
+    // Gets the key sent for the key, after shape shifting, and whether it is sent with Shift
+    // held.
+    fn resolve(&self, key: u8) -> (u8, bool) {
+        if self.modifier & layers::SHIFT_MODIFIERS == 0 {
+            (key, layers::key_is_shifted(key))
+        } else if let Some(replacement) = layers::shape_shifted_key(key, self.shape_shifter) {
+            (replacement, layers::key_is_shifted(replacement))
+        } else {
+            (key, true)
+        }
+    }
+}
+
+impl Default for ReportBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Default capacity of a [ReportQueue].
+pub const REPORT_QUEUE_LEN: usize = 16;
+
+/// Gets whether two [KeyboardReport]s send the same keys to the host.
+pub fn same_keys(a: &KeyboardReport, b: &KeyboardReport) -> bool {
+    a.modifier == b.modifier && a.keycodes == b.keycodes
+}
+
+/// Copies a [KeyboardReport].
+pub const fn copy_report(report: &KeyboardReport) -> KeyboardReport {
+    KeyboardReport {
+        modifier: report.modifier,
+        reserved: report.reserved,
+        leds: report.leds,
+        keycodes: report.keycodes,
+    }
+}
+
+/// Fixed-capacity FIFO of [KeyboardReport]s waiting to be sent to the host.
+///
+/// Lets the matrix scan prepare reports outside of the USB interrupts, which then only have to
+/// send the queued reports. Only reports that change the keys sent to the host are queued.
+///
+/// Reports stay queued while the endpoint is busy, and are only [popped](Self::pop) once the
+/// endpoint accepted them. If a busy endpoint fills the queue, the dropped report is queued again
+/// by the next scan, so key releases are never lost.
+pub struct ReportQueue<const N: usize = REPORT_QUEUE_LEN> {
+    reports: [KeyboardReport; N],
+    head: usize,
+    len: usize,
+    last: KeyboardReport,
+}
+
+impl<const N: usize> ReportQueue<N> {
+    /// Creates a new [ReportQueue].
+    pub const fn new() -> Self {
+        Self {
+            reports: [BLANK_REPORT; N],
+            head: 0,
+            len: 0,
+            last: BLANK_REPORT,
+        }
+    }
+
+    /// Gets the number of queued reports.
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Gets whether the queue is empty.
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Gets whether the queue is full.
+    pub const fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    /// Queues the report, if it changes the keys sent to the host.
+    ///
+    /// Returns `false` if the report was dropped because the queue is full. Dropped reports are
+    /// not remembered, so pushing the same report again queues it.
+    pub fn push(&mut self, report: KeyboardReport) -> bool {
+        if same_keys(&report, &self.last) {
+            true
+        } else if self.is_full() {
+            false
+        } else {
+            self.last = copy_report(&report);
+            self.reports[(self.head + self.len) % N] = report;
+            self.len += 1;
+            true
+        }
+    }
+
+    /// Queues the last queued report again, e.g. to repeat it at the HID idle rate.
+    ///
+    /// Returns `false` if the report was dropped because the queue is full.
+    pub fn repeat_last(&mut self) -> bool {
+        if self.is_full() {
+            false
+        } else {
+            self.reports[(self.head + self.len) % N] = copy_report(&self.last);
+            self.len += 1;
+            true
+        }
+    }
+
+    /// Gets the report at the front of the queue, without removing it.
+    pub fn front(&self) -> Option<&KeyboardReport> {
+        (!self.is_empty()).then(|| &self.reports[self.head])
+    }
+
+    /// Removes the report at the front of the queue.
+    pub fn pop(&mut self) -> Option<KeyboardReport> {
+        if self.is_empty() {
+            None
+        } else {
+            let report = core::mem::replace(&mut self.reports[self.head], BLANK_REPORT);
+
+            self.head = (self.head + 1) % N;
+            self.len -= 1;
+
+            Some(report)
+        }
+    }
+}
+
+impl<const N: usize> Default for ReportQueue<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Gets the `(modifier, keycode)` pairs sent by a [KeyboardReport].
+///
+/// A report with only modifiers held yields a single pair with a blank keycode, and a blank
+/// or [ERROR_ROLL_OVER] report yields nothing.
+pub fn keystrokes(report: &KeyboardReport) -> impl Iterator<Item = (u8, u8)> + '_ {
+    let modifier = report.modifier;
+    let modifier_only = modifier != 0 && report.keycodes.iter().all(|&k| k == 0);
+
+    report
+        .keycodes
+        .iter()
+        .filter(|&&k| k != 0 && k != ERROR_ROLL_OVER)
+        .map(move |&k| (modifier, k))
+        .chain(modifier_only.then_some((modifier, 0)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::{
+        key_to_modifier, A, B, C, COMMA, CTRL, D, E, EXCL, F, G, HASH, HENKAN, INTL1, INTL9, LANG1,
+        LANG9, ONE, Q, SHIFT, THREE,
+    };
+
+    #[test]
+    fn test_shifted_keys() {
+        let shift = key_to_modifier(SHIFT);
+
+        let mut builder = ReportBuilder::new();
+        builder.add_key(EXCL);
+
+        let report = builder.build();
+        assert!(builder.shift());
+        assert_eq!(report.modifier, shift);
+        assert_eq!(report.keycodes, [ONE, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_mixed_shifted_keys() {
+        let shift = key_to_modifier(SHIFT);
+        let ctrl = key_to_modifier(CTRL);
+
+        let mut builder = ReportBuilder::new();
+        builder.add_key(A);
+        builder.add_key(EXCL);
+        builder.add_key(CTRL);
+        builder.add_key(B);
+        builder.add_key(HASH);
+
+        // the unshifted keys were held first, the shifted keys wait for them to be released
+        let report = builder.build();
+        assert_eq!(report.modifier, ctrl);
+        assert_eq!(report.keycodes, [A, B, 0, 0, 0, 0]);
+
+        // the shifted keys were held first, the unshifted keys wait instead
+        builder.set_previous_shift(true);
+
+        let report = builder.build();
+        assert_eq!(report.modifier, ctrl | shift);
+        assert_eq!(report.keycodes, [ONE, THREE, 0, 0, 0, 0]);
+
+        // physically held Shift lets every key share the report
+        builder.add_key(SHIFT);
+        builder.set_previous_shift(false);
+
+        let report = builder.build();
+        assert_eq!(report.modifier, ctrl | shift);
+        assert_eq!(report.keycodes, [A, ONE, B, THREE, 0, 0]);
+    }
+
+    #[test]
+    fn test_shape_shifted_keys() {
+        const SHAPE_SHIFTER: &[ShapeShift] =
+            &[ShapeShift::new(COMMA, EXCL), ShapeShift::new(ONE, ONE)];
+
+        let shift = key_to_modifier(SHIFT);
+
+        // the Shift injected for a shifted key is not a held Shift, so `!` is not replaced by `1`
+        let mut builder = ReportBuilder::new().with_shape_shifter(SHAPE_SHIFTER);
+        builder.add_key(EXCL);
+
+        let report = builder.build();
+        assert_eq!(report.modifier, shift);
+        assert_eq!(report.keycodes, [ONE, 0, 0, 0, 0, 0]);
+
+        builder.add_key(COMMA);
+        builder.set_previous_shift(true);
+
+        let report = builder.build();
+        assert_eq!(report.modifier, shift);
+        assert_eq!(report.keycodes, [ONE, 0, 0, 0, 0, 0]);
+
+        // physically held Shift replaces the key, and keeps Shift for a shifted replacement
+        builder.clear_keys();
+        builder.add_key(SHIFT);
+        builder.add_key(HASH);
+        builder.add_key(COMMA);
+
+        let report = builder.build();
+        assert_eq!(report.modifier, shift);
+        assert_eq!(report.keycodes, [THREE, ONE, 0, 0, 0, 0]);
+
+        // an unshifted replacement releases Shift, and is not sent with shifted keys
+        let mut builder = ReportBuilder::new().with_shape_shifter(SHAPE_SHIFTER);
+        builder.add_key(SHIFT);
+        builder.add_key(ONE);
+
+        let report = builder.build();
+        assert!(!builder.shift());
+        assert_eq!(report.modifier, 0);
+        assert_eq!(report.keycodes, [ONE, 0, 0, 0, 0, 0]);
+
+        builder.add_key(Q);
+
+        let report = builder.build();
+        assert_eq!(report.modifier, 0);
+        assert_eq!(report.keycodes, [ONE, 0, 0, 0, 0, 0]);
+
+        builder.set_previous_shift(true);
+
+        let report = builder.build();
+        assert_eq!(report.modifier, shift);
+        assert_eq!(report.keycodes, [Q, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_international_keys() {
+        let mut builder = ReportBuilder::new();
+
+        for key in [INTL1, HENKAN, INTL9, LANG1, LANG9] {
+            builder.add_key(key);
+        }
+
+        // usages from 0x80 up are sent as they are, without Shift
+        let report = builder.build();
+        assert_eq!(report.modifier, 0);
+        assert_eq!(report.keycodes, [0x87, 0x8a, 0x8f, 0x90, 0x98, 0]);
+    }
+
+    #[test]
+    fn test_rollover() {
+        let mut builder = ReportBuilder::new();
+
+        for key in [A, B, C, D, E, F] {
+            builder.add_key(key);
+        }
+
+        builder.add_key(CTRL);
+
+        let report = builder.build();
+        assert_eq!(report.keycodes, [A, B, C, D, E, F]);
+
+        // a seventh key is not split into another report
+        builder.add_key(G);
+
+        let report = builder.build();
+        assert_eq!(report.modifier, key_to_modifier(CTRL));
+        assert_eq!(report.keycodes, [ERROR_ROLL_OVER; REPORT_KEYS]);
+        assert_eq!(keystrokes(&report).count(), 0);
+    }
+
+    #[test]
+    fn test_shifted_key_while_held() {
+        let shift = key_to_modifier(SHIFT);
+
+        // builds the report for a scan, carrying the Shift state over like the key scanner
+        let scan = |keys: &[u8], previous_shift: &mut bool| {
+            let mut builder = ReportBuilder::new().with_previous_shift(*previous_shift);
+
+            for &key in keys {
+                builder.add_key(key);
+            }
+
+            *previous_shift = builder.shift();
+            builder.build()
+        };
+
+        let mut previous_shift = false;
+
+        // the held unshifted key stays in every report while the shifted key is pressed
+        for keys in [
+            &[A][..],
+            &[A, EXCL],
+            &[A, EXCL, B],
+            &[A, B, C, D, E, F, EXCL],
+        ] {
+            let report = scan(keys, &mut previous_shift);
+            assert_eq!(report.modifier, 0);
+            assert_eq!(report.keycodes[0], A);
+            assert!(!report.keycodes.contains(&ONE));
+            assert!(!report.keycodes.contains(&ERROR_ROLL_OVER));
+        }
+
+        // the shifted key is sent once the unshifted keys are released
+        let report = scan(&[EXCL], &mut previous_shift);
+        assert_eq!(report.modifier, shift);
+        assert_eq!(report.keycodes, [ONE, 0, 0, 0, 0, 0]);
+
+        // and stays held while an unshifted key is pressed
+        let report = scan(&[EXCL, A], &mut previous_shift);
+        assert_eq!(report.modifier, shift);
+        assert_eq!(report.keycodes, [ONE, 0, 0, 0, 0, 0]);
+
+        let report = scan(&[A], &mut previous_shift);
+        assert_eq!(report.modifier, 0);
+        assert_eq!(report.keycodes, [A, 0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_keystrokes() {
+        let ctrl = key_to_modifier(CTRL);
+
+        let mut builder = ReportBuilder::new();
+        builder.add_key(CTRL);
+
+        let report = builder.build();
+        assert!(keystrokes(&report).eq([(ctrl, 0)]));
+
+        builder.add_key(A);
+        builder.add_key(B);
+
+        let report = builder.build();
+        assert!(keystrokes(&report).eq([(ctrl, A), (ctrl, B)]));
+
+        assert_eq!(keystrokes(&BLANK_REPORT).count(), 0);
+    }
+
+    #[test]
+    fn test_report_queue() {
+        let mut builder = ReportBuilder::new();
+        builder.add_key(A);
+        let pressed = builder.build();
+
+        let mut queue = ReportQueue::<2>::new();
+
+        // nothing to release yet
+        assert!(queue.push(ReportBuilder::new().build()));
+        assert!(queue.is_empty());
+
+        // held keys are only queued once
+        assert!(queue.push(builder.build()));
+        assert!(queue.push(pressed));
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.front().map(|r| r.keycodes[0]), Some(A));
+
+        // releasing queues a blank report
+        assert!(queue.push(ReportBuilder::new().build()));
+        assert!(queue.is_full());
+
+        builder.add_key(B);
+        assert!(!queue.push(builder.build()));
+
+        assert_eq!(queue.pop().map(|r| r.keycodes[0]), Some(A));
+        assert!(queue.pop().is_some_and(|r| same_keys(&r, &BLANK_REPORT)));
+        assert!(queue.pop().is_none());
+    }
+
+    #[test]
+    fn test_report_queue_busy_endpoint() {
+        let mut builder = ReportBuilder::new();
+        builder.add_key(A);
+
+        let mut queue = ReportQueue::<1>::new();
+        let released = ReportBuilder::new();
+
+        // the endpoint is busy, so the press stays at the front of the queue
+        assert!(queue.push(builder.build()));
+        assert_eq!(queue.front().map(|r| r.keycodes[0]), Some(A));
+
+        // the release does not fit
+        assert!(!queue.push(released.build()));
+
+        // the endpoint accepts the press, and the next scan queues the release again
+        assert_eq!(queue.pop().map(|r| r.keycodes[0]), Some(A));
+        assert!(queue.push(released.build()));
+        assert!(queue.pop().is_some_and(|r| same_keys(&r, &BLANK_REPORT)));
+    }
+
+    #[test]
+    fn test_report_queue_unchanged_scan() {
+        let mut builder = ReportBuilder::new();
+        builder.add_key(A);
+        builder.add_key(EXCL);
+
+        let mut queue = ReportQueue::<4>::new();
+
+        // held shifted and unshifted keys are queued once, not alternately on every scan
+        assert!(queue.push(builder.build()));
+        assert!(queue.push(builder.build()));
+        assert!(queue.push(builder.build()));
+        assert_eq!(queue.len(), 1);
+    }
+
+    #[test]
+    fn test_report_queue_repeat() {
+        let mut builder = ReportBuilder::new();
+        builder.add_key(A);
+
+        let mut queue = ReportQueue::<2>::new();
+
+        assert!(queue.push(builder.build()));
+        assert_eq!(queue.pop().map(|r| r.keycodes[0]), Some(A));
+
+        // the held key is repeated, even though it did not change
+        assert!(queue.repeat_last());
+        assert!(queue.repeat_last());
+        assert!(!queue.repeat_last());
+        assert_eq!(queue.pop().map(|r| r.keycodes[0]), Some(A));
+        assert_eq!(queue.pop().map(|r| r.keycodes[0]), Some(A));
+    }
+}
@@ -0,0 +1,322 @@
+//! Per-key RGB color maps.
+//!
+//! Each layer assigns every key an index into a shared palette of [PALETTE_LEN] colors. Indices
+//! are packed two per byte, so a color map for all layers fits in [RGB_MAP_LEN] bytes of EEPROM,
+//! well within the 1KB budget shared with the key maps.
+//!
+//! The layout of a serialized color map:
+//!
+//! ```text
+//! | magic "RM" | version | palette (r, g, b) ... | packed key indices (low nibble first) ... |
+//! ```
+
+use crate::animation::Rgb;
+use crate::config::{Command, ConfigError};
+use crate::layers::{COLS, NUM_LAYERS, ROWS};
+use crate::storage::EepromStorage;
+
+/// Magic bytes at the start of a serialized color map.
+pub const RGB_MAP_MAGIC: [u8; 2] = *b"RM";
+/// Current version of the serialized color map format.
+pub const RGB_MAP_VERSION: u8 = 1;
+/// Number of colors in the palette.
+pub const PALETTE_LEN: usize = 16;
+/// Number of keys in each layer of the color map.
+pub const RGB_MAP_KEYS: usize = ROWS * COLS;
+/// Length of the packed key indices for all layers.
+pub const PACKED_INDICES_LEN: usize = NUM_LAYERS * RGB_MAP_KEYS.div_ceil(2);
+/// Length of a serialized color map.
+pub const RGB_MAP_LEN: usize = 3 + PALETTE_LEN * 3 + PACKED_INDICES_LEN;
+/// Offset of the serialized [RgbMap] in the EEPROM, at the start of the
+/// [storage layout](crate::storage).
+pub const RGB_MAP_OFFSET: usize = 0;
+
+/// Errors from accessing or decoding a color map.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RgbMapError {
+    /// The color map does not begin with [RGB_MAP_MAGIC].
+    InvalidMagic,
+    /// The color map version is not supported.
+    InvalidVersion(u8),
+    /// The color map data is shorter than [RGB_MAP_LEN].
+    Truncated,
+    /// Layer outside of the color map.
+    InvalidLayer(u8),
+    /// Key outside of the color map layer.
+    InvalidKey(u8),
+    /// Palette index outside of the palette.
+    InvalidPaletteIndex(u8),
+}
+
+impl From<RgbMapError> for ConfigError {
+    fn from(_: RgbMapError) -> Self {
+        Self::InvalidArgument
+    }
+}
+
+/// Per-key color map for all layers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RgbMap {
+    palette: [Rgb; PALETTE_LEN],
+    indices: [u8; PACKED_INDICES_LEN],
+}
+
+impl RgbMap {
+    /// Creates a new [RgbMap] with every key set to the first (off) palette color.
+    pub const fn new() -> Self {
+        Self {
+            palette: [Rgb::off(); PALETTE_LEN],
+            indices: [0; PACKED_INDICES_LEN],
+        }
+    }
+
+    /// Gets the palette color at the `index`.
+    pub fn palette_color(&self, index: u8) -> Result<Rgb, RgbMapError> {
+        self.palette
+            .get(index as usize)
+            .copied()
+            .ok_or(RgbMapError::InvalidPaletteIndex(index))
+    }
+
+    /// Sets the palette color at the `index`.
+    pub fn set_palette_color(&mut self, index: u8, color: Rgb) -> Result<(), RgbMapError> {
+        let entry = self
+            .palette
+            .get_mut(index as usize)
+            .ok_or(RgbMapError::InvalidPaletteIndex(index))?;
+
+        *entry = color;
+
+        Ok(())
+    }
+
+    /// Gets the palette index for the `key` on the `layer`.
+    pub fn key_index(&self, layer: u8, key: u8) -> Result<u8, RgbMapError> {
+        let (byte, shift) = Self::position(layer, key)?;
+
+        Ok((self.indices[byte] >> shift) & 0xf)
+    }
+
+    /// Sets the palette index for the `key` on the `layer`.
+    pub fn set_key_index(&mut self, layer: u8, key: u8, index: u8) -> Result<(), RgbMapError> {
+        if index as usize >= PALETTE_LEN {
+            return Err(RgbMapError::InvalidPaletteIndex(index));
+        }
+
+        let (byte, shift) = Self::position(layer, key)?;
+
+        self.indices[byte] = (self.indices[byte] & !(0xf << shift)) | (index << shift);
+
+        Ok(())
+    }
+
+    /// Gets the color for the `key` on the `layer`.
+    pub fn key_color(&self, layer: u8, key: u8) -> Result<Rgb, RgbMapError> {
+        self.palette_color(self.key_index(layer, key)?)
+    }
+
+    /// Gets the packed byte offset and bit shift of a key index.
+    fn position(layer: u8, key: u8) -> Result<(usize, u8), RgbMapError> {
+        if layer as usize >= NUM_LAYERS {
+            Err(RgbMapError::InvalidLayer(layer))
+        } else if key as usize >= RGB_MAP_KEYS {
+            Err(RgbMapError::InvalidKey(key))
+        } else {
+            let byte = layer as usize * RGB_MAP_KEYS.div_ceil(2) + key as usize / 2;
+            Ok((byte, (key % 2) * 4))
+        }
+    }
+
+    /// Converts the [RgbMap] into its serialized form.
+    pub fn to_bytes(&self) -> [u8; RGB_MAP_LEN] {
+        let mut out = [0u8; RGB_MAP_LEN];
+
+        out[..2].copy_from_slice(RGB_MAP_MAGIC.as_ref());
+        out[2] = RGB_MAP_VERSION;
+
+        let (palette, indices) = out[3..].split_at_mut(PALETTE_LEN * 3);
+
+        for (chunk, color) in palette.chunks_exact_mut(3).zip(self.palette.iter()) {
+            chunk.copy_from_slice([color.r, color.g, color.b].as_ref());
+        }
+
+        indices.copy_from_slice(self.indices.as_ref());
+
+        out
+    }
+
+    /// Parses an [RgbMap] from its serialized form.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, RgbMapError> {
+        if data.len() < RGB_MAP_LEN {
+            return Err(RgbMapError::Truncated);
+        } else if data[..2] != RGB_MAP_MAGIC {
+            return Err(RgbMapError::InvalidMagic);
+        } else if data[2] != RGB_MAP_VERSION {
+            return Err(RgbMapError::InvalidVersion(data[2]));
+        }
+
+        let (palette, indices) = data[3..RGB_MAP_LEN].split_at(PALETTE_LEN * 3);
+
+        let mut map = Self::new();
+
+        for (color, chunk) in map.palette.iter_mut().zip(palette.chunks_exact(3)) {
+            *color = Rgb::new(chunk[0], chunk[1], chunk[2]);
+        }
+
+        map.indices.copy_from_slice(indices);
+
+        Ok(map)
+    }
+
+    /// Handles a color map configuration protocol command.
+    ///
+    /// Writes the response into the buffer, and returns the number of bytes written.
+    pub fn handle_command(
+        &mut self,
+        command: Command,
+        args: &[u8],
+        buf: &mut [u8],
+    ) -> Result<usize, ConfigError> {
+        let response: &[u8] = match (command, args) {
+            (Command::GetKeyColor, &[layer, key, ..]) => {
+                let index = self.key_index(layer, key)?;
+                let color = self.palette_color(index)?;
+                &[index, color.r, color.g, color.b]
+            }
+            (Command::SetKeyColor, &[layer, key, index, ..]) => {
+                self.set_key_index(layer, key, index)?;
+                &[]
+            }
+            (Command::GetPaletteColor, &[index, ..]) => {
+                let color = self.palette_color(index)?;
+                &[color.r, color.g, color.b]
+            }
+            (Command::SetPaletteColor, &[index, r, g, b, ..]) => {
+                self.set_palette_color(index, Rgb::new(r, g, b))?;
+                &[]
+            }
+            (
+                Command::GetKeyColor
+                | Command::SetKeyColor
+                | Command::GetPaletteColor
+                | Command::SetPaletteColor,
+                _,
+            ) => return Err(ConfigError::InvalidArgument),
+            _ => return Err(ConfigError::UnhandledCommand(command)),
+        };
+
+        buf.get_mut(..response.len())
+            .ok_or(ConfigError::BufferTooSmall)?
+            .copy_from_slice(response);
+
+        Ok(response.len())
+    }
+}
+
+impl Default for RgbMap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Loads the [RgbMap] stored in the EEPROM.
+///
+/// Falls back to a blank [RgbMap] if the stored color map is missing or invalid.
+pub fn load_rgb_map<S: EepromStorage>(storage: &S) -> RgbMap {
+    let mut data = [0u8; RGB_MAP_LEN];
+
+    storage
+        .load(RGB_MAP_OFFSET, &mut data)
+        .ok()
+        .and_then(|_| RgbMap::from_bytes(&data).ok())
+        .unwrap_or_default()
+}
+
+/// Stores the [RgbMap] in the EEPROM.
+pub fn store_rgb_map<S: EepromStorage>(storage: &mut S, map: &RgbMap) -> Result<(), ConfigError> {
+    storage.store(RGB_MAP_OFFSET, map.to_bytes().as_ref())
+}
+
+/// Handles the color map [Command]s against the color map stored in the EEPROM.
+///
+/// The set commands store the changed color map. Returns [ConfigError::UnhandledCommand] for any
+/// other command.
+pub fn handle_rgb_map_command<S: EepromStorage>(
+    storage: &mut S,
+    command: Command,
+    args: &[u8],
+    buf: &mut [u8],
+) -> Result<usize, ConfigError> {
+    let mut map = load_rgb_map(storage);
+    let len = map.handle_command(command, args, buf)?;
+
+    if matches!(command, Command::SetKeyColor | Command::SetPaletteColor) {
+        store_rgb_map(storage, &map)?;
+    }
+
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Rgb = Rgb::new(0xff, 0, 0);
+
+    #[test]
+    fn test_rgb_map() {
+        let mut map = RgbMap::new();
+
+        map.set_palette_color(1, RED).unwrap();
+        map.set_key_index(0, 1, 1).unwrap();
+        map.set_key_index(3, 47, 15).unwrap();
+
+        assert_eq!(map.key_color(0, 0), Ok(Rgb::off()));
+        assert_eq!(map.key_color(0, 1), Ok(RED));
+        assert_eq!(map.key_index(3, 47), Ok(15));
+        assert_eq!(map.key_index(3, 46), Ok(0));
+
+        assert_eq!(map.key_index(4, 0), Err(RgbMapError::InvalidLayer(4)));
+        assert_eq!(map.key_index(0, 48), Err(RgbMapError::InvalidKey(48)));
+        assert_eq!(
+            map.set_key_index(0, 0, 16),
+            Err(RgbMapError::InvalidPaletteIndex(16))
+        );
+
+        let bytes = map.to_bytes();
+        assert_eq!(RgbMap::from_bytes(&bytes), Ok(map));
+        assert_eq!(
+            RgbMap::from_bytes(&bytes[..RGB_MAP_LEN - 1]),
+            Err(RgbMapError::Truncated)
+        );
+    }
+
+    #[test]
+    fn test_rgb_map_commands() {
+        let mut map = RgbMap::new();
+        let mut buf = [0u8; 8];
+
+        assert_eq!(
+            map.handle_command(Command::SetPaletteColor, &[2, 0xff, 0, 0], &mut buf),
+            Ok(0)
+        );
+        assert_eq!(
+            map.handle_command(Command::SetKeyColor, &[1, 12, 2], &mut buf),
+            Ok(0)
+        );
+        assert_eq!(
+            map.handle_command(Command::GetKeyColor, &[1, 12], &mut buf),
+            Ok(4)
+        );
+        assert_eq!(buf[..4], [2, 0xff, 0, 0]);
+
+        assert_eq!(
+            map.handle_command(Command::GetPaletteColor, &[], &mut buf),
+            Err(ConfigError::InvalidArgument)
+        );
+        assert_eq!(
+            map.handle_command(Command::Lint, &[], &mut buf),
+            Err(ConfigError::UnhandledCommand(Command::Lint))
+        );
+    }
+}
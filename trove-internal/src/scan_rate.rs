@@ -0,0 +1,162 @@
+//! Adaptive matrix scan rate.
+//!
+//! Scanning at the full rate while nobody is typing wastes CPU time and power. The
+//! [AdaptiveScanRate] slows the scan interval once the matrix has been idle for a while, and snaps
+//! back to the full rate on the first key activation. The first raw sample of a press switches
+//! back, so debouncing and everything after it runs at the full rate.
+
+use crate::health::DEFAULT_TICK_INTERVAL_US;
+
+/// Default scan interval (in microseconds) while keys are active.
+pub const DEFAULT_FAST_INTERVAL_US: u16 = DEFAULT_TICK_INTERVAL_US;
+/// Default scan interval (in microseconds) while the matrix is idle.
+pub const DEFAULT_SLOW_INTERVAL_US: u16 = 8000;
+/// Default idle time (in milliseconds) before slowing the scan interval.
+pub const DEFAULT_IDLE_MS: u16 = 1000;
+
+/// Chooses the scan interval based on recent matrix activity.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AdaptiveScanRate {
+    fast_interval_us: u16,
+    slow_interval_us: u16,
+    idle_ms: u16,
+    idle_us: u32,
+    slow: bool,
+}
+
+impl AdaptiveScanRate {
+    /// Creates a new [AdaptiveScanRate], starting at the full rate.
+    pub const fn new() -> Self {
+        Self {
+            fast_interval_us: DEFAULT_FAST_INTERVAL_US,
+            slow_interval_us: DEFAULT_SLOW_INTERVAL_US,
+            idle_ms: DEFAULT_IDLE_MS,
+            idle_us: 0,
+            slow: false,
+        }
+    }
+
+    /// Gets the scan interval (in microseconds) while keys are active.
+    pub const fn fast_interval_us(&self) -> u16 {
+        self.fast_interval_us
+    }
+
+    /// Sets the scan interval (in microseconds) while keys are active.
+    pub fn set_fast_interval_us(&mut self, val: u16) {
+        self.fast_interval_us = val.max(1);
+    }
+
+    /// Builder function that sets the scan interval (in microseconds) while keys are active.
+    pub fn with_fast_interval_us(mut self, val: u16) -> Self {
+        self.set_fast_interval_us(val);
+        self
+    }
+
+    /// Gets the scan interval (in microseconds) while the matrix is idle.
+    pub const fn slow_interval_us(&self) -> u16 {
+        self.slow_interval_us
+    }
+
+    /// Sets the scan interval (in microseconds) while the matrix is idle.
+    ///
+    /// The slow interval is also the worst-case latency of the first key press after idling.
+    pub fn set_slow_interval_us(&mut self, val: u16) {
+        self.slow_interval_us = val.max(1);
+    }
+
+    /// Builder function that sets the scan interval (in microseconds) while the matrix is idle.
+    pub fn with_slow_interval_us(mut self, val: u16) -> Self {
+        self.set_slow_interval_us(val);
+        self
+    }
+
+    /// Gets the idle time (in milliseconds) before slowing the scan interval.
+    pub const fn idle_ms(&self) -> u16 {
+        self.idle_ms
+    }
+
+    /// Sets the idle time (in milliseconds) before slowing the scan interval.
+    pub fn set_idle_ms(&mut self, val: u16) {
+        self.idle_ms = val;
+    }
+
+    /// Builder function that sets the idle time (in milliseconds) before slowing the scan
+    /// interval.
+    pub fn with_idle_ms(mut self, val: u16) -> Self {
+        self.set_idle_ms(val);
+        self
+    }
+
+    /// Gets whether the scan interval is slowed.
+    pub const fn is_slow(&self) -> bool {
+        self.slow
+    }
+
+    /// Gets the current scan interval in microseconds.
+    pub const fn interval_us(&self) -> u16 {
+        if self.slow {
+            self.slow_interval_us
+        } else {
+            self.fast_interval_us
+        }
+    }
+
+    /// Updates the scan rate after a matrix scan.
+    ///
+    /// The scan is `active` if any key is pressed, or any key changed state.
+    ///
+    /// Returns the new scan interval (in microseconds) if it changed.
+    pub fn update(&mut self, active: bool) -> Option<u16> {
+        if active {
+            self.idle_us = 0;
+
+            if self.slow {
+                self.slow = false;
+                return Some(self.fast_interval_us);
+            }
+        } else if !self.slow {
+            self.idle_us = self.idle_us.saturating_add(self.fast_interval_us as u32);
+
+            if self.idle_us >= self.idle_ms as u32 * 1000 {
+                self.slow = true;
+                return Some(self.slow_interval_us);
+            }
+        }
+
+        None
+    }
+}
+
+impl Default for AdaptiveScanRate {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adaptive_scan_rate() {
+        let mut rate = AdaptiveScanRate::new()
+            .with_fast_interval_us(1000)
+            .with_slow_interval_us(8000)
+            .with_idle_ms(3);
+
+        assert_eq!(rate.update(false), None);
+        assert_eq!(rate.update(true), None);
+
+        // idle for 3ms
+        assert_eq!(rate.update(false), None);
+        assert_eq!(rate.update(false), None);
+        assert_eq!(rate.update(false), Some(8000));
+        assert!(rate.is_slow());
+        assert_eq!(rate.update(false), None);
+
+        // snap back on the first activation
+        assert_eq!(rate.update(true), Some(1000));
+        assert_eq!(rate.interval_us(), 1000);
+        assert_eq!(rate.update(true), None);
+    }
+}
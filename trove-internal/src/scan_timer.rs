@@ -0,0 +1,158 @@
+//! Scan timer configuration.
+//!
+//! The scan timer (Timer 1) runs in phase and frequency correct PWM mode, with `ICR1` as `TOP`.
+//! It counts up to `TOP` and back down, and overflows once per period of `2 * TOP` timer cycles.
+//! A [ScanTimerConfig] picks the smallest clock prescaler that fits the requested interval into
+//! the 16-bit `TOP`, for the finest resolution.
+//!
+//! The [health](crate::health) tick interval is kept in 16-bit microseconds, so intervals are
+//! limited to [MIN_SCAN_TIMER_INTERVAL_US] - [MAX_SCAN_TIMER_INTERVAL_US].
+
+/// Shortest scan timer interval (in microseconds), to leave the main loop time to run.
+pub const MIN_SCAN_TIMER_INTERVAL_US: u16 = 100;
+/// Longest scan timer interval (in microseconds).
+pub const MAX_SCAN_TIMER_INTERVAL_US: u16 = 62_500;
+/// Highest scan frequency in Hz.
+pub const MAX_SCAN_FREQUENCY_HZ: u16 = (1_000_000 / MIN_SCAN_TIMER_INTERVAL_US as u32) as u16;
+/// Lowest scan frequency in Hz.
+pub const MIN_SCAN_FREQUENCY_HZ: u16 = (1_000_000 / MAX_SCAN_TIMER_INTERVAL_US as u32) as u16;
+
+/// Timer 1 clock prescalers, and their `CS1` clock select bits.
+const PRESCALERS: [(u32, u8); 5] = [
+    (1, 0b001),
+    (8, 0b010),
+    (64, 0b011),
+    (256, 0b100),
+    (1024, 0b101),
+];
+
+/// Register values of the scan timer for an interval.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ScanTimerConfig {
+    clock_select: u8,
+    prescaler: u16,
+    top: u16,
+    interval_us: u16,
+}
+
+impl ScanTimerConfig {
+    /// Creates a new [ScanTimerConfig] for an `interval` in microseconds, with the CPU clock
+    /// `f_cpu` in Hz (a multiple of 1MHz).
+    ///
+    /// The interval is clamped to [MIN_SCAN_TIMER_INTERVAL_US] - [MAX_SCAN_TIMER_INTERVAL_US].
+    pub const fn from_interval_us(f_cpu: u32, interval: u16) -> Self {
+        let interval = if interval < MIN_SCAN_TIMER_INTERVAL_US {
+            MIN_SCAN_TIMER_INTERVAL_US
+        } else if interval > MAX_SCAN_TIMER_INTERVAL_US {
+            MAX_SCAN_TIMER_INTERVAL_US
+        } else {
+            interval
+        };
+
+        // half a period, in CPU cycles, without overflowing at 16MHz
+        Self::from_half_period_cycles(f_cpu, (f_cpu / 1000) * interval as u32 / 2000)
+    }
+
+    /// Creates a new [ScanTimerConfig] for a frequency in Hz, with the CPU clock `f_cpu` in Hz (a
+    /// multiple of 1MHz).
+    ///
+    /// The frequency is clamped to [MIN_SCAN_FREQUENCY_HZ] - [MAX_SCAN_FREQUENCY_HZ].
+    pub const fn from_frequency_hz(f_cpu: u32, hz: u16) -> Self {
+        let hz = if hz < MIN_SCAN_FREQUENCY_HZ {
+            MIN_SCAN_FREQUENCY_HZ
+        } else if hz > MAX_SCAN_FREQUENCY_HZ {
+            MAX_SCAN_FREQUENCY_HZ
+        } else {
+            hz
+        };
+
+        Self::from_half_period_cycles(f_cpu, f_cpu / (2 * hz as u32))
+    }
+
+    /// Gets the `CS1` clock select bits of the `TCCR1B` register.
+    pub const fn clock_select(&self) -> u8 {
+        self.clock_select
+    }
+
+    /// Gets the clock prescaler selected by the [clock_select](Self::clock_select) bits.
+    pub const fn prescaler(&self) -> u16 {
+        self.prescaler
+    }
+
+    /// Gets the `TOP` value of the `ICR1` register.
+    pub const fn top(&self) -> u16 {
+        self.top
+    }
+
+    /// Gets the achieved interval (in microseconds), after rounding to timer cycles.
+    pub const fn interval_us(&self) -> u16 {
+        self.interval_us
+    }
+
+    /// Gets the achieved scan frequency in Hz.
+    pub const fn frequency_hz(&self) -> u16 {
+        ((1_000_000 + self.interval_us as u32 / 2) / self.interval_us as u32) as u16
+    }
+
+    // Picks the smallest prescaler that fits half a period into the 16-bit `TOP`.
+    const fn from_half_period_cycles(f_cpu: u32, cycles: u32) -> Self {
+        let mut i = 0;
+
+        while i < PRESCALERS.len() - 1 && cycles / PRESCALERS[i].0 > u16::MAX as u32 {
+            i += 1;
+        }
+
+        let (prescaler, clock_select) = PRESCALERS[i];
+        let top = cycles / prescaler;
+        let top = if top == 0 {
+            1
+        } else if top > u16::MAX as u32 {
+            u16::MAX as u32
+        } else {
+            top
+        };
+
+        // fits into 32 bits for any TOP and prescaler
+        let interval_us = (2 * top * prescaler / (f_cpu / 1_000_000)) as u16;
+
+        Self {
+            clock_select,
+            prescaler: prescaler as u16,
+            top: top as u16,
+            interval_us,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const F_CPU: u32 = 16_000_000;
+
+    #[test]
+    fn test_scan_timer_config() {
+        let config = ScanTimerConfig::from_interval_us(F_CPU, 1500);
+        assert_eq!(config.clock_select(), 0b001);
+        assert_eq!(config.top(), 12_000);
+        assert_eq!(config.interval_us(), 1500);
+
+        // too long for the 16-bit TOP without a prescaler
+        let config = ScanTimerConfig::from_interval_us(F_CPU, 20_000);
+        assert_eq!(config.prescaler(), 8);
+        assert_eq!(config.top(), 20_000);
+        assert_eq!(config.frequency_hz(), 50);
+
+        let config = ScanTimerConfig::from_frequency_hz(F_CPU, 1000);
+        assert_eq!((config.prescaler(), config.top()), (1, 8000));
+        assert_eq!(config.interval_us(), 1000);
+
+        let config = ScanTimerConfig::from_frequency_hz(F_CPU, 1);
+        assert_eq!((config.prescaler(), config.top()), (8, 62_500));
+        assert_eq!(config.frequency_hz(), MIN_SCAN_FREQUENCY_HZ);
+        assert_eq!(config.interval_us(), MAX_SCAN_TIMER_INTERVAL_US);
+
+        let config = ScanTimerConfig::from_interval_us(F_CPU, 0);
+        assert_eq!(config.interval_us(), MIN_SCAN_TIMER_INTERVAL_US);
+    }
+}
@@ -0,0 +1,162 @@
+//! Matrix scanning traits.
+//!
+//! The firmware key scanner debounces and reports the [RowState]s produced by a [MatrixScanner],
+//! so exotic hardware (I/O expanders, shift registers, direct pins, or a simulator) can be
+//! dropped in without modifying it.
+//!
+//! Most backends read one row at a time, and implement [MatrixReader], which provides a
+//! [MatrixScanner] that reads the rows in order. Backends that read the whole matrix at once
+//! implement [MatrixScanner] directly.
+
+use crate::debounce::{RowState, MAX_COLS};
+
+/// Default time (in microseconds) for an input pin to settle after activating a row or column.
+pub const DEFAULT_SETTLE_US: u16 = 30;
+
+/// Reads the switch states of a keyboard in one pass.
+pub trait MatrixScanner<const ROWS: usize, const COLS: usize> {
+    /// Reads the pressed switches in every row.
+    fn scan(&mut self) -> [RowState; ROWS];
+
+    /// Prepares the switches for idling, so any key press changes an input pin.
+    ///
+    /// Returns `false` if the backend can not wake from idle on a key press.
+    fn prepare_idle(&mut self) -> bool {
+        false
+    }
+
+    /// Restores the switches for scanning after [prepare_idle](Self::prepare_idle).
+    fn resume_scan(&mut self) {}
+
+    /// Sets the time (in microseconds) for the switch inputs to settle before they are read.
+    ///
+    /// Backends without a settle delay ignore it.
+    fn set_settle_us(&mut self, _val: u16) {}
+}
+
+/// Reads the switch states of a keyboard, one row at a time.
+///
+/// Implemented by the scanning backends, so the key scanner can debounce and report keys
+/// regardless of how the switches are wired.
+pub trait MatrixReader<const ROWS: usize, const COLS: usize> {
+    /// Reads the pressed switches in the `row`.
+    fn read_row(&mut self, row: usize) -> RowState;
+
+    /// Prepares the switches for idling, so any key press changes an input pin.
+    ///
+    /// Returns `false` if the backend can not wake from idle on a key press.
+    fn prepare_idle(&mut self) -> bool {
+        false
+    }
+
+    /// Restores the switches for scanning after [prepare_idle](Self::prepare_idle).
+    fn resume_scan(&mut self) {}
+
+    /// Sets the time (in microseconds) for the switch inputs to settle before they are read.
+    ///
+    /// Backends without a settle delay ignore it.
+    fn set_settle_us(&mut self, _val: u16) {}
+}
+
+impl<const ROWS: usize, const COLS: usize, M: MatrixReader<ROWS, COLS>> MatrixScanner<ROWS, COLS>
+    for M
+{
+    fn scan(&mut self) -> [RowState; ROWS] {
+        let mut rows = [RowState::new(); ROWS];
+
+        for (i, row) in rows.iter_mut().enumerate() {
+            *row = self.read_row(i);
+        }
+
+        rows
+    }
+
+    fn prepare_idle(&mut self) -> bool {
+        MatrixReader::prepare_idle(self)
+    }
+
+    fn resume_scan(&mut self) {
+        MatrixReader::resume_scan(self)
+    }
+
+    fn set_settle_us(&mut self, val: u16) {
+        MatrixReader::set_settle_us(self, val)
+    }
+}
+
+/// Simulated key matrix, for testing the scanning pipeline off-target.
+///
+/// Keys are pressed and released by the test, and every [scan](MatrixScanner::scan) returns the
+/// current switch states.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SimMatrix<const ROWS: usize, const COLS: usize> {
+    rows: [RowState; ROWS],
+}
+
+impl<const ROWS: usize, const COLS: usize> SimMatrix<ROWS, COLS> {
+    const VALID_COLS: () = assert!(COLS <= MAX_COLS, "too many columns for a RowState");
+
+    /// Creates a new [SimMatrix] with no keys pressed.
+    pub const fn new() -> Self {
+        #[allow(clippy::let_unit_value)]
+        let _ = Self::VALID_COLS;
+
+        Self {
+            rows: [RowState::new(); ROWS],
+        }
+    }
+
+    /// Sets whether the key at the `row` and `col` is pressed.
+    ///
+    /// Keys outside of the matrix are ignored.
+    pub fn set_key(&mut self, row: usize, col: usize, pressed: bool) {
+        if let Some(state) = self.rows.get_mut(row).filter(|_| col < COLS) {
+            state.set_column(col, pressed);
+        }
+    }
+
+    /// Sets the switch states of every row.
+    pub fn set_rows(&mut self, rows: [RowState; ROWS]) {
+        self.rows = rows;
+    }
+}
+
+impl<const ROWS: usize, const COLS: usize> Default for SimMatrix<ROWS, COLS> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const ROWS: usize, const COLS: usize> MatrixScanner<ROWS, COLS> for SimMatrix<ROWS, COLS> {
+    fn scan(&mut self) -> [RowState; ROWS] {
+        self.rows
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Reads the row index into the first columns, to check rows are read in order.
+    struct RowIndex;
+
+    impl MatrixReader<3, 4> for RowIndex {
+        fn read_row(&mut self, row: usize) -> RowState {
+            RowState::from_u16(row as u16)
+        }
+    }
+
+    #[test]
+    fn test_matrix_scanner() {
+        let rows = RowIndex.scan();
+        assert_eq!(rows, [0, 1, 2].map(RowState::from_u16));
+        assert!(!MatrixScanner::prepare_idle(&mut RowIndex));
+
+        let mut sim = SimMatrix::<2, 4>::new();
+        sim.set_key(1, 3, true);
+        sim.set_key(1, 4, true);
+        sim.set_key(2, 0, true);
+
+        assert_eq!(sim.scan(), [RowState::new(), RowState::from_u16(1 << 3)]);
+    }
+}
@@ -0,0 +1,259 @@
+//! Deferred tasks.
+//!
+//! Features that act after a delay (tap-dance and one-shot timeouts, macros with delays, LED
+//! effects) share these timers, instead of rolling their own counters:
+//!
+//! - [Timeout]: a polled one-shot timer, owned by the feature
+//! - [Scheduler]: runs [Task] callbacks once their delay elapsed, polled from the main loop
+//!
+//! Times are milliseconds of a wrapping `u16` clock, e.g. the low half of
+//! [millis](crate::time::millis), so delays are limited to `u16::MAX` milliseconds.
+//! Poll at least once per wrap of the clock, or an expired timer is missed.
+
+/// Polled one-shot timer.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Timeout {
+    start_ms: u16,
+    delay_ms: u16,
+    pending: bool,
+}
+
+impl Timeout {
+    /// Creates a new [Timeout], which is not started.
+    pub const fn new() -> Self {
+        Self {
+            start_ms: 0,
+            delay_ms: 0,
+            pending: false,
+        }
+    }
+
+    /// Creates a new [Timeout], started at the time `now_ms`.
+    pub const fn started(now_ms: u16, delay_ms: u16) -> Self {
+        Self {
+            start_ms: now_ms,
+            delay_ms,
+            pending: true,
+        }
+    }
+
+    /// Starts (or restarts) the timer at the time `now_ms`, to expire after `delay_ms`.
+    pub fn start(&mut self, now_ms: u16, delay_ms: u16) {
+        *self = Self::started(now_ms, delay_ms);
+    }
+
+    /// Stops the timer without expiring.
+    pub fn cancel(&mut self) {
+        self.pending = false;
+    }
+
+    /// Gets whether the timer is started, and did not expire yet.
+    pub const fn is_pending(&self) -> bool {
+        self.pending
+    }
+
+    /// Gets whether the timer expired at the time `now_ms`, without stopping it.
+    pub const fn is_expired(&self, now_ms: u16) -> bool {
+        self.pending && now_ms.wrapping_sub(self.start_ms) >= self.delay_ms
+    }
+
+    /// Gets the time (in milliseconds) left at the time `now_ms`.
+    ///
+    /// Returns `0` if the timer is not pending.
+    pub const fn remaining_ms(&self, now_ms: u16) -> u16 {
+        if self.pending {
+            self.delay_ms
+                .saturating_sub(now_ms.wrapping_sub(self.start_ms))
+        } else {
+            0
+        }
+    }
+
+    /// Polls the timer at the time `now_ms`.
+    ///
+    /// Returns `true` once the timer expired, and stops it.
+    pub fn poll(&mut self, now_ms: u16) -> bool {
+        let expired = self.is_expired(now_ms);
+
+        if expired {
+            self.pending = false;
+        }
+
+        expired
+    }
+}
+
+/// Callback run by the [Scheduler].
+pub type Task = fn();
+
+/// Identifies a task of a [Scheduler], to cancel it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TaskId {
+    slot: u8,
+    generation: u8,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Slot {
+    task: Option<Task>,
+    timeout: Timeout,
+    generation: u8,
+}
+
+/// Runs up to `N` [Task]s after a delay.
+#[derive(Clone, Copy, Debug)]
+pub struct Scheduler<const N: usize> {
+    slots: [Slot; N],
+}
+
+impl<const N: usize> Scheduler<N> {
+    /// Creates a new [Scheduler], without tasks.
+    pub const fn new() -> Self {
+        Self {
+            slots: [Slot {
+                task: None,
+                timeout: Timeout::new(),
+                generation: 0,
+            }; N],
+        }
+    }
+
+    /// Schedules the `task` to run `delay_ms` after the time `now_ms`.
+    ///
+    /// Returns `None` if every slot is taken.
+    pub fn schedule(&mut self, now_ms: u16, delay_ms: u16, task: Task) -> Option<TaskId> {
+        let (slot, entry) = self
+            .slots
+            .iter_mut()
+            .enumerate()
+            .find(|(_, s)| s.task.is_none())?;
+
+        entry.task = Some(task);
+        entry.timeout.start(now_ms, delay_ms);
+
+        Some(TaskId {
+            slot: slot as u8,
+            generation: entry.generation,
+        })
+    }
+
+    /// Cancels a scheduled task.
+    ///
+    /// Returns `false` if the task already ran, or was cancelled.
+    pub fn cancel(&mut self, id: TaskId) -> bool {
+        let scheduled = self.is_scheduled(id);
+
+        if scheduled {
+            self.free(id.slot as usize);
+        }
+
+        scheduled
+    }
+
+    /// Gets whether a task is still waiting to run.
+    pub fn is_scheduled(&self, id: TaskId) -> bool {
+        self.slots
+            .get(id.slot as usize)
+            .is_some_and(|s| s.task.is_some() && s.generation == id.generation)
+    }
+
+    /// Gets the number of scheduled tasks.
+    pub fn len(&self) -> usize {
+        self.slots.iter().filter(|s| s.task.is_some()).count()
+    }
+
+    /// Gets whether no tasks are scheduled.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Runs the tasks that are due at the time `now_ms`.
+    ///
+    /// Returns the number of tasks that ran.
+    pub fn run(&mut self, now_ms: u16) -> usize {
+        let mut ran = 0;
+
+        for slot in 0..N {
+            if let Some(task) = self.slots[slot].task {
+                if self.slots[slot].timeout.poll(now_ms) {
+                    // free the slot first, so the task can be scheduled again
+                    self.free(slot);
+                    task();
+                    ran += 1;
+                }
+            }
+        }
+
+        ran
+    }
+
+    // Frees a slot, and invalidates its task ID.
+    fn free(&mut self, slot: usize) {
+        let entry = &mut self.slots[slot];
+
+        entry.task = None;
+        entry.timeout.cancel();
+        entry.generation = entry.generation.wrapping_add(1);
+    }
+}
+
+impl<const N: usize> Default for Scheduler<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::sync::atomic::{AtomicU8, Ordering};
+
+    static RUNS: AtomicU8 = AtomicU8::new(0);
+
+    fn count_run() {
+        RUNS.store(RUNS.load(Ordering::Relaxed) + 1, Ordering::SeqCst);
+    }
+
+    #[test]
+    fn test_timeout() {
+        let mut timeout = Timeout::new();
+        assert!(!timeout.poll(0));
+
+        // the clock wraps around while pending
+        timeout.start(65_500, 100);
+        assert_eq!(timeout.remaining_ms(65_530), 70);
+        assert!(!timeout.poll(63));
+        assert!(timeout.poll(64));
+        assert!(!timeout.is_pending());
+        assert!(!timeout.poll(65));
+
+        timeout.start(0, 10);
+        timeout.cancel();
+        assert!(!timeout.poll(10));
+    }
+
+    #[test]
+    fn test_scheduler() {
+        let mut scheduler = Scheduler::<2>::new();
+
+        let first = scheduler.schedule(0, 10, count_run).unwrap();
+        let second = scheduler.schedule(0, 20, count_run).unwrap();
+        assert_eq!(scheduler.schedule(0, 30, count_run), None);
+
+        assert_eq!(scheduler.run(9), 0);
+        assert_eq!(scheduler.run(10), 1);
+        assert!(!scheduler.is_scheduled(first));
+        assert!(!scheduler.cancel(first));
+
+        // the freed slot is reused, without reviving the old ID
+        let third = scheduler.schedule(10, 5, count_run).unwrap();
+        assert!(!scheduler.is_scheduled(first));
+        assert!(scheduler.cancel(second));
+        assert_eq!(scheduler.len(), 1);
+
+        assert_eq!(scheduler.run(20), 1);
+        assert!(!scheduler.is_scheduled(third));
+        assert!(scheduler.is_empty());
+        assert_eq!(RUNS.load(Ordering::Relaxed), 2);
+    }
+}
@@ -0,0 +1,59 @@
+//! USB serial number.
+//!
+//! Each ATmega32u4 has a unique serial number in its signature row: the production lot, wafer,
+//! and die coordinates. The firmware reads those bytes, and presents them as a hex string in the
+//! USB serial number descriptor, so the host can tell several trove keyboards apart, and udev
+//! rules can match a specific keyboard.
+
+/// Offset of the unique serial number in the ATmega32u4 signature row.
+pub const SIGNATURE_SERIAL_OFFSET: u8 = 0x0e;
+/// Length of the unique serial number in the signature row.
+pub const SIGNATURE_SERIAL_LEN: usize = 10;
+/// Length of the serial number string, two hex digits for every signature byte.
+pub const SERIAL_NUMBER_LEN: usize = SIGNATURE_SERIAL_LEN * 2;
+
+/// Uppercase hex digits.
+const HEX_DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+
+/// Represents the USB serial number string of a keyboard.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SerialNumber {
+    digits: [u8; SERIAL_NUMBER_LEN],
+}
+
+impl SerialNumber {
+    /// Creates a new [SerialNumber] from the serial bytes of the signature row.
+    pub const fn from_signature(bytes: &[u8; SIGNATURE_SERIAL_LEN]) -> Self {
+        let mut digits = [0u8; SERIAL_NUMBER_LEN];
+        let mut i = 0;
+
+        while i < SIGNATURE_SERIAL_LEN {
+            digits[i * 2] = HEX_DIGITS[(bytes[i] >> 4) as usize];
+            digits[i * 2 + 1] = HEX_DIGITS[(bytes[i] & 0xf) as usize];
+            i += 1;
+        }
+
+        Self { digits }
+    }
+
+    /// Gets the serial number string.
+    pub fn as_str(&self) -> &str {
+        // only hex digits are ever written
+        core::str::from_utf8(self.digits.as_ref()).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_serial_number() {
+        let serial = SerialNumber::from_signature(&[
+            0x59, 0x36, 0x33, 0x38, 0x31, 0x31, 0x0a, 0x12, 0x00, 0xff,
+        ]);
+
+        assert_eq!(serial.as_str(), "5936333831310A1200FF");
+        assert_eq!(serial.as_str().len(), SERIAL_NUMBER_LEN);
+    }
+}
@@ -0,0 +1,517 @@
+//! Persistent settings.
+//!
+//! User options that survive a power cycle are stored in a settings block in EEPROM at
+//! [SETTINGS_OFFSET], right after the [pin map](crate::pin_map). The layout of a serialized
+//! settings block:
+//!
+//! ```text
+//! | magic "TS" | version | CRC-16 | default layer | debounce ms | feature flags | report mode |
+//! | settle us (u16) | tapping term ms (u16) | mouse speed | wheel speed |
+//! ```
+//!
+//! The CRC covers the version and the settings fields, so a block torn by a reset during a write
+//! is detected. Each version has its own [payload length](payload_len), and fields are only ever
+//! appended: a block from an older firmware is migrated by keeping its fields, and filling in the
+//! defaults for the newer ones. Erased, corrupted, or newer blocks are replaced by the defaults,
+//! see [restore].
+//!
+//! Runtime state the user changes from the keyboard or a host tool (the locked layer, the [report
+//! mode](crate::nkro::ReportMode), the [features](crate::features), and the
+//! [tuning](crate::tuning) parameters) is written back by a [SettingsTracker], once it stops
+//! changing for [PERSIST_DELAY_MS].
+
+use crate::features::{self, FeatureFlags};
+use crate::layers::{self, Layer, NUM_LAYERS};
+use crate::nkro::{self, ReportMode};
+use crate::pin_map::{PIN_MAP_LEN, PIN_MAP_OFFSET};
+use crate::scheduler::Timeout;
+use crate::storage::{crc16, crc16_update};
+use crate::tuning::{self, Tuning};
+
+/// Magic bytes at the start of a serialized settings block.
+pub const SETTINGS_MAGIC: [u8; 2] = *b"TS";
+/// Current version of the serialized settings block format.
+pub const SETTINGS_VERSION: u8 = 3;
+/// Length of the settings block header: `| magic | version | CRC-16 |`.
+pub const SETTINGS_HEADER_LEN: usize = 5;
+/// Length of a serialized settings block in the current version.
+pub const SETTINGS_LEN: usize = SETTINGS_HEADER_LEN + payload_len(SETTINGS_VERSION);
+/// Offset of the settings block in the EEPROM, right after the pin map.
+pub const SETTINGS_OFFSET: usize = PIN_MAP_OFFSET + PIN_MAP_LEN;
+/// Space reserved for the settings block in the EEPROM, so later versions can add fields.
+pub const MAX_SETTINGS_LEN: usize = 32;
+/// Time (in milliseconds) the runtime state must stay unchanged before it is persisted.
+///
+/// Toggling through several states writes the EEPROM once, to limit wear.
+pub const PERSIST_DELAY_MS: u16 = 2000;
+
+const _: () = assert!(SETTINGS_LEN <= MAX_SETTINGS_LEN);
+
+/// Errors from decoding a settings block.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SettingsError {
+    /// The settings block does not begin with [SETTINGS_MAGIC].
+    InvalidMagic,
+    /// The settings block version is not supported.
+    InvalidVersion(u8),
+    /// The settings block is shorter than its version requires.
+    Truncated,
+    /// The CRC does not match the settings block.
+    InvalidCrc,
+    /// A setting is out of range.
+    InvalidValue,
+}
+
+/// Represents how [Settings] were [restored](restore) from a settings block.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Restored {
+    /// The block has the current version.
+    Current,
+    /// The block has an older version, and should be written back in the current version.
+    Migrated,
+    /// The block is missing or invalid, and should be written back with the defaults.
+    Initialized(SettingsError),
+}
+
+impl Restored {
+    /// Gets whether the settings block should be written back to the EEPROM.
+    pub const fn needs_write(&self) -> bool {
+        !matches!(self, Self::Current)
+    }
+}
+
+/// Represents the persistent user options.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Settings {
+    default_layer: u8,
+    debounce_ms: u8,
+    features: FeatureFlags,
+    report_mode: ReportMode,
+    settle_us: u16,
+    tapping_term_ms: u16,
+    mouse_speed: u8,
+    wheel_speed: u8,
+}
+
+impl Settings {
+    /// Creates a new [Settings] with the defaults.
+    pub const fn new() -> Self {
+        Self {
+            default_layer: 0,
+            debounce_ms: Tuning::DebounceMs.default_value() as u8,
+            features: FeatureFlags::new(),
+            report_mode: ReportMode::Boot,
+            settle_us: Tuning::SettleUs.default_value(),
+            tapping_term_ms: Tuning::TappingTermMs.default_value(),
+            mouse_speed: Tuning::MouseSpeed.default_value() as u8,
+            wheel_speed: Tuning::WheelSpeed.default_value() as u8,
+        }
+    }
+
+    /// Gets the layer that is active after a reset.
+    pub const fn default_layer(&self) -> u8 {
+        self.default_layer
+    }
+
+    /// Builder function that sets the layer that is active after a reset.
+    ///
+    /// Layers beyond [NUM_LAYERS] fall back to the base layer.
+    pub const fn with_default_layer(mut self, val: u8) -> Self {
+        self.default_layer = if (val as usize) < NUM_LAYERS { val } else { 0 };
+        self
+    }
+
+    /// Gets the debounce window in milliseconds.
+    pub const fn debounce_ms(&self) -> u8 {
+        self.debounce_ms
+    }
+
+    /// Builder function that sets the debounce window in milliseconds.
+    pub const fn with_debounce_ms(mut self, val: u8) -> Self {
+        self.debounce_ms = val;
+        self
+    }
+
+    /// Gets the enabled runtime [features](crate::features).
+    pub const fn features(&self) -> FeatureFlags {
+        self.features
+    }
+
+    /// Builder function that sets the enabled runtime [features](crate::features).
+    pub const fn with_features(mut self, val: FeatureFlags) -> Self {
+        self.features = val;
+        self
+    }
+
+    /// Gets the [ReportMode] used to send keys to the host.
+    pub const fn report_mode(&self) -> ReportMode {
+        self.report_mode
+    }
+
+    /// Builder function that sets the [ReportMode] used to send keys to the host.
+    pub const fn with_report_mode(mut self, val: ReportMode) -> Self {
+        self.report_mode = val;
+        self
+    }
+
+    /// Gets the time (in microseconds) for the matrix inputs to settle before they are read.
+    pub const fn settle_us(&self) -> u16 {
+        self.settle_us
+    }
+
+    /// Builder function that sets the time (in microseconds) for the matrix inputs to settle.
+    pub const fn with_settle_us(mut self, val: u16) -> Self {
+        self.settle_us = val;
+        self
+    }
+
+    /// Gets the tapping term in milliseconds.
+    pub const fn tapping_term_ms(&self) -> u16 {
+        self.tapping_term_ms
+    }
+
+    /// Builder function that sets the tapping term in milliseconds.
+    pub const fn with_tapping_term_ms(mut self, val: u16) -> Self {
+        self.tapping_term_ms = val;
+        self
+    }
+
+    /// Gets the distance (in pixels) a mouse key moves the cursor per report.
+    pub const fn mouse_speed(&self) -> u8 {
+        self.mouse_speed
+    }
+
+    /// Builder function that sets the distance (in pixels) a mouse key moves the cursor per
+    /// report.
+    pub const fn with_mouse_speed(mut self, val: u8) -> Self {
+        self.mouse_speed = val;
+        self
+    }
+
+    /// Gets the number of wheel steps a mouse key scrolls per report.
+    pub const fn wheel_speed(&self) -> u8 {
+        self.wheel_speed
+    }
+
+    /// Builder function that sets the number of wheel steps a mouse key scrolls per report.
+    pub const fn with_wheel_speed(mut self, val: u8) -> Self {
+        self.wheel_speed = val;
+        self
+    }
+
+    /// Gets the value of a [Tuning] parameter.
+    pub const fn tuning(&self, param: Tuning) -> u16 {
+        match param {
+            Tuning::DebounceMs => self.debounce_ms as u16,
+            Tuning::SettleUs => self.settle_us,
+            Tuning::TappingTermMs => self.tapping_term_ms,
+            Tuning::MouseSpeed => self.mouse_speed as u16,
+            Tuning::WheelSpeed => self.wheel_speed as u16,
+        }
+    }
+
+    /// Builder function that sets the value of a [Tuning] parameter.
+    ///
+    /// Values are not range checked, see [Tuning::is_valid].
+    pub const fn with_tuning(self, param: Tuning, val: u16) -> Self {
+        match param {
+            Tuning::DebounceMs => self.with_debounce_ms(val as u8),
+            Tuning::SettleUs => self.with_settle_us(val),
+            Tuning::TappingTermMs => self.with_tapping_term_ms(val),
+            Tuning::MouseSpeed => self.with_mouse_speed(val as u8),
+            Tuning::WheelSpeed => self.with_wheel_speed(val as u8),
+        }
+    }
+
+    /// Builder function that sets the runtime state from the running firmware: the active layer,
+    /// the [ReportMode], the enabled [features](crate::features), and the [tuning] parameters.
+    ///
+    /// The momentary [Fun](Layer::Fun) layer keeps the current default layer.
+    pub fn with_runtime_state(self) -> Self {
+        let default_layer = match layers::active_layer() {
+            Layer::Fun => self.default_layer,
+            layer => layer.index() as u8,
+        };
+
+        let settings = self
+            .with_default_layer(default_layer)
+            .with_report_mode(nkro::report_mode())
+            .with_features(features::features());
+
+        Tuning::ALL.iter().fold(settings, |settings, &param| {
+            settings.with_tuning(param, tuning::tuning(param))
+        })
+    }
+
+    /// Applies the runtime state to the running firmware: the default layer, the [ReportMode],
+    /// the enabled [features](crate::features), and the [tuning] parameters.
+    ///
+    /// The key scanner picks up the tuning parameters on its own.
+    pub fn apply(&self) {
+        layers::set_active_layer(self.default_layer.into());
+        nkro::set_report_mode(self.report_mode);
+        features::set_features(self.features);
+
+        for param in Tuning::ALL {
+            // out of range values fall back to the defaults
+            tuning::set_tuning(param, self.tuning(param))
+                .or_else(|_| tuning::set_tuning(param, param.default_value()))
+                .ok();
+        }
+    }
+
+    /// Converts the [Settings] into a settings block in the current version.
+    pub fn to_bytes(&self) -> [u8; SETTINGS_LEN] {
+        let mut out = [0u8; SETTINGS_LEN];
+
+        out[..2].copy_from_slice(SETTINGS_MAGIC.as_ref());
+        out[2] = SETTINGS_VERSION;
+        out[SETTINGS_HEADER_LEN..SETTINGS_HEADER_LEN + 4].copy_from_slice(&[
+            self.default_layer,
+            self.debounce_ms,
+            self.features.as_inner(),
+            self.report_mode.into(),
+        ]);
+        out[SETTINGS_HEADER_LEN + 4..SETTINGS_HEADER_LEN + 6]
+            .copy_from_slice(&self.settle_us.to_le_bytes());
+        out[SETTINGS_HEADER_LEN + 6..SETTINGS_HEADER_LEN + 8]
+            .copy_from_slice(&self.tapping_term_ms.to_le_bytes());
+        out[SETTINGS_HEADER_LEN + 8] = self.mouse_speed;
+        out[SETTINGS_HEADER_LEN + 9] = self.wheel_speed;
+
+        let crc = block_crc(SETTINGS_VERSION, &out[SETTINGS_HEADER_LEN..]);
+        out[3..SETTINGS_HEADER_LEN].copy_from_slice(&crc.to_le_bytes());
+
+        out
+    }
+
+    /// Parses [Settings] from a settings block of the current or an older version.
+    ///
+    /// Fields missing from older versions keep their defaults. Erased EEPROM fails with
+    /// [SettingsError::InvalidMagic].
+    pub fn from_bytes(data: &[u8]) -> Result<Self, SettingsError> {
+        if data.len() < SETTINGS_HEADER_LEN {
+            return Err(SettingsError::Truncated);
+        } else if data[..2] != SETTINGS_MAGIC {
+            return Err(SettingsError::InvalidMagic);
+        }
+
+        let version = data[2];
+
+        if version == 0 || version > SETTINGS_VERSION {
+            return Err(SettingsError::InvalidVersion(version));
+        }
+
+        let payload = data
+            .get(SETTINGS_HEADER_LEN..SETTINGS_HEADER_LEN + payload_len(version))
+            .ok_or(SettingsError::Truncated)?;
+
+        if u16::from_le_bytes([data[3], data[4]]) != block_crc(version, payload) {
+            return Err(SettingsError::InvalidCrc);
+        }
+
+        if payload[0] as usize >= NUM_LAYERS {
+            return Err(SettingsError::InvalidValue);
+        }
+
+        // fields are only appended in later versions, so every version starts with these
+        let settings = Self::new()
+            .with_default_layer(payload[0])
+            .with_debounce_ms(payload[1])
+            .with_features(FeatureFlags::from_u8(payload[2]));
+
+        let settings = match payload.get(3) {
+            Some(&mode) => ReportMode::try_from(mode)
+                .map(|mode| settings.with_report_mode(mode))
+                .map_err(|_| SettingsError::InvalidValue)?,
+            None => settings,
+        };
+
+        let settings = match payload.get(4..10) {
+            Some(&[settle_lo, settle_hi, term_lo, term_hi, mouse_speed, wheel_speed]) => settings
+                .with_settle_us(u16::from_le_bytes([settle_lo, settle_hi]))
+                .with_tapping_term_ms(u16::from_le_bytes([term_lo, term_hi]))
+                .with_mouse_speed(mouse_speed)
+                .with_wheel_speed(wheel_speed),
+            _ => settings,
+        };
+
+        if Tuning::ALL
+            .iter()
+            .all(|param| param.is_valid(settings.tuning(*param)))
+        {
+            Ok(settings)
+        } else {
+            Err(SettingsError::InvalidValue)
+        }
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Gets the length of the settings fields in a settings block `version`.
+///
+/// Returns `0` for unknown versions.
+pub const fn payload_len(version: u8) -> usize {
+    match version {
+        1 => 3,
+        2 => 4,
+        3 => 10,
+        _ => 0,
+    }
+}
+
+/// Restores the [Settings] from a settings block read from the EEPROM.
+///
+/// Falls back to the defaults if the block is missing or invalid, and tells the caller whether
+/// the block should be written back with [to_bytes](Settings::to_bytes).
+pub fn restore(data: &[u8]) -> (Settings, Restored) {
+    match Settings::from_bytes(data) {
+        Ok(settings) if data[2] == SETTINGS_VERSION => (settings, Restored::Current),
+        Ok(settings) => (settings, Restored::Migrated),
+        Err(err) => (Settings::new(), Restored::Initialized(err)),
+    }
+}
+
+/// Tracks changes of the runtime state, to persist them to the settings block.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SettingsTracker {
+    saved: Settings,
+    pending: Option<(Settings, Timeout)>,
+}
+
+impl SettingsTracker {
+    /// Creates a new [SettingsTracker], with the `saved` settings from the EEPROM.
+    pub const fn new(saved: Settings) -> Self {
+        Self {
+            saved,
+            pending: None,
+        }
+    }
+
+    /// Gets the settings last written to the EEPROM.
+    pub const fn saved(&self) -> Settings {
+        self.saved
+    }
+
+    /// Updates the `current` settings at the time `now_ms`.
+    ///
+    /// Returns the settings to write to the EEPROM, once they differ from the saved settings, and
+    /// stayed unchanged for [PERSIST_DELAY_MS].
+    pub fn update(&mut self, current: Settings, now_ms: u16) -> Option<Settings> {
+        match self.pending {
+            _ if current == self.saved => {
+                self.pending = None;
+                None
+            }
+            Some((pending, timeout)) if pending == current => {
+                if timeout.is_expired(now_ms) {
+                    *self = Self::new(current);
+                    Some(current)
+                } else {
+                    None
+                }
+            }
+            _ => {
+                self.pending = Some((current, Timeout::started(now_ms, PERSIST_DELAY_MS)));
+                None
+            }
+        }
+    }
+}
+
+// Calculates the CRC of a settings block, over the version and the settings fields.
+fn block_crc(version: u8, payload: &[u8]) -> u16 {
+    crc16_update(crc16(&[version]), payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::features::Feature;
+
+    #[test]
+    fn test_settings() {
+        let settings = Settings::new()
+            .with_default_layer(2)
+            .with_debounce_ms(8)
+            .with_features(FeatureFlags::new().with_enabled(Feature::CapsWord, true))
+            .with_report_mode(ReportMode::Nkro)
+            .with_settle_us(45)
+            .with_tapping_term_ms(250)
+            .with_wheel_speed(2);
+        let bytes = settings.to_bytes();
+
+        assert_eq!(Settings::from_bytes(&bytes), Ok(settings));
+        assert_eq!(restore(&bytes), (settings, Restored::Current));
+        assert!(!Restored::Current.needs_write());
+
+        let mut corrupted = bytes;
+        corrupted[SETTINGS_HEADER_LEN + 1] ^= 1;
+        assert_eq!(
+            Settings::from_bytes(&corrupted),
+            Err(SettingsError::InvalidCrc)
+        );
+
+        let mut newer = bytes;
+        newer[2] = SETTINGS_VERSION + 1;
+        assert_eq!(
+            restore(&newer),
+            (
+                Settings::new(),
+                Restored::Initialized(SettingsError::InvalidVersion(SETTINGS_VERSION + 1))
+            )
+        );
+
+        let out_of_range = settings.with_tuning(Tuning::SettleUs, 5000).to_bytes();
+        assert_eq!(
+            Settings::from_bytes(&out_of_range),
+            Err(SettingsError::InvalidValue)
+        );
+
+        let (erased, restored) = restore(&[0xff; SETTINGS_LEN]);
+        assert_eq!(erased, Settings::new());
+        assert!(restored.needs_write());
+
+        assert_eq!(
+            Settings::from_bytes(&bytes[..SETTINGS_LEN - 1]),
+            Err(SettingsError::Truncated)
+        );
+        assert_eq!(
+            Settings::new()
+                .with_default_layer(NUM_LAYERS as u8)
+                .default_layer(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_settings_migration() {
+        let payload = [1, 8, Feature::MouseKeys.mask()];
+        let mut v1 = [0u8; SETTINGS_HEADER_LEN + 3];
+        v1[..3].copy_from_slice(&[b'T', b'S', 1]);
+        v1[3..SETTINGS_HEADER_LEN].copy_from_slice(&block_crc(1, &payload).to_le_bytes());
+        v1[SETTINGS_HEADER_LEN..].copy_from_slice(&payload);
+
+        let (settings, restored) = restore(&v1);
+        assert_eq!(restored, Restored::Migrated);
+        assert_eq!(settings.default_layer(), 1);
+        assert_eq!(settings.report_mode(), ReportMode::Boot);
+
+        let mut tracker = SettingsTracker::new(settings);
+        let nkro = settings.with_report_mode(ReportMode::Nkro);
+        assert_eq!(tracker.update(settings, 0), None);
+        assert_eq!(tracker.update(nkro, 10), None);
+        // a further change restarts the delay
+        let layer = nkro.with_default_layer(2);
+        assert_eq!(tracker.update(layer, 1000), None);
+        assert_eq!(tracker.update(layer, 1000 + PERSIST_DELAY_MS - 1), None);
+        assert_eq!(tracker.update(layer, 1000 + PERSIST_DELAY_MS), Some(layer));
+        assert_eq!(tracker.saved(), layer);
+        assert_eq!(tracker.update(layer, 5000), None);
+    }
+}
@@ -0,0 +1,238 @@
+//! Multiple reports on one HID interface.
+//!
+//! Every HID interface takes an endpoint, and the ATmega32u4 only has six besides the control
+//! endpoint. The shared interface sends the [N-key rollover](crate::nkro),
+//! [System Control](crate::system_control), [Consumer Control](crate::consumer), and
+//! [mouse](crate::mouse) reports under distinct report IDs, so the media and system keys, and
+//! pointing devices, only take one endpoint next to the boot keyboard interface.
+//!
+//! The boot keyboard report stays on its own interface without a report ID, since BIOS and UEFI
+//! hosts only understand the fixed boot layout.
+
+use crate::mouse::MouseReport;
+use crate::nkro::{NkroReport, NKRO_REPORT_LEN};
+
+/// Report ID of the N-key rollover report.
+pub const REPORT_ID_NKRO: u8 = 1;
+/// Report ID of the System Control report.
+pub const REPORT_ID_SYSTEM: u8 = 2;
+/// Report ID of the Consumer Control report.
+pub const REPORT_ID_CONSUMER: u8 = 3;
+/// Report ID of the mouse report.
+pub const REPORT_ID_MOUSE: u8 = 4;
+/// Maximum length of a [SharedReport], including the report ID.
+pub const MAX_SHARED_REPORT_LEN: usize = NKRO_REPORT_LEN + 1;
+
+/// HID report descriptor for the shared interface.
+pub const SHARED_REPORT_DESCRIPTOR: &[u8] = &[
+    0x05,
+    0x01, // Usage Page (Generic Desktop)
+    0x09,
+    0x06, // Usage (Keyboard)
+    0xa1,
+    0x01, // Collection (Application)
+    0x85,
+    REPORT_ID_NKRO, //   Report ID (1)
+    0x05,
+    0x07, //   Usage Page (Keyboard/Keypad)
+    0x19,
+    0x00, //   Usage Minimum (0)
+    0x29,
+    0xff, //   Usage Maximum (255)
+    0x15,
+    0x00, //   Logical Minimum (0)
+    0x25,
+    0x01, //   Logical Maximum (1)
+    0x75,
+    0x01, //   Report Size (1)
+    0x96,
+    0x00,
+    0x01, //   Report Count (256)
+    0x81,
+    0x02, //   Input (Data, Variable, Absolute)
+    0xc0, // End Collection
+    0x05,
+    0x01, // Usage Page (Generic Desktop)
+    0x09,
+    0x80, // Usage (System Control)
+    0xa1,
+    0x01, // Collection (Application)
+    0x85,
+    REPORT_ID_SYSTEM, //   Report ID (2)
+    0x19,
+    0x00, //   Usage Minimum (0)
+    0x29,
+    0xb7, //   Usage Maximum (0xb7)
+    0x15,
+    0x00, //   Logical Minimum (0)
+    0x26,
+    0xb7,
+    0x00, //   Logical Maximum (0xb7)
+    0x75,
+    0x08, //   Report Size (8)
+    0x95,
+    0x01, //   Report Count (1)
+    0x81,
+    0x00, //   Input (Data, Array, Absolute)
+    0xc0, // End Collection
+    0x05,
+    0x0c, // Usage Page (Consumer)
+    0x09,
+    0x01, // Usage (Consumer Control)
+    0xa1,
+    0x01, // Collection (Application)
+    0x85,
+    REPORT_ID_CONSUMER, //   Report ID (3)
+    0x19,
+    0x00, //   Usage Minimum (0)
+    0x2a,
+    0xff,
+    0x03, //   Usage Maximum (0x3ff)
+    0x15,
+    0x00, //   Logical Minimum (0)
+    0x26,
+    0xff,
+    0x03, //   Logical Maximum (0x3ff)
+    0x75,
+    0x10, //   Report Size (16)
+    0x95,
+    0x01, //   Report Count (1)
+    0x81,
+    0x00, //   Input (Data, Array, Absolute)
+    0xc0, // End Collection
+    0x05,
+    0x01, // Usage Page (Generic Desktop)
+    0x09,
+    0x02, // Usage (Mouse)
+    0xa1,
+    0x01, // Collection (Application)
+    0x85,
+    REPORT_ID_MOUSE, //   Report ID (4)
+    0x05,
+    0x09, //   Usage Page (Button)
+    0x19,
+    0x01, //   Usage Minimum (1)
+    0x29,
+    0x05, //   Usage Maximum (5)
+    0x15,
+    0x00, //   Logical Minimum (0)
+    0x25,
+    0x01, //   Logical Maximum (1)
+    0x75,
+    0x01, //   Report Size (1)
+    0x95,
+    0x05, //   Report Count (5)
+    0x81,
+    0x02, //   Input (Data, Variable, Absolute)
+    0x75,
+    0x03, //   Report Size (3)
+    0x95,
+    0x01, //   Report Count (1)
+    0x81,
+    0x01, //   Input (Constant)
+    0x05,
+    0x01, //   Usage Page (Generic Desktop)
+    0x09,
+    0x30, //   Usage (X)
+    0x09,
+    0x31, //   Usage (Y)
+    0x09,
+    0x38, //   Usage (Wheel)
+    0x15,
+    0x81, //   Logical Minimum (-127)
+    0x25,
+    0x7f, //   Logical Maximum (127)
+    0x75,
+    0x08, //   Report Size (8)
+    0x95,
+    0x03, //   Report Count (3)
+    0x81,
+    0x06, //   Input (Data, Variable, Relative)
+    0xc0, // End Collection
+];
+
+/// Represents a report on the shared interface, prefixed with its report ID.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SharedReport {
+    buf: [u8; MAX_SHARED_REPORT_LEN],
+    len: usize,
+}
+
+impl SharedReport {
+    /// Creates a new [SharedReport] from the `report_id`, and the report `data`.
+    ///
+    /// Data beyond [MAX_SHARED_REPORT_LEN] is cut off.
+    pub fn new(report_id: u8, data: &[u8]) -> Self {
+        let len = data.len().min(MAX_SHARED_REPORT_LEN - 1);
+        let mut buf = [0u8; MAX_SHARED_REPORT_LEN];
+
+        buf[0] = report_id;
+        buf[1..=len].copy_from_slice(&data[..len]);
+
+        Self { buf, len: len + 1 }
+    }
+
+    /// Creates a new [SharedReport] from an [NkroReport].
+    pub fn nkro(report: &NkroReport) -> Self {
+        Self::new(REPORT_ID_NKRO, report.as_bytes())
+    }
+
+    /// Creates a new [SharedReport] from a System Control usage.
+    pub fn system(usage: u8) -> Self {
+        Self::new(REPORT_ID_SYSTEM, &[usage])
+    }
+
+    /// Creates a new [SharedReport] from a Consumer Control usage.
+    pub fn consumer(usage: u16) -> Self {
+        Self::new(REPORT_ID_CONSUMER, &usage.to_le_bytes())
+    }
+
+    /// Creates a new [SharedReport] from a [MouseReport].
+    pub fn mouse(report: &MouseReport) -> Self {
+        Self::new(REPORT_ID_MOUSE, &report.to_bytes())
+    }
+
+    /// Gets the report ID.
+    pub const fn report_id(&self) -> u8 {
+        self.buf[0]
+    }
+
+    /// Gets the report bytes, starting with the report ID.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shared_report() {
+        assert_eq!(
+            SharedReport::system(0x83).as_bytes(),
+            [REPORT_ID_SYSTEM, 0x83]
+        );
+        assert_eq!(
+            SharedReport::consumer(0x0224).as_bytes(),
+            [REPORT_ID_CONSUMER, 0x24, 0x02]
+        );
+        assert_eq!(
+            SharedReport::mouse(&MouseReport::new().with_motion((1, -1))).as_bytes(),
+            [REPORT_ID_MOUSE, 0, 1, 0xff, 0]
+        );
+
+        let mut nkro_report = NkroReport::new();
+        nkro_report.set_usage(0x04, true);
+
+        let report = SharedReport::nkro(&nkro_report);
+        assert_eq!(report.report_id(), REPORT_ID_NKRO);
+        assert_eq!(report.as_bytes().len(), MAX_SHARED_REPORT_LEN);
+        assert_eq!(report.as_bytes()[1], 1 << 4);
+
+        assert_eq!(
+            SharedReport::new(4, &[0; 64]).as_bytes().len(),
+            MAX_SHARED_REPORT_LEN
+        );
+    }
+}
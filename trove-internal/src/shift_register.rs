@@ -0,0 +1,84 @@
+//! Bit layout of shift-register key matrices.
+//!
+//! Boards with more matrix lines than GPIO pins can drive the rows through chained 74HC595
+//! shift registers, and read the columns through chained 74HC165 shift registers, both clocked
+//! over SPI. Both chains are shifted most-significant bit first:
+//!
+//! - row `0` is output `QA` of the first 74HC595, the last bit shifted out
+//! - column `0` is input `H` of the first 74HC165, the first bit shifted in
+//!
+//! Rows and columns are active-low, like a col-to-row matrix wired to GPIO pins.
+
+use crate::debounce::{RowState, MAX_COLS};
+
+/// Maximum number of rows driven by a shift-register chain.
+pub const MAX_SHIFT_ROWS: usize = 16;
+/// Maximum number of bytes shifted out to select a row.
+pub const MAX_ROW_BYTES: usize = MAX_SHIFT_ROWS / 8;
+/// Maximum number of bytes shifted in to read the columns.
+pub const MAX_COL_BYTES: usize = MAX_COLS / 8;
+
+/// Gets the number of bytes shifted through a chain of `lines` outputs or inputs.
+pub const fn chain_bytes(lines: usize) -> usize {
+    lines.div_ceil(8)
+}
+
+/// Gets the bytes that drive the `row` low, and every other row high, for `rows` rows.
+///
+/// Only the first [chain_bytes] bytes are shifted out.
+pub fn row_select_bytes(row: usize, rows: usize) -> [u8; MAX_ROW_BYTES] {
+    let mut bytes = [0xff; MAX_ROW_BYTES];
+    let len = chain_bytes(rows).min(MAX_ROW_BYTES);
+
+    if row < rows && len > 0 {
+        // the first byte shifted out ends up in the last register of the chain
+        bytes[len - 1 - row / 8] &= !(1 << (row % 8));
+    }
+
+    bytes
+}
+
+/// Gets the pressed columns from the bytes shifted in from the column registers.
+pub fn cols_from_bytes(bytes: &[u8], cols: usize) -> RowState {
+    let mut hot_pins = RowState::new();
+
+    for j in 0..cols.min(MAX_COLS) {
+        let low = bytes
+            .get(j / 8)
+            .is_some_and(|byte| byte & (0x80 >> (j % 8)) == 0);
+
+        if low {
+            hot_pins.set_column(j, true);
+        }
+    }
+
+    hot_pins
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shift_register() {
+        assert_eq!(chain_bytes(4), 1);
+        assert_eq!(chain_bytes(12), 2);
+
+        assert_eq!(row_select_bytes(0, 4), [0b1111_1110, 0xff]);
+        assert_eq!(row_select_bytes(3, 4), [0b1111_0111, 0xff]);
+        // the second register is shifted out first
+        assert_eq!(row_select_bytes(9, 12), [0b1111_1101, 0xff]);
+        assert_eq!(row_select_bytes(0, 12), [0xff, 0b1111_1110]);
+        // out of range rows select nothing
+        assert_eq!(row_select_bytes(4, 4), [0xff; MAX_ROW_BYTES]);
+
+        assert!(cols_from_bytes(&[0xff, 0xff], 12).is_inactive());
+        assert_eq!(
+            cols_from_bytes(&[0b0111_1111, 0b1110_1111], 12),
+            RowState::from_u16((1 << 0) | (1 << 11))
+        );
+        // unused inputs beyond the columns are ignored
+        assert!(cols_from_bytes(&[0xff, 0x00], 12).column(11));
+        assert!(!cols_from_bytes(&[0xff, 0x00], 12).column(12));
+    }
+}
@@ -0,0 +1,238 @@
+//! Configuration protocol simulator for host-side development.
+//!
+//! Runs the same command handlers as the firmware against an in-memory EEPROM, so configurator
+//! developers can build and test host tools without hardware attached.
+//!
+//! The [SimDevice] can be driven directly with [handle](SimDevice::handle), or over any byte
+//! stream (e.g. a TCP socket or a pseudo-terminal) with [serve](SimDevice::serve), using the
+//! frames:
+//!
+//! ```text
+//! request:  | length | command | args ... |
+//! response: | status | length | data ... |
+//! ```
+//!
+//! The request length counts the command and argument bytes. The response status is `0` on
+//! success, or a [ConfigError] [code](ConfigError::code).
+
+use std::io::{self, Read, Write};
+
+use crate::config::{self, Command, ConfigError};
+use crate::lint;
+use crate::rgb_map::{self, RgbMap};
+use crate::storage::EepromStorage;
+
+pub use crate::rgb_map::RGB_MAP_OFFSET;
+pub use crate::storage::EEPROM_LEN;
+
+/// Maximum length of a response payload.
+pub const MAX_RESPONSE_LEN: usize = u8::MAX as usize;
+
+/// In-memory EEPROM, erased to `0xff` like the real device.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimEeprom {
+    data: [u8; EEPROM_LEN],
+}
+
+impl SimEeprom {
+    /// Creates a new, erased [SimEeprom].
+    pub const fn new() -> Self {
+        Self {
+            data: [0xff; EEPROM_LEN],
+        }
+    }
+
+    /// Gets the EEPROM contents.
+    pub fn as_bytes(&self) -> &[u8] {
+        self.data.as_ref()
+    }
+
+    /// Reads `len` bytes starting at the `offset`.
+    pub fn read(&self, offset: usize, len: usize) -> Result<&[u8], ConfigError> {
+        self.data
+            .get(offset..offset.saturating_add(len))
+            .ok_or(ConfigError::InvalidArgument)
+    }
+
+    /// Writes the `data` starting at the `offset`.
+    pub fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), ConfigError> {
+        self.data
+            .get_mut(offset..offset.saturating_add(data.len()))
+            .ok_or(ConfigError::InvalidArgument)?
+            .copy_from_slice(data);
+
+        Ok(())
+    }
+}
+
+impl Default for SimEeprom {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EepromStorage for SimEeprom {
+    fn load(&self, offset: usize, buf: &mut [u8]) -> Result<(), ConfigError> {
+        buf.copy_from_slice(self.read(offset, buf.len())?);
+        Ok(())
+    }
+
+    fn store(&mut self, offset: usize, data: &[u8]) -> Result<(), ConfigError> {
+        self.write(offset, data)
+    }
+}
+
+/// Simulated keyboard that answers configuration protocol commands.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimDevice {
+    eeprom: SimEeprom,
+}
+
+impl SimDevice {
+    /// Creates a new [SimDevice] with an erased EEPROM.
+    pub fn new() -> Self {
+        Self::from_eeprom(SimEeprom::new())
+    }
+
+    /// Creates a new [SimDevice] from existing EEPROM contents.
+    ///
+    /// Settings that are missing or invalid in the EEPROM fall back to their defaults.
+    pub const fn from_eeprom(eeprom: SimEeprom) -> Self {
+        Self { eeprom }
+    }
+
+    /// Gets the simulated EEPROM.
+    pub fn eeprom(&self) -> &SimEeprom {
+        &self.eeprom
+    }
+
+    /// Gets the color map stored in the EEPROM.
+    pub fn rgb_map(&self) -> RgbMap {
+        rgb_map::load_rgb_map(&self.eeprom)
+    }
+
+    /// Handles a `| command | args ... |` request.
+    ///
+    /// Writes the response into the buffer, and returns the number of bytes written. Commands
+    /// that change settings persist them to the EEPROM.
+    pub fn handle(&mut self, request: &[u8], buf: &mut [u8]) -> Result<usize, ConfigError> {
+        let (&command, args) = request.split_first().ok_or(ConfigError::InvalidArgument)?;
+        let command = Command::try_from(command)?;
+
+        match command {
+            Command::Lint => lint::handle_lint_command(buf),
+            // the simulated keyboard has no bootloader to reboot into
+            Command::EnterBootloader => Ok(0),
+            _ => config::handle_command(&mut self.eeprom, command, args, buf),
+        }
+    }
+
+    /// Serves framed requests from the `stream` until it is closed.
+    pub fn serve<S: Read + Write>(&mut self, stream: &mut S) -> io::Result<()> {
+        let mut request = [0u8; u8::MAX as usize];
+        let mut response = [0u8; 2 + MAX_RESPONSE_LEN];
+
+        loop {
+            let mut len = [0u8; 1];
+
+            match stream.read_exact(&mut len) {
+                Ok(()) => (),
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(err) => return Err(err),
+            }
+
+            let request = &mut request[..len[0] as usize];
+            stream.read_exact(request)?;
+
+            let (header, data) = response.split_at_mut(2);
+
+            let written = match self.handle(request, data) {
+                Ok(written) => {
+                    header.copy_from_slice(&[0, written as u8]);
+                    written
+                }
+                Err(err) => {
+                    header.copy_from_slice(&[err.code(), 0]);
+                    0
+                }
+            };
+
+            stream.write_all(&response[..2 + written])?;
+            stream.flush()?;
+        }
+    }
+}
+
+impl Default for SimDevice {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use std::vec::Vec;
+
+    use super::*;
+
+    /// Byte stream with separate request input and response output.
+    struct Pipe {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for Pipe {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for Pipe {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_sim_device() {
+        let mut device = SimDevice::new();
+        let mut buf = [0u8; 8];
+
+        assert_eq!(device.handle(&[0x05, 2, 0xff, 0, 0], &mut buf), Ok(0));
+        assert_eq!(device.handle(&[0x03, 1, 12, 2], &mut buf), Ok(0));
+        assert_eq!(device.handle(&[0x02, 1, 12], &mut buf), Ok(4));
+        assert_eq!(buf[..4], [2, 0xff, 0, 0]);
+
+        assert_eq!(
+            device.handle(&[0xee], &mut buf),
+            Err(ConfigError::InvalidCommand(0xee))
+        );
+        assert_eq!(
+            device.handle(&[], &mut buf),
+            Err(ConfigError::InvalidArgument)
+        );
+
+        // settings survive a "power cycle"
+        let restored = SimDevice::from_eeprom(device.eeprom().clone());
+        assert_eq!(restored.rgb_map(), device.rgb_map());
+        assert_eq!(SimDevice::new().rgb_map(), RgbMap::new());
+    }
+
+    #[test]
+    fn test_sim_serve() {
+        let mut device = SimDevice::new();
+        let mut pipe = Pipe {
+            input: Cursor::new([2, 0x04, 0, 1, 0xee].to_vec()),
+            output: Vec::new(),
+        };
+
+        device.serve(&mut pipe).unwrap();
+
+        assert_eq!(pipe.output, [0, 3, 0, 0, 0, 1, 0]);
+    }
+}
@@ -0,0 +1,211 @@
+//! Software PWM.
+//!
+//! The hardware PWM pins of the ATmega32u4 are mostly taken by the key matrix, so LED brightness
+//! (e.g. a backlight) and buzzer volume are dimmed in software instead. A spare timer interrupt
+//! calls [step](SoftPwm::step) [PWM_STEPS] times per PWM period, and writes the returned
+//! [PwmLevels] to the port registers. Each channel is on for the first `duty / 256` of the
+//! period.
+
+use crate::pin_map::{PinId, PinMap, NUM_PORTS};
+
+/// Maximum number of PWM channels.
+pub const MAX_PWM_CHANNELS: usize = 4;
+/// Number of timer steps per PWM period, the resolution of the duty cycle.
+pub const PWM_STEPS: u8 = 32;
+/// PWM frequency in Hz, fast enough to not flicker.
+pub const PWM_FREQUENCY_HZ: u16 = 200;
+/// Duty cycle of a fully driven channel.
+pub const MAX_DUTY: u8 = u8::MAX;
+
+/// Represents a PWM output pin.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PwmChannel {
+    pin: PinId,
+    active_low: bool,
+}
+
+impl PwmChannel {
+    /// Creates a new [PwmChannel], with the output on while the `pin` is driven high.
+    pub const fn new(pin: PinId) -> Self {
+        Self {
+            pin,
+            active_low: false,
+        }
+    }
+
+    /// Gets the pin of the channel.
+    pub const fn pin(&self) -> PinId {
+        self.pin
+    }
+
+    /// Gets whether the output is on while the pin is driven low.
+    pub const fn active_low(&self) -> bool {
+        self.active_low
+    }
+
+    /// Builder function that sets whether the output is on while the pin is driven low.
+    pub const fn with_active_low(mut self, val: bool) -> Self {
+        self.active_low = val;
+        self
+    }
+
+    /// Gets whether the pin is usable for the channel with the key matrix [PinMap].
+    ///
+    /// Pins of the key matrix, and pins not bonded out, are never driven.
+    pub fn is_valid(&self, map: &PinMap) -> bool {
+        self.pin.is_available() && !map.contains(&self.pin)
+    }
+}
+
+/// Pin levels of the PWM channels, per port.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PwmLevels {
+    mask: [u8; NUM_PORTS],
+    high: [u8; NUM_PORTS],
+}
+
+impl PwmLevels {
+    /// Gets the bits of the PWM pins in each port.
+    pub const fn mask(&self) -> &[u8; NUM_PORTS] {
+        &self.mask
+    }
+
+    /// Gets the bits of the PWM pins driven high in each port.
+    pub const fn high(&self) -> &[u8; NUM_PORTS] {
+        &self.high
+    }
+
+    /// Applies the levels to a port register value, leaving the other pins alone.
+    pub const fn apply(&self, port: usize, val: u8) -> u8 {
+        (val & !self.mask[port]) | self.high[port]
+    }
+
+    // Sets the level of the `pin`.
+    fn set(&mut self, pin: PinId, high: bool) {
+        let port = pin.port() as usize;
+
+        self.mask[port] |= 1 << pin.bit();
+        if high {
+            self.high[port] |= 1 << pin.bit();
+        }
+    }
+}
+
+/// Drives up to [MAX_PWM_CHANNELS] [PwmChannel]s with a duty cycle each.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SoftPwm {
+    channels: [Option<PwmChannel>; MAX_PWM_CHANNELS],
+    duty: [u8; MAX_PWM_CHANNELS],
+    phase: u8,
+}
+
+impl SoftPwm {
+    /// Creates a new [SoftPwm] for the valid `channels`, with every output off.
+    ///
+    /// Channels on key matrix pins are left out, see [is_valid](PwmChannel::is_valid). At most
+    /// [MAX_PWM_CHANNELS] are driven.
+    pub fn new(channels: &[PwmChannel], map: &PinMap) -> Self {
+        let mut valid = [None; MAX_PWM_CHANNELS];
+
+        for (slot, channel) in valid
+            .iter_mut()
+            .zip(channels.iter().filter(|c| c.is_valid(map)))
+        {
+            *slot = Some(*channel);
+        }
+
+        Self {
+            channels: valid,
+            duty: [0; MAX_PWM_CHANNELS],
+            phase: 0,
+        }
+    }
+
+    /// Gets the driven channels.
+    pub fn channels(&self) -> impl Iterator<Item = &PwmChannel> {
+        self.channels.iter().flatten()
+    }
+
+    /// Gets the duty cycle of a channel, from `0` (off) to [MAX_DUTY] (on).
+    pub fn duty(&self, channel: usize) -> Option<u8> {
+        self.channels
+            .get(channel)
+            .copied()
+            .flatten()
+            .map(|_| self.duty[channel])
+    }
+
+    /// Sets the duty cycle of a channel, from `0` (off) to [MAX_DUTY] (on).
+    ///
+    /// Returns `false` if the channel is not driven.
+    pub fn set_duty(&mut self, channel: usize, duty: u8) -> bool {
+        let driven = self.duty(channel).is_some();
+
+        if driven {
+            self.duty[channel] = duty;
+        }
+
+        driven
+    }
+
+    /// Gets the pin levels at the current step of the period.
+    pub fn levels(&self) -> PwmLevels {
+        let mut levels = PwmLevels::default();
+        let threshold = self.phase as u16 * (MAX_DUTY as u16 + 1) / PWM_STEPS as u16;
+
+        for (channel, &duty) in self.channels.iter().zip(self.duty.iter()) {
+            if let Some(channel) = channel {
+                let on = (duty as u16) > threshold;
+                levels.set(channel.pin(), on != channel.active_low());
+            }
+        }
+
+        levels
+    }
+
+    /// Advances one step of the period.
+    ///
+    /// Returns the pin levels to drive until the next step.
+    pub fn step(&mut self) -> PwmLevels {
+        let levels = self.levels();
+        self.phase = (self.phase + 1) % PWM_STEPS;
+
+        levels
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pin_map::Port;
+
+    #[test]
+    fn test_soft_pwm() {
+        let led = PwmChannel::new(PinId::new(Port::B, 7));
+        let buzzer = PwmChannel::new(PinId::new(Port::D, 0)).with_active_low(true);
+        // a key matrix pin of the Atreus
+        let matrix = PwmChannel::new(PinMap::atreus().rows()[0]);
+
+        let mut pwm = SoftPwm::new(&[led, matrix, buzzer], &PinMap::atreus());
+        assert_eq!(pwm.channels().count(), 2);
+        assert!(pwm.set_duty(0, 64));
+        assert!(pwm.set_duty(1, MAX_DUTY));
+        assert!(!pwm.set_duty(2, 1));
+
+        let mut led_steps = 0;
+        for _ in 0..PWM_STEPS {
+            let levels = pwm.step();
+            assert_eq!(levels.mask()[Port::B as usize], 1 << 7);
+            // the active-low buzzer is always on
+            assert_eq!(levels.high()[Port::D as usize], 0);
+
+            if levels.high()[Port::B as usize] != 0 {
+                led_steps += 1;
+            }
+        }
+        assert_eq!(led_steps, PWM_STEPS / 4);
+
+        pwm.set_duty(0, 0);
+        assert_eq!(pwm.levels().apply(Port::B as usize, 0xff), 0x7f);
+    }
+}
@@ -0,0 +1,173 @@
+//! SPI bus settings.
+//!
+//! Shift registers, optical sensors, and displays share the hardware SPI bus, each with its own
+//! clock mode and speed. A [SpiConfig] holds the settings of one device, and converts them into
+//! the SPI control register values of the ATmega32u4, so the bus can be reconfigured between
+//! devices.
+
+/// Bits of the SPI control register (`SPCR`).
+const SPE: u8 = 1 << 6;
+const DORD: u8 = 1 << 5;
+const MSTR: u8 = 1 << 4;
+const CPOL: u8 = 1 << 3;
+const CPHA: u8 = 1 << 2;
+
+/// Default SPI clock frequency.
+pub const DEFAULT_SPI_CLOCK_HZ: u32 = 1_000_000;
+
+/// Represents the clock polarity and phase of an SPI device.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SpiMode {
+    /// Clock idles low, data is sampled on the rising edge.
+    #[default]
+    Mode0,
+    /// Clock idles low, data is sampled on the falling edge.
+    Mode1,
+    /// Clock idles high, data is sampled on the falling edge.
+    Mode2,
+    /// Clock idles high, data is sampled on the rising edge.
+    Mode3,
+}
+
+impl SpiMode {
+    /// Gets whether the clock idles high.
+    pub const fn cpol(&self) -> bool {
+        matches!(self, Self::Mode2 | Self::Mode3)
+    }
+
+    /// Gets whether data is sampled on the trailing clock edge.
+    pub const fn cpha(&self) -> bool {
+        matches!(self, Self::Mode1 | Self::Mode3)
+    }
+}
+
+/// Clock mode, speed, and bit order of an SPI device.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpiConfig {
+    mode: SpiMode,
+    clock_hz: u32,
+    lsb_first: bool,
+}
+
+impl SpiConfig {
+    /// Creates a new [SpiConfig], in [SpiMode::Mode0] at the [DEFAULT_SPI_CLOCK_HZ], most
+    /// significant bit first.
+    pub const fn new() -> Self {
+        Self {
+            mode: SpiMode::Mode0,
+            clock_hz: DEFAULT_SPI_CLOCK_HZ,
+            lsb_first: false,
+        }
+    }
+
+    /// Gets the [SpiMode].
+    pub const fn mode(&self) -> SpiMode {
+        self.mode
+    }
+
+    /// Builder function that sets the [SpiMode].
+    pub const fn with_mode(mut self, mode: SpiMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Gets the highest clock frequency of the device.
+    pub const fn clock_hz(&self) -> u32 {
+        self.clock_hz
+    }
+
+    /// Builder function that sets the highest clock frequency of the device.
+    pub const fn with_clock_hz(mut self, val: u32) -> Self {
+        self.clock_hz = val;
+        self
+    }
+
+    /// Gets whether the least significant bit is sent first.
+    pub const fn lsb_first(&self) -> bool {
+        self.lsb_first
+    }
+
+    /// Builder function that sets whether the least significant bit is sent first.
+    pub const fn with_lsb_first(mut self, val: bool) -> Self {
+        self.lsb_first = val;
+        self
+    }
+
+    /// Gets the clock divider of the CPU clock `f_cpu`, the smallest one not above the device
+    /// clock.
+    ///
+    /// Returns the largest divider (`128`) for devices slower than that.
+    pub const fn divider(&self, f_cpu: u32) -> u8 {
+        let mut divider = 2u32;
+
+        while divider < 128 && f_cpu / divider > self.clock_hz {
+            divider *= 2;
+        }
+
+        divider as u8
+    }
+
+    /// Gets the SPI control register (`SPCR`) value for a master at the CPU clock `f_cpu`.
+    pub const fn spcr(&self, f_cpu: u32) -> u8 {
+        let mut spcr = SPE | MSTR;
+
+        if self.lsb_first {
+            spcr |= DORD;
+        }
+        if self.mode.cpol() {
+            spcr |= CPOL;
+        }
+        if self.mode.cpha() {
+            spcr |= CPHA;
+        }
+
+        // SPR bits: divider 4, 16, 64, and 128, halved by the double speed bit
+        spcr | match self.divider(f_cpu) {
+            2 | 4 => 0b00,
+            8 | 16 => 0b01,
+            32 | 64 => 0b10,
+            _ => 0b11,
+        }
+    }
+
+    /// Gets whether the double speed bit (`SPI2X`) is set for the CPU clock `f_cpu`.
+    pub const fn double_speed(&self, f_cpu: u32) -> bool {
+        matches!(self.divider(f_cpu), 2 | 8 | 32)
+    }
+}
+
+impl Default for SpiConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const F_CPU: u32 = 16_000_000;
+
+    #[test]
+    fn test_spi_config() {
+        let config = SpiConfig::new();
+        assert_eq!(config.divider(F_CPU), 16);
+        assert_eq!(config.spcr(F_CPU), SPE | MSTR | 0b01);
+        assert!(!config.double_speed(F_CPU));
+
+        let config = SpiConfig::new()
+            .with_mode(SpiMode::Mode3)
+            .with_clock_hz(2_000_000)
+            .with_lsb_first(true);
+        assert_eq!(config.divider(F_CPU), 8);
+        assert_eq!(config.spcr(F_CPU), SPE | MSTR | DORD | CPOL | CPHA | 0b01);
+        assert!(config.double_speed(F_CPU));
+
+        assert_eq!(SpiConfig::new().with_clock_hz(u32::MAX).divider(F_CPU), 2);
+        assert_eq!(SpiConfig::new().with_clock_hz(1).divider(F_CPU), 128);
+        assert_eq!(
+            SpiConfig::new().with_clock_hz(1).spcr(F_CPU),
+            SPE | MSTR | 0b11
+        );
+    }
+}
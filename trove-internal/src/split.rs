@@ -0,0 +1,513 @@
+//! Matrix state link between two keyboards.
+//!
+//! Two boards can be combined into one split keyboard by having the secondary board send its
+//! matrix state to the USB-connected primary board over a serial link. Both halves run the same
+//! firmware, and pick their [SplitRole] at boot, from whether they are powered over USB.
+//!
+//! The primary board scans [SPLIT_ROWS] rows: the rows of the left half, then the rows of the
+//! right half, see [merge_halves]. The key map is laid out as one `ROWS` x [SPLIT_COLS] logical
+//! matrix, with the right half as additional columns to the right of the left half, see
+//! [SplitKeymap]. [SplitAtreusKeymap] is the default key map of two linked Atreus boards.
+//!
+//! Each link frame has the layout:
+//!
+//! ```text
+//! | sync (0xa5) | row 0 (u16 LE) | ... | row N (u16 LE) | checksum |
+//! ```
+//!
+//! The checksum is the XOR of the sync byte and every row byte.
+
+use core::marker::PhantomData;
+
+use crate::debounce::RowState;
+use crate::diagnostics::{self, Diagnostic};
+use crate::layers::{
+    self, AtreusKeymap, Keymap, LayerKeys, BKSP, CALC, COLS, DEL, D_ARROW, END, F1, F10, F11, F12,
+    F2, F3, F4, F5, F6, F7, F8, F9, FUN, HOME, INS, KP0, KP1, KP2, KP3, KP4, KP5, KP6, KP7, KP8,
+    KP9, KP_DOT, KP_ENTER, KP_EQUAL, KP_MINUS, KP_PLUS, KP_SLASH, KP_STAR, L_ARROW, MD_MUTE,
+    MD_NEXT, MD_PREV, MD_STOP, MD_VOL_DN, MD_VOL_UP, NUM_LAYERS, NUM_LOCK, PGDN, PGUP, PLAY_PS,
+    PRT_SC, ROWS, R_ARROW, SCR_LK, TRANS, U_ARROW, WWW_BACK, WWW_FWD, WWW_HOME,
+};
+
+/// Sync byte at the start of every link frame.
+pub const LINK_SYNC: u8 = 0xa5;
+/// Length of a serialized link frame.
+pub const LINK_FRAME_LEN: usize = 2 + ROWS * 2;
+/// Number of rows scanned by the primary board, the rows of both halves.
+pub const SPLIT_ROWS: usize = 2 * ROWS;
+/// Number of columns of the logical key map of both halves.
+pub const SPLIT_COLS: usize = 2 * COLS;
+/// Time (in milliseconds) without a valid link frame before the keys of the secondary board are
+/// released, e.g. after the cable was unplugged.
+pub const LINK_TIMEOUT_MS: u16 = 100;
+
+/// Represents the role of a board in a split keyboard.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SplitRole {
+    /// USB-connected board, merging both halves into the reports.
+    #[default]
+    Primary,
+    /// Board sending its matrix state to the primary board.
+    Secondary,
+}
+
+impl SplitRole {
+    /// Selects the [SplitRole] of a board: the board powered over USB is the primary.
+    pub const fn from_usb_powered(usb_powered: bool) -> Self {
+        if usb_powered {
+            Self::Primary
+        } else {
+            Self::Secondary
+        }
+    }
+}
+
+/// Represents the half of a split keyboard a board is.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum SplitHand {
+    /// Left half, the first [COLS] logical columns.
+    #[default]
+    Left,
+    /// Right half, the last [COLS] logical columns.
+    Right,
+}
+
+impl SplitHand {
+    /// Selects the [SplitHand] of a board with two identical halves: the primary board is the left
+    /// half, so either board can be plugged in.
+    pub const fn from_role(role: SplitRole) -> Self {
+        match role {
+            SplitRole::Primary => Self::Left,
+            SplitRole::Secondary => Self::Right,
+        }
+    }
+}
+
+/// Raw matrix state of a board, one `u16` column bitfield per row.
+pub type LinkRows = [u16; ROWS];
+
+/// Serializes the matrix state into a link frame.
+pub fn encode_link_frame(rows: &LinkRows) -> [u8; LINK_FRAME_LEN] {
+    let mut frame = [0u8; LINK_FRAME_LEN];
+
+    frame[0] = LINK_SYNC;
+
+    for (chunk, row) in frame[1..].chunks_exact_mut(2).zip(rows.iter()) {
+        chunk.copy_from_slice(row.to_le_bytes().as_ref());
+    }
+
+    frame[LINK_FRAME_LEN - 1] = checksum(&frame[..LINK_FRAME_LEN - 1]);
+
+    frame
+}
+
+/// Gets the matrix state sent over the link for the scanned `rows` of a board.
+pub fn link_rows(rows: &[RowState; ROWS]) -> LinkRows {
+    rows.map(u16::from)
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |acc, b| acc ^ b)
+}
+
+/// Byte-by-byte decoder for link frames received over a serial link.
+///
+/// Corrupted frames are dropped, and the decoder resynchronizes on the next sync byte.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LinkDecoder {
+    buf: [u8; LINK_FRAME_LEN],
+    len: usize,
+    errors: u16,
+}
+
+impl LinkDecoder {
+    /// Creates a new [LinkDecoder].
+    pub const fn new() -> Self {
+        Self {
+            buf: [0; LINK_FRAME_LEN],
+            len: 0,
+            errors: 0,
+        }
+    }
+
+    /// Gets the number of dropped frames due to checksum errors.
+    pub const fn errors(&self) -> u16 {
+        self.errors
+    }
+
+    /// Pushes a received byte into the decoder.
+    ///
+    /// Returns the matrix state when a complete, valid frame has been received.
+    pub fn push(&mut self, byte: u8) -> Option<LinkRows> {
+        if self.len == 0 && byte != LINK_SYNC {
+            return None;
+        }
+
+        self.buf[self.len] = byte;
+        self.len += 1;
+
+        if self.len < LINK_FRAME_LEN {
+            return None;
+        }
+
+        self.len = 0;
+
+        if checksum(&self.buf[..LINK_FRAME_LEN - 1]) != self.buf[LINK_FRAME_LEN - 1] {
+            self.errors = self.errors.saturating_add(1);
+            return None;
+        }
+
+        let mut rows = [0u16; ROWS];
+        for (row, chunk) in rows.iter_mut().zip(self.buf[1..].chunks_exact(2)) {
+            *row = u16::from_le_bytes([chunk[0], chunk[1]]);
+        }
+
+        Some(rows)
+    }
+}
+
+/// Serves the matrix state of the secondary board, one byte at a time, to the primary board
+/// reading it over the link.
+///
+/// The matrix state is [published](Self::publish) after every scan, and each read of the primary
+/// board gets the frame published last before the read started.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LinkTransmitter {
+    next: [u8; LINK_FRAME_LEN],
+    frame: [u8; LINK_FRAME_LEN],
+    pos: usize,
+}
+
+impl LinkTransmitter {
+    /// Creates a new [LinkTransmitter].
+    ///
+    /// Until the first matrix state is published, reads get an invalid frame, which the primary
+    /// board drops.
+    pub const fn new() -> Self {
+        Self {
+            next: [0; LINK_FRAME_LEN],
+            frame: [0; LINK_FRAME_LEN],
+            pos: 0,
+        }
+    }
+
+    /// Publishes the matrix state `rows` for the next read.
+    pub fn publish(&mut self, rows: &LinkRows) {
+        self.next = encode_link_frame(rows);
+    }
+
+    /// Starts a read of the primary board, and gets the first byte to send.
+    pub fn start(&mut self) -> u8 {
+        self.frame = self.next;
+        self.pos = 0;
+
+        self.next_byte()
+    }
+
+    /// Gets the next byte to send, `0xff` past the end of the frame.
+    pub fn next_byte(&mut self) -> u8 {
+        match self.frame.get(self.pos) {
+            Some(&byte) => {
+                self.pos += 1;
+                byte
+            }
+            None => 0xff,
+        }
+    }
+}
+
+impl Default for LinkTransmitter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Receives the matrix state of the secondary board, on the primary board.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LinkReceiver {
+    decoder: LinkDecoder,
+    rows: LinkRows,
+    last_frame: Option<u16>,
+}
+
+impl LinkReceiver {
+    /// Creates a new [LinkReceiver], with every key of the secondary board released.
+    pub const fn new() -> Self {
+        Self {
+            decoder: LinkDecoder::new(),
+            rows: [0; ROWS],
+            last_frame: None,
+        }
+    }
+
+    /// Gets the number of dropped frames due to checksum errors.
+    pub const fn errors(&self) -> u16 {
+        self.decoder.errors()
+    }
+
+    /// Gets whether a valid frame was received within [LINK_TIMEOUT_MS] of the time `now_ms`.
+    pub fn connected(&self, now_ms: u16) -> bool {
+        self.last_frame
+            .is_some_and(|last| now_ms.wrapping_sub(last) < LINK_TIMEOUT_MS)
+    }
+
+    /// Pushes a byte received at the time `now_ms`.
+    pub fn push(&mut self, byte: u8, now_ms: u16) {
+        if let Some(rows) = self.decoder.push(byte) {
+            self.rows = rows;
+            self.last_frame = Some(now_ms);
+        }
+    }
+
+    /// Gets the matrix state of the secondary board at the time `now_ms`.
+    ///
+    /// Every key is released while the link is not [connected](Self::connected).
+    pub fn rows(&mut self, now_ms: u16) -> [RowState; ROWS] {
+        if !self.connected(now_ms) {
+            self.rows = [0; ROWS];
+            self.last_frame = None;
+        }
+
+        self.rows.map(RowState::from_u16)
+    }
+}
+
+/// Merges the matrix states of the `local` and `remote` boards into the [SPLIT_ROWS] rows scanned
+/// by the primary board, the rows of the left half first.
+pub fn merge_halves(
+    local: &[RowState; ROWS],
+    remote: &[RowState; ROWS],
+    hand: SplitHand,
+) -> [RowState; SPLIT_ROWS] {
+    let (left, right) = match hand {
+        SplitHand::Left => (local, remote),
+        SplitHand::Right => (remote, local),
+    };
+
+    let mut rows = [RowState::new(); SPLIT_ROWS];
+    rows[..ROWS].copy_from_slice(left.as_ref());
+    rows[ROWS..].copy_from_slice(right.as_ref());
+
+    rows
+}
+
+/// Gets the logical `(row, col)` of the key at a `row` and `col` scanned by the primary board.
+pub const fn logical_position(row: usize, col: usize) -> (usize, usize) {
+    (row % ROWS, col + (row / ROWS) * COLS)
+}
+
+/// [Keymap] of the rows scanned by the primary board, looking keys up in a `ROWS` x
+/// [SPLIT_COLS] logical key map `K` of both halves.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SplitKeymap<K>(PhantomData<K>);
+
+impl<K: Keymap<ROWS, SPLIT_COLS>> Keymap<SPLIT_ROWS, COLS> for SplitKeymap<K> {
+    const LAYERS: usize = K::LAYERS;
+
+    fn layer_key(layer: usize, row: usize, col: usize) -> u8 {
+        let (row, col) = logical_position(row, col);
+
+        K::layer_key(layer, row, col)
+    }
+
+    fn passthrough_key(layer: usize, row: usize, col: usize) -> u8 {
+        let (row, col) = logical_position(row, col);
+
+        K::passthrough_key(layer, row, col)
+    }
+}
+
+/// Base layer of the right half on the default split layout, with function keys, navigation, and
+/// a keypad.
+#[rustfmt::skip]
+const RIGHT_LAYER0_KEYS: LayerKeys = [
+    [ F1,      F2,      F3,      F4,      F5,     0,         0,  NUM_LOCK, KP7, KP8,    KP9, KP_MINUS ],
+    [ F6,      F7,      F8,      F9,      F10,    0,         0,  KP_SLASH, KP4, KP5,    KP6,  KP_PLUS ],
+    [ F11,     F12,     INS,     HOME,    PGUP, PRT_SC, SCR_LK,   KP_STAR, KP1, KP2,    KP3, KP_ENTER ],
+    [ L_ARROW, D_ARROW, U_ARROW, R_ARROW, DEL,  END,      PGDN,      BKSP, FUN, KP0, KP_DOT, KP_EQUAL ],
+];
+
+/// Function layer of the right half on the default split layout, with media keys.
+#[rustfmt::skip]
+const RIGHT_LAYER1_KEYS: LayerKeys = [
+    [ PLAY_PS,   MD_PREV,   MD_NEXT,  MD_STOP, MD_MUTE,  0,         0,  TRANS, TRANS, TRANS, TRANS, TRANS ],
+    [ MD_VOL_DN, MD_VOL_UP, WWW_BACK, WWW_FWD, WWW_HOME, 0,         0,  TRANS, TRANS, TRANS, TRANS, TRANS ],
+    [ CALC,      TRANS,     TRANS,    TRANS,   TRANS,    TRANS, TRANS,  TRANS, TRANS, TRANS, TRANS, TRANS ],
+    [ TRANS,     TRANS,     TRANS,    TRANS,   TRANS,    TRANS, TRANS,  TRANS, TRANS, TRANS, TRANS, TRANS ],
+];
+
+/// Upper and numpad layers of the right half on the default split layout, passing through to the
+/// lower layers.
+#[rustfmt::skip]
+const RIGHT_TRANS_KEYS: LayerKeys = [
+    [ TRANS, TRANS, TRANS, TRANS, TRANS, 0,         0,  TRANS, TRANS, TRANS, TRANS, TRANS ],
+    [ TRANS, TRANS, TRANS, TRANS, TRANS, 0,         0,  TRANS, TRANS, TRANS, TRANS, TRANS ],
+    [ TRANS, TRANS, TRANS, TRANS, TRANS, TRANS, TRANS,  TRANS, TRANS, TRANS, TRANS, TRANS ],
+    [ TRANS, TRANS, TRANS, TRANS, TRANS, TRANS, TRANS,  TRANS, TRANS, TRANS, TRANS, TRANS ],
+];
+
+#[cfg(target_arch = "avr")]
+avr_progmem::progmem! {
+    /// Layers of the right half on the default split layout.
+    static progmem RIGHT_LAYERS: [LayerKeys; NUM_LAYERS] = [RIGHT_LAYER0_KEYS, RIGHT_LAYER1_KEYS, RIGHT_TRANS_KEYS, RIGHT_TRANS_KEYS];
+}
+
+/// Layers of the right half on the default split layout.
+#[cfg(not(target_arch = "avr"))]
+static RIGHT_LAYERS: [LayerKeys; NUM_LAYERS] = [
+    RIGHT_LAYER0_KEYS,
+    RIGHT_LAYER1_KEYS,
+    RIGHT_TRANS_KEYS,
+    RIGHT_TRANS_KEYS,
+];
+
+/// [Keymap] of the default split layout of two linked Atreus boards.
+///
+/// The left half has the default Atreus layers, see [AtreusKeymap], and the right half its own
+/// layers, with function keys, navigation, media keys, and a keypad.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct SplitAtreusKeymap;
+
+impl Keymap<ROWS, SPLIT_COLS> for SplitAtreusKeymap {
+    fn layer_key(layer: usize, row: usize, col: usize) -> u8 {
+        if col < COLS {
+            return AtreusKeymap::layer_key(layer, row, col);
+        }
+
+        if row >= ROWS || col >= SPLIT_COLS {
+            diagnostics::record(Diagnostic::InvalidKey);
+            return 0;
+        }
+
+        let layer = layers::checked_layer(layer);
+
+        #[cfg(target_arch = "avr")]
+        let key_layer = RIGHT_LAYERS.load_at(layer);
+        #[cfg(not(target_arch = "avr"))]
+        let key_layer = RIGHT_LAYERS[layer];
+
+        key_layer[row][col - COLS]
+    }
+}
+
+/// Merges the primary and secondary matrix states into one logical row.
+///
+/// The secondary columns are placed after the `primary_cols` columns of the primary board.
+pub const fn merge_rows(primary: u16, secondary: u16, primary_cols: u32) -> u32 {
+    (primary as u32) | ((secondary as u32) << primary_cols)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::{A, B, EXCL, Q};
+
+    // Logical key map with `A` on the left half, and `B` on the right half.
+    struct HalvesKeymap;
+
+    impl Keymap<ROWS, SPLIT_COLS> for HalvesKeymap {
+        fn layer_key(_layer: usize, _row: usize, col: usize) -> u8 {
+            if col < COLS {
+                A
+            } else {
+                B
+            }
+        }
+    }
+
+    #[test]
+    fn test_link_round_trip() {
+        let rows = [0x0001, 0x0800, 0x0000, 0x0fff];
+        let frame = encode_link_frame(&rows);
+
+        let mut decoder = LinkDecoder::new();
+
+        // garbage before the sync byte is skipped
+        assert_eq!(decoder.push(0x00), None);
+
+        for &b in frame[..LINK_FRAME_LEN - 1].iter() {
+            assert_eq!(decoder.push(b), None);
+        }
+        assert_eq!(decoder.push(frame[LINK_FRAME_LEN - 1]), Some(rows));
+
+        // corrupted frame is dropped
+        let mut bad = frame;
+        bad[2] ^= 0xff;
+        assert!(bad.iter().all(|&b| decoder.push(b).is_none()));
+        assert_eq!(decoder.errors(), 1);
+
+        assert_eq!(merge_rows(0x0fff, 0x0001, 12), 0x1fff);
+    }
+
+    #[test]
+    fn test_split_link() {
+        let mut transmitter = LinkTransmitter::new();
+        let mut receiver = LinkReceiver::new();
+        let mut rows = [RowState::new(); ROWS];
+        rows[1].set_column(3, true);
+
+        // nothing published yet, the frame is dropped
+        receiver.push(transmitter.start(), 0);
+        for _ in 1..LINK_FRAME_LEN {
+            receiver.push(transmitter.next_byte(), 0);
+        }
+        assert!(!receiver.connected(0));
+
+        transmitter.publish(&link_rows(&rows));
+        receiver.push(transmitter.start(), 0);
+
+        // a state published during a read is sent with the next read
+        transmitter.publish(&[0; ROWS]);
+        for _ in 1..LINK_FRAME_LEN {
+            receiver.push(transmitter.next_byte(), 0);
+        }
+        assert_eq!(transmitter.next_byte(), 0xff);
+        assert!(receiver.connected(0));
+        assert_eq!(receiver.rows(10), rows);
+
+        // the keys of the secondary board are released once the link times out
+        assert_eq!(receiver.rows(LINK_TIMEOUT_MS), [RowState::new(); ROWS]);
+        assert!(!receiver.connected(LINK_TIMEOUT_MS));
+
+        assert_eq!(SplitRole::from_usb_powered(false), SplitRole::Secondary);
+        assert_eq!(SplitHand::from_role(SplitRole::Primary), SplitHand::Left);
+    }
+
+    #[test]
+    fn test_split_keymap() {
+        let mut local = [RowState::new(); ROWS];
+        local[0].set_column(1, true);
+        let remote = [RowState::from_u16(0b100); ROWS];
+
+        let rows = merge_halves(&local, &remote, SplitHand::Right);
+        assert_eq!(rows[0], remote[0]);
+        assert_eq!(rows[ROWS], local[0]);
+
+        // the right half is looked up in the columns after the left half
+        assert_eq!(logical_position(ROWS + 2, 1), (2, COLS + 1));
+        assert_eq!(SplitKeymap::<HalvesKeymap>::layer_key(0, 3, COLS - 1), A);
+        assert_eq!(SplitKeymap::<HalvesKeymap>::layer_key(0, ROWS, 0), B);
+        assert_eq!(
+            SplitKeymap::<HalvesKeymap>::passthrough_key(1, SPLIT_ROWS - 1, 0),
+            B
+        );
+    }
+
+    #[test]
+    fn test_split_atreus_keymap() {
+        type Split = SplitKeymap<SplitAtreusKeymap>;
+
+        // the left half has the Atreus layers
+        assert_eq!(Split::layer_key(0, 0, 0), Q);
+        assert_eq!(Split::passthrough_key(1, 0, 0), EXCL);
+        assert_eq!(Split::passthrough_key(2, 3, 8), FUN);
+
+        // the right half has its own layers, passing through to its base layer
+        assert_eq!(Split::layer_key(0, ROWS, 0), F1);
+        assert_eq!(Split::layer_key(0, SPLIT_ROWS - 1, COLS - 1), KP_EQUAL);
+        assert_eq!(Split::passthrough_key(1, ROWS, 0), PLAY_PS);
+        assert_eq!(Split::passthrough_key(1, ROWS, 8), KP7);
+        assert_eq!(Split::passthrough_key(3, ROWS + 3, 8), FUN);
+        assert_eq!(SplitAtreusKeymap::layer_key(0, 0, SPLIT_COLS), 0);
+    }
+}
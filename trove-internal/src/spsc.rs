@@ -0,0 +1,192 @@
+//! Lock-free single-producer, single-consumer queue.
+//!
+//! The matrix is scanned in the main loop, and the reports are sent from the USB context. A
+//! [SpscQueue] hands the finished reports over without a critical section: the [Producer] only
+//! stores the tail index, and the [Consumer] only stores the head index.
+//!
+//! The AVR target has no atomic read-modify-write operations, but each index is only ever stored
+//! by one side, so plain atomic loads and stores are enough.
+
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+
+/// Fixed-capacity ring buffer, holding up to `N - 1` items.
+///
+/// Items left in the queue are never dropped.
+pub struct SpscQueue<T, const N: usize> {
+    items: [UnsafeCell<MaybeUninit<T>>; N],
+    head: AtomicU8,
+    tail: AtomicU8,
+    split: AtomicBool,
+}
+
+// Safety: the producer and consumer never access the same slot at the same time, slots are only
+// handed to the other side by storing an index.
+unsafe impl<T: Send, const N: usize> Sync for SpscQueue<T, N> {}
+
+impl<T, const N: usize> SpscQueue<T, N> {
+    /// Creates a new [SpscQueue].
+    ///
+    /// `N` must be between `2` and `255`.
+    pub const fn new() -> Self {
+        assert!(N >= 2 && N <= u8::MAX as usize);
+
+        Self {
+            items: [const { UnsafeCell::new(MaybeUninit::uninit()) }; N],
+            head: AtomicU8::new(0),
+            tail: AtomicU8::new(0),
+            split: AtomicBool::new(false),
+        }
+    }
+
+    /// Gets the maximum number of queued items.
+    pub const fn capacity(&self) -> usize {
+        N - 1
+    }
+
+    /// Gets the number of queued items.
+    pub fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Relaxed) as usize;
+        let tail = self.tail.load(Ordering::Relaxed) as usize;
+
+        (tail + N - head) % N
+    }
+
+    /// Gets whether the queue is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Splits the queue into its [Producer] and [Consumer].
+    ///
+    /// Returns `None` if the queue was already split. Meant to be called once during setup, with
+    /// the queue in a `static`.
+    pub fn split(&self) -> Option<(Producer<'_, T, N>, Consumer<'_, T, N>)> {
+        if self.split.load(Ordering::Relaxed) {
+            return None;
+        }
+
+        self.split.store(true, Ordering::SeqCst);
+
+        Some((Producer { queue: self }, Consumer { queue: self }))
+    }
+
+    // Gets the index following `index`.
+    const fn next(index: u8) -> u8 {
+        ((index as usize + 1) % N) as u8
+    }
+}
+
+impl<T, const N: usize> Default for SpscQueue<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Pushing side of a [SpscQueue].
+pub struct Producer<'a, T, const N: usize> {
+    queue: &'a SpscQueue<T, N>,
+}
+
+impl<T, const N: usize> Producer<'_, T, N> {
+    /// Pushes an item onto the back of the queue.
+    ///
+    /// Returns `false` if the queue is full, and the item was dropped.
+    pub fn push(&mut self, item: T) -> bool {
+        let tail = self.queue.tail.load(Ordering::Relaxed);
+        let next = SpscQueue::<T, N>::next(tail);
+
+        // pairs with the head store of the consumer, so the slot is no longer read
+        if next == self.queue.head.load(Ordering::Acquire) {
+            return false;
+        }
+
+        // Safety: only the producer writes the slot at the tail, and the consumer does not read
+        // it until the tail is stored.
+        unsafe { (*self.queue.items[tail as usize].get()).write(item) };
+
+        self.queue.tail.store(next, Ordering::SeqCst);
+
+        true
+    }
+
+    /// Gets whether the queue is full.
+    pub fn is_full(&self) -> bool {
+        self.queue.len() == self.queue.capacity()
+    }
+}
+
+/// Popping side of a [SpscQueue].
+pub struct Consumer<'a, T, const N: usize> {
+    queue: &'a SpscQueue<T, N>,
+}
+
+impl<T, const N: usize> Consumer<'_, T, N> {
+    /// Pops an item from the front of the queue.
+    ///
+    /// Returns `None` if the queue is empty.
+    pub fn pop(&mut self) -> Option<T> {
+        let head = self.queue.head.load(Ordering::Relaxed);
+
+        // pairs with the tail store of the producer, so the slot is fully written
+        if head == self.queue.tail.load(Ordering::Acquire) {
+            return None;
+        }
+
+        // Safety: the producer initialized the slot before storing the tail, and does not write
+        // it again until the head is stored.
+        let item = unsafe { (*self.queue.items[head as usize].get()).assume_init_read() };
+
+        self.queue
+            .head
+            .store(SpscQueue::<T, N>::next(head), Ordering::SeqCst);
+
+        Some(item)
+    }
+
+    /// Gets the number of queued items.
+    pub fn len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// Gets whether the queue is empty.
+    pub fn is_empty(&self) -> bool {
+        self.queue.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_spsc_queue() {
+        let queue = SpscQueue::<u16, 4>::new();
+        let (mut producer, mut consumer) = queue.split().unwrap();
+
+        assert!(queue.split().is_none());
+        assert_eq!(consumer.pop(), None);
+
+        assert!(producer.push(1));
+        assert!(producer.push(2));
+        assert!(producer.push(3));
+        assert!(producer.is_full());
+        assert!(!producer.push(4));
+        assert_eq!(consumer.len(), 3);
+
+        assert_eq!(consumer.pop(), Some(1));
+        assert_eq!(consumer.pop(), Some(2));
+
+        // wraps around the end of the buffer
+        assert!(producer.push(5));
+        assert!(producer.push(6));
+        assert_eq!(consumer.len(), 3);
+
+        assert_eq!(consumer.pop(), Some(3));
+        assert_eq!(consumer.pop(), Some(5));
+        assert_eq!(consumer.pop(), Some(6));
+        assert_eq!(consumer.pop(), None);
+        assert!(consumer.is_empty());
+    }
+}
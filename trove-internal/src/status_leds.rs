@@ -0,0 +1,384 @@
+//! Firmware status LEDs.
+//!
+//! Features report their state through [set_status], and never drive LEDs themselves. Board code
+//! maps the states to LED colors and patterns with [StatusRule]s, and the [StatusLeds] pick the
+//! rule to show on each LED:
+//!
+//! - every rule whose [Condition] holds in the [FirmwareStatus] is a candidate for its LED
+//! - the candidate with the highest priority wins, the first registered on a tie
+//! - the [LedPattern] of the winner animates its color
+//!
+//! An LED is turned off while none of its rules hold, LEDs without rules are left to other
+//! effects. The colors are written to a [FrameBuffer], single-color LEDs can treat any color as
+//! lit.
+
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use crate::animation::Rgb;
+use crate::bootloader;
+use crate::host_leds::{self, HostLedState};
+use crate::indicator::HostLock;
+use crate::layers::{self, Layer};
+use crate::ws2812::FrameBuffer;
+
+/// Maximum number of rules of a [StatusLeds].
+pub const MAX_STATUS_RULES: usize = 8;
+
+static STATUS_FLAGS: AtomicU8 = AtomicU8::new(0);
+
+/// Represents a state reported by a feature.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StatusFlag {
+    /// Caps Word is active.
+    CapsWord = 0,
+    /// A one-shot modifier or layer waits for the next key.
+    OneShotPending = 1,
+    /// A macro is recording.
+    Recording = 2,
+}
+
+/// Sets a state reported by a feature.
+pub fn set_status(flag: StatusFlag, val: bool) {
+    let flags = STATUS_FLAGS.load(Ordering::Relaxed);
+    let bit = 1 << flag as u8;
+
+    STATUS_FLAGS.store(
+        if val { flags | bit } else { flags & !bit },
+        Ordering::SeqCst,
+    );
+}
+
+/// Snapshot of the firmware state shown by the [StatusLeds].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FirmwareStatus {
+    layer: Layer,
+    flags: u8,
+    bootloader: bool,
+    host_leds: HostLedState,
+}
+
+impl FirmwareStatus {
+    /// Creates a new [FirmwareStatus], on the base layer, with every state off.
+    pub const fn new() -> Self {
+        Self {
+            layer: Layer::Base,
+            flags: 0,
+            bootloader: false,
+            host_leds: HostLedState::new(),
+        }
+    }
+
+    /// Gets the current [FirmwareStatus].
+    pub fn current() -> Self {
+        Self {
+            layer: layers::active_layer(),
+            flags: STATUS_FLAGS.load(Ordering::Relaxed),
+            bootloader: bootloader::bootloader_requested(),
+            host_leds: host_leds::host_leds(),
+        }
+    }
+
+    /// Builder function that sets the active layer.
+    pub const fn with_layer(mut self, layer: Layer) -> Self {
+        self.layer = layer;
+        self
+    }
+
+    /// Builder function that sets a [StatusFlag].
+    pub const fn with_flag(mut self, flag: StatusFlag, val: bool) -> Self {
+        if val {
+            self.flags |= 1 << flag as u8;
+        } else {
+            self.flags &= !(1 << flag as u8);
+        }
+        self
+    }
+
+    /// Builder function that sets whether a reboot into the bootloader is pending.
+    pub const fn with_bootloader(mut self, val: bool) -> Self {
+        self.bootloader = val;
+        self
+    }
+
+    /// Builder function that sets the [HostLedState].
+    pub const fn with_host_leds(mut self, leds: HostLedState) -> Self {
+        self.host_leds = leds;
+        self
+    }
+
+    /// Gets the active layer.
+    pub const fn layer(&self) -> Layer {
+        self.layer
+    }
+
+    /// Gets whether a reboot into the bootloader is pending.
+    pub const fn bootloader(&self) -> bool {
+        self.bootloader
+    }
+
+    /// Gets the [HostLedState].
+    pub const fn host_leds(&self) -> HostLedState {
+        self.host_leds
+    }
+
+    /// Gets whether a [StatusFlag] is set.
+    pub const fn flag(&self, flag: StatusFlag) -> bool {
+        self.flags & (1 << flag as u8) != 0
+    }
+}
+
+/// Represents a state shown by a [StatusRule].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Condition {
+    /// The layer is active.
+    Layer(Layer),
+    /// A [StatusFlag] is set.
+    Flag(StatusFlag),
+    /// A host lock is on.
+    HostLock(HostLock),
+    /// The keyboard is about to reboot into the bootloader.
+    Bootloader,
+    /// Always holds, e.g. as a low-priority idle color.
+    Always,
+}
+
+impl Condition {
+    /// Gets whether the condition holds in the [FirmwareStatus].
+    pub fn holds(&self, status: &FirmwareStatus) -> bool {
+        match self {
+            Self::Layer(layer) => status.layer == *layer,
+            Self::Flag(flag) => status.flag(*flag),
+            Self::HostLock(lock) => lock.is_on(status.host_leds),
+            Self::Bootloader => status.bootloader,
+            Self::Always => true,
+        }
+    }
+}
+
+/// Represents how an LED shows its color over time.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum LedPattern {
+    /// Steady color.
+    #[default]
+    Solid,
+    /// On for the first half of the period (in milliseconds), off for the second.
+    Blink(u16),
+    /// Fades in and out over the period (in milliseconds).
+    Breathe(u16),
+}
+
+impl LedPattern {
+    /// Gets the brightness of the pattern at the time `now_ms`, from `0` to `255`.
+    pub const fn level(&self, now_ms: u16) -> u8 {
+        match *self {
+            Self::Solid => u8::MAX,
+            Self::Blink(0) | Self::Breathe(0) => u8::MAX,
+            Self::Blink(period) => {
+                if now_ms % period < period / 2 {
+                    u8::MAX
+                } else {
+                    0
+                }
+            }
+            Self::Breathe(period) => {
+                let phase = (now_ms % period) as u32;
+                let half = (period as u32).div_ceil(2);
+                let ramp = if phase < half {
+                    phase
+                } else {
+                    period as u32 - phase
+                };
+
+                (ramp * u8::MAX as u32 / half) as u8
+            }
+        }
+    }
+}
+
+/// Shows a [Condition] on an LED, with a color, pattern, and priority.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StatusRule {
+    led: u8,
+    condition: Condition,
+    color: Rgb,
+    pattern: LedPattern,
+    priority: u8,
+}
+
+impl StatusRule {
+    /// Creates a new [StatusRule], showing the `color` solid on the `led`, with the lowest
+    /// priority.
+    pub const fn new(led: u8, condition: Condition, color: Rgb) -> Self {
+        Self {
+            led,
+            condition,
+            color,
+            pattern: LedPattern::Solid,
+            priority: 0,
+        }
+    }
+
+    /// Gets the index of the LED.
+    pub const fn led(&self) -> u8 {
+        self.led
+    }
+
+    /// Gets the [Condition] shown.
+    pub const fn condition(&self) -> Condition {
+        self.condition
+    }
+
+    /// Gets the color.
+    pub const fn color(&self) -> Rgb {
+        self.color
+    }
+
+    /// Gets the [LedPattern].
+    pub const fn pattern(&self) -> LedPattern {
+        self.pattern
+    }
+
+    /// Builder function that sets the [LedPattern].
+    pub const fn with_pattern(mut self, pattern: LedPattern) -> Self {
+        self.pattern = pattern;
+        self
+    }
+
+    /// Gets the priority, higher priorities win.
+    pub const fn priority(&self) -> u8 {
+        self.priority
+    }
+
+    /// Builder function that sets the priority, higher priorities win.
+    pub const fn with_priority(mut self, priority: u8) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Gets the color of the rule at the time `now_ms`, with the pattern applied.
+    pub const fn color_at(&self, now_ms: u16) -> Rgb {
+        let level = self.pattern.level(now_ms) as u16 + 1;
+
+        Rgb::new(
+            ((self.color.r as u16 * level) >> 8) as u8,
+            ((self.color.g as u16 * level) >> 8) as u8,
+            ((self.color.b as u16 * level) >> 8) as u8,
+        )
+    }
+}
+
+/// Up to [MAX_STATUS_RULES] [StatusRule]s, see the [module](self) docs.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct StatusLeds {
+    rules: [Option<StatusRule>; MAX_STATUS_RULES],
+}
+
+impl StatusLeds {
+    /// Creates a new [StatusLeds], without rules.
+    pub const fn new() -> Self {
+        Self {
+            rules: [None; MAX_STATUS_RULES],
+        }
+    }
+
+    /// Builder function that adds a rule.
+    ///
+    /// Rules past [MAX_STATUS_RULES] are dropped.
+    pub const fn with_rule(mut self, rule: StatusRule) -> Self {
+        let mut i = 0;
+
+        while i < MAX_STATUS_RULES {
+            if self.rules[i].is_none() {
+                self.rules[i] = Some(rule);
+                break;
+            }
+            i += 1;
+        }
+
+        self
+    }
+
+    /// Gets the rule shown on the `led` in the [FirmwareStatus], if any holds.
+    pub fn active_rule(&self, led: u8, status: &FirmwareStatus) -> Option<&StatusRule> {
+        self.rules
+            .iter()
+            .flatten()
+            .filter(|rule| rule.led == led && rule.condition.holds(status))
+            .fold(None, |best: Option<&StatusRule>, rule| match best {
+                Some(best) if best.priority >= rule.priority => Some(best),
+                _ => Some(rule),
+            })
+    }
+
+    /// Gets the color of the `led` in the [FirmwareStatus] at the time `now_ms`.
+    ///
+    /// Returns [off](Rgb::off) if no rule of the LED holds.
+    pub fn color(&self, led: u8, status: &FirmwareStatus, now_ms: u16) -> Rgb {
+        self.active_rule(led, status)
+            .map_or(Rgb::off(), |rule| rule.color_at(now_ms))
+    }
+
+    /// Writes the colors of the LEDs with rules into the `frame`.
+    pub fn update<const N: usize>(
+        &self,
+        status: &FirmwareStatus,
+        now_ms: u16,
+        frame: &mut FrameBuffer<N>,
+    ) {
+        for rule in self.rules.iter().flatten() {
+            frame.set(rule.led as usize, self.color(rule.led, status, now_ms));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Rgb = Rgb::new(0xff, 0, 0);
+    const BLUE: Rgb = Rgb::new(0, 0, 0xff);
+    const WHITE: Rgb = Rgb::new(0xff, 0xff, 0xff);
+
+    #[test]
+    fn test_status_leds() {
+        let leds = StatusLeds::new()
+            .with_rule(StatusRule::new(0, Condition::Always, WHITE))
+            .with_rule(StatusRule::new(0, Condition::Layer(Layer::Fun), BLUE).with_priority(1))
+            .with_rule(
+                StatusRule::new(0, Condition::Bootloader, RED)
+                    .with_pattern(LedPattern::Blink(100))
+                    .with_priority(9),
+            )
+            .with_rule(StatusRule::new(
+                1,
+                Condition::Flag(StatusFlag::CapsWord),
+                RED,
+            ));
+
+        let status = FirmwareStatus::new();
+        assert_eq!(leds.color(0, &status, 0), WHITE);
+        assert_eq!(leds.color(1, &status, 0), Rgb::off());
+
+        let status = status
+            .with_layer(Layer::Fun)
+            .with_flag(StatusFlag::CapsWord, true);
+        assert_eq!(leds.color(0, &status, 0), BLUE);
+        assert_eq!(leds.color(1, &status, 0), RED);
+
+        let status = status.with_bootloader(true);
+        assert_eq!(leds.color(0, &status, 10), RED);
+        assert_eq!(leds.color(0, &status, 60), Rgb::off());
+
+        let mut frame = FrameBuffer::<3>::new();
+        frame.set(2, WHITE);
+        leds.update(&status, 10, &mut frame);
+        assert_eq!(frame.get(0), Some(RED));
+        assert_eq!(frame.get(1), Some(RED));
+        // LEDs without rules are left alone
+        assert_eq!(frame.get(2), Some(WHITE));
+
+        assert_eq!(LedPattern::Breathe(100).level(0), 0);
+        assert_eq!(LedPattern::Breathe(100).level(50), u8::MAX);
+    }
+}
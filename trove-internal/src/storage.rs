@@ -0,0 +1,92 @@
+//! Persistent storage.
+//!
+//! Runtime configuration (e.g. the [stored key map](crate::stored_keymap)) is kept in the EEPROM.
+//! Subsystems access it through [EepromStorage], so the same code runs against the ATmega32u4
+//! EEPROM on the keyboard, and the simulated EEPROM (`sim::SimEeprom`) on the host.
+//!
+//! The EEPROM layout, in order:
+//!
+//! ```text
+//! | color map | pin map | settings | key map | LED animation |
+//! ```
+
+use crate::config::ConfigError;
+
+/// Size of the ATmega32u4 EEPROM.
+pub const EEPROM_LEN: usize = 1024;
+/// Number of bytes copied at a time by [copy_within](EepromStorage::copy_within).
+const COPY_CHUNK_LEN: usize = 16;
+
+/// Byte-addressed persistent storage, e.g. the EEPROM.
+pub trait EepromStorage {
+    /// Reads `buf.len()` bytes starting at the `offset`.
+    ///
+    /// Returns [ConfigError::InvalidArgument] if the range is out of bounds.
+    fn load(&self, offset: usize, buf: &mut [u8]) -> Result<(), ConfigError>;
+
+    /// Writes the `data` starting at the `offset`.
+    ///
+    /// Returns [ConfigError::InvalidArgument] if the range is out of bounds.
+    fn store(&mut self, offset: usize, data: &[u8]) -> Result<(), ConfigError>;
+
+    /// Reads the byte at the `offset`.
+    fn load_byte(&self, offset: usize) -> Result<u8, ConfigError> {
+        let mut buf = [0u8; 1];
+        self.load(offset, &mut buf)?;
+        Ok(buf[0])
+    }
+
+    /// Copies `len` bytes from the `src` offset to the `dst` offset, in chunks.
+    fn copy_within(&mut self, src: usize, dst: usize, len: usize) -> Result<(), ConfigError> {
+        let mut chunk = [0u8; COPY_CHUNK_LEN];
+
+        for start in (0..len).step_by(COPY_CHUNK_LEN) {
+            let chunk = &mut chunk[..(len - start).min(COPY_CHUNK_LEN)];
+
+            self.load(src + start, chunk)?;
+            self.store(dst + start, chunk)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Calculates the CRC-16/CCITT-FALSE checksum of the `data`.
+pub const fn crc16(data: &[u8]) -> u16 {
+    crc16_update(0xffff, data)
+}
+
+/// Continues a CRC-16/CCITT-FALSE checksum from a previous `crc` over the `data`.
+pub const fn crc16_update(mut crc: u16, data: &[u8]) -> u16 {
+    let mut i = 0;
+
+    while i < data.len() {
+        crc ^= (data[i] as u16) << 8;
+
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+
+        i += 1;
+    }
+
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc16() {
+        assert_eq!(crc16(b"123456789"), 0x29b1);
+        assert_eq!(crc16_update(crc16(b"1234"), b"56789"), 0x29b1);
+        assert_eq!(crc16(&[]), 0xffff);
+    }
+}
@@ -0,0 +1,282 @@
+//! LED animation stored in EEPROM.
+//!
+//! Host tools upload a serialized [animation](crate::animation) over the
+//! [raw HID](crate::raw_hid) interface, in chunks that fit in one report:
+//!
+//! - [WriteAnimation](Command::WriteAnimation): `| position (u16) | count | bytes ... |`
+//! - [CommitAnimation](Command::CommitAnimation): `| length (u16) |`, checks the written
+//!   animation, and makes it active
+//!
+//! At most [MAX_ANIMATION_CHUNK] bytes are written at a time, and an animation is at most
+//! [MAX_ANIMATION_LEN] bytes long.
+//!
+//! The animation fills the rest of the EEPROM after the [stored key map](crate::stored_keymap), at
+//! [ANIMATION_OFFSET]:
+//!
+//! ```text
+//! | length (u16) | CRC-16 | animation ... |
+//! ```
+//!
+//! The first write of an upload clears the length, so a partly uploaded animation is never
+//! played, and the commit stores the length and the CRC once the whole animation is written. The
+//! LED engine plays the active animation straight from the EEPROM, see [StoredAnimation].
+
+use core::sync::atomic::{AtomicU16, AtomicU8, Ordering};
+
+use crate::animation::{AnimationData, AnimationPlayer};
+use crate::config::{Command, ConfigError};
+use crate::storage::{crc16, crc16_update, EepromStorage, EEPROM_LEN};
+use crate::stored_keymap::{KEYMAP_LEN, KEYMAP_OFFSET};
+
+/// Offset of the stored animation in the EEPROM, after the stored key map.
+pub const ANIMATION_OFFSET: usize = KEYMAP_OFFSET + KEYMAP_LEN;
+/// Length of the stored animation header: `| length (u16) | CRC-16 |`.
+pub const ANIMATION_SLOT_HEADER_LEN: usize = 4;
+/// Maximum length of a stored animation.
+pub const MAX_ANIMATION_LEN: usize = EEPROM_LEN - ANIMATION_OFFSET - ANIMATION_SLOT_HEADER_LEN;
+/// Maximum number of bytes written by one command.
+///
+/// Each written byte takes ~3.4 ms, so writes are kept short to not stall the USB interrupts.
+pub const MAX_ANIMATION_CHUNK: usize = 32;
+
+/// Offset of the animation bytes in the EEPROM.
+const ANIMATION_DATA_OFFSET: usize = ANIMATION_OFFSET + ANIMATION_SLOT_HEADER_LEN;
+
+const _: () = assert!(ANIMATION_DATA_OFFSET < EEPROM_LEN);
+
+/// Length of the active animation, or `0` if no animation is stored.
+static ACTIVE_LEN: AtomicU16 = AtomicU16::new(0);
+/// Incremented whenever the active animation changes, so players restart.
+static REVISION: AtomicU8 = AtomicU8::new(0);
+
+/// Active animation in the EEPROM, read by an [AnimationPlayer].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StoredAnimation<'s, S: EepromStorage> {
+    storage: &'s S,
+    len: usize,
+}
+
+impl<'s, S: EepromStorage> StoredAnimation<'s, S> {
+    /// Creates a new [StoredAnimation] for the active animation in the `storage`.
+    ///
+    /// Returns `None` if no animation is stored.
+    pub fn active(storage: &'s S) -> Option<Self> {
+        match ACTIVE_LEN.load(Ordering::Relaxed) as usize {
+            0 => None,
+            len => Some(Self { storage, len }),
+        }
+    }
+
+    /// Gets the length of the animation.
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Gets whether the animation is empty.
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl<S: EepromStorage> AnimationData for StoredAnimation<'_, S> {
+    fn byte(&self, offset: usize) -> Option<u8> {
+        if offset < self.len {
+            self.storage.load_byte(ANIMATION_DATA_OFFSET + offset).ok()
+        } else {
+            None
+        }
+    }
+}
+
+/// Gets the length of the stored animation, if its stored CRC matches.
+pub fn stored_len<S: EepromStorage>(storage: &S) -> Result<Option<usize>, ConfigError> {
+    let mut header = [0u8; ANIMATION_SLOT_HEADER_LEN];
+    storage.load(ANIMATION_OFFSET, &mut header)?;
+
+    let len = u16::from_le_bytes([header[0], header[1]]) as usize;
+    let crc = u16::from_le_bytes([header[2], header[3]]);
+
+    if len == 0 || len > MAX_ANIMATION_LEN || crc != animation_crc(storage, len)? {
+        Ok(None)
+    } else {
+        Ok(Some(len))
+    }
+}
+
+/// Reloads which animation is active, after it changed in the `storage`.
+///
+/// Call once at startup, later commits reload it themselves.
+pub fn reload_animation<S: EepromStorage>(storage: &S) {
+    let len = stored_len(storage).ok().flatten().unwrap_or(0);
+
+    set_active_len(len);
+}
+
+/// Gets the revision of the active animation, which changes whenever an animation is uploaded.
+pub fn revision() -> u8 {
+    REVISION.load(Ordering::Relaxed)
+}
+
+/// Writes the `data` of an upload starting at the `position`, to be made active by [commit].
+///
+/// The active animation is stopped by the first write.
+pub fn write_bytes<S: EepromStorage>(
+    storage: &mut S,
+    position: usize,
+    data: &[u8],
+) -> Result<(), ConfigError> {
+    if data.len() > MAX_ANIMATION_CHUNK || position.saturating_add(data.len()) > MAX_ANIMATION_LEN {
+        return Err(ConfigError::InvalidArgument);
+    }
+
+    // only clear the length once, each EEPROM write is slow
+    let mut len = [0u8; 2];
+    storage.load(ANIMATION_OFFSET, &mut len)?;
+    if len != [0, 0] {
+        storage.store(ANIMATION_OFFSET, &[0, 0])?;
+        set_active_len(0);
+    }
+
+    storage.store(ANIMATION_DATA_OFFSET + position, data)
+}
+
+/// Makes the first `len` written bytes the active animation.
+///
+/// Returns [ConfigError::InvalidArgument] if they are not a valid animation.
+pub fn commit<S: EepromStorage>(storage: &mut S, len: usize) -> Result<(), ConfigError> {
+    if len == 0 || len > MAX_ANIMATION_LEN {
+        return Err(ConfigError::InvalidArgument);
+    }
+
+    let written = StoredAnimation { storage, len };
+    AnimationPlayer::new(&written).map_err(|_| ConfigError::InvalidArgument)?;
+
+    let len = len as u16;
+    let crc = animation_crc(storage, len as usize)?;
+    let [len_lo, len_hi] = len.to_le_bytes();
+    let [crc_lo, crc_hi] = crc.to_le_bytes();
+
+    storage.store(ANIMATION_OFFSET, &[len_lo, len_hi, crc_lo, crc_hi])?;
+    reload_animation(storage);
+
+    Ok(())
+}
+
+/// Handles the animation [Command]s, with the `args` of a raw HID report.
+///
+/// Returns [ConfigError::UnhandledCommand] for any other command.
+pub fn handle_animation_command<S: EepromStorage>(
+    storage: &mut S,
+    command: Command,
+    args: &[u8],
+) -> Result<usize, ConfigError> {
+    match command {
+        Command::WriteAnimation => match args {
+            [lo, hi, count, data @ ..] => {
+                let data = data
+                    .get(..*count as usize)
+                    .ok_or(ConfigError::InvalidArgument)?;
+
+                write_bytes(storage, u16::from_le_bytes([*lo, *hi]) as usize, data)?;
+                Ok(0)
+            }
+            _ => Err(ConfigError::InvalidArgument),
+        },
+        Command::CommitAnimation => match args {
+            [lo, hi, ..] => commit(storage, u16::from_le_bytes([*lo, *hi]) as usize).map(|_| 0),
+            _ => Err(ConfigError::InvalidArgument),
+        },
+        _ => Err(ConfigError::UnhandledCommand(command)),
+    }
+}
+
+// Stores the length of the active animation, and restarts the players.
+fn set_active_len(len: usize) {
+    ACTIVE_LEN.store(len as u16, Ordering::SeqCst);
+    REVISION.store(revision().wrapping_add(1), Ordering::SeqCst);
+}
+
+// Calculates the CRC of the first `len` animation bytes, over the length and the bytes.
+fn animation_crc<S: EepromStorage>(storage: &S, len: usize) -> Result<u16, ConfigError> {
+    let mut crc = crc16(&(len as u16).to_le_bytes());
+    let mut chunk = [0u8; MAX_ANIMATION_CHUNK];
+
+    for start in (0..len).step_by(chunk.len()) {
+        let chunk = &mut chunk[..(len - start).min(MAX_ANIMATION_CHUNK)];
+
+        storage.load(ANIMATION_DATA_OFFSET + start, chunk)?;
+        crc = crc16_update(crc, chunk);
+    }
+
+    Ok(crc)
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::animation::{write_frame, AnimationHeader, Rgb};
+    use crate::sim::SimEeprom;
+
+    #[test]
+    fn test_stored_animation() {
+        let mut eeprom = SimEeprom::new();
+        let frames = [[Rgb::new(1, 2, 3); 2], [Rgb::new(1, 2, 3), Rgb::off()]];
+
+        let mut data = [0u8; 32];
+        let mut len = AnimationHeader::new(2, 2, 50).write(&mut data).unwrap();
+        len += write_frame(&[], frames[0].as_ref(), &mut data[len..]).unwrap();
+        len += write_frame(frames[0].as_ref(), frames[1].as_ref(), &mut data[len..]).unwrap();
+
+        // erased EEPROM has no animation
+        reload_animation(&eeprom);
+        assert_eq!(stored_len(&eeprom), Ok(None));
+        assert!(StoredAnimation::active(&eeprom).is_none());
+
+        let before = revision();
+        for (i, chunk) in data[..len].chunks(8).enumerate() {
+            let mut args = [0u8; 11];
+            args[..2].copy_from_slice((i as u16 * 8).to_le_bytes().as_ref());
+            args[2] = chunk.len() as u8;
+            args[3..3 + chunk.len()].copy_from_slice(chunk);
+
+            assert_eq!(
+                handle_animation_command(&mut eeprom, Command::WriteAnimation, &args),
+                Ok(0)
+            );
+        }
+        assert_eq!(stored_len(&eeprom), Ok(None));
+
+        let args = (len as u16).to_le_bytes();
+        assert_eq!(
+            handle_animation_command(&mut eeprom, Command::CommitAnimation, &args),
+            Ok(0)
+        );
+        assert_eq!(stored_len(&eeprom), Ok(Some(len)));
+        assert_ne!(revision(), before);
+
+        // the LED engine plays it straight from the EEPROM
+        let stored = StoredAnimation::active(&eeprom).unwrap();
+        let mut player = AnimationPlayer::new(&stored).unwrap();
+        let mut leds = [Rgb::off(); 2];
+        assert_eq!(player.header().frame_ms(), 50);
+        for frame in frames.iter() {
+            player.next_frame(&stored, &mut leds).unwrap();
+            assert_eq!(&leds, frame);
+        }
+
+        // a new upload stops the active animation
+        write_bytes(&mut eeprom, 0, b"XX").unwrap();
+        assert!(StoredAnimation::active(&eeprom).is_none());
+        assert_eq!(commit(&mut eeprom, len), Err(ConfigError::InvalidArgument));
+        assert_eq!(stored_len(&eeprom), Ok(None));
+
+        assert_eq!(
+            write_bytes(&mut eeprom, MAX_ANIMATION_LEN - 1, &[0, 0]),
+            Err(ConfigError::InvalidArgument)
+        );
+        assert_eq!(
+            commit(&mut eeprom, MAX_ANIMATION_LEN + 1),
+            Err(ConfigError::InvalidArgument)
+        );
+    }
+}
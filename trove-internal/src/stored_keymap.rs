@@ -0,0 +1,425 @@
+//! Key map stored in EEPROM.
+//!
+//! Host tools (e.g. a future configurator) change the key map at runtime over the
+//! [raw HID](crate::raw_hid) interface, in chunks that fit in one report:
+//!
+//! - [ReadKeymap](Command::ReadKeymap): `| position (u16) | count |`, responds with the keys
+//! - [WriteKeymap](Command::WriteKeymap): `| position (u16) | count | keys ... |`
+//! - [CommitKeymap](Command::CommitKeymap): makes the written keys active
+//! - [RevertKeymap](Command::RevertKeymap): discards the written keys
+//!
+//! A position is the index of a key over all layers: `layer * ROWS * COLS + row * COLS + col`,
+//! and at most [MAX_KEYMAP_CHUNK] keys are read or written at a time.
+//!
+//! The key map has two slots in EEPROM at [KEYMAP_OFFSET]:
+//!
+//! ```text
+//! | magic "KM" | version | state | slot 0: CRC-16 | keys ... | slot 1: CRC-16 | keys ... |
+//! ```
+//!
+//! Writes are staged in the inactive slot, which starts as a copy of the active key map (or the
+//! compiled-in layers). A commit stores the CRC of the staged keys, and switches the active slot
+//! with a single byte write, so a reset in the middle of an edit never leaves a half-written key
+//! map active. Until the first commit, or if the active slot is corrupted, the compiled-in
+//! [layers](crate::layers) are used.
+
+use core::sync::atomic::{AtomicU16, Ordering};
+
+use crate::config::{Command, ConfigError};
+use crate::layers::{self, COLS, NUM_LAYERS, ROWS};
+use crate::settings::{MAX_SETTINGS_LEN, SETTINGS_OFFSET};
+use crate::storage::{crc16, crc16_update, EepromStorage, EEPROM_LEN};
+
+/// Magic bytes at the start of the stored key map.
+pub const KEYMAP_MAGIC: [u8; 2] = *b"KM";
+/// Current version of the stored key map format.
+pub const KEYMAP_VERSION: u8 = 1;
+/// Number of keys over all layers.
+pub const KEYMAP_KEYS: usize = NUM_LAYERS * ROWS * COLS;
+/// Length of the key map header: `| magic | version | state |`.
+pub const KEYMAP_HEADER_LEN: usize = 4;
+/// Length of a key map slot: `| CRC-16 | keys ... |`.
+pub const KEYMAP_SLOT_LEN: usize = 2 + KEYMAP_KEYS;
+/// Length of the stored key map, with both slots.
+pub const KEYMAP_LEN: usize = KEYMAP_HEADER_LEN + 2 * KEYMAP_SLOT_LEN;
+/// Offset of the stored key map in the EEPROM, after the space reserved for the settings.
+pub const KEYMAP_OFFSET: usize = SETTINGS_OFFSET + MAX_SETTINGS_LEN;
+/// Maximum number of keys read or written by one command.
+///
+/// Each written byte takes ~3.4 ms, so writes are kept short to not stall the USB interrupts.
+pub const MAX_KEYMAP_CHUNK: usize = 32;
+
+const _: () = assert!(KEYMAP_OFFSET + KEYMAP_LEN <= EEPROM_LEN);
+
+/// State bit of the active slot.
+const STATE_SLOT: u8 = 0b0000_0001;
+/// State bit set while writes are staged in the inactive slot.
+const STATE_EDITING: u8 = 0b1000_0000;
+
+/// Offset of the keys of the active slot, or `0` if the compiled-in layers are active.
+static ACTIVE_KEYS_OFFSET: AtomicU16 = AtomicU16::new(0);
+
+/// Represents the header state of the stored key map.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct KeymapState {
+    slot: u8,
+    editing: bool,
+}
+
+impl KeymapState {
+    /// Reads the [KeymapState] from the `storage`.
+    ///
+    /// Returns `None` if there is no stored key map, e.g. in erased EEPROM.
+    pub fn load<S: EepromStorage>(storage: &S) -> Result<Option<Self>, ConfigError> {
+        let mut header = [0u8; KEYMAP_HEADER_LEN];
+        storage.load(KEYMAP_OFFSET, &mut header)?;
+
+        if header[..2] != KEYMAP_MAGIC || header[2] != KEYMAP_VERSION {
+            return Ok(None);
+        }
+
+        Ok(Some(Self {
+            slot: header[3] & STATE_SLOT,
+            editing: header[3] & STATE_EDITING != 0,
+        }))
+    }
+
+    /// Gets the active slot.
+    pub const fn slot(&self) -> u8 {
+        self.slot
+    }
+
+    /// Gets the slot writes are staged in.
+    pub const fn staging_slot(&self) -> u8 {
+        self.slot ^ STATE_SLOT
+    }
+
+    /// Gets whether writes are staged.
+    pub const fn editing(&self) -> bool {
+        self.editing
+    }
+
+    // Writes the header with the state to the `storage`.
+    fn store<S: EepromStorage>(&self, storage: &mut S) -> Result<(), ConfigError> {
+        let state = self.slot | if self.editing { STATE_EDITING } else { 0 };
+
+        storage.store(
+            KEYMAP_OFFSET,
+            &[KEYMAP_MAGIC[0], KEYMAP_MAGIC[1], KEYMAP_VERSION, state],
+        )
+    }
+}
+
+/// Gets the offset of the keys in a `slot`.
+pub const fn slot_keys_offset(slot: u8) -> usize {
+    KEYMAP_OFFSET + KEYMAP_HEADER_LEN + (slot & STATE_SLOT) as usize * KEYMAP_SLOT_LEN + 2
+}
+
+/// Gets the offset of the keys of the active slot, if it holds a valid key map.
+pub fn active_keys_offset<S: EepromStorage>(storage: &S) -> Result<Option<usize>, ConfigError> {
+    match KeymapState::load(storage)? {
+        Some(state) if slot_is_valid(storage, state.slot())? => {
+            Ok(Some(slot_keys_offset(state.slot())))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Reloads which key map is active, after it changed in the `storage`.
+///
+/// Call once at startup, later commits reload it themselves.
+pub fn reload_active_keymap<S: EepromStorage>(storage: &S) {
+    let offset = active_keys_offset(storage).ok().flatten().unwrap_or(0);
+
+    ACTIVE_KEYS_OFFSET.store(offset as u16, Ordering::SeqCst);
+}
+
+/// Gets the key at a `position` of the active stored key map.
+///
+/// Returns `None` if the compiled-in layers are active, or the position is out of range.
+pub fn stored_key<S: EepromStorage>(storage: &S, position: usize) -> Option<u8> {
+    match ACTIVE_KEYS_OFFSET.load(Ordering::Relaxed) as usize {
+        0 => None,
+        _ if position >= KEYMAP_KEYS => None,
+        offset => storage.load_byte(offset + position).ok(),
+    }
+}
+
+/// Reads the keys starting at the `position` into the `buf`.
+///
+/// Reads the staged keys while editing, so host tools see their own writes.
+pub fn read_keys<S: EepromStorage>(
+    storage: &S,
+    position: usize,
+    buf: &mut [u8],
+) -> Result<(), ConfigError> {
+    check_range(position, buf.len())?;
+
+    let offset = match KeymapState::load(storage)? {
+        Some(state) if state.editing() => Some(slot_keys_offset(state.staging_slot())),
+        _ => active_keys_offset(storage)?,
+    };
+
+    match offset {
+        Some(offset) => storage.load(offset + position, buf),
+        None => {
+            for (i, key) in buf.iter_mut().enumerate() {
+                *key = compiled_key(position + i);
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Stages the `keys` starting at the `position`, to be made active by [commit].
+pub fn write_keys<S: EepromStorage>(
+    storage: &mut S,
+    position: usize,
+    keys: &[u8],
+) -> Result<(), ConfigError> {
+    check_range(position, keys.len())?;
+
+    let state = begin_edit(storage)?;
+
+    storage.store(slot_keys_offset(state.staging_slot()) + position, keys)
+}
+
+/// Makes the staged keys active.
+///
+/// Does nothing if no keys are staged.
+pub fn commit<S: EepromStorage>(storage: &mut S) -> Result<(), ConfigError> {
+    let state = match KeymapState::load(storage)? {
+        Some(state) if state.editing() => state,
+        _ => return Ok(()),
+    };
+
+    let staging = state.staging_slot();
+    let crc = slot_crc(storage, staging)?;
+
+    storage.store(slot_keys_offset(staging) - 2, &crc.to_le_bytes())?;
+
+    KeymapState {
+        slot: staging,
+        editing: false,
+    }
+    .store(storage)?;
+
+    reload_active_keymap(storage);
+
+    Ok(())
+}
+
+/// Discards the staged keys.
+pub fn revert<S: EepromStorage>(storage: &mut S) -> Result<(), ConfigError> {
+    match KeymapState::load(storage)? {
+        Some(state) if state.editing() => KeymapState {
+            editing: false,
+            ..state
+        }
+        .store(storage),
+        _ => Ok(()),
+    }
+}
+
+/// Erases the stored key map, so the compiled-in layers are active again.
+pub fn erase<S: EepromStorage>(storage: &mut S) -> Result<(), ConfigError> {
+    storage.store(KEYMAP_OFFSET, &[0xff; KEYMAP_HEADER_LEN])?;
+    reload_active_keymap(storage);
+
+    Ok(())
+}
+
+/// Handles the key map [Command]s, with the `args` of a raw HID report.
+///
+/// Returns [ConfigError::UnhandledCommand] for any other command.
+pub fn handle_keymap_command<S: EepromStorage>(
+    storage: &mut S,
+    command: Command,
+    args: &[u8],
+    buf: &mut [u8],
+) -> Result<usize, ConfigError> {
+    match command {
+        Command::ReadKeymap => {
+            let (position, count) = parse_chunk(args)?;
+            let buf = buf.get_mut(..count).ok_or(ConfigError::BufferTooSmall)?;
+
+            read_keys(storage, position, buf)?;
+            Ok(count)
+        }
+        Command::WriteKeymap => {
+            let (position, count) = parse_chunk(args)?;
+            let keys = args.get(3..3 + count).ok_or(ConfigError::InvalidArgument)?;
+
+            write_keys(storage, position, keys)?;
+            Ok(0)
+        }
+        Command::CommitKeymap => commit(storage).map(|_| 0),
+        Command::RevertKeymap => revert(storage).map(|_| 0),
+        _ => Err(ConfigError::UnhandledCommand(command)),
+    }
+}
+
+/// Gets whether the `command` is one of the key map commands.
+pub const fn is_keymap_command(command: u8) -> bool {
+    matches!(
+        command,
+        0x09..=0x0c // ReadKeymap, WriteKeymap, CommitKeymap, RevertKeymap
+    )
+}
+
+// Starts staging writes, with a copy of the active key map, if not already editing.
+fn begin_edit<S: EepromStorage>(storage: &mut S) -> Result<KeymapState, ConfigError> {
+    let state = match KeymapState::load(storage)? {
+        Some(state) if state.editing() => return Ok(state),
+        Some(state) => state,
+        None => KeymapState::default(),
+    };
+    let staging = slot_keys_offset(state.staging_slot());
+
+    match active_keys_offset(storage)? {
+        Some(active) => storage.copy_within(active, staging, KEYMAP_KEYS)?,
+        None => {
+            for position in 0..KEYMAP_KEYS {
+                storage.store(staging + position, &[compiled_key(position)])?;
+            }
+        }
+    }
+
+    let state = KeymapState {
+        editing: true,
+        ..state
+    };
+    state.store(storage)?;
+
+    Ok(state)
+}
+
+// Gets whether the stored CRC of the `slot` matches its keys.
+fn slot_is_valid<S: EepromStorage>(storage: &S, slot: u8) -> Result<bool, ConfigError> {
+    let mut crc = [0u8; 2];
+    storage.load(slot_keys_offset(slot) - 2, &mut crc)?;
+
+    Ok(u16::from_le_bytes(crc) == slot_crc(storage, slot)?)
+}
+
+// Calculates the CRC of the keys in the `slot`, over the version and the keys.
+fn slot_crc<S: EepromStorage>(storage: &S, slot: u8) -> Result<u16, ConfigError> {
+    let offset = slot_keys_offset(slot);
+    let mut crc = crc16(&[KEYMAP_VERSION]);
+    let mut chunk = [0u8; ROWS * COLS];
+
+    for start in (0..KEYMAP_KEYS).step_by(chunk.len()) {
+        storage.load(offset + start, &mut chunk)?;
+        crc = crc16_update(crc, &chunk);
+    }
+
+    Ok(crc)
+}
+
+// Gets the compiled-in key at the `position`.
+fn compiled_key(position: usize) -> u8 {
+    layers::layer_key(position / (ROWS * COLS), position % (ROWS * COLS)).unwrap_or(0)
+}
+
+// Checks that `count` keys starting at the `position` are in range, and fit in one command.
+fn check_range(position: usize, count: usize) -> Result<(), ConfigError> {
+    if count > MAX_KEYMAP_CHUNK || position.saturating_add(count) > KEYMAP_KEYS {
+        Err(ConfigError::InvalidArgument)
+    } else {
+        Ok(())
+    }
+}
+
+// Parses the `| position (u16) | count |` arguments of a read or write command.
+fn parse_chunk(args: &[u8]) -> Result<(usize, usize), ConfigError> {
+    match args {
+        [lo, hi, count, ..] => Ok((u16::from_le_bytes([*lo, *hi]) as usize, *count as usize)),
+        _ => Err(ConfigError::InvalidArgument),
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::layers::{A, Q, W};
+    use crate::sim::SimEeprom;
+
+    #[test]
+    fn test_stored_keymap() {
+        let mut eeprom = SimEeprom::new();
+        let mut keys = [0u8; 2];
+
+        // erased EEPROM reads the compiled-in layers
+        read_keys(&eeprom, 0, &mut keys).unwrap();
+        assert_eq!(keys, [Q, W]);
+        assert_eq!(active_keys_offset(&eeprom), Ok(None));
+
+        write_keys(&mut eeprom, 1, &[A]).unwrap();
+        read_keys(&eeprom, 0, &mut keys).unwrap();
+        assert_eq!(keys, [Q, A]);
+        assert_eq!(active_keys_offset(&eeprom), Ok(None));
+
+        revert(&mut eeprom).unwrap();
+        read_keys(&eeprom, 0, &mut keys).unwrap();
+        assert_eq!(keys, [Q, W]);
+
+        write_keys(&mut eeprom, 0, &[A, A]).unwrap();
+        commit(&mut eeprom).unwrap();
+        assert_eq!(active_keys_offset(&eeprom), Ok(Some(slot_keys_offset(1))));
+        read_keys(&eeprom, 0, &mut keys).unwrap();
+        assert_eq!(keys, [A, A]);
+
+        // the next edit starts from the active key map, in the other slot
+        write_keys(&mut eeprom, 1, &[W]).unwrap();
+        read_keys(&eeprom, 0, &mut keys).unwrap();
+        assert_eq!(keys, [A, W]);
+        commit(&mut eeprom).unwrap();
+        assert_eq!(active_keys_offset(&eeprom), Ok(Some(slot_keys_offset(0))));
+
+        // a corrupted slot falls back to the compiled-in layers
+        eeprom.write(slot_keys_offset(0) + 5, &[0xee]).unwrap();
+        assert_eq!(active_keys_offset(&eeprom), Ok(None));
+
+        assert_eq!(
+            write_keys(&mut eeprom, KEYMAP_KEYS - 1, &[A, A]),
+            Err(ConfigError::InvalidArgument)
+        );
+    }
+
+    #[test]
+    fn test_keymap_commands() {
+        let mut eeprom = SimEeprom::new();
+        let mut buf = [0u8; 4];
+
+        let write = [1, 0, 2, A, A];
+        assert_eq!(
+            handle_keymap_command(&mut eeprom, Command::WriteKeymap, &write, &mut buf),
+            Ok(0)
+        );
+        assert_eq!(
+            handle_keymap_command(&mut eeprom, Command::CommitKeymap, &[], &mut buf),
+            Ok(0)
+        );
+        assert_eq!(
+            handle_keymap_command(&mut eeprom, Command::ReadKeymap, &[0, 0, 4], &mut buf),
+            Ok(4)
+        );
+        assert_eq!(buf[..3], [Q, A, A]);
+
+        assert_eq!(
+            handle_keymap_command(&mut eeprom, Command::ReadKeymap, &[0, 0, 5], &mut buf),
+            Err(ConfigError::BufferTooSmall)
+        );
+        assert_eq!(
+            handle_keymap_command(&mut eeprom, Command::WriteKeymap, &[0, 0, 2, A], &mut buf),
+            Err(ConfigError::InvalidArgument)
+        );
+        assert_eq!(
+            handle_keymap_command(&mut eeprom, Command::GetTiming, &[], &mut buf),
+            Err(ConfigError::UnhandledCommand(Command::GetTiming))
+        );
+
+        assert!(is_keymap_command(Command::RevertKeymap.into()));
+        assert!(!is_keymap_command(Command::EnterBootloader.into()));
+    }
+}
@@ -0,0 +1,41 @@
+//! System Control keys.
+//!
+//! Power management keys are Generic Desktop System Control usages, sent on a System Control
+//! interface (or the [shared interface](crate::shared_report)) instead of the keyboard reports,
+//! one 8-bit usage per report.
+//!
+//! A key press only wakes a sleeping host with USB remote wakeup, which some hosts disable or
+//! ignore for keyboards. The [WAKE](crate::layers::WAKE) key signals the remote wakeup, and also
+//! sends the System Control `Wake Up` usage, so at least one of them reaches the host.
+
+use usbd_hid::descriptor::SystemControlKey;
+
+use crate::layers::WAKE;
+
+/// System Control `Wake Up` usage.
+pub const SYSTEM_WAKE_UP: u8 = SystemControlKey::WakeUp as u8;
+
+/// Gets the System Control usage of a System Control `key`.
+///
+/// Returns `None` for any other key.
+pub fn system_usage(key: u8) -> Option<u8> {
+    match key {
+        WAKE => Some(SYSTEM_WAKE_UP),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::{A, FUN, PLAY_PS};
+
+    #[test]
+    fn test_system_usage() {
+        assert_eq!(system_usage(WAKE), Some(0x83));
+
+        assert_eq!(system_usage(A), None);
+        assert_eq!(system_usage(FUN), None);
+        assert_eq!(system_usage(PLAY_PS), None);
+    }
+}
@@ -0,0 +1,1055 @@
+//! Tap-hold resolution for dual-role keys.
+//!
+//! Dual-role keys send one key when tapped, and another (usually a modifier) when held. Home-row
+//! modifiers put these keys under the fingers used for normal typing, so fast rolls between keys
+//! must not be mistaken for holds.
+//!
+//! Timestamps are in milliseconds, and use wrapping arithmetic, so any free-running millisecond
+//! counter works as a time source.
+//!
+//! Keymaps use the [TAP_HOLD0](layers::TAP_HOLD0) - [TAP_HOLD7](layers::TAP_HOLD7) keycodes for
+//! dual-role keys, which [TapHoldKeys] resolves in the key event stream, using the [TapHoldKey]
+//! at the same index in its table.
+
+use crate::events::KeyEvent;
+use crate::layers::{self, NUM_TAP_HOLD_KEYS};
+
+/// Default time (in milliseconds) after which an undecided key resolves to a hold.
+pub const TAPPING_TERM_MS: u16 = 200;
+/// Default time (in milliseconds) since the previous key release for a press to be considered
+/// part of a typing flow.
+pub const FLOW_TAP_MS: u16 = 150;
+/// Default time (in milliseconds) after a tap's press in which pressing the same key again
+/// repeats the tap instead of resolving to a hold.
+pub const QUICK_TAP_MS: u16 = TAPPING_TERM_MS;
+
+/// Represents the strategy used to resolve a tap-hold key.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TapHoldStrategy {
+    /// Resolves to a hold only after the tapping term expires.
+    #[default]
+    Timeout = 0,
+    /// Resolves to a hold when another key is pressed and released while the tap-hold key is
+    /// held (a nested press). Rolls, where the tap-hold key is released first, resolve to taps.
+    PermissiveHold = 1,
+    /// Resolves to a tap when the key is pressed shortly after the previous key release (typing
+    /// flow), otherwise behaves like [PermissiveHold](Self::PermissiveHold).
+    FlowTap = 2,
+}
+
+impl From<u8> for TapHoldStrategy {
+    fn from(val: u8) -> Self {
+        match val {
+            1 => Self::PermissiveHold,
+            2 => Self::FlowTap,
+            _ => Self::Timeout,
+        }
+    }
+}
+
+impl From<TapHoldStrategy> for u8 {
+    fn from(val: TapHoldStrategy) -> Self {
+        val as u8
+    }
+}
+
+/// Represents the resolution of a tap-hold key.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Resolution {
+    /// The key is not pressed.
+    #[default]
+    Idle,
+    /// Not enough information to resolve the key yet.
+    Undecided,
+    /// The key resolved to a tap.
+    Tap,
+    /// The key resolved to a hold.
+    Hold,
+}
+
+/// Represents a dual-role key definition.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TapHoldKey {
+    tap: u8,
+    hold: u8,
+    strategy: TapHoldStrategy,
+}
+
+impl TapHoldKey {
+    /// Creates a new [TapHoldKey].
+    pub const fn new(tap: u8, hold: u8, strategy: TapHoldStrategy) -> Self {
+        Self {
+            tap,
+            hold,
+            strategy,
+        }
+    }
+
+    /// Gets the key sent on tap.
+    pub const fn tap(&self) -> u8 {
+        self.tap
+    }
+
+    /// Gets the key sent on hold.
+    pub const fn hold(&self) -> u8 {
+        self.hold
+    }
+
+    /// Gets the [TapHoldStrategy].
+    pub const fn strategy(&self) -> TapHoldStrategy {
+        self.strategy
+    }
+}
+
+/// Timing configuration for tap-hold resolution.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TapHoldConfig {
+    tapping_term_ms: u16,
+    flow_tap_ms: u16,
+    quick_tap_ms: u16,
+}
+
+impl TapHoldConfig {
+    /// Creates a new [TapHoldConfig] with the default timings.
+    pub const fn new() -> Self {
+        Self {
+            tapping_term_ms: TAPPING_TERM_MS,
+            flow_tap_ms: FLOW_TAP_MS,
+            quick_tap_ms: QUICK_TAP_MS,
+        }
+    }
+
+    /// Gets the tapping term in milliseconds.
+    pub const fn tapping_term_ms(&self) -> u16 {
+        self.tapping_term_ms
+    }
+
+    /// Sets the tapping term in milliseconds.
+    pub fn set_tapping_term_ms(&mut self, val: u16) {
+        self.tapping_term_ms = val;
+    }
+
+    /// Builder function that sets the tapping term in milliseconds.
+    pub fn with_tapping_term_ms(mut self, val: u16) -> Self {
+        self.set_tapping_term_ms(val);
+        self
+    }
+
+    /// Gets the flow-tap term in milliseconds.
+    pub const fn flow_tap_ms(&self) -> u16 {
+        self.flow_tap_ms
+    }
+
+    /// Sets the flow-tap term in milliseconds.
+    pub fn set_flow_tap_ms(&mut self, val: u16) {
+        self.flow_tap_ms = val;
+    }
+
+    /// Builder function that sets the flow-tap term in milliseconds.
+    pub fn with_flow_tap_ms(mut self, val: u16) -> Self {
+        self.set_flow_tap_ms(val);
+        self
+    }
+
+    /// Gets the quick-tap term in milliseconds.
+    pub const fn quick_tap_ms(&self) -> u16 {
+        self.quick_tap_ms
+    }
+
+    /// Sets the quick-tap term in milliseconds.
+    ///
+    /// Tapping a key, then pressing and holding it again within the quick-tap term holds the tap
+    /// key (letting the host auto-repeat it). Zero disables the behavior.
+    pub fn set_quick_tap_ms(&mut self, val: u16) {
+        self.quick_tap_ms = val;
+    }
+
+    /// Builder function that sets the quick-tap term in milliseconds.
+    pub fn with_quick_tap_ms(mut self, val: u16) -> Self {
+        self.set_quick_tap_ms(val);
+        self
+    }
+}
+
+impl Default for TapHoldConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Resolution state machine for a single pressed [TapHoldKey].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TapHold {
+    strategy: TapHoldStrategy,
+    config: TapHoldConfig,
+    pressed_at: u16,
+    last_tap_at: Option<u16>,
+    other_pressed: bool,
+    resolution: Resolution,
+}
+
+impl TapHold {
+    /// Creates a new [TapHold] state machine.
+    pub const fn new(strategy: TapHoldStrategy, config: TapHoldConfig) -> Self {
+        Self {
+            strategy,
+            config,
+            pressed_at: 0,
+            last_tap_at: None,
+            other_pressed: false,
+            resolution: Resolution::Idle,
+        }
+    }
+
+    /// Gets the current [Resolution].
+    pub const fn resolution(&self) -> Resolution {
+        self.resolution
+    }
+
+    /// Handles the press of the tap-hold key.
+    ///
+    /// `last_release` is the time of the most recent release of any other key, if any.
+    ///
+    /// Pressing the key again within the quick-tap term of a previous tap resolves to a tap
+    /// immediately, regardless of the [TapHoldStrategy].
+    pub fn press(&mut self, now: u16, last_release: Option<u16>) -> Resolution {
+        let quick_tap = self
+            .last_tap_at
+            .map(|t| now.wrapping_sub(t) < self.config.quick_tap_ms)
+            .unwrap_or(false);
+
+        self.pressed_at = now;
+        self.other_pressed = false;
+
+        let in_flow = last_release
+            .map(|t| now.wrapping_sub(t) < self.config.flow_tap_ms)
+            .unwrap_or(false);
+
+        self.resolution = if quick_tap || (self.strategy == TapHoldStrategy::FlowTap && in_flow) {
+            Resolution::Tap
+        } else {
+            Resolution::Undecided
+        };
+
+        self.resolution
+    }
+
+    /// Handles the release of the tap-hold key.
+    ///
+    /// Returns the final [Resolution]; an undecided key released before the tapping term is a
+    /// tap.
+    pub fn release(&mut self, now: u16) -> Resolution {
+        let resolution = match self.tick(now) {
+            Resolution::Undecided => Resolution::Tap,
+            res => res,
+        };
+
+        self.last_tap_at = if resolution == Resolution::Tap {
+            Some(self.pressed_at)
+        } else {
+            None
+        };
+        self.resolution = Resolution::Idle;
+
+        resolution
+    }
+
+    /// Handles the press of another key while the tap-hold key is held.
+    pub fn other_press(&mut self, now: u16) -> Resolution {
+        if self.tick(now) == Resolution::Undecided {
+            self.other_pressed = true;
+        }
+
+        self.resolution
+    }
+
+    /// Handles the release of another key while the tap-hold key is held.
+    ///
+    /// With [PermissiveHold](TapHoldStrategy::PermissiveHold) and
+    /// [FlowTap](TapHoldStrategy::FlowTap), a key pressed and released entirely within the
+    /// tap-hold key press resolves it to a hold.
+    pub fn other_release(&mut self, now: u16) -> Resolution {
+        if self.tick(now) == Resolution::Undecided
+            && self.other_pressed
+            && self.strategy != TapHoldStrategy::Timeout
+        {
+            self.resolution = Resolution::Hold;
+        }
+
+        self.resolution
+    }
+
+    /// Updates the [Resolution] for the passage of time.
+    ///
+    /// An undecided key held past the tapping term resolves to a hold.
+    pub fn tick(&mut self, now: u16) -> Resolution {
+        if self.resolution == Resolution::Undecided
+            && now.wrapping_sub(self.pressed_at) >= self.config.tapping_term_ms
+        {
+            self.resolution = Resolution::Hold;
+        }
+
+        self.resolution
+    }
+}
+
+/// Maximum number of key events held back while a tap-hold key is undecided.
+///
+/// A tap-hold key with more events behind it resolves to a hold.
+pub const TAP_HOLD_QUEUE_LEN: usize = 8;
+
+// Capacity of the queue of resolved events, and of events run again after a resolution.
+const READY_QUEUE_LEN: usize = 2 * TAP_HOLD_QUEUE_LEN;
+
+// Fixed-capacity FIFO of key events with their keycodes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct KeyQueue<const N: usize> {
+    events: [(KeyEvent, u8); N],
+    head: usize,
+    len: usize,
+}
+
+impl<const N: usize> KeyQueue<N> {
+    const fn new() -> Self {
+        Self {
+            events: [(KeyEvent::new(0, 0, false, 0), 0); N],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, event: (KeyEvent, u8)) -> bool {
+        if self.len == N {
+            false
+        } else {
+            self.events[(self.head + self.len) % N] = event;
+            self.len += 1;
+            true
+        }
+    }
+
+    fn pop(&mut self) -> Option<(KeyEvent, u8)> {
+        if self.len == 0 {
+            None
+        } else {
+            let event = self.events[self.head];
+
+            self.head = (self.head + 1) % N;
+            self.len -= 1;
+
+            Some(event)
+        }
+    }
+
+    fn front(&self) -> Option<&(KeyEvent, u8)> {
+        self.iter().next()
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &(KeyEvent, u8)> {
+        (0..self.len).map(move |i| &self.events[(self.head + i) % N])
+    }
+}
+
+/// Resolves dual-role keys in the key event stream.
+///
+/// Events are [pushed](Self::push) with the keycode from the key map, and come out of
+/// [pop](Self::pop) in the same order, with each [TAP_HOLD0](layers::TAP_HOLD0) -
+/// [TAP_HOLD7](layers::TAP_HOLD7) press replaced by the tap or hold key of its [TapHoldKey].
+///
+/// While a tap-hold key is undecided, its press and every event after it are held back, so keys
+/// pressed in the meantime see the resolved key (e.g. a held modifier). Each key resolves with
+/// its own [TapHoldStrategy], and the shared [TapHoldConfig] timings. The clock is advanced with
+/// [tick](Self::tick), which resolves keys held past the tapping term.
+///
+/// A key pressed and released in the same scan (e.g. a tapped dual-role key, or a key tapped
+/// while one was undecided) would never reach the host, so its release, and every event after
+/// it, is held back until the [next scan](Self::next_scan).
+///
+/// One key is resolved at a time, a tap-hold key pressed while another is undecided resolves
+/// after it. Tap-hold keycodes without an entry in the table are dropped.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TapHoldKeys {
+    keys: &'static [TapHoldKey],
+    config: TapHoldConfig,
+    states: [TapHold; NUM_TAP_HOLD_KEYS as usize],
+    active: [Option<(u8, u8)>; NUM_TAP_HOLD_KEYS as usize],
+    pending: Option<(KeyEvent, usize)>,
+    waiting: KeyQueue<TAP_HOLD_QUEUE_LEN>,
+    ready: KeyQueue<READY_QUEUE_LEN>,
+    scan_presses: KeyQueue<READY_QUEUE_LEN>,
+    last_release: Option<u16>,
+}
+
+impl TapHoldKeys {
+    /// Creates a new [TapHoldKeys], without any dual-role keys.
+    pub const fn new() -> Self {
+        Self {
+            keys: &[],
+            config: TapHoldConfig::new(),
+            states: [TapHold::new(TapHoldStrategy::Timeout, TapHoldConfig::new());
+                NUM_TAP_HOLD_KEYS as usize],
+            active: [None; NUM_TAP_HOLD_KEYS as usize],
+            pending: None,
+            waiting: KeyQueue::new(),
+            ready: KeyQueue::new(),
+            scan_presses: KeyQueue::new(),
+            last_release: None,
+        }
+    }
+
+    /// Gets the table of [TapHoldKey]s.
+    pub const fn keys(&self) -> &'static [TapHoldKey] {
+        self.keys
+    }
+
+    /// Sets the table of [TapHoldKey]s, indexed by the dual-role keycodes.
+    ///
+    /// Entries past [NUM_TAP_HOLD_KEYS] are ignored.
+    pub fn set_keys(&mut self, keys: &'static [TapHoldKey]) {
+        self.keys = keys;
+        self.reset_states();
+    }
+
+    /// Builder function that sets the table of [TapHoldKey]s.
+    pub fn with_keys(mut self, keys: &'static [TapHoldKey]) -> Self {
+        self.set_keys(keys);
+        self
+    }
+
+    /// Gets the [TapHoldConfig].
+    pub const fn config(&self) -> &TapHoldConfig {
+        &self.config
+    }
+
+    /// Sets the [TapHoldConfig].
+    pub fn set_config(&mut self, config: TapHoldConfig) {
+        self.config = config;
+        self.reset_states();
+    }
+
+    /// Builder function that sets the [TapHoldConfig].
+    pub fn with_config(mut self, config: TapHoldConfig) -> Self {
+        self.set_config(config);
+        self
+    }
+
+    /// Gets whether a tap-hold key is waiting to be resolved.
+    pub const fn is_undecided(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// Handles a key event, with the key map keycode for presses.
+    ///
+    /// The keycode of releases is passed through unchanged.
+    pub fn push(&mut self, event: KeyEvent, key: u8) {
+        let mut input = KeyQueue::<READY_QUEUE_LEN>::new();
+        input.push((event, key));
+
+        self.run(input);
+    }
+
+    /// Resolves an undecided key held past the tapping term, at the time `now`.
+    pub fn tick(&mut self, now: u16) {
+        if let Some((press, index)) = self.pending {
+            if self.states[index].tick(now) == Resolution::Hold {
+                self.resolve(press, index, Resolution::Hold);
+                self.run(KeyQueue::new());
+            }
+        }
+    }
+
+    /// Gets whether no key is undecided, and no event is held back until the next scan.
+    pub const fn is_idle(&self) -> bool {
+        self.pending.is_none() && self.ready.len == 0
+    }
+
+    /// Starts a new scan, which releases the keys pressed in the previous scan.
+    ///
+    /// Call before handling the events of a scan.
+    pub fn next_scan(&mut self) {
+        self.scan_presses = KeyQueue::new();
+    }
+
+    /// Removes the next resolved event, with the keycode to handle it with.
+    ///
+    /// Returns `None` at the release of a key pressed in the same scan, see
+    /// [next_scan](Self::next_scan).
+    pub fn pop(&mut self) -> Option<(KeyEvent, u8)> {
+        let &(event, _) = self.ready.front()?;
+        let pos = (event.row(), event.col());
+
+        if !event.pressed()
+            && self
+                .scan_presses
+                .iter()
+                .any(|(e, _)| (e.row(), e.col()) == pos)
+        {
+            return None;
+        }
+
+        let next = self.ready.pop()?;
+        if event.pressed() {
+            self.scan_presses.push(next);
+        }
+
+        Some(next)
+    }
+
+    // Handles the events in order. Events held back by a resolved key run again first.
+    fn run(&mut self, mut input: KeyQueue<READY_QUEUE_LEN>) {
+        loop {
+            if self.pending.is_none() && self.waiting.len > 0 {
+                let mut next = KeyQueue::new();
+
+                while let Some(event) = self.waiting.pop() {
+                    next.push(event);
+                }
+                while let Some(event) = input.pop() {
+                    next.push(event);
+                }
+
+                input = next;
+            }
+
+            match input.pop() {
+                Some((event, key)) => self.step(event, key),
+                None => break,
+            }
+        }
+    }
+
+    // Handles an event, holding it back if a key is undecided.
+    fn step(&mut self, event: KeyEvent, key: u8) {
+        let pos = (event.row(), event.col());
+        let now = event.timestamp();
+
+        if let Some((press, index)) = self.pending {
+            let state = &mut self.states[index];
+
+            let mut resolution = if !event.pressed() && (press.row(), press.col()) == pos {
+                // the release of the undecided key is final
+                self.active[index] = None;
+                state.release(now)
+            } else if event.pressed() {
+                state.other_press(now)
+            } else if self
+                .waiting
+                .iter()
+                .any(|(e, _)| e.pressed() && (e.row(), e.col()) == pos)
+            {
+                state.other_release(now)
+            } else {
+                state.tick(now)
+            };
+
+            self.waiting.push((event, key));
+
+            // keep room for the release of the undecided key
+            if resolution == Resolution::Undecided && self.waiting.len + 1 >= TAP_HOLD_QUEUE_LEN {
+                state.resolution = Resolution::Hold;
+                resolution = Resolution::Hold;
+            }
+
+            if resolution != Resolution::Undecided {
+                self.resolve(press, index, resolution);
+            }
+        } else if event.pressed() && layers::key_is_tap_hold(key) {
+            let index = layers::tap_hold_index(key) as usize;
+
+            match self.keys.get(index) {
+                Some(tap_hold) => {
+                    self.active[index] = Some(pos);
+
+                    if self.states[index].press(now, self.last_release) == Resolution::Tap {
+                        self.ready.push((event, tap_hold.tap()));
+                    } else {
+                        self.pending = Some((event, index));
+                    }
+                }
+                None => {
+                    self.ready.push((event, 0));
+                }
+            }
+        } else {
+            if !event.pressed() {
+                self.last_release = Some(now);
+
+                if let Some(index) = self.active.iter().position(|&a| a == Some(pos)) {
+                    self.states[index].release(now);
+                    self.active[index] = None;
+                }
+            }
+
+            self.ready.push((event, key));
+        }
+    }
+
+    // Sends the press of the undecided key as its resolved key.
+    fn resolve(&mut self, press: KeyEvent, index: usize, resolution: Resolution) {
+        let tap_hold = self.keys.get(index).copied().unwrap_or_default();
+        let key = match resolution {
+            Resolution::Hold => tap_hold.hold(),
+            _ => tap_hold.tap(),
+        };
+
+        self.ready.push((press, key));
+        self.pending = None;
+    }
+
+    // Creates the state machines for the table, with the current timings.
+    fn reset_states(&mut self) {
+        for (i, state) in self.states.iter_mut().enumerate() {
+            let strategy = self.keys.get(i).map(|k| k.strategy()).unwrap_or_default();
+
+            *state = TapHold::new(strategy, self.config);
+        }
+
+        self.active = [None; NUM_TAP_HOLD_KEYS as usize];
+    }
+}
+
+impl Default for TapHoldKeys {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Counts consecutive taps of the same key.
+///
+/// A press within the tapping term of the previous press of the same key continues the tap
+/// sequence, any other press starts a new one.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TapCounter {
+    tapping_term_ms: u16,
+    key: u8,
+    count: u8,
+    last_press: Option<u16>,
+}
+
+impl TapCounter {
+    /// Creates a new [TapCounter].
+    pub const fn new(tapping_term_ms: u16) -> Self {
+        Self {
+            tapping_term_ms,
+            key: 0,
+            count: 0,
+            last_press: None,
+        }
+    }
+
+    /// Gets the tapping term in milliseconds.
+    pub const fn tapping_term_ms(&self) -> u16 {
+        self.tapping_term_ms
+    }
+
+    /// Sets the tapping term in milliseconds.
+    pub fn set_tapping_term_ms(&mut self, val: u16) {
+        self.tapping_term_ms = val;
+    }
+
+    /// Builder function that sets the tapping term in milliseconds.
+    pub fn with_tapping_term_ms(mut self, val: u16) -> Self {
+        self.set_tapping_term_ms(val);
+        self
+    }
+
+    /// Gets the number of consecutive taps in the current sequence.
+    pub const fn count(&self) -> u8 {
+        self.count
+    }
+
+    /// Records a press of the `key`, and returns the number of consecutive taps.
+    pub fn press(&mut self, key: u8, now: u16) -> u8 {
+        let in_term = self
+            .last_press
+            .is_some_and(|t| now.wrapping_sub(t) < self.tapping_term_ms);
+
+        self.count = if in_term && key == self.key {
+            self.count.saturating_add(1)
+        } else {
+            1
+        };
+
+        self.key = key;
+        self.last_press = Some(now);
+
+        self.count
+    }
+
+    /// Ends the current tap sequence.
+    pub fn reset(&mut self) {
+        self.count = 0;
+        self.last_press = None;
+    }
+}
+
+impl Default for TapCounter {
+    fn default() -> Self {
+        Self::new(TAPPING_TERM_MS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::{self, A, CTRL, S, SHIFT, TAP_HOLD0, X};
+    use crate::report::ReportBuilder;
+    use usbd_hid::descriptor::KeyboardReport;
+
+    const KEYS: &[TapHoldKey] = &[
+        TapHoldKey::new(A, SHIFT, TapHoldStrategy::PermissiveHold),
+        TapHoldKey::new(S, CTRL, TapHoldStrategy::Timeout),
+    ];
+
+    fn press(col: u8, timestamp: u16) -> KeyEvent {
+        KeyEvent::new(0, col, true, timestamp)
+    }
+
+    fn release(col: u8, timestamp: u16) -> KeyEvent {
+        KeyEvent::new(0, col, false, timestamp)
+    }
+
+    // Gets the resolved (column, pressed, keycode) of the next event.
+    fn pop(keys: &mut TapHoldKeys) -> Option<(u8, bool, u8)> {
+        keys.pop().map(|(e, k)| (e.col(), e.pressed(), k))
+    }
+
+    // Runs one scan the way the key scanner does, with the `events` from the key map, and builds
+    // the report from the keys `held` in each column.
+    fn scan(
+        keys: &mut TapHoldKeys,
+        held: &mut [u8; 4],
+        events: &[(KeyEvent, u8)],
+        now: u16,
+    ) -> KeyboardReport {
+        fn handle(keys: &mut TapHoldKeys, held: &mut [u8; 4]) {
+            while let Some((event, key)) = keys.pop() {
+                held[event.col() as usize] = if event.pressed() { key } else { 0 };
+            }
+        }
+
+        keys.next_scan();
+        handle(keys, held);
+
+        for &(event, key) in events {
+            keys.push(event, key);
+            handle(keys, held);
+        }
+
+        keys.tick(now);
+        handle(keys, held);
+
+        let mut builder = ReportBuilder::new();
+        for &key in held.iter().filter(|&&key| key != 0) {
+            builder.add_key(key);
+        }
+
+        builder.build()
+    }
+
+    #[test]
+    fn test_timeout() {
+        let mut key = TapHold::new(TapHoldStrategy::Timeout, TapHoldConfig::new());
+
+        assert_eq!(key.press(0, None), Resolution::Undecided);
+        assert_eq!(key.other_press(10), Resolution::Undecided);
+        assert_eq!(key.other_release(20), Resolution::Undecided);
+        assert_eq!(key.release(30), Resolution::Tap);
+
+        assert_eq!(key.press(1000, None), Resolution::Undecided);
+        assert_eq!(key.tick(1000 + TAPPING_TERM_MS), Resolution::Hold);
+        assert_eq!(key.release(1400), Resolution::Hold);
+        assert_eq!(key.resolution(), Resolution::Idle);
+    }
+
+    #[test]
+    fn test_permissive_hold() {
+        let mut key = TapHold::new(TapHoldStrategy::PermissiveHold, TapHoldConfig::new());
+
+        // roll: tap-hold key released before the other key
+        assert_eq!(key.press(0, None), Resolution::Undecided);
+        assert_eq!(key.other_press(30), Resolution::Undecided);
+        assert_eq!(key.release(60), Resolution::Tap);
+
+        // nested: other key pressed and released within the tap-hold key press
+        assert_eq!(key.press(1000, None), Resolution::Undecided);
+        assert_eq!(key.other_press(1030), Resolution::Undecided);
+        assert_eq!(key.other_release(1060), Resolution::Hold);
+        assert_eq!(key.release(1090), Resolution::Hold);
+    }
+
+    #[test]
+    fn test_flow_tap() {
+        let config = TapHoldConfig::new().with_flow_tap_ms(100);
+        let mut key = TapHold::new(TapHoldStrategy::FlowTap, config);
+
+        // pressed during a typing flow
+        assert_eq!(
+            key.press(u16::MAX - 10, Some(u16::MAX - 60)),
+            Resolution::Tap
+        );
+        assert_eq!(key.tick(500), Resolution::Tap);
+        assert_eq!(key.release(500), Resolution::Tap);
+
+        // pressed after a pause in typing
+        assert_eq!(key.press(1000, Some(800)), Resolution::Undecided);
+        assert_eq!(key.other_press(1030), Resolution::Undecided);
+        assert_eq!(key.other_release(1060), Resolution::Hold);
+    }
+
+    #[test]
+    fn test_quick_tap() {
+        let config = TapHoldConfig::new().with_quick_tap_ms(150);
+        let mut key = TapHold::new(TapHoldStrategy::PermissiveHold, config);
+
+        // tap, then press and hold again within the quick-tap term
+        assert_eq!(key.press(0, None), Resolution::Undecided);
+        assert_eq!(key.release(50), Resolution::Tap);
+        assert_eq!(key.press(100, None), Resolution::Tap);
+        assert_eq!(key.tick(1000), Resolution::Tap);
+        assert_eq!(key.release(1000), Resolution::Tap);
+
+        // outside the quick-tap term
+        assert_eq!(key.press(2000, None), Resolution::Undecided);
+        assert_eq!(key.release(2050), Resolution::Tap);
+        assert_eq!(key.press(2200, None), Resolution::Undecided);
+        assert_eq!(key.tick(2400), Resolution::Hold);
+        assert_eq!(key.release(2500), Resolution::Hold);
+
+        // disabled quick-tap term
+        let mut key = TapHold::new(
+            TapHoldStrategy::Timeout,
+            TapHoldConfig::new().with_quick_tap_ms(0),
+        );
+        assert_eq!(key.press(0, None), Resolution::Undecided);
+        assert_eq!(key.release(50), Resolution::Tap);
+        assert_eq!(key.press(60, None), Resolution::Undecided);
+    }
+
+    #[test]
+    fn test_tap_counter() {
+        let mut taps = TapCounter::default();
+
+        assert_eq!(taps.press(1, 0), 1);
+        assert_eq!(taps.press(1, 100), 2);
+        assert_eq!(taps.press(1, 100 + TAPPING_TERM_MS - 1), 3);
+
+        // a different key, or a press after the tapping term, starts a new sequence
+        assert_eq!(taps.press(2, 400), 1);
+        assert_eq!(taps.press(2, 400 + TAPPING_TERM_MS), 1);
+
+        // the tapping term wraps with the millisecond counter
+        assert_eq!(taps.press(2, u16::MAX - 10), 1);
+        assert_eq!(taps.press(2, 10), 2);
+
+        taps.reset();
+        assert_eq!(taps.count(), 0);
+        assert_eq!(taps.press(2, 20), 1);
+    }
+
+    #[test]
+    fn test_tap_hold_keys() {
+        let mut keys = TapHoldKeys::new().with_keys(KEYS);
+
+        // tap: the press is held back until the release
+        keys.push(press(0, 0), TAP_HOLD0);
+        assert!(keys.is_undecided());
+        assert_eq!(pop(&mut keys), None);
+
+        keys.push(release(0, 50), 0);
+        assert_eq!(pop(&mut keys), Some((0, true, A)));
+        // the release waits for the next scan, so the host sees the tap
+        assert_eq!(pop(&mut keys), None);
+        assert!(!keys.is_idle());
+        keys.next_scan();
+        assert_eq!(pop(&mut keys), Some((0, false, 0)));
+        assert_eq!(pop(&mut keys), None);
+        assert!(keys.is_idle());
+
+        // hold: the key resolves once the tapping term expires
+        keys.push(press(0, 1000), TAP_HOLD0);
+        keys.tick(1000 + TAPPING_TERM_MS - 1);
+        assert_eq!(pop(&mut keys), None);
+        keys.tick(1000 + TAPPING_TERM_MS);
+        assert_eq!(pop(&mut keys), Some((0, true, SHIFT)));
+        assert!(!keys.is_undecided());
+
+        keys.next_scan();
+        keys.push(release(0, 1500), 0);
+        assert_eq!(pop(&mut keys), Some((0, false, 0)));
+
+        // other keys are passed through
+        keys.push(press(2, 2000), X);
+        assert_eq!(pop(&mut keys), Some((2, true, X)));
+    }
+
+    #[test]
+    fn test_tap_hold_keys_rolls() {
+        let mut keys = TapHoldKeys::new().with_keys(KEYS);
+
+        // nested press resolves the permissive hold key to a hold, before the other key
+        keys.push(press(0, 0), TAP_HOLD0);
+        keys.push(press(2, 10), X);
+        assert_eq!(pop(&mut keys), None);
+
+        keys.push(release(2, 20), 0);
+        assert_eq!(pop(&mut keys), Some((0, true, SHIFT)));
+        assert_eq!(pop(&mut keys), Some((2, true, X)));
+        assert_eq!(pop(&mut keys), None);
+        keys.next_scan();
+        assert_eq!(pop(&mut keys), Some((2, false, 0)));
+
+        keys.push(release(0, 30), 0);
+        assert_eq!(pop(&mut keys), Some((0, false, 0)));
+
+        // roll: the tap-hold key is released first, and resolves to a tap
+        keys.push(press(0, 1000), TAP_HOLD0);
+        keys.push(press(2, 1010), X);
+        keys.push(release(0, 1020), 0);
+        assert_eq!(pop(&mut keys), Some((0, true, A)));
+        assert_eq!(pop(&mut keys), Some((2, true, X)));
+        assert_eq!(pop(&mut keys), None);
+        keys.next_scan();
+        assert_eq!(pop(&mut keys), Some((0, false, 0)));
+        assert_eq!(pop(&mut keys), None);
+
+        // the timeout strategy ignores nested presses
+        keys.push(release(2, 2000), 0);
+        assert_eq!(pop(&mut keys), Some((2, false, 0)));
+
+        keys.push(press(1, 3000), TAP_HOLD0 + 1);
+        keys.push(press(2, 3010), X);
+        keys.push(release(2, 3020), 0);
+        assert_eq!(pop(&mut keys), None);
+
+        keys.push(release(1, 3030), 0);
+        assert_eq!(pop(&mut keys), Some((1, true, S)));
+        assert_eq!(pop(&mut keys), Some((2, true, X)));
+        assert_eq!(pop(&mut keys), None);
+        keys.next_scan();
+        assert_eq!(pop(&mut keys), Some((2, false, 0)));
+        assert_eq!(pop(&mut keys), Some((1, false, 0)));
+    }
+
+    #[test]
+    fn test_tap_hold_keys_queued() {
+        let mut keys = TapHoldKeys::new().with_keys(KEYS);
+
+        // a tap-hold key pressed while another is undecided resolves after it
+        keys.push(press(0, 0), TAP_HOLD0);
+        keys.push(press(1, 10), TAP_HOLD0 + 1);
+        keys.push(release(0, 20), 0);
+        assert_eq!(pop(&mut keys), Some((0, true, A)));
+        assert_eq!(pop(&mut keys), None);
+        assert!(keys.is_undecided());
+
+        keys.next_scan();
+        keys.tick(10 + TAPPING_TERM_MS);
+        assert_eq!(pop(&mut keys), Some((1, true, CTRL)));
+        assert_eq!(pop(&mut keys), Some((0, false, 0)));
+
+        // too many events behind an undecided key resolve it to a hold
+        keys.push(press(0, 1000), TAP_HOLD0);
+
+        for i in 0..TAP_HOLD_QUEUE_LEN as u16 - 1 {
+            keys.push(press(2, 1001 + i), X);
+        }
+
+        assert_eq!(pop(&mut keys), Some((0, true, SHIFT)));
+
+        // undefined tap-hold keys are dropped
+        let mut keys = TapHoldKeys::new();
+        keys.push(press(0, 0), TAP_HOLD0);
+        assert_eq!(pop(&mut keys), Some((0, true, 0)));
+    }
+
+    #[test]
+    fn test_tap_hold_keys_scan_reports() {
+        let mut keys = TapHoldKeys::new().with_keys(KEYS);
+        let mut held = [0u8; 4];
+
+        // a tap resolves with the release, and the tap key is sent in one report
+        let report = scan(&mut keys, &mut held, &[(press(0, 0), TAP_HOLD0)], 0);
+        assert_eq!(report.keycodes, [0; 6]);
+
+        let report = scan(&mut keys, &mut held, &[(release(0, 50), 0)], 50);
+        assert_eq!(report.keycodes, [A, 0, 0, 0, 0, 0]);
+
+        let report = scan(&mut keys, &mut held, &[], 51);
+        assert_eq!(report.keycodes, [0; 6]);
+
+        // a key tapped while the dual-role key is undecided is sent with the hold key
+        scan(&mut keys, &mut held, &[(press(0, 1000), TAP_HOLD0)], 1000);
+        scan(&mut keys, &mut held, &[(press(2, 1010), X)], 1010);
+
+        let report = scan(&mut keys, &mut held, &[(release(2, 1020), 0)], 1020);
+        assert_eq!(report.modifier, layers::key_to_modifier(SHIFT));
+        assert_eq!(report.keycodes, [X, 0, 0, 0, 0, 0]);
+
+        let report = scan(&mut keys, &mut held, &[(release(0, 1030), 0)], 1030);
+        assert_eq!(report.modifier, 0);
+        assert_eq!(report.keycodes, [0; 6]);
+    }
+
+    #[test]
+    fn test_tap_hold_keys_quick_tap() {
+        let config = TapHoldConfig::new().with_quick_tap_ms(150);
+        let mut keys = TapHoldKeys::new().with_keys(KEYS).with_config(config);
+        let mut held = [0u8; 4];
+
+        // the first tap is sent
+        scan(&mut keys, &mut held, &[(press(0, 0), TAP_HOLD0)], 0);
+        let report = scan(&mut keys, &mut held, &[(release(0, 50), 0)], 50);
+        assert_eq!(report.keycodes, [A, 0, 0, 0, 0, 0]);
+
+        let report = scan(&mut keys, &mut held, &[], 51);
+        assert_eq!(report.keycodes, [0; 6]);
+
+        // pressed again within the quick-tap term: the tap key is held right away, so the host
+        // auto-repeats it, and it never turns into a hold
+        let report = scan(&mut keys, &mut held, &[(press(0, 100), TAP_HOLD0)], 100);
+        assert!(!keys.is_undecided());
+        assert_eq!(report.keycodes, [A, 0, 0, 0, 0, 0]);
+
+        let report = scan(&mut keys, &mut held, &[], 100 + 5 * TAPPING_TERM_MS);
+        assert_eq!(report.modifier, 0);
+        assert_eq!(report.keycodes, [A, 0, 0, 0, 0, 0]);
+
+        keys.next_scan();
+        keys.push(press(2, 1100), X);
+        keys.push(release(2, 1110), 0);
+        assert_eq!(pop(&mut keys), Some((2, true, X)));
+        keys.next_scan();
+        assert_eq!(pop(&mut keys), Some((2, false, 0)));
+
+        keys.push(release(0, 1200), 0);
+        assert_eq!(pop(&mut keys), Some((0, false, 0)));
+
+        // pressed again after the quick-tap term: resolves as usual
+        keys.push(press(0, 2000), TAP_HOLD0);
+        keys.push(release(0, 2050), 0);
+        assert_eq!(pop(&mut keys), Some((0, true, A)));
+        keys.next_scan();
+        assert_eq!(pop(&mut keys), Some((0, false, 0)));
+
+        keys.push(press(0, 2200), TAP_HOLD0);
+        assert!(keys.is_undecided());
+        keys.tick(2200 + TAPPING_TERM_MS);
+        assert_eq!(pop(&mut keys), Some((0, true, SHIFT)));
+        keys.next_scan();
+        keys.push(release(0, 2500), 0);
+        assert_eq!(pop(&mut keys), Some((0, false, 0)));
+
+        // zero disables the quick-tap term
+        keys.set_config(config.with_quick_tap_ms(0));
+        keys.push(press(0, 3000), TAP_HOLD0);
+        keys.push(release(0, 3050), 0);
+        assert_eq!(pop(&mut keys), Some((0, true, A)));
+        keys.next_scan();
+        assert_eq!(pop(&mut keys), Some((0, false, 0)));
+
+        keys.push(press(0, 3060), TAP_HOLD0);
+        assert!(keys.is_undecided());
+    }
+}
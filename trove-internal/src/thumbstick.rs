@@ -0,0 +1,245 @@
+//! Analog thumbstick pointing.
+//!
+//! A two-axis analog stick (two potentiometers on ADC channels) moves the mouse pointer, as an
+//! alternative to mouse keys. Each poll, the [Thumbstick] turns the samples into pointer motion:
+//!
+//! - the samples are taken relative to the center, [calibrated](Thumbstick::calibrate) at rest
+//! - deflection inside the deadzone is ignored, so a stick resting off-center does not drift
+//! - the rest of the deflection is shaped by the [StickCurve], for fine control near the center
+//! - full deflection moves the pointer by the maximum speed
+//!
+//! Motion is kept in 1/256 pixel steps between polls, so slow speeds still move the pointer.
+
+/// Half the range of a 10-bit ADC, the deflection of a fully pushed stick.
+pub const STICK_HALF_RANGE: u16 = 512;
+/// Default deadzone (in ADC counts) around the center.
+pub const DEFAULT_DEADZONE: u16 = 40;
+/// Default pointer speed (in pixels per poll) at full deflection.
+pub const DEFAULT_MAX_SPEED: u8 = 12;
+/// Center of an uncalibrated stick.
+pub const DEFAULT_CENTER: u16 = 512;
+
+// Fixed-point scale of the deflection and motion.
+const UNIT: u32 = 256;
+
+/// Represents how the deflection maps to pointer speed.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum StickCurve {
+    /// Speed grows with the deflection.
+    Linear,
+    /// Speed grows with the square of the deflection.
+    #[default]
+    Quadratic,
+    /// Speed grows with the cube of the deflection, the finest control near the center.
+    Cubic,
+}
+
+impl StickCurve {
+    /// Applies the curve to a deflection from `0` to `256`.
+    pub const fn apply(&self, val: u32) -> u32 {
+        match self {
+            Self::Linear => val,
+            Self::Quadratic => val * val / UNIT,
+            Self::Cubic => val * val / UNIT * val / UNIT,
+        }
+    }
+}
+
+/// Deadzone, curve, speed, and orientation of a [Thumbstick].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StickConfig {
+    deadzone: u16,
+    max_speed: u8,
+    curve: StickCurve,
+    invert_x: bool,
+    invert_y: bool,
+}
+
+impl StickConfig {
+    /// Creates a new [StickConfig], with the default deadzone, speed, and curve.
+    pub const fn new() -> Self {
+        Self {
+            deadzone: DEFAULT_DEADZONE,
+            max_speed: DEFAULT_MAX_SPEED,
+            curve: StickCurve::Quadratic,
+            invert_x: false,
+            invert_y: false,
+        }
+    }
+
+    /// Gets the deadzone (in ADC counts) around the center.
+    pub const fn deadzone(&self) -> u16 {
+        self.deadzone
+    }
+
+    /// Builder function that sets the deadzone (in ADC counts) around the center.
+    pub const fn with_deadzone(mut self, val: u16) -> Self {
+        self.deadzone = val;
+        self
+    }
+
+    /// Gets the pointer speed (in pixels per poll) at full deflection.
+    pub const fn max_speed(&self) -> u8 {
+        self.max_speed
+    }
+
+    /// Builder function that sets the pointer speed (in pixels per poll) at full deflection.
+    pub const fn with_max_speed(mut self, val: u8) -> Self {
+        self.max_speed = val;
+        self
+    }
+
+    /// Gets the [StickCurve].
+    pub const fn curve(&self) -> StickCurve {
+        self.curve
+    }
+
+    /// Builder function that sets the [StickCurve].
+    pub const fn with_curve(mut self, curve: StickCurve) -> Self {
+        self.curve = curve;
+        self
+    }
+
+    /// Builder function that sets whether the horizontal pointer motion is inverted.
+    pub const fn with_invert_x(mut self, val: bool) -> Self {
+        self.invert_x = val;
+        self
+    }
+
+    /// Builder function that sets whether the vertical pointer motion is inverted.
+    pub const fn with_invert_y(mut self, val: bool) -> Self {
+        self.invert_y = val;
+        self
+    }
+
+    /// Gets the speed (in 1/256 pixels per poll) for a deflection from the center.
+    pub const fn speed(&self, offset: i16) -> i32 {
+        let magnitude = offset.unsigned_abs();
+
+        if magnitude <= self.deadzone || self.deadzone >= STICK_HALF_RANGE {
+            return 0;
+        }
+
+        let travel = (magnitude - self.deadzone) as u32 * UNIT;
+        let range = (STICK_HALF_RANGE - self.deadzone) as u32;
+        let deflection = if travel / range > UNIT {
+            UNIT
+        } else {
+            travel / range
+        };
+        let speed = (self.curve.apply(deflection) * self.max_speed as u32) as i32;
+
+        if offset < 0 {
+            -speed
+        } else {
+            speed
+        }
+    }
+}
+
+impl Default for StickConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Turns thumbstick samples into pointer motion, see the [module](self) docs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Thumbstick {
+    config: StickConfig,
+    center: [u16; 2],
+    remainder: [i32; 2],
+}
+
+impl Thumbstick {
+    /// Creates a new [Thumbstick] with the [StickConfig], centered at [DEFAULT_CENTER].
+    pub const fn new(config: StickConfig) -> Self {
+        Self {
+            config,
+            center: [DEFAULT_CENTER; 2],
+            remainder: [0; 2],
+        }
+    }
+
+    /// Gets the [StickConfig].
+    pub const fn config(&self) -> &StickConfig {
+        &self.config
+    }
+
+    /// Sets the [StickConfig].
+    pub fn set_config(&mut self, config: StickConfig) {
+        self.config = config;
+    }
+
+    /// Sets the center to the samples of the stick at rest.
+    pub fn calibrate(&mut self, x: u16, y: u16) {
+        self.center = [x, y];
+        self.remainder = [0; 2];
+    }
+
+    /// Gets the pointer motion (in pixels) for the samples of one poll.
+    pub fn motion(&mut self, x: u16, y: u16) -> (i16, i16) {
+        let dx = self.axis(0, x);
+        let dy = self.axis(1, y);
+
+        (
+            if self.config.invert_x { -dx } else { dx },
+            if self.config.invert_y { -dy } else { dy },
+        )
+    }
+
+    // Adds the speed of an axis to its remainder, and takes the whole pixels.
+    fn axis(&mut self, axis: usize, sample: u16) -> i16 {
+        let offset = (sample as i32 - self.center[axis] as i32)
+            .clamp(-(STICK_HALF_RANGE as i32), STICK_HALF_RANGE as i32) as i16;
+        let speed = self.config.speed(offset);
+
+        // snap back to rest, so leftover fractions do not move the pointer later
+        if speed == 0 {
+            self.remainder[axis] = 0;
+            return 0;
+        }
+
+        let total = self.remainder[axis] + speed;
+        let pixels = total / UNIT as i32;
+        self.remainder[axis] = total - pixels * UNIT as i32;
+
+        pixels as i16
+    }
+}
+
+impl Default for Thumbstick {
+    fn default() -> Self {
+        Self::new(StickConfig::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_thumbstick() {
+        let config = StickConfig::new()
+            .with_deadzone(12)
+            .with_max_speed(10)
+            .with_curve(StickCurve::Linear)
+            .with_invert_y(true);
+        assert_eq!(config.speed(12), 0);
+        assert_eq!(config.speed(-512), -10 * 256);
+        assert_eq!(config.speed(262), 5 * 256);
+
+        let mut stick = Thumbstick::new(config);
+        stick.calibrate(512, 511);
+        assert_eq!(stick.motion(522, 521), (0, 0));
+        assert_eq!(stick.motion(0, 1023), (-10, -10));
+
+        // a quarter deflection moves 2.5 pixels per poll
+        let moved = [(); 4].map(|_| stick.motion(512 + 137, 511).0);
+        assert_eq!(moved, [2, 3, 2, 3]);
+
+        assert_eq!(StickCurve::Quadratic.apply(128), 64);
+        assert_eq!(StickCurve::Cubic.apply(128), 32);
+        assert_eq!(StickCurve::Cubic.apply(256), 256);
+    }
+}
@@ -0,0 +1,99 @@
+//! Monotonic timekeeping.
+//!
+//! The clock is driven by the scan timer: every timer interrupt calls [tick], which advances the
+//! clock by the [tick interval](crate::health::tick_interval_us). The resolution is one tick
+//! interval, and the clock is paused while the scan timer is stopped (idle sleep and USB suspend).
+//!
+//! The AVR target has no 32-bit atomics, so the millisecond counter is split into two 16-bit
+//! halves. Readers retry when the high half changed while reading, which is safe as long as the
+//! clock is only advanced from an interrupt handler (or with interrupts disabled).
+
+use core::sync::atomic::{AtomicU16, Ordering};
+
+use crate::health;
+
+static MILLIS_LO: AtomicU16 = AtomicU16::new(0);
+static MILLIS_HI: AtomicU16 = AtomicU16::new(0);
+static SUB_MS_US: AtomicU16 = AtomicU16::new(0);
+
+/// Advances the clock by one scan timer interval.
+///
+/// Call from the scan timer interrupt.
+pub fn tick() {
+    advance_us(health::tick_interval_us());
+}
+
+/// Advances the clock by `us` microseconds.
+pub fn advance_us(us: u16) {
+    let elapsed_us = SUB_MS_US.load(Ordering::Relaxed) as u32 + us as u32;
+    let millis = millis().wrapping_add(elapsed_us / 1000);
+
+    SUB_MS_US.store((elapsed_us % 1000) as u16, Ordering::SeqCst);
+    MILLIS_LO.store(millis as u16, Ordering::SeqCst);
+    MILLIS_HI.store((millis >> 16) as u16, Ordering::SeqCst);
+}
+
+/// Gets the time (in milliseconds) since boot.
+///
+/// Wraps around after ~49 days.
+pub fn millis() -> u32 {
+    loop {
+        let hi = MILLIS_HI.load(Ordering::Relaxed);
+        let lo = MILLIS_LO.load(Ordering::Relaxed);
+
+        if hi == MILLIS_HI.load(Ordering::Relaxed) {
+            return ((hi as u32) << 16) | lo as u32;
+        }
+    }
+}
+
+/// Gets the time (in microseconds) since boot.
+///
+/// Wraps around after ~71 minutes, use [elapsed_us] to measure durations.
+pub fn micros() -> u32 {
+    loop {
+        let millis = millis();
+        let sub_ms = SUB_MS_US.load(Ordering::Relaxed);
+
+        if millis == self::millis() {
+            return millis.wrapping_mul(1000).wrapping_add(sub_ms as u32);
+        }
+    }
+}
+
+/// Gets the time (in milliseconds) elapsed since `start`, a previous [millis] value.
+pub fn elapsed_ms(start: u32) -> u32 {
+    millis().wrapping_sub(start)
+}
+
+/// Gets the time (in microseconds) elapsed since `start`, a previous [micros] value.
+pub fn elapsed_us(start: u32) -> u32 {
+    micros().wrapping_sub(start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clock() {
+        let start_ms = millis();
+        let start_us = micros();
+
+        advance_us(1500);
+        assert_eq!(elapsed_ms(start_ms), 1);
+        assert_eq!(elapsed_us(start_us), 1500);
+
+        advance_us(500);
+        assert_eq!(elapsed_ms(start_ms), 2);
+        assert_eq!(elapsed_us(start_us), 2000);
+
+        // the low half carries into the high half
+        for _ in 0..1100 {
+            advance_us(u16::MAX);
+        }
+        assert_eq!(elapsed_ms(start_ms), 2 + (1100 * u16::MAX as u32) / 1000);
+        assert!(millis() > u16::MAX as u32);
+        assert_eq!(elapsed_us(start_us), 2000 + 1100 * u16::MAX as u32);
+    }
+}
@@ -0,0 +1,142 @@
+//! Scan timing instrumentation.
+//!
+//! Measures how long matrix scans and interrupt handlers take, so users can verify their polling
+//! rate and debug latency complaints. Together with the scan frequency from [health], the
+//! measurements are sent to host tools with the [GetTiming](crate::config::Command::GetTiming) command.
+//!
+//! Durations are measured by the firmware binary, and recorded here in microseconds. Like the
+//! [health] counters, they are updated with plain loads and stores: scan durations are recorded
+//! from the main loop, and interrupt durations from the interrupts, which do not nest.
+
+use core::sync::atomic::{AtomicU16, Ordering};
+
+use crate::config::ConfigError;
+use crate::health;
+
+/// Length of a serialized [TimingReport].
+pub const TIMING_REPORT_LEN: usize = 8;
+
+static LAST_SCAN_US: AtomicU16 = AtomicU16::new(0);
+static MAX_SCAN_US: AtomicU16 = AtomicU16::new(0);
+static MAX_ISR_US: AtomicU16 = AtomicU16::new(0);
+
+/// Snapshot of the scan timing measurements.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TimingReport {
+    scan_rate: u16,
+    last_scan_us: u16,
+    max_scan_us: u16,
+    max_isr_us: u16,
+}
+
+impl TimingReport {
+    /// Gets the achieved scan frequency in Hz, over the last [health] window.
+    pub const fn scan_rate(&self) -> u16 {
+        self.scan_rate
+    }
+
+    /// Gets the duration (in microseconds) of the last matrix scan.
+    pub const fn last_scan_us(&self) -> u16 {
+        self.last_scan_us
+    }
+
+    /// Gets the longest matrix scan duration (in microseconds).
+    pub const fn max_scan_us(&self) -> u16 {
+        self.max_scan_us
+    }
+
+    /// Gets the longest interrupt handler duration (in microseconds).
+    pub const fn max_isr_us(&self) -> u16 {
+        self.max_isr_us
+    }
+
+    /// Serializes the [TimingReport] for sending to the host.
+    ///
+    /// Fields are little-endian `u16`s, in the order of the getters.
+    pub fn to_bytes(&self) -> [u8; TIMING_REPORT_LEN] {
+        let mut out = [0u8; TIMING_REPORT_LEN];
+
+        for (chunk, val) in out.chunks_exact_mut(2).zip([
+            self.scan_rate,
+            self.last_scan_us,
+            self.max_scan_us,
+            self.max_isr_us,
+        ]) {
+            chunk.copy_from_slice(val.to_le_bytes().as_ref());
+        }
+
+        out
+    }
+}
+
+/// Records the duration (in microseconds) of a matrix scan.
+pub fn record_scan_us(us: u16) {
+    LAST_SCAN_US.store(us, Ordering::SeqCst);
+
+    if us > MAX_SCAN_US.load(Ordering::Relaxed) {
+        MAX_SCAN_US.store(us, Ordering::SeqCst);
+    }
+}
+
+/// Records the duration (in microseconds) of an interrupt handler.
+pub fn record_isr_us(us: u16) {
+    if us > MAX_ISR_US.load(Ordering::Relaxed) {
+        MAX_ISR_US.store(us, Ordering::SeqCst);
+    }
+}
+
+/// Gets the current [TimingReport].
+pub fn timing_report() -> TimingReport {
+    TimingReport {
+        scan_rate: health::health_report().scan_rate(),
+        last_scan_us: LAST_SCAN_US.load(Ordering::Relaxed),
+        max_scan_us: MAX_SCAN_US.load(Ordering::Relaxed),
+        max_isr_us: MAX_ISR_US.load(Ordering::Relaxed),
+    }
+}
+
+/// Resets the worst-case durations, e.g. after changing the scan configuration.
+pub fn reset_max() {
+    MAX_SCAN_US.store(0, Ordering::SeqCst);
+    MAX_ISR_US.store(0, Ordering::SeqCst);
+}
+
+/// Handles the [GetTiming](crate::config::Command::GetTiming) configuration protocol command.
+///
+/// Returns the number of bytes written.
+pub fn handle_timing_command(buf: &mut [u8]) -> Result<usize, ConfigError> {
+    buf.get_mut(..TIMING_REPORT_LEN)
+        .ok_or(ConfigError::BufferTooSmall)?
+        .copy_from_slice(timing_report().to_bytes().as_ref());
+
+    Ok(TIMING_REPORT_LEN)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timing_report() {
+        record_scan_us(300);
+        record_scan_us(120);
+        record_isr_us(40);
+        record_isr_us(25);
+
+        let report = timing_report();
+        assert_eq!(report.last_scan_us(), 120);
+        assert_eq!(report.max_scan_us(), 300);
+        assert_eq!(report.max_isr_us(), 40);
+
+        let mut buf = [0u8; TIMING_REPORT_LEN];
+        assert_eq!(handle_timing_command(&mut buf), Ok(TIMING_REPORT_LEN));
+        assert_eq!(buf[2..], [120, 0, 44, 1, 40, 0]);
+        assert_eq!(
+            handle_timing_command(&mut buf[..4]),
+            Err(ConfigError::BufferTooSmall)
+        );
+
+        reset_max();
+        assert_eq!(timing_report().max_isr_us(), 0);
+    }
+}
@@ -0,0 +1,190 @@
+//! Per-board tuning parameters.
+//!
+//! Timing that depends on the switches and the matrix wiring can be tuned per keyboard without
+//! recompiling. The running values live here: the firmware applies the matrix timing to the key
+//! scanner, and the speeds are kept for the [MouseKeys](crate::features::Feature::MouseKeys)
+//! feature. Host tools read and change them with the [GetTuning](Command::GetTuning) and
+//! [SetTuning](Command::SetTuning) raw HID commands, or the Focus commands named after each
+//! [Tuning], e.g. `tuning.debounce 8`.
+//!
+//! Changes are persisted to the [settings](crate::settings) block like the other runtime state.
+
+use core::sync::atomic::{AtomicU16, Ordering};
+
+use crate::config::{Command, ConfigError};
+use crate::debounce::DEFAULT_DEBOUNCE_MS;
+use crate::scanner::DEFAULT_SETTLE_US;
+use crate::tap_hold::TAPPING_TERM_MS;
+
+/// Default distance (in pixels) a mouse key moves the cursor per report.
+pub const DEFAULT_MOUSE_SPEED: u16 = 8;
+/// Default number of wheel steps a mouse key scrolls per report.
+pub const DEFAULT_WHEEL_SPEED: u16 = 1;
+
+/// Represents a tuning parameter.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tuning {
+    /// Debounce window in milliseconds.
+    DebounceMs = 0,
+    /// Time (in microseconds) for the matrix inputs to settle before they are read.
+    SettleUs = 1,
+    /// Time (in milliseconds) between taps to count as a double-tap, or before a tap becomes a
+    /// hold.
+    TappingTermMs = 2,
+    /// Distance (in pixels) a mouse key moves the cursor per report.
+    MouseSpeed = 3,
+    /// Number of wheel steps a mouse key scrolls per report.
+    WheelSpeed = 4,
+}
+
+impl Tuning {
+    /// Every tuning parameter.
+    pub const ALL: [Self; 5] = [
+        Self::DebounceMs,
+        Self::SettleUs,
+        Self::TappingTermMs,
+        Self::MouseSpeed,
+        Self::WheelSpeed,
+    ];
+
+    /// Gets the compiled-in default value.
+    pub const fn default_value(&self) -> u16 {
+        match self {
+            Self::DebounceMs => DEFAULT_DEBOUNCE_MS,
+            Self::SettleUs => DEFAULT_SETTLE_US,
+            Self::TappingTermMs => TAPPING_TERM_MS,
+            Self::MouseSpeed => DEFAULT_MOUSE_SPEED,
+            Self::WheelSpeed => DEFAULT_WHEEL_SPEED,
+        }
+    }
+
+    /// Gets the highest valid value.
+    ///
+    /// Values fit in the width stored in the settings block.
+    pub const fn max_value(&self) -> u16 {
+        match self {
+            Self::DebounceMs => 50,
+            Self::SettleUs => 1000,
+            Self::TappingTermMs => 1000,
+            Self::MouseSpeed => 127,
+            Self::WheelSpeed => 16,
+        }
+    }
+
+    /// Gets whether the `val` is in range.
+    pub const fn is_valid(&self, val: u16) -> bool {
+        val <= self.max_value()
+    }
+
+    /// Gets the name of the Focus command that reads and sets the parameter.
+    pub const fn focus_name(&self) -> &'static str {
+        match self {
+            Self::DebounceMs => "tuning.debounce",
+            Self::SettleUs => "tuning.settle",
+            Self::TappingTermMs => "tuning.tappingTerm",
+            Self::MouseSpeed => "mousekeys.speed",
+            Self::WheelSpeed => "mousekeys.wheelSpeed",
+        }
+    }
+}
+
+impl TryFrom<u8> for Tuning {
+    type Error = ConfigError;
+
+    fn try_from(val: u8) -> Result<Self, Self::Error> {
+        Self::ALL
+            .get(val as usize)
+            .copied()
+            .ok_or(ConfigError::InvalidArgument)
+    }
+}
+
+impl From<Tuning> for u8 {
+    fn from(val: Tuning) -> Self {
+        val as u8
+    }
+}
+
+/// Running values of the tuning parameters, in the order of [Tuning::ALL].
+static VALUES: [AtomicU16; Tuning::ALL.len()] = [
+    AtomicU16::new(Tuning::DebounceMs.default_value()),
+    AtomicU16::new(Tuning::SettleUs.default_value()),
+    AtomicU16::new(Tuning::TappingTermMs.default_value()),
+    AtomicU16::new(Tuning::MouseSpeed.default_value()),
+    AtomicU16::new(Tuning::WheelSpeed.default_value()),
+];
+
+/// Gets the running value of a [Tuning] parameter.
+pub fn tuning(param: Tuning) -> u16 {
+    VALUES[param as usize].load(Ordering::Relaxed)
+}
+
+/// Sets the running value of a [Tuning] parameter.
+///
+/// Returns [ConfigError::InvalidArgument] if the `val` is out of range.
+pub fn set_tuning(param: Tuning, val: u16) -> Result<(), ConfigError> {
+    if param.is_valid(val) {
+        VALUES[param as usize].store(val, Ordering::SeqCst);
+        Ok(())
+    } else {
+        Err(ConfigError::InvalidArgument)
+    }
+}
+
+/// Handles the [GetTuning](Command::GetTuning) and [SetTuning](Command::SetTuning) commands.
+///
+/// - `GetTuning`: `| parameter |`, responds with `| value (u16) |`
+/// - `SetTuning`: `| parameter | value (u16) |`, responds without data
+pub fn handle_tuning_command(
+    command: Command,
+    args: &[u8],
+    buf: &mut [u8],
+) -> Result<usize, ConfigError> {
+    match (command, args) {
+        (Command::GetTuning, [param, ..]) => {
+            let out = buf.get_mut(..2).ok_or(ConfigError::BufferTooSmall)?;
+
+            out.copy_from_slice(&tuning(Tuning::try_from(*param)?).to_le_bytes());
+            Ok(2)
+        }
+        (Command::SetTuning, [param, lo, hi, ..]) => {
+            set_tuning(Tuning::try_from(*param)?, u16::from_le_bytes([*lo, *hi]))?;
+            Ok(0)
+        }
+        (Command::GetTuning | Command::SetTuning, _) => Err(ConfigError::InvalidArgument),
+        _ => Err(ConfigError::UnhandledCommand(command)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tuning_commands() {
+        let mut buf = [0u8; 2];
+
+        assert_eq!(
+            handle_tuning_command(Command::SetTuning, &[2, 0x2c, 0x01], &mut buf),
+            Ok(0)
+        );
+        assert_eq!(tuning(Tuning::TappingTermMs), 300);
+        assert_eq!(
+            handle_tuning_command(Command::GetTuning, &[2], &mut buf),
+            Ok(2)
+        );
+        assert_eq!(buf, [0x2c, 0x01]);
+
+        assert_eq!(
+            handle_tuning_command(Command::SetTuning, &[0, 51, 0], &mut buf),
+            Err(ConfigError::InvalidArgument)
+        );
+        assert_eq!(
+            handle_tuning_command(Command::GetTuning, &[5], &mut buf),
+            Err(ConfigError::InvalidArgument)
+        );
+        assert_eq!(tuning(Tuning::DebounceMs), DEFAULT_DEBOUNCE_MS);
+        assert_eq!(Tuning::try_from(4), Ok(Tuning::WheelSpeed));
+    }
+}
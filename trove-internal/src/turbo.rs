@@ -0,0 +1,122 @@
+//! Turbo (rapid-fire) key handling.
+//!
+//! While the [TURBO](crate::layers::TURBO) key is held, other held keys are repeatedly pressed
+//! and released at a configurable rate.
+//!
+//! The rate is set in milliseconds, and timed by the scan clock (see [time](crate::time)), so it
+//! does not change with the scan interval, e.g. when the
+//! [adaptive scan rate](crate::scan_rate) slows down scanning.
+
+/// Default time (in milliseconds) that keys stay pressed (and released) in each turbo cycle.
+pub const TURBO_HALF_PERIOD_MS: u16 = 30;
+
+/// Turbo state, updated once per matrix scan.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Turbo {
+    half_period_ms: u16,
+    phase_start: Option<u16>,
+    pressed: bool,
+}
+
+impl Turbo {
+    /// Creates a new [Turbo] with the default rate.
+    pub const fn new() -> Self {
+        Self {
+            half_period_ms: TURBO_HALF_PERIOD_MS,
+            phase_start: None,
+            pressed: true,
+        }
+    }
+
+    /// Gets the time (in milliseconds) that keys stay pressed (and released) in each turbo cycle.
+    pub const fn half_period_ms(&self) -> u16 {
+        self.half_period_ms
+    }
+
+    /// Sets the time (in milliseconds) that keys stay pressed (and released) in each turbo cycle.
+    ///
+    /// The full press/release cycle takes twice as long. Each phase lasts at least one scan.
+    pub fn set_half_period_ms(&mut self, val: u16) {
+        self.half_period_ms = val.max(1);
+    }
+
+    /// Builder function that sets the turbo half period in milliseconds.
+    pub fn with_half_period_ms(mut self, val: u16) -> Self {
+        self.set_half_period_ms(val);
+        self
+    }
+
+    /// Updates the [Turbo] state for a matrix scan at the scan clock time `now_ms`.
+    ///
+    /// Returns whether held keys should be reported as pressed during this scan. The first scan
+    /// with turbo held always reports keys as pressed.
+    pub fn update(&mut self, turbo_held: bool, now_ms: u16) -> bool {
+        if !turbo_held {
+            self.phase_start = None;
+            self.pressed = true;
+            return true;
+        }
+
+        let start = *self.phase_start.get_or_insert(now_ms);
+        let elapsed = now_ms.wrapping_sub(start);
+
+        if elapsed >= self.half_period_ms {
+            self.pressed = !self.pressed;
+            // keep the rate when a scan is late, but restart the phase after a long pause
+            self.phase_start = Some(if elapsed >= 2 * self.half_period_ms {
+                now_ms
+            } else {
+                start.wrapping_add(self.half_period_ms)
+            });
+        }
+
+        self.pressed
+    }
+}
+
+impl Default for Turbo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_turbo_cycle() {
+        let mut turbo = Turbo::new().with_half_period_ms(2);
+
+        assert!(turbo.update(false, 0));
+
+        let cycle: [bool; 6] = core::array::from_fn(|i| turbo.update(true, 10 + i as u16));
+        assert_eq!(cycle, [true, true, false, false, true, true]);
+
+        // releasing turbo resets the cycle
+        assert!(turbo.update(false, 20));
+        assert!(turbo.update(true, 21));
+    }
+
+    #[test]
+    fn test_turbo_scan_interval() {
+        // the cycle takes the same time with fast and slow scans
+        for interval in [1, 3, 5] {
+            let mut turbo = Turbo::new().with_half_period_ms(15);
+
+            let released = (0..60u16)
+                .step_by(interval)
+                .map(|t| (t, turbo.update(true, (u16::MAX - 10).wrapping_add(t))))
+                .find(|&(_, pressed)| !pressed);
+
+            assert_eq!(released, Some((15, false)));
+
+            let pressed = (15..60u16)
+                .step_by(interval)
+                .map(|t| (t, turbo.update(true, (u16::MAX - 10).wrapping_add(t))))
+                .find(|&(_, pressed)| pressed);
+
+            assert_eq!(pressed, Some((30, true)));
+        }
+    }
+}
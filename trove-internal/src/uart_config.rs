@@ -0,0 +1,202 @@
+//! UART frame format and baud rate settings.
+//!
+//! The USART1 of the ATmega32u4 links split halves, talks steno protocols, and logs debug text on
+//! boards without the USB-CDC port. A [UartConfig] holds the baud rate and frame format, and
+//! converts them into the register values, picking the clock mode with the smaller baud rate
+//! error.
+
+/// Default baud rate.
+pub const DEFAULT_BAUD_RATE: u32 = 115_200;
+/// Largest baud rate register value, the register is 12 bits wide.
+pub const MAX_UBRR: u16 = 0x0fff;
+
+// Character size bits of `UCSR1C`, always 8 data bits.
+const UCSZ_8BIT: u8 = 0b11 << 1;
+// Stop bit select bit of `UCSR1C`.
+const USBS: u8 = 1 << 3;
+
+/// Represents the parity bit of a UART frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum UartParity {
+    /// No parity bit.
+    #[default]
+    None,
+    /// Even parity.
+    Even,
+    /// Odd parity.
+    Odd,
+}
+
+impl UartParity {
+    /// Gets the parity mode bits (`UPM1`) of `UCSR1C`.
+    pub const fn bits(&self) -> u8 {
+        match self {
+            Self::None => 0,
+            Self::Even => 0b10 << 4,
+            Self::Odd => 0b11 << 4,
+        }
+    }
+}
+
+/// Baud rate register values of a [UartConfig].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UartBaud {
+    /// Baud rate register (`UBRR1`) value.
+    pub ubrr: u16,
+    /// Whether the double speed bit (`U2X1`) is set.
+    pub double_speed: bool,
+}
+
+impl UartBaud {
+    /// Gets the baud rate generated at the CPU clock `f_cpu`.
+    pub const fn actual(&self, f_cpu: u32) -> u32 {
+        let divisor = if self.double_speed { 8 } else { 16 };
+
+        f_cpu / (divisor * (self.ubrr as u32 + 1))
+    }
+}
+
+/// Baud rate and frame format of the UART, always 8 data bits.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UartConfig {
+    baud_rate: u32,
+    parity: UartParity,
+    two_stop_bits: bool,
+}
+
+impl UartConfig {
+    /// Creates a new [UartConfig], at the [DEFAULT_BAUD_RATE] with 8N1 frames.
+    pub const fn new() -> Self {
+        Self {
+            baud_rate: DEFAULT_BAUD_RATE,
+            parity: UartParity::None,
+            two_stop_bits: false,
+        }
+    }
+
+    /// Gets the baud rate.
+    pub const fn baud_rate(&self) -> u32 {
+        self.baud_rate
+    }
+
+    /// Builder function that sets the baud rate.
+    pub const fn with_baud_rate(mut self, val: u32) -> Self {
+        self.baud_rate = val;
+        self
+    }
+
+    /// Gets the [UartParity].
+    pub const fn parity(&self) -> UartParity {
+        self.parity
+    }
+
+    /// Builder function that sets the [UartParity].
+    pub const fn with_parity(mut self, parity: UartParity) -> Self {
+        self.parity = parity;
+        self
+    }
+
+    /// Gets whether frames end with two stop bits.
+    pub const fn two_stop_bits(&self) -> bool {
+        self.two_stop_bits
+    }
+
+    /// Builder function that sets whether frames end with two stop bits.
+    pub const fn with_two_stop_bits(mut self, val: bool) -> Self {
+        self.two_stop_bits = val;
+        self
+    }
+
+    /// Gets the [UartBaud] closest to the baud rate at the CPU clock `f_cpu`.
+    ///
+    /// Normal speed samples each bit more often, so it is kept unless double speed is closer.
+    pub const fn baud(&self, f_cpu: u32) -> UartBaud {
+        let normal = self.ubrr(f_cpu, false);
+        let double = self.ubrr(f_cpu, true);
+
+        if self.error(&double, f_cpu) < self.error(&normal, f_cpu) {
+            double
+        } else {
+            normal
+        }
+    }
+
+    /// Gets the frame format register (`UCSR1C`) value.
+    pub const fn ucsr1c(&self) -> u8 {
+        let stop = if self.two_stop_bits { USBS } else { 0 };
+
+        UCSZ_8BIT | stop | self.parity.bits()
+    }
+
+    // Gets the rounded baud rate register value for a clock mode.
+    const fn ubrr(&self, f_cpu: u32, double_speed: bool) -> UartBaud {
+        let samples = if double_speed { 8 } else { 16 };
+        let divisor = samples
+            * if self.baud_rate > 0 {
+                self.baud_rate
+            } else {
+                1
+            };
+        let counts = (f_cpu + divisor / 2) / divisor;
+        let counts = if counts == 0 {
+            1
+        } else if counts > MAX_UBRR as u32 + 1 {
+            MAX_UBRR as u32 + 1
+        } else {
+            counts
+        };
+
+        UartBaud {
+            ubrr: (counts - 1) as u16,
+            double_speed,
+        }
+    }
+
+    // Gets the difference from the baud rate.
+    const fn error(&self, baud: &UartBaud, f_cpu: u32) -> u32 {
+        baud.actual(f_cpu).abs_diff(self.baud_rate)
+    }
+}
+
+impl Default for UartConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const F_CPU: u32 = 16_000_000;
+
+    #[test]
+    fn test_uart_config() {
+        let config = UartConfig::new();
+        let baud = config.baud(F_CPU);
+        assert_eq!(
+            baud,
+            UartBaud {
+                ubrr: 16,
+                double_speed: true
+            }
+        );
+        assert_eq!(baud.actual(F_CPU), 117_647);
+        assert_eq!(config.ucsr1c(), 0b0000_0110);
+
+        let config = UartConfig::new()
+            .with_baud_rate(9600)
+            .with_parity(UartParity::Odd)
+            .with_two_stop_bits(true);
+        assert_eq!(
+            config.baud(F_CPU),
+            UartBaud {
+                ubrr: 103,
+                double_speed: false
+            }
+        );
+        assert_eq!(config.ucsr1c(), 0b0011_1110);
+
+        assert_eq!(UartConfig::new().with_baud_rate(1).baud(F_CPU).ubrr, 4095);
+    }
+}
@@ -0,0 +1,387 @@
+//! USB descriptors in program memory.
+//!
+//! Constant data is copied from flash to SRAM at startup on AVR, unless it is placed in program
+//! memory, and the ATmega32u4 only has 2.5 KB of SRAM. The HID report descriptors, and the
+//! manufacturer and product strings of the [BOARD_USB_IDENTITY], are only read when the host
+//! enumerates the keyboard, so they stay in program memory. A [Descriptor] is
+//! [loaded](Descriptor::load) into a buffer on the stack to answer the `GET_DESCRIPTOR` request.
+//!
+//! The HID classes are given a [placeholder](Descriptor::placeholder) of the same length instead
+//! of the report descriptor, which they only use for the length in the HID descriptor. The
+//! [DescriptorTable] tells the USB context which descriptor belongs to which interface.
+
+use crate::gamepad::GAMEPAD_REPORT_DESCRIPTOR;
+use crate::raw_hid::RAW_HID_REPORT_DESCRIPTOR;
+use crate::shared_report::SHARED_REPORT_DESCRIPTOR;
+use crate::usb_identity::BOARD_USB_IDENTITY;
+
+/// HID report descriptor type, in the high byte of a `GET_DESCRIPTOR` request value.
+pub const REPORT_DESCRIPTOR_TYPE: u8 = 0x22;
+/// String descriptor type, in the high byte of a `GET_DESCRIPTOR` request value.
+pub const STRING_DESCRIPTOR_TYPE: u8 = 0x03;
+/// String index of the manufacturer, as assigned by the USB device.
+pub const MANUFACTURER_STRING_INDEX: u8 = 1;
+/// String index of the product, as assigned by the USB device.
+pub const PRODUCT_STRING_INDEX: u8 = 2;
+/// Maximum length of a loaded [Descriptor], the size of the control endpoint buffer.
+pub const MAX_DESCRIPTOR_LEN: usize = 128;
+/// Maximum number of HID interfaces in a [DescriptorTable].
+pub const MAX_HID_INTERFACES: usize = 4;
+
+/// HID report descriptor for the boot keyboard interface.
+///
+/// Same report layout as the `usbd_hid` `KeyboardReport`: an input report with the modifier
+/// bitfield, a reserved byte and six keycodes, and the LED output report.
+pub const KEYBOARD_REPORT_DESCRIPTOR: &[u8] = &[
+    0x05, 0x01, // Usage Page (Generic Desktop)
+    0x09, 0x06, // Usage (Keyboard)
+    0xa1, 0x01, // Collection (Application)
+    0x05, 0x07, //   Usage Page (Keyboard/Keypad)
+    0x19, 0xe0, //   Usage Minimum (0xe0)
+    0x29, 0xe7, //   Usage Maximum (0xe7)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x25, 0x01, //   Logical Maximum (1)
+    0x75, 0x01, //   Report Size (1)
+    0x95, 0x08, //   Report Count (8)
+    0x81, 0x02, //   Input (Data, Variable, Absolute)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x01, //   Report Count (1)
+    0x81, 0x01, //   Input (Constant)
+    0x05, 0x08, //   Usage Page (LEDs)
+    0x19, 0x01, //   Usage Minimum (1)
+    0x29, 0x05, //   Usage Maximum (5)
+    0x75, 0x01, //   Report Size (1)
+    0x95, 0x05, //   Report Count (5)
+    0x91, 0x02, //   Output (Data, Variable, Absolute)
+    0x75, 0x03, //   Report Size (3)
+    0x95, 0x01, //   Report Count (1)
+    0x91, 0x01, //   Output (Constant)
+    0x05, 0x07, //   Usage Page (Keyboard/Keypad)
+    0x19, 0x00, //   Usage Minimum (0)
+    0x29, 0xff, //   Usage Maximum (255)
+    0x15, 0x00, //   Logical Minimum (0)
+    0x26, 0xff, 0x00, //   Logical Maximum (255)
+    0x75, 0x08, //   Report Size (8)
+    0x95, 0x06, //   Report Count (6)
+    0x81, 0x00, //   Input (Data, Array, Absolute)
+    0xc0, // End Collection
+];
+
+/// Number of [Descriptor]s.
+const NUM_DESCRIPTORS: usize = 6;
+
+/// Data of every [Descriptor], in declaration order.
+const DESCRIPTOR_PARTS: [&[u8]; NUM_DESCRIPTORS] = [
+    KEYBOARD_REPORT_DESCRIPTOR,
+    SHARED_REPORT_DESCRIPTOR,
+    GAMEPAD_REPORT_DESCRIPTOR,
+    RAW_HID_REPORT_DESCRIPTOR,
+    BOARD_USB_IDENTITY.manufacturer().as_bytes(),
+    BOARD_USB_IDENTITY.product().as_bytes(),
+];
+
+/// Total length of the [Descriptor] data.
+const DESCRIPTORS_LEN: usize = parts_len(&DESCRIPTOR_PARTS, NUM_DESCRIPTORS);
+
+/// Length of the longest report descriptor.
+const MAX_REPORT_DESCRIPTOR_LEN: usize =
+    max_len(&DESCRIPTOR_PARTS, Descriptor::Manufacturer as usize);
+
+#[cfg(target_arch = "avr")]
+avr_progmem::progmem! {
+    /// Data of every [Descriptor], back to back.
+    static progmem DESCRIPTORS: [u8; DESCRIPTORS_LEN] = concat_parts(&DESCRIPTOR_PARTS);
+}
+
+/// Data of every [Descriptor], back to back.
+#[cfg(not(target_arch = "avr"))]
+static DESCRIPTORS: [u8; DESCRIPTORS_LEN] = concat_parts(&DESCRIPTOR_PARTS);
+
+/// Zeroed placeholder for the report descriptors given to the HID classes.
+static PLACEHOLDER: [u8; MAX_REPORT_DESCRIPTOR_LEN] = [0; MAX_REPORT_DESCRIPTOR_LEN];
+
+/// Represents a descriptor stored in program memory.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Descriptor {
+    /// Boot keyboard report descriptor, see [KEYBOARD_REPORT_DESCRIPTOR].
+    KeyboardReport = 0,
+    /// Shared interface report descriptor, see [shared_report](crate::shared_report).
+    SharedReport = 1,
+    /// Gamepad report descriptor, see [gamepad](crate::gamepad).
+    GamepadReport = 2,
+    /// Raw HID report descriptor, see [raw_hid](crate::raw_hid).
+    RawHidReport = 3,
+    /// Manufacturer string of the [BOARD_USB_IDENTITY].
+    Manufacturer = 4,
+    /// Product string of the [BOARD_USB_IDENTITY].
+    Product = 5,
+}
+
+impl Descriptor {
+    /// Gets the [Descriptor] of a string `index`.
+    ///
+    /// Returns `None` for any string not stored in program memory.
+    pub const fn from_string_index(index: u8) -> Option<Self> {
+        match index {
+            MANUFACTURER_STRING_INDEX => Some(Self::Manufacturer),
+            PRODUCT_STRING_INDEX => Some(Self::Product),
+            _ => None,
+        }
+    }
+
+    /// Gets the descriptor type sent by the host to request the descriptor.
+    pub const fn descriptor_type(&self) -> u8 {
+        match self {
+            Self::Manufacturer | Self::Product => STRING_DESCRIPTOR_TYPE,
+            _ => REPORT_DESCRIPTOR_TYPE,
+        }
+    }
+
+    /// Gets the length of the stored data.
+    ///
+    /// Strings are stored as UTF-8, so the loaded string descriptor is longer.
+    pub const fn len(&self) -> usize {
+        DESCRIPTOR_PARTS[*self as usize].len()
+    }
+
+    /// Gets whether the stored data is empty.
+    pub const fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Gets a zeroed placeholder of the same length as a report descriptor, for a HID class.
+    ///
+    /// Strings have an empty placeholder.
+    pub fn placeholder(&self) -> &'static [u8] {
+        match self.descriptor_type() {
+            REPORT_DESCRIPTOR_TYPE => &PLACEHOLDER[..self.len()],
+            _ => &[],
+        }
+    }
+
+    /// Loads the descriptor from program memory into the `buf`, as it is sent to the host.
+    ///
+    /// Strings are encoded as UTF-16 string descriptors, and cut off at the end of the `buf`.
+    pub fn load<'b>(&self, buf: &'b mut [u8; MAX_DESCRIPTOR_LEN]) -> &'b [u8] {
+        let offset = parts_len(&DESCRIPTOR_PARTS, *self as usize);
+        let len = self.len();
+
+        if self.descriptor_type() == REPORT_DESCRIPTOR_TYPE {
+            for (i, b) in buf.iter_mut().take(len).enumerate() {
+                *b = load_byte(offset + i);
+            }
+
+            return &buf[..len.min(MAX_DESCRIPTOR_LEN)];
+        }
+
+        // the UTF-8 string is loaded first, then encoded as UTF-16 behind the descriptor header
+        let mut utf8 = [0u8; MAX_DESCRIPTOR_LEN / 2];
+        let utf8_len = len.min(utf8.len());
+
+        for (i, b) in utf8.iter_mut().take(utf8_len).enumerate() {
+            *b = load_byte(offset + i);
+        }
+
+        let mut desc_len = 2;
+
+        for unit in core::str::from_utf8(&utf8[..utf8_len])
+            .unwrap_or_default()
+            .encode_utf16()
+        {
+            if desc_len + 2 > MAX_DESCRIPTOR_LEN {
+                break;
+            }
+
+            buf[desc_len..desc_len + 2].copy_from_slice(&unit.to_le_bytes());
+            desc_len += 2;
+        }
+
+        buf[0] = desc_len as u8;
+        buf[1] = STRING_DESCRIPTOR_TYPE;
+
+        &buf[..desc_len]
+    }
+}
+
+/// Assigns report [Descriptor]s to HID interfaces, and tells whether the manufacturer and product
+/// strings are served from program memory.
+///
+/// Interface numbers are assigned in allocation order, so the HID classes must be allocated
+/// first, in the order their descriptors are added.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DescriptorTable {
+    reports: [Option<Descriptor>; MAX_HID_INTERFACES],
+    strings: bool,
+}
+
+impl DescriptorTable {
+    /// Creates a new [DescriptorTable], without any descriptor.
+    pub const fn new() -> Self {
+        Self {
+            reports: [None; MAX_HID_INTERFACES],
+            strings: false,
+        }
+    }
+
+    /// Builder function that adds the report [Descriptor] of the next HID interface.
+    ///
+    /// Descriptors beyond [MAX_HID_INTERFACES] are left out.
+    pub const fn with_report(mut self, descriptor: Descriptor) -> Self {
+        let mut i = 0;
+
+        while i < MAX_HID_INTERFACES {
+            if self.reports[i].is_none() {
+                self.reports[i] = Some(descriptor);
+                break;
+            }
+            i += 1;
+        }
+
+        self
+    }
+
+    /// Gets whether the manufacturer and product strings are served from program memory.
+    pub const fn strings(&self) -> bool {
+        self.strings
+    }
+
+    /// Builder function that sets whether the manufacturer and product strings are served from
+    /// program memory.
+    ///
+    /// The USB device should then be given empty strings, so only their indexes are allocated.
+    pub const fn with_strings(mut self, val: bool) -> Self {
+        self.strings = val;
+        self
+    }
+
+    /// Gets the report [Descriptor] of the HID `interface`.
+    pub fn report(&self, interface: u16) -> Option<Descriptor> {
+        self.reports.get(interface as usize).copied().flatten()
+    }
+
+    /// Gets the [Descriptor] of a string `index`, if the strings are served from program memory.
+    pub fn string(&self, index: u8) -> Option<Descriptor> {
+        self.strings
+            .then(|| Descriptor::from_string_index(index))
+            .flatten()
+    }
+}
+
+impl Default for DescriptorTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Reads one byte of the descriptor data.
+fn load_byte(index: usize) -> u8 {
+    #[cfg(target_arch = "avr")]
+    let b = DESCRIPTORS.load_at(index);
+    #[cfg(not(target_arch = "avr"))]
+    let b = DESCRIPTORS[index];
+
+    b
+}
+
+// Sums the length of the first `count` parts.
+const fn parts_len(parts: &[&[u8]], count: usize) -> usize {
+    let mut len = 0;
+    let mut i = 0;
+
+    while i < count {
+        len += parts[i].len();
+        i += 1;
+    }
+
+    len
+}
+
+// Gets the length of the longest of the first `count` parts.
+const fn max_len(parts: &[&[u8]], count: usize) -> usize {
+    let mut len = 0;
+    let mut i = 0;
+
+    while i < count {
+        if parts[i].len() > len {
+            len = parts[i].len();
+        }
+        i += 1;
+    }
+
+    len
+}
+
+// Copies the parts back to back.
+const fn concat_parts<const N: usize>(parts: &[&[u8]]) -> [u8; N] {
+    let mut buf = [0u8; N];
+    let mut offset = 0;
+    let mut i = 0;
+
+    while i < parts.len() {
+        let mut j = 0;
+
+        while j < parts[i].len() {
+            buf[offset] = parts[i][j];
+            offset += 1;
+            j += 1;
+        }
+        i += 1;
+    }
+
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_descriptors() {
+        let mut buf = [0u8; MAX_DESCRIPTOR_LEN];
+
+        assert_eq!(
+            Descriptor::KeyboardReport.load(&mut buf),
+            KEYBOARD_REPORT_DESCRIPTOR
+        );
+        assert_eq!(
+            Descriptor::SharedReport.load(&mut buf),
+            SHARED_REPORT_DESCRIPTOR
+        );
+        assert_eq!(
+            Descriptor::RawHidReport.load(&mut buf),
+            RAW_HID_REPORT_DESCRIPTOR
+        );
+        assert_eq!(
+            Descriptor::GamepadReport.placeholder().len(),
+            GAMEPAD_REPORT_DESCRIPTOR.len()
+        );
+        assert!(Descriptor::Product.placeholder().is_empty());
+
+        let manufacturer = Descriptor::Manufacturer.load(&mut buf);
+        assert_eq!(
+            manufacturer[..6],
+            [22, STRING_DESCRIPTOR_TYPE, b'K', 0, b'e', 0]
+        );
+        assert_eq!(manufacturer.len(), 22);
+    }
+
+    #[test]
+    fn test_descriptor_table() {
+        let table = DescriptorTable::new()
+            .with_report(Descriptor::KeyboardReport)
+            .with_report(Descriptor::SharedReport);
+
+        assert_eq!(table.report(1), Some(Descriptor::SharedReport));
+        assert_eq!(table.report(2), None);
+        assert_eq!(table.report(9), None);
+        assert_eq!(table.string(PRODUCT_STRING_INDEX), None);
+
+        let table = table.with_strings(true);
+        assert_eq!(
+            table.string(PRODUCT_STRING_INDEX),
+            Some(Descriptor::Product)
+        );
+        assert_eq!(table.string(3), None);
+    }
+}
@@ -0,0 +1,115 @@
+//! USB device identity.
+//!
+//! The vendor and product IDs, the manufacturer and product strings, and the device release
+//! number tell the host which keyboard is attached. Each board has its own [UsbIdentity], and the
+//! firmware presents the [BOARD_USB_IDENTITY], so forks for other keyboards only change the board
+//! configuration instead of the firmware entry point.
+
+/// Default device release number, in binary-coded decimal (`0.1.0`).
+pub const DEFAULT_DEVICE_RELEASE: u16 = 0x0010;
+
+/// USB identity of the Keyboardio Atreus running trove.
+pub const ATREUS_USB_IDENTITY: UsbIdentity = UsbIdentity::new(0x1209, 0x2303)
+    .with_manufacturer("Keyboardio")
+    .with_product("Trove Atreus");
+
+/// USB identity presented by the firmware.
+///
+/// Forks for other keyboards set their own identity here, and should use a product ID allocated
+/// to them.
+pub const BOARD_USB_IDENTITY: UsbIdentity = ATREUS_USB_IDENTITY;
+
+/// Represents the identity a keyboard presents to the USB host.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UsbIdentity {
+    vid: u16,
+    pid: u16,
+    manufacturer: &'static str,
+    product: &'static str,
+    device_release: u16,
+}
+
+impl UsbIdentity {
+    /// Creates a new [UsbIdentity] from the vendor and product IDs.
+    ///
+    /// The strings are empty, and the release is the [DEFAULT_DEVICE_RELEASE].
+    pub const fn new(vid: u16, pid: u16) -> Self {
+        Self {
+            vid,
+            pid,
+            manufacturer: "",
+            product: "",
+            device_release: DEFAULT_DEVICE_RELEASE,
+        }
+    }
+
+    /// Gets the USB vendor ID.
+    pub const fn vid(&self) -> u16 {
+        self.vid
+    }
+
+    /// Gets the USB product ID.
+    pub const fn pid(&self) -> u16 {
+        self.pid
+    }
+
+    /// Gets the manufacturer string.
+    pub const fn manufacturer(&self) -> &'static str {
+        self.manufacturer
+    }
+
+    /// Builder function that sets the manufacturer string.
+    pub const fn with_manufacturer(mut self, val: &'static str) -> Self {
+        self.manufacturer = val;
+        self
+    }
+
+    /// Gets the product string.
+    pub const fn product(&self) -> &'static str {
+        self.product
+    }
+
+    /// Builder function that sets the product string.
+    pub const fn with_product(mut self, val: &'static str) -> Self {
+        self.product = val;
+        self
+    }
+
+    /// Gets the device release number, in binary-coded decimal.
+    pub const fn device_release(&self) -> u16 {
+        self.device_release
+    }
+
+    /// Builder function that sets the device release number, in binary-coded decimal.
+    pub const fn with_device_release(mut self, val: u16) -> Self {
+        self.device_release = val;
+        self
+    }
+}
+
+impl Default for UsbIdentity {
+    fn default() -> Self {
+        BOARD_USB_IDENTITY
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usb_identity() {
+        let identity = UsbIdentity::new(0x1209, 0x0001)
+            .with_manufacturer("Handwired")
+            .with_product("Trove Split")
+            .with_device_release(0x0120);
+
+        assert_eq!((identity.vid(), identity.pid()), (0x1209, 0x0001));
+        assert_eq!(identity.manufacturer(), "Handwired");
+        assert_eq!(identity.product(), "Trove Split");
+        assert_eq!(identity.device_release(), 0x0120);
+
+        assert_eq!(UsbIdentity::default(), ATREUS_USB_IDENTITY);
+        assert_eq!(ATREUS_USB_IDENTITY.device_release(), DEFAULT_DEVICE_RELEASE);
+    }
+}
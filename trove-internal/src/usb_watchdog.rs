@@ -0,0 +1,206 @@
+//! USB error-recovery watchdog.
+//!
+//! Flaky hubs and KVM switches can glitch the bus, and leave the USB controller wedged: the host
+//! stops polling the keyboard endpoint, or enumeration never finishes. The keyboard then needs a
+//! replug, although the firmware is still running.
+//!
+//! The [UsbWatchdog] is checked after every matrix scan, and asks for a re-enumeration when:
+//!
+//! - reports stay queued for the [stall timeout](UsbWatchdog::stall_ms) on a configured device
+//! - a bus reset is not followed by the configuration within the
+//!   [enumeration timeout](UsbWatchdog::enumeration_ms)
+//!
+//! A suspended bus is never treated as wedged. Repeated recoveries back off, doubling the
+//! timeouts up to [MAX_BACKOFF_SHIFT] times, so a host that is genuinely not talking (e.g. a
+//! charger) is not reset continuously.
+
+/// Default time (in milliseconds) that reports can stay queued on a configured device.
+pub const DEFAULT_STALL_MS: u16 = 1000;
+/// Default time (in milliseconds) that enumeration can take after a bus reset.
+pub const DEFAULT_ENUMERATION_MS: u16 = 3000;
+/// Maximum number of times the timeouts are doubled after consecutive recoveries.
+pub const MAX_BACKOFF_SHIFT: u8 = 3;
+
+/// Represents the state of the USB device, as seen by the [UsbWatchdog].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum UsbStatus {
+    /// The device is enumerating, after attaching or a bus reset.
+    #[default]
+    Enumerating,
+    /// The host configured the device.
+    Configured,
+    /// The host suspended the bus.
+    Suspended,
+}
+
+/// Detects a wedged USB device.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UsbWatchdog {
+    stall_ms: u16,
+    enumeration_ms: u16,
+    busy_since_ms: Option<u16>,
+    enumerating_since_ms: Option<u16>,
+    backoff: u8,
+}
+
+impl UsbWatchdog {
+    /// Creates a new [UsbWatchdog] with the [DEFAULT_STALL_MS] and [DEFAULT_ENUMERATION_MS].
+    pub const fn new() -> Self {
+        Self {
+            stall_ms: DEFAULT_STALL_MS,
+            enumeration_ms: DEFAULT_ENUMERATION_MS,
+            busy_since_ms: None,
+            enumerating_since_ms: None,
+            backoff: 0,
+        }
+    }
+
+    /// Gets the time (in milliseconds) that reports can stay queued on a configured device.
+    pub const fn stall_ms(&self) -> u16 {
+        self.stall_ms
+    }
+
+    /// Sets the time (in milliseconds) that reports can stay queued on a configured device.
+    pub fn set_stall_ms(&mut self, val: u16) {
+        self.stall_ms = val;
+    }
+
+    /// Builder function that sets the time (in milliseconds) that reports can stay queued on a
+    /// configured device.
+    pub fn with_stall_ms(mut self, val: u16) -> Self {
+        self.set_stall_ms(val);
+        self
+    }
+
+    /// Gets the time (in milliseconds) that enumeration can take after a bus reset.
+    pub const fn enumeration_ms(&self) -> u16 {
+        self.enumeration_ms
+    }
+
+    /// Sets the time (in milliseconds) that enumeration can take after a bus reset.
+    pub fn set_enumeration_ms(&mut self, val: u16) {
+        self.enumeration_ms = val;
+    }
+
+    /// Builder function that sets the time (in milliseconds) that enumeration can take after a
+    /// bus reset.
+    pub fn with_enumeration_ms(mut self, val: u16) -> Self {
+        self.set_enumeration_ms(val);
+        self
+    }
+
+    /// Gets the number of consecutive recoveries the timeouts are backed off for.
+    pub const fn backoff(&self) -> u8 {
+        self.backoff
+    }
+
+    /// Checks the USB device `status` at the scan clock `now_ms`.
+    ///
+    /// `busy` is whether reports are still queued, waiting for the host. Returns `true` if the
+    /// device is wedged, and should be re-enumerated.
+    pub fn check(&mut self, status: UsbStatus, busy: bool, now_ms: u16) -> bool {
+        let wedged = match status {
+            UsbStatus::Enumerating => {
+                let timeout = self.timeout(self.enumeration_ms);
+
+                self.busy_since_ms = None;
+                Self::expired(&mut self.enumerating_since_ms, timeout, now_ms)
+            }
+            UsbStatus::Configured => {
+                self.enumerating_since_ms = None;
+
+                if busy {
+                    let timeout = self.timeout(self.stall_ms);
+
+                    Self::expired(&mut self.busy_since_ms, timeout, now_ms)
+                } else {
+                    // the host is polling again
+                    self.busy_since_ms = None;
+                    self.backoff = 0;
+                    false
+                }
+            }
+            UsbStatus::Suspended => {
+                self.busy_since_ms = None;
+                self.enumerating_since_ms = None;
+                false
+            }
+        };
+
+        if wedged {
+            self.busy_since_ms = None;
+            self.enumerating_since_ms = None;
+            self.backoff = self.backoff.saturating_add(1).min(MAX_BACKOFF_SHIFT);
+        }
+
+        wedged
+    }
+
+    // Gets the `timeout` backed off for the consecutive recoveries.
+    fn timeout(&self, timeout: u16) -> u16 {
+        ((timeout as u32) << self.backoff).min(u16::MAX as u32) as u16
+    }
+
+    // Starts the `since` timer, and gets whether it ran for the `timeout`.
+    fn expired(since: &mut Option<u16>, timeout: u16, now_ms: u16) -> bool {
+        now_ms.wrapping_sub(*since.get_or_insert(now_ms)) >= timeout
+    }
+}
+
+impl Default for UsbWatchdog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_usb_watchdog_stall() {
+        let mut watchdog = UsbWatchdog::new().with_stall_ms(100);
+
+        // reports are sent normally
+        assert!(!watchdog.check(UsbStatus::Configured, true, 0));
+        assert!(!watchdog.check(UsbStatus::Configured, false, 50));
+        assert!(!watchdog.check(UsbStatus::Configured, true, 60));
+        assert!(!watchdog.check(UsbStatus::Configured, true, 159));
+
+        // the endpoint never became ready
+        assert!(watchdog.check(UsbStatus::Configured, true, 160));
+        assert_eq!(watchdog.backoff(), 1);
+
+        // the timeout doubles after a recovery
+        assert!(!watchdog.check(UsbStatus::Configured, true, 200));
+        assert!(!watchdog.check(UsbStatus::Configured, true, 399));
+        assert!(watchdog.check(UsbStatus::Configured, true, 400));
+        assert_eq!(watchdog.backoff(), 2);
+
+        // the backoff is cleared once reports are sent again
+        assert!(!watchdog.check(UsbStatus::Configured, false, 500));
+        assert_eq!(watchdog.backoff(), 0);
+    }
+
+    #[test]
+    fn test_usb_watchdog_enumeration() {
+        let mut watchdog = UsbWatchdog::new().with_enumeration_ms(1000);
+
+        assert!(!watchdog.check(UsbStatus::Enumerating, false, 65000));
+        assert!(!watchdog.check(UsbStatus::Enumerating, false, 400));
+        // the scan clock wraps around
+        assert!(watchdog.check(UsbStatus::Enumerating, false, 464));
+
+        // a suspended bus is never wedged
+        assert!(!watchdog.check(UsbStatus::Suspended, true, 0));
+        assert!(!watchdog.check(UsbStatus::Suspended, true, 30000));
+        assert!(!watchdog.check(UsbStatus::Configured, true, 30000));
+
+        // the backoff is capped
+        for _ in 0..8 {
+            watchdog.check(UsbStatus::Enumerating, false, 0);
+            watchdog.check(UsbStatus::Enumerating, false, u16::MAX);
+        }
+        assert_eq!(watchdog.backoff(), MAX_BACKOFF_SHIFT);
+    }
+}
@@ -0,0 +1,111 @@
+//! Typing speed.
+//!
+//! [count_key_event] is a [KeyEventHook](crate::hooks::KeyEventHook) counting the presses of
+//! typing keys. A [WpmMeter] samples the count from the main loop, and estimates the words per
+//! minute over the last [WPM_WINDOW_S] seconds, with five key presses to a word.
+
+use core::sync::atomic::{AtomicU16, Ordering};
+
+use crate::events::KeyEvent;
+use crate::layers;
+
+/// Length (in seconds) of the window the typing speed is measured over.
+pub const WPM_WINDOW_S: usize = 10;
+/// Key presses counted as a word.
+pub const KEYS_PER_WORD: u16 = 5;
+
+static KEY_PRESSES: AtomicU16 = AtomicU16::new(0);
+
+/// Counts a press of a typing key, register as a [KeyEventHook](crate::hooks::KeyEventHook).
+///
+/// Modifiers, and keys without a keycode (e.g. layer keys), are not counted.
+pub fn count_key_event(event: &KeyEvent, key: u8) {
+    if event.pressed() && key != 0 && !layers::key_is_modifier(key) {
+        KEY_PRESSES.store(key_presses().wrapping_add(1), Ordering::SeqCst);
+    }
+}
+
+/// Gets the (wrapping) number of typing key presses counted.
+pub fn key_presses() -> u16 {
+    KEY_PRESSES.load(Ordering::Relaxed)
+}
+
+/// Estimates the typing speed from the key press count, one-second buckets at a time.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct WpmMeter {
+    buckets: [u16; WPM_WINDOW_S],
+    index: usize,
+    bucket_start_ms: u16,
+    last_presses: Option<u16>,
+}
+
+impl WpmMeter {
+    /// Creates a new [WpmMeter].
+    pub const fn new() -> Self {
+        Self {
+            buckets: [0; WPM_WINDOW_S],
+            index: 0,
+            bucket_start_ms: 0,
+            last_presses: None,
+        }
+    }
+
+    /// Adds the key presses counted since the last update, at the time `now_ms`.
+    ///
+    /// Update at least once per second, idle seconds are skipped otherwise.
+    pub fn update(&mut self, now_ms: u16, presses: u16) {
+        let Some(last) = self.last_presses.replace(presses) else {
+            self.bucket_start_ms = now_ms;
+            return;
+        };
+
+        if now_ms.wrapping_sub(self.bucket_start_ms) >= 1000 {
+            self.index = (self.index + 1) % WPM_WINDOW_S;
+            self.buckets[self.index] = 0;
+            self.bucket_start_ms = now_ms;
+        }
+
+        let bucket = &mut self.buckets[self.index];
+        *bucket = bucket.saturating_add(presses.wrapping_sub(last));
+    }
+
+    /// Gets the words per minute over the window.
+    pub fn wpm(&self) -> u16 {
+        let presses = self
+            .buckets
+            .iter()
+            .fold(0u16, |sum, &bucket| sum.saturating_add(bucket));
+
+        (presses as u32 * (60 / WPM_WINDOW_S as u32) / KEYS_PER_WORD as u32) as u16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wpm_meter() {
+        let mut meter = WpmMeter::new();
+        meter.update(0, 100);
+        assert_eq!(meter.wpm(), 0);
+
+        // 5 presses per second for ten seconds: 300 presses per minute
+        for second in 1..=10u16 {
+            meter.update(second * 1000, 100 + second * 5);
+        }
+        assert_eq!(meter.wpm(), 60);
+
+        // idle for a full window
+        for second in 11..=20u16 {
+            meter.update(second * 1000, 150);
+        }
+        assert_eq!(meter.wpm(), 0);
+
+        let presses = key_presses();
+        count_key_event(&KeyEvent::new(0, 0, true, 0), layers::A);
+        count_key_event(&KeyEvent::new(0, 0, false, 5), layers::A);
+        count_key_event(&KeyEvent::new(0, 1, true, 5), layers::SHIFT);
+        assert_eq!(key_presses(), presses.wrapping_add(1));
+    }
+}
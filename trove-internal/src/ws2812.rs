@@ -0,0 +1,217 @@
+//! WS2812 and SK6812 RGB LED strips.
+//!
+//! Addressable LEDs (e.g. underglow, or per-key RGB) are chained on a single data pin, and take
+//! their colors as a stream of bytes, in the [ColorOrder] of the LED. Effects and indicators write
+//! colors into a [FrameBuffer], and the driver sends the changed frame once per main loop
+//! iteration, see [take_dirty](FrameBuffer::take_dirty).
+//!
+//! The LEDs latch the colors once the data pin is held low for [WS2812_RESET_US].
+
+use crate::animation::Rgb;
+
+/// Time (in microseconds) the data pin is held low to latch the colors.
+///
+/// Newer WS2812B revisions need 280 microseconds, older WS2812 and SK6812 LEDs only 80.
+pub const WS2812_RESET_US: u16 = 280;
+/// Default brightness of a [FrameBuffer], a little under half to stay within the USB power
+/// budget with longer strips.
+pub const DEFAULT_BRIGHTNESS: u8 = 96;
+
+/// Represents the order of the color bytes sent to an LED.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ColorOrder {
+    /// Green, red, blue, used by WS2812 and most SK6812 LEDs.
+    #[default]
+    Grb,
+    /// Red, green, blue.
+    Rgb,
+    /// Green, red, blue, white, used by SK6812 RGBW LEDs. The white LED is left off.
+    Grbw,
+}
+
+impl ColorOrder {
+    /// Gets the number of bytes sent for each LED.
+    pub const fn bytes_per_led(&self) -> usize {
+        match self {
+            Self::Grb | Self::Rgb => 3,
+            Self::Grbw => 4,
+        }
+    }
+
+    /// Gets the bytes sent for a color.
+    ///
+    /// Returns the byte array, and the number of bytes used.
+    pub const fn encode(&self, color: Rgb) -> ([u8; 4], usize) {
+        let Rgb { r, g, b } = color;
+
+        match self {
+            Self::Grb => ([g, r, b, 0], 3),
+            Self::Rgb => ([r, g, b, 0], 3),
+            Self::Grbw => ([g, r, b, 0], 4),
+        }
+    }
+}
+
+/// Colors of a strip of `N` LEDs, in chain order.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FrameBuffer<const N: usize> {
+    leds: [Rgb; N],
+    brightness: u8,
+    dirty: bool,
+}
+
+impl<const N: usize> FrameBuffer<N> {
+    /// Creates a new [FrameBuffer], with every LED off.
+    ///
+    /// The buffer starts dirty, so the first refresh turns off LEDs left on by a reset.
+    pub const fn new() -> Self {
+        Self {
+            leds: [Rgb::off(); N],
+            brightness: DEFAULT_BRIGHTNESS,
+            dirty: true,
+        }
+    }
+
+    /// Gets the number of LEDs.
+    pub const fn len(&self) -> usize {
+        N
+    }
+
+    /// Gets whether the strip has no LEDs.
+    pub const fn is_empty(&self) -> bool {
+        N == 0
+    }
+
+    /// Gets the color of an LED, before the brightness is applied.
+    pub fn get(&self, index: usize) -> Option<Rgb> {
+        self.leds.get(index).copied()
+    }
+
+    /// Sets the color of an LED.
+    ///
+    /// Returns `false` if the LED is outside of the strip.
+    pub fn set(&mut self, index: usize, color: Rgb) -> bool {
+        match self.leds.get_mut(index) {
+            Some(led) => {
+                if *led != color {
+                    *led = color;
+                    self.dirty = true;
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Gets the colors of all LEDs to change them at once, e.g. by an
+    /// [AnimationPlayer](crate::animation::AnimationPlayer), and marks the buffer changed.
+    pub fn leds_mut(&mut self) -> &mut [Rgb] {
+        self.dirty = true;
+        self.leds.as_mut()
+    }
+
+    /// Sets every LED to the `color`.
+    pub fn fill(&mut self, color: Rgb) {
+        for index in 0..N {
+            self.set(index, color);
+        }
+    }
+
+    /// Turns every LED off.
+    pub fn clear(&mut self) {
+        self.fill(Rgb::off());
+    }
+
+    /// Gets the brightness, from `0` (off) to `255` (full).
+    pub const fn brightness(&self) -> u8 {
+        self.brightness
+    }
+
+    /// Sets the brightness, from `0` (off) to `255` (full).
+    pub fn set_brightness(&mut self, val: u8) {
+        if self.brightness != val {
+            self.brightness = val;
+            self.dirty = true;
+        }
+    }
+
+    /// Builder function that sets the brightness.
+    pub const fn with_brightness(mut self, val: u8) -> Self {
+        self.brightness = val;
+        self
+    }
+
+    /// Gets whether the colors changed since they were last sent.
+    pub const fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    /// Takes the changed flag, call before sending the colors.
+    pub fn take_dirty(&mut self) -> bool {
+        core::mem::take(&mut self.dirty)
+    }
+
+    /// Gets the bytes sent to the strip, with the brightness applied.
+    pub fn bytes(&self, order: ColorOrder) -> impl Iterator<Item = u8> + '_ {
+        let brightness = self.brightness as u16 + 1;
+
+        self.leds.iter().flat_map(move |led| {
+            let scaled = Rgb::new(
+                scale(led.r, brightness),
+                scale(led.g, brightness),
+                scale(led.b, brightness),
+            );
+            let (bytes, len) = order.encode(scaled);
+
+            bytes.into_iter().take(len)
+        })
+    }
+}
+
+impl<const N: usize> Default for FrameBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Scales a color channel by the brightness, in `1..=256`.
+const fn scale(val: u8, brightness: u16) -> u8 {
+    ((val as u16 * brightness) >> 8) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_buffer() {
+        let mut frame = FrameBuffer::<2>::new().with_brightness(u8::MAX);
+        assert!(frame.take_dirty());
+        assert!(!frame.is_dirty());
+
+        assert!(frame.set(0, Rgb::new(0x10, 0x20, 0x30)));
+        assert!(!frame.set(2, Rgb::new(1, 1, 1)));
+        assert!(frame.take_dirty());
+
+        // setting the same color again does not need a refresh
+        frame.set(0, Rgb::new(0x10, 0x20, 0x30));
+        assert!(!frame.is_dirty());
+
+        let mut bytes = [0u8; 8];
+        for (slot, byte) in bytes.iter_mut().zip(frame.bytes(ColorOrder::Grb)) {
+            *slot = byte;
+        }
+        assert_eq!(frame.bytes(ColorOrder::Grb).count(), 6);
+        assert_eq!(bytes[..6], [0x20, 0x10, 0x30, 0, 0, 0]);
+        assert_eq!(frame.bytes(ColorOrder::Grbw).count(), 8);
+
+        frame.set_brightness(127);
+        assert!(frame.is_dirty());
+        assert_eq!(frame.bytes(ColorOrder::Rgb).next(), Some(0x08));
+
+        frame.take_dirty();
+        frame.leds_mut()[1] = Rgb::new(1, 2, 3);
+        assert!(frame.is_dirty());
+        assert_eq!(frame.get(1), Some(Rgb::new(1, 2, 3)));
+    }
+}